@@ -22,6 +22,7 @@ pub fn requested(headers: &HeaderMap) -> bool {
 ///
 /// Returns HTTP response, and a future that eventually resolves
 /// into websocket object.
+#[allow(clippy::type_complexity)]
 pub fn accept(
     headers: &HeaderMap,
     on_upgrade: OnUpgrade,
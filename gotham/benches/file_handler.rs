@@ -64,7 +64,7 @@ impl BenchServer {
         let listener = runtime.block_on(TcpListener::bind(addr)).unwrap();
         // use any free port
         let addr = listener.local_addr().unwrap();
-        let _ = runtime.spawn(async move {
+        runtime.spawn(async move {
             bind_server(listener, router, future::ok).await;
         });
         std::thread::sleep(Duration::from_millis(100));
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gotham::bench::Bench;
+use gotham::helpers::http::response::{create_json_response, create_response};
+use gotham::hyper::{body, Body, Request, Response, StatusCode};
+use gotham::router::build_simple_router;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::router::Router;
+use gotham::state::State;
+use serde::Serialize;
+use tokio::runtime;
+
+#[derive(Serialize)]
+struct Product {
+    id: u64,
+    name: String,
+    tags: Vec<&'static str>,
+    price: f64,
+}
+
+fn products() -> Vec<Product> {
+    (0..50)
+        .map(|id| Product {
+            id,
+            name: format!("Product number {}", id),
+            tags: vec!["clothing", "sale", "summer"],
+            price: 19.99,
+        })
+        .collect()
+}
+
+fn allocated(state: State) -> (State, Response<Body>) {
+    let body = serde_json::to_vec(&products()).unwrap();
+    let response = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
+    (state, response)
+}
+
+fn pooled(state: State) -> (State, Response<Body>) {
+    let response = create_json_response(&state, StatusCode::OK, &products()).unwrap();
+    (state, response)
+}
+
+fn router() -> Router {
+    build_simple_router(|route| {
+        route.get("/allocated").to(allocated);
+        route.get("/pooled").to(pooled);
+    })
+}
+
+pub fn json_response_benchmark(c: &mut Criterion) {
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let bench = Bench::new(router());
+    let mut group = c.benchmark_group("json_response_bench");
+    for path in ["/allocated", "/pooled"] {
+        group.bench_with_input(BenchmarkId::new("products", path), &path, |b, path| {
+            b.to_async(&runtime).iter(|| async {
+                let request = Request::get(*path).body(Body::empty()).unwrap();
+                let response = bench.call(request).await.unwrap();
+                // consume the body, so pooled buffers can be reused
+                body::to_bytes(response.into_body()).await.unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = json_response;
+    config = Criterion::default().measurement_time(Duration::from_millis(5_000)).warm_up_time(Duration::from_millis(10));
+    targets = json_response_benchmark
+}
+
+criterion_main!(json_response);
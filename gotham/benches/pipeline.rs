@@ -0,0 +1,126 @@
+use std::pin::Pin;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures_util::{FutureExt, TryFutureExt};
+use gotham::bench::Bench;
+use gotham::handler::HandlerFuture;
+use gotham::hyper::{Body, Request};
+use gotham::middleware::{Middleware, NewMiddleware};
+use gotham::pipeline::{new_pipeline, single_pipeline};
+use gotham::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+use gotham::router::Router;
+use gotham::state::State;
+use tokio::runtime;
+
+fn handler(state: State) -> (State, &'static str) {
+    (state, "Hello")
+}
+
+// Passes the request on without processing the response.
+#[derive(Clone, Copy)]
+struct PassThrough;
+
+impl NewMiddleware for PassThrough {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self> {
+        Ok(*self)
+    }
+}
+
+impl Middleware for PassThrough {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        chain(state)
+    }
+}
+
+// Processes the response, which requires a boxed future of its own.
+#[derive(Clone, Copy)]
+struct Wrapping;
+
+impl NewMiddleware for Wrapping {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self> {
+        Ok(*self)
+    }
+}
+
+impl Middleware for Wrapping {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        chain(state)
+            .map_ok(|(state, mut response)| {
+                response
+                    .headers_mut()
+                    .insert("x-wrapped", "true".parse().unwrap());
+                (state, response)
+            })
+            .boxed()
+    }
+}
+
+macro_rules! router {
+    ($($middleware:expr),*) => {{
+        let (chain, pipelines) = single_pipeline(new_pipeline()$(.add($middleware))*.build());
+        build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        })
+    }};
+}
+
+fn routers() -> Vec<(&'static str, Router)> {
+    vec![
+        ("none", router!()),
+        (
+            "pass_through_8",
+            router!(
+                PassThrough,
+                PassThrough,
+                PassThrough,
+                PassThrough,
+                PassThrough,
+                PassThrough,
+                PassThrough,
+                PassThrough
+            ),
+        ),
+        (
+            "wrapping_8",
+            router!(Wrapping, Wrapping, Wrapping, Wrapping, Wrapping, Wrapping, Wrapping, Wrapping),
+        ),
+    ]
+}
+
+pub fn pipeline_benchmark(c: &mut Criterion) {
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("pipeline_bench");
+    for (name, router) in routers() {
+        let bench = Bench::new(router);
+        group.bench_with_input(BenchmarkId::new("middleware", name), &bench, |b, bench| {
+            b.to_async(&runtime).iter(|| async {
+                let request = Request::get("/").body(Body::empty()).unwrap();
+                bench.call(request).await.unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = pipeline;
+    config = Criterion::default().measurement_time(Duration::from_millis(5_000)).warm_up_time(Duration::from_millis(10));
+    targets = pipeline_benchmark
+}
+
+criterion_main!(pipeline);
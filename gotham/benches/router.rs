@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gotham::bench::Bench;
+use gotham::hyper::{Body, Request};
+use gotham::router::build_simple_router;
+use gotham::router::builder::{DefineSingleRoute, DrawRoutes};
+use gotham::router::Router;
+use gotham::state::State;
+use tokio::runtime;
+
+fn handler(state: State) -> (State, &'static str) {
+    (state, "Hello")
+}
+
+fn router(routes: usize) -> Router {
+    build_simple_router(|route| {
+        for i in 0..routes {
+            route.get(&format!("/resource{i}/:id")).to(handler);
+        }
+    })
+}
+
+pub fn router_benchmark(c: &mut Criterion) {
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("router_bench");
+    for routes in [1, 10, 100] {
+        let bench = Bench::new(router(routes));
+        let uri = format!("/resource{}/42", routes - 1);
+        group.bench_with_input(BenchmarkId::new("last_route", routes), &uri, |b, uri| {
+            b.to_async(&runtime).iter(|| async {
+                let request = Request::get(uri.as_str()).body(Body::empty()).unwrap();
+                bench.call(request).await.unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = router_benches;
+    config = Criterion::default().measurement_time(Duration::from_millis(5_000)).warm_up_time(Duration::from_millis(10));
+    targets = router_benchmark
+}
+
+criterion_main!(router_benches);
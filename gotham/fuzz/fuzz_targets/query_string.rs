@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[allow(dead_code)]
+enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Params {
+    name: String,
+    count: Option<u32>,
+    ratio: Option<f64>,
+    enabled: Option<bool>,
+    ids: Vec<i64>,
+    order: Option<Order>,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = gotham::fuzz::parse_query::<Params>(data);
+});
@@ -0,0 +1,63 @@
+#![no_main]
+
+use gotham::prelude::*;
+use gotham::router::{build_simple_router, Router};
+use gotham::state::State;
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct UserPath {
+    #[allow(dead_code)]
+    id: u64,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct FilePath {
+    #[serde(rename = "*")]
+    #[allow(dead_code)]
+    parts: Vec<String>,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct SearchQuery {
+    #[allow(dead_code)]
+    q: String,
+    #[allow(dead_code)]
+    page: Option<u32>,
+    #[allow(dead_code)]
+    tags: Vec<String>,
+}
+
+fn handler(state: State) -> (State, &'static str) {
+    (state, "ok")
+}
+
+fn router() -> Router {
+    build_simple_router(|route| {
+        route.get("/").to(handler);
+        route
+            .get("/users/:id")
+            .with_path_extractor::<UserPath>()
+            .to(handler);
+        route
+            .get("/files/*")
+            .with_path_extractor::<FilePath>()
+            .to(handler);
+        route.get("/numbers/:n:[0-9]+").to(handler);
+        route
+            .get("/search")
+            .with_query_string_extractor::<SearchQuery>()
+            .to(handler);
+        route.scope("/api", |route| {
+            route.post("/items").to(handler);
+            route.delete("/items/:id").to(handler);
+        });
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    static ROUTER: OnceLock<Router> = OnceLock::new();
+    let _ = gotham::fuzz::route_request_bytes(ROUTER.get_or_init(router), data);
+});
@@ -0,0 +1,333 @@
+//! Measuring the performance of routers, middleware and handlers without a network.
+//!
+//! A `Bench` dispatches synthetic requests directly to a `NewHandler` (typically a `Router`),
+//! exactly like the server does after parsing a request, and reports the latency and throughput
+//! it observed. As no sockets or HTTP parsing are involved, the numbers are stable enough to
+//! detect regressions in routing and middleware in CI.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use gotham::hyper::{Body, Request};
+//! # use gotham::router::builder::*;
+//! # use gotham::state::State;
+//! use gotham::bench::Bench;
+//!
+//! # fn handler(state: State) -> (State, &'static str) {
+//! #     (state, "Hello!")
+//! # }
+//! #
+//! # fn main() {
+//! let router = build_simple_router(|route| route.get("/users/:id").to(handler));
+//!
+//! let report = Bench::new(router)
+//!     .with_iterations(1_000)
+//!     .run(|i| {
+//!         Request::get(format!("/users/{}", i))
+//!             .body(Body::empty())
+//!             .unwrap()
+//!     })
+//!     .unwrap();
+//!
+//! assert_eq!(report.iterations(), 1_000);
+//! println!("{}", report);
+//! # }
+//! ```
+//!
+//! # Allocations
+//!
+//! Counting allocations requires a global allocator keeping track of them, which Gotham does not
+//! install. Applications which already use one can pass a function returning its current count to
+//! `Bench::with_allocation_counter`, and the report will include the allocations per request.
+
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::{body, Body, Request, Response};
+use tokio::runtime;
+
+use crate::handler::NewHandler;
+use crate::service::call_handler;
+use crate::state::State;
+
+/// Drives a `NewHandler` with synthetic requests and measures its performance.
+pub struct Bench<NH> {
+    new_handler: Arc<NH>,
+    iterations: usize,
+    warmup: usize,
+    allocation_counter: Option<fn() -> u64>,
+}
+
+impl<NH> Bench<NH>
+where
+    NH: NewHandler + 'static,
+{
+    /// Creates a new `Bench` for the `Handler`s spawned by `new_handler`, which sends 10,000
+    /// requests after a warmup of 100 requests.
+    pub fn new(new_handler: NH) -> Self {
+        Bench {
+            new_handler: Arc::new(new_handler),
+            iterations: 10_000,
+            warmup: 100,
+            allocation_counter: None,
+        }
+    }
+
+    /// Sets the number of measured requests.
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Sets the number of requests sent before measuring, e.g. to populate caches.
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Sets a function returning the number of allocations made by the process so far, which is
+    /// used to report the allocations per request.
+    pub fn with_allocation_counter(mut self, counter: fn() -> u64) -> Self {
+        self.allocation_counter = Some(counter);
+        self
+    }
+
+    /// Dispatches a single request and reads the response body entirely, as a server would. This
+    /// can be used within other benchmarking harnesses, such as criterion.
+    pub async fn call(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        let client_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let state = State::from_request(request, client_addr);
+        let response = call_handler(self.new_handler.clone(), AssertUnwindSafe(state)).await?;
+
+        let (parts, body) = response.into_parts();
+        let body = body::to_bytes(body).await?;
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Runs the benchmark on a single-threaded runtime, building the request of each iteration
+    /// with `request`, which receives the index of the iteration.
+    pub fn run<F>(&self, mut request: F) -> anyhow::Result<BenchReport>
+    where
+        F: FnMut(usize) -> Request<Body>,
+    {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        runtime.block_on(async {
+            for i in 0..self.warmup {
+                self.call(request(i)).await?;
+            }
+
+            let allocations_before = self.allocation_counter.map(|counter| counter());
+            let mut latencies = Vec::with_capacity(self.iterations);
+            let mut server_errors = 0;
+            let start = Instant::now();
+            for i in 0..self.iterations {
+                let request = request(i);
+                let request_start = Instant::now();
+                let response = self.call(request).await?;
+                latencies.push(request_start.elapsed());
+                if response.status().is_server_error() {
+                    server_errors += 1;
+                }
+            }
+            let total = start.elapsed();
+            let allocations = self
+                .allocation_counter
+                .zip(allocations_before)
+                .map(|(counter, before)| counter().saturating_sub(before));
+
+            latencies.sort_unstable();
+            Ok(BenchReport {
+                latencies,
+                total,
+                server_errors,
+                allocations,
+            })
+        })
+    }
+}
+
+/// The measurements of a benchmark run by `Bench::run`.
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    // sorted in ascending order
+    latencies: Vec<Duration>,
+    total: Duration,
+    server_errors: usize,
+    allocations: Option<u64>,
+}
+
+impl BenchReport {
+    /// Returns the number of measured requests.
+    pub fn iterations(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Returns the number of requests answered with a `5xx` status code.
+    pub fn server_errors(&self) -> usize {
+        self.server_errors
+    }
+
+    /// Returns the time taken by all measured requests, including building them.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Returns the mean latency of a request.
+    pub fn mean(&self) -> Duration {
+        match self.latencies.len() {
+            0 => Duration::ZERO,
+            n => self.latencies.iter().sum::<Duration>().div_f64(n as f64),
+        }
+    }
+
+    /// Returns the lowest latency of a request.
+    pub fn min(&self) -> Duration {
+        self.latencies.first().copied().unwrap_or_default()
+    }
+
+    /// Returns the highest latency of a request.
+    pub fn max(&self) -> Duration {
+        self.latencies.last().copied().unwrap_or_default()
+    }
+
+    /// Returns the latency which `percentile` percent of the requests did not exceed, e.g. `99.0`
+    /// for the 99th percentile.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.latencies.len() as f64).ceil();
+        let index = (rank as usize).clamp(1, self.latencies.len()) - 1;
+        self.latencies[index]
+    }
+
+    /// Returns the number of requests per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.total.as_secs_f64();
+        if secs > 0.0 {
+            self.latencies.len() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the number of allocations made while measuring, if an allocation counter was set.
+    pub fn allocations(&self) -> Option<u64> {
+        self.allocations
+    }
+
+    /// Returns the mean number of allocations per request, if an allocation counter was set.
+    pub fn allocations_per_request(&self) -> Option<f64> {
+        match self.latencies.len() {
+            0 => None,
+            n => self
+                .allocations
+                .map(|allocations| allocations as f64 / n as f64),
+        }
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests in {:?} ({:.0} req/s), latency mean {:?}, min {:?}, p50 {:?}, p99 {:?}, \
+             max {:?}",
+            self.iterations(),
+            self.total,
+            self.throughput(),
+            self.mean(),
+            self.min(),
+            self.percentile(50.0),
+            self.percentile(99.0),
+            self.max()
+        )?;
+        if self.server_errors > 0 {
+            write!(f, ", {} server errors", self.server_errors)?;
+        }
+        if let Some(allocations) = self.allocations_per_request() {
+            write!(f, ", {:.1} allocations/request", allocations)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use hyper::StatusCode;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn report(millis: &[u64]) -> BenchReport {
+        BenchReport {
+            latencies: millis.iter().copied().map(Duration::from_millis).collect(),
+            total: Duration::from_millis(millis.iter().sum()),
+            server_errors: 0,
+            allocations: Some(10),
+        }
+    }
+
+    #[test]
+    fn computes_statistics() {
+        let report = report(&[1, 2, 3, 4, 10]);
+        assert_eq!(report.mean(), Duration::from_millis(4));
+        assert_eq!(report.min(), Duration::from_millis(1));
+        assert_eq!(report.max(), Duration::from_millis(10));
+        assert_eq!(report.percentile(50.0), Duration::from_millis(3));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(10));
+        assert_eq!(report.percentile(0.0), Duration::from_millis(1));
+        assert!((report.throughput() - 250.0).abs() < 1e-9);
+        assert_eq!(report.allocations_per_request(), Some(2.0));
+    }
+
+    #[test]
+    fn runs_requests_against_router() {
+        static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+        fn handler(state: State) -> (State, &'static str) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+            (state, "Hello")
+        }
+
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+        });
+        let bench = Bench::new(router)
+            .with_iterations(20)
+            .with_warmup(5)
+            .with_allocation_counter(|| ALLOCATIONS.load(Ordering::SeqCst));
+
+        let report = bench
+            .run(|i| {
+                let uri = if i % 2 == 0 { "/" } else { "/missing" };
+                Request::get(uri).body(Body::empty()).unwrap()
+            })
+            .unwrap();
+
+        assert_eq!(report.iterations(), 20);
+        assert_eq!(report.server_errors(), 0);
+        assert_eq!(report.allocations(), Some(10));
+        assert!(report.min() <= report.max());
+        assert!(report.to_string().starts_with("20 requests in "));
+    }
+
+    #[test]
+    fn calls_handler_directly() {
+        fn handler(state: State) -> (State, &'static str) {
+            (state, "Hello")
+        }
+
+        let bench = Bench::new(|| Ok(handler));
+        let response =
+            futures_executor::block_on(bench.call(Request::get("/").body(Body::empty()).unwrap()))
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
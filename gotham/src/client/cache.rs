@@ -0,0 +1,672 @@
+//! Caching of responses to outbound `GET` requests.
+//!
+//! A `ResponseCache` given to `HttpClient::with_cache` stores the responses of upstream services
+//! according to their `Cache-Control` and `Expires` headers, like a shared cache does. Fresh
+//! responses are served from the cache without contacting the upstream service, with an `Age`
+//! header. Stale responses with an `ETag` or `Last-Modified` header are revalidated with a
+//! conditional request, and served from the cache again if the upstream service answers with
+//! `304 Not Modified`, so only changed bodies are transferred.
+//!
+//! Responses marked `no-store` or `private`, or varying on `*`, are not stored, and neither are
+//! responses to requests with an `Authorization` or `Range` header, or with conditional headers
+//! of their own. The cache holds at most `max_entries` responses with at most `max_bytes` bytes in
+//! total, and evicts the least recently used ones to make room. Bodies larger than
+//! `max_entry_size` are passed through without being stored.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use gotham::client::{HttpClient, ResponseCache};
+//!
+//! let cache = ResponseCache::new()
+//!     .with_max_entries(500)
+//!     .with_max_bytes(16 * 1024 * 1024);
+//! let client = HttpClient::new().with_cache(cache);
+//! # let _ = client;
+//! ```
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::Bytes;
+use futures_util::future;
+use futures_util::stream::{self, StreamExt};
+use httpdate::parse_http_date;
+use hyper::body::HttpBody;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, AGE, AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, DATE,
+    ETAG, EXPIRES, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE,
+    LAST_MODIFIED, RANGE,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::client::ClientError;
+use crate::helpers::buffer;
+use crate::helpers::http::vary::vary_names;
+
+const DEFAULT_MAX_ENTRIES: usize = 1_000;
+const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_MAX_ENTRY_SIZE: usize = 1024 * 1024;
+
+// The `Cache-Control` directives relevant to a shared cache.
+#[derive(Debug, Default, PartialEq)]
+struct Directives {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+}
+
+impl Directives {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut directives = Directives::default();
+        let values = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in values {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = || value.and_then(|value| value.parse::<u64>().ok());
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "max-age" => directives.max_age = seconds(),
+                "s-maxage" => directives.s_maxage = seconds(),
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+struct Entry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    // the request headers named by `Vary`, which later requests must match
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    lifetime: Duration,
+    age: Duration,
+    stored: Instant,
+    used: u64,
+}
+
+impl Entry {
+    fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        self.body.len() + headers
+    }
+
+    fn current_age(&self) -> Duration {
+        self.age + self.stored.elapsed()
+    }
+
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name) == value.as_ref())
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    by_key: HashMap<String, Entry>,
+    // the keys of the entries by their last use, to evict the least recently used ones
+    by_use: BTreeMap<u64, String>,
+    uses: u64,
+    bytes: usize,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.by_key.remove(key)?;
+        self.by_use.remove(&entry.used);
+        self.bytes -= entry.size();
+        Some(entry)
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.uses += 1;
+        if let Some(entry) = self.by_key.get_mut(key) {
+            self.by_use.remove(&entry.used);
+            entry.used = self.uses;
+            self.by_use.insert(self.uses, key.to_owned());
+        }
+    }
+}
+
+// A stored response, as found for a request.
+struct Cached {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    age: Duration,
+    fresh: bool,
+}
+
+impl Cached {
+    fn into_response(self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+            .headers_mut()
+            .insert(AGE, HeaderValue::from(self.age.as_secs()));
+        response
+    }
+}
+
+/// A cache for the responses of an `HttpClient`, which may be shared by several clients.
+///
+/// Clones of a `ResponseCache` share the stored responses.
+#[derive(Clone)]
+pub struct ResponseCache {
+    max_entries: usize,
+    max_bytes: usize,
+    max_entry_size: usize,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl ResponseCache {
+    /// Creates a new, empty `ResponseCache`.
+    pub fn new() -> Self {
+        ResponseCache {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_entry_size: DEFAULT_MAX_ENTRY_SIZE,
+            entries: Arc::new(Mutex::new(Entries::default())),
+        }
+    }
+
+    /// Sets the number of responses which are stored (defaults to 1,000).
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Sets the total size of the stored responses, including their headers (defaults to 64 MiB).
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Sets the size of the largest body which is stored (defaults to 1 MiB).
+    pub fn with_max_entry_size(mut self, max_entry_size: usize) -> Self {
+        self.max_entry_size = max_entry_size;
+        self
+    }
+
+    /// Returns the number of stored responses.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().by_key.len()
+    }
+
+    /// Checks whether no responses are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all stored responses.
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+
+    /// Sends `request` with `send`, unless a fresh response is stored for it, and stores the
+    /// response if allowed.
+    pub(crate) async fn send<F, Fut>(
+        &self,
+        mut request: Request<Body>,
+        send: F,
+    ) -> Result<Response<Body>, ClientError>
+    where
+        F: FnOnce(Request<Body>) -> Fut,
+        Fut: Future<Output = Result<Response<Body>, ClientError>>,
+    {
+        let directives = Directives::parse(request.headers());
+        if !storable_request(&request) || directives.no_store {
+            return send(request).await;
+        }
+
+        let key = request.uri().to_string();
+        let cached = self.lookup(&key, request.headers());
+        if let Some(cached) = &cached {
+            if cached.fresh && !directives.no_cache {
+                return Ok(cached_response(cached));
+            }
+            let headers = request.headers_mut();
+            if let Some(etag) = cached.headers.get(ETAG) {
+                headers.insert(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(modified) = cached.headers.get(LAST_MODIFIED) {
+                headers.insert(IF_MODIFIED_SINCE, modified.clone());
+            }
+        }
+
+        let request_headers = request.headers().clone();
+        let response = send(request).await?;
+        match cached {
+            Some(cached) if response.status() == StatusCode::NOT_MODIFIED => {
+                Ok(self.revalidate(&key, cached, response.headers()))
+            }
+            _ => self.store(key, &request_headers, response).await,
+        }
+    }
+
+    fn lookup(&self, key: &str, headers: &HeaderMap) -> Option<Cached> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.by_key.get(key)?;
+        if !entry.matches(headers) {
+            return None;
+        }
+        let age = entry.current_age();
+        let cached = Cached {
+            status: entry.status,
+            headers: entry.headers.clone(),
+            body: entry.body.clone(),
+            age,
+            fresh: age < entry.lifetime,
+        };
+        entries.touch(key);
+        Some(cached)
+    }
+
+    // Updates a stored response with the headers of a `304 Not Modified` response.
+    fn revalidate(&self, key: &str, cached: Cached, headers: &HeaderMap) -> Response<Body> {
+        let mut cached = Cached {
+            age: age(headers),
+            fresh: true,
+            ..cached
+        };
+        for name in headers.keys() {
+            if name != CONTENT_LENGTH {
+                cached.headers.remove(name);
+            }
+        }
+        for (name, value) in headers {
+            if name != CONTENT_LENGTH {
+                cached.headers.append(name, value.clone());
+            }
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if let (Some(entry), Some(lifetime)) = (
+            entries.remove(key),
+            lifetime(cached.status, &cached.headers),
+        ) {
+            let entry = Entry {
+                headers: cached.headers.clone(),
+                lifetime,
+                age: cached.age,
+                stored: Instant::now(),
+                ..entry
+            };
+            self.insert(&mut entries, key.to_owned(), entry);
+        }
+        cached.into_response()
+    }
+
+    async fn store(
+        &self,
+        key: String,
+        request_headers: &HeaderMap,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, ClientError> {
+        let lifetime = match lifetime(response.status(), response.headers()) {
+            Some(lifetime) if content_length(response.headers()) <= Some(self.max_entry_size) => {
+                lifetime
+            }
+            _ => {
+                self.entries.lock().unwrap().remove(&key);
+                return Ok(response);
+            }
+        };
+
+        let (parts, mut body) = response.into_parts();
+        let mut buffered = buffer::acquire(cmp::min(self.max_entry_size, buffer::SIZE_CLASSES[0]));
+        while let Some(chunk) = body.data().await {
+            buffered.extend_from_slice(&chunk?);
+            if buffered.len() > self.max_entry_size {
+                // pass the body on without storing it
+                let read = stream::once(future::ok::<_, hyper::Error>(buffered.split().freeze()));
+                let body = Body::wrap_stream(read.chain(body));
+                return Ok(Response::from_parts(parts, body));
+            }
+        }
+        let body = buffered.split().freeze();
+
+        let vary = vary_names(&parts.headers)
+            .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+            .map(|name| {
+                let value = request_headers.get(&name).cloned();
+                (name, value)
+            })
+            .collect();
+        let entry = Entry {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+            vary,
+            lifetime,
+            age: age(&parts.headers),
+            stored: Instant::now(),
+            used: 0,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&key);
+        self.insert(&mut entries, key, entry);
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    fn insert(&self, entries: &mut Entries, key: String, mut entry: Entry) {
+        let size = entry.size();
+        if size > self.max_bytes || self.max_entries == 0 {
+            return;
+        }
+        while entries.by_key.len() >= self.max_entries || entries.bytes + size > self.max_bytes {
+            let oldest = match entries.by_use.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+            entries.remove(&oldest);
+        }
+
+        entries.uses += 1;
+        entry.used = entries.uses;
+        entries.bytes += size;
+        entries.by_use.insert(entry.used, key.clone());
+        entries.by_key.insert(key, entry);
+    }
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        ResponseCache::new()
+    }
+}
+
+impl fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.entries.lock().unwrap();
+        f.debug_struct("ResponseCache")
+            .field("max_entries", &self.max_entries)
+            .field("max_bytes", &self.max_bytes)
+            .field("max_entry_size", &self.max_entry_size)
+            .field("len", &entries.by_key.len())
+            .field("bytes", &entries.bytes)
+            .finish()
+    }
+}
+
+fn cached_response(cached: &Cached) -> Response<Body> {
+    Cached {
+        headers: cached.headers.clone(),
+        body: cached.body.clone(),
+        ..*cached
+    }
+    .into_response()
+}
+
+// Checks whether the response to a request may be stored, or served from the cache.
+fn storable_request(request: &Request<Body>) -> bool {
+    let headers = request.headers();
+    request.method() == Method::GET
+        && [
+            AUTHORIZATION,
+            RANGE,
+            IF_MATCH,
+            IF_NONE_MATCH,
+            IF_MODIFIED_SINCE,
+            IF_UNMODIFIED_SINCE,
+            IF_RANGE,
+        ]
+        .iter()
+        .all(|name| !headers.contains_key(name))
+}
+
+// Returns how long a response stays fresh, or `None` if it must not be stored. Responses without
+// an explicit lifetime are only stored if they can be revalidated.
+fn lifetime(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    let cacheable = matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    );
+    let directives = Directives::parse(headers);
+    if !cacheable
+        || directives.no_store
+        || directives.private
+        || vary_names(headers).any(|name| name == "*")
+    {
+        return None;
+    }
+
+    let lifetime = if directives.no_cache {
+        Duration::ZERO
+    } else if let Some(seconds) = directives.s_maxage.or(directives.max_age) {
+        Duration::from_secs(seconds)
+    } else {
+        let date = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_http_date(value).ok())
+        };
+        match date(EXPIRES) {
+            Some(expires) => {
+                let date = date(DATE).unwrap_or_else(SystemTime::now);
+                expires.duration_since(date).unwrap_or_default()
+            }
+            None => Duration::ZERO,
+        }
+    };
+
+    let validated = headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED);
+    if lifetime.is_zero() && !validated {
+        None
+    } else {
+        Some(lifetime)
+    }
+}
+
+fn age(headers: &HeaderMap) -> Duration {
+    let seconds = headers
+        .get(AGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    Duration::from_secs(seconds)
+}
+
+fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::HttpClient;
+    use crate::state::{set_request_id, State};
+    use hyper::header::VARY;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Server;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Starts a server answering with the given headers and body, and with `304 Not Modified` to
+    // requests with a matching `If-None-Match` header. Returns its address and request counter.
+    fn upstream(
+        headers: &'static [(&'static str, &'static str)],
+        body: &'static str,
+    ) -> (SocketAddr, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let counter = counter.clone();
+            future::ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut response = Response::new(Body::from(body));
+                for (name, value) in headers {
+                    response
+                        .headers_mut()
+                        .insert(*name, HeaderValue::from_static(value));
+                }
+                let etag = response.headers().get(ETAG).cloned();
+                if etag.is_some() && req.headers().get(IF_NONE_MATCH) == etag.as_ref() {
+                    *response.status_mut() = StatusCode::NOT_MODIFIED;
+                    *response.body_mut() = Body::empty();
+                }
+                future::ok::<_, Infallible>(response)
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, requests)
+    }
+
+    async fn get(
+        client: &HttpClient,
+        addr: SocketAddr,
+        language: &str,
+    ) -> (Response<Body>, String) {
+        let mut request = None;
+        State::with_new(|state| {
+            state.put(HeaderMap::new());
+            set_request_id(state);
+            let uri = format!("http://{}/", addr);
+            request = Some(client.get(state, uri).header("accept-language", language));
+        });
+        let response = request.unwrap().send().await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    #[test]
+    fn computes_lifetimes() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static(value));
+            headers
+        };
+        let ok = StatusCode::OK;
+        assert_eq!(
+            lifetime(ok, &headers("max-age=60")),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            lifetime(ok, &headers("public, max-age=60, s-maxage=\"10\"")),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(lifetime(ok, &headers("no-store, max-age=60")), None);
+        assert_eq!(lifetime(ok, &headers("private, max-age=60")), None);
+        assert_eq!(lifetime(ok, &headers("no-cache")), None);
+        assert_eq!(lifetime(StatusCode::CREATED, &headers("max-age=60")), None);
+
+        let mut validated = headers("no-cache");
+        validated.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        assert_eq!(lifetime(ok, &validated), Some(Duration::ZERO));
+
+        let mut expires = HeaderMap::new();
+        expires.insert(
+            DATE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        expires.insert(
+            EXPIRES,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:50:37 GMT"),
+        );
+        assert_eq!(lifetime(ok, &expires), Some(Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_responses() {
+        let (addr, requests) = upstream(&[("cache-control", "max-age=60")], "fresh");
+        let cache = ResponseCache::new();
+        let client = HttpClient::new().with_cache(cache.clone());
+
+        let (response, body) = get(&client, addr, "en").await;
+        assert_eq!(body, "fresh");
+        assert!(response.headers().get(AGE).is_none());
+        let (response, body) = get(&client, addr, "en").await;
+        assert_eq!(body, "fresh");
+        assert_eq!(response.headers()[AGE], "0");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_responses() {
+        let (addr, requests) = upstream(
+            &[
+                ("cache-control", "no-cache"),
+                ("etag", "\"v1\""),
+                ("vary", "accept-language"),
+            ],
+            "validated",
+        );
+        let client = HttpClient::new().with_cache(ResponseCache::new());
+
+        for _ in 0..2 {
+            let (response, body) = get(&client, addr, "en").await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[ETAG], "\"v1\"");
+            assert_eq!(response.headers()[VARY], "accept-language");
+            assert_eq!(body, "validated");
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // a different language doesn't match the stored response
+        let (response, body) = get(&client, addr, "de").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body, "validated");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn limits_stored_responses() {
+        let (addr, requests) = upstream(&[("cache-control", "max-age=60")], "too large");
+        let cache = ResponseCache::new().with_max_entry_size(4);
+        let client = HttpClient::new().with_cache(cache.clone());
+        for _ in 0..2 {
+            let (_, body) = get(&client, addr, "en").await;
+            assert_eq!(body, "too large");
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(cache.is_empty());
+
+        let (addr, _) = upstream(&[("cache-control", "no-store")], "secret");
+        let (_, body) = get(&client, addr, "en").await;
+        assert_eq!(body, "secret");
+        assert!(cache.is_empty());
+
+        // the least recently used response is evicted
+        let cache = ResponseCache::new().with_max_entries(1);
+        let client = HttpClient::new().with_cache(cache.clone());
+        let (first, first_requests) = upstream(&[("cache-control", "max-age=60")], "1");
+        let (second, _) = upstream(&[("cache-control", "max-age=60")], "2");
+        get(&client, first, "en").await;
+        get(&client, second, "en").await;
+        get(&client, first, "en").await;
+        assert_eq!(first_requests.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,467 @@
+//! An HTTP client for calling other services from handlers.
+//!
+//! `HttpClient` wraps a `hyper::Client`, and builds requests from the `State` of the incoming
+//! request: the request ID is sent as `X-Request-ID`, and trace context headers such as
+//! `traceparent` are copied from the incoming request, so calls can be correlated across services.
+//! Requests time out after 30 seconds unless configured otherwise, and no later than the
+//! `Deadline` of the incoming request, if one was set by the `DeadlineMiddleware`.
+//!
+//! Connections are opened by an `UpstreamPool` given to `HttpClient::with_upstream_pool`, which
+//! caches the addresses of upstream hosts, prefers addresses which accept connections, and limits
+//! the connections to each host.
+//!
+//! Responses to `GET` requests can be cached by giving a `ResponseCache` to `HttpClient::with_cache`,
+//! which honors the `Cache-Control` headers of upstream services, and revalidates stale responses
+//! with their `ETag` or `Last-Modified` header.
+//!
+//! `HttpClient` is also a `Middleware`, which places the client into the `State` of every request.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use gotham::handler::HandlerResult;
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! use gotham::client::HttpClient;
+//! use gotham::helpers::http::response::create_empty_response;
+//! use gotham::hyper::StatusCode;
+//! use gotham::state::{FromState, State};
+//!
+//! async fn handler(state: State) -> HandlerResult {
+//!     let request = HttpClient::borrow_from(&state).get(&state, "http://users.internal/me");
+//!     let status = match request.send().await {
+//!         Ok(response) => response.status(),
+//!         Err(_) => StatusCode::BAD_GATEWAY,
+//!     };
+//!     let response = create_empty_response(&state, status);
+//!     Ok((state, response))
+//! }
+//!
+//! # fn main() {
+//! let client = HttpClient::new().with_timeout(Some(Duration::from_secs(5)));
+//! let (chain, pipelines) = single_pipeline(new_pipeline().add(client).build());
+//! let router = build_router(chain, pipelines, |route| {
+//!     route.get("/").to_async(handler);
+//! });
+//! # let _ = router;
+//! # }
+//! ```
+
+mod cache;
+mod upstream;
+
+pub use self::cache::ResponseCache;
+pub use self::upstream::{UpstreamConnection, UpstreamPool};
+
+use std::convert::TryFrom;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::time::Duration;
+
+use hyper::client::{HttpConnector, ResponseFuture};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::http::request::Builder;
+use hyper::{Body, Client, Method, Request, Response, Uri};
+use thiserror::Error;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::deadline::Deadline;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, FromState, State, StateData};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The error returned when an outbound request fails.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ClientError {
+    /// The request could not be built, e.g. because of an invalid URI or header.
+    #[error("invalid request: {0}")]
+    InvalidRequest(#[from] hyper::http::Error),
+    /// The request failed.
+    #[error("request failed: {0}")]
+    Request(#[from] hyper::Error),
+    /// No response was received before the timeout elapsed.
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// An HTTP client which propagates the request ID and trace context of the current request.
+pub struct HttpClient {
+    // The connection pool of the client is not `RefUnwindSafe`, but only accessed through hyper.
+    client: AssertUnwindSafe<Connector>,
+    timeout: Option<Duration>,
+    propagated_headers: Vec<HeaderName>,
+    cache: Option<ResponseCache>,
+}
+
+impl Clone for HttpClient {
+    fn clone(&self) -> Self {
+        HttpClient {
+            client: AssertUnwindSafe(self.client.0.clone()),
+            timeout: self.timeout,
+            propagated_headers: self.propagated_headers.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::from_client(Client::new())
+    }
+}
+
+impl HttpClient {
+    /// Creates a new `HttpClient` with a default `hyper::Client`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `HttpClient` using the given `hyper::Client`, e.g. one with a customized
+    /// connection pool.
+    pub fn from_client(client: Client<HttpConnector, Body>) -> Self {
+        HttpClient {
+            client: AssertUnwindSafe(Connector::Http(client)),
+            timeout: Some(DEFAULT_TIMEOUT),
+            propagated_headers: vec![
+                HeaderName::from_static("traceparent"),
+                HeaderName::from_static("tracestate"),
+            ],
+            cache: None,
+        }
+    }
+
+    /// Sets the default timeout of requests, or disables it if `None` (defaults to 30 seconds).
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Opens the connections of the client with `pool`, see `UpstreamPool`. This replaces a client
+    /// given to `from_client`.
+    pub fn with_upstream_pool(self, pool: UpstreamPool) -> Self {
+        HttpClient {
+            client: AssertUnwindSafe(Connector::Upstream(pool.build_client())),
+            ..self
+        }
+    }
+
+    /// Adds a header which is copied from the incoming request to outbound requests. The trace
+    /// context headers `traceparent` and `tracestate` are propagated by default.
+    pub fn with_propagated_header(mut self, name: HeaderName) -> Self {
+        if !self.propagated_headers.contains(&name) {
+            self.propagated_headers.push(name);
+        }
+        self
+    }
+
+    /// Caches the responses to `GET` requests in `cache`, see `ResponseCache`.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Starts building a request with the given method and URI, propagating headers from `state`.
+    /// The request times out no later than the `Deadline` in `state`, if any.
+    pub fn request<U>(&self, state: &State, method: Method, uri: U) -> ClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<hyper::http::Error>,
+    {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("X-Request-ID", request_id(state));
+
+        if let Some(headers) = HeaderMap::try_borrow_from(state) {
+            for name in &self.propagated_headers {
+                for value in headers.get_all(name) {
+                    builder = builder.header(name, value);
+                }
+            }
+        }
+
+        let timeout = match Deadline::try_borrow_from(state) {
+            Some(deadline) => Some(deadline.bound_timeout(self.timeout)),
+            None => self.timeout,
+        };
+
+        ClientRequest {
+            client: self.clone(),
+            builder,
+            body: Body::empty(),
+            timeout,
+        }
+    }
+
+    /// Starts building a `GET` request, propagating headers from `state`.
+    pub fn get<U>(&self, state: &State, uri: U) -> ClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<hyper::http::Error>,
+    {
+        self.request(state, Method::GET, uri)
+    }
+
+    /// Starts building a `POST` request, propagating headers from `state`.
+    pub fn post<U>(&self, state: &State, uri: U) -> ClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<hyper::http::Error>,
+    {
+        self.request(state, Method::POST, uri)
+    }
+}
+
+impl StateData for HttpClient {}
+
+impl NewMiddleware for HttpClient {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for HttpClient {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        state.put(self);
+        chain(state)
+    }
+}
+
+/// An outbound request, as created by `HttpClient::request`. It doesn't borrow the `State`, so
+/// it can be sent after the `State` was moved.
+pub struct ClientRequest {
+    client: HttpClient,
+    builder: Builder,
+    body: Body,
+    timeout: Option<Duration>,
+}
+
+impl ClientRequest {
+    /// Adds a header to the request.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<hyper::http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<hyper::http::Error>,
+    {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    /// Sets the body of the request.
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Overrides the timeout of the client for this request, or disables it if `None`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends the request, and resolves to the response once its head was received. If the client
+    /// has a `ResponseCache`, the response may be served from the cache instead, and is read
+    /// completely before resolving if it is stored.
+    pub async fn send(self) -> Result<Response<Body>, ClientError> {
+        let request = self.builder.body(self.body)?;
+        let client = &self.client.client;
+        let timeout = self.timeout;
+        match &self.client.cache {
+            Some(cache) => {
+                cache
+                    .send(request, |request| execute(client, request, timeout))
+                    .await
+            }
+            None => execute(client, request, timeout).await,
+        }
+    }
+}
+
+// The `hyper::Client` of an `HttpClient`, depending on what opens its connections.
+#[derive(Clone)]
+enum Connector {
+    Http(Client<HttpConnector, Body>),
+    Upstream(Client<UpstreamPool, Body>),
+}
+
+impl Connector {
+    fn request(&self, request: Request<Body>) -> ResponseFuture {
+        match self {
+            Connector::Http(client) => client.request(request),
+            Connector::Upstream(client) => client.request(request),
+        }
+    }
+}
+
+async fn execute(
+    client: &Connector,
+    request: Request<Body>,
+    timeout: Option<Duration>,
+) -> Result<Response<Body>, ClientError> {
+    let response = client.request(request);
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, response)
+            .await
+            .map_err(|_| ClientError::Timeout(timeout))?
+            .map_err(ClientError::from),
+        None => response.await.map_err(ClientError::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::set_request_id;
+    use futures_util::future;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Server;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Starts a server which echoes the given request header in its response body.
+    fn echo_server(header: &'static str) -> SocketAddr {
+        let make_service = make_service_fn(move |_| {
+            future::ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let value = req
+                    .headers()
+                    .get_all(header)
+                    .iter()
+                    .map(|value| value.to_str().unwrap())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                future::ok::<_, Infallible>(Response::new(Body::from(value)))
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    fn request(client: &HttpClient, uri: String) -> ClientRequest {
+        let mut request = None;
+        State::with_new(|state| {
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Request-ID", "abc".parse().unwrap());
+            headers.insert("traceparent", "00-0af7-b7ad-01".parse().unwrap());
+            headers.insert("X-Tenant", "acme".parse().unwrap());
+            state.put(headers);
+            set_request_id(state);
+            request = Some(client.get(state, uri));
+        });
+        request.unwrap()
+    }
+
+    async fn body(response: Response<Body>) -> String {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn propagates_request_id_and_trace_context() {
+        let client = HttpClient::new();
+        for (header, expected) in &[("x-request-id", "abc"), ("traceparent", "00-0af7-b7ad-01")] {
+            let addr = echo_server(header);
+            let response = request(&client, format!("http://{}/", addr))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(body(response).await, *expected);
+        }
+
+        // other headers are only propagated when configured
+        let addr = echo_server("x-tenant");
+        let response = request(&client, format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(body(response).await, "");
+
+        let client = client.with_propagated_header(HeaderName::from_static("x-tenant"));
+        let response = request(&client, format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(body(response).await, "acme");
+    }
+
+    #[tokio::test]
+    async fn limits_idle_connections() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let make_service = make_service_fn(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            future::ok::<_, Infallible>(service_fn(|_: Request<Body>| {
+                future::ok::<_, Infallible>(Response::new(Body::from("pooled")))
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        // without idle connections, every request opens a new connection
+        let pool = UpstreamPool::new().with_max_idle_per_host(0);
+        let client = HttpClient::new().with_upstream_pool(pool);
+        for _ in 0..2 {
+            let response = request(&client, format!("http://{}/", addr))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(body(response).await, "pooled");
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        // accepts connections, but never responds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = HttpClient::new().with_timeout(Some(Duration::from_millis(10)));
+        let result = request(&client, format!("http://{}/", addr)).send().await;
+        assert!(matches!(result, Err(ClientError::Timeout(_))));
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn times_out_at_deadline() {
+        // accepts connections, but never responds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = HttpClient::new();
+        let mut request = None;
+        State::with_new(|state| {
+            state.put(HeaderMap::new());
+            state.put(Deadline::after(Duration::from_millis(10)));
+            set_request_id(state);
+            request = Some(client.get(state, format!("http://{}/", addr)));
+        });
+        let result = request.unwrap().send().await;
+        match result {
+            Err(ClientError::Timeout(timeout)) => assert!(timeout <= Duration::from_millis(10)),
+            _ => panic!("expected timeout"),
+        }
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_requests() {
+        let client = HttpClient::new();
+        let result = request(&client, "not a uri".to_owned()).send().await;
+        assert!(matches!(result, Err(ClientError::InvalidRequest(_))));
+    }
+}
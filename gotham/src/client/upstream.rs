@@ -0,0 +1,396 @@
+//! Connections to upstream services, with DNS caching and health-aware selection of addresses.
+//!
+//! An `UpstreamPool` given to `HttpClient::with_upstream_pool` opens the connections of the client.
+//! The addresses a host name resolves to are cached for `dns_ttl`, so requests don't wait for
+//! DNS once a connection has to be opened. Addresses are tried in the order they were resolved,
+//! except that addresses which recently failed to accept a connection are only tried after all
+//! others, until `unhealthy_for` has passed. At most `max_connections_per_host` connections are
+//! open to each host, and further requests wait for one of them to become idle. Idle connections
+//! are kept open to be reused by later requests, at most `max_idle_per_host` of them per host.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use gotham::client::{HttpClient, UpstreamPool};
+//!
+//! let pool = UpstreamPool::new()
+//!     .with_max_connections_per_host(Some(32))
+//!     .with_dns_ttl(Duration::from_secs(30));
+//! let client = HttpClient::new().with_upstream_pool(pool);
+//! # let _ = client;
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::{Body, Client, Uri};
+use log::debug;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_MAX_IDLE_PER_HOST: usize = 32;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_DNS_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_UNHEALTHY_FOR: Duration = Duration::from_secs(10);
+
+/// The connection pool of an `HttpClient`, which may be shared by several clients.
+///
+/// Clones of an `UpstreamPool` share the cached addresses, their health and the limits of
+/// connections per host, but every `HttpClient` keeps its own idle connections.
+#[derive(Clone)]
+pub struct UpstreamPool {
+    max_connections_per_host: Option<usize>,
+    max_idle_per_host: usize,
+    idle_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    dns_ttl: Duration,
+    unhealthy_for: Duration,
+    hosts: Arc<Mutex<Hosts>>,
+}
+
+#[derive(Default)]
+struct Hosts {
+    resolved: HashMap<(String, u16), (Instant, Vec<SocketAddr>)>,
+    limits: HashMap<(String, u16), Arc<Semaphore>>,
+    failed: HashMap<SocketAddr, Instant>,
+}
+
+impl UpstreamPool {
+    /// Creates a new `UpstreamPool`, without a limit on the connections per host.
+    pub fn new() -> Self {
+        UpstreamPool {
+            max_connections_per_host: None,
+            max_idle_per_host: DEFAULT_MAX_IDLE_PER_HOST,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            dns_ttl: DEFAULT_DNS_TTL,
+            unhealthy_for: DEFAULT_UNHEALTHY_FOR,
+            hosts: Arc::new(Mutex::new(Hosts::default())),
+        }
+    }
+
+    /// Sets the number of connections which are open to each host at once, or removes the limit
+    /// if `None` (the default).
+    pub fn with_max_connections_per_host(mut self, max_connections: Option<usize>) -> Self {
+        self.max_connections_per_host = max_connections;
+        self
+    }
+
+    /// Sets the number of idle connections which are kept open to each host (defaults to 32).
+    pub fn with_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.max_idle_per_host = max_idle;
+        self
+    }
+
+    /// Sets how long idle connections are kept open, or keeps them open if `None` (defaults to
+    /// 90 seconds).
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets how long connecting to a single address may take, or disables the timeout if `None`
+    /// (defaults to 10 seconds).
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Sets how long resolved addresses are cached (defaults to 60 seconds).
+    pub fn with_dns_ttl(mut self, dns_ttl: Duration) -> Self {
+        self.dns_ttl = dns_ttl;
+        self
+    }
+
+    /// Sets how long an address which failed to accept a connection is tried last (defaults to
+    /// 10 seconds).
+    pub fn with_unhealthy_for(mut self, unhealthy_for: Duration) -> Self {
+        self.unhealthy_for = unhealthy_for;
+        self
+    }
+
+    pub(super) fn build_client(&self) -> Client<UpstreamPool, Body> {
+        Client::builder()
+            .pool_max_idle_per_host(self.max_idle_per_host)
+            .pool_idle_timeout(self.idle_timeout)
+            .build(self.clone())
+    }
+
+    async fn connect(self, uri: Uri) -> io::Result<UpstreamConnection> {
+        if uri.scheme_str() != Some("http") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only http URIs are supported",
+            ));
+        }
+        let host = uri
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URI without a host"))?;
+        // IPv6 literals are enclosed in brackets
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let key = (host.to_owned(), uri.port_u16().unwrap_or(80));
+
+        let permit = match self.limit(&key) {
+            Some(limit) => Some(limit.acquire_owned().await.expect("limit closed")),
+            None => None,
+        };
+        let addrs = self.resolve(&key).await?;
+
+        let mut last_err = None;
+        for addr in self.by_health(addrs) {
+            let connect = TcpStream::connect(addr);
+            let result = match self.connect_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, connect).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "connecting timed out",
+                    )),
+                },
+                None => connect.await,
+            };
+            match result {
+                Ok(stream) => {
+                    self.hosts.lock().unwrap().failed.remove(&addr);
+                    stream.set_nodelay(true)?;
+                    return Ok(UpstreamConnection {
+                        stream,
+                        _permit: permit,
+                    });
+                }
+                Err(err) => {
+                    debug!("connecting to {} failed: {}", addr, err);
+                    let mut hosts = self.hosts.lock().unwrap();
+                    hosts.failed.insert(addr, Instant::now());
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no addresses")))
+    }
+
+    fn limit(&self, key: &(String, u16)) -> Option<Arc<Semaphore>> {
+        let max_connections = self.max_connections_per_host?;
+        let mut hosts = self.hosts.lock().unwrap();
+        let limit = hosts
+            .limits
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(max_connections)));
+        Some(limit.clone())
+    }
+
+    async fn resolve(&self, key: &(String, u16)) -> io::Result<Vec<SocketAddr>> {
+        if let Some((resolved_at, addrs)) = self.hosts.lock().unwrap().resolved.get(key) {
+            if resolved_at.elapsed() < self.dns_ttl {
+                return Ok(addrs.clone());
+            }
+        }
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((key.0.as_str(), key.1))
+            .await?
+            .collect();
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .resolved
+            .insert(key.clone(), (Instant::now(), addrs.clone()));
+        Ok(addrs)
+    }
+
+    // Moves the addresses which recently failed to accept a connection to the end, keeping the
+    // order of the others. They are still tried, in case all addresses failed.
+    fn by_health(&self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let unhealthy_for = self.unhealthy_for;
+        hosts
+            .failed
+            .retain(|_, failed_at| failed_at.elapsed() < unhealthy_for);
+        let (mut healthy, unhealthy): (Vec<_>, Vec<_>) = addrs
+            .into_iter()
+            .partition(|addr| !hosts.failed.contains_key(addr));
+        healthy.extend(unhealthy);
+        healthy
+    }
+}
+
+impl Default for UpstreamPool {
+    fn default() -> Self {
+        UpstreamPool::new()
+    }
+}
+
+impl Service<Uri> for UpstreamPool {
+    type Response = UpstreamConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UpstreamConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Box::pin(self.clone().connect(uri))
+    }
+}
+
+/// A connection opened by an `UpstreamPool`, which counts towards the limit of connections to its
+/// host until it is closed.
+pub struct UpstreamConnection {
+    stream: TcpStream,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl AsyncRead for UpstreamConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UpstreamConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+impl Connection for UpstreamConnection {
+    fn connected(&self) -> Connected {
+        self.stream.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Request, Response, Server};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Starts a server answering every request, and counting the connections it accepted.
+    fn server() -> (SocketAddr, Arc<AtomicUsize>) {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let make_service = make_service_fn(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            future::ok::<_, Infallible>(service_fn(|_: Request<Body>| async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok::<_, Infallible>(Response::new(Body::from("upstream")))
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, connections)
+    }
+
+    async fn unused_addr() -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn caches_resolved_addresses() {
+        let (addr, _) = server();
+        let pool = UpstreamPool::new();
+        let client = pool.build_client();
+        let uri: Uri = format!("http://localhost:{}/", addr.port())
+            .parse()
+            .unwrap();
+        let key = ("localhost".to_owned(), addr.port());
+
+        client.get(uri.clone()).await.unwrap();
+        let resolved_at = pool.hosts.lock().unwrap().resolved[&key].0;
+        // a new client has no idle connections, so it has to connect again
+        let client = pool.build_client();
+        client.get(uri).await.unwrap();
+        assert_eq!(pool.hosts.lock().unwrap().resolved[&key].0, resolved_at);
+    }
+
+    #[tokio::test]
+    async fn tries_unhealthy_addresses_last() {
+        let (live, _) = server();
+        let dead = unused_addr().await;
+        let pool = UpstreamPool::new();
+        // the host resolves to an address refusing connections before a live one
+        pool.hosts.lock().unwrap().resolved.insert(
+            ("upstream.test".to_owned(), 80),
+            (Instant::now(), vec![dead, live]),
+        );
+
+        let response = pool
+            .build_client()
+            .get("http://upstream.test/".parse().unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "upstream");
+        assert_eq!(pool.by_health(vec![dead, live]), vec![live, dead]);
+
+        // once the address was unhealthy for long enough, its original place is restored
+        let pool = pool.with_unhealthy_for(Duration::ZERO);
+        assert_eq!(pool.by_health(vec![dead, live]), vec![dead, live]);
+    }
+
+    #[tokio::test]
+    async fn limits_connections_per_host() {
+        let (addr, connections) = server();
+        let client = UpstreamPool::new()
+            .with_max_connections_per_host(Some(1))
+            .build_client();
+        let uri: Uri = format!("http://{}/", addr).parse().unwrap();
+
+        // the requests wait for the single connection instead of opening their own
+        let responses = future::join_all((0..3).map(|_| client.get(uri.clone()))).await;
+        for response in responses {
+            assert!(response.unwrap().status().is_success());
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn fails_if_no_address_accepts() {
+        let dead = unused_addr().await;
+        let client = UpstreamPool::new().build_client();
+        let uri: Uri = format!("http://{}/", dead).parse().unwrap();
+        assert!(client.get(uri).await.is_err());
+    }
+}
@@ -0,0 +1,596 @@
+//! Loads the settings of the server from configuration files and the environment.
+//!
+//! `ServerConfig` holds the address to listen on, the number of worker threads, socket options,
+//! TLS certificate paths or whether to offer h2c upgrades, bandwidth limits and timeouts. It is
+//! read from TOML or YAML files, and individual settings can be overridden by `GOTHAM_*`
+//! environment variables, so deployments can be reconfigured without recompiling. `config::start`
+//! then starts the server with these settings.
+//!
+//! Applications can embed a `ServerConfig` into their own configuration type, next to settings
+//! for their middleware, and read it with `config::load`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use gotham::state::State;
+//! use gotham::config::{self, ServerConfig};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct AppConfig {
+//!     server: ServerConfig,
+//!     greeting: String,
+//! }
+//!
+//! fn main() {
+//!     // server.toml:
+//!     //
+//!     // greeting = "Hello"
+//!     //
+//!     // [server]
+//!     // address = "0.0.0.0:8080"
+//!     // threads = 4
+//!     //
+//!     // [server.limits]
+//!     // write_bytes_per_sec = 1048576
+//!     //
+//!     // [server.timeouts]
+//!     // read_secs = 30
+//!     let app: AppConfig = config::load("server.toml").unwrap();
+//!     let server = app.server.with_env_overrides().unwrap();
+//!
+//!     let greeting = app.greeting;
+//!     config::start(&server, move || {
+//!         let greeting = greeting.clone();
+//!         Ok(move |state: State| (state, greeting))
+//!     })
+//!     .unwrap();
+//! }
+//! ```
+
+use std::env;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::future;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::handler::NewHandler;
+use crate::listener::BindOptions;
+use crate::throttle::ThrottleConfig;
+use crate::{bind_server_with_options, new_runtime, tcp_listener, ServerOptions, StartError};
+
+/// The prefix of the environment variables overriding settings of a `ServerConfig`.
+pub const ENV_PREFIX: &str = "GOTHAM_";
+
+/// The error returned when loading a configuration fails.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+    /// The configuration file has an extension other than `toml`, `yaml` or `yml`.
+    #[error("unsupported configuration format: {0}")]
+    UnsupportedFormat(PathBuf),
+    /// The TOML configuration is invalid.
+    #[error("invalid TOML configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// The YAML configuration is invalid.
+    #[error("invalid YAML configuration: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    /// An environment variable has an invalid value.
+    #[error("invalid value for environment variable {name}: {value:?}")]
+    Env {
+        /// The name of the variable.
+        name: String,
+        /// The value of the variable.
+        value: String,
+    },
+}
+
+/// The settings of the server.
+///
+/// All settings are optional when deserializing, and default to the values of the Gotham API,
+/// except for the address, which defaults to `127.0.0.1:7878`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address to listen on, e.g. `0.0.0.0:8080` (env: `GOTHAM_ADDRESS`).
+    pub address: String,
+    /// The number of worker threads, defaults to the number of CPUs (env: `GOTHAM_THREADS`).
+    pub threads: Option<usize>,
+    /// The maximum number of pending connections (env: `GOTHAM_BACKLOG`).
+    pub backlog: Option<i32>,
+    /// Whether a socket bound to an IPv6 address only accepts IPv6 connections
+    /// (env: `GOTHAM_ONLY_V6`).
+    pub only_v6: Option<bool>,
+    /// The certificate and key for serving HTTPS, which requires the `rustls` feature.
+    pub tls: Option<TlsSettings>,
+    /// Whether plaintext connections are upgraded to HTTP/2 on request, see
+    /// `bind_server_with_h2c`. This requires the `http2` feature, and can't be combined with TLS
+    /// (env: `GOTHAM_H2C`).
+    pub h2c: bool,
+    /// The bandwidth limits of each connection.
+    pub limits: Limits,
+    /// The timeouts of each connection and of the shutdown.
+    pub timeouts: Timeouts,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "127.0.0.1:7878".to_owned(),
+            threads: None,
+            backlog: None,
+            only_v6: None,
+            tls: None,
+            h2c: false,
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+/// The paths of the PEM encoded certificate chain and private key for serving HTTPS.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// The path of the certificate chain (env: `GOTHAM_TLS_CERT`).
+    pub cert: PathBuf,
+    /// The path of the private key (env: `GOTHAM_TLS_KEY`).
+    pub key: PathBuf,
+}
+
+/// The bandwidth limits of each connection, see `ThrottleConfig`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// The number of bytes per second read from each connection
+    /// (env: `GOTHAM_READ_BYTES_PER_SEC`).
+    pub read_bytes_per_sec: Option<u64>,
+    /// The number of bytes per second written to each connection
+    /// (env: `GOTHAM_WRITE_BYTES_PER_SEC`).
+    pub write_bytes_per_sec: Option<u64>,
+}
+
+/// The timeouts in seconds, see `ServerOptions`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// The time for reading the headers of a request (env: `GOTHAM_READ_TIMEOUT_SECS`).
+    pub read_secs: Option<u64>,
+    /// The time after which idle connections are closed
+    /// (env: `GOTHAM_KEEP_ALIVE_TIMEOUT_SECS`).
+    pub keep_alive_secs: Option<u64>,
+    /// The time to wait for open connections on shutdown (env: `GOTHAM_SHUTDOWN_TIMEOUT_SECS`).
+    pub shutdown_secs: Option<u64>,
+}
+
+/// Deserializes a configuration of any type from a TOML or YAML file, depending on the extension
+/// of `path`.
+pub fn load<T, P>(path: P) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    match extension {
+        Some("toml") | Some("yaml") | Some("yml") => {}
+        _ => return Err(ConfigError::UnsupportedFormat(path.to_owned())),
+    }
+
+    let content = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+    match extension {
+        Some("toml") => Ok(toml::from_str(&content)?),
+        _ => Ok(serde_yaml::from_str(&content)?),
+    }
+}
+
+impl ServerConfig {
+    /// Reads a `ServerConfig` from a TOML or YAML file, depending on the extension of `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        load(path)
+    }
+
+    /// Parses a `ServerConfig` from TOML.
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// Parses a `ServerConfig` from YAML.
+    pub fn from_yaml(yaml: &str) -> Result<Self, ConfigError> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Overrides settings with the `GOTHAM_*` environment variables which are set. The TLS
+    /// settings are only overridden if both `GOTHAM_TLS_CERT` and `GOTHAM_TLS_KEY` are set.
+    pub fn with_env_overrides(self) -> Result<Self, ConfigError> {
+        self.with_overrides(|name| env::var(format!("{}{}", ENV_PREFIX, name)).ok())
+    }
+
+    fn with_overrides<F>(mut self, var: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        fn parse<T: std::str::FromStr>(name: &str, value: String) -> Result<T, ConfigError> {
+            value.parse().map_err(|_| ConfigError::Env {
+                name: format!("{}{}", ENV_PREFIX, name),
+                value,
+            })
+        }
+
+        if let Some(address) = var("ADDRESS") {
+            self.address = address;
+        }
+        if let Some(threads) = var("THREADS") {
+            self.threads = Some(parse("THREADS", threads)?);
+        }
+        if let Some(backlog) = var("BACKLOG") {
+            self.backlog = Some(parse("BACKLOG", backlog)?);
+        }
+        if let Some(only_v6) = var("ONLY_V6") {
+            self.only_v6 = Some(parse("ONLY_V6", only_v6)?);
+        }
+        if let (Some(cert), Some(key)) = (var("TLS_CERT"), var("TLS_KEY")) {
+            self.tls = Some(TlsSettings {
+                cert: cert.into(),
+                key: key.into(),
+            });
+        }
+        if let Some(h2c) = var("H2C") {
+            self.h2c = parse("H2C", h2c)?;
+        }
+        if let Some(limit) = var("READ_BYTES_PER_SEC") {
+            self.limits.read_bytes_per_sec = Some(parse("READ_BYTES_PER_SEC", limit)?);
+        }
+        if let Some(limit) = var("WRITE_BYTES_PER_SEC") {
+            self.limits.write_bytes_per_sec = Some(parse("WRITE_BYTES_PER_SEC", limit)?);
+        }
+        if let Some(timeout) = var("READ_TIMEOUT_SECS") {
+            self.timeouts.read_secs = Some(parse("READ_TIMEOUT_SECS", timeout)?);
+        }
+        if let Some(timeout) = var("KEEP_ALIVE_TIMEOUT_SECS") {
+            self.timeouts.keep_alive_secs = Some(parse("KEEP_ALIVE_TIMEOUT_SECS", timeout)?);
+        }
+        if let Some(timeout) = var("SHUTDOWN_TIMEOUT_SECS") {
+            self.timeouts.shutdown_secs = Some(parse("SHUTDOWN_TIMEOUT_SECS", timeout)?);
+        }
+        Ok(self)
+    }
+
+    /// Returns the `BindOptions` for the listening socket.
+    pub fn bind_options(&self) -> BindOptions {
+        let mut options = BindOptions::new();
+        if let Some(only_v6) = self.only_v6 {
+            options = options.only_v6(only_v6);
+        }
+        if let Some(backlog) = self.backlog {
+            options = options.backlog(backlog);
+        }
+        options
+    }
+
+    /// Returns the `ThrottleConfig` for each connection, if any limit is set.
+    pub fn throttle(&self) -> Option<ThrottleConfig> {
+        let limits = &self.limits;
+        if limits.read_bytes_per_sec.is_none() && limits.write_bytes_per_sec.is_none() {
+            return None;
+        }
+
+        let mut config = ThrottleConfig::new();
+        if let Some(limit) = limits.read_bytes_per_sec {
+            config = config.with_read_limit(limit);
+        }
+        if let Some(limit) = limits.write_bytes_per_sec {
+            config = config.with_write_limit(limit);
+        }
+        Some(config)
+    }
+
+    /// Returns the `ServerOptions` with the bandwidth limits and timeouts of each connection.
+    pub fn server_options(&self) -> ServerOptions {
+        let mut options = ServerOptions::new();
+        if let Some(throttle) = self.throttle() {
+            options = options.with_throttle(throttle);
+        }
+        let timeouts = &self.timeouts;
+        if let Some(secs) = timeouts.read_secs {
+            options = options.with_read_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = timeouts.keep_alive_secs {
+            options = options.with_keep_alive_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = timeouts.shutdown_secs {
+            options = options.with_shutdown_timeout(Duration::from_secs(secs));
+        }
+        options
+    }
+
+    /// Loads the TLS certificate chain and private key into a `rustls::ServerConfig`.
+    #[cfg(feature = "rustls")]
+    pub fn tls_config(&self) -> Result<Option<crate::rustls::ServerConfig>, StartError> {
+        use crate::rustls::{Certificate, PrivateKey, ServerConfig};
+        use std::io::BufReader;
+
+        let tls = match &self.tls {
+            Some(tls) => tls,
+            None => return Ok(None),
+        };
+        let open = |path: &Path| fs::File::open(path).map(BufReader::new);
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let certs = rustls_pemfile::certs(&mut open(&tls.cert)?)?
+            .into_iter()
+            .map(Certificate)
+            .collect::<Vec<_>>();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut open(&tls.key)?)?
+            .into_iter()
+            .chain(rustls_pemfile::rsa_private_keys(&mut open(&tls.key)?)?)
+            .next()
+            .map(PrivateKey)
+            .ok_or_else(|| invalid(format!("no private key in {}", tls.key.display())))?;
+
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|err| invalid(err.to_string()))?;
+        Ok(Some(config))
+    }
+}
+
+/// Starts a Gotham application with the given settings.
+pub fn start<NH>(config: &ServerConfig, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+{
+    let runtime = new_runtime(config.threads.unwrap_or_else(num_cpus::get));
+    runtime.block_on(init_server(config, new_handler))
+}
+
+/// Returns a `Future` used to spawn a Gotham application with the given settings.
+///
+/// Serving HTTPS requires the `rustls` feature, without which TLS settings are rejected. So are
+/// h2c upgrades without the `http2` feature, or together with TLS.
+pub async fn init_server<NH>(config: &ServerConfig, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+{
+    init_server_with_shutdown(config, new_handler, future::pending()).await
+}
+
+/// Returns a `Future` used to spawn a Gotham application with the given settings, which is shut
+/// down once `signal` completes.
+///
+/// Open connections are given up to the shutdown timeout of the configuration to complete the
+/// requests in progress, see `bind_server_with_options`.
+pub async fn init_server_with_shutdown<NH, Sig>(
+    config: &ServerConfig,
+    new_handler: NH,
+    signal: Sig,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    Sig: Future<Output = ()>,
+{
+    #[cfg(feature = "rustls")]
+    let tls_config = config.tls_config()?;
+    #[cfg(not(feature = "rustls"))]
+    if config.tls.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "serving HTTPS requires the rustls feature of gotham",
+        )
+        .into());
+    }
+    if config.h2c && (config.tls.is_some() || cfg!(not(feature = "http2"))) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "h2c upgrades require the http2 feature of gotham, and are only offered without TLS",
+        )
+        .into());
+    }
+
+    let listener = tcp_listener(config.address.clone(), config.bind_options()).await?;
+    let addr = listener.local_addr().unwrap();
+    let options = config.server_options();
+
+    #[cfg(feature = "rustls")]
+    if let Some(tls_config) = tls_config {
+        info! {
+            target: "gotham::start",
+            " Gotham listening on https://{}", addr
+        }
+        let wrap = crate::tls::rustls_wrap(tls_config);
+        bind_server_with_options(listener, new_handler, wrap, options, signal).await;
+        return Ok(());
+    }
+
+    info! {
+        target: "gotham::start",
+        " Gotham listening on http://{}", addr
+    }
+    // the option can't be set publicly, as connections wrapped in TLS must not be upgraded
+    #[cfg(feature = "http2")]
+    let options = if config.h2c {
+        options.with_h2c()
+    } else {
+        options
+    };
+    bind_server_with_options(listener, new_handler, future::ok, options, signal).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_toml_and_yaml() {
+        let toml = r#"
+            address = "0.0.0.0:8080"
+            threads = 4
+
+            [tls]
+            cert = "cert.pem"
+            key = "key.pem"
+
+            [limits]
+            write_bytes_per_sec = 1024
+
+            [timeouts]
+            read_secs = 30
+        "#;
+        let yaml = r#"
+            address: "0.0.0.0:8080"
+            threads: 4
+            tls:
+              cert: cert.pem
+              key: key.pem
+            limits:
+              write_bytes_per_sec: 1024
+            timeouts:
+              read_secs: 30
+        "#;
+
+        let expected = ServerConfig {
+            address: "0.0.0.0:8080".to_owned(),
+            threads: Some(4),
+            tls: Some(TlsSettings {
+                cert: "cert.pem".into(),
+                key: "key.pem".into(),
+            }),
+            limits: Limits {
+                read_bytes_per_sec: None,
+                write_bytes_per_sec: Some(1024),
+            },
+            timeouts: Timeouts {
+                read_secs: Some(30),
+                ..Timeouts::default()
+            },
+            ..ServerConfig::default()
+        };
+        assert_eq!(ServerConfig::from_toml(toml).unwrap(), expected);
+        assert_eq!(ServerConfig::from_yaml(yaml).unwrap(), expected);
+
+        let throttle = expected.throttle().unwrap();
+        assert_eq!(throttle.read_limit(), None);
+        assert_eq!(throttle.write_limit(), Some(1024));
+        assert!(ServerConfig::default().throttle().is_none());
+
+        let options = expected.server_options();
+        assert_eq!(options.throttle(), Some(throttle));
+        assert_eq!(options.read_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(options.keep_alive_timeout(), None);
+        assert_eq!(
+            ServerConfig::default().server_options(),
+            ServerOptions::new()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(matches!(
+            ServerConfig::from_toml("adress = \"0.0.0.0:8080\""),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn overrides_from_env() {
+        let vars: HashMap<&str, &str> = vec![
+            ("ADDRESS", "[::]:80"),
+            ("BACKLOG", "16"),
+            ("ONLY_V6", "false"),
+            ("TLS_CERT", "cert.pem"),
+            ("H2C", "true"),
+            ("KEEP_ALIVE_TIMEOUT_SECS", "5"),
+            ("SHUTDOWN_TIMEOUT_SECS", "10"),
+        ]
+        .into_iter()
+        .collect();
+        let config = ServerConfig::default()
+            .with_overrides(|name| vars.get(name).map(|value| value.to_string()))
+            .unwrap();
+
+        assert_eq!(config.address, "[::]:80");
+        assert_eq!(config.bind_options(), BindOptions::dual_stack().backlog(16));
+        // the key is missing
+        assert!(config.tls.is_none());
+        assert!(config.h2c);
+        assert_eq!(
+            config.server_options(),
+            ServerOptions::new()
+                .with_keep_alive_timeout(Duration::from_secs(5))
+                .with_shutdown_timeout(Duration::from_secs(10))
+        );
+
+        let err = ServerConfig::default()
+            .with_overrides(|name| {
+                Some(name)
+                    .filter(|name| *name == "THREADS")
+                    .map(|_| "many".to_owned())
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value for environment variable GOTHAM_THREADS: \"many\""
+        );
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn loads_tls_config() {
+        use base64::prelude::*;
+
+        fn pem(label: &str, der: &[u8]) -> String {
+            format!(
+                "-----BEGIN {0}-----\n{1}\n-----END {0}-----\n",
+                label,
+                BASE64_STANDARD.encode(der)
+            )
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        let key = dir.path().join("key.pem");
+        let cert_pem = pem("CERTIFICATE", include_bytes!("tls/tls_cert.der"));
+        fs::write(&cert, cert_pem).unwrap();
+        fs::write(&key, pem("PRIVATE KEY", include_bytes!("tls/tls_key.der"))).unwrap();
+
+        let mut config = ServerConfig {
+            tls: Some(TlsSettings {
+                cert: cert.clone(),
+                key: key.clone(),
+            }),
+            ..ServerConfig::default()
+        };
+        assert!(config.tls_config().unwrap().is_some());
+        assert!(ServerConfig::default().tls_config().unwrap().is_none());
+
+        // the certificate does not contain a private key
+        config.tls = Some(TlsSettings {
+            cert: cert.clone(),
+            key: cert,
+        });
+        let err = config.tls_config().unwrap_err().to_string();
+        assert!(err.contains("no private key"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unsupported_format() {
+        assert!(matches!(
+            ServerConfig::from_file("server.ini"),
+            Err(ConfigError::UnsupportedFormat(_))
+        ));
+    }
+}
@@ -20,6 +20,8 @@ use crate::router::tree::segment::SegmentMapping;
 /// `PathExtractor` provided by the application.
 #[derive(Debug)]
 #[non_exhaustive]
+// The fields are only read through the `Debug` impl, when the error is displayed.
+#[allow(dead_code)]
 pub(crate) enum ExtractorError {
     /// The `PathExtractor` type is not one which can be deserialized from a
     /// `ExtractorDeserializer`.  This deserializer requires a structured type (usually a custom
@@ -766,8 +768,8 @@ mod tests {
         assert_eq!(p.u16_val, 40511);
         assert_eq!(p.u32_val, 4_000_000_000);
         assert_eq!(p.u64_val, 9_000_000_000);
-        assert!((p.f32_val - 1.4).abs() < std::f32::EPSILON);
-        assert!((p.f64_val - 2.6).abs() < std::f64::EPSILON);
+        assert!((p.f32_val - 1.4).abs() < f32::EPSILON);
+        assert!((p.f64_val - 2.6).abs() < f64::EPSILON);
         assert_eq!(p.string_val, "this is an owned string");
         assert_eq!(p.char_val, 'a');
         assert_eq!(p.optional_val, Some("this is optional".to_owned()));
@@ -845,8 +847,8 @@ mod tests {
         assert_eq!(p.u16_val, 40511);
         assert_eq!(p.u32_val, 4_000_000_000);
         assert_eq!(p.u64_val, 9_000_000_000);
-        assert!((p.f32_val - 1.4).abs() < std::f32::EPSILON);
-        assert!((p.f64_val - 2.6).abs() < std::f64::EPSILON);
+        assert!((p.f32_val - 1.4).abs() < f32::EPSILON);
+        assert!((p.f64_val - 2.6).abs() < f64::EPSILON);
         assert_eq!(p.string_val, "this is an owned string");
         assert_eq!(p.char_val, 'a');
         assert_eq!(p.optional_val, Some("this is optional".to_owned()));
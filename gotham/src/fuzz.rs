@@ -0,0 +1,173 @@
+//! Entry points for fuzzing the request handling of Gotham with arbitrary input.
+//!
+//! The functions in this module accept raw bytes, as provided by fuzzers like `cargo fuzz`, and
+//! never panic because of malformed input. Input which can't be turned into a request is
+//! rejected, so any panic observed while fuzzing is a bug in the code under test, e.g. in path
+//! parsing, percent-decoding or the deserialization of extractors. The harnesses for Gotham itself
+//! are in the `fuzz` directory of the repository.
+//!
+//! # Examples
+//!
+//! A `cargo fuzz` target for the router of an application:
+//!
+//! ```rust,ignore
+//! #![no_main]
+//! use libfuzzer_sys::fuzz_target;
+//!
+//! fuzz_target!(|data: &[u8]| {
+//!     let _ = gotham::fuzz::route_request_bytes(&my_app::router(), data);
+//! });
+//! ```
+
+use std::cell::RefCell;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use hyper::{body, Body, Method, Request, StatusCode};
+use serde::de::DeserializeOwned;
+use tokio::runtime::{self, Runtime};
+
+use crate::extractor::internal::from_query_string_mapping;
+use crate::handler::{Handler, NewHandler};
+use crate::helpers::http::request::query_string;
+use crate::state::State;
+
+thread_local! {
+    static RUNTIME: RefCell<Option<Runtime>> = const { RefCell::new(None) };
+}
+
+/// Builds a request from `data` and dispatches it to a `Handler` spawned by `new_handler`, usually
+/// a `Router`, returning the status code of the response once its body has been read.
+///
+/// The first line of `data` is the request line, consisting of the method and the request target
+/// separated by a space (e.g. `GET /users/42?format=json`). If the method is omitted, `GET` is
+/// used. The remaining bytes are sent as the request body. Returns `None` if `data` does not
+/// describe a valid request, or if `new_handler` fails to spawn a handler.
+///
+/// Unlike the server, this does not catch panics of the handler, so fuzzers can detect them. The
+/// request is handled on a single-threaded runtime which is reused by subsequent calls on the same
+/// thread.
+pub fn route_request_bytes<NH>(new_handler: &NH, data: &[u8]) -> Option<StatusCode>
+where
+    NH: NewHandler,
+{
+    let request = parse_request(data)?;
+    let handler = new_handler.new_handler().ok()?;
+    let client_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    let state = State::from_request(request, client_addr);
+
+    block_on(async move {
+        let response = match handler.handle(state).await {
+            Ok((_, response)) => response,
+            Err((_, err)) => return Some(err.status()),
+        };
+        let status = response.status();
+        body::to_bytes(response.into_body()).await.ok()?;
+        Some(status)
+    })
+}
+
+/// Deserializes a value of type `T` from the query string `data`, exactly like a
+/// `QueryStringExtractor` would from the query string of a request. Fails if `data` is not valid
+/// UTF-8, or if the query string does not match `T`.
+pub fn parse_query<T>(data: &[u8]) -> anyhow::Result<T>
+where
+    T: DeserializeOwned,
+{
+    let query = std::str::from_utf8(data)?;
+    let query_string_mapping = query_string::split(Some(query));
+    Ok(from_query_string_mapping(&query_string_mapping)?)
+}
+
+fn parse_request(data: &[u8]) -> Option<Request<Body>> {
+    let (line, body) = match data.iter().position(|b| *b == b'\n') {
+        Some(index) => (&data[..index], &data[index + 1..]),
+        None => (data, &[][..]),
+    };
+    let line = std::str::from_utf8(line).ok()?.trim_end_matches('\r');
+    let (method, target) = match line.split_once(' ') {
+        Some((method, target)) => (Method::from_bytes(method.as_bytes()).ok()?, target),
+        None => (Method::GET, line),
+    };
+
+    Request::builder()
+        .method(method)
+        .uri(target)
+        .body(Body::from(body.to_vec()))
+        .ok()
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    RUNTIME.with(|runtime| {
+        let mut runtime = runtime.borrow_mut();
+        let runtime = runtime.get_or_insert_with(|| {
+            runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("unable to create fuzzing runtime")
+        });
+        runtime.block_on(future)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use serde::Deserialize;
+
+    fn handler(state: State) -> (State, &'static str) {
+        (state, "Hello")
+    }
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.post("/users/:id").to(handler);
+        })
+    }
+
+    #[test]
+    fn routes_requests() {
+        let router = router();
+        let route = |data: &[u8]| route_request_bytes(&router, data);
+
+        assert_eq!(route(b"/"), Some(StatusCode::OK));
+        assert_eq!(route(b"POST /users/42\r\nbody"), Some(StatusCode::OK));
+        assert_eq!(
+            route(b"GET /users/42"),
+            Some(StatusCode::METHOD_NOT_ALLOWED)
+        );
+        assert_eq!(route(b"GET /missing?a=%zz"), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let router = router();
+        assert_eq!(route_request_bytes(&router, b"GET /\xff"), None);
+        assert_eq!(route_request_bytes(&router, b"G(T /"), None);
+        assert_eq!(route_request_bytes(&router, b"GET /a b"), None);
+    }
+
+    #[test]
+    fn parses_query_strings() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Params {
+            name: String,
+            ids: Vec<u32>,
+            page: Option<u8>,
+        }
+
+        let params = parse_query::<Params>(b"name=caf%C3%A9&ids=1&ids=2").unwrap();
+        assert_eq!(
+            params,
+            Params {
+                name: "caf\u{e9}".to_owned(),
+                ids: vec![1, 2],
+                page: None
+            }
+        );
+        assert!(parse_query::<Params>(b"name=a&ids=x").is_err());
+        assert!(parse_query::<Params>(b"\xff").is_err());
+    }
+}
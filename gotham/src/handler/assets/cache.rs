@@ -0,0 +1,234 @@
+//! An in-memory cache for the content of small, frequently requested files.
+
+use bytes::Bytes;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+type PendingRead = Shared<BoxFuture<'static, Result<Bytes, Arc<io::Error>>>>;
+
+struct CachedFile {
+    contents: Bytes,
+    modified: SystemTime,
+    last_used: u64,
+}
+
+struct Entries {
+    files: HashMap<PathBuf, CachedFile>,
+    // incremented on every access, to find the least recently used entry
+    clock: u64,
+}
+
+// Keeps the content of up to `max_entries` files of at most `max_file_size` bytes, evicting the
+// least recently used file when full. Cached content is valid as long as the size and
+// modification time of the file are unchanged.
+pub(super) struct FileCache {
+    max_entries: usize,
+    max_file_size: u64,
+    entries: Mutex<Entries>,
+    // reads in progress by path, size and modification time of the file
+    pending: Mutex<HashMap<(PathBuf, u64, SystemTime), PendingRead>>,
+}
+
+impl FileCache {
+    pub(super) fn new(max_entries: usize, max_file_size: u64) -> Self {
+        FileCache {
+            max_entries,
+            max_file_size,
+            entries: Mutex::new(Entries {
+                files: HashMap::new(),
+                clock: 0,
+            }),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Checks whether a file with the given metadata may be kept in the cache.
+    pub(super) fn admits(&self, metadata: &Metadata) -> bool {
+        self.max_entries > 0
+            && metadata.is_file()
+            && metadata.len() <= self.max_file_size
+            && metadata.modified().is_ok()
+    }
+
+    // Returns the cached content of the file at `path`, unless the file changed since.
+    pub(super) fn get(&self, path: &Path, metadata: &Metadata) -> Option<Bytes> {
+        let modified = metadata.modified().ok()?;
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let cached = entries.files.get_mut(path)?;
+        if cached.modified != modified || cached.contents.len() as u64 != metadata.len() {
+            entries.files.remove(path);
+            return None;
+        }
+        cached.last_used = clock;
+        Some(cached.contents.clone())
+    }
+
+    // Reads the file at `path` with the given metadata and adds its content to the cache. Requests
+    // of the same file arriving while it is read share a single read, rather than each reading
+    // the file, e.g. during a spike of requests for a file which is not cached yet. Returns `None`
+    // if the file changed while it was read.
+    pub(super) async fn load(&self, path: &Path, metadata: &Metadata) -> io::Result<Option<Bytes>> {
+        let modified = metadata.modified()?;
+        let key = (path.to_path_buf(), metadata.len(), modified);
+        let read = self
+            .pending
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let path = path.to_path_buf();
+                async move {
+                    tokio::fs::read(path)
+                        .await
+                        .map(Bytes::from)
+                        .map_err(Arc::new)
+                }
+                .boxed()
+                .shared()
+            })
+            .clone();
+        let result = read.await;
+        self.pending.lock().unwrap().remove(&key);
+
+        let contents = result.map_err(|err| io::Error::new(err.kind(), err.to_string()))?;
+        if contents.len() as u64 != metadata.len() {
+            return Ok(None);
+        }
+        self.insert(path, metadata, contents.clone());
+        Ok(Some(contents))
+    }
+
+    pub(super) fn insert(&self, path: &Path, metadata: &Metadata, contents: Bytes) {
+        let modified = match metadata.modified() {
+            Ok(modified) => modified,
+            Err(_) => return,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.files.contains_key(path) && entries.files.len() >= self.max_entries {
+            let lru = entries
+                .files
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(lru, _)| lru.clone());
+            if let Some(lru) = lru {
+                entries.files.remove(&lru);
+            }
+        }
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.files.insert(
+            path.to_path_buf(),
+            CachedFile {
+                contents,
+                modified,
+                last_used,
+            },
+        );
+    }
+}
+
+impl PartialEq for FileCache {
+    fn eq(&self, other: &FileCache) -> bool {
+        self.max_entries == other.max_entries && self.max_file_size == other.max_file_size
+    }
+}
+
+impl Eq for FileCache {}
+
+impl fmt::Debug for FileCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileCache")
+            .field("max_entries", &self.max_entries)
+            .field("max_file_size", &self.max_file_size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn evicts_least_recently_used_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "c"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for path in &paths {
+            fs::write(path, "content").unwrap();
+        }
+        let meta = |path: &PathBuf| fs::metadata(path).unwrap();
+
+        let cache = FileCache::new(2, 16);
+        cache.insert(&paths[0], &meta(&paths[0]), Bytes::from("content"));
+        cache.insert(&paths[1], &meta(&paths[1]), Bytes::from("content"));
+        assert!(cache.get(&paths[0], &meta(&paths[0])).is_some());
+
+        cache.insert(&paths[2], &meta(&paths[2]), Bytes::from("content"));
+        assert!(cache.get(&paths[0], &meta(&paths[0])).is_some());
+        assert!(cache.get(&paths[1], &meta(&paths[1])).is_none());
+        assert!(cache.get(&paths[2], &meta(&paths[2])).is_some());
+    }
+
+    #[tokio::test]
+    async fn shares_concurrent_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, "content").unwrap();
+        let meta = fs::metadata(&path).unwrap();
+
+        let cache = FileCache::new(8, 16);
+        // a read in progress which completes only once all requests are waiting for it, as
+        // reading the file itself may complete before the other requests arrive
+        let (sender, receiver) = oneshot::channel::<Bytes>();
+        let read = async move {
+            receiver
+                .await
+                .map_err(|_| Arc::new(io::Error::from(io::ErrorKind::Interrupted)))
+        };
+        let key = (path.clone(), meta.len(), meta.modified().unwrap());
+        cache
+            .pending
+            .lock()
+            .unwrap()
+            .insert(key, read.boxed().shared());
+
+        let loads = (0..4).map(|_| cache.load(&path, &meta));
+        let (contents, _) = tokio::join!(futures_util::future::try_join_all(loads), async {
+            tokio::task::yield_now().await;
+            sender.send(Bytes::from("content")).unwrap();
+        });
+        let contents: Vec<Bytes> = contents.unwrap().into_iter().map(Option::unwrap).collect();
+        assert!(contents.iter().all(|c| c == "content"));
+        // all requests received the content of the same read
+        assert!(contents.iter().all(|c| c.as_ptr() == contents[0].as_ptr()));
+        assert!(cache.pending.lock().unwrap().is_empty());
+        assert!(cache.get(&path, &meta).is_some());
+    }
+
+    #[test]
+    fn admits_small_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small");
+        let large = dir.path().join("large");
+        fs::write(&small, "small").unwrap();
+        fs::write(&large, "larger than the limit").unwrap();
+
+        let cache = FileCache::new(8, 16);
+        assert!(cache.admits(&fs::metadata(&small).unwrap()));
+        assert!(!cache.admits(&fs::metadata(&large).unwrap()));
+        assert!(!cache.admits(&fs::metadata(dir.path()).unwrap()));
+    }
+}
@@ -0,0 +1,263 @@
+//! Compresses static assets on the fly, for clients accepting gzip or deflate when no
+//! pre-compressed file is available. Also used by the `CompressionMiddleware` for responses of
+//! any handler.
+
+use brotli::CompressorWriter;
+use bytes::Bytes;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use futures_util::stream::{self, TryStream, TryStreamExt};
+use mime::Mime;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use super::accepted_encoding::AcceptedEncoding;
+
+/// The default minimum size of files to compress on the fly.
+pub(crate) const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// The default compression level, a tradeoff between size and speed suitable for compressing on
+/// the fly.
+pub(crate) const DEFAULT_LEVEL: u32 = 6;
+
+/// The default types of files to compress on the fly.
+pub(crate) const DEFAULT_TYPES: &[&str] = &[
+    "text/*",
+    "application/javascript",
+    "application/json",
+    "application/wasm",
+    "application/xml",
+    "image/svg+xml",
+];
+
+/// An encoding supported for compression on the fly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+// Returns the preferred encoding accepted by the client out of the `supported` ones. Of several
+// encodings with the same quality, the first one listed by the client is used.
+pub(crate) fn negotiate(accepted: &[AcceptedEncoding], supported: &[Encoding]) -> Option<Encoding> {
+    accepted.iter().filter(|e| e.quality > 0.0).find_map(|e| {
+        supported
+            .iter()
+            .copied()
+            .find(|encoding| e.encoding.eq_ignore_ascii_case(encoding.as_str()))
+    })
+}
+
+// Checks whether the given mime type matches one of the patterns, which are either a full type
+// like `application/json`, or a type with a wildcard subtype like `text/*`.
+pub(crate) fn is_compressible(mime: &Mime, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.split_once('/') {
+            Some((type_, "*")) => mime.type_().as_str().eq_ignore_ascii_case(type_),
+            Some(_) => mime.essence_str().eq_ignore_ascii_case(pattern),
+            None => false,
+        })
+}
+
+// The output of the brotli encoder, which is shared since the encoder owns its writer until it
+// is dropped.
+#[derive(Clone, Default)]
+struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl SharedOutput {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Brotli(Box<CompressorWriter<SharedOutput>>, SharedOutput),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    // Creates an encoder compressing with the given level from 0 to 9.
+    fn new(encoding: Encoding, level: u32) -> Self {
+        let level = level.min(9);
+        match encoding {
+            Encoding::Brotli => {
+                let output = SharedOutput::default();
+                let writer = CompressorWriter::new(output.clone(), 4096, level, 22);
+                Encoder::Brotli(Box::new(writer), output)
+            }
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), Compression::new(level))),
+            Encoding::Deflate => {
+                Encoder::Deflate(ZlibEncoder::new(Vec::new(), Compression::new(level)))
+            }
+        }
+    }
+
+    // Compresses the chunk, returning the compressed output available so far.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let output = match self {
+            Encoder::Brotli(encoder, output) => {
+                encoder.write_all(chunk)?;
+                return Ok(Bytes::from(output.take()));
+            }
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Encoder::Brotli(encoder, output) => {
+                // the stream is finished when the encoder is dropped
+                drop(encoder);
+                output.take()
+            }
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Deflate(encoder) => encoder.finish()?,
+        };
+        Ok(Bytes::from(output))
+    }
+}
+
+// Compresses the given stream with the given encoding and the default level.
+pub(super) fn compress<S>(
+    stream: S,
+    encoding: Encoding,
+) -> impl TryStream<Ok = Bytes, Error = io::Error> + Send
+where
+    S: TryStream<Ok = Bytes, Error = io::Error> + Send + Unpin,
+{
+    compress_with_level(stream, encoding, DEFAULT_LEVEL)
+}
+
+// Compresses the given stream with the given encoding and level from 0 to 9.
+pub(crate) fn compress_with_level<S>(
+    stream: S,
+    encoding: Encoding,
+    level: u32,
+) -> impl TryStream<Ok = Bytes, Error = io::Error> + Send
+where
+    S: TryStream<Ok = Bytes, Error = io::Error> + Send + Unpin,
+{
+    stream::try_unfold(
+        (stream, Some(Encoder::new(encoding, level))),
+        |(mut stream, encoder)| async move {
+            let mut encoder = match encoder {
+                Some(encoder) => encoder,
+                None => return Ok(None),
+            };
+            match stream.try_next().await? {
+                Some(chunk) => {
+                    let output = encoder.write(&chunk)?;
+                    Ok(Some((output, (stream, Some(encoder)))))
+                }
+                None => Ok(Some((encoder.finish()?, (stream, None)))),
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::assets::accepted_encoding::accepted_encodings;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use futures_executor::block_on;
+    use hyper::header::{HeaderMap, ACCEPT_ENCODING};
+    use std::io::Read;
+
+    #[test]
+    fn negotiates_encoding() {
+        let supported = [Encoding::Gzip, Encoding::Deflate];
+        let mut headers = HeaderMap::new();
+        assert_eq!(negotiate(&accepted_encodings(&headers), &supported), None);
+
+        headers.insert(
+            ACCEPT_ENCODING,
+            "br, deflate;q=0.5, gzip;q=0".parse().unwrap(),
+        );
+        assert_eq!(
+            negotiate(&accepted_encodings(&headers), &supported),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(
+            negotiate(&accepted_encodings(&headers), &[Encoding::Brotli]),
+            Some(Encoding::Brotli)
+        );
+
+        headers.insert(ACCEPT_ENCODING, "deflate;q=0.5, gzip".parse().unwrap());
+        assert_eq!(
+            negotiate(&accepted_encodings(&headers), &supported),
+            Some(Encoding::Gzip)
+        );
+    }
+
+    #[test]
+    fn matches_compressible_types() {
+        let patterns: Vec<String> = DEFAULT_TYPES.iter().map(|t| t.to_string()).collect();
+        assert!(is_compressible(&mime::TEXT_HTML_UTF_8, &patterns));
+        assert!(is_compressible(&mime::APPLICATION_JSON, &patterns));
+        assert!(!is_compressible(&mime::IMAGE_PNG, &patterns));
+        assert!(!is_compressible(&mime::APPLICATION_OCTET_STREAM, &patterns));
+    }
+
+    #[test]
+    fn compresses_stream() {
+        for &encoding in &[Encoding::Brotli, Encoding::Gzip, Encoding::Deflate] {
+            let chunks: Vec<io::Result<Bytes>> = vec![
+                Ok(Bytes::from_static(b"hello ")),
+                Ok(Bytes::from_static(b"world")),
+            ];
+            let compressed: Vec<Bytes> = block_on(
+                compress(stream::iter(chunks), encoding)
+                    .into_stream()
+                    .try_collect(),
+            )
+            .unwrap();
+            let compressed = compressed.concat();
+
+            let mut decompressed = String::new();
+            match encoding {
+                Encoding::Brotli => brotli::Decompressor::new(&compressed[..], 4096)
+                    .read_to_string(&mut decompressed),
+                Encoding::Gzip => GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed),
+                Encoding::Deflate => {
+                    ZlibDecoder::new(&compressed[..]).read_to_string(&mut decompressed)
+                }
+            }
+            .unwrap();
+            assert_eq!(decompressed, "hello world");
+        }
+    }
+}
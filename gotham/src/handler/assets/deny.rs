@@ -0,0 +1,119 @@
+//! Rules for paths below the root of a `to_dir` route which are never served, like hidden files
+//! or files matching glob patterns, and the glob matching shared with other per-path rules.
+
+use std::path::{Component, Path};
+
+// Checks whether the path relative to the root directory is denied, because one of its
+// components is hidden or it matches one of the patterns.
+pub(super) fn is_denied(path: &Path, deny_hidden: bool, patterns: &[String]) -> bool {
+    let segments = segments(path);
+    if deny_hidden && segments.iter().any(|segment| segment.starts_with('.')) {
+        return true;
+    }
+    patterns
+        .iter()
+        .any(|pattern| matches_pattern(pattern, &segments))
+}
+
+// Checks whether the path relative to the root directory matches the glob pattern.
+pub(super) fn matches_glob(pattern: &str, path: &Path) -> bool {
+    matches_pattern(pattern, &segments(path))
+}
+
+fn segments(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Patterns without a `/` are matched against every component of the path, other patterns
+// against the whole path.
+fn matches_pattern(pattern: &str, segments: &[String]) -> bool {
+    let pattern = pattern.trim_matches('/');
+    if !pattern.contains('/') {
+        return segments
+            .iter()
+            .any(|segment| matches_name(&chars(pattern), &chars(segment)));
+    }
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    matches_segments(&pattern, &segments)
+}
+
+// Matches path segments, where a `**` segment of the pattern matches any number of segments.
+fn matches_segments(pattern: &[&str], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => {
+            matches_segments(rest, segments)
+                || (!segments.is_empty() && matches_segments(pattern, &segments[1..]))
+        }
+        Some((first, rest)) => match segments.split_first() {
+            Some((segment, remaining)) => {
+                matches_name(&chars(first), &chars(segment)) && matches_segments(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+// Matches a single segment, where `*` matches any number of characters and `?` matches a single
+// character.
+fn matches_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_name(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_name(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && matches_name(rest, &name[1..]),
+    }
+}
+
+fn chars(s: &str) -> Vec<char> {
+    s.chars().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(path: &str, patterns: &[&str]) -> bool {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        is_denied(Path::new(path), false, &patterns)
+    }
+
+    #[test]
+    fn denies_hidden_paths() {
+        assert!(is_denied(Path::new(".env"), true, &[]));
+        assert!(is_denied(Path::new("app/.git/config"), true, &[]));
+        assert!(!is_denied(Path::new("app/config"), true, &[]));
+        assert!(!is_denied(Path::new(".env"), false, &[]));
+    }
+
+    #[test]
+    fn denies_matching_paths() {
+        assert!(denied("keys.secret", &["*.secret"]));
+        assert!(denied("nested/dir/keys.secret", &["*.secret"]));
+        assert!(!denied("keys.secret.txt", &["*.secret"]));
+
+        assert!(denied(".git", &[".git/**"]));
+        assert!(denied(".git/objects/ab/cdef", &[".git/**"]));
+        assert!(!denied("app/.git/config", &[".git/**"]));
+        assert!(denied("app/.git/config", &["**/.git/**"]));
+
+        assert!(denied("config/db.yml", &["config/*.yml"]));
+        assert!(!denied("config/nested/db.yml", &["config/*.yml"]));
+        assert!(denied("backup1.tar", &["backup?.tar"]));
+        assert!(!denied("public/index.html", &["*.secret", ".git/**"]));
+    }
+
+    #[test]
+    fn matches_glob_patterns() {
+        assert!(matches_glob("*.html", Path::new("docs/index.html")));
+        assert!(matches_glob("assets/**", Path::new("assets/app.3f2a.js")));
+        assert!(!matches_glob("assets/**", Path::new("docs/assets.js")));
+        assert!(!matches_glob("*.html", Path::new("")));
+    }
+}
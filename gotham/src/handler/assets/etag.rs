@@ -0,0 +1,125 @@
+//! Strong entity tags derived from the content of files, memoized by path, size and
+//! modification time.
+
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+struct CachedTag {
+    len: u64,
+    modified: SystemTime,
+    etag: String,
+}
+
+// Computed entity tags by path, valid as long as the size and modification time of the file are
+// unchanged.
+static CACHE: Mutex<Option<HashMap<PathBuf, CachedTag>>> = Mutex::new(None);
+
+// Returns the strong entity tag of the file at `path` with the given metadata, hashing its
+// content unless the entity tag was computed before and the file is unchanged since.
+pub(super) async fn strong_entity_tag(path: &Path, metadata: &Metadata) -> io::Result<String> {
+    let modified = metadata.modified().ok();
+    if let Some(modified) = modified {
+        let cache = CACHE.lock().unwrap();
+        let cached = cache.as_ref().and_then(|cache| cache.get(path));
+        if let Some(cached) = cached {
+            if cached.len == metadata.len() && cached.modified == modified {
+                return Ok(cached.etag.clone());
+            }
+        }
+    }
+
+    let etag = hash_file(path).await?;
+    if let Some(modified) = modified {
+        let mut cache = CACHE.lock().unwrap();
+        cache.get_or_insert_with(HashMap::new).insert(
+            path.to_path_buf(),
+            CachedTag {
+                len: metadata.len(),
+                modified,
+                etag: etag.clone(),
+            },
+        );
+    }
+    Ok(etag)
+}
+
+// Returns the strong entity tag of content held in memory, which is identical to the entity tag
+// of a file with the same content.
+pub(super) fn content_entity_tag(contents: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(contents);
+    format_tag(hasher)
+}
+
+async fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; READ_BUF_SIZE];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(format_tag(hasher))
+}
+
+fn format_tag(hasher: Sha256) -> String {
+    digest_entity_tag(&hasher.finalize().into())
+}
+
+// Returns the strong entity tag of content with the given SHA-256 digest, like that of a file
+// whose digest was computed while it was stored.
+pub(super) fn digest_entity_tag(sha256: &[u8; 32]) -> String {
+    // 128 bits of the hash are plenty to tell the contents of a file apart
+    let mut etag = String::with_capacity(34);
+    etag.push('"');
+    for byte in &sha256[..16] {
+        let _ = write!(etag, "{:02x}", byte);
+    }
+    etag.push('"');
+    etag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn identical_content_has_identical_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.txt");
+        std::fs::write(&first, "same content").unwrap();
+        std::fs::write(&second, "same content").unwrap();
+
+        let first_tag = strong_entity_tag(&first, &std::fs::metadata(&first).unwrap())
+            .await
+            .unwrap();
+        let second_tag = strong_entity_tag(&second, &std::fs::metadata(&second).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(first_tag, second_tag);
+        assert!(first_tag.starts_with('"') && first_tag.ends_with('"'));
+        assert_eq!(first_tag.len(), 34);
+
+        std::fs::write(&second, "other content").unwrap();
+        let changed_tag = strong_entity_tag(&second, &std::fs::metadata(&second).unwrap())
+            .await
+            .unwrap();
+        assert_ne!(first_tag, changed_tag);
+        assert_eq!(first_tag, content_entity_tag(b"same content"));
+    }
+}
@@ -0,0 +1,146 @@
+use httpdate::fmt_http_date;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The characters to encode in a path segment of a link
+pub(super) const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+// Renders an HTML listing of the directory at `dir`, which was requested at `request_path`.
+// Hidden entries, whose name starts with a dot, are not listed.
+pub(super) async fn render(dir: &Path, request_path: &str) -> io::Result<String> {
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let meta = entry.metadata().await?;
+        entries.push(Entry {
+            name,
+            is_dir: meta.is_dir(),
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        });
+    }
+    // directories first, each sorted by name
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    let base = request_path.trim_end_matches('/');
+    let title = escape(if base.is_empty() { "/" } else { base });
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n\
+         <body>\n<h1>Index of {0}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n",
+        title
+    );
+    if !base.is_empty() {
+        html.push_str("<tr><td><a href=\"");
+        html.push_str(&escape(&base[..base.rfind('/').unwrap_or(0) + 1]));
+        html.push_str("\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir {
+            "-".to_owned()
+        } else {
+            entry.len.to_string()
+        };
+        // modification times before the epoch cannot be formatted as HTTP dates
+        let modified = entry
+            .modified
+            .filter(|modified| modified.duration_since(UNIX_EPOCH).is_ok())
+            .map(fmt_http_date)
+            .unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr><td><a href=\"{}/{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>",
+            escape(base),
+            utf8_percent_encode(&entry.name, SEGMENT),
+            suffix,
+            escape(&entry.name),
+            suffix,
+            size,
+            modified
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+pub(super) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape("<a href=\"x\">'&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[tokio::test]
+    async fn renders_listing() {
+        let html = render(Path::new("resources/test/assets"), "/files/")
+            .await
+            .unwrap();
+        assert!(html.contains("<title>Index of /files</title>"));
+        assert!(html.contains("<a href=\"/\">../</a>"));
+        assert!(html.contains("<a href=\"/files/scripts/\">scripts/</a>"));
+        assert!(html.contains("<a href=\"/files/doc.html\">doc.html</a>"));
+
+        // directories are listed first
+        let scripts = html.find("scripts/").unwrap();
+        let doc = html.find("doc.html").unwrap();
+        assert!(scripts < doc);
+    }
+
+    #[tokio::test]
+    async fn renders_files_modified_before_epoch() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = std::fs::File::create(dir.path().join("old.txt")).unwrap();
+        file.set_modified(UNIX_EPOCH - Duration::from_secs(86400))
+            .unwrap();
+
+        let html = render(dir.path(), "/").await.unwrap();
+        assert!(html.contains("<a href=\"/old.txt\">old.txt</a>"));
+    }
+}
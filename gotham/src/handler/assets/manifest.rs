@@ -0,0 +1,217 @@
+//! Serves a JSON manifest of the static assets below a directory, available with the
+//! `asset-manifest` feature.
+
+use bytes::Bytes;
+use futures_util::{future, FutureExt};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use hyper::{Body, HeaderMap, Response, StatusCode};
+use serde::Serialize;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::{fs, io};
+
+use super::mime_for_path;
+use crate::handler::{Handler, HandlerFuture, NewHandler};
+use crate::helpers::http::conditional::entity_tag;
+use crate::state::{FromState, State};
+
+/// A file listed in the manifest served by `AssetManifestHandler`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AssetEntry {
+    /// The path of the file relative to the root directory, separated by `/`.
+    pub path: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The entity tag which the file is served with, if the modification time is available.
+    pub etag: Option<String>,
+    /// The mime type which the file is served with.
+    pub mime: String,
+}
+
+struct Manifest {
+    entries: Vec<AssetEntry>,
+    body: Bytes,
+    etag: String,
+}
+
+/// A `Handler` which responds with a JSON array describing every file below a root directory,
+/// with its path, size, entity tag and mime type, as served by a `to_dir` route for the same
+/// directory. Clients like service workers can use it to prefetch and validate sets of assets.
+///
+/// The manifest is generated once, when the handler is created, so files added or changed later
+/// are not reflected. Hidden files, whose name starts with a dot, and pre-compressed `.gz` and
+/// `.br` siblings of other files are not listed. The manifest itself is served with an entity
+/// tag, so clients can revalidate it with `If-None-Match`.
+///
+/// ```rust
+/// # use gotham::handler::AssetManifestHandler;
+/// # use gotham::router::builder::*;
+/// #
+/// # fn main() -> std::io::Result<()> {
+/// let manifest = AssetManifestHandler::new("resources/test/assets")?;
+///
+/// let router = build_simple_router(|route| {
+///     route.get("/assets/manifest.json").to_new_handler(manifest);
+///     route.get("/assets/*").to_dir("resources/test/assets");
+/// });
+/// # let _ = router;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AssetManifestHandler {
+    manifest: Arc<Manifest>,
+}
+
+impl AssetManifestHandler {
+    /// Creates a new `AssetManifestHandler`, generating the manifest of the files below `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        collect_entries(root.as_ref(), "", &mut entries)?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let body = serde_json::to_vec(&entries).map_err(io::Error::from)?;
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let etag = format!("\"{:x}\"", hasher.finish());
+
+        Ok(AssetManifestHandler {
+            manifest: Arc::new(Manifest {
+                entries,
+                body: Bytes::from(body),
+                etag,
+            }),
+        })
+    }
+
+    /// Returns the files listed in the manifest, sorted by path.
+    pub fn entries(&self) -> &[AssetEntry] {
+        &self.manifest.entries
+    }
+}
+
+impl NewHandler for AssetManifestHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for AssetManifestHandler {
+    fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
+        let manifest = &self.manifest;
+        let not_modified = HeaderMap::borrow_from(&state)
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .any(|etag| etag == manifest.etag.as_str());
+
+        let response = Response::builder()
+            .header(ETAG, manifest.etag.as_str())
+            .header(CACHE_CONTROL, "no-cache");
+        let response = if not_modified {
+            response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+        } else {
+            response
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(manifest.body.clone()))
+        };
+        future::ok((state, response.unwrap())).boxed()
+    }
+}
+
+// Adds the files below `dir` to `entries`, with paths prefixed by `prefix`.
+fn collect_entries(dir: &Path, prefix: &str, entries: &mut Vec<AssetEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            collect_entries(&entry.path(), &format!("{}/", path), entries)?;
+        } else if !is_compressed_sibling(&entry.path()) {
+            entries.push(AssetEntry {
+                path,
+                size: meta.len(),
+                etag: entity_tag(&meta),
+                mime: mime_for_path(&entry.path()).to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Checks whether the file is a pre-compressed variant of another file, like `app.js.gz`.
+fn is_compressed_sibling(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") | Some("br") => path.with_extension("").is_file(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+
+    #[test]
+    fn lists_assets() {
+        let handler = AssetManifestHandler::new("resources/test/assets").unwrap();
+        let paths: Vec<&str> = handler.entries().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "doc.html",
+                "file.txt",
+                "scripts/script.js",
+                "styles/style.css"
+            ]
+        );
+
+        let doc = &handler.entries()[0];
+        assert_eq!(doc.size, 24);
+        assert_eq!(doc.mime, "text/html");
+        assert!(doc.etag.is_some());
+    }
+
+    #[test]
+    fn serves_manifest() {
+        let handler = AssetManifestHandler::new("resources/test/assets").unwrap();
+        let etag = handler.manifest.etag.clone();
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/manifest.json").to_new_handler(handler)
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/manifest.json")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(ETAG).unwrap(), etag.as_str());
+        let body = response.read_utf8_body().unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(manifest[2]["path"], "scripts/script.js");
+        assert_eq!(manifest[2]["size"], 32);
+
+        let response = test_server
+            .client()
+            .get("http://localhost/manifest.json")
+            .with_header(IF_NONE_MATCH, etag.parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+}
@@ -0,0 +1,349 @@
+//! Serves static assets held in memory, like files embedded with `include_bytes!`.
+
+use bytes::Bytes;
+use futures_util::{future, FutureExt};
+use httpdate::fmt_http_date;
+use hyper::header::*;
+use hyper::{Body, HeaderMap, Method, Response, StatusCode, Uri};
+use mime::Mime;
+use percent_encoding::percent_decode_str;
+
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::etag::content_entity_tag;
+use super::{io_handler_error, mime_for_path, FilePathExtractor};
+use crate::handler::{Handler, HandlerFuture, NewHandler};
+use crate::helpers::http::conditional::{
+    not_modified, precondition_failed, resolve_range, slice_range,
+};
+use crate::state::{FromState, State};
+
+/// A file served by `MemoryFileHandler`, with its content, mime type and a strong entity tag
+/// computed from its content.
+#[derive(Clone, Debug)]
+pub struct MemoryFile {
+    contents: Bytes,
+    mime: Mime,
+    etag: String,
+    modified: Option<SystemTime>,
+}
+
+impl MemoryFile {
+    /// Creates a new `MemoryFile` with the given content and mime type.
+    pub fn new<B: Into<Bytes>>(contents: B, mime: Mime) -> Self {
+        let contents = contents.into();
+        let etag = content_entity_tag(&contents);
+        MemoryFile {
+            contents,
+            mime,
+            etag,
+            modified: None,
+        }
+    }
+
+    /// Sets the modification time of the file, which is sent as `Last-Modified` header and
+    /// checked against `If-Modified-Since` (defaults to none, sending no `Last-Modified` header).
+    pub fn with_last_modified(mut self, modified: SystemTime) -> Self {
+        self.modified = Some(modified);
+        self
+    }
+}
+
+/// A `Handler` which serves files held in memory, like files embedded into the binary at build
+/// time with `include_bytes!`, so an application can be shipped as a single binary including its
+/// frontend.
+///
+/// Files are answered like by `to_file` and `to_dir` routes, with an entity tag derived from
+/// their content, support for `If-None-Match`, `If-Modified-Since` and range requests, and the
+/// index file served for requests of directories. Unknown paths are answered with
+/// "404 Not Found".
+///
+/// On a route with a glob segment, the file is looked up by the part of the path matched by the
+/// glob, if the route extracts it with `FilePathExtractor`. Otherwise, the file is looked up by
+/// the full request path.
+///
+/// ```rust
+/// # use gotham::handler::{FilePathExtractor, MemoryFile, MemoryFileHandler};
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// # use hyper::StatusCode;
+/// #
+/// # fn main() {
+/// let assets = MemoryFileHandler::new()
+///     .with_static_file("index.html", b"<html></html>")
+///     .with_static_file("app.js", b"console.log('hello')")
+///     .with_file("config.json", MemoryFile::new("{}", mime::APPLICATION_JSON));
+///
+/// let router = build_simple_router(|route| {
+///     route
+///         .get("/assets/*")
+///         .with_path_extractor::<FilePathExtractor>()
+///         .to_new_handler(assets);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .get("http://localhost/assets/app.js")
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.status(), StatusCode::OK);
+/// # assert_eq!(response.read_utf8_body().unwrap(), "console.log('hello')");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MemoryFileHandler {
+    files: Arc<HashMap<String, MemoryFile>>,
+    cache_control: String,
+    index_file: Option<String>,
+}
+
+impl Default for MemoryFileHandler {
+    fn default() -> Self {
+        MemoryFileHandler::new()
+    }
+}
+
+impl MemoryFileHandler {
+    /// Creates a new `MemoryFileHandler` without any files.
+    pub fn new() -> Self {
+        MemoryFileHandler {
+            files: Arc::new(HashMap::new()),
+            cache_control: "public".to_string(),
+            index_file: Some("index.html".to_string()),
+        }
+    }
+
+    /// Adds a file, served for the given path relative to the route, like `css/style.css`.
+    pub fn with_file<P: AsRef<str>>(mut self, path: P, file: MemoryFile) -> Self {
+        let path = path.as_ref().trim_matches('/').to_string();
+        Arc::make_mut(&mut self.files).insert(path, file);
+        self
+    }
+
+    /// Adds a file with static content, like the content included with `include_bytes!`, without
+    /// copying it. The mime type is guessed from the extension of the path.
+    pub fn with_static_file<P: AsRef<str>>(self, path: P, contents: &'static [u8]) -> Self {
+        let mime = mime_for_path(Path::new(path.as_ref()));
+        self.with_file(path, MemoryFile::new(Bytes::from_static(contents), mime))
+    }
+
+    /// Sets the "cache_control" header in responses to the given value (defaults to `public`).
+    pub fn with_cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = cache_control.to_owned();
+        self
+    }
+
+    /// Sets the file to serve for requests of a directory, or of the root of the route (defaults
+    /// to `index.html`).
+    pub fn with_index_file(mut self, index_file: Option<&str>) -> Self {
+        self.index_file = index_file.map(ToOwned::to_owned);
+        self
+    }
+
+    // Looks up the file for the given path, or the index file if the path is a directory.
+    fn lookup(&self, path: &str) -> Option<&MemoryFile> {
+        let path = path.trim_matches('/');
+        if let Some(file) = self.files.get(path) {
+            return Some(file);
+        }
+        let index_file = self.index_file.as_ref()?;
+        if path.is_empty() {
+            self.files.get(index_file)
+        } else {
+            self.files.get(&format!("{}/{}", path, index_file))
+        }
+    }
+
+    fn respond(&self, state: &State, file: &MemoryFile) -> Response<Body> {
+        let headers = HeaderMap::borrow_from(state);
+        let mut response = Response::builder()
+            .header(CACHE_CONTROL, self.cache_control.as_str())
+            .header(ETAG, file.etag.as_str());
+        if let Some(modified) = file.modified {
+            response = response.header(LAST_MODIFIED, fmt_http_date(modified));
+        }
+        if precondition_failed(Some(&file.etag), file.modified, headers) {
+            return response
+                .status(StatusCode::PRECONDITION_FAILED)
+                .body(Body::empty())
+                .unwrap();
+        }
+        if not_modified(Some(&file.etag), file.modified, headers) {
+            return response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        let (len, range_start) = match resolve_range(file.contents.len() as u64, headers) {
+            Ok(range) => range,
+            Err(e) => {
+                return Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .body(Body::from(e))
+                    .unwrap();
+            }
+        };
+        let contents = slice_range(&file.contents, len, range_start);
+        response = response
+            .header(CONTENT_TYPE, file.mime.as_ref())
+            .header(CONTENT_LENGTH, contents.len());
+        response = match range_start {
+            Some(range_start) => response.status(StatusCode::PARTIAL_CONTENT).header(
+                CONTENT_RANGE,
+                format!(
+                    "bytes {}-{}/{}",
+                    range_start,
+                    (range_start + contents.len() as u64).saturating_sub(1),
+                    file.contents.len()
+                ),
+            ),
+            None => response.status(StatusCode::OK),
+        };
+
+        let body = if Method::borrow_from(state) == Method::HEAD {
+            Body::empty()
+        } else {
+            Body::from(contents)
+        };
+        response.body(body).unwrap()
+    }
+}
+
+impl NewHandler for MemoryFileHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for MemoryFileHandler {
+    fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
+        let path = match FilePathExtractor::try_borrow_from(&state) {
+            Some(extractor) => extractor.parts.join("/"),
+            None => {
+                let path = Uri::borrow_from(&state).path();
+                percent_decode_str(path).decode_utf8_lossy().into_owned()
+            }
+        };
+        match self.lookup(&path) {
+            Some(file) => {
+                let response = self.respond(&state, file);
+                future::ok((state, response)).boxed()
+            }
+            None => {
+                let err = io::Error::from(ErrorKind::NotFound);
+                future::err(io_handler_error(state, err)).boxed()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+
+    fn test_server() -> TestServer {
+        let handler = MemoryFileHandler::new()
+            .with_static_file("index.html", b"<html>index</html>")
+            .with_static_file("docs/index.html", b"<html>docs</html>")
+            .with_static_file("scripts/app.js", b"console.log('embedded')")
+            .with_cache_control("max-age=3600");
+        TestServer::new(build_simple_router(|route| {
+            route
+                .get_or_head("/*")
+                .with_path_extractor::<FilePathExtractor>()
+                .to_new_handler(handler.clone());
+            route.get("/").to_new_handler(handler);
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn serves_memory_files() {
+        let test_server = test_server();
+        let response = test_server
+            .client()
+            .get("http://localhost/scripts/app.js")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/javascript"
+        );
+        assert_eq!(
+            response.headers().get(CACHE_CONTROL).unwrap(),
+            "max-age=3600"
+        );
+        let etag = response.headers().get(ETAG).unwrap().clone();
+        assert_eq!(
+            etag,
+            content_entity_tag(b"console.log('embedded')").as_str()
+        );
+        assert_eq!(
+            response.read_utf8_body().unwrap(),
+            "console.log('embedded')"
+        );
+
+        let response = test_server
+            .client()
+            .get("http://localhost/scripts/app.js")
+            .with_header(IF_NONE_MATCH, etag)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = test_server
+            .client()
+            .get("http://localhost/scripts/app.js")
+            .with_header(RANGE, "bytes=12-".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(CONTENT_RANGE).unwrap(),
+            "bytes 12-22/23"
+        );
+        assert_eq!(response.read_utf8_body().unwrap(), "'embedded')");
+
+        let response = test_server
+            .client()
+            .head("http://localhost/scripts/app.js")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "23");
+        assert_eq!(response.read_body().unwrap(), b"");
+    }
+
+    #[test]
+    fn serves_index_files() {
+        let test_server = test_server();
+        for (path, body) in &[
+            ("http://localhost/", "<html>index</html>"),
+            ("http://localhost/docs/", "<html>docs</html>"),
+            ("http://localhost/docs", "<html>docs</html>"),
+        ] {
+            let response = test_server.client().get(*path).perform().unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.read_utf8_body().unwrap(), *body);
+        }
+
+        let response = test_server
+            .client()
+            .get("http://localhost/missing.js")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,18 @@
+//! Memory-mapping of files, so their content is sent without being copied into buffers.
+
+use std::io;
+
+use bytes::Bytes;
+use memmap2::Mmap;
+use tokio::fs::File;
+
+// Maps the whole of `file` into memory. Slices of the returned `Bytes` are written to the
+// connection straight from the page cache.
+#[allow(unsafe_code)]
+pub(super) async fn map(file: File) -> io::Result<Bytes> {
+    let file = file.into_std().await;
+    // Safety: the mapping is only read, and stays valid unless the file is truncated, which
+    // `FileOptions::with_mmap_min_size` rules out for the files it is enabled for.
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(Bytes::from_owner(mmap))
+}
@@ -1,17 +1,46 @@
 //! Defines handlers for static assets, used by `to_file` and `to_dir` routes.
 //! Both 'If-None-Match' (etags) and 'If-Modified-Since' are supported to check
 //! file modification.
-//! Side-by-side compressed files for gzip and brotli are supported if enabled
+//! Side-by-side compressed files for gzip and brotli are supported if enabled,
+//! in which case responses carry 'Vary: Accept-Encoding'. With the 'compression'
+//! feature, files without a compressed sibling can be compressed on the fly.
+//! Requests for directories are served an index file, 'index.html' by default,
+//! or optionally an HTML listing of the directory. With the 'asset-manifest'
+//! feature, a JSON manifest of the files below a directory can be served,
+//! and 'MemoryFileHandler' serves files held in memory, like embedded assets.
+//! With the 'mmap' feature, large files can be memory-mapped, so they are sent
+//! without copying their content into buffers.
+//! With the 'upload' feature, 'FileUploadHandler' stores uploaded files below a
+//! directory. 'SendFileMiddleware' serves files on behalf of handlers authorizing
+//! requests.
+//! 'WebDavHandler' serves a directory read-only to WebDAV clients.
 //! See 'FileOptions' for more details.
 
-mod accepted_encoding;
-
-use bytes::{BufMut, Bytes, BytesMut};
-use futures_util::stream::{self, TryStream, TryStreamExt};
-use futures_util::{ready, FutureExt, TryFutureExt};
-use httpdate::parse_http_date;
+pub(crate) mod accepted_encoding;
+mod cache;
+#[cfg(feature = "compression")]
+pub(crate) mod compression;
+mod deny;
+mod etag;
+mod listing;
+#[cfg(feature = "asset-manifest")]
+mod manifest;
+mod memory;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod not_found;
+mod send_file;
+#[cfg(feature = "upload")]
+mod upload;
+mod webdav;
+
+use bytes::{BufMut, Bytes};
+use futures_util::future::Either;
+use futures_util::stream::{self, Stream, TryStream, TryStreamExt};
+use futures_util::{ready, FutureExt};
+use httpdate::fmt_http_date;
 use hyper::header::*;
-use hyper::{Body, Response, StatusCode};
+use hyper::{Body, Method, Response, StatusCode, Uri};
 use log::debug;
 use mime::{self, Mime};
 use mime_guess::from_path;
@@ -19,20 +48,36 @@ use serde::Deserialize;
 use tokio::fs::File;
 use tokio::io::{AsyncRead, AsyncSeekExt, ReadBuf};
 
-use self::accepted_encoding::accepted_encodings;
-use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use self::accepted_encoding::{accepted_encodings, AcceptedEncoding};
+use self::cache::FileCache;
+#[cfg(feature = "asset-manifest")]
+pub use self::manifest::{AssetEntry, AssetManifestHandler};
+pub use self::memory::{MemoryFile, MemoryFileHandler};
+use self::not_found::NotFoundCache;
+pub use self::send_file::{create_send_file_response, SendFile, SendFileMiddleware};
+#[cfg(feature = "upload")]
+pub use self::upload::FileUploadHandler;
+pub use self::webdav::WebDavHandler;
+use crate::handler::{Handler, HandlerError, HandlerFuture, HandlerResult, NewHandler};
+use crate::helpers::buffer;
+use crate::helpers::http::conditional::{
+    entity_tag, not_modified, precondition_failed, resolve_range, slice_range,
+};
+use crate::helpers::http::request::negotiation::parse_cached;
 use crate::router::response::StaticResponseExtender;
 use crate::state::{FromState, State, StateData};
 
+use std::collections::HashMap;
 use std::convert::From;
 use std::fs::Metadata;
 use std::io::{ErrorKind, SeekFrom};
-use std::iter::FromIterator;
+use std::iter::{self, FromIterator};
 use std::mem::MaybeUninit;
 use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::Poll;
-use std::time::UNIX_EPOCH;
+use std::time::{Duration, Instant};
 use std::{cmp, io};
 
 /// Represents a handler for any files under a directory.
@@ -54,7 +99,8 @@ pub struct FileHandler {
 /// path can be passed to router builder methods if only default options are required.
 ///
 /// For overridding default options, `FileOptions` provides builder methods. The default
-/// values and use of the builder methods are shown in the example below.
+/// values and use of the builder methods are shown in the example below. The router builder
+/// methods also accept the builder itself, so calling `build` is optional there.
 ///
 ///
 /// ```rust
@@ -72,10 +118,33 @@ pub struct FileHandler {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct FileOptions {
     path: PathBuf,
+    fallback_dirs: Vec<PathBuf>,
     cache_control: String,
+    cache_control_rules: Vec<(String, String)>,
     gzip: bool,
     brotli: bool,
     buffer_size: Option<usize>,
+    index_file: Option<String>,
+    directory_listing: bool,
+    not_found_page: Option<PathBuf>,
+    strong_etag: bool,
+    follow_symlinks: bool,
+    deny_hidden: bool,
+    deny_patterns: Vec<String>,
+    mime_overrides: HashMap<String, Mime>,
+    default_mime_type: Mime,
+    charset: Option<String>,
+    cache: Option<Arc<FileCache>>,
+    not_found_cache: Option<Arc<NotFoundCache>>,
+    throttle: Option<u64>,
+    #[cfg(feature = "compression")]
+    compress: bool,
+    #[cfg(feature = "compression")]
+    compression_min_size: u64,
+    #[cfg(feature = "compression")]
+    compressible_types: Vec<String>,
+    #[cfg(feature = "mmap")]
+    mmap_min_size: Option<u64>,
 }
 
 impl FileOptions {
@@ -86,10 +155,36 @@ impl FileOptions {
     {
         FileOptions {
             path: PathBuf::from(path),
+            fallback_dirs: Vec::new(),
             cache_control: "public".to_string(),
+            cache_control_rules: Vec::new(),
             gzip: false,
             brotli: false,
             buffer_size: None,
+            index_file: Some("index.html".to_string()),
+            directory_listing: false,
+            not_found_page: None,
+            strong_etag: false,
+            follow_symlinks: true,
+            deny_hidden: false,
+            deny_patterns: Vec::new(),
+            mime_overrides: HashMap::new(),
+            default_mime_type: mime::APPLICATION_OCTET_STREAM,
+            charset: Some("utf-8".to_string()),
+            cache: None,
+            not_found_cache: None,
+            throttle: None,
+            #[cfg(feature = "compression")]
+            compress: false,
+            #[cfg(feature = "compression")]
+            compression_min_size: compression::DEFAULT_MIN_SIZE,
+            #[cfg(feature = "compression")]
+            compressible_types: compression::DEFAULT_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            #[cfg(feature = "mmap")]
+            mmap_min_size: None,
         }
     }
 
@@ -99,6 +194,20 @@ impl FileOptions {
         self
     }
 
+    /// Sets rules of glob patterns and the "cache_control" header sent for files matching them,
+    /// like `("assets/**", "public, max-age=31536000, immutable")` for hashed assets and
+    /// `("*.html", "no-cache")` for pages referencing them. Patterns use the syntax of
+    /// `with_deny_patterns` and are matched against the path relative to the root directory of a
+    /// `to_dir` route, or the file name of a `to_file` route. The first matching rule applies,
+    /// and files matching no rule get the header set by `with_cache_control`.
+    pub fn with_cache_control_rules(&mut self, rules: &[(&str, &str)]) -> &mut Self {
+        self.cache_control_rules = rules
+            .iter()
+            .map(|(pattern, cache_control)| (pattern.to_string(), cache_control.to_string()))
+            .collect();
+        self
+    }
+
     /// If `true`, given a request for FILE, serves FILE.gz if it exists in the static directory and
     /// if the accept-encoding header is set to allow gzipped content (defaults to false).
     pub fn with_gzip(&mut self, gzip: bool) -> &mut Self {
@@ -120,10 +229,207 @@ impl FileOptions {
         self
     }
 
+    /// Sets the file to serve when the requested path is a directory (defaults to `index.html`).
+    /// If `None`, or if the directory contains no such file, requests for directories are
+    /// answered with "404 Not Found", unless directory listings are enabled.
+    pub fn with_index_file(&mut self, index_file: Option<&str>) -> &mut Self {
+        self.index_file = index_file.map(ToOwned::to_owned);
+        self
+    }
+
+    /// If `true`, requests for directories without an index file are answered with an HTML
+    /// listing of the name, size and modification date of each entry (defaults to false). Hidden
+    /// entries, whose name starts with a dot, are not listed.
+    pub fn with_directory_listing(&mut self, directory_listing: bool) -> &mut Self {
+        self.directory_listing = directory_listing;
+        self
+    }
+
+    /// Sets a custom error document, which is served with "404 Not Found" when the requested file
+    /// does not exist (defaults to none, answering with an empty body). The path is not resolved
+    /// against the root of a `to_dir` route, so the page may be kept outside of the served files.
+    pub fn with_not_found_page<P: AsRef<Path>>(&mut self, not_found_page: P) -> &mut Self {
+        self.not_found_page = Some(not_found_page.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets directories which are searched in order for files which don't exist below the root
+    /// directory of a `to_dir` route (defaults to none), like the directory of a default theme
+    /// below a directory of overrides, or source assets below generated ones. The file is served
+    /// from the first directory it exists in, and checked against the symlink rules of that
+    /// directory. Missing files are answered as if they were missing in the root directory.
+    pub fn with_fallback_dirs<P: AsRef<Path>>(&mut self, dirs: &[P]) -> &mut Self {
+        self.fallback_dirs = dirs.iter().map(|dir| dir.as_ref().to_path_buf()).collect();
+        self
+    }
+
+    /// If `true`, symlinks below the root directory of a `to_dir` route are followed, as long as
+    /// the file they resolve to is still inside the root directory (defaults to true). If
+    /// `false`, requests for paths containing a symlink are answered with "404 Not Found", like
+    /// nginx's `disable_symlinks on`. Symlinks leading outside of the root directory are never
+    /// followed.
+    pub fn with_follow_symlinks(&mut self, follow_symlinks: bool) -> &mut Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// If `true`, requests for paths below the root directory of a `to_dir` route containing a
+    /// hidden file or directory, whose name starts with a dot like `.env` or `.git`, are answered
+    /// with "404 Not Found" even if the file exists (defaults to false).
+    pub fn with_deny_hidden(&mut self, deny_hidden: bool) -> &mut Self {
+        self.deny_hidden = deny_hidden;
+        self
+    }
+
+    /// Sets glob patterns for paths below the root directory of a `to_dir` route which are
+    /// answered with "404 Not Found" even if the file exists, like `*.secret` or `.git/**`.
+    /// Within a path segment, `*` matches any number of characters and `?` a single character,
+    /// while a `**` segment matches any number of segments. Patterns without a `/` are matched
+    /// against every segment of the path, and other patterns against the whole path relative to
+    /// the root directory.
+    pub fn with_deny_patterns(&mut self, patterns: &[&str]) -> &mut Self {
+        self.deny_patterns = patterns.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// If `true`, responses carry a strong entity tag computed from a hash of the file content,
+    /// instead of a weak one derived from its size and modification time (defaults to false).
+    /// The hash is computed when a file is first served, and reused until its size or
+    /// modification time changes. Files rebuilt with identical content keep their entity tag, so
+    /// client caches stay valid across deployments.
+    pub fn with_strong_etag(&mut self, strong_etag: bool) -> &mut Self {
+        self.strong_etag = strong_etag;
+        self
+    }
+
+    /// Sets the mime types of files with the given extensions, like `map` or `wasm`, taking
+    /// precedence over the mime types guessed from the extension. Extensions are given without
+    /// the leading dot and are matched case-insensitively.
+    pub fn with_mime_overrides(&mut self, overrides: HashMap<String, Mime>) -> &mut Self {
+        self.mime_overrides = overrides
+            .into_iter()
+            .map(|(ext, mime)| (ext.trim_start_matches('.').to_ascii_lowercase(), mime))
+            .collect();
+        self
+    }
+
+    /// Sets the mime type of files whose extension is unknown, or which have no extension
+    /// (defaults to `application/octet-stream`).
+    pub fn with_default_mime_type(&mut self, mime: Mime) -> &mut Self {
+        self.default_mime_type = mime;
+        self
+    }
+
+    /// Sets the charset appended to the "content-type" header of files with a `text/*` mime type,
+    /// unless the mime type already carries one, or `None` to append no charset (defaults to
+    /// `utf-8`).
+    pub fn with_charset(&mut self, charset: Option<&str>) -> &mut Self {
+        self.charset = charset.map(ToOwned::to_owned);
+        self
+    }
+
+    /// Keeps the content of up to `max_entries` files of at most `max_file_size` bytes in memory
+    /// (defaults to no cache), evicting the least recently used file when full. Cached files are
+    /// still checked for changes of their size and modification time on every request, but are
+    /// not opened and read again. Concurrent requests of a file which is not cached yet share a
+    /// single read of the file. The cache is shared by all handlers built from these options.
+    pub fn with_memory_cache(&mut self, max_entries: usize, max_file_size: u64) -> &mut Self {
+        self.cache = Some(Arc::new(FileCache::new(max_entries, max_file_size)));
+        self
+    }
+
+    /// Remembers up to `max_entries` paths below the root directory of a `to_dir` route which
+    /// were answered with "404 Not Found" for `ttl` (defaults to none), answering further requests
+    /// for them without looking them up in the file system, e.g. when bots scan for common
+    /// vulnerable paths. Files created at such a path are served once its entry expired. When
+    /// full, the entry expiring first is dropped. The cache is shared by all handlers built from
+    /// these options.
+    pub fn with_not_found_cache(&mut self, ttl: Duration, max_entries: usize) -> &mut Self {
+        self.not_found_cache = Some(Arc::new(NotFoundCache::new(ttl, max_entries)));
+        self
+    }
+
+    /// Limits the rate at which the body of each file response is sent to `bytes_per_sec`
+    /// (defaults to no limit), so large downloads can't saturate the uplink of the server. Unlike
+    /// the limits of a `ThrottleConfig`, this only applies to the files served by these options,
+    /// and not to other responses on the same connection.
+    pub fn with_throttle(&mut self, bytes_per_sec: u64) -> &mut Self {
+        self.throttle = Some(bytes_per_sec);
+        self
+    }
+
+    /// If `true`, files are compressed on the fly with gzip or deflate if the client accepts it
+    /// and no pre-compressed file is served (defaults to false). Only files of compressible types
+    /// which are at least as large as the minimum size are compressed, and range requests are
+    /// always answered uncompressed.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(&mut self, compress: bool) -> &mut Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets the minimum size in bytes of files to compress on the fly (defaults to 1024).
+    #[cfg(feature = "compression")]
+    pub fn with_compression_min_size(&mut self, min_size: u64) -> &mut Self {
+        self.compression_min_size = min_size;
+        self
+    }
+
+    /// Sets the types of files to compress on the fly, either as a full mime type like
+    /// `application/json`, or with a wildcard subtype like `text/*`. Defaults to text, JavaScript,
+    /// JSON, WebAssembly, XML and SVG files, leaving out already compressed formats like most
+    /// images.
+    #[cfg(feature = "compression")]
+    pub fn with_compressible_types(&mut self, types: &[&str]) -> &mut Self {
+        self.compressible_types = types.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    /// If `Some`, files of at least this many bytes are memory-mapped to send them (defaults to
+    /// `None`). Their content is written to the connection straight from the page cache instead
+    /// of being read into buffers first, which makes serving large files cheaper.
+    ///
+    /// Only enable this for files which are replaced by renaming a new version into place, not
+    /// modified in place: a mapped file which is truncated while it is sent crashes the process.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap_min_size(&mut self, min_size: Option<u64>) -> &mut Self {
+        self.mmap_min_size = min_size;
+        self
+    }
+
     /// Clones `self` to return an owned value for passing to a handler.
     pub fn build(&mut self) -> Self {
         self.clone()
     }
+
+    // The "cache_control" header of the first rule matching the path relative to the root
+    // directory, or the default one.
+    fn cache_control_for(&self, relative: &Path) -> &str {
+        self.cache_control_rules
+            .iter()
+            .find(|(pattern, _)| deny::matches_glob(pattern, relative))
+            .map_or(&self.cache_control, |(_, cache_control)| cache_control)
+    }
+
+    // Returns the mime type to serve the file at `path` with.
+    fn mime_type(&self, path: &Path) -> Mime {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        let mime = ext
+            .and_then(|ext| self.mime_overrides.get(&ext.to_ascii_lowercase()))
+            .cloned()
+            .or_else(|| from_path(path).first())
+            .unwrap_or_else(|| self.default_mime_type.clone());
+        match &self.charset {
+            Some(charset)
+                if mime.type_() == mime::TEXT && mime.get_param(mime::CHARSET).is_none() =>
+            {
+                format!("{}; charset={}", mime, charset)
+                    .parse()
+                    .unwrap_or(mime)
+            }
+            _ => mime,
+        }
+    }
 }
 
 /// Create a `FileOptions` from various types, used in
@@ -145,6 +451,12 @@ derive_from!(&str);
 derive_from!(&String);
 derive_from!(String);
 
+impl From<&mut FileOptions> for FileOptions {
+    fn from(options: &mut FileOptions) -> FileOptions {
+        options.build()
+    }
+}
+
 impl FileHandler {
     /// Create a new `FileHandler` for the given path.
     pub fn new<P>(path: P) -> FileHandler
@@ -187,43 +499,197 @@ impl NewHandler for DirHandler {
 
 impl Handler for DirHandler {
     fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
-        let path = {
-            let mut base_path = self.options.path;
-            let file_path = PathBuf::from_iter(&FilePathExtractor::borrow_from(&state).parts);
-            base_path.extend(&normalize_path(&file_path));
-            base_path
-        };
-        create_file_response(
-            FileOptions {
+        let file_path = normalize_path(&PathBuf::from_iter(
+            &FilePathExtractor::borrow_from(&state).parts,
+        ));
+        let denied = deny::is_denied(
+            &file_path,
+            self.options.deny_hidden,
+            &self.options.deny_patterns,
+        );
+        async move {
+            let not_found_cache = self.options.not_found_cache.clone();
+            let known_missing = not_found_cache
+                .as_ref()
+                .is_some_and(|cache| cache.contains(&file_path));
+            if denied || known_missing {
+                let err = io::Error::from(ErrorKind::NotFound);
+                return io_error_response(state, err, self.options.not_found_page).await;
+            }
+            let root = find_root(&self.options, &file_path).await;
+            let mut path = root.clone();
+            path.extend(&file_path);
+            let options = FileOptions {
                 path,
                 ..self.options
-            },
-            state,
-        )
+            };
+            let result = create_file_response(options, state, file_path.clone(), Some(root)).await;
+            if let Some(cache) = not_found_cache {
+                let status = match &result {
+                    Ok((_, response)) => response.status(),
+                    Err((_, err)) => err.status(),
+                };
+                if status == StatusCode::NOT_FOUND {
+                    cache.insert(&file_path);
+                }
+            }
+            result
+        }
+        .boxed()
     }
 }
 
 impl Handler for FileHandler {
     fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
-        create_file_response(self.options, state)
+        let relative = self
+            .options
+            .path
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        create_file_response(self.options, state, relative, None)
+    }
+}
+
+// Returns the first of the root directory and the fallback directories in which the file at
+// `file_path` exists, or the root directory if it exists in none of them.
+async fn find_root(options: &FileOptions, file_path: &Path) -> PathBuf {
+    if !options.fallback_dirs.is_empty() {
+        for dir in iter::once(&options.path).chain(&options.fallback_dirs) {
+            if tokio::fs::symlink_metadata(dir.join(file_path))
+                .await
+                .is_ok()
+            {
+                return dir.clone();
+            }
+        }
     }
+    options.path.clone()
 }
 
-// Creates the `HandlerFuture` response based on the given `FileOptions`.
-fn create_file_response(options: FileOptions, state: State) -> Pin<Box<HandlerFuture>> {
-    let mime_type = mime_for_path(&options.path);
-    let headers = HeaderMap::borrow_from(&state).clone();
+// Creates the `HandlerFuture` response based on the given `FileOptions`, where `relative` is the
+// path of the file relative to the root directory. Requests for a directory are answered with its
+// index file or listing. Files served from a `root` directory are checked against the symlink
+// rules once they were opened.
+fn create_file_response(
+    mut options: FileOptions,
+    state: State,
+    mut relative: PathBuf,
+    root: Option<PathBuf>,
+) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let is_dir = tokio::fs::metadata(&options.path)
+            .await
+            .is_ok_and(|meta| meta.is_dir());
+        if is_dir {
+            let index = match options.index_file.clone() {
+                Some(index_file) => tokio::fs::metadata(options.path.join(&index_file))
+                    .await
+                    .is_ok_and(|meta| meta.is_file())
+                    .then_some(index_file),
+                None => None,
+            };
+            match index {
+                Some(index_file) => {
+                    options.path.push(&index_file);
+                    relative.push(index_file);
+                }
+                None if options.directory_listing => {
+                    if let Some(root) = &root {
+                        if let Err(err) =
+                            check_symlinks(root, &options.path, options.follow_symlinks).await
+                        {
+                            return io_error_response(state, err, options.not_found_page).await;
+                        }
+                    }
+                    return create_listing_response(options.path, state).await;
+                }
+                None => {
+                    let err = io::Error::from(ErrorKind::NotFound);
+                    return io_error_response(state, err, options.not_found_page).await;
+                }
+            }
+        }
+        serve_file(options, state, relative, root).await
+    }
+    .boxed()
+}
 
-    let (path, encoding) = check_compressed_options(&options, &headers);
+// Creates the `HandlerFuture` response serving the file at the path of the given `FileOptions`.
+fn serve_file(
+    options: FileOptions,
+    state: State,
+    relative: PathBuf,
+    root: Option<PathBuf>,
+) -> Pin<Box<HandlerFuture>> {
+    let mime_type = options.mime_type(&options.path);
+    let cache_control = options.cache_control_for(&relative).to_owned();
+    let request_headers = HeaderMap::borrow_from(&state);
+    // HEAD requests are answered with the headers of the file, but without its content
+    let head = Method::borrow_from(&state) == Method::HEAD;
+    let not_found_page = options.not_found_page.clone();
+
+    let accepted = parse_cached(&state, ACCEPT_ENCODING, accepted_encodings);
+    let (path, encoding) = check_compressed_options(&options, &accepted);
+    // the response depends on the accepted encodings if compressed files may be served
+    #[cfg(not(feature = "compression"))]
+    let vary = options.gzip || options.brotli;
+    #[cfg(feature = "compression")]
+    let vary = options.gzip || options.brotli || options.compress;
+    #[cfg(feature = "compression")]
+    let compress = if options.compress
+        && encoding.is_none()
+        && compression::is_compressible(&mime_type, &options.compressible_types)
+    {
+        compression::negotiate(
+            &accepted,
+            &[compression::Encoding::Gzip, compression::Encoding::Deflate],
+        )
+    } else {
+        None
+    };
 
-    let response_future = File::open(path).and_then(move |mut file| async move {
-        let meta = file.metadata().await?;
-        if not_modified(&meta, &headers) {
+    let headers = conditional_headers(request_headers);
+    let response_future = async move {
+        let (source, meta) = open_file(&path, options.cache.as_deref()).await?;
+        if let Some(root) = &root {
+            check_opened(root, &path, options.follow_symlinks, &meta).await?;
+        }
+        let etag = if options.strong_etag {
+            let etag = etag::strong_entity_tag(&path, &meta).await?;
+            // files compressed on the fly are a different representation of the same content
+            #[cfg(feature = "compression")]
+            let etag = match compress {
+                Some(_) => format!("W/{}", etag),
+                None => etag,
+            };
+            Some(etag)
+        } else {
+            entity_tag(&meta)
+        };
+        let last_modified = meta.modified().ok().map(fmt_http_date);
+        if precondition_failed(etag.as_deref(), meta.modified().ok(), &headers) {
             return Ok(hyper::Response::builder()
-                .status(StatusCode::NOT_MODIFIED)
+                .status(StatusCode::PRECONDITION_FAILED)
                 .body(Body::empty())
                 .unwrap());
         }
+        if not_modified(etag.as_deref(), meta.modified().ok(), &headers) {
+            // a 304 response carries the validators and caching headers of the full response
+            let mut response = hyper::Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(CACHE_CONTROL, cache_control);
+            if let Some(etag) = etag {
+                response = response.header(ETAG, etag);
+            }
+            if let Some(last_modified) = last_modified {
+                response = response.header(LAST_MODIFIED, last_modified);
+            }
+            if vary {
+                response = response.header(VARY, ACCEPT_ENCODING.as_str());
+            }
+            return Ok(response.body(Body::empty()).unwrap());
+        }
         let buf_size = options
             .buffer_size
             .unwrap_or_else(|| optimal_buf_size(&meta));
@@ -236,24 +702,65 @@ fn create_file_response(options: FileOptions, state: State) -> Pin<Box<HandlerFu
                     .unwrap());
             }
         };
-        if let Some(seek_to) = range_start {
-            file.seek(SeekFrom::Start(seek_to)).await?;
+        // a mapped file is sent like a cached one, as a single chunk
+        #[cfg(feature = "mmap")]
+        let source = match source {
+            FileSource::File(file)
+                if options
+                    .mmap_min_size
+                    .is_some_and(|min_size| meta.len() > 0 && meta.len() >= min_size) =>
+            {
+                FileSource::Cached(mmap::map(file).await?)
+            }
+            source => source,
+        };
+        let stream = match source {
+            FileSource::File(mut file) => {
+                if let Some(seek_to) = range_start {
+                    file.seek(SeekFrom::Start(seek_to)).await?;
+                };
+                Either::Left(file_stream(file, cmp::min(buf_size, len as usize), len).into_stream())
+            }
+            FileSource::Cached(contents) => {
+                let contents = slice_range(&contents, len, range_start);
+                Either::Right(stream::iter(iter::once(Ok(contents))))
+            }
         };
-
-        let stream = file_stream(file, cmp::min(buf_size, len as usize), len);
-        let body = Body::wrap_stream(stream.into_stream());
         let mut response = hyper::Response::builder()
             .status(StatusCode::OK)
-            .header(CONTENT_LENGTH, len)
             .header(CONTENT_TYPE, mime_type.as_ref())
-            .header(CACHE_CONTROL, options.cache_control);
+            .header(CACHE_CONTROL, cache_control);
+
+        #[cfg(feature = "compression")]
+        let body = match compress {
+            Some(compress) if range_start.is_none() && len >= options.compression_min_size => {
+                // the length of the compressed body is not known in advance
+                response = response.header(CONTENT_ENCODING, compress.as_str());
+                stream_body(compression::compress(stream, compress), options.throttle)
+            }
+            _ => {
+                response = response.header(CONTENT_LENGTH, len);
+                stream_body(stream, options.throttle)
+            }
+        };
+        #[cfg(not(feature = "compression"))]
+        let body = {
+            response = response.header(CONTENT_LENGTH, len);
+            stream_body(stream, options.throttle)
+        };
 
-        if let Some(etag) = entity_tag(&meta) {
+        if let Some(etag) = etag {
             response = response.header(ETAG, etag);
         }
+        if let Some(last_modified) = last_modified {
+            response = response.header(LAST_MODIFIED, last_modified);
+        }
         if let Some(content_encoding) = encoding {
             response = response.header(CONTENT_ENCODING, content_encoding);
         }
+        if vary {
+            response = response.header(VARY, ACCEPT_ENCODING.as_str());
+        }
 
         if let Some(range_start) = range_start {
             let val = format!(
@@ -264,75 +771,128 @@ fn create_file_response(options: FileOptions, state: State) -> Pin<Box<HandlerFu
             );
             response = response.status(StatusCode::PARTIAL_CONTENT).header(
                 CONTENT_RANGE,
-                HeaderValue::from_str(&val).map_err(|e| io::Error::new(ErrorKind::Other, e))?,
+                HeaderValue::from_str(&val).map_err(io::Error::other)?,
             );
         }
 
+        let body = if head { Body::empty() } else { body };
         Ok(response.body(body).unwrap())
-    });
+    };
 
-    response_future
-        .map(|result| match result {
+    async move {
+        match response_future.await {
             Ok(response) => Ok((state, response)),
-            Err(err) => {
-                let status = match err.kind() {
-                    io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
-                    io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
-                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+            Err(err) => io_error_response(state, err, not_found_page).await,
+        }
+    }
+    .boxed()
+}
+
+// The content of a file to serve, either from the file itself or from the in-memory cache.
+enum FileSource {
+    File(File),
+    Cached(Bytes),
+}
+
+// Opens the file at `path`, or returns its cached content if it is unchanged since it was cached.
+// Files admitted to the cache are read completely and added to it, with a single read shared by
+// concurrent requests of the same file.
+async fn open_file(path: &Path, cache: Option<&FileCache>) -> io::Result<(FileSource, Metadata)> {
+    if let Some(cache) = cache {
+        let meta = tokio::fs::metadata(path).await?;
+        if let Some(contents) = cache.get(path, &meta) {
+            return Ok((FileSource::Cached(contents), meta));
+        }
+        if cache.admits(&meta) {
+            // the file may have changed since its metadata was read
+            if let Some(contents) = cache.load(path, &meta).await? {
+                return Ok((FileSource::Cached(contents), meta));
+            }
+        }
+    }
+    let file = File::open(path).await?;
+    let meta = file.metadata().await?;
+    Ok((FileSource::File(file), meta))
+}
+
+// Creates the `HandlerFuture` response listing the contents of the directory at `dir`.
+fn create_listing_response(dir: PathBuf, state: State) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let request_path = Uri::borrow_from(&state).path().to_owned();
+        match listing::render(&dir, &request_path).await {
+            Ok(html) => {
+                let len = html.len();
+                let body = if Method::borrow_from(&state) == Method::HEAD {
+                    Body::empty()
+                } else {
+                    Body::from(html)
                 };
-                let err: HandlerError = err.into();
-                Err((state, err.with_status(status)))
+                let response = hyper::Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, mime::TEXT_HTML_UTF_8.as_ref())
+                    .header(CONTENT_LENGTH, len)
+                    .header(CACHE_CONTROL, "no-cache")
+                    .body(body)
+                    .unwrap();
+                Ok((state, response))
             }
-        })
-        .boxed()
+            Err(err) => Err(io_handler_error(state, err)),
+        }
+    }
+    .boxed()
 }
 
-/// Checks for existence of "Range" header and whether it is in supported format
-/// This implementations only supports single part ranges.
-/// Returns a result of length and optional starting position, or an error if range value is invalid
-/// If range header does not exist or is unsupported the length is the whole file length and start position is none.
-fn resolve_range(len: u64, headers: &HeaderMap) -> Result<(u64, Option<u64>), &'static str> {
-    let Some(range_val) = headers.get(RANGE) else {
-        return Ok((len, None));
+// Answers a request for a file which failed with the given IO error, serving the custom error
+// document if the file was not found and a `not_found_page` is configured.
+async fn io_error_response(
+    state: State,
+    err: io::Error,
+    not_found_page: Option<PathBuf>,
+) -> HandlerResult {
+    let page = match not_found_page {
+        Some(page) if err.kind() == ErrorKind::NotFound => page,
+        _ => return Err(io_handler_error(state, err)),
     };
-    range_val
-        .to_str()
-        .ok()
-        .and_then(|range_val| {
-            regex::Regex::new(r"^bytes=(\d*)-(\d*)$")
-                .unwrap()
-                .captures(range_val)
-                .map(|captures| {
-                    let begin = captures
-                        .get(1)
-                        .and_then(|digits| digits.as_str().parse::<u64>().ok());
-                    let end = captures
-                        .get(2)
-                        .and_then(|digits| digits.as_str().parse::<u64>().ok());
-                    match (begin, end) {
-                        (Some(begin), Some(end)) => {
-                            let end = cmp::min(end, len.saturating_sub(1));
-                            if end < begin {
-                                Err("invalid range")
-                            } else {
-                                let begin = cmp::min(begin, end);
-                                Ok(((1 + end).saturating_sub(begin), Some(begin)))
-                            }
-                        }
-                        (Some(begin), None) => {
-                            let end = len.saturating_sub(1);
-                            let begin = cmp::min(begin, len);
-                            Ok((1 + end.saturating_sub(begin), Some(begin)))
-                        }
-                        (None, Some(end)) => {
-                            let begin = len.saturating_sub(end);
-                            Ok((end, Some(begin)))
-                        }
-                        (None, None) => Err("invalid range"),
-                    }
-                })
-        })
-        .unwrap_or(Ok((len, None)))
+    let file = match File::open(&page).await {
+        Ok(file) => file,
+        Err(page_err) => {
+            debug!("failed to open not found page {:?}: {}", page, page_err);
+            return Err(io_handler_error(state, err));
+        }
+    };
+    let meta = match file.metadata().await {
+        Ok(meta) => meta,
+        Err(page_err) => return Err(io_handler_error(state, page_err)),
+    };
+
+    let body = if Method::borrow_from(&state) == Method::HEAD {
+        Body::empty()
+    } else {
+        let buf_size = cmp::min(optimal_buf_size(&meta), meta.len() as usize);
+        Body::wrap_stream(file_stream(file, buf_size, meta.len()).into_stream())
+    };
+    let response = hyper::Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(CONTENT_TYPE, mime_for_path(&page).as_ref())
+        .header(CONTENT_LENGTH, meta.len())
+        .body(body)
+        .unwrap();
+    Ok((state, response))
+}
+
+// Maps an IO error to a `HandlerError` with a matching status code.
+fn io_handler_error(state: State, err: io::Error) -> (State, HandlerError) {
+    (state, io_error(err))
+}
+
+fn io_error(err: io::Error) -> HandlerError {
+    let status = match err.kind() {
+        io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let err: HandlerError = err.into();
+    err.with_status(status)
 }
 
 // Checks for existence of compressed files if `FileOptions` and
@@ -340,14 +900,16 @@ fn resolve_range(len: u64, headers: &HeaderMap) -> Result<(u64, Option<u64>), &'
 // along with an optional encoding to return as the "Content-Encoding".
 fn check_compressed_options(
     options: &FileOptions,
-    headers: &HeaderMap,
+    accepted: &[AcceptedEncoding],
 ) -> (PathBuf, Option<String>) {
     options
         .path
         .file_name()
         .and_then(|filename| {
-            accepted_encodings(headers)
+            accepted
                 .iter()
+                // a quality of 0 marks the encoding as not acceptable
+                .filter(|e| e.quality > 0.0)
                 .filter_map(|e| {
                     get_extension(&e.encoding, options).map(|ext| (e.encoding.to_string(), ext))
                 })
@@ -398,38 +960,81 @@ fn normalize_path(path: &Path) -> PathBuf {
         })
 }
 
-// Checks whether a file is modified based on metadata and request headers.
-fn not_modified(metadata: &Metadata, headers: &HeaderMap) -> bool {
-    // If-None-Match header takes precedence over If-Modified-Since
-    match headers.get(IF_NONE_MATCH) {
-        Some(_) => entity_tag(metadata)
-            .map(|etag| headers.get_all(IF_NONE_MATCH).iter().any(|v| v == &etag))
-            .unwrap_or(false),
-        _ => headers
-            .get(IF_MODIFIED_SINCE)
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| parse_http_date(v).ok())
-            .and_then(|if_modified_time| {
-                metadata
-                    .modified()
-                    .map(|modified| modified <= if_modified_time)
-                    .ok()
-            })
-            .unwrap_or(false),
-    }
-}
-
-fn entity_tag(metadata: &Metadata) -> Option<String> {
-    metadata.modified().ok().and_then(|modified| {
-        modified.duration_since(UNIX_EPOCH).ok().map(|duration| {
-            format!(
-                "W/\"{0:x}-{1:x}.{2:x}\"",
-                metadata.len(),
-                duration.as_secs(),
-                duration.subsec_nanos()
-            )
-        })
-    })
+// Checks that the requested path below `root` does not resolve to a file outside of `root`, and
+// contains no symlinks at all if they may not be followed. Violations are reported as not found,
+// so responses do not reveal the existence of symlinks.
+async fn check_symlinks(root: &Path, path: &Path, follow_symlinks: bool) -> io::Result<()> {
+    if follow_symlinks {
+        let root = tokio::fs::canonicalize(root).await?;
+        if !tokio::fs::canonicalize(path).await?.starts_with(&root) {
+            debug!("refusing to follow symlink outside of {:?}", root);
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+    } else {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let mut current = root.to_path_buf();
+        for component in relative.components() {
+            current.push(component);
+            if tokio::fs::symlink_metadata(&current)
+                .await?
+                .file_type()
+                .is_symlink()
+            {
+                debug!("refusing to follow symlink {:?}", current);
+                return Err(io::Error::from(ErrorKind::NotFound));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks the file opened at `path`, with the given metadata, against the symlink rules. The path
+// must still refer to the opened file once it was checked, so it cannot be replaced by a symlink
+// between opening and checking it.
+async fn check_opened(
+    root: &Path,
+    path: &Path,
+    follow_symlinks: bool,
+    opened: &Metadata,
+) -> io::Result<()> {
+    check_symlinks(root, path, follow_symlinks).await?;
+    if !same_file(&tokio::fs::metadata(path).await?, opened) {
+        debug!(
+            "refusing to serve {:?}, which changed while it was opened",
+            path
+        );
+        return Err(io::Error::from(ErrorKind::NotFound));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+// Copies the request headers which are checked once the file was opened, so the `HeaderMap` of
+// the request need not be cloned as a whole. Requests without these headers allocate nothing.
+fn conditional_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut conditional = HeaderMap::new();
+    for name in &[
+        IF_MATCH,
+        IF_UNMODIFIED_SINCE,
+        IF_NONE_MATCH,
+        IF_MODIFIED_SINCE,
+        RANGE,
+    ] {
+        for value in headers.get_all(name) {
+            conditional.append(name.clone(), value.clone());
+        }
+    }
+    conditional
 }
 
 /// Responsible for extracting the file path matched by the glob segment from the URL.
@@ -446,6 +1051,44 @@ impl StaticResponseExtender for FilePathExtractor {
     fn extend(_state: &mut State, _res: &mut Response<Self::ResBody>) {}
 }
 
+// Wraps the stream of a file response in a body, sending at most `throttle` bytes per second.
+fn stream_body<S>(stream: S, throttle: Option<u64>) -> Body
+where
+    S: TryStream<Ok = Bytes, Error = io::Error> + Send + 'static,
+{
+    match throttle {
+        Some(bytes_per_sec) => Body::wrap_stream(throttle_stream(stream, bytes_per_sec)),
+        None => Body::wrap_stream(stream.into_stream()),
+    }
+}
+
+// Delays the chunks of `stream` so that at most `bytes_per_sec` bytes are yielded per second on
+// average. Chunks are split to about a tenth of a second worth of bytes, so large chunks like
+// cached files are sent evenly instead of in bursts.
+fn throttle_stream<S>(stream: S, bytes_per_sec: u64) -> impl Stream<Item = io::Result<Bytes>> + Send
+where
+    S: TryStream<Ok = Bytes, Error = io::Error> + Send + 'static,
+{
+    let bytes_per_sec = cmp::max(bytes_per_sec, 1);
+    let chunk_size = cmp::max(bytes_per_sec / 10, 1) as usize;
+    let start = Instant::now();
+    let init = (Box::pin(stream.into_stream()), Bytes::new(), 0u64);
+    stream::try_unfold(init, move |(mut stream, mut pending, sent)| async move {
+        while pending.is_empty() {
+            match stream.try_next().await? {
+                Some(chunk) => pending = chunk,
+                None => return Ok(None),
+            }
+        }
+        // the bytes sent so far must not exceed the budget of the time passed
+        let due = Duration::from_secs_f64(sent as f64 / bytes_per_sec as f64);
+        tokio::time::sleep_until((start + due).into()).await;
+        let chunk = pending.split_to(cmp::min(chunk_size, pending.len()));
+        let sent = sent + chunk.len() as u64;
+        Ok(Some((chunk, (stream, pending, sent))))
+    })
+}
+
 // Creates a Stream from the given file, for streaming as part of the Response.
 // Inspired by Warp https://github.com/seanmonstar/warp/blob/master/src/filters/fs.rs
 // Inspired by tokio https://github.com/tokio-rs/tokio/blob/master/tokio/src/io/util/read_buf.rs
@@ -456,7 +1099,7 @@ fn file_stream(
     buf_size: usize,
     mut len: u64,
 ) -> impl TryStream<Ok = Bytes, Error = io::Error> + Send {
-    let mut buf = BytesMut::with_capacity(buf_size);
+    let mut buf = buffer::acquire(buf_size);
     stream::poll_fn(move |cx| {
         if len == 0 {
             return Poll::Ready(None);
@@ -524,12 +1167,14 @@ fn get_block_size(metadata: &Metadata) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::FileOptions;
+    use super::{conditional_headers, FileOptions};
     use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
     use crate::router::Router;
     use crate::test::TestServer;
+    use httpdate::fmt_http_date;
     use hyper::header::*;
     use hyper::StatusCode;
+    use std::collections::HashMap;
     use std::fs::File;
     use std::io::{Read, Seek, SeekFrom};
     use std::path::PathBuf;
@@ -539,22 +1184,22 @@ mod tests {
         let expected_docs = vec![
             (
                 "doc.html",
-                HeaderValue::from_static("text/html"),
+                HeaderValue::from_static("text/html; charset=utf-8"),
                 "<html>I am a doc.</html>",
             ),
             (
                 "file.txt",
-                HeaderValue::from_static("text/plain"),
+                HeaderValue::from_static("text/plain; charset=utf-8"),
                 "I am a file",
             ),
             (
                 "styles/style.css",
-                HeaderValue::from_static("text/css"),
+                HeaderValue::from_static("text/css; charset=utf-8"),
                 ".styled { border: none; }",
             ),
             (
                 "scripts/script.js",
-                HeaderValue::from_static("application/javascript"),
+                HeaderValue::from_static("text/javascript; charset=utf-8"),
                 "console.log('I am javascript!');",
             ),
         ];
@@ -616,77 +1261,616 @@ mod tests {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::OK);
-        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/html");
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
 
         let body = response.read_body().unwrap();
         assert_eq!(&body[..], b"<html>I am a doc.</html>");
     }
 
     #[test]
-    fn assets_if_none_match_etag() {
-        use hyper::header::{ETAG, IF_NONE_MATCH};
-        use std::fs::File;
+    fn assets_head_request() {
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/").to_file("resources/test/assets/doc.html");
+            route.get("/*").to_dir("resources/test/assets");
+        }))
+        .unwrap();
 
-        let path = "resources/test/assets/doc.html";
-        let test_server =
-            TestServer::new(build_simple_router(|route| route.get("/").to_file(path))).unwrap();
+        for uri in &["http://localhost/", "http://localhost/doc.html"] {
+            let response = test_server.client().head(*uri).perform().unwrap();
 
-        let etag = File::open(path)
-            .and_then(|file| file.metadata())
-            .map(|meta| super::entity_tag(&meta).expect("entity tag"))
-            .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "text/html; charset=utf-8"
+            );
+            assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "24");
+            assert!(response.headers().get(ETAG).is_some());
+
+            let body = response.read_body().unwrap();
+            assert!(body.is_empty());
+        }
 
-        // matching etag
         let response = test_server
             .client()
-            .get("http://localhost/")
-            .with_header(
-                IF_NONE_MATCH,
-                HeaderValue::from_bytes(etag.as_bytes()).unwrap(),
-            )
+            .post("http://localhost/", "", mime::TEXT_PLAIN)
             .perform()
             .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow: Vec<_> = response.headers().get_all(ALLOW).iter().collect();
+        assert_eq!(allow, vec!["GET", "HEAD"]);
+    }
 
-        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    #[test]
+    fn assets_not_found_page() {
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new("resources/test/assets")
+                    .with_not_found_page("resources/test/errors/404.html"),
+            );
+            route.get("/missing-page/*").to_dir(
+                FileOptions::new("resources/test/assets")
+                    .with_not_found_page("resources/test/errors/missing.html"),
+            );
+        }))
+        .unwrap();
 
-        // not matching etag
         let response = test_server
             .client()
-            .get("http://localhost/")
-            .with_header(IF_NONE_MATCH, HeaderValue::from_bytes(b"bogus").unwrap())
+            .get("http://localhost/missing.html")
             .perform()
             .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/html");
+        let body = response.read_body().unwrap();
+        assert_eq!(&body[..], b"<html>Nothing to see here.</html>");
 
+        // existing files are served as usual
+        let response = test_server
+            .client()
+            .get("http://localhost/doc.html")
+            .perform()
+            .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
-        assert_eq!(
-            response.headers().get(ETAG).unwrap().to_str().unwrap(),
-            etag
-        );
+
+        // a missing error document falls back to the empty response
+        let response = test_server
+            .client()
+            .get("http://localhost/missing-page/missing.html")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.read_body().unwrap().is_empty());
     }
 
     #[test]
-    fn assets_if_modified_since() {
-        use httpdate::fmt_http_date;
-        use hyper::header::IF_MODIFIED_SINCE;
-        use std::fs::File;
-        use std::time::Duration;
-
-        let path = "resources/test/assets/doc.html";
-        let test_server =
-            TestServer::new(build_simple_router(|route| route.get("/").to_file(path))).unwrap();
+    fn assets_strong_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "identical").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "identical").unwrap();
+        let root = dir.path().to_path_buf();
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .get("/*")
+                .to_dir(FileOptions::new(root).with_strong_etag(true))
+        }))
+        .unwrap();
 
-        let modified = File::open(path)
-            .and_then(|file| file.metadata())
-            .and_then(|meta| meta.modified())
-            .unwrap();
+        let etag = |uri: &str| {
+            let response = test_server.client().get(uri).perform().unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.headers().get(ETAG).unwrap().clone()
+        };
+        let etag_a = etag("http://localhost/a.txt");
+        assert!(!etag_a.to_str().unwrap().starts_with("W/"));
+        assert_eq!(etag_a, etag("http://localhost/b.txt"));
 
-        // if-modified-since a newer date
         let response = test_server
             .client()
-            .get("http://localhost/")
-            .with_header(
-                IF_MODIFIED_SINCE,
-                HeaderValue::from_bytes(fmt_http_date(modified + Duration::new(5, 0)).as_bytes())
+            .get("http://localhost/b.txt")
+            .with_header(IF_NONE_MATCH, etag_a)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn assets_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("file.txt"), "public").unwrap();
+        symlink(root.path().join("file.txt"), root.path().join("link.txt")).unwrap();
+        symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("escape.txt"),
+        )
+        .unwrap();
+        // the index file and precompressed files are checked too
+        std::fs::create_dir(root.path().join("dir")).unwrap();
+        symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("dir/index.html"),
+        )
+        .unwrap();
+        symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("file.txt.gz"),
+        )
+        .unwrap();
+
+        let follow = root.path().to_path_buf();
+        let no_follow = root.path().to_path_buf();
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .get("/follow/*")
+                .to_dir(FileOptions::new(follow).with_gzip(true));
+            route
+                .get("/no-follow/*")
+                .to_dir(FileOptions::new(no_follow).with_follow_symlinks(false));
+        }))
+        .unwrap();
+
+        let status = |uri: &str| {
+            let response = test_server.client().get(uri).perform().unwrap();
+            response.status()
+        };
+        assert_eq!(status("http://localhost/follow/file.txt"), StatusCode::OK);
+        assert_eq!(status("http://localhost/follow/link.txt"), StatusCode::OK);
+        assert_eq!(
+            status("http://localhost/follow/escape.txt"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("http://localhost/no-follow/file.txt"),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("http://localhost/no-follow/link.txt"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("http://localhost/no-follow/escape.txt"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("http://localhost/follow/dir/"),
+            StatusCode::NOT_FOUND
+        );
+
+        let response = test_server
+            .client()
+            .get("http://localhost/follow/file.txt")
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_static("gzip"))
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn copies_conditional_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, "text/html".parse().unwrap());
+        headers.append(IF_NONE_MATCH, "\"a\"".parse().unwrap());
+        headers.append(IF_NONE_MATCH, "\"b\"".parse().unwrap());
+        headers.insert(RANGE, "bytes=0-1".parse().unwrap());
+
+        let conditional = conditional_headers(&headers);
+        assert_eq!(conditional.len(), 3);
+        assert_eq!(conditional.get_all(IF_NONE_MATCH).iter().count(), 2);
+        assert_eq!(conditional.get(RANGE).unwrap(), "bytes=0-1");
+        assert!(conditional.get(ACCEPT).is_none());
+
+        assert_eq!(conditional_headers(&HeaderMap::new()).capacity(), 0);
+    }
+
+    #[test]
+    fn assets_last_modified() {
+        use std::fs::File;
+
+        let path = "resources/test/assets/doc.html";
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .get("/")
+                .to_file(FileOptions::new(path).with_cache_control("max-age=60"))
+        }))
+        .unwrap();
+
+        let modified = File::open(path)
+            .and_then(|file| file.metadata())
+            .and_then(|meta| meta.modified())
+            .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let last_modified = response.headers().get(LAST_MODIFIED).unwrap().clone();
+        assert_eq!(last_modified, fmt_http_date(modified).as_str());
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(IF_MODIFIED_SINCE, last_modified.clone())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(
+            response.headers().get(LAST_MODIFIED).unwrap(),
+            last_modified
+        );
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+        assert!(response.headers().get(ETAG).is_some());
+    }
+
+    #[test]
+    fn assets_mime_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js.MAP"), "{}").unwrap();
+        std::fs::write(dir.path().join("data.unknown"), "data").unwrap();
+        std::fs::write(dir.path().join("style.css"), "body {}").unwrap();
+        let root = dir.path().to_path_buf();
+
+        let mut overrides = HashMap::new();
+        overrides.insert(".map".to_string(), mime::APPLICATION_JSON);
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new(root)
+                    .with_mime_overrides(overrides)
+                    .with_default_mime_type(mime::TEXT_PLAIN),
+            )
+        }))
+        .unwrap();
+
+        let content_type = |path: &str| {
+            let response = test_server
+                .client()
+                .get(&format!("http://localhost/{}", path))
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.headers().get(CONTENT_TYPE).unwrap().clone()
+        };
+        assert_eq!(content_type("app.js.MAP"), "application/json");
+        assert_eq!(content_type("data.unknown"), "text/plain; charset=utf-8");
+        assert_eq!(content_type("style.css"), "text/css; charset=utf-8");
+    }
+
+    #[test]
+    fn assets_charset() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("index.html"), "<html>ünïcödé</html>").unwrap();
+        fs::write(dir.path().join("legacy.txt"), "legacy").unwrap();
+        fs::write(dir.path().join("data.json"), "{}").unwrap();
+        let root = dir.path().to_path_buf();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/dir/*").to_dir(root.clone());
+            route.get("/legacy.txt").to_file(
+                FileOptions::new(root.join("legacy.txt")).with_charset(Some("iso-8859-1")),
+            );
+            route
+                .get("/plain.txt")
+                .to_file(FileOptions::new(root.join("legacy.txt")).with_charset(None));
+        }))
+        .unwrap();
+
+        for (path, content_type) in &[
+            ("dir/index.html", "text/html; charset=utf-8"),
+            ("dir/data.json", "application/json"),
+            ("legacy.txt", "text/plain; charset=iso-8859-1"),
+            ("plain.txt", "text/plain"),
+        ] {
+            let response = test_server
+                .client()
+                .get(&format!("http://localhost/{}", path))
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                content_type,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn assets_throttle() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("large.bin"), vec![7u8; 2048]).unwrap();
+        let root = dir.path().to_path_buf();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .get("/*")
+                .to_dir(FileOptions::new(root.clone()).with_throttle(4096));
+        }))
+        .unwrap();
+
+        let started = std::time::Instant::now();
+        let response = test_server
+            .client()
+            .get("http://localhost/large.bin")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_body().unwrap(), vec![7u8; 2048]);
+        // the last chunk is due after half a second
+        assert!(started.elapsed() >= std::time::Duration::from_millis(400));
+    }
+
+    #[test]
+    fn assets_memory_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "cached content").unwrap();
+        let file = path.clone();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .get("/")
+                .to_file(FileOptions::new(file).with_memory_cache(16, 1024))
+        }))
+        .unwrap();
+        let get = |range: Option<&str>| {
+            let client = test_server.client();
+            let mut request = client.get("http://localhost/");
+            if let Some(range) = range {
+                request = request.with_header(RANGE, range.parse().unwrap());
+            }
+            request.perform().unwrap().read_utf8_body().unwrap()
+        };
+
+        assert_eq!(get(None), "cached content");
+        assert_eq!(get(None), "cached content");
+        assert_eq!(get(Some("bytes=7-")), "content");
+
+        // the cached content is replaced once the file changes
+        fs::write(&path, "changed").unwrap();
+        assert_eq!(get(None), "changed");
+    }
+
+    #[test]
+    fn assets_not_found_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new(root.clone())
+                    .with_not_found_cache(std::time::Duration::from_millis(200), 16),
+            );
+        }))
+        .unwrap();
+        let status = || {
+            test_server
+                .client()
+                .get("http://localhost/wp-login.php")
+                .perform()
+                .unwrap()
+                .status()
+        };
+
+        assert_eq!(status(), StatusCode::NOT_FOUND);
+        // the file is not looked up again until the entry expired
+        fs::write(dir.path().join("wp-login.php"), "login").unwrap();
+        assert_eq!(status(), StatusCode::NOT_FOUND);
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        assert_eq!(status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn assets_fallback_dirs() {
+        let overrides = tempfile::tempdir().unwrap();
+        let theme = tempfile::tempdir().unwrap();
+        fs::write(overrides.path().join("style.css"), "override").unwrap();
+        fs::write(theme.path().join("style.css"), "theme").unwrap();
+        fs::create_dir(theme.path().join("img")).unwrap();
+        fs::write(theme.path().join("img/logo.svg"), "<svg/>").unwrap();
+        let root = overrides.path().to_path_buf();
+        let fallback = theme.path().to_path_buf();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .get("/*")
+                .to_dir(FileOptions::new(root).with_fallback_dirs(&[fallback]))
+        }))
+        .unwrap();
+
+        for (path, status, body) in &[
+            ("style.css", StatusCode::OK, "override"),
+            ("img/logo.svg", StatusCode::OK, "<svg/>"),
+            ("missing.css", StatusCode::NOT_FOUND, ""),
+        ] {
+            let response = test_server
+                .client()
+                .get(&format!("http://localhost/{}", path))
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), *status, "{}", path);
+            if *status == StatusCode::OK {
+                assert_eq!(response.read_utf8_body().unwrap(), *body);
+            }
+        }
+    }
+
+    #[test]
+    fn assets_deny_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/config"), "config").unwrap();
+        fs::write(dir.path().join(".env"), "SECRET=1").unwrap();
+        fs::write(dir.path().join("keys.secret"), "keys").unwrap();
+        fs::write(dir.path().join("public.txt"), "public").unwrap();
+        let root = dir.path().to_path_buf();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new(root)
+                    .with_deny_hidden(true)
+                    .with_deny_patterns(&["*.secret"]),
+            )
+        }))
+        .unwrap();
+
+        for (path, status) in &[
+            (".git/config", StatusCode::NOT_FOUND),
+            (".env", StatusCode::NOT_FOUND),
+            ("keys.secret", StatusCode::NOT_FOUND),
+            ("public.txt", StatusCode::OK),
+        ] {
+            let response = test_server
+                .client()
+                .get(&format!("http://localhost/{}", path))
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), *status, "{}", path);
+        }
+    }
+
+    #[test]
+    fn assets_cache_control_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("assets")).unwrap();
+        fs::write(dir.path().join("assets/app.3f2a.js"), "app").unwrap();
+        fs::write(dir.path().join("index.html"), "index").unwrap();
+        fs::write(dir.path().join("robots.txt"), "robots").unwrap();
+        let root = dir.path().to_path_buf();
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new(&root)
+                    .with_cache_control("max-age=60")
+                    .with_cache_control_rules(&[
+                        ("assets/**", "public, max-age=31536000, immutable"),
+                        ("*.html", "no-cache"),
+                    ]),
+            );
+            route.get("/").to_file(
+                FileOptions::new(root.join("index.html"))
+                    .with_cache_control_rules(&[("*.html", "no-cache")]),
+            )
+        }))
+        .unwrap();
+
+        for (path, cache_control) in &[
+            ("assets/app.3f2a.js", "public, max-age=31536000, immutable"),
+            ("index.html", "no-cache"),
+            ("robots.txt", "max-age=60"),
+            ("", "no-cache"),
+        ] {
+            let response = test_server
+                .client()
+                .get(&format!("http://localhost/{}", path))
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+            assert_eq!(
+                response.headers().get(CACHE_CONTROL).unwrap(),
+                cache_control,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn assets_if_none_match_etag() {
+        use hyper::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+        use std::fs::File;
+
+        let path = "resources/test/assets/doc.html";
+        let test_server =
+            TestServer::new(build_simple_router(|route| route.get("/").to_file(path))).unwrap();
+
+        let etag = File::open(path)
+            .and_then(|file| file.metadata())
+            .map(|meta| super::entity_tag(&meta).expect("entity tag"))
+            .unwrap();
+
+        // matching etag
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(
+                IF_NONE_MATCH,
+                HeaderValue::from_bytes(etag.as_bytes()).unwrap(),
+            )
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // not matching etag
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(IF_NONE_MATCH, HeaderValue::from_bytes(b"bogus").unwrap())
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(ETAG).unwrap().to_str().unwrap(),
+            etag
+        );
+
+        // a strong entity tag in the list matches the weak one of the file
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(
+                IF_NONE_MATCH,
+                HeaderValue::from_str(&format!("\"other\", {}", &etag[2..])).unwrap(),
+            )
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // weak entity tags never satisfy If-Match
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(IF_MATCH, HeaderValue::from_bytes(etag.as_bytes()).unwrap())
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[test]
+    fn assets_if_modified_since() {
+        use hyper::header::IF_MODIFIED_SINCE;
+        use std::fs::File;
+        use std::time::Duration;
+
+        let path = "resources/test/assets/doc.html";
+        let test_server =
+            TestServer::new(build_simple_router(|route| route.get("/").to_file(path))).unwrap();
+
+        let modified = File::open(path)
+            .and_then(|file| file.metadata())
+            .and_then(|meta| meta.modified())
+            .unwrap();
+
+        // if-modified-since a newer date
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(
+                IF_MODIFIED_SINCE,
+                HeaderValue::from_bytes(fmt_http_date(modified + Duration::new(5, 0)).as_bytes())
                     .unwrap(),
             )
             .perform()
@@ -737,6 +1921,184 @@ mod tests {
         );
     }
 
+    #[test]
+    fn assets_with_unbuilt_options() {
+        let router = build_simple_router(|route| {
+            route.get("/").to_file(
+                FileOptions::new("resources/test/assets/doc.html")
+                    .with_cache_control("max-age=3600")
+                    .with_gzip(true),
+            )
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/")
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_str("gzip").unwrap())
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CACHE_CONTROL).unwrap(),
+            "max-age=3600"
+        );
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    }
+
+    #[test]
+    fn assets_index_file() {
+        let router = build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new("resources/test/assets").with_index_file(Some("script.js")),
+            )
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/scripts")
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/javascript; charset=utf-8"
+        );
+        let expected_body = fs::read("resources/test/assets/scripts/script.js").unwrap();
+        assert_eq!(response.read_body().unwrap(), expected_body);
+    }
+
+    #[test]
+    fn assets_directory_without_index_file() {
+        let router = build_simple_router(|route| {
+            route
+                .get("/*")
+                .to_dir(FileOptions::new("resources/test/assets").with_index_file(None))
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/scripts")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // the default index file does not exist
+        let server = test_server();
+        let response = server
+            .client()
+            .get("http://localhost/scripts")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn assets_directory_listing() {
+        let router = build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new("resources/test/assets")
+                    .with_index_file(None)
+                    .with_directory_listing(true),
+            )
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/scripts")
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
+        let body = response.read_utf8_body().unwrap();
+        assert!(body.contains("<a href=\"/scripts/script.js\">script.js</a>"));
+
+        // files are still served
+        let response = server
+            .client()
+            .get("http://localhost/scripts/script.js")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn assets_compressed_on_the_fly() {
+        use flate2::read::GzDecoder;
+
+        let router = build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new("resources/test/assets")
+                    .with_compression(true)
+                    .with_compression_min_size(0),
+            )
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/styles/style.css")
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_str("gzip").unwrap())
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(response.headers().get(VARY).unwrap(), "accept-encoding");
+        assert!(response.headers().get(CONTENT_LENGTH).is_none());
+
+        let mut body = Vec::new();
+        GzDecoder::new(&response.read_body().unwrap()[..])
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(
+            body,
+            fs::read("resources/test/assets/styles/style.css").unwrap()
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn assets_not_compressed_on_the_fly() {
+        let router = build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new("resources/test/assets")
+                    .with_compression(true)
+                    .with_compressible_types(&["application/javascript"]),
+            )
+        });
+        let server = TestServer::new(router).unwrap();
+
+        // files smaller than the minimum size
+        let response = server
+            .client()
+            .get("http://localhost/scripts/script.js")
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_str("gzip").unwrap())
+            .perform()
+            .unwrap();
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+
+        // files which are not of a compressible type
+        let response = server
+            .client()
+            .get("http://localhost/styles/style.css")
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_str("gzip").unwrap())
+            .perform()
+            .unwrap();
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "25");
+    }
+
     #[test]
     fn assets_default_cache_control() {
         let router = build_simple_router(|route| route.get("/*").to_dir("resources/test/assets"));
@@ -805,9 +2167,11 @@ mod tests {
                     .unwrap()
                     .to_str()
                     .unwrap(),
-                "text/html"
+                "text/html; charset=utf-8"
             );
 
+            assert_eq!(response.headers().get(VARY).unwrap(), "accept-encoding");
+
             let expected_body =
                 fs::read(format!("resources/test/assets/doc.html{}", extension)).unwrap();
             assert_eq!(response.read_body().unwrap(), expected_body);
@@ -841,7 +2205,7 @@ mod tests {
                 .unwrap()
                 .to_str()
                 .unwrap(),
-            "text/html"
+            "text/html; charset=utf-8"
         );
 
         let expected_body = fs::read("resources/test/assets/doc.html").unwrap();
@@ -876,13 +2240,58 @@ mod tests {
                 .unwrap()
                 .to_str()
                 .unwrap(),
-            "text/html"
+            "text/html; charset=utf-8"
         );
 
+        assert_eq!(response.headers().get(VARY).unwrap(), "accept-encoding");
+
         let expected_body = fs::read("resources/test/assets_uncompressed/doc.html").unwrap();
         assert_eq!(response.read_body().unwrap(), expected_body);
     }
 
+    #[test]
+    fn assets_no_compression_if_quality_zero() {
+        let router = build_simple_router(|route| {
+            route.get("/*").to_dir(
+                FileOptions::new("resources/test/assets")
+                    .with_gzip(true)
+                    .with_brotli(true)
+                    .build(),
+            )
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/doc.html")
+            .with_header(
+                ACCEPT_ENCODING,
+                HeaderValue::from_str("br;q=0, gzip;q=0").unwrap(),
+            )
+            .perform()
+            .unwrap();
+
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        let expected_body = fs::read("resources/test/assets/doc.html").unwrap();
+        assert_eq!(response.read_body().unwrap(), expected_body);
+    }
+
+    #[test]
+    fn assets_no_vary_if_compression_disabled() {
+        let router = build_simple_router(|route| route.get("/*").to_dir("resources/test/assets"));
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/doc.html")
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_str("gzip").unwrap())
+            .perform()
+            .unwrap();
+
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert!(response.headers().get(VARY).is_none());
+    }
+
     #[test]
     fn assets_weighted_accept_encoding() {
         let router = build_simple_router(|route| {
@@ -912,7 +2321,7 @@ mod tests {
                 .unwrap()
                 .to_str()
                 .unwrap(),
-            "text/html"
+            "text/html; charset=utf-8"
         );
 
         assert_eq!(
@@ -928,6 +2337,36 @@ mod tests {
         assert_eq!(response.read_body().unwrap(), expected_body);
     }
 
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn assets_memory_mapped() {
+        let root = PathBuf::from("resources/test/assets");
+        let contents = fs::read(root.join("doc.html")).unwrap();
+        let router = build_simple_router(|route| {
+            route
+                .get("/*")
+                .to_dir(FileOptions::new(&root).with_mmap_min_size(Some(1)))
+        });
+        let server = TestServer::new(router).unwrap();
+
+        let response = server
+            .client()
+            .get("http://localhost/doc.html")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_body().unwrap(), contents);
+
+        let response = server
+            .client()
+            .get("http://localhost/doc.html")
+            .with_header(RANGE, HeaderValue::from_static("bytes=5-9"))
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.read_body().unwrap(), &contents[5..10]);
+    }
+
     #[test]
     fn assets_range_request() {
         let root = PathBuf::from("resources/test/assets");
@@ -0,0 +1,105 @@
+//! A short-lived cache of paths which were not found, so repeated requests of missing files don't
+//! reach the file system.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Remembers up to `max_entries` missing paths for `ttl` each. When full, expired paths are
+// dropped, and otherwise the path expiring first makes room for the new one.
+pub(super) struct NotFoundCache {
+    ttl: Duration,
+    max_entries: usize,
+    // the point in time at which each path expires
+    paths: Mutex<HashMap<PathBuf, Instant>>,
+}
+
+impl NotFoundCache {
+    pub(super) fn new(ttl: Duration, max_entries: usize) -> Self {
+        NotFoundCache {
+            ttl,
+            max_entries,
+            paths: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Checks whether `path` was recently found to be missing.
+    pub(super) fn contains(&self, path: &Path) -> bool {
+        let mut paths = self.paths.lock().unwrap();
+        match paths.get(path) {
+            Some(expires) if *expires > Instant::now() => true,
+            Some(_) => {
+                paths.remove(path);
+                false
+            }
+            None => false,
+        }
+    }
+
+    // Remembers `path` as missing until the TTL passed.
+    pub(super) fn insert(&self, path: &Path) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut paths = self.paths.lock().unwrap();
+        if paths.len() >= self.max_entries && !paths.contains_key(path) {
+            paths.retain(|_, expires| *expires > now);
+            if paths.len() >= self.max_entries {
+                let first = paths
+                    .iter()
+                    .min_by_key(|(_, expires)| **expires)
+                    .map(|(path, _)| path.clone());
+                if let Some(first) = first {
+                    paths.remove(&first);
+                }
+            }
+        }
+        paths.insert(path.to_path_buf(), now + self.ttl);
+    }
+}
+
+impl PartialEq for NotFoundCache {
+    fn eq(&self, other: &NotFoundCache) -> bool {
+        self.ttl == other.ttl && self.max_entries == other.max_entries
+    }
+}
+
+impl Eq for NotFoundCache {}
+
+impl fmt::Debug for NotFoundCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotFoundCache")
+            .field("ttl", &self.ttl)
+            .field("max_entries", &self.max_entries)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_and_evicts_paths() {
+        let cache = NotFoundCache::new(Duration::from_secs(60), 2);
+        cache.insert(Path::new("a"));
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert(Path::new("b"));
+        assert!(cache.contains(Path::new("a")));
+        assert!(!cache.contains(Path::new("c")));
+
+        // the path expiring first makes room
+        cache.insert(Path::new("c"));
+        assert!(!cache.contains(Path::new("a")));
+        assert!(cache.contains(Path::new("b")));
+        assert!(cache.contains(Path::new("c")));
+
+        let cache = NotFoundCache::new(Duration::from_millis(10), 2);
+        cache.insert(Path::new("a"));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!cache.contains(Path::new("a")));
+    }
+}
@@ -0,0 +1,220 @@
+//! Internal redirects of responses to files, in the style of `X-Sendfile` and
+//! `X-Accel-Redirect`, so handlers can authorize requests and leave serving files to the
+//! static file handler.
+
+use futures_util::future::{self, FutureExt, TryFutureExt};
+use hyper::{Body, Response, StatusCode};
+
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+
+use super::{DirHandler, FileOptions, FilePathExtractor};
+use crate::handler::{Handler, HandlerFuture};
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{State, StateData};
+
+/// An instruction to answer the request with a file, placed into the `State` by
+/// `create_send_file_response` and carried out by the `SendFileMiddleware`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendFile {
+    path: PathBuf,
+}
+
+impl StateData for SendFile {}
+
+impl SendFile {
+    /// Creates an instruction to send the file at `path`, relative to the root directory of the
+    /// `SendFileMiddleware`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        SendFile { path: path.into() }
+    }
+
+    /// Returns the path of the file, relative to the root directory of the `SendFileMiddleware`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Answers the request with the file at `path`, relative to the root directory of the
+/// `SendFileMiddleware` of the route.
+///
+/// The returned response is a placeholder, which the middleware replaces with the response for
+/// the file, as a `to_dir` route would serve it. Headers of the placeholder which the file
+/// response doesn't set, like `Content-Disposition` or `Set-Cookie`, are kept.
+///
+/// ```rust
+/// # use gotham::handler::{create_send_file_response, FileOptions, SendFileMiddleware};
+/// # use gotham::hyper::{Body, Response, StatusCode};
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn download(mut state: State) -> (State, Response<Body>) {
+///     // authorize the request, then let the middleware serve the file
+///     let response = create_send_file_response(&mut state, "doc.html");
+///     (state, response)
+/// }
+///
+/// # fn main() {
+/// let send_file = SendFileMiddleware::new(FileOptions::new("resources/test/assets").build());
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(send_file).build());
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/download").to(download);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client().get("http://localhost/download").perform().unwrap();
+/// # assert_eq!(response.status(), StatusCode::OK);
+/// # assert_eq!(response.read_utf8_body().unwrap(), "<html>I am a doc.</html>");
+/// # }
+/// ```
+pub fn create_send_file_response<P: Into<PathBuf>>(state: &mut State, path: P) -> Response<Body> {
+    state.put(SendFile::new(path));
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// A `Middleware` which answers requests with a file, if the handler placed a `SendFile` into the
+/// `State`, e.g. with `create_send_file_response`.
+///
+/// Files are looked up below the root directory of the `FileOptions`, and served with the same
+/// options and checks as by a `to_dir` route, including conditional and range requests, deny
+/// rules and symlink checks. The path is normalized, so it can't escape the root directory.
+#[derive(Clone, Debug)]
+pub struct SendFileMiddleware {
+    options: FileOptions,
+}
+
+impl SendFileMiddleware {
+    /// Creates a new `SendFileMiddleware` serving files with the given options.
+    pub fn new(options: FileOptions) -> Self {
+        SendFileMiddleware { options }
+    }
+}
+
+impl NewMiddleware for SendFileMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for SendFileMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        let options = self.options;
+        chain(state)
+            .and_then(move |(mut state, placeholder)| {
+                let send_file = match state.try_take::<SendFile>() {
+                    Some(send_file) => send_file,
+                    None => return future::ok((state, placeholder)).boxed(),
+                };
+                let parts = send_file
+                    .path
+                    .components()
+                    .filter_map(|component| match component {
+                        Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                        Component::ParentDir => Some("..".to_owned()),
+                        _ => None,
+                    })
+                    .collect();
+                state.put(FilePathExtractor { parts });
+
+                DirHandler { options }
+                    .handle(state)
+                    .map_ok(move |(state, mut response)| {
+                        let headers = response.headers_mut();
+                        for (name, value) in placeholder.headers() {
+                            if !headers.contains_key(name) {
+                                headers.append(name, value.clone());
+                            }
+                        }
+                        (state, response)
+                    })
+                    .boxed()
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::header::{CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+
+    fn download(mut state: State) -> (State, Response<Body>) {
+        let mut response = create_send_file_response(&mut state, "../doc.html");
+        response.headers_mut().insert(
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"doc.html\"".parse().unwrap(),
+        );
+        (state, response)
+    }
+
+    fn forbidden(state: State) -> (State, Response<Body>) {
+        let response = Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::empty())
+            .unwrap();
+        (state, response)
+    }
+
+    #[test]
+    fn sends_files() {
+        let send_file = SendFileMiddleware::new(FileOptions::new("resources/test/assets").build());
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(send_file).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/download").to(download);
+            route.get("/forbidden").to(forbidden);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/download")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"doc.html\""
+        );
+        assert!(response
+            .headers()
+            .get(CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let etag = response.headers().get(ETAG).unwrap().clone();
+        assert_eq!(
+            response.read_utf8_body().unwrap(),
+            "<html>I am a doc.</html>"
+        );
+
+        let response = test_server
+            .client()
+            .get("http://localhost/download")
+            .with_header(IF_NONE_MATCH, etag)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // responses without an instruction are passed on
+        let response = test_server
+            .client()
+            .get("http://localhost/forbidden")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,333 @@
+//! Stores request bodies as files below a directory, the counterpart of `to_dir` routes.
+
+use futures_util::FutureExt;
+use hyper::header::{ETAG, LOCATION};
+use hyper::{Body, Response, StatusCode, Uri};
+
+use std::io::{self, ErrorKind};
+use std::iter::FromIterator;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use super::{check_symlinks, deny, etag, io_error, normalize_path, FilePathExtractor};
+use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use crate::helpers::http::upload::{upload_to_file, UploadOptions};
+use crate::state::{FromState, State};
+
+/// A `Handler` which streams the body of `PUT` or `POST` requests to a file below a directory,
+/// like a `to_dir` route serves them.
+///
+/// The file is looked up by the part of the path matched by the glob segment of the route, which
+/// must extract it with `FilePathExtractor`, and is normalized like for `to_dir` routes, so
+/// uploads can't escape the directory. The body is written to a temporary file, which is renamed
+/// to its destination once the body was received completely, see `upload_to_file`.
+///
+/// Successful uploads are answered with "201 Created", or "204 No Content" if an existing file
+/// was replaced, along with the strong entity tag of the content, as served by `to_dir` routes
+/// with `FileOptions::with_strong_etag`. Paths containing a hidden segment, whose name starts with
+/// a dot, are rejected with "400 Bad Request", as are uploads to the directory itself.
+///
+/// ```rust
+/// # use gotham::handler::{FilePathExtractor, FileUploadHandler};
+/// # use gotham::helpers::http::upload::UploadOptions;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// # use hyper::StatusCode;
+/// #
+/// # fn main() {
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let root = dir.path().to_path_buf();
+/// let uploads = FileUploadHandler::new(root)
+///     .with_options(UploadOptions::new().with_max_size(10 * 1024 * 1024));
+///
+/// let router = build_simple_router(|route| {
+///     route
+///         .put("/uploads/*")
+///         .with_path_extractor::<FilePathExtractor>()
+///         .to_new_handler(uploads);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .put("http://localhost/uploads/report.csv", "a,b", mime::TEXT_CSV)
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.status(), StatusCode::CREATED);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FileUploadHandler {
+    root: PathBuf,
+    // the progress callback of the options is not required to be unwind safe
+    options: AssertUnwindSafe<UploadOptions>,
+    create_dirs: bool,
+    overwrite: bool,
+}
+
+impl Clone for FileUploadHandler {
+    fn clone(&self) -> Self {
+        FileUploadHandler {
+            root: self.root.clone(),
+            options: AssertUnwindSafe(self.options.0.clone()),
+            create_dirs: self.create_dirs,
+            overwrite: self.overwrite,
+        }
+    }
+}
+
+impl FileUploadHandler {
+    /// Creates a new `FileUploadHandler` storing files below the directory `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        FileUploadHandler {
+            root: root.into(),
+            options: AssertUnwindSafe(UploadOptions::new()),
+            create_dirs: true,
+            overwrite: true,
+        }
+    }
+
+    /// Sets the options used to write the body, like its maximum size (defaults to
+    /// `UploadOptions::new()`).
+    pub fn with_options(mut self, options: UploadOptions) -> Self {
+        self.options = AssertUnwindSafe(options);
+        self
+    }
+
+    /// If `true`, missing directories below the root directory are created for uploads into them,
+    /// otherwise such uploads are answered with "404 Not Found" (defaults to true).
+    pub fn with_create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// If `true`, uploads replace existing files, otherwise they are answered with
+    /// "409 Conflict" (defaults to true).
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    // Stores the body of the request at `path`, returning the status and entity tag to answer
+    // with.
+    async fn store(
+        &self,
+        state: &mut State,
+        path: &Path,
+    ) -> Result<(StatusCode, String), HandlerError> {
+        let destination = self.root.join(path);
+        let parent = destination.parent().unwrap_or(&self.root);
+        if self.create_dirs {
+            self.create_parent_dirs(parent).await.map_err(io_error)?;
+        }
+        // directories below the root may be symlinks to elsewhere
+        check_symlinks(&self.root, parent, true)
+            .await
+            .map_err(io_error)?;
+
+        let exists = match tokio::fs::metadata(&destination).await {
+            Ok(meta) if meta.is_dir() => {
+                return Err(
+                    HandlerError::from(io::Error::from(ErrorKind::AlreadyExists))
+                        .with_status(StatusCode::CONFLICT),
+                )
+            }
+            Ok(_) => true,
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => return Err(io_error(e)),
+        };
+        if exists && !self.overwrite {
+            return Err(
+                HandlerError::from(io::Error::from(ErrorKind::AlreadyExists))
+                    .with_status(StatusCode::CONFLICT),
+            );
+        }
+
+        let file = upload_to_file(state, &destination, &self.options)
+            .await
+            .map_err(|err| {
+                let status = err.status();
+                HandlerError::from(err).with_status(status)
+            })?;
+        let status = if exists {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::CREATED
+        };
+        // identical to the strong entity tags of files served by `to_dir` routes
+        Ok((status, etag::digest_entity_tag(file.sha256())))
+    }
+
+    // Creates the missing directories of `parent` one at a time, checking each one before
+    // creating anything inside it, so a symlink below the root can't make an upload create
+    // directories outside of it.
+    async fn create_parent_dirs(&self, parent: &Path) -> io::Result<()> {
+        let relative = parent.strip_prefix(&self.root).unwrap_or(parent);
+        let mut current = self.root.clone();
+        for component in relative.components() {
+            current.push(component);
+            match tokio::fs::create_dir(&current).await {
+                Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e),
+                _ => check_symlinks(&self.root, &current, true).await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NewHandler for FileUploadHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for FileUploadHandler {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        let path = normalize_path(&PathBuf::from_iter(
+            &FilePathExtractor::borrow_from(&state).parts,
+        ));
+        async move {
+            if path.as_os_str().is_empty() || deny::is_denied(&path, true, &[]) {
+                let err = HandlerError::from(io::Error::from(ErrorKind::InvalidInput))
+                    .with_status(StatusCode::BAD_REQUEST);
+                return Err((state, err));
+            }
+            match self.store(&mut state, &path).await {
+                Ok((status, etag)) => {
+                    let response = Response::builder()
+                        .status(status)
+                        .header(ETAG, etag)
+                        .header(LOCATION, Uri::borrow_from(&state).path())
+                        .body(Body::empty())
+                        .unwrap();
+                    Ok((state, response))
+                }
+                Err(err) => Err((state, err)),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use std::fs;
+
+    fn test_server(handler: FileUploadHandler) -> TestServer {
+        TestServer::new(build_simple_router(|route| {
+            route
+                .request(vec![hyper::Method::PUT, hyper::Method::POST], "/*")
+                .with_path_extractor::<FilePathExtractor>()
+                .to_new_handler(handler);
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn stores_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let test_server = test_server(FileUploadHandler::new(dir.path()));
+
+        let response = test_server
+            .client()
+            .put("http://localhost/docs/notes.txt", "first", mime::TEXT_PLAIN)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get(ETAG).unwrap(),
+            super::super::etag::content_entity_tag(b"first").as_str()
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("docs/notes.txt")).unwrap(),
+            "first"
+        );
+
+        let response = test_server
+            .client()
+            .post(
+                "http://localhost/docs/notes.txt",
+                "second",
+                mime::TEXT_PLAIN,
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("docs/notes.txt")).unwrap(),
+            "second"
+        );
+
+        // traversal is normalized into the root directory
+        let response = test_server
+            .client()
+            .put("http://localhost/../escape.txt", "escape", mime::TEXT_PLAIN)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(dir.path().join("escape.txt").is_file());
+    }
+
+    #[test]
+    fn rejects_invalid_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("existing.txt"), "existing").unwrap();
+        let handler = FileUploadHandler::new(dir.path())
+            .with_options(UploadOptions::new().with_max_size(4))
+            .with_create_dirs(false)
+            .with_overwrite(false);
+        let test_server = test_server(handler);
+
+        for (path, status) in &[
+            (".env", StatusCode::BAD_REQUEST),
+            ("existing.txt", StatusCode::CONFLICT),
+            ("missing/file.txt", StatusCode::NOT_FOUND),
+            ("large.txt", StatusCode::PAYLOAD_TOO_LARGE),
+        ] {
+            let response = test_server
+                .client()
+                .put(
+                    format!("http://localhost/{}", path),
+                    "too large",
+                    mime::TEXT_PLAIN,
+                )
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), *status, "{}", path);
+        }
+        assert_eq!(
+            fs::read_to_string(dir.path().join("existing.txt")).unwrap(),
+            "existing"
+        );
+        assert!(!dir.path().join("large.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_uploads_through_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let outside = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        symlink(outside.path(), dir.path().join("escape")).unwrap();
+        let test_server = test_server(FileUploadHandler::new(dir.path()));
+
+        let response = test_server
+            .client()
+            .put(
+                "http://localhost/escape/nested/file.txt",
+                "escape",
+                mime::TEXT_PLAIN,
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,337 @@
+//! Serves directories to WebDAV clients, like the Finder of macOS or the file explorer of Windows,
+//! as a read-only WebDAV class 1 server.
+
+use futures_util::FutureExt;
+use httpdate::fmt_http_date;
+use hyper::header::{HeaderMap, HeaderValue, ALLOW, CONTENT_TYPE};
+use hyper::{Body, Method, Response, StatusCode, Uri};
+use percent_encoding::utf8_percent_encode;
+
+use std::fmt::Write;
+use std::fs::Metadata;
+use std::io;
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use super::listing::{escape, SEGMENT};
+use super::{
+    check_symlinks, deny, io_error, normalize_path, DirHandler, FileOptions, FilePathExtractor,
+};
+use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use crate::helpers::http::conditional::entity_tag;
+use crate::state::{FromState, State};
+
+const ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+/// A `Handler` which serves the files below a directory to WebDAV clients.
+///
+/// `GET` and `HEAD` requests are answered like by a `to_dir` route with the same `FileOptions`.
+/// `PROPFIND` requests are answered with the size, mime type, modification date and entity tag
+/// of the requested file, or of a directory and its entries with a `Depth` of 1, while requests
+/// with an infinite depth are rejected. Files denied by the `FileOptions` are neither listed nor
+/// served. Methods modifying files aren't supported, so clients mount the directory read-only.
+///
+/// The route must match the methods of WebDAV and extract the path with `FilePathExtractor`:
+///
+/// ```rust
+/// # use gotham::handler::{FileOptions, FilePathExtractor, WebDavHandler};
+/// # use gotham::hyper::Method;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// #
+/// # fn main() {
+/// let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+/// let webdav = WebDavHandler::new(FileOptions::new("resources/test/assets").build());
+///
+/// let router = build_simple_router(|route| {
+///     route
+///         .request(
+///             vec![Method::OPTIONS, Method::GET, Method::HEAD, propfind.clone()],
+///             "/dav/*",
+///         )
+///         .with_path_extractor::<FilePathExtractor>()
+///         .to_new_handler(webdav);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .build_request(propfind, "http://localhost/dav/doc.html")
+/// #     .with_header("depth", "0".parse().unwrap())
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.status(), 207);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct WebDavHandler {
+    options: FileOptions,
+}
+
+impl WebDavHandler {
+    /// Creates a new `WebDavHandler` serving the root directory of the given options.
+    pub fn new(options: FileOptions) -> Self {
+        WebDavHandler { options }
+    }
+}
+
+impl NewHandler for WebDavHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for WebDavHandler {
+    fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
+        let method = Method::borrow_from(&state).clone();
+        match method.as_str() {
+            "GET" | "HEAD" => DirHandler {
+                options: self.options,
+            }
+            .handle(state),
+            "PROPFIND" => {
+                // owned copies, as the `State` can't be borrowed across awaits of a `Send` future
+                let depth = depth(HeaderMap::borrow_from(&state));
+                let file_path = normalize_path(&PathBuf::from_iter(
+                    &FilePathExtractor::borrow_from(&state).parts,
+                ));
+                let href = Uri::borrow_from(&state).path().to_owned();
+                async move {
+                    match propfind(&self.options, depth, file_path, href).await {
+                        Ok(response) => Ok((state, response)),
+                        Err(err) => Err((state, err)),
+                    }
+                }
+                .boxed()
+            }
+            method => {
+                let status = if method == "OPTIONS" {
+                    StatusCode::OK
+                } else {
+                    StatusCode::METHOD_NOT_ALLOWED
+                };
+                let response = Response::builder()
+                    .status(status)
+                    .header("DAV", "1")
+                    .header("MS-Author-Via", "DAV")
+                    .header(ALLOW, ALLOWED_METHODS)
+                    .body(Body::empty())
+                    .unwrap();
+                futures_util::future::ok((state, response)).boxed()
+            }
+        }
+    }
+}
+
+// The depth of a `PROPFIND` request, which is infinite if the header is missing.
+fn depth(headers: &HeaderMap) -> Option<u8> {
+    match headers.get("depth").map(HeaderValue::as_bytes) {
+        Some(b"0") => Some(0),
+        Some(b"1") => Some(1),
+        _ => None,
+    }
+}
+
+// Answers a `PROPFIND` request with the properties of the requested file, and of its entries for
+// directories requested with a depth of 1.
+async fn propfind(
+    options: &FileOptions,
+    depth: Option<u8>,
+    file_path: PathBuf,
+    mut href: String,
+) -> Result<Response<Body>, HandlerError> {
+    let depth = match depth {
+        Some(depth) => depth,
+        None => {
+            let body = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                        <D:error xmlns:D=\"DAV:\"><D:propfind-finite-depth/></D:error>\n";
+            return Ok(xml_response(StatusCode::FORBIDDEN, body.to_owned()));
+        }
+    };
+
+    if deny::is_denied(&file_path, options.deny_hidden, &options.deny_patterns) {
+        return Err(io_error(io::Error::from(io::ErrorKind::NotFound)));
+    }
+    let mut path = options.path.clone();
+    path.extend(&file_path);
+    check_symlinks(&options.path, &path, options.follow_symlinks)
+        .await
+        .map_err(io_error)?;
+    let meta = tokio::fs::metadata(&path).await.map_err(io_error)?;
+
+    if meta.is_dir() && !href.ends_with('/') {
+        href.push('/');
+    }
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut body = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+    );
+    push_response(&mut body, options, &href, &name, &path, &meta);
+
+    if depth == 1 && meta.is_dir() {
+        let mut entries = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(&path).await.map_err(io_error)?;
+        while let Some(entry) = read_dir.next_entry().await.map_err(io_error)? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if deny::is_denied(
+                &file_path.join(&name),
+                options.deny_hidden,
+                &options.deny_patterns,
+            ) {
+                continue;
+            }
+            // entries which vanished or can't be read are left out
+            if let Ok(meta) = tokio::fs::metadata(entry.path()).await {
+                entries.push((name, entry.path(), meta));
+            }
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, path, meta) in entries {
+            let suffix = if meta.is_dir() { "/" } else { "" };
+            let entry_href = format!("{}{}{}", href, utf8_percent_encode(&name, SEGMENT), suffix);
+            push_response(&mut body, options, &entry_href, &name, &path, &meta);
+        }
+    }
+    body.push_str("</D:multistatus>\n");
+
+    Ok(xml_response(StatusCode::MULTI_STATUS, body))
+}
+
+// Appends the `response` element describing the file at `path` to `body`.
+fn push_response(
+    body: &mut String,
+    options: &FileOptions,
+    href: &str,
+    name: &str,
+    path: &Path,
+    meta: &Metadata,
+) {
+    let _ = write!(
+        body,
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop>\
+         <D:displayname>{}</D:displayname>",
+        escape(href),
+        escape(name)
+    );
+    if meta.is_dir() {
+        body.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+    } else {
+        let _ = write!(
+            body,
+            "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>\
+             <D:getcontenttype>{}</D:getcontenttype>",
+            meta.len(),
+            escape(options.mime_type(path).as_ref())
+        );
+        if let Some(etag) = entity_tag(meta) {
+            let _ = write!(body, "<D:getetag>{}</D:getetag>", escape(&etag));
+        }
+    }
+    if let Ok(modified) = meta.modified() {
+        let _ = write!(
+            body,
+            "<D:getlastmodified>{}</D:getlastmodified>",
+            fmt_http_date(modified)
+        );
+    }
+    body.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n");
+}
+
+fn xml_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::header::ETAG;
+    use std::fs;
+
+    #[test]
+    fn answers_propfind_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("docs/a b.txt"), "text").unwrap();
+        fs::write(dir.path().join("docs/.secret"), "secret").unwrap();
+        fs::create_dir(dir.path().join("docs/nested")).unwrap();
+
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        let webdav =
+            WebDavHandler::new(FileOptions::new(dir.path()).with_deny_hidden(true).build());
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route
+                .request(
+                    vec![Method::OPTIONS, Method::GET, Method::HEAD, propfind.clone()],
+                    "/dav/*",
+                )
+                .with_path_extractor::<FilePathExtractor>()
+                .to_new_handler(webdav);
+        }))
+        .unwrap();
+        let request = |uri: &str, depth: Option<&str>| {
+            let client = test_server.client();
+            let mut request = client.build_request(propfind.clone(), uri);
+            if let Some(depth) = depth {
+                request = request.with_header("depth", depth.parse().unwrap());
+            }
+            request.perform().unwrap()
+        };
+
+        let response = request("http://localhost/dav/docs", Some("1"));
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        let body = response.read_utf8_body().unwrap();
+        assert!(body.contains("<D:href>/dav/docs/</D:href>"));
+        assert!(body.contains("<D:href>/dav/docs/a%20b.txt</D:href>"));
+        assert!(body.contains("<D:getcontentlength>4</D:getcontentlength>"));
+        assert!(body.contains("<D:href>/dav/docs/nested/</D:href>"));
+        assert!(!body.contains(".secret"));
+        assert_eq!(body.matches("<D:collection/>").count(), 2);
+
+        let response = request("http://localhost/dav/docs", Some("0"));
+        let body = response.read_utf8_body().unwrap();
+        assert_eq!(body.matches("<D:response>").count(), 1);
+
+        // the entity tag matches the one of GET requests
+        let etag = test_server
+            .client()
+            .get("http://localhost/dav/docs/a%20b.txt")
+            .perform()
+            .unwrap()
+            .headers()
+            .get(ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = request("http://localhost/dav/docs/a%20b.txt", Some("0"))
+            .read_utf8_body()
+            .unwrap();
+        assert!(body.contains(&format!("<D:getetag>{}</D:getetag>", escape(&etag))));
+
+        let response = request("http://localhost/dav/docs", None);
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = request("http://localhost/dav/missing", Some("0"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = test_server
+            .client()
+            .options("http://localhost/dav/docs")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("dav").unwrap(), "1");
+    }
+}
@@ -6,16 +6,22 @@ use std::task::{Context, Poll};
 
 use hyper::{Body, Response, StatusCode};
 use log::{debug, trace};
+use mime::Mime;
 
 use crate::handler::IntoResponse;
-use crate::helpers::http::response::create_empty_response;
+use crate::helpers::http::response::{create_empty_response, create_response};
 use crate::state::{request_id, State};
 
+#[cfg(feature = "derive")]
+pub use gotham_derive::HttpError;
+
 /// Describes an error which occurred during handler execution, and allows the creation of a HTTP
 /// `Response`.
 #[derive(Debug)]
 pub struct HandlerError {
     status_code: StatusCode,
+    // boxed to keep `HandlerError`, and the results containing it, small
+    body: Option<Box<(Mime, String)>>,
     cause: anyhow::Error,
 }
 
@@ -30,6 +36,7 @@ where
 
         HandlerError {
             status_code: StatusCode::INTERNAL_SERVER_ERROR,
+            body: None,
             cause: error.into(),
         }
     }
@@ -120,7 +127,112 @@ impl IntoResponse for HandlerError {
             self.cause
         );
 
-        create_empty_response(state, self.status_code)
+        match self.body.map(|body| *body) {
+            Some((mime, body)) => create_response(state, self.status_code, mime, body),
+            None => create_empty_response(state, self.status_code),
+        }
+    }
+}
+
+/// An application error which determines the response sent when it is returned by a handler.
+///
+/// Handlers passed to `to_async_borrowing` may return any error implementing `IntoHandlerError`,
+/// which includes all `HttpError`s. This allows handlers to use the `?` operator with their own
+/// error types, instead of mapping each error to a status code.
+///
+/// `HttpError` can be derived for structs and enums, typically alongside `thiserror::Error`. The
+/// status code is set with `#[http_error(status = ...)]` on the type or on each variant, and
+/// defaults to "500 Internal Server Error". With `#[http_error(expose)]`, the `Display` message
+/// of the error is sent as the response body, which is otherwise empty. Deriving `HttpError` also
+/// implements `IntoResponse`, so the error can be returned by `#[handler]` functions as well.
+///
+/// ```rust
+/// # use gotham::hyper::StatusCode;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::handler::HttpError;
+/// use gotham::state::State;
+///
+/// #[derive(Debug, thiserror::Error, HttpError)]
+/// enum ApiError {
+///     #[error("no such flavor: {0}")]
+///     #[http_error(status = 404, expose)]
+///     UnknownFlavor(String),
+///     #[error("failed to read the flavors")]
+///     Io(#[from] std::io::Error),
+/// }
+///
+/// async fn flavor(_state: &mut State) -> Result<String, ApiError> {
+///     let flavors = std::fs::read_to_string("coffee-flavors.txt").unwrap_or_default();
+///     match flavors.lines().next() {
+///         Some(flavor) => Ok(flavor.to_owned()),
+///         None => Err(ApiError::UnknownFlavor("vanilla".to_owned())),
+///     }
+/// }
+///
+/// # fn main() {
+/// let router = build_simple_router(|route| {
+///     route.get("/flavor").to_async_borrowing(flavor);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client()
+/// #     .get("http://localhost/flavor")
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.status(), StatusCode::NOT_FOUND);
+/// # assert_eq!(response.read_utf8_body().unwrap(), "no such flavor: vanilla");
+/// # }
+/// ```
+///
+/// Invalid status codes are rejected at compile time:
+///
+/// ```rust,compile_fail
+/// use gotham::handler::HttpError;
+///
+/// #[derive(Debug, thiserror::Error, HttpError)]
+/// #[error("teapot")]
+/// #[http_error(status = 1000)]
+/// struct Teapot;
+/// # fn main() { let _ = Teapot; }
+/// ```
+pub trait HttpError: std::error::Error + Send + Sync + 'static {
+    /// Returns the status code of the response.
+    fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    /// Returns the content type and body of the response, or `None` for an empty body.
+    fn body(&self) -> Option<(Mime, String)> {
+        None
+    }
+}
+
+/// Converts an error into a `HandlerError`. This is implemented for `HandlerError` itself, and
+/// for all `HttpError`s.
+pub trait IntoHandlerError {
+    /// Converts this error into a `HandlerError`.
+    fn into_handler_error(self) -> HandlerError;
+}
+
+impl IntoHandlerError for HandlerError {
+    fn into_handler_error(self) -> HandlerError {
+        self
+    }
+}
+
+impl<E> IntoHandlerError for E
+where
+    E: HttpError,
+{
+    fn into_handler_error(self) -> HandlerError {
+        trace!(" converting HttpError to HandlerError: {}", self);
+
+        HandlerError {
+            status_code: self.status(),
+            body: self.body().map(Box::new),
+            cause: self.into(),
+        }
     }
 }
 
@@ -165,6 +277,7 @@ where
             trace!(" converting Error to HandlerError: {}", err);
             HandlerError {
                 status_code,
+                body: None,
                 cause: err.into(),
             }
         })
@@ -285,6 +398,36 @@ mod test {
         Err(DummyError.into())
     }
 
+    #[derive(Debug, Error)]
+    #[error("Not Found Error")]
+    struct NotFoundError;
+
+    impl HttpError for NotFoundError {
+        fn status(&self) -> StatusCode {
+            StatusCode::NOT_FOUND
+        }
+
+        fn body(&self) -> Option<(Mime, String)> {
+            Some((mime::TEXT_PLAIN, self.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_http_error() {
+        let err = NotFoundError.into_handler_error();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        assert!(err.downcast_cause_ref::<NotFoundError>().is_some());
+
+        let request = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let state = State::from_request(request, "127.0.0.1:10000".parse().unwrap());
+        let response = err.into_response(&state);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[hyper::header::CONTENT_TYPE],
+            mime::TEXT_PLAIN.as_ref()
+        );
+    }
+
     #[test]
     fn test_error_downcast() {
         let mut err = error_prone().unwrap_err();
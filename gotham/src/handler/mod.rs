@@ -3,6 +3,44 @@
 //! A function can be used directly as a handler using one of the default implementations of
 //! `Handler`, but the traits can also be implemented directly for greater control. See the
 //! `Handler` trait for some examples of valid handlers.
+//!
+//! With the `derive` feature, the `#[handler]` attribute from the prelude turns a function taking
+//! extracted values as arguments into a handler, taking them from the `State` on its behalf:
+//!
+//! ```rust
+//! # use gotham::hyper::HeaderMap;
+//! # use gotham::router::builder::*;
+//! # use gotham::test::TestServer;
+//! # use serde::Deserialize;
+//! use gotham::prelude::*;
+//!
+//! #[derive(Deserialize, StateData, StaticResponseExtender)]
+//! struct GreetingPath {
+//!     name: String,
+//! }
+//!
+//! #[handler]
+//! async fn greet(path: GreetingPath, headers: &HeaderMap) -> String {
+//!     let greeting = headers.get("x-greeting").and_then(|value| value.to_str().ok());
+//!     format!("{}, {}!", greeting.unwrap_or("Hello"), path.name)
+//! }
+//!
+//! # fn main() {
+//! let router = build_simple_router(|route| {
+//!     route
+//!         .get("/greet/:name")
+//!         .with_path_extractor::<GreetingPath>()
+//!         .to(greet);
+//! });
+//! let test_server = TestServer::new(router).unwrap();
+//! let response = test_server
+//!     .client()
+//!     .get("http://localhost/greet/gotham")
+//!     .perform()
+//!     .unwrap();
+//! assert_eq!(response.read_utf8_body().unwrap(), "Hello, gotham!");
+//! # }
+//! ```
 use std::borrow::Cow;
 use std::future::Future;
 use std::ops::Deref;
@@ -18,11 +56,15 @@ use mime::{self, Mime};
 use crate::helpers::http::response;
 use crate::state::State;
 
-mod assets;
+pub(crate) mod assets;
 pub use assets::*;
 
+pub mod tus;
+
 mod error;
-pub use error::{HandlerError, MapHandlerError, MapHandlerErrorFuture};
+pub use error::{
+    HandlerError, HttpError, IntoHandlerError, MapHandlerError, MapHandlerErrorFuture,
+};
 
 /// A type alias for the results returned by async fns that can be passed to to_async.
 pub type HandlerResult = std::result::Result<(State, Response<Body>), (State, HandlerError)>;
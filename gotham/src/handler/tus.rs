@@ -0,0 +1,695 @@
+//! Resumable uploads following the tus protocol.
+//!
+//! Uploads over flaky connections, like large files from mobile clients, don't have to restart
+//! from the beginning when the connection drops: a client implementing the
+//! [tus protocol](https://tus.io/protocols/resumable-upload) creates an upload with a `POST`
+//! request, sends its content with `PATCH` requests, and after an interruption asks for the
+//! offset the server received with a `HEAD` request, to continue from there.
+//!
+//! The `TusHandler` implements version 1.0.0 of the core protocol with the `creation` and
+//! `termination` extensions. The content of uploads is written to files in a directory, while
+//! their length, offset and metadata are kept in a `TusStore`, which keeps them in memory by
+//! default. Once an upload is complete, the callback set with `TusHandler::with_on_complete` is
+//! invoked with the file, e.g. to move it to its final location.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use gotham::router::builder::*;
+//! use gotham::handler::tus::TusHandler;
+//! use gotham::hyper::Method;
+//!
+//! # fn main() {
+//! # let dir = tempfile::tempdir().unwrap();
+//! # let dir = dir.path().to_path_buf();
+//! let tus = TusHandler::new(dir).with_max_size(1024 * 1024 * 1024).with_on_complete(
+//!     |upload, path| async move {
+//!         let name = upload.metadata("filename").unwrap_or("unnamed");
+//!         println!("received {} at {:?}", name, path);
+//!         Ok(())
+//!     },
+//! );
+//!
+//! let router = build_simple_router(|route| {
+//!     route
+//!         .request(vec![Method::OPTIONS, Method::POST], "/files")
+//!         .to_new_handler(tus.clone());
+//!     route
+//!         .request(
+//!             vec![Method::OPTIONS, Method::HEAD, Method::PATCH, Method::DELETE],
+//!             "/files/:id",
+//!         )
+//!         .to_new_handler(tus);
+//! });
+//! # let _ = router;
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::panic::RefUnwindSafe;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use base64::prelude::*;
+use futures_util::future::{self, FutureExt};
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use hyper::{Body, Method, Response, StatusCode, Uri};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use crate::helpers::http::response::create_empty_response;
+use crate::state::{request_id, FromState, State};
+
+/// The version of the tus protocol required by every request but `OPTIONS`.
+pub const TUS_RESUMABLE: &str = "tus-resumable";
+/// The versions of the tus protocol supported by the server.
+pub const TUS_VERSION: &str = "tus-version";
+/// The extensions of the tus protocol supported by the server.
+pub const TUS_EXTENSION: &str = "tus-extension";
+/// The maximum size of uploads accepted by the server.
+pub const TUS_MAX_SIZE: &str = "tus-max-size";
+/// The number of bytes of an upload received by the server.
+pub const UPLOAD_OFFSET: &str = "upload-offset";
+/// The size of an upload.
+pub const UPLOAD_LENGTH: &str = "upload-length";
+/// The metadata of an upload, as comma separated keys and base64 encoded values.
+pub const UPLOAD_METADATA: &str = "upload-metadata";
+
+const VERSION: &str = "1.0.0";
+const EXTENSIONS: &str = "creation,termination";
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// The state of a resumable upload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Upload {
+    id: String,
+    length: u64,
+    offset: u64,
+    metadata: BTreeMap<String, String>,
+}
+
+impl Upload {
+    /// Returns the ID of the upload, the last segment of its URL.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the size of the upload.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the number of bytes received.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns whether all bytes of the upload have been received.
+    pub fn is_complete(&self) -> bool {
+        self.offset == self.length
+    }
+
+    /// Returns a value of the metadata sent by the client when creating the upload, like
+    /// `filename` or `filetype`.
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    // The metadata as sent in the `Upload-Metadata` header.
+    fn encoded_metadata(&self) -> String {
+        self.metadata
+            .iter()
+            .map(|(key, value)| match value.as_str() {
+                "" => key.clone(),
+                value => format!("{} {}", key, BASE64_STANDARD.encode(value)),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Type alias for the trait objects returned by `TusStore`.
+pub type TusFuture<T> = dyn Future<Output = anyhow::Result<T>> + Send;
+
+/// A `TusStore` keeps the length, offset and metadata of uploads, e.g. in memory or in a database
+/// shared by several servers which write uploads to a shared directory.
+pub trait TusStore: Send + Sync + RefUnwindSafe {
+    /// Stores a new upload.
+    fn create(&self, upload: &Upload) -> Pin<Box<TusFuture<()>>>;
+
+    /// Returns the upload with the given ID, if it exists.
+    fn get(&self, id: &str) -> Pin<Box<TusFuture<Option<Upload>>>>;
+
+    /// Sets the offset of an upload, after the bytes up to the offset have been written.
+    fn set_offset(&self, id: &str, offset: u64) -> Pin<Box<TusFuture<()>>>;
+
+    /// Removes an upload.
+    fn remove(&self, id: &str) -> Pin<Box<TusFuture<()>>>;
+}
+
+/// A `TusStore` keeping uploads in memory, so uploads can't be resumed after a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    uploads: Mutex<HashMap<String, Upload>>,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("len", &self.uploads.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl TusStore for MemoryStore {
+    fn create(&self, upload: &Upload) -> Pin<Box<TusFuture<()>>> {
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.insert(upload.id.clone(), upload.clone());
+        future::ok(()).boxed()
+    }
+
+    fn get(&self, id: &str) -> Pin<Box<TusFuture<Option<Upload>>>> {
+        future::ok(self.uploads.lock().unwrap().get(id).cloned()).boxed()
+    }
+
+    fn set_offset(&self, id: &str, offset: u64) -> Pin<Box<TusFuture<()>>> {
+        if let Some(upload) = self.uploads.lock().unwrap().get_mut(id) {
+            upload.offset = offset;
+        }
+        future::ok(()).boxed()
+    }
+
+    fn remove(&self, id: &str) -> Pin<Box<TusFuture<()>>> {
+        self.uploads.lock().unwrap().remove(id);
+        future::ok(()).boxed()
+    }
+}
+
+type OnComplete = dyn Fn(Upload, PathBuf) -> Pin<Box<TusFuture<()>>> + Send + Sync + RefUnwindSafe;
+
+/// A `Handler` implementing the tus protocol for resumable uploads, see the module documentation.
+///
+/// The handler answers `POST` requests to create an upload, whose URL is the path of the request
+/// followed by the ID of the upload, and `HEAD`, `PATCH` and `DELETE` requests to the URL of an
+/// upload, taking the ID from the last segment of the path. `OPTIONS` requests are answered with
+/// the capabilities of the server.
+///
+/// The bytes received by a `PATCH` request are kept even if the request is interrupted, so the
+/// client can resume from there. Concurrent `PATCH` requests to the same upload are answered with
+/// `423 Locked`.
+#[derive(Clone)]
+pub struct TusHandler {
+    dir: PathBuf,
+    store: Arc<dyn TusStore>,
+    max_size: Option<u64>,
+    on_complete: Option<Arc<OnComplete>>,
+    locks: Arc<Mutex<HashSet<String>>>,
+}
+
+impl TusHandler {
+    /// Creates a new `TusHandler` writing uploads to files in `dir`, which must exist, and keeping
+    /// their state in a `MemoryStore`.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        TusHandler {
+            dir: dir.into(),
+            store: Arc::new(MemoryStore::new()),
+            max_size: None,
+            on_complete: None,
+            locks: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Sets the store keeping the state of uploads.
+    pub fn with_store<S: TusStore + 'static>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Rejects uploads larger than `max_size` bytes with `413 Payload Too Large`.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Sets a callback invoked with an upload and its file once all of its bytes have been
+    /// received, before the last `PATCH` request is answered. If it fails, the request is answered
+    /// with `500 Internal Server Error`, and the upload is kept.
+    pub fn with_on_complete<F, Fut>(mut self, on_complete: F) -> Self
+    where
+        F: Fn(Upload, PathBuf) -> Fut + Send + Sync + RefUnwindSafe + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.on_complete = Some(Arc::new(move |upload, path| {
+            on_complete(upload, path).boxed()
+        }));
+        self
+    }
+
+    /// Returns the file the content of an upload is written to.
+    pub fn path(&self, upload: &Upload) -> PathBuf {
+        self.dir.join(&upload.id)
+    }
+
+    async fn respond(&self, state: &mut State) -> Result<Response<Body>, HandlerError> {
+        let method = Method::borrow_from(state).clone();
+        if method == Method::OPTIONS {
+            let mut response = tus_response(state, StatusCode::NO_CONTENT);
+            let headers = response.headers_mut();
+            headers.insert(TUS_VERSION, HeaderValue::from_static(VERSION));
+            headers.insert(TUS_EXTENSION, HeaderValue::from_static(EXTENSIONS));
+            if let Some(max_size) = self.max_size {
+                headers.insert(TUS_MAX_SIZE, max_size.into());
+            }
+            return Ok(response);
+        }
+
+        let headers = HeaderMap::borrow_from(state);
+        if headers.get(TUS_RESUMABLE).is_none_or(|v| v != VERSION) {
+            let mut response = tus_response(state, StatusCode::PRECONDITION_FAILED);
+            let headers = response.headers_mut();
+            headers.insert(TUS_VERSION, HeaderValue::from_static(VERSION));
+            return Ok(response);
+        }
+
+        if method == Method::POST {
+            return self.create(state).await;
+        }
+        let id = match upload_id(Uri::borrow_from(state).path()) {
+            Some(id) => id.to_owned(),
+            None => return Ok(tus_response(state, StatusCode::NOT_FOUND)),
+        };
+        let upload = match self.store.get(&id).await? {
+            Some(upload) => upload,
+            None => return Ok(tus_response(state, StatusCode::NOT_FOUND)),
+        };
+        match method {
+            Method::HEAD => {
+                let mut response = tus_response(state, StatusCode::OK);
+                let headers = response.headers_mut();
+                headers.insert(UPLOAD_OFFSET, upload.offset.into());
+                headers.insert(UPLOAD_LENGTH, upload.length.into());
+                if !upload.metadata.is_empty() {
+                    if let Ok(metadata) = HeaderValue::try_from(upload.encoded_metadata()) {
+                        headers.insert(UPLOAD_METADATA, metadata);
+                    }
+                }
+                headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+                Ok(response)
+            }
+            Method::PATCH => self.patch(state, upload).await,
+            Method::DELETE => {
+                self.store.remove(&upload.id).await?;
+                match fs::remove_file(self.path(&upload)).await {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(err.into())
+                    }
+                    _ => {}
+                }
+                Ok(tus_response(state, StatusCode::NO_CONTENT))
+            }
+            _ => Ok(tus_response(state, StatusCode::METHOD_NOT_ALLOWED)),
+        }
+    }
+
+    async fn create(&self, state: &mut State) -> Result<Response<Body>, HandlerError> {
+        let headers = HeaderMap::borrow_from(state);
+        let length = match header_u64(headers, UPLOAD_LENGTH) {
+            Some(length) => length,
+            None => return Ok(tus_response(state, StatusCode::BAD_REQUEST)),
+        };
+        if self.max_size.is_some_and(|max_size| length > max_size) {
+            return Ok(tus_response(state, StatusCode::PAYLOAD_TOO_LARGE));
+        }
+        let metadata = match headers.get(UPLOAD_METADATA) {
+            Some(value) => match value.to_str().ok().and_then(parse_metadata) {
+                Some(metadata) => metadata,
+                None => return Ok(tus_response(state, StatusCode::BAD_REQUEST)),
+            },
+            None => BTreeMap::new(),
+        };
+
+        let upload = Upload {
+            id: Uuid::new_v4().simple().to_string(),
+            length,
+            offset: 0,
+            metadata,
+        };
+        fs::File::create(self.path(&upload)).await?;
+        self.store.create(&upload).await?;
+        debug!("[{}] created upload {}", request_id(state), upload.id);
+
+        let location = format!(
+            "{}/{}",
+            Uri::borrow_from(state).path().trim_end_matches('/'),
+            upload.id
+        );
+        let mut response = tus_response(state, StatusCode::CREATED);
+        response
+            .headers_mut()
+            .insert(LOCATION, HeaderValue::try_from(location)?);
+        if upload.is_complete() {
+            self.complete(upload).await?;
+        }
+        Ok(response)
+    }
+
+    async fn patch(
+        &self,
+        state: &mut State,
+        upload: Upload,
+    ) -> Result<Response<Body>, HandlerError> {
+        let headers = HeaderMap::borrow_from(state);
+        if headers
+            .get(CONTENT_TYPE)
+            .is_none_or(|v| v != OFFSET_OCTET_STREAM)
+        {
+            return Ok(tus_response(state, StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        }
+        match header_u64(headers, UPLOAD_OFFSET) {
+            Some(offset) if offset == upload.offset => {}
+            Some(_) => return Ok(tus_response(state, StatusCode::CONFLICT)),
+            None => return Ok(tus_response(state, StatusCode::BAD_REQUEST)),
+        }
+        let _lock = match UploadLock::acquire(&self.locks, &upload.id) {
+            Some(lock) => lock,
+            None => return Ok(tus_response(state, StatusCode::LOCKED)),
+        };
+
+        let path = self.path(&upload);
+        let mut file = OpenOptions::new().write(true).open(&path).await?;
+        // bytes written after the offset was last stored are written again
+        file.set_len(upload.offset).await?;
+        file.seek(SeekFrom::Start(upload.offset)).await?;
+
+        let mut body = Body::try_take_from(state).unwrap_or_else(Body::empty);
+        let mut offset = upload.offset;
+        let mut status = None;
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    debug!(
+                        "[{}] upload {} interrupted at {}: {}",
+                        request_id(state),
+                        upload.id,
+                        offset,
+                        err
+                    );
+                    status = Some(StatusCode::BAD_REQUEST);
+                    break;
+                }
+            };
+            if offset + chunk.len() as u64 > upload.length {
+                // the client must not send more bytes than the length of the upload
+                status = Some(StatusCode::PAYLOAD_TOO_LARGE);
+                break;
+            }
+            file.write_all(&chunk).await?;
+            offset += chunk.len() as u64;
+        }
+        // keep the bytes received so far, even if the request failed
+        file.flush().await?;
+        file.sync_data().await?;
+        drop(file);
+        self.store.set_offset(&upload.id, offset).await?;
+
+        if let Some(status) = status {
+            return Ok(tus_response(state, status));
+        }
+        let upload = Upload { offset, ..upload };
+        if upload.is_complete() {
+            debug!("[{}] completed upload {}", request_id(state), upload.id);
+            self.complete(upload).await?;
+        }
+        let mut response = tus_response(state, StatusCode::NO_CONTENT);
+        response.headers_mut().insert(UPLOAD_OFFSET, offset.into());
+        Ok(response)
+    }
+
+    async fn complete(&self, upload: Upload) -> anyhow::Result<()> {
+        match &self.on_complete {
+            Some(on_complete) => {
+                let path = self.path(&upload);
+                on_complete(upload, path).await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for TusHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TusHandler")
+            .field("dir", &self.dir)
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+impl NewHandler for TusHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for TusHandler {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        async move {
+            match self.respond(&mut state).await {
+                Ok(response) => Ok((state, response)),
+                Err(err) => Err((state, err)),
+            }
+        }
+        .boxed()
+    }
+}
+
+// Marks an upload as being written by a `PATCH` request, until dropped.
+struct UploadLock {
+    locks: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl UploadLock {
+    fn acquire(locks: &Arc<Mutex<HashSet<String>>>, id: &str) -> Option<Self> {
+        if locks.lock().unwrap().insert(id.to_owned()) {
+            Some(UploadLock {
+                locks: locks.clone(),
+                id: id.to_owned(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        self.locks.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn tus_response(state: &State, status: StatusCode) -> Response<Body> {
+    let mut response = create_empty_response(state, status);
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(VERSION));
+    response
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+// The ID in the last segment of the path, if it can be the name of a file created for an upload.
+fn upload_id(path: &str) -> Option<&str> {
+    let id = path.trim_end_matches('/').rsplit('/').next()?;
+    if !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+// Parses the `Upload-Metadata` header: comma separated keys, each followed by a space and the
+// base64 encoded value, unless the value is empty.
+fn parse_metadata(value: &str) -> Option<BTreeMap<String, String>> {
+    let mut metadata = BTreeMap::new();
+    for pair in value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let mut parts = pair.splitn(2, ' ');
+        let key = parts.next()?;
+        let value = match parts.next() {
+            Some(value) => String::from_utf8(BASE64_STANDARD.decode(value.trim()).ok()?).ok()?,
+            None => String::new(),
+        };
+        if metadata.insert(key.to_owned(), value).is_some() {
+            return None;
+        }
+    }
+    Some(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use std::fs as std_fs;
+
+    fn test_server(tus: TusHandler) -> TestServer {
+        TestServer::new(build_simple_router(|route| {
+            route
+                .request(vec![Method::OPTIONS, Method::POST], "/files")
+                .to_new_handler(tus.clone());
+            route
+                .request(
+                    vec![Method::OPTIONS, Method::HEAD, Method::PATCH, Method::DELETE],
+                    "/files/:id",
+                )
+                .to_new_handler(tus);
+        }))
+        .unwrap()
+    }
+
+    fn tus_header() -> HeaderValue {
+        HeaderValue::from_static(VERSION)
+    }
+
+    fn patch(test_server: &TestServer, location: &str, offset: u64, body: &'static str) -> u16 {
+        test_server
+            .client()
+            .patch(
+                format!("http://localhost{}", location),
+                body,
+                OFFSET_OCTET_STREAM.parse().unwrap(),
+            )
+            .with_header(TUS_RESUMABLE, tus_header())
+            .with_header(UPLOAD_OFFSET, offset.into())
+            .perform()
+            .unwrap()
+            .status()
+            .as_u16()
+    }
+
+    #[test]
+    fn resumes_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        let tus = TusHandler::new(dir.path()).with_on_complete({
+            let completed = completed.clone();
+            move |upload: Upload, path: PathBuf| {
+                let content = std_fs::read_to_string(path).unwrap();
+                completed.lock().unwrap().push((upload, content));
+                async { Ok(()) }
+            }
+        });
+        let test_server = test_server(tus);
+
+        let response = test_server
+            .client()
+            .post("http://localhost/files", "", mime::TEXT_PLAIN)
+            .with_header(TUS_RESUMABLE, tus_header())
+            .with_header(UPLOAD_LENGTH, 11u64.into())
+            .with_header(
+                UPLOAD_METADATA,
+                "filename aGVsbG8udHh0,public".parse().unwrap(),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[TUS_RESUMABLE], VERSION);
+        let location = response.headers()[LOCATION].to_str().unwrap().to_owned();
+        assert!(location.starts_with("/files/"));
+
+        assert_eq!(patch(&test_server, &location, 0, "hello "), 204);
+        // the client lost track of the offset
+        assert_eq!(patch(&test_server, &location, 0, "hello "), 409);
+
+        let response = test_server
+            .client()
+            .head(format!("http://localhost{}", location))
+            .with_header(TUS_RESUMABLE, tus_header())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "6");
+        assert_eq!(response.headers()[UPLOAD_LENGTH], "11");
+        assert_eq!(
+            response.headers()[UPLOAD_METADATA],
+            "filename aGVsbG8udHh0,public"
+        );
+
+        assert!(completed.lock().unwrap().is_empty());
+        assert_eq!(patch(&test_server, &location, 6, "world"), 204);
+        let completed = completed.lock().unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].0.metadata("filename"), Some("hello.txt"));
+        assert_eq!(completed[0].0.metadata("public"), Some(""));
+        assert_eq!(completed[0].1, "hello world");
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let test_server = test_server(TusHandler::new(dir.path()).with_max_size(10));
+
+        let response = test_server
+            .client()
+            .options("http://localhost/files")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[TUS_EXTENSION], EXTENSIONS);
+        assert_eq!(response.headers()[TUS_MAX_SIZE], "10");
+
+        let create = |length: u64, version: &'static str| {
+            test_server
+                .client()
+                .post("http://localhost/files", "", mime::TEXT_PLAIN)
+                .with_header(TUS_RESUMABLE, HeaderValue::from_static(version))
+                .with_header(UPLOAD_LENGTH, length.into())
+                .perform()
+                .unwrap()
+        };
+        assert_eq!(create(5, "0.2.2").status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(create(11, VERSION).status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = create(5, VERSION);
+        let location = response.headers()[LOCATION].to_str().unwrap().to_owned();
+        assert_eq!(patch(&test_server, &location, 0, "too long"), 413);
+        assert_eq!(patch(&test_server, "/files/unknown", 0, "hello"), 404);
+
+        let response = test_server
+            .client()
+            .delete(format!("http://localhost{}", location))
+            .with_header(TUS_RESUMABLE, tus_header())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(std_fs::read_dir(dir.path()).unwrap().count(), 0);
+        assert_eq!(patch(&test_server, &location, 0, "hello"), 404);
+    }
+}
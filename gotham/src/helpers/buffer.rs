@@ -0,0 +1,113 @@
+//! A shared pool of byte buffers in a few size classes, used to read and aggregate bodies.
+//!
+//! Reading bodies into a freshly allocated `BytesMut` for every request causes a lot of
+//! allocator traffic under high concurrency. Buffers acquired from the pool are returned to it
+//! when dropped, and their allocation is reused once all `Bytes` split off from them have been
+//! dropped as well. Buffers larger than the largest size class are not pooled.
+
+use bytes::BytesMut;
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// The capacities of the pooled buffers. Buffers are acquired from the smallest class which
+/// fits the requested capacity.
+pub const SIZE_CLASSES: [usize; 5] = [4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20];
+
+// The maximum number of idle buffers kept per size class.
+const MAX_IDLE_PER_CLASS: usize = 64;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_CLASS: Mutex<Vec<BytesMut>> = Mutex::new(Vec::new());
+
+static POOL: [Mutex<Vec<BytesMut>>; SIZE_CLASSES.len()] = [EMPTY_CLASS; SIZE_CLASSES.len()];
+
+/// A buffer acquired from the pool, which is returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buf: BytesMut,
+    class: Option<usize>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(class) = self.class {
+            let mut idle = POOL[class].lock().unwrap();
+            if idle.len() < MAX_IDLE_PER_CLASS {
+                let mut buf = std::mem::take(&mut self.buf);
+                buf.clear();
+                idle.push(buf);
+            }
+        }
+    }
+}
+
+/// Acquires an empty buffer with at least the given capacity from the pool, allocating a new
+/// one if no idle buffer of the matching size class is available.
+pub fn acquire(capacity: usize) -> PooledBuffer {
+    let class = SIZE_CLASSES.iter().position(|&size| size >= capacity);
+    let buf = match class {
+        Some(class) => {
+            let idle = POOL[class].lock().unwrap().pop();
+            match idle {
+                Some(mut buf) => {
+                    // reclaims the allocation if all bytes split off have been dropped
+                    buf.reserve(SIZE_CLASSES[class]);
+                    buf
+                }
+                None => BytesMut::with_capacity(SIZE_CLASSES[class]),
+            }
+        }
+        None => BytesMut::with_capacity(capacity),
+    };
+    PooledBuffer { buf, class }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquires_from_size_classes() {
+        assert!(acquire(100).capacity() >= SIZE_CLASSES[0]);
+        assert!(acquire(5000).capacity() >= SIZE_CLASSES[1]);
+
+        let unpooled = acquire(2 << 20);
+        assert!(unpooled.capacity() >= 2 << 20);
+        assert_eq!(unpooled.class, None);
+    }
+
+    #[test]
+    fn returns_buffers_to_the_pool() {
+        // the largest class is not used by other tests, which may run concurrently
+        let class = SIZE_CLASSES.len() - 1;
+        let idle = POOL[class].lock().unwrap().len();
+
+        let mut buf = acquire(SIZE_CLASSES[class]);
+        buf.extend_from_slice(b"pooled");
+        let chunk = buf.split().freeze();
+        drop(buf);
+        assert_eq!(POOL[class].lock().unwrap().len(), idle + 1);
+
+        // the bytes split off are unaffected by reusing the buffer
+        let mut buf = acquire(SIZE_CLASSES[class]);
+        assert!(buf.is_empty());
+        buf.extend_from_slice(b"reused");
+        assert_eq!(chunk, "pooled");
+        assert_eq!(POOL[class].lock().unwrap().len(), idle);
+    }
+}
@@ -0,0 +1,113 @@
+//! The clock used for time-dependent behaviour, which tests can replace with a `MockClock`.
+//!
+//! Middleware and handlers comparing timestamps (e.g. for session expiry or cache TTLs) should
+//! read the current time via `now` and `system_time`, rather than `Instant::now` and
+//! `SystemTime::now`. These return the real time, unless a `MockClock` has been put into the
+//! `State`, as done by `TestServer::with_mock_clock`. Tests can then advance the clock
+//! deterministically instead of sleeping.
+//!
+//! Note that the `MockClock` does not affect timers, such as those of `tokio::time`.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::state::{FromState, State, StateData};
+
+/// A clock which only moves when advanced explicitly. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    inner: Arc<MockClockInner>,
+}
+
+#[derive(Debug)]
+struct MockClockInner {
+    instant: Instant,
+    system_time: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl StateData for MockClock {}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl MockClock {
+    /// Creates a new `MockClock`, starting at the current time.
+    pub fn new() -> Self {
+        MockClock {
+            inner: Arc::new(MockClockInner {
+                instant: Instant::now(),
+                system_time: SystemTime::now(),
+                elapsed: Mutex::new(Duration::ZERO),
+            }),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.inner.elapsed.lock().expect("mock clock poisoned") += duration;
+    }
+
+    /// Returns how far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.inner.elapsed.lock().expect("mock clock poisoned")
+    }
+
+    /// Returns the current `Instant` of this clock.
+    pub fn now(&self) -> Instant {
+        self.inner.instant + self.elapsed()
+    }
+
+    /// Returns the current `SystemTime` of this clock.
+    pub fn system_time(&self) -> SystemTime {
+        self.inner.system_time + self.elapsed()
+    }
+}
+
+/// Returns the current `Instant`, as seen by the request in `state`.
+pub fn now(state: &State) -> Instant {
+    match MockClock::try_borrow_from(state) {
+        Some(clock) => clock.now(),
+        None => Instant::now(),
+    }
+}
+
+/// Returns the current `SystemTime`, as seen by the request in `state`.
+pub fn system_time(state: &State) -> SystemTime {
+    match MockClock::try_borrow_from(state) {
+        Some(clock) => clock.system_time(),
+        None => SystemTime::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_real_time_by_default() {
+        let state = State::new();
+        let before = Instant::now();
+        let now = now(&state);
+        assert!(now >= before && now <= Instant::now());
+    }
+
+    #[test]
+    fn uses_mock_clock_in_state() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let start_system = clock.system_time();
+
+        let mut state = State::new();
+        state.put(clock.clone());
+        assert_eq!(now(&state), start);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.elapsed(), Duration::from_secs(90));
+        assert_eq!(now(&state), start + Duration::from_secs(90));
+        assert_eq!(system_time(&state), start_system + Duration::from_secs(90));
+    }
+}
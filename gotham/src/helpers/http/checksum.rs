@@ -0,0 +1,350 @@
+//! Verifies request bodies against the digests sent by the client, for upload integrity.
+//!
+//! Clients uploading artifacts can send a digest of the body in a `Content-Digest` (RFC 9530),
+//! `Digest` (RFC 3230) or `Content-MD5` (RFC 1864) header. `ExpectedChecksum::from_headers`
+//! picks the strongest supported digest from these headers, and its `ChecksumVerifier` computes
+//! the digest of the body chunk by chunk while it is streamed, so the body never has to be held
+//! in memory just to be verified.
+//!
+//! SHA-256 and SHA-512 are always supported, MD5 only with the `content-md5` feature. Digests
+//! using other algorithms are ignored.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use gotham::helpers::http::checksum::ExpectedChecksum;
+//! use gotham::helpers::http::header::DIGEST;
+//! use hyper::HeaderMap;
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert(
+//!     DIGEST,
+//!     "SHA-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="
+//!         .parse()
+//!         .unwrap(),
+//! );
+//!
+//! let expected = ExpectedChecksum::from_headers(&headers).unwrap().unwrap();
+//! let mut verifier = expected.verifier();
+//! verifier.update(b"hel");
+//! verifier.update(b"lo");
+//! assert!(verifier.verify().is_ok());
+//! ```
+
+use std::fmt;
+
+use base64::prelude::*;
+use hyper::{HeaderMap, StatusCode};
+use sha2::{Digest, Sha256, Sha512};
+use thiserror::Error;
+
+#[cfg(feature = "content-md5")]
+use crate::helpers::http::header::CONTENT_MD5;
+use crate::helpers::http::header::{CONTENT_DIGEST, DIGEST};
+
+/// The digest algorithms a request body can be verified with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ChecksumAlgorithm {
+    /// MD5, as sent in a `Content-MD5` header. Only supported with the `content-md5` feature.
+    #[cfg(feature = "content-md5")]
+    Md5,
+    /// SHA-256.
+    Sha256,
+    /// SHA-512.
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    // Parses the algorithm names of `Digest` and `Content-Digest` headers.
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "content-md5")]
+            "md5" => Some(ChecksumAlgorithm::Md5),
+            "sha-256" => Some(ChecksumAlgorithm::Sha256),
+            "sha-512" => Some(ChecksumAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn digest_len(self) -> usize {
+        match self {
+            #[cfg(feature = "content-md5")]
+            ChecksumAlgorithm::Md5 => 16,
+            ChecksumAlgorithm::Sha256 => 32,
+            ChecksumAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            #[cfg(feature = "content-md5")]
+            ChecksumAlgorithm::Md5 => "MD5",
+            ChecksumAlgorithm::Sha256 => "SHA-256",
+            ChecksumAlgorithm::Sha512 => "SHA-512",
+        })
+    }
+}
+
+/// The errors that can occur while verifying a request body against its digest.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ChecksumError {
+    /// A digest header is malformed, or its digest has the wrong length for its algorithm.
+    #[error("invalid {0} header")]
+    InvalidHeader(&'static str),
+    /// The body doesn't match the digest sent by the client.
+    #[error("body does not match its {0} digest")]
+    Mismatch(ChecksumAlgorithm),
+}
+
+impl ChecksumError {
+    /// Returns the status code appropriate for rejecting the request, `400 Bad Request`.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// A digest of the request body, as sent by the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedChecksum {
+    algorithm: ChecksumAlgorithm,
+    digest: Vec<u8>,
+}
+
+impl ExpectedChecksum {
+    /// Returns the strongest supported digest of the `Content-Digest`, `Digest` and
+    /// `Content-MD5` headers, or `None` if the request has no digest of a supported algorithm.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>, ChecksumError> {
+        let mut strongest: Option<ExpectedChecksum> = None;
+        let mut consider = |checksum: ExpectedChecksum| {
+            if strongest
+                .as_ref()
+                .is_none_or(|strongest| checksum.algorithm > strongest.algorithm)
+            {
+                strongest = Some(checksum);
+            }
+        };
+
+        for (header, delimited) in [(CONTENT_DIGEST, true), (DIGEST, false)] {
+            for value in headers.get_all(header) {
+                let value = value
+                    .to_str()
+                    .map_err(|_| ChecksumError::InvalidHeader(header))?;
+                for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                    let (name, digest) = entry
+                        .split_once('=')
+                        .ok_or(ChecksumError::InvalidHeader(header))?;
+                    let algorithm = match ChecksumAlgorithm::from_name(name.trim()) {
+                        Some(algorithm) => algorithm,
+                        None => continue,
+                    };
+                    let digest = digest.trim();
+                    let digest = if delimited {
+                        digest
+                            .strip_prefix(':')
+                            .and_then(|digest| digest.strip_suffix(':'))
+                            .ok_or(ChecksumError::InvalidHeader(header))?
+                    } else {
+                        digest
+                    };
+                    consider(ExpectedChecksum::decode(header, algorithm, digest)?);
+                }
+            }
+        }
+
+        #[cfg(feature = "content-md5")]
+        {
+            if let Some(value) = headers.get(CONTENT_MD5) {
+                let value = value
+                    .to_str()
+                    .map_err(|_| ChecksumError::InvalidHeader(CONTENT_MD5))?;
+                consider(ExpectedChecksum::decode(
+                    CONTENT_MD5,
+                    ChecksumAlgorithm::Md5,
+                    value.trim(),
+                )?);
+            }
+        }
+        Ok(strongest)
+    }
+
+    fn decode(
+        header: &'static str,
+        algorithm: ChecksumAlgorithm,
+        digest: &str,
+    ) -> Result<Self, ChecksumError> {
+        match BASE64_STANDARD.decode(digest) {
+            Ok(digest) if digest.len() == algorithm.digest_len() => {
+                Ok(ExpectedChecksum { algorithm, digest })
+            }
+            _ => Err(ChecksumError::InvalidHeader(header)),
+        }
+    }
+
+    /// Returns the algorithm of the digest.
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the digest sent by the client.
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Creates a `ChecksumVerifier` computing the digest of a body to compare with this one.
+    pub fn verifier(&self) -> ChecksumVerifier {
+        let hasher = match self.algorithm {
+            #[cfg(feature = "content-md5")]
+            ChecksumAlgorithm::Md5 => Hasher::Md5(md5::Md5::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        };
+        ChecksumVerifier {
+            expected: self.clone(),
+            hasher,
+        }
+    }
+}
+
+/// Computes the digest of a body incrementally, and compares it with an `ExpectedChecksum`.
+#[derive(Clone)]
+pub struct ChecksumVerifier {
+    expected: ExpectedChecksum,
+    hasher: Hasher,
+}
+
+#[derive(Clone)]
+enum Hasher {
+    #[cfg(feature = "content-md5")]
+    Md5(md5::Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl fmt::Debug for ChecksumVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChecksumVerifier")
+            .field("expected", &self.expected)
+            .finish()
+    }
+}
+
+impl ChecksumVerifier {
+    /// Adds the next chunk of the body to the digest.
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.hasher {
+            #[cfg(feature = "content-md5")]
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finishes the digest of the body, and fails with `ChecksumError::Mismatch` if it doesn't
+    /// match the expected digest.
+    pub fn verify(self) -> Result<(), ChecksumError> {
+        let digest = match self.hasher {
+            #[cfg(feature = "content-md5")]
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        };
+        if digest == self.expected.digest {
+            Ok(())
+        } else {
+            Err(ChecksumError::Mismatch(self.expected.algorithm))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA256: &str = "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=";
+    const HELLO_SHA512: &str =
+        "m3HSJL1i83hdltRq0+o9czGb+8KJDKra4t/3JRlnPKcjI8PZm6XBHXx6zG4UuMXaDEZjR1wuXDre9G9zvN7AQw==";
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn verify(expected: &ExpectedChecksum, body: &[u8]) -> Result<(), ChecksumError> {
+        let mut verifier = expected.verifier();
+        for chunk in body.chunks(2) {
+            verifier.update(chunk);
+        }
+        verifier.verify()
+    }
+
+    #[test]
+    fn picks_strongest_digest() {
+        let digest = format!("SHA-256={}, UNIXsum=30637", HELLO_SHA256);
+        let expected = ExpectedChecksum::from_headers(&headers(&[(DIGEST, &digest)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected.algorithm(), ChecksumAlgorithm::Sha256);
+        assert!(verify(&expected, b"hello").is_ok());
+
+        let content_digest = format!("sha-512=:{}:", HELLO_SHA512);
+        let expected = ExpectedChecksum::from_headers(&headers(&[
+            (DIGEST, &digest),
+            (CONTENT_DIGEST, &content_digest),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(expected.algorithm(), ChecksumAlgorithm::Sha512);
+        assert!(verify(&expected, b"hello").is_ok());
+
+        let unsupported = headers(&[(DIGEST, "UNIXsum=30637")]);
+        assert!(ExpectedChecksum::from_headers(&unsupported)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_mismatches_and_malformed_headers() {
+        let digest = format!("SHA-256={}", HELLO_SHA256);
+        let expected = ExpectedChecksum::from_headers(&headers(&[(DIGEST, &digest)]))
+            .unwrap()
+            .unwrap();
+        let err = verify(&expected, b"hullo").unwrap_err();
+        assert!(matches!(
+            err,
+            ChecksumError::Mismatch(ChecksumAlgorithm::Sha256)
+        ));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        for (header, value) in [
+            (DIGEST, "SHA-256=not base64"),
+            (DIGEST, "SHA-256=aGVsbG8="),
+            (
+                CONTENT_DIGEST,
+                "sha-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=",
+            ),
+        ] {
+            assert!(matches!(
+                ExpectedChecksum::from_headers(&headers(&[(header, value)])),
+                Err(ChecksumError::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "content-md5")]
+    #[test]
+    fn verifies_content_md5() {
+        let expected =
+            ExpectedChecksum::from_headers(&headers(&[(CONTENT_MD5, "XUFAKrxLKna5cZ2REBfFkg==")]))
+                .unwrap()
+                .unwrap();
+        assert_eq!(expected.algorithm(), ChecksumAlgorithm::Md5);
+        assert!(verify(&expected, b"hello").is_ok());
+    }
+}
@@ -0,0 +1,361 @@
+//! Helpers for conditional and range requests, as answered by the static file handlers.
+//!
+//! Handlers serving dynamic content, like blobs stored in a database, can use these helpers to
+//! answer `If-Match`, `If-Unmodified-Since`, `If-None-Match`, `If-Modified-Since` and `Range`
+//! headers with the same semantics as `to_file` and `to_dir` routes. Entity tags are compared
+//! following RFC 7232: headers may list several tags or `*`, `If-None-Match` uses the weak
+//! comparison, which ignores whether tags are weak, and `If-Match` uses the strong comparison,
+//! which never matches weak tags.
+//!
+//! ```rust
+//! # use gotham::helpers::http::conditional::{not_modified, resolve_range, slice_range};
+//! # use gotham::hyper::header::{HeaderMap, ETAG, IF_NONE_MATCH, RANGE};
+//! # use gotham::hyper::{Body, Response, StatusCode};
+//! # use bytes::Bytes;
+//! #
+//! fn respond(blob: Bytes, etag: &str, headers: &HeaderMap) -> Response<Body> {
+//!     let response = Response::builder().header(ETAG, etag);
+//!     if not_modified(Some(etag), None, headers) {
+//!         return response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap();
+//!     }
+//!     match resolve_range(blob.len() as u64, headers) {
+//!         Ok((len, None)) => response.body(Body::from(slice_range(&blob, len, None))),
+//!         Ok((len, Some(start))) => response
+//!             .status(StatusCode::PARTIAL_CONTENT)
+//!             .body(Body::from(slice_range(&blob, len, Some(start)))),
+//!         Err(e) => response.status(StatusCode::RANGE_NOT_SATISFIABLE).body(Body::from(e)),
+//!     }
+//!     .unwrap()
+//! }
+//! #
+//! # fn main() {
+//! #     let mut headers = HeaderMap::new();
+//! #     headers.insert(IF_NONE_MATCH, "\"v1\"".parse().unwrap());
+//! #     let response = respond(Bytes::from_static(b"blob"), "\"v1\"", &headers);
+//! #     assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+//! #
+//! #     let mut headers = HeaderMap::new();
+//! #     headers.insert(RANGE, "bytes=1-2".parse().unwrap());
+//! #     let response = respond(Bytes::from_static(b"blob"), "\"v1\"", &headers);
+//! #     assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+//! # }
+//! ```
+
+use bytes::Bytes;
+use httpdate::parse_http_date;
+use hyper::header::{
+    HeaderMap, HeaderName, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE, RANGE,
+};
+use regex::Regex;
+
+use std::cmp;
+use std::fs::Metadata;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Checks whether the client already holds the current representation of a resource with the
+/// given entity tag and modification time, so it can be answered with "304 Not Modified".
+///
+/// The `If-None-Match` header takes precedence over `If-Modified-Since`, which is compared at
+/// the resolution of whole seconds of HTTP dates. Tags listed in `If-None-Match` match with the
+/// weak comparison, and `*` matches any existing resource.
+pub fn not_modified(etag: Option<&str>, modified: Option<SystemTime>, headers: &HeaderMap) -> bool {
+    // If-None-Match header takes precedence over If-Modified-Since
+    match headers.get(IF_NONE_MATCH) {
+        Some(_) => entity_tags(headers, &IF_NONE_MATCH)
+            .any(|tag| tag == "*" || etag.is_some_and(|etag| weak_match(tag, etag))),
+        _ => modified_since(headers, &IF_MODIFIED_SINCE, modified) == Some(false),
+    }
+}
+
+/// Checks whether a request must be answered with "412 Precondition Failed", since the client
+/// asked for the resource to be unchanged, e.g. before overwriting it with a `PUT` request.
+///
+/// The `If-Match` header takes precedence over `If-Unmodified-Since`. Tags listed in `If-Match`
+/// match with the strong comparison, so a resource with only a weak entity tag fails unless `*`
+/// is listed.
+pub fn precondition_failed(
+    etag: Option<&str>,
+    modified: Option<SystemTime>,
+    headers: &HeaderMap,
+) -> bool {
+    match headers.get(IF_MATCH) {
+        Some(_) => !entity_tags(headers, &IF_MATCH)
+            .any(|tag| tag == "*" || etag.is_some_and(|etag| strong_match(tag, etag))),
+        _ => modified_since(headers, &IF_UNMODIFIED_SINCE, modified) == Some(true),
+    }
+}
+
+/// Compares two entity tags with the weak comparison, under which they match if their opaque
+/// tags are equal, whether or not either of them is weak.
+pub fn weak_match(a: &str, b: &str) -> bool {
+    match (opaque_tag(a), opaque_tag(b)) {
+        (Some((_, a)), Some((_, b))) => a == b,
+        _ => false,
+    }
+}
+
+/// Compares two entity tags with the strong comparison, under which they match if neither of
+/// them is weak and their opaque tags are equal.
+pub fn strong_match(a: &str, b: &str) -> bool {
+    match (opaque_tag(a), opaque_tag(b)) {
+        (Some((false, a)), Some((false, b))) => a == b,
+        _ => false,
+    }
+}
+
+// Splits an entity tag into whether it's weak and its quoted opaque tag, or returns `None` if it
+// isn't a valid entity tag.
+fn opaque_tag(tag: &str) -> Option<(bool, &str)> {
+    let tag = tag.trim();
+    let (weak, opaque) = match tag.strip_prefix("W/") {
+        Some(opaque) => (true, opaque),
+        None => (false, tag),
+    };
+    let valid = opaque.len() >= 2
+        && opaque.starts_with('"')
+        && opaque.ends_with('"')
+        && !opaque[1..opaque.len() - 1].contains('"');
+    if valid {
+        Some((weak, opaque))
+    } else {
+        None
+    }
+}
+
+// Returns the entity tags listed in all headers `name`. Commas within quoted tags, which are
+// allowed by the grammar of entity tags, don't separate tags.
+fn entity_tags<'a>(headers: &'a HeaderMap, name: &HeaderName) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| {
+            let mut tags = Vec::new();
+            let mut quoted = false;
+            let mut start = 0;
+            for (i, c) in value.char_indices() {
+                match c {
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => {
+                        tags.push(&value[start..i]);
+                        start = i + 1;
+                    }
+                    _ => {}
+                }
+            }
+            tags.push(&value[start..]);
+            tags
+        })
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+}
+
+// Checks whether the resource was modified after the date in the header `name`, comparing whole
+// seconds like HTTP dates, or returns `None` if the header or the modification time is missing.
+fn modified_since(
+    headers: &HeaderMap,
+    name: &HeaderName,
+    modified: Option<SystemTime>,
+) -> Option<bool> {
+    let since = headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_http_date(v).ok())?;
+    // HTTP dates have a resolution of whole seconds, like `Last-Modified`
+    let modified = modified?.duration_since(UNIX_EPOCH).ok()?;
+    let since = since.duration_since(UNIX_EPOCH).ok()?;
+    Some(modified.as_secs() > since.as_secs())
+}
+
+/// Returns the weak entity tag of a file, derived from its size and modification time, or `None`
+/// if the modification time is unavailable.
+pub fn entity_tag(metadata: &Metadata) -> Option<String> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| weak_entity_tag(metadata.len(), modified))
+}
+
+/// Returns a weak entity tag derived from the size and modification time of content, in the
+/// format used for files, or `None` if the modification time precedes the UNIX epoch.
+pub fn weak_entity_tag(len: u64, modified: SystemTime) -> Option<String> {
+    modified.duration_since(UNIX_EPOCH).ok().map(|duration| {
+        format!(
+            "W/\"{0:x}-{1:x}.{2:x}\"",
+            len,
+            duration.as_secs(),
+            duration.subsec_nanos()
+        )
+    })
+}
+
+// compiled once, as every ranged request for a file is resolved with it
+static RANGE_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Checks for existence of "Range" header and whether it is in supported format
+/// This implementations only supports single part ranges.
+/// Returns a result of length and optional starting position, or an error if range value is invalid
+/// or selects no byte of the file
+/// If range header does not exist or is unsupported the length is the whole file length and start position is none.
+pub fn resolve_range(len: u64, headers: &HeaderMap) -> Result<(u64, Option<u64>), &'static str> {
+    let Some(range_val) = headers.get(RANGE) else {
+        return Ok((len, None));
+    };
+    range_val
+        .to_str()
+        .ok()
+        .and_then(|range_val| {
+            RANGE_REGEX
+                .get_or_init(|| Regex::new(r"^bytes=(\d*)-(\d*)$").unwrap())
+                .captures(range_val)
+                .map(|captures| {
+                    let begin = captures
+                        .get(1)
+                        .and_then(|digits| digits.as_str().parse::<u64>().ok());
+                    let end = captures
+                        .get(2)
+                        .and_then(|digits| digits.as_str().parse::<u64>().ok());
+                    match (begin, end) {
+                        // a range starting at or past the end of the file selects nothing
+                        (Some(begin), _) if begin >= len => Err("invalid range"),
+                        (Some(begin), Some(end)) => {
+                            if end < begin {
+                                Err("invalid range")
+                            } else {
+                                let end = cmp::min(end, len - 1);
+                                Ok((1 + end - begin, Some(begin)))
+                            }
+                        }
+                        (Some(begin), None) => Ok((len - begin, Some(begin))),
+                        (None, Some(suffix)) => {
+                            // the suffix covers at most the whole file, and `-0` nothing
+                            let suffix = cmp::min(suffix, len);
+                            if suffix == 0 {
+                                Err("invalid range")
+                            } else {
+                                Ok((suffix, Some(len - suffix)))
+                            }
+                        }
+                        (None, None) => Err("invalid range"),
+                    }
+                })
+        })
+        .unwrap_or(Ok((len, None)))
+}
+
+/// Returns the part of `contents` selected by `resolve_range`, clamped to the contents.
+pub fn slice_range(contents: &Bytes, len: u64, range_start: Option<u64>) -> Bytes {
+    let start = cmp::min(range_start.unwrap_or(0), contents.len() as u64) as usize;
+    let end = cmp::min(start as u64 + len, contents.len() as u64) as usize;
+    contents.slice(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpdate::fmt_http_date;
+    use std::time::Duration;
+
+    #[test]
+    fn checks_validators() {
+        let modified = UNIX_EPOCH + Duration::from_millis(1_600_000_000_500);
+        let mut headers = HeaderMap::new();
+        assert!(!not_modified(Some("\"a\""), Some(modified), &headers));
+
+        headers.insert(IF_MODIFIED_SINCE, fmt_http_date(modified).parse().unwrap());
+        assert!(not_modified(None, Some(modified), &headers));
+        assert!(!not_modified(
+            None,
+            Some(modified + Duration::from_secs(1)),
+            &headers
+        ));
+
+        // If-None-Match takes precedence
+        headers.insert(IF_NONE_MATCH, "\"b\"".parse().unwrap());
+        assert!(!not_modified(Some("\"a\""), Some(modified), &headers));
+        headers.append(IF_NONE_MATCH, "\"a\"".parse().unwrap());
+        assert!(not_modified(Some("\"a\""), Some(modified), &headers));
+    }
+
+    #[test]
+    fn compares_entity_tags() {
+        assert!(weak_match("W/\"a\"", "\"a\""));
+        assert!(weak_match("\"a\"", "\"a\""));
+        assert!(!weak_match("\"a\"", "\"b\""));
+        assert!(!weak_match("a", "a"));
+        assert!(strong_match("\"a\"", "\"a\""));
+        assert!(!strong_match("W/\"a\"", "\"a\""));
+        assert!(!strong_match("W/\"a\"", "W/\"a\""));
+
+        // lists of tags, with commas inside tags and weak variants
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, "\"x\", W/\"a,b\"".parse().unwrap());
+        assert!(not_modified(Some("\"a,b\""), None, &headers));
+        assert!(not_modified(Some("W/\"x\""), None, &headers));
+        assert!(!not_modified(Some("\"a\""), None, &headers));
+        headers.insert(IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(not_modified(Some("\"a\""), None, &headers));
+    }
+
+    #[test]
+    fn checks_preconditions() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut headers = HeaderMap::new();
+        assert!(!precondition_failed(
+            Some("\"a\""),
+            Some(modified),
+            &headers
+        ));
+
+        headers.insert(
+            IF_UNMODIFIED_SINCE,
+            fmt_http_date(modified).parse().unwrap(),
+        );
+        assert!(!precondition_failed(None, Some(modified), &headers));
+        assert!(precondition_failed(
+            None,
+            Some(modified + Duration::from_secs(1)),
+            &headers
+        ));
+
+        // If-Match takes precedence and uses the strong comparison
+        headers.insert(IF_MATCH, "\"b\", \"a\"".parse().unwrap());
+        assert!(!precondition_failed(Some("\"a\""), None, &headers));
+        assert!(precondition_failed(Some("W/\"a\""), None, &headers));
+        assert!(precondition_failed(None, Some(modified), &headers));
+        headers.insert(IF_MATCH, "*".parse().unwrap());
+        assert!(!precondition_failed(Some("W/\"a\""), None, &headers));
+    }
+
+    #[test]
+    fn resolves_ranges() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RANGE, value.parse().unwrap());
+            resolve_range(10, &headers)
+        };
+        assert_eq!(resolve_range(10, &HeaderMap::new()), Ok((10, None)));
+        assert_eq!(range("bytes=2-5"), Ok((4, Some(2))));
+        assert_eq!(range("bytes=4-"), Ok((6, Some(4))));
+        assert_eq!(range("bytes=-3"), Ok((3, Some(7))));
+        assert_eq!(range("bytes=5-2"), Err("invalid range"));
+        assert_eq!(range("bytes=8-20"), Ok((2, Some(8))));
+        assert_eq!(range("bytes=-20"), Ok((10, Some(0))));
+        assert_eq!(range("bytes=-0"), Err("invalid range"));
+        assert_eq!(range("bytes=10-"), Err("invalid range"));
+        assert_eq!(range("bytes=12-"), Err("invalid range"));
+        assert_eq!(range("bytes=10-12"), Err("invalid range"));
+        assert_eq!(range("lines=1-2"), Ok((10, None)));
+
+        let contents = Bytes::from_static(b"0123456789");
+        assert_eq!(slice_range(&contents, 4, Some(2)), "2345");
+        assert_eq!(slice_range(&contents, 4, Some(20)), "");
+    }
+
+    #[test]
+    fn formats_weak_entity_tags() {
+        let modified = UNIX_EPOCH + Duration::new(0x10, 0x20);
+        assert_eq!(weak_entity_tag(0xff, modified).unwrap(), "W/\"ff-10.20\"");
+    }
+}
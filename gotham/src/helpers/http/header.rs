@@ -6,3 +6,16 @@ pub const X_REQUEST_ID: &str = "x-request-id";
 
 /// Marks the execution time of a Gotham request.
 pub const X_RUNTIME_DURATION: &str = "x-runtime-duration";
+
+/// Carries digests of the request or response body (RFC 3230), e.g. `SHA-256=<base64>`.
+pub const DIGEST: &str = "digest";
+
+/// Carries digests of the content of a request or response (RFC 9530), e.g. `sha-256=:<base64>:`.
+pub const CONTENT_DIGEST: &str = "content-digest";
+
+/// Carries the base64 encoded MD5 digest of the body (RFC 1864).
+pub const CONTENT_MD5: &str = "content-md5";
+
+/// Carries the addresses of the client and proxies a request was forwarded by, e.g.
+/// `203.0.113.7, 10.0.0.2`.
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
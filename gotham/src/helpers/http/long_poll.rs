@@ -0,0 +1,390 @@
+//! Helpers for long-polling endpoints, for clients which cannot use Server-Sent Events or
+//! WebSockets.
+//!
+//! A long-poll request is parked until either a notification arrives, in which case the payload
+//! is sent, or a timeout expires, in which case an empty `304 Not Modified` (or `204 No Content`)
+//! response tells the client to poll again.
+//!
+//! A `Notifier` holds the latest value of a shared resource. Every value is tagged with a version,
+//! which is sent as the `ETag` of the response. Clients pass it back in `If-None-Match` to be
+//! parked until a newer value is available, so no notification is lost between two polls.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::pin::Pin;
+//! # use std::time::Duration;
+//! # use gotham::handler::HandlerFuture;
+//! # use gotham::middleware::state::StateMiddleware;
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! # use gotham::state::{FromState, State};
+//! use gotham::helpers::http::long_poll::{LongPoll, Notifier};
+//!
+//! fn status(state: State) -> Pin<Box<HandlerFuture>> {
+//!     let notifier = Notifier::<String>::borrow_from(&state).clone();
+//!     LongPoll::new(Duration::from_secs(30)).respond(state, &notifier)
+//! }
+//!
+//! # fn main() {
+//! let notifier = Notifier::new(String::from("idle"));
+//! let (chain, pipelines) = single_pipeline(
+//!     new_pipeline()
+//!         .add(StateMiddleware::new(notifier.clone()))
+//!         .build(),
+//! );
+//! let router = build_router(chain, pipelines, |route| {
+//!     route.get("/status").to(status);
+//! });
+//! # let _ = router;
+//!
+//! // wakes up all parked requests
+//! notifier.notify(String::from("busy"));
+//! # }
+//! ```
+
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::FutureExt;
+use hyper::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH};
+use hyper::StatusCode;
+use tokio::sync::{broadcast, watch};
+use tokio::time::{timeout_at, Instant};
+
+use crate::handler::{HandlerFuture, IntoResponse};
+use crate::helpers::http::response::create_empty_response;
+use crate::state::{FromState, State, StateData};
+
+/// Holds the latest value of a shared resource, waking up long-poll requests whenever it
+/// changes. Clones share the same value.
+pub struct Notifier<T> {
+    // Depending on the features of tokio, the channel is not `RefUnwindSafe`, but its value is
+    // only ever replaced as a whole.
+    inner: AssertUnwindSafe<Arc<NotifierInner<T>>>,
+}
+
+struct NotifierInner<T> {
+    sender: watch::Sender<(u64, T)>,
+    // keeps the channel open, so values are stored even while no request is waiting
+    receiver: watch::Receiver<(u64, T)>,
+    version: Mutex<u64>,
+}
+
+impl<T> Clone for Notifier<T> {
+    fn clone(&self) -> Self {
+        Notifier {
+            inner: AssertUnwindSafe(self.inner.0.clone()),
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static> StateData for Notifier<T> {}
+
+impl<T> Notifier<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    /// Creates a new `Notifier` holding `initial` as version 0.
+    pub fn new(initial: T) -> Self {
+        let (sender, receiver) = watch::channel((0, initial));
+        Notifier {
+            inner: AssertUnwindSafe(Arc::new(NotifierInner {
+                sender,
+                receiver,
+                version: Mutex::new(0),
+            })),
+        }
+    }
+
+    /// Replaces the value with `value`, responding to all parked requests.
+    pub fn notify(&self, value: T) {
+        let mut version = self
+            .inner
+            .version
+            .lock()
+            .expect("notifier version poisoned");
+        *version += 1;
+        // cannot fail, as `inner` holds a receiver
+        let _ = self.inner.sender.send((*version, value));
+    }
+
+    /// Returns the version of the current value, which starts at 0 and is incremented by each
+    /// call to `notify`.
+    pub fn version(&self) -> u64 {
+        self.inner.receiver.borrow().0
+    }
+
+    /// Returns a clone of the current value.
+    pub fn current(&self) -> T {
+        self.inner.receiver.borrow().1.clone()
+    }
+
+    /// Waits up to `timeout` for a value with a version other than `since`, returning the version
+    /// and value, or `None` if the timeout expires. Resolves immediately if the current version
+    /// differs already, or if `since` is `None`.
+    pub async fn wait(&self, since: Option<u64>, timeout: Duration) -> Option<(u64, T)> {
+        let deadline = Instant::now() + timeout;
+        let mut receiver = self.inner.receiver.clone();
+        loop {
+            {
+                let current = receiver.borrow();
+                if Some(current.0) != since {
+                    return Some((*current).clone());
+                }
+            }
+            match timeout_at(deadline, receiver.changed()).await {
+                Ok(Ok(())) => continue,
+                Ok(Err(_)) | Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Responds to long-poll requests, parking them until a notification arrives or the timeout
+/// expires.
+#[derive(Clone, Copy, Debug)]
+pub struct LongPoll {
+    timeout: Duration,
+    timeout_status: StatusCode,
+}
+
+impl LongPoll {
+    /// Creates a new `LongPoll`, parking requests for at most `timeout`. Timed out requests are
+    /// answered with `304 Not Modified`.
+    pub fn new(timeout: Duration) -> Self {
+        LongPoll {
+            timeout,
+            timeout_status: StatusCode::NOT_MODIFIED,
+        }
+    }
+
+    /// Answers timed out requests with `204 No Content` instead of `304 Not Modified`.
+    pub fn with_no_content(self) -> Self {
+        LongPoll {
+            timeout_status: StatusCode::NO_CONTENT,
+            ..self
+        }
+    }
+
+    /// Responds with the value of `notifier`, as soon as its version differs from the one the
+    /// client passed in `If-None-Match`. The version is sent as the `ETag` of the response.
+    pub fn respond<T>(self, state: State, notifier: &Notifier<T>) -> Pin<Box<HandlerFuture>>
+    where
+        T: IntoResponse + Clone + Send + Sync + 'static,
+    {
+        let since = last_seen_version(&state);
+        let notifier = notifier.clone();
+        async move {
+            let response = match notifier.wait(since, self.timeout).await {
+                Some((version, value)) => {
+                    let mut response = value.into_response(&state);
+                    response.headers_mut().insert(ETAG, etag(version));
+                    response
+                }
+                None => {
+                    let mut response = create_empty_response(&state, self.timeout_status);
+                    if let Some(version) = since {
+                        response.headers_mut().insert(ETAG, etag(version));
+                    }
+                    response
+                }
+            };
+            Ok((state, response))
+        }
+        .boxed()
+    }
+
+    /// Responds with the next message received from `receiver`. Messages the client missed
+    /// because it was not connected are not replayed; use a `Notifier` for that.
+    pub fn respond_broadcast<T>(
+        self,
+        state: State,
+        mut receiver: broadcast::Receiver<T>,
+    ) -> Pin<Box<HandlerFuture>>
+    where
+        T: IntoResponse + Clone + Send + 'static,
+    {
+        async move {
+            let deadline = Instant::now() + self.timeout;
+            let message = loop {
+                match timeout_at(deadline, receiver.recv()).await {
+                    Ok(Ok(message)) => break Some(message),
+                    Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                    Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => break None,
+                }
+            };
+
+            let response = match message {
+                Some(message) => message.into_response(&state),
+                None => create_empty_response(&state, self.timeout_status),
+            };
+            Ok((state, response))
+        }
+        .boxed()
+    }
+}
+
+fn etag(version: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", version)).expect("valid ETag")
+}
+
+// Parses the version from an `If-None-Match: "<version>"` request header.
+fn last_seen_version(state: &State) -> Option<u64> {
+    HeaderMap::borrow_from(state)
+        .get(IF_NONE_MATCH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use crate::test::TestServer;
+    use std::thread;
+
+    fn router(notifier: Notifier<String>, long_poll: LongPoll) -> Router {
+        build_simple_router(move |route| {
+            route.get("/").to_new_handler(move || {
+                let notifier = notifier.clone();
+                Ok(move |state: State| long_poll.respond(state, &notifier))
+            });
+        })
+    }
+
+    fn short_poll() -> LongPoll {
+        LongPoll::new(Duration::from_millis(100))
+    }
+
+    #[tokio::test]
+    async fn waits_for_newer_versions() {
+        let notifier = Notifier::new(1);
+        assert_eq!(notifier.wait(None, Duration::ZERO).await, Some((0, 1)));
+        assert_eq!(
+            notifier.wait(Some(0), Duration::from_millis(10)).await,
+            None
+        );
+
+        let waiting = notifier.wait(Some(0), Duration::from_secs(10));
+        let notify = async {
+            tokio::task::yield_now().await;
+            notifier.notify(2);
+        };
+        let (result, _) = tokio::join!(waiting, notify);
+        assert_eq!(result, Some((1, 2)));
+        assert_eq!(notifier.version(), 1);
+        assert_eq!(notifier.current(), 2);
+    }
+
+    #[test]
+    fn responds_immediately_without_version() {
+        let notifier = Notifier::new(String::from("idle"));
+        let test_server = TestServer::new(router(notifier, short_poll())).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"0\"");
+        assert_eq!(response.read_utf8_body().unwrap(), "idle");
+    }
+
+    #[test]
+    fn responds_not_modified_on_timeout() {
+        let notifier = Notifier::new(String::from("idle"));
+        let test_server = TestServer::new(router(notifier, short_poll())).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(IF_NONE_MATCH, HeaderValue::from_static("\"0\""))
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], "\"0\"");
+    }
+
+    #[test]
+    fn responds_no_content_on_timeout() {
+        let notifier = Notifier::new(String::from("idle"));
+        let test_server =
+            TestServer::new(router(notifier, short_poll().with_no_content())).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(IF_NONE_MATCH, HeaderValue::from_static("\"0\""))
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn responds_when_notified() {
+        let notifier = Notifier::new(String::from("idle"));
+        let test_server = TestServer::new(router(
+            notifier.clone(),
+            LongPoll::new(Duration::from_secs(5)),
+        ))
+        .unwrap();
+
+        let notify = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            notifier.notify(String::from("busy"));
+        });
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(IF_NONE_MATCH, HeaderValue::from_static("\"0\""))
+            .perform()
+            .unwrap();
+        notify.join().unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"1\"");
+        assert_eq!(response.read_utf8_body().unwrap(), "busy");
+    }
+
+    #[test]
+    fn responds_with_broadcast_messages() {
+        let (sender, _) = broadcast::channel::<&'static str>(4);
+        let subscriber = AssertUnwindSafe(sender.clone());
+        let router = build_simple_router(move |route| {
+            route.get("/").to_new_handler(move || {
+                let subscriber = subscriber.clone();
+                Ok(move |state: State| {
+                    LongPoll::new(Duration::from_secs(5))
+                        .respond_broadcast(state, subscriber.subscribe())
+                })
+            });
+        });
+        let test_server = TestServer::new(router).unwrap();
+
+        let notify = thread::spawn(move || {
+            while sender.receiver_count() == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+            sender.send("hello").unwrap();
+        });
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        notify.join().unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "hello");
+    }
+}
@@ -1,16 +1,35 @@
 //! Helpers for HTTP request handling and response generation
 
+pub mod checksum;
+pub mod conditional;
 pub mod header;
+pub mod long_poll;
 pub mod request;
 pub mod response;
+#[cfg(feature = "upload")]
+pub mod upload;
+pub mod vary;
 
 use log::trace;
 use percent_encoding::percent_decode;
 
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
 /// Represents data that has been successfully percent decoded and is valid UTF-8
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone)]
 pub struct PercentDecoded {
-    val: String,
+    val: DecodedValue,
+}
+
+// Data which contained no percent encoded characters refers to a range of the shared source it
+// was taken from, instead of being copied.
+#[derive(Clone)]
+enum DecodedValue {
+    Shared(Arc<str>, Range<usize>),
+    Owned(String),
 }
 
 impl PercentDecoded {
@@ -20,12 +39,13 @@ impl PercentDecoded {
     /// On success, the decoded data is returned as a `PercentDecoded` value, which allows a
     /// compile-time check that the decode has occurred in places where it's assumed to have
     /// occurred.
+    #[cfg(test)]
     pub(crate) fn new(raw: &str) -> Option<Self> {
         match percent_decode(raw.as_bytes()).decode_utf8() {
             Ok(pd) => {
                 trace!(" percent_decode: {}, src: {}", pd, raw);
                 Some(PercentDecoded {
-                    val: pd.into_owned(),
+                    val: DecodedValue::Owned(pd.into_owned()),
                 })
             }
             Err(_) => {
@@ -34,11 +54,48 @@ impl PercentDecoded {
             }
         }
     }
+
+    /// Like `new`, but decodes the given range of `source`. If the range contains no percent
+    /// encoded characters, the value refers to `source` instead of allocating a copy.
+    pub(crate) fn from_shared(source: &Arc<str>, range: Range<usize>) -> Option<Self> {
+        let raw = &source[range.clone()];
+        let val = match percent_decode(raw.as_bytes()).decode_utf8() {
+            Ok(Cow::Borrowed(_)) => DecodedValue::Shared(source.clone(), range),
+            Ok(Cow::Owned(pd)) => {
+                trace!(" percent_decode: {}, src: {}", pd, raw);
+                DecodedValue::Owned(pd)
+            }
+            Err(_) => {
+                trace!(" percent_decode: error, src: {}", raw);
+                return None;
+            }
+        };
+        Some(PercentDecoded { val })
+    }
 }
 
 impl AsRef<str> for PercentDecoded {
     fn as_ref(&self) -> &str {
-        &self.val
+        match self.val {
+            DecodedValue::Shared(ref source, ref range) => &source[range.clone()],
+            DecodedValue::Owned(ref val) => val,
+        }
+    }
+}
+
+impl PartialEq for PercentDecoded {
+    fn eq(&self, other: &PercentDecoded) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl Eq for PercentDecoded {}
+
+impl fmt::Debug for PercentDecoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PercentDecoded")
+            .field("val", &self.as_ref())
+            .finish()
     }
 }
 
@@ -95,6 +152,22 @@ mod tests {
         assert_eq!("A+B+c d", pd.as_ref());
     }
 
+    #[test]
+    fn shares_unencoded_source() {
+        let source = Arc::from("/plain/%41%42");
+        let plain = PercentDecoded::from_shared(&source, 1..6).unwrap();
+        assert_eq!("plain", plain.as_ref());
+        assert!(matches!(plain.val, DecodedValue::Shared(..)));
+
+        let encoded = PercentDecoded::from_shared(&source, 7..13).unwrap();
+        assert_eq!("AB", encoded.as_ref());
+        assert!(matches!(encoded.val, DecodedValue::Owned(_)));
+        assert_eq!(encoded, PercentDecoded::new("AB").unwrap());
+
+        let source = Arc::from("/%FF");
+        assert!(PercentDecoded::from_shared(&source, 1..4).is_none());
+    }
+
     #[test]
     fn ensure_valid_www_form_url_encoded_value() {
         let f = FormUrlDecoded::new("%41+%42%2B%63%20%64").unwrap();
@@ -1,4 +1,5 @@
 //! Helpers for HTTP request handling
 
+pub(crate) mod negotiation;
 pub mod path;
 pub mod query_string;
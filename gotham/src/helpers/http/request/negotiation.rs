@@ -0,0 +1,123 @@
+//! Caches the parsed values of content negotiation headers, like `Accept` and `Accept-Encoding`,
+//! for the lifetime of a connection.
+//!
+//! Clients keeping a connection alive usually send identical negotiation headers with every
+//! request. A `NegotiationCache` is placed into `State` for every request received on a
+//! connection, and keeps the parsed value of each header together with the raw header values it
+//! was parsed from, so the header is only parsed again when its value changes.
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::HeaderMap;
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::state::{FromState, State, StateData};
+
+type Parsed = Arc<dyn Any + Send + Sync>;
+
+struct CachedHeader {
+    raw: Vec<HeaderValue>,
+    parsed: Parsed,
+}
+
+/// The parsed values of negotiation headers received on a connection, keyed by header name and
+/// the type they were parsed into.
+#[derive(Clone, Default)]
+pub(crate) struct NegotiationCache {
+    headers: Arc<Mutex<HashMap<(HeaderName, TypeId), CachedHeader>>>,
+}
+
+impl StateData for NegotiationCache {}
+
+impl NegotiationCache {
+    pub(crate) fn new() -> Self {
+        NegotiationCache::default()
+    }
+
+    // Returns the parsed values of the header `name` in `headers`, calling `parse` unless the
+    // header values are identical to the ones parsed before.
+    fn get_or_parse<T, F>(&self, headers: &HeaderMap, name: HeaderName, parse: F) -> Arc<T>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce(&HeaderMap) -> T,
+    {
+        let key = (name, TypeId::of::<T>());
+        let mut cache = self.headers.lock().unwrap();
+        if let Some(cached) = cache.get(&key) {
+            if headers.get_all(&key.0).iter().eq(cached.raw.iter()) {
+                if let Ok(parsed) = cached.parsed.clone().downcast::<T>() {
+                    return parsed;
+                }
+            }
+        }
+
+        let parsed = Arc::new(parse(headers));
+        let raw = headers.get_all(&key.0).iter().cloned().collect();
+        cache.insert(
+            key,
+            CachedHeader {
+                raw,
+                parsed: parsed.clone(),
+            },
+        );
+        parsed
+    }
+}
+
+/// Parses the header `name` of the request with `parse`, reusing the value parsed for an earlier
+/// request on the same connection if the header is unchanged.
+pub(crate) fn parse_cached<T, F>(state: &State, name: HeaderName, parse: F) -> Arc<T>
+where
+    T: Send + Sync + 'static,
+    F: FnOnce(&HeaderMap) -> T,
+{
+    let headers = HeaderMap::borrow_from(state);
+    match state.try_borrow::<NegotiationCache>() {
+        Some(cache) => cache.get_or_parse(headers, name, parse),
+        None => Arc::new(parse(headers)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{ACCEPT, ACCEPT_ENCODING};
+
+    fn count_values(headers: &HeaderMap, name: &HeaderName, parses: &mut usize) -> usize {
+        *parses += 1;
+        headers.get_all(name).iter().count()
+    }
+
+    #[test]
+    fn parses_changed_headers_only() {
+        let cache = NegotiationCache::new();
+        let mut parses = 0;
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, "gzip".parse().unwrap());
+
+        let first = cache.get_or_parse(&headers, ACCEPT_ENCODING, |h| {
+            count_values(h, &ACCEPT_ENCODING, &mut parses)
+        });
+        let again = cache.get_or_parse(&headers, ACCEPT_ENCODING, |h| {
+            count_values(h, &ACCEPT_ENCODING, &mut parses)
+        });
+        assert_eq!(*first, 1);
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(parses, 1);
+
+        headers.append(ACCEPT_ENCODING, "br".parse().unwrap());
+        let changed = cache.get_or_parse(&headers, ACCEPT_ENCODING, |h| {
+            count_values(h, &ACCEPT_ENCODING, &mut parses)
+        });
+        assert_eq!(*changed, 2);
+        assert_eq!(parses, 2);
+
+        // other headers are cached independently
+        let accept =
+            cache.get_or_parse(&headers, ACCEPT, |h| count_values(h, &ACCEPT, &mut parses));
+        assert_eq!(*accept, 0);
+        assert_eq!(parses, 3);
+    }
+}
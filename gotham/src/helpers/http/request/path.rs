@@ -1,5 +1,7 @@
 //! Defines helper functions for processing the request path
 
+use std::sync::Arc;
+
 use crate::helpers::http::PercentDecoded;
 
 const EXCLUDED_SEGMENTS: [&str; 1] = [""];
@@ -26,9 +28,16 @@ impl RequestPathSegments {
     /// ```plain
     /// ["/", "some", "path", "to", "my", "handler"]
     /// ```
+    ///
+    /// The path is copied once and shared by all segments, so only segments containing percent
+    /// encoded characters need an allocation of their own.
     pub(crate) fn new(path: &str) -> Self {
-        let segments = split_path_segments(path)
-            .filter_map(PercentDecoded::new)
+        let source: Arc<str> = Arc::from(path);
+        let segments = split_path_segments(&source)
+            .filter_map(|segment| {
+                let start = segment.as_ptr() as usize - source.as_ptr() as usize;
+                PercentDecoded::from_shared(&source, start..start + segment.len())
+            })
             .collect();
 
         RequestPathSegments { segments }
@@ -66,4 +75,21 @@ mod tests {
             vec!["some", "path", "to", "my", "handler"]
         );
     }
+
+    #[test]
+    fn decodes_request_path_segments() {
+        let rps = RequestPathSegments::new("/%61ctiv%61te/%E2%9C%93/%FF/workflow");
+
+        assert_eq!(
+            rps.segments.iter().map(AsRef::as_ref).collect::<Vec<_>>(),
+            vec!["activate", "\u{2713}", "workflow"]
+        );
+        assert_eq!(
+            rps.subsegments(1).segments(),
+            &vec![
+                PercentDecoded::new("\u{2713}").unwrap(),
+                PercentDecoded::new("workflow").unwrap()
+            ]
+        );
+    }
 }
@@ -54,7 +54,7 @@ mod tests {
             })
             .collect();
 
-        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        pairs.sort_by_key(|(key, _)| *key);
         pairs
     }
 
@@ -8,6 +8,13 @@ use std::borrow::Cow;
 use crate::helpers::http::header::X_REQUEST_ID;
 use crate::state::{request_id, FromState, State};
 
+#[cfg(feature = "response-pool")]
+use crate::helpers::buffer;
+#[cfg(feature = "response-pool")]
+use bytes::BufMut;
+#[cfg(feature = "response-pool")]
+use serde::Serialize;
+
 /// Creates a `Response` object and populates it with a set of default headers that help to improve
 /// security and conformance to best practice.
 ///
@@ -77,6 +84,74 @@ where
     res
 }
 
+/// Creates a `Response` like `create_response`, with the JSON serialization of `value` as body and
+/// `application/json` as content type. Available with the `response-pool` feature.
+///
+/// The value is serialized into a buffer from the shared buffer pool instead of a freshly
+/// allocated `Vec`, so endpoints answering many requests with JSON reuse the same allocations
+/// once the bodies of earlier responses have been sent.
+///
+/// # Examples
+///
+/// ```rust
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::CONTENT_TYPE;
+/// # use gotham::state::State;
+/// # use gotham::helpers::http::response::create_json_response;
+/// # use gotham::test::TestServer;
+/// # use serde::Serialize;
+/// #
+/// #[derive(Serialize)]
+/// struct Product {
+///     name: &'static str,
+///     price: u32,
+/// }
+///
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let product = Product {
+///         name: "t-shirt",
+///         price: 15,
+///     };
+///     let response = create_json_response(&state, StatusCode::OK, &product)
+///         .expect("products are serializable");
+///
+///     (state, response)
+/// }
+/// #
+/// # fn main() {
+/// #     let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// #     let response = test_server
+/// #         .client()
+/// #         .get("http://example.com/")
+/// #         .perform()
+/// #         .unwrap();
+/// #
+/// #     assert_eq!(response.status(), StatusCode::OK);
+/// #     assert_eq!(
+/// #         *response.headers().get(CONTENT_TYPE).unwrap(),
+/// #         mime::APPLICATION_JSON.to_string()
+/// #     );
+/// #     assert_eq!(
+/// #         response.read_utf8_body().unwrap(),
+/// #         r#"{"name":"t-shirt","price":15}"#
+/// #     );
+/// # }
+/// ```
+#[cfg(feature = "response-pool")]
+pub fn create_json_response<T>(
+    state: &State,
+    status: StatusCode,
+    value: &T,
+) -> serde_json::Result<Response<Body>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut buf = buffer::acquire(buffer::SIZE_CLASSES[0]);
+    serde_json::to_writer((&mut *buf).writer(), value)?;
+    let body = buf.split().freeze();
+    Ok(create_response(state, status, mime::APPLICATION_JSON, body))
+}
+
 /// Produces a simple empty `Response` with a provided status.
 ///
 /// # Examples
@@ -0,0 +1,496 @@
+//! Streams request bodies to disk, for artifact and media upload services, available with the
+//! `upload` feature.
+//!
+//! `upload_to_file` writes the body of the current request to a temporary file next to the
+//! destination while it is being received, computing its SHA-256 digest on the fly. Only once
+//! the whole body has been written (and optionally synced to disk) is the file atomically renamed
+//! to its destination, so readers never observe a partial upload. Failed uploads, including
+//! those exceeding the size limit or cancelled because the client went away, leave no file
+//! behind.
+//!
+//! If the client sent a digest of the body in a `Content-Digest`, `Digest` or `Content-MD5`
+//! header, the body is verified against it while it is written, and an upload which doesn't match
+//! fails with `UploadError::Checksum` instead of being moved to its destination.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use std::pin::Pin;
+//! # use futures_util::future::FutureExt;
+//! # use gotham::handler::{HandlerError, HandlerFuture};
+//! # use gotham::helpers::http::response::create_response;
+//! # use gotham::state::State;
+//! # use hyper::StatusCode;
+//! use gotham::helpers::http::upload::{upload_to_file, UploadOptions};
+//!
+//! # #[allow(dead_code)]
+//! fn upload(mut state: State) -> Pin<Box<HandlerFuture>> {
+//!     async move {
+//!         let options = UploadOptions::new().with_max_size(100 * 1024 * 1024);
+//!         let file = match upload_to_file(&mut state, "/srv/artifacts/latest", &options).await {
+//!             Ok(file) => file,
+//!             Err(err) => {
+//!                 let status = err.status();
+//!                 return Err((state, HandlerError::from(err).with_status(status)));
+//!             }
+//!         };
+//!
+//!         let response = create_response(
+//!             &state,
+//!             StatusCode::CREATED,
+//!             mime::TEXT_PLAIN,
+//!             file.sha256_hex(),
+//!         );
+//!         Ok((state, response))
+//!     }
+//!     .boxed()
+//! }
+//! #
+//! # fn main() {}
+//! ```
+
+use std::fmt::{self, Write as _};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, CONTENT_LENGTH};
+use hyper::{Body, StatusCode};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::helpers::http::checksum::{ChecksumError, ChecksumVerifier, ExpectedChecksum};
+use crate::state::{FromState, State};
+
+/// The progress of an upload, as reported to the callback set with
+/// `UploadOptions::with_progress`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UploadProgress {
+    /// The number of bytes written so far.
+    pub received: u64,
+    /// The total size of the upload, if the client sent a `Content-Length` header.
+    pub expected: Option<u64>,
+}
+
+type ProgressCallback = Arc<dyn Fn(UploadProgress) + Send + Sync>;
+
+/// Options controlling how `upload_to_file` writes a request body to disk.
+#[derive(Clone)]
+pub struct UploadOptions {
+    max_size: Option<u64>,
+    temp_dir: Option<PathBuf>,
+    sync: bool,
+    progress: Option<ProgressCallback>,
+}
+
+impl fmt::Debug for UploadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadOptions")
+            .field("max_size", &self.max_size)
+            .field("temp_dir", &self.temp_dir)
+            .field("sync", &self.sync)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            max_size: None,
+            temp_dir: None,
+            sync: true,
+            progress: None,
+        }
+    }
+}
+
+impl UploadOptions {
+    /// Creates new `UploadOptions` without a size limit, syncing the file to disk before it is
+    /// moved to its destination.
+    pub fn new() -> Self {
+        UploadOptions::default()
+    }
+
+    /// Rejects bodies larger than `max_size` bytes with `UploadError::TooLarge`.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Writes the temporary file to `temp_dir` instead of the destination's directory. The
+    /// directory must be on the same file system as the destination, or renaming will fail.
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    /// Sets whether the file is synced to disk (`fsync`) before being renamed. Enabled by
+    /// default; disabling it trades durability after a crash for speed.
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Invokes `progress` each time a chunk of the body has been written.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+}
+
+/// A request body which has been written to disk by `upload_to_file`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UploadedFile {
+    path: PathBuf,
+    size: u64,
+    sha256: [u8; 32],
+}
+
+impl UploadedFile {
+    /// Returns the path the file has been written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the SHA-256 digest of the file.
+    pub fn sha256(&self) -> &[u8; 32] {
+        &self.sha256
+    }
+
+    /// Returns the SHA-256 digest of the file as a lowercase hex string.
+    pub fn sha256_hex(&self) -> String {
+        self.sha256
+            .iter()
+            .fold(String::with_capacity(64), |mut s, b| {
+                let _ = write!(s, "{:02x}", b);
+                s
+            })
+    }
+}
+
+/// The errors that can occur while uploading a request body to disk.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum UploadError {
+    /// The body is larger than the configured maximum size.
+    #[error("upload exceeds the maximum size of {0} bytes")]
+    TooLarge(u64),
+    /// Reading the body failed, e.g. because the client disconnected.
+    #[error("unable to read request body: {0}")]
+    Body(#[from] hyper::Error),
+    /// Writing the file failed.
+    #[error("unable to write upload: {0}")]
+    Io(#[from] io::Error),
+    /// A digest header of the request is invalid, or the body doesn't match it.
+    #[error("{0}")]
+    Checksum(#[from] ChecksumError),
+}
+
+impl UploadError {
+    /// Returns the status code appropriate for responding to the failed upload.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::Body(_) => StatusCode::BAD_REQUEST,
+            UploadError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            UploadError::Checksum(err) => err.status(),
+        }
+    }
+}
+
+/// Streams the body of the request in `state` to `destination`, replacing any existing file.
+///
+/// The body is taken from `state`, so this can only be called once per request. If the request
+/// declares a `Content-Length` larger than the maximum size, it is rejected without reading the
+/// body, as is a request with an invalid digest header.
+pub async fn upload_to_file<P>(
+    state: &mut State,
+    destination: P,
+    options: &UploadOptions,
+) -> Result<UploadedFile, UploadError>
+where
+    P: AsRef<Path>,
+{
+    let headers = HeaderMap::try_borrow_from(state);
+    let expected = headers
+        .and_then(|headers| headers.get(CONTENT_LENGTH))
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse().ok());
+    if let (Some(expected), Some(max_size)) = (expected, options.max_size) {
+        if expected > max_size {
+            return Err(UploadError::TooLarge(max_size));
+        }
+    }
+    let verifier = match headers {
+        Some(headers) => ExpectedChecksum::from_headers(headers)?.map(|c| c.verifier()),
+        None => None,
+    };
+
+    let body = Body::try_take_from(state).unwrap_or_else(Body::empty);
+    let destination = destination.as_ref();
+    let mut temp_file = TempFile {
+        path: temp_path(destination, options),
+        persisted: false,
+    };
+
+    let (size, sha256) = write_body(body, &temp_file.path, expected, verifier, options).await?;
+    fs::rename(&temp_file.path, destination).await?;
+    temp_file.persisted = true;
+    Ok(UploadedFile {
+        path: destination.to_owned(),
+        size,
+        sha256,
+    })
+}
+
+// Removes the temporary file when dropped unless it was renamed to its destination, so neither
+// failed nor cancelled uploads leave it behind.
+struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+async fn write_body(
+    mut body: Body,
+    path: &Path,
+    expected: Option<u64>,
+    mut verifier: Option<ChecksumVerifier>,
+    options: &UploadOptions,
+) -> Result<(u64, [u8; 32]), UploadError> {
+    let mut file = File::create(path).await?;
+    let mut hasher = Sha256::new();
+    let mut received = 0u64;
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if let Some(max_size) = options.max_size {
+            if received > max_size {
+                return Err(UploadError::TooLarge(max_size));
+            }
+        }
+
+        hasher.update(&chunk);
+        if let Some(verifier) = &mut verifier {
+            verifier.update(&chunk);
+        }
+        file.write_all(&chunk).await?;
+        if let Some(progress) = &options.progress {
+            progress(UploadProgress { received, expected });
+        }
+    }
+
+    if let Some(verifier) = verifier {
+        verifier.verify()?;
+    }
+
+    file.flush().await?;
+    if options.sync {
+        file.sync_all().await?;
+    }
+    Ok((received, hasher.finalize().into()))
+}
+
+// A hidden, uniquely named file in the directory the upload is written to.
+fn temp_path(destination: &Path, options: &UploadOptions) -> PathBuf {
+    let dir = match &options.temp_dir {
+        Some(dir) => dir.as_path(),
+        None => destination.parent().unwrap_or_else(|| Path::new("")),
+    };
+    let name = destination
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    dir.join(format!(".{}.{}.part", name, Uuid::new_v4().simple()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::http::header::CONTENT_DIGEST;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn state_with_body(body: &'static str, content_length: bool) -> State {
+        let mut headers = HeaderMap::new();
+        if content_length {
+            headers.insert(CONTENT_LENGTH, body.len().into());
+        }
+        let mut state = State::new();
+        state.put(headers);
+        state.put(Body::from(body));
+        state
+    }
+
+    #[tokio::test]
+    async fn writes_body_and_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("upload.txt");
+        let mut state = state_with_body("hello", true);
+
+        let file = upload_to_file(&mut state, &destination, &UploadOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(file.path(), destination);
+        assert_eq!(file.size(), 5);
+        assert_eq!(
+            file.sha256_hex(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(std::fs::read(&destination).unwrap(), b"hello");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_declared_oversized_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("upload.txt");
+        let mut state = state_with_body("hello", true);
+
+        let options = UploadOptions::new().with_max_size(4);
+        let err = upload_to_file(&mut state, &destination, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        // the body has not been consumed
+        assert!(state.has::<Body>());
+    }
+
+    #[tokio::test]
+    async fn rejects_streamed_oversized_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("upload.txt");
+        let mut state = state_with_body("hello", false);
+
+        let options = UploadOptions::new().with_max_size(4);
+        let err = upload_to_file(&mut state, &destination, &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UploadError::TooLarge(4)));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn verifies_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("upload.txt");
+        let digest = "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:";
+
+        let mut state = state_with_body("hello", false);
+        state
+            .borrow_mut::<HeaderMap>()
+            .insert(CONTENT_DIGEST, digest.parse().unwrap());
+        let file = upload_to_file(&mut state, &destination, &UploadOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(file.size(), 5);
+
+        let mut state = state_with_body("hullo", false);
+        state
+            .borrow_mut::<HeaderMap>()
+            .insert(CONTENT_DIGEST, digest.parse().unwrap());
+        let err = upload_to_file(&mut state, &destination, &UploadOptions::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            UploadError::Checksum(ChecksumError::Mismatch(_))
+        ));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        // the previous upload is left in place
+        assert_eq!(std::fs::read(&destination).unwrap(), b"hello");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("upload.txt");
+        let mut state = state_with_body("hello", true);
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let options = UploadOptions::new().with_sync(false).with_progress({
+            let reported = reported.clone();
+            move |progress| reported.lock().unwrap().push(progress)
+        });
+        upload_to_file(&mut state, &destination, &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![UploadProgress {
+                received: 5,
+                expected: Some(5)
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn removes_temp_file_if_rename_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        // a file can't replace a directory which isn't empty
+        let destination = dir.path().join("upload");
+        std::fs::create_dir_all(destination.join("occupied")).unwrap();
+        let mut state = state_with_body("hello", false);
+
+        upload_to_file(&mut state, &destination, &UploadOptions::new())
+            .await
+            .unwrap_err();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn removes_temp_file_if_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("upload.txt");
+        let (mut sender, body) = Body::channel();
+        sender.send_data("hel".into()).await.unwrap();
+        let mut state = State::new();
+        state.put(HeaderMap::new());
+        state.put(body);
+
+        // the body is never completed
+        let options = UploadOptions::new();
+        let upload = upload_to_file(&mut state, &destination, &options);
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), upload).await;
+        assert!(cancelled.is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn writes_to_temp_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let temp_dir = tempfile::tempdir_in(dir.path()).unwrap();
+        let destination = dir.path().join("upload.txt");
+        let mut state = state_with_body("hello", false);
+
+        let options = UploadOptions::new().with_temp_dir(temp_dir.path());
+        let file = upload_to_file(&mut state, &destination, &options)
+            .await
+            .unwrap();
+        assert_eq!(file.size(), 5);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+}
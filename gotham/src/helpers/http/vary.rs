@@ -0,0 +1,156 @@
+//! Helpers for the `Vary` header, which lists the request headers a response depends on.
+//!
+//! Several layers may make a response depend on a request header, like the `Accept-Encoding`
+//! of compression, the `Accept` of content negotiation in a handler, or the `Origin` of CORS.
+//! Caches only serve a response to requests with the same values of all of them, so each layer
+//! must add to the `Vary` header instead of replacing it. `add_vary` merges a header name into
+//! the `Vary` header of a response, while `vary_on` records a header name in `State` before the
+//! response exists, e.g. in a handler or before calling the next middleware. The names recorded
+//! in `State` are merged into the response when the request is finished, so the response carries
+//! a single `Vary` header listing every name once.
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, VARY};
+
+use crate::state::{State, StateData};
+
+/// The header names recorded with `vary_on` for the current request.
+#[derive(Clone, Debug, Default)]
+struct VaryNames(Vec<HeaderName>);
+
+impl StateData for VaryNames {}
+
+/// Records that the response to the current request depends on the request header `name`, which
+/// is added to the `Vary` header of the response when the request is finished.
+///
+/// ```rust
+/// # use gotham::hyper::header::{ACCEPT, VARY};
+/// # use gotham::hyper::{Body, Response, StatusCode};
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// use gotham::helpers::http::vary::vary_on;
+///
+/// fn handler(mut state: State) -> (State, Response<Body>) {
+///     vary_on(&mut state, ACCEPT);
+///     let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, "text");
+///     (state, response)
+/// }
+///
+/// # fn main() {
+/// # let test_server = TestServer::new(build_simple_router(|route| {
+/// #     route.get("/").to(handler);
+/// # }))
+/// # .unwrap();
+/// # let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// # assert_eq!(response.headers()[VARY], "accept");
+/// # }
+/// ```
+pub fn vary_on(state: &mut State, name: HeaderName) {
+    match state.try_borrow_mut::<VaryNames>() {
+        Some(names) => {
+            if !names.0.contains(&name) {
+                names.0.push(name);
+            }
+        }
+        None => state.put(VaryNames(vec![name])),
+    }
+}
+
+/// Adds the request header `name` to the `Vary` header of a response, merging all of its `Vary`
+/// headers into one which lists every name once. A `Vary` header of `*`, meaning the response
+/// depends on more than request headers, is kept as it is.
+pub fn add_vary(headers: &mut HeaderMap, name: HeaderName) {
+    merge_vary(headers, std::iter::once(name));
+}
+
+/// Checks whether the `Vary` headers of a response list the request header `name`, or `*`.
+pub fn varies_on(headers: &HeaderMap, name: &HeaderName) -> bool {
+    vary_names(headers).any(|value| value == "*" || value.eq_ignore_ascii_case(name.as_str()))
+}
+
+// Adds the names recorded in `state` to the `Vary` header of the response.
+pub(crate) fn apply_vary(state: &State, headers: &mut HeaderMap) {
+    if let Some(names) = state.try_borrow::<VaryNames>() {
+        merge_vary(headers, names.0.iter().cloned());
+    }
+}
+
+// Returns the comma separated names of all `Vary` headers.
+pub(crate) fn vary_names(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn merge_vary<I>(headers: &mut HeaderMap, names: I)
+where
+    I: IntoIterator<Item = HeaderName>,
+{
+    let mut merged: Vec<String> = Vec::new();
+    let existing = vary_names(headers).map(str::to_owned).collect::<Vec<_>>();
+    let added = names.into_iter().map(|name| name.as_str().to_owned());
+    for name in existing.into_iter().chain(added) {
+        if !merged.iter().any(|other| other.eq_ignore_ascii_case(&name)) {
+            merged.push(name);
+        }
+    }
+    if merged.is_empty() {
+        return;
+    }
+    let value = if merged.iter().any(|name| name == "*") {
+        HeaderValue::from_static("*")
+    } else {
+        match HeaderValue::from_str(&merged.join(", ")) {
+            Ok(value) => value,
+            Err(_) => return,
+        }
+    };
+    headers.insert(VARY, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{ACCEPT, ACCEPT_ENCODING, ORIGIN};
+
+    #[test]
+    fn merges_vary_headers() {
+        let mut headers = HeaderMap::new();
+        add_vary(&mut headers, ACCEPT_ENCODING);
+        assert_eq!(headers[VARY], "accept-encoding");
+
+        headers.append(VARY, HeaderValue::from_static("Cookie, Accept-Encoding"));
+        add_vary(&mut headers, ORIGIN);
+        add_vary(&mut headers, ORIGIN);
+        assert_eq!(headers.get_all(VARY).iter().count(), 1);
+        assert_eq!(headers[VARY], "accept-encoding, Cookie, origin");
+        assert!(varies_on(&headers, &ACCEPT_ENCODING));
+        assert!(!varies_on(&headers, &ACCEPT));
+
+        headers.append(VARY, HeaderValue::from_static("*"));
+        add_vary(&mut headers, ACCEPT);
+        assert_eq!(headers[VARY], "*");
+        assert!(varies_on(&headers, &ACCEPT));
+    }
+
+    #[test]
+    fn applies_names_recorded_in_state() {
+        State::with_new(|state| {
+            let mut headers = HeaderMap::new();
+            apply_vary(state, &mut headers);
+            assert!(headers.get(VARY).is_none());
+
+            vary_on(state, ACCEPT);
+            vary_on(state, ORIGIN);
+            vary_on(state, ACCEPT);
+            headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+            apply_vary(state, &mut headers);
+            assert_eq!(headers[VARY], "accept-encoding, accept, origin");
+        });
+    }
+}
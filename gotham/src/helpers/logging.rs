@@ -0,0 +1,202 @@
+//! A logger which attaches the context of the current request to every log record.
+//!
+//! Gotham serves every request within the scope of its `RequestContext`, so log records emitted
+//! while a request is handled, by Gotham itself as well as by middleware and handlers using the
+//! macros of the `log` crate, can be correlated by the ID and client address of the request.
+//! Work spawned in the background keeps the context if it runs within `RequestContext::scope`.
+//!
+//! `ContextLogger` writes records as text lines, or as JSON objects for log aggregation systems.
+//!
+//! ```rust
+//! use gotham::helpers::logging::ContextLogger;
+//! use log::LevelFilter;
+//!
+//! # fn main() {
+//! ContextLogger::new(LevelFilter::Info)
+//!     .with_json(true)
+//!     .init()
+//!     .expect("no other logger was installed");
+//!
+//! // {"level":"INFO","target":"app","message":"started","request_id":null,"client_addr":null}
+//! log::info!(target: "app", "started");
+//! # }
+//! ```
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use crate::state::RequestContext;
+
+/// A `log::Log` implementation attaching the request ID and client address of the current
+/// `RequestContext` to every record.
+pub struct ContextLogger {
+    level: LevelFilter,
+    json: bool,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl ContextLogger {
+    /// Creates a new `ContextLogger` writing text lines for records up to the given level to
+    /// standard error.
+    pub fn new(level: LevelFilter) -> Self {
+        ContextLogger {
+            level,
+            json: false,
+            writer: Mutex::new(Box::new(io::stderr())),
+        }
+    }
+
+    /// If `true`, records are written as JSON objects, one per line (defaults to false).
+    pub fn with_json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Writes records to `writer` instead of standard error.
+    pub fn with_writer<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.writer = Mutex::new(Box::new(writer));
+        self
+    }
+
+    /// Installs the logger as the logger of the `log` crate.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let level = self.level;
+        log::set_logger(Box::leak(Box::new(self)))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+
+    fn format(&self, record: &Record<'_>, context: Option<&RequestContext>) -> String {
+        let request_id = context.map(RequestContext::request_id);
+        let client_addr = context
+            .and_then(RequestContext::client_addr)
+            .map(|addr| addr.to_string());
+        let message = record.args().to_string();
+        if self.json {
+            let mut line = String::with_capacity(96 + message.len());
+            line.push_str("{\"level\":");
+            push_json_str(&mut line, &record.level().to_string());
+            line.push_str(",\"target\":");
+            push_json_str(&mut line, record.target());
+            line.push_str(",\"message\":");
+            push_json_str(&mut line, &message);
+            line.push_str(",\"request_id\":");
+            push_json_opt(&mut line, request_id);
+            line.push_str(",\"client_addr\":");
+            push_json_opt(&mut line, client_addr.as_deref());
+            line.push('}');
+            line
+        } else {
+            format!(
+                "[{}][{}][{}] {}: {}",
+                record.level(),
+                request_id.unwrap_or("-"),
+                client_addr.as_deref().unwrap_or("-"),
+                record.target(),
+                message
+            )
+        }
+    }
+}
+
+impl Log for ContextLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = RequestContext::with_current(|context| self.format(record, Some(context)))
+            .unwrap_or_else(|| self.format(record, None));
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writeln!(writer, "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = self.writer.lock().unwrap().flush();
+    }
+}
+
+fn push_json_opt(out: &mut String, value: Option<&str>) {
+    match value {
+        Some(value) => push_json_str(out, value),
+        None => out.push_str("null"),
+    }
+}
+
+fn push_json_str(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::client_addr::put_client_addr;
+    use crate::state::{set_request_id, State};
+    use hyper::HeaderMap;
+    use log::Level;
+
+    fn context() -> RequestContext {
+        let mut context = None;
+        State::with_new(|state| {
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Request-ID", "abc".parse().unwrap());
+            state.put(headers);
+            set_request_id(state);
+            put_client_addr(state, "127.0.0.1:8080".parse().unwrap());
+            context = Some(RequestContext::new(state));
+        });
+        context.unwrap()
+    }
+
+    fn format(logger: &ContextLogger, context: Option<&RequestContext>) -> String {
+        logger.format(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("app")
+                .args(format_args!("said \"hi\"\n"))
+                .build(),
+            context,
+        )
+    }
+
+    #[test]
+    fn formats_text() {
+        let logger = ContextLogger::new(LevelFilter::Info);
+        assert_eq!(
+            format(&logger, Some(&context())),
+            "[WARN][abc][127.0.0.1:8080] app: said \"hi\"\n"
+        );
+        assert_eq!(format(&logger, None), "[WARN][-][-] app: said \"hi\"\n");
+    }
+
+    #[test]
+    fn formats_json() {
+        let logger = ContextLogger::new(LevelFilter::Info).with_json(true);
+        assert_eq!(
+            format(&logger, Some(&context())),
+            "{\"level\":\"WARN\",\"target\":\"app\",\"message\":\"said \\\"hi\\\"\\n\",\
+             \"request_id\":\"abc\",\"client_addr\":\"127.0.0.1:8080\"}"
+        );
+        assert!(format(&logger, None).ends_with("\"request_id\":null,\"client_addr\":null}"));
+    }
+}
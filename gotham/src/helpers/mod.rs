@@ -1,4 +1,7 @@
 //! Helpers, e.g. for HTTP request handling and response generation
 
+pub mod buffer;
+pub mod clock;
 pub mod http;
+pub mod logging;
 pub(crate) mod timing;
@@ -0,0 +1,447 @@
+//! Background jobs which run on the runtime of the server, alongside the requests.
+//!
+//! Jobs are registered at startup with `Jobs`, either to run once or periodically, and are started
+//! with `Jobs::start`. The returned `JobRunner` provides a `JobHandle`, which can be added to a
+//! pipeline to place it into the `State` of every request, so handlers can enqueue work which
+//! continues after the response has been sent. On shutdown, recurring jobs are stopped, and jobs
+//! which are still running or enqueued are given some time to complete.
+//!
+//! Jobs return an `anyhow::Result<()>`, and errors are logged along with the name of the job.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::time::Duration;
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! use gotham::jobs::{JobHandle, Jobs};
+//! use gotham::state::{FromState, State};
+//!
+//! fn handler(state: State) -> (State, &'static str) {
+//!     JobHandle::borrow_from(&state)
+//!         .enqueue(async {
+//!             // send a mail, which may take a while
+//!             Ok(())
+//!         })
+//!         .expect("jobs are shutting down");
+//!     (state, "Mail will be sent shortly")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let runner = Jobs::new()
+//!         .every("purge-sessions", Duration::from_secs(60), || async {
+//!             // remove expired sessions
+//!             Ok(())
+//!         })
+//!         .start();
+//!
+//!     let (chain, pipelines) = single_pipeline(new_pipeline().add(runner.handle()).build());
+//!     let router = build_router(chain, pipelines, |route| {
+//!         route.get("/").to(handler);
+//!     });
+//!
+//!     // e.g. completed by a signal handler
+//!     let (_stop, stopped) = tokio::sync::oneshot::channel::<()>();
+//!     let shutdown = async {
+//!         let _ = stopped.await;
+//!     };
+//!
+//!     let server = gotham::init_server("127.0.0.1:7878", router);
+//!     runner
+//!         .run_server(server, shutdown, Duration::from_secs(30))
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::{self, BoxFuture, Either, FutureExt};
+use log::{debug, error};
+use thiserror::Error;
+use tokio::runtime::Handle;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{State, StateData};
+use crate::StartError;
+
+type JobFn = Box<dyn FnMut() -> BoxFuture<'static, anyhow::Result<()>> + Send>;
+
+enum Schedule {
+    Once,
+    Every(Duration),
+}
+
+struct Job {
+    name: String,
+    schedule: Schedule,
+    run: JobFn,
+}
+
+/// The error returned when enqueuing or draining jobs fails.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum JobError {
+    /// The jobs are shutting down, and don't accept new work.
+    #[error("jobs are shutting down")]
+    ShuttingDown,
+    /// Jobs were still running when the drain timeout elapsed.
+    #[error("{0} jobs were still running when the drain timeout elapsed")]
+    DrainTimeout(usize),
+}
+
+/// Registers the jobs to run in the background, before they are started with `Jobs::start`.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Creates an empty set of jobs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a job which runs once, as soon as the jobs are started.
+    pub fn once<F, Fut>(mut self, name: &str, job: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let mut job = Some(job);
+        self.jobs.push(Job {
+            name: name.to_owned(),
+            schedule: Schedule::Once,
+            run: Box::new(move || match job.take() {
+                Some(job) => job().boxed(),
+                None => future::ok(()).boxed(),
+            }),
+        });
+        self
+    }
+
+    /// Registers a job which runs every `period`, starting as soon as the jobs are started. A run
+    /// which takes longer than `period` delays the next one, so runs of a job never overlap.
+    pub fn every<F, Fut>(mut self, name: &str, period: Duration, mut job: F) -> Self
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.jobs.push(Job {
+            name: name.to_owned(),
+            schedule: Schedule::Every(period),
+            run: Box::new(move || job().boxed()),
+        });
+        self
+    }
+
+    /// Starts the registered jobs on the current runtime.
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a tokio runtime.
+    pub fn start(self) -> JobRunner {
+        let handle = JobHandle {
+            shared: AssertUnwindSafe(Arc::new(Shared {
+                runtime: Handle::current(),
+                accepting: AtomicBool::new(true),
+                active: AtomicUsize::new(0),
+                idle: Notify::new(),
+            })),
+        };
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let tasks = self
+            .jobs
+            .into_iter()
+            .map(|job| spawn_job(job, handle.clone(), shutdown_rx.clone()))
+            .collect();
+
+        JobRunner {
+            handle,
+            shutdown: shutdown_tx,
+            tasks,
+        }
+    }
+}
+
+fn spawn_job(
+    mut job: Job,
+    handle: JobHandle,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    handle.shared.runtime.clone().spawn(async move {
+        let mut interval = match job.schedule {
+            Schedule::Once => {
+                let _guard = ActiveGuard::new(&handle.shared);
+                run_job(&job.name, (job.run)()).await;
+                return;
+            }
+            Schedule::Every(period) => tokio::time::interval(period),
+        };
+
+        loop {
+            let tick = interval.tick().boxed();
+            let stopped = shutdown.changed().boxed();
+            if let Either::Right(_) = future::select(tick, stopped).await {
+                debug!("stopping recurring job `{}`", job.name);
+                return;
+            }
+
+            let _guard = ActiveGuard::new(&handle.shared);
+            run_job(&job.name, (job.run)()).await;
+        }
+    })
+}
+
+async fn run_job(name: &str, job: BoxFuture<'static, anyhow::Result<()>>) {
+    debug!("running job `{}`", name);
+    if let Err(err) = job.await {
+        error!("job `{}` failed: {:#}", name, err);
+    }
+}
+
+struct Shared {
+    runtime: Handle,
+    accepting: AtomicBool,
+    active: AtomicUsize,
+    idle: Notify,
+}
+
+// Counts a job as active while it is alive, including when the job panics.
+struct ActiveGuard {
+    shared: Arc<Shared>,
+}
+
+impl ActiveGuard {
+    fn new(shared: &Arc<Shared>) -> Self {
+        shared.active.fetch_add(1, Ordering::SeqCst);
+        ActiveGuard {
+            shared: shared.clone(),
+        }
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        if self.shared.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.idle.notify_waiters();
+        }
+    }
+}
+
+/// A handle for enqueuing jobs, which can be cloned freely.
+///
+/// `JobHandle` is also a `Middleware`, which places the handle into the `State` of every request.
+pub struct JobHandle {
+    // The runtime handle is not `RefUnwindSafe`, but the shared state is only accessed atomically.
+    shared: AssertUnwindSafe<Arc<Shared>>,
+}
+
+impl Clone for JobHandle {
+    fn clone(&self) -> Self {
+        JobHandle {
+            shared: AssertUnwindSafe(self.shared.0.clone()),
+        }
+    }
+}
+
+impl JobHandle {
+    /// Enqueues a job, which starts running immediately on the runtime the jobs were started on.
+    /// Fails if the jobs are shutting down.
+    pub fn enqueue<F>(&self, job: F) -> Result<(), JobError>
+    where
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        if !self.shared.accepting.load(Ordering::SeqCst) {
+            return Err(JobError::ShuttingDown);
+        }
+
+        let guard = ActiveGuard::new(&self.shared);
+        self.shared.runtime.spawn(async move {
+            let _guard = guard;
+            run_job("enqueued", job.boxed()).await;
+        });
+        Ok(())
+    }
+
+    /// Returns the number of jobs which are currently running.
+    pub fn active(&self) -> usize {
+        self.shared.active.load(Ordering::SeqCst)
+    }
+}
+
+impl StateData for JobHandle {}
+
+impl NewMiddleware for JobHandle {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for JobHandle {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        state.put(self);
+        chain(state)
+    }
+}
+
+/// The running jobs, as returned by `Jobs::start`.
+///
+/// Recurring jobs are stopped when the `JobRunner` is dropped, so it should be kept until the
+/// server shuts down.
+pub struct JobRunner {
+    handle: JobHandle,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl JobRunner {
+    /// Returns a handle for enqueuing jobs.
+    pub fn handle(&self) -> JobHandle {
+        self.handle.clone()
+    }
+
+    /// Stops the recurring jobs and rejects new jobs, then waits up to `timeout` for the jobs
+    /// which are still running to complete. Recurring jobs are not interrupted while running.
+    pub async fn shutdown(self, timeout: Duration) -> Result<(), JobError> {
+        let JobRunner {
+            handle,
+            shutdown,
+            tasks,
+        } = self;
+        let shared = &*handle.shared;
+        shared.accepting.store(false, Ordering::SeqCst);
+        let _ = shutdown.send(true);
+
+        let drained = async move {
+            future::join_all(tasks).await;
+            loop {
+                let idle = shared.idle.notified();
+                if shared.active.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        };
+
+        match tokio::time::timeout(timeout, drained).await {
+            Ok(()) => Ok(()),
+            Err(_) => Err(JobError::DrainTimeout(shared.active.load(Ordering::SeqCst))),
+        }
+    }
+
+    /// Runs `server` until `signal` completes, then shuts the jobs down, waiting up to
+    /// `drain_timeout` for them to complete. Jobs which don't complete in time are logged.
+    pub async fn run_server<S, Sig>(
+        self,
+        server: S,
+        signal: Sig,
+        drain_timeout: Duration,
+    ) -> Result<(), StartError>
+    where
+        S: Future<Output = Result<(), StartError>>,
+        Sig: Future<Output = ()>,
+    {
+        let result = match future::select(Box::pin(server), Box::pin(signal)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Ok(()),
+        };
+
+        if let Err(err) = self.shutdown(drain_timeout).await {
+            error!("{}", err);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn runs_one_shot_and_recurring_jobs() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let once = runs.clone();
+        let every = runs.clone();
+
+        let runner = Jobs::new()
+            .once("once", move || async move {
+                once.lock().unwrap().push("once");
+                Ok(())
+            })
+            .every("every", Duration::from_millis(10), move || {
+                let every = every.clone();
+                async move {
+                    every.lock().unwrap().push("every");
+                    Err(anyhow::anyhow!("recurring jobs keep running after errors"))
+                }
+            })
+            .start();
+
+        tokio::time::sleep(Duration::from_millis(55)).await;
+        runner.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let stopped = runs.lock().unwrap().clone();
+        assert_eq!(stopped.iter().filter(|run| **run == "once").count(), 1);
+        assert!(stopped.iter().filter(|run| **run == "every").count() >= 3);
+
+        // recurring jobs are stopped after the shutdown
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(runs.lock().unwrap().len(), stopped.len());
+    }
+
+    #[tokio::test]
+    async fn drains_enqueued_jobs() {
+        let runner = Jobs::new().start();
+        let handle = runner.handle();
+        let (tx, rx) = oneshot::channel();
+
+        handle
+            .enqueue(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                tx.send(()).unwrap();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(handle.active(), 1);
+
+        runner.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert!(rx.await.is_ok());
+        assert_eq!(handle.active(), 0);
+        assert!(matches!(
+            handle.enqueue(async { Ok(()) }),
+            Err(JobError::ShuttingDown)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_jobs_exceeding_drain_timeout() {
+        let runner = Jobs::new().start();
+        runner
+            .handle()
+            .enqueue(future::pending::<anyhow::Result<()>>())
+            .unwrap();
+
+        match runner.shutdown(Duration::from_millis(10)).await {
+            Err(JobError::DrainTimeout(1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
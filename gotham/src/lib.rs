@@ -5,8 +5,6 @@
 //! We look forward to welcoming you into the Gotham community!
 #![doc(html_root_url = "https://docs.rs/gotham/0.7.4")]
 // Update when changed in Cargo.toml
-// Stricter requirements once we get to pull request stage, all warnings must be resolved.
-#![cfg_attr(feature = "ci", deny(warnings))]
 #![allow(
     clippy::needless_lifetimes,
     clippy::should_implement_trait,
@@ -22,20 +20,36 @@
 #![deny(elided_lifetimes_in_paths, unsafe_code)]
 #![doc(test(no_crate_inject, attr(deny(warnings))))]
 
+pub mod bench;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "config")]
+pub mod config;
 pub mod extractor;
 pub mod handler;
 pub mod helpers;
+pub mod jobs;
+pub mod listener;
 pub mod middleware;
 pub mod pipeline;
 pub mod prelude;
 pub mod router;
 pub mod service;
+pub mod sse;
 pub mod state;
+pub mod throttle;
+pub mod tunnel;
+pub mod upgrade;
+#[cfg(feature = "client")]
+pub mod webhook;
 
 /// Test utilities for Gotham and Gotham consumer apps.
 #[cfg(feature = "testing")]
 pub mod test;
 
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+
 /// Functions for creating a Gotham service using HTTP.
 pub mod plain;
 
@@ -43,6 +57,13 @@ pub mod plain;
 #[cfg(feature = "rustls")]
 pub mod tls;
 
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+/// Functions for creating a Gotham service listening on a Unix domain socket.
+#[cfg(unix)]
+pub mod unix;
+
 /// Re-export anyhow
 pub use anyhow;
 /// Re-export cookie
@@ -56,19 +77,23 @@ pub use mime;
 #[cfg(feature = "rustls")]
 pub use tokio_rustls::rustls;
 
-use futures_util::TryFutureExt;
+use futures_util::future::{self, Either, FutureExt};
 use hyper::server::conn::Http;
 use std::future::Future;
 use std::io;
 use std::net::ToSocketAddrs;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::{self, Runtime};
+use tokio::sync::watch;
 
 use crate::handler::NewHandler;
-use crate::service::GothamService;
+use crate::listener::BindOptions;
+use crate::service::{ConnectionActivity, GothamService};
+use crate::throttle::{ConnectionThrottle, ThrottleConfig, Throttled};
 
 pub use plain::*;
 #[cfg(feature = "rustls")]
@@ -92,14 +117,11 @@ fn new_runtime(threads: usize) -> Runtime {
         .unwrap()
 }
 
-async fn tcp_listener<A>(addr: A) -> io::Result<TcpListener>
+async fn tcp_listener<A>(addr: A, options: BindOptions) -> io::Result<TcpListener>
 where
     A: ToSocketAddrs + 'static,
 {
-    let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
-        io::Error::new(io::ErrorKind::Other, "unable to resolve listener address")
-    })?;
-    TcpListener::bind(addr).await
+    listener::bind(addr, options).await
 }
 
 /// Returns a `Future` used to spawn a Gotham application.
@@ -108,10 +130,33 @@ where
 /// support. The wrap argument is a function that will receive a tokio-io TcpStream and should wrap
 /// the socket as necessary. Errors returned by this function will be ignored and the connection
 /// will be dropped if the future returned by the wrapper resolves to an error.
-pub async fn bind_server<'a, NH, F, Wrapped, Wrap>(
+pub async fn bind_server<NH, F, Wrapped, Wrap>(
+    listener: TcpListener,
+    new_handler: NH,
+    wrap: Wrap,
+) -> !
+where
+    NH: NewHandler + 'static,
+    F: Future<Output = Result<Wrapped, ()>> + Unpin + Send + 'static,
+    Wrapped: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    Wrap: Fn(TcpStream) -> F,
+{
+    let options = ServerOptions::new();
+    serve_connections(listener, new_handler, wrap, options, future::pending()).await;
+    unreachable!("the server is never shut down")
+}
+
+/// Returns a `Future` used to spawn a Gotham application, limiting the bandwidth of every
+/// accepted connection according to the given `ThrottleConfig`.
+///
+/// This behaves like `bind_server`, but additionally places a `ConnectionThrottle` into the
+/// `State` of every request, which middleware can use to override the limits of the current
+/// connection. See the `throttle` module for details.
+pub async fn bind_server_with_throttle<NH, F, Wrapped, Wrap>(
     listener: TcpListener,
     new_handler: NH,
     wrap: Wrap,
+    throttle: ThrottleConfig,
 ) -> !
 where
     NH: NewHandler + 'static,
@@ -119,36 +164,430 @@ where
     Wrapped: Unpin + AsyncRead + AsyncWrite + Send + 'static,
     Wrap: Fn(TcpStream) -> F,
 {
-    let protocol = Arc::new(Http::new());
+    let options = ServerOptions::new().with_throttle(throttle);
+    serve_connections(listener, new_handler, wrap, options, future::pending()).await;
+    unreachable!("the server is never shut down")
+}
+
+/// Returns a `Future` used to spawn a Gotham application on plaintext connections, which are
+/// upgraded to HTTP/2 when requested by the client with `Upgrade: h2c`.
+///
+/// This behaves like `bind_server` without wrapping the connections. Clients negotiate HTTP/2
+/// over TLS with ALPN instead, so h2c upgrades are never offered on connections using TLS.
+#[cfg(feature = "http2")]
+pub async fn bind_server_with_h2c<NH>(listener: TcpListener, new_handler: NH) -> !
+where
+    NH: NewHandler + 'static,
+{
+    let options = ServerOptions::new().with_h2c();
+    serve_connections(
+        listener,
+        new_handler,
+        future::ok,
+        options,
+        future::pending(),
+    )
+    .await;
+    unreachable!("the server is never shut down")
+}
+
+/// The settings of the connections accepted by a server started with `bind_server_with_options`.
+///
+/// Timeouts are disabled by default.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ServerOptions {
+    throttle: Option<ThrottleConfig>,
+    read_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    shutdown_timeout: Option<Duration>,
+    // only set for plaintext connections, see `bind_server_with_h2c`
+    h2c: bool,
+}
+
+impl ServerOptions {
+    /// Creates new `ServerOptions` without bandwidth limits and timeouts.
+    pub fn new() -> Self {
+        ServerOptions::default()
+    }
+
+    /// Limits the bandwidth of every connection, see `bind_server_with_throttle`.
+    pub fn with_throttle(mut self, throttle: ThrottleConfig) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Closes HTTP/1 connections which do not send the complete headers of a request within
+    /// `timeout`. The time waiting for the next request on a connection kept alive counts too.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Closes connections once they were idle, without any request in progress, for `timeout`.
+    pub fn with_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Waits up to `timeout` for the open connections to complete their requests when the server
+    /// is shut down. Without a shutdown timeout, the server does not wait for them.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(feature = "http2")]
+    pub(crate) fn with_h2c(mut self) -> Self {
+        self.h2c = true;
+        self
+    }
+
+    /// Returns the bandwidth limits of every connection.
+    pub fn throttle(&self) -> Option<ThrottleConfig> {
+        self.throttle
+    }
+
+    /// Returns the timeout for reading the headers of a request.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// Returns the timeout after which idle connections are closed.
+    pub fn keep_alive_timeout(&self) -> Option<Duration> {
+        self.keep_alive_timeout
+    }
+
+    /// Returns the time to wait for open connections when the server is shut down.
+    pub fn shutdown_timeout(&self) -> Option<Duration> {
+        self.shutdown_timeout
+    }
+}
+
+/// Returns a `Future` used to spawn a Gotham application with the given `ServerOptions`, which
+/// completes once the server was shut down.
+///
+/// This behaves like `bind_server`, until `signal` completes. The server then stops accepting
+/// connections, and lets the open connections complete the requests in progress before closing
+/// them, waiting up to the shutdown timeout of the `ServerOptions` for them.
+pub async fn bind_server_with_options<NH, F, Wrapped, Wrap, Sig>(
+    listener: TcpListener,
+    new_handler: NH,
+    wrap: Wrap,
+    options: ServerOptions,
+    signal: Sig,
+) where
+    NH: NewHandler + 'static,
+    F: Future<Output = Result<Wrapped, ()>> + Unpin + Send + 'static,
+    Wrapped: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    Wrap: Fn(TcpStream) -> F,
+    Sig: Future<Output = ()>,
+{
+    serve_connections(listener, new_handler, wrap, options, signal).await
+}
+
+async fn serve_connections<NH, F, Wrapped, Wrap, Sig>(
+    listener: TcpListener,
+    new_handler: NH,
+    wrap: Wrap,
+    options: ServerOptions,
+    signal: Sig,
+) where
+    NH: NewHandler + 'static,
+    F: Future<Output = Result<Wrapped, ()>> + Unpin + Send + 'static,
+    Wrapped: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    Wrap: Fn(TcpStream) -> F,
+    Sig: Future<Output = ()>,
+{
+    let mut protocol = Http::new();
+    if let Some(timeout) = options.read_timeout {
+        protocol.http1_header_read_timeout(timeout);
+    }
+    let protocol = Arc::new(protocol);
     let gotham_service = GothamService::new(new_handler);
+    // every connection holds a receiver, so the sender is closed once all of them completed
+    let (shutdown, shutdown_rx) = watch::channel(false);
+
+    // stop accepting connections once the signal completes
+    {
+        let accept = async move {
+            loop {
+                let (socket, addr) = match listener.accept().await {
+                    Ok(ok) => ok,
+                    Err(err) => {
+                        log::error!("Socket Error: {}", err);
+                        continue;
+                    }
+                };
+
+                let accepted_protocol = protocol.clone();
+                let wrapper = wrap(socket);
+                let shutdown = shutdown_rx.clone();
+
+                match options.throttle {
+                    Some(config) => {
+                        let connection_throttle = ConnectionThrottle::new(config);
+                        let service = gotham_service
+                            .connect(addr)
+                            .with_throttle(connection_throttle.clone());
 
-    loop {
-        let (socket, addr) = match listener.accept().await {
-            Ok(ok) => ok,
-            Err(err) => {
-                log::error!("Socket Error: {}", err);
-                continue;
+                        tokio::spawn(async move {
+                            let socket = Throttled::new(wrapper.await?, connection_throttle);
+                            serve_connection(&accepted_protocol, socket, service, options, shutdown)
+                                .await
+                        });
+                    }
+                    None => {
+                        let service = gotham_service.connect(addr);
+
+                        tokio::spawn(async move {
+                            let socket = wrapper.await?;
+                            serve_connection(&accepted_protocol, socket, service, options, shutdown)
+                                .await
+                        });
+                    }
+                }
             }
         };
+        futures_util::pin_mut!(accept, signal);
+        future::select(accept, signal).await;
+    }
 
-        let service = gotham_service.connect(addr);
-        let accepted_protocol = protocol.clone();
-        let wrapper = wrap(socket);
-
-        // NOTE: HTTP protocol errors and handshake errors are ignored here (i.e. so the socket
-        // will be dropped).
-        let task = async move {
-            let socket = wrapper.await?;
+    let _ = shutdown.send(true);
+    if let Some(timeout) = options.shutdown_timeout {
+        if tokio::time::timeout(timeout, shutdown.closed())
+            .await
+            .is_err()
+        {
+            log::warn!(
+                "connections still open after the shutdown timeout of {:?}",
+                timeout
+            );
+        }
+    }
+}
 
-            accepted_protocol
-                .serve_connection(socket, service)
-                .with_upgrades()
-                .map_err(|_| ())
-                .await?;
+// Serves the connection until the client closes it, or until it is closed gracefully once it was
+// idle for the keep-alive timeout of the `options` or the server is shut down. A connection
+// upgraded to h2c is served the same way once upgraded.
+//
+// NOTE: HTTP protocol errors and handshake errors are ignored here (i.e. so the socket will be
+// dropped).
+async fn serve_connection<IO, NH>(
+    protocol: &Http,
+    socket: IO,
+    service: service::ConnectedGothamService<NH>,
+    options: ServerOptions,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), ()>
+where
+    IO: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    NH: NewHandler + 'static,
+{
+    let keep_alive_timeout = options.keep_alive_timeout;
 
-            Result::<_, ()>::Ok(())
+    #[cfg(feature = "http2")]
+    if options.h2c {
+        let pending = service::h2c::PendingUpgrade::default();
+        let upgradable = service.clone().with_h2c(pending.clone());
+        serve_http(
+            protocol,
+            socket,
+            upgradable,
+            keep_alive_timeout,
+            &mut shutdown,
+        )
+        .await?;
+        return match pending.upgraded().await {
+            Some(Ok(io)) => {
+                serve_http(protocol, io, service, keep_alive_timeout, &mut shutdown).await
+            }
+            Some(Err(err)) => {
+                log::debug!("h2c upgrade failed: {}", err);
+                Err(())
+            }
+            None => Ok(()),
         };
+    }
+
+    serve_http(protocol, socket, service, keep_alive_timeout, &mut shutdown).await
+}
+
+async fn serve_http<IO, NH>(
+    protocol: &Http,
+    socket: IO,
+    service: service::ConnectedGothamService<NH>,
+    keep_alive_timeout: Option<Duration>,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<(), ()>
+where
+    IO: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    NH: NewHandler + 'static,
+{
+    let activity = Arc::new(ConnectionActivity::new());
+    let service = match keep_alive_timeout {
+        Some(_) => service.with_activity(activity.clone()),
+        None => service,
+    };
+    let connection = protocol.serve_connection(socket, service).with_upgrades();
+    futures_util::pin_mut!(connection);
+
+    let idle = async {
+        match keep_alive_timeout {
+            Some(timeout) => activity.idle_for(timeout).await,
+            None => future::pending().await,
+        }
+    };
+    let shut_down = async {
+        while !*shutdown.borrow() {
+            if shutdown.changed().await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+    };
+    let closing = future::select(idle.boxed(), shut_down.boxed());
+    if let Either::Left((result, _)) = future::select(connection.as_mut(), closing).await {
+        return result.map_err(|_| ());
+    }
+
+    connection.as_mut().graceful_shutdown();
+    connection.await.map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::StatusCode;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    use crate::helpers::http::response::create_empty_response;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route.get("/").to_async(|state| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let response = create_empty_response(&state, StatusCode::OK);
+                Ok((state, response))
+            })
+        })
+    }
+
+    async fn serve<Sig>(
+        options: ServerOptions,
+        signal: Sig,
+    ) -> (SocketAddr, tokio::task::JoinHandle<()>)
+    where
+        Sig: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = bind_server_with_options(listener, router(), future::ok, options, signal);
+        (addr, tokio::spawn(server))
+    }
+
+    // Reads from `stream` until the headers of a response were received, or the stream was
+    // closed, returning what was read.
+    async fn read_head(stream: &mut TcpStream) -> String {
+        let mut head = Vec::new();
+        let mut buf = [0; 1024];
+        while !head.ends_with(b"\r\n\r\n") {
+            match stream.read(&mut buf).await.unwrap() {
+                0 => break,
+                n => head.extend_from_slice(&buf[..n]),
+            }
+        }
+        String::from_utf8(head).unwrap()
+    }
+
+    async fn closed(stream: &mut TcpStream) -> bool {
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf));
+        matches!(read.await, Ok(Ok(0)))
+    }
+
+    #[tokio::test]
+    async fn closes_connections_after_read_timeout() {
+        let options = ServerOptions::new().with_read_timeout(Duration::from_millis(50));
+        let (addr, _server) = serve(options, future::pending()).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        assert!(closed(&mut stream).await);
+    }
+
+    #[tokio::test]
+    async fn closes_idle_connections_after_keep_alive_timeout() {
+        let options = ServerOptions::new().with_keep_alive_timeout(Duration::from_millis(50));
+        let (addr, _server) = serve(options, future::pending()).await;
+
+        // the connection is not idle while the request is in progress
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        assert!(read_head(&mut stream).await.starts_with("HTTP/1.1 200 OK"));
+        assert!(closed(&mut stream).await);
+    }
+
+    #[cfg(feature = "http2")]
+    #[tokio::test]
+    async fn closes_idle_h2c_connections_after_keep_alive_timeout() {
+        let options = ServerOptions::new()
+            .with_h2c()
+            .with_keep_alive_timeout(Duration::from_millis(50));
+        let (addr, _server) = serve(options, future::pending()).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\n\
+                  Host: localhost\r\n\
+                  Connection: Upgrade, HTTP2-Settings\r\n\
+                  Upgrade: h2c\r\n\
+                  HTTP2-Settings: \r\n\r\n",
+            )
+            .await
+            .unwrap();
+        assert!(read_head(&mut stream).await.starts_with("HTTP/1.1 101"));
+        stream
+            .write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0\0\x04\0\0\0\0\0")
+            .await
+            .unwrap();
+
+        // the upgraded connection is shut down gracefully, which waits for pings to be answered
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut header = [0u8; 9];
+            while stream.read_exact(&mut header).await.is_ok() {
+                let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+                let mut payload = vec![0u8; len];
+                stream.read_exact(&mut payload).await.unwrap();
+                if header[3] == 0x6 && header[4] == 0 {
+                    let mut pong = vec![0, 0, 8, 0x6, 0x1, 0, 0, 0, 0];
+                    pong.extend_from_slice(&payload);
+                    let _ = stream.write_all(&pong).await;
+                }
+            }
+        });
+        assert!(closed.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn completes_requests_on_shutdown() {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let options = ServerOptions::new().with_shutdown_timeout(Duration::from_secs(5));
+        let (addr, server) = serve(options, stopped.map(|_| ())).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stop.send(()).unwrap();
 
-        tokio::spawn(task);
+        assert!(read_head(&mut stream).await.starts_with("HTTP/1.1 200 OK"));
+        assert!(closed(&mut stream).await);
+        server.await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
     }
 }
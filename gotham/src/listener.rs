@@ -0,0 +1,154 @@
+//! Explicit control over how the listening socket of a Gotham server is bound.
+//!
+//! Binding to the unspecified IPv6 address (`[::]`) behaves differently across platforms: on most
+//! Linux systems the socket also accepts IPv4 connections, whereas on Windows and the BSDs it only
+//! accepts IPv6 by default. `BindOptions` makes the `IPV6_V6ONLY` socket option explicit, so the
+//! same configuration yields the same behaviour everywhere.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use gotham::state::State;
+//! # use hyper::{Body, Response};
+//! use gotham::listener::{dual_stack_addr, BindOptions};
+//!
+//! # fn handler(_state: State) -> (State, Response<Body>) {
+//! #   unimplemented!()
+//! # }
+//! #
+//! # fn main() {
+//! // accept both IPv4 and IPv6 connections on port 7878
+//! gotham::start_with_options(dual_stack_addr(7878), || Ok(handler), BindOptions::dual_stack())
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+
+// Same backlog as used by `tokio::net::TcpListener::bind`.
+const DEFAULT_BACKLOG: i32 = 1024;
+
+/// Options applied to the listening socket before it is bound.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BindOptions {
+    only_v6: Option<bool>,
+    backlog: i32,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        BindOptions {
+            only_v6: None,
+            backlog: DEFAULT_BACKLOG,
+        }
+    }
+}
+
+impl BindOptions {
+    /// Creates new `BindOptions`, leaving all socket options at the platform defaults.
+    pub fn new() -> Self {
+        BindOptions::default()
+    }
+
+    /// Options for a socket accepting both IPv4 and IPv6 connections when bound to an IPv6
+    /// address, i.e. `IPV6_V6ONLY` is explicitly disabled.
+    pub fn dual_stack() -> Self {
+        BindOptions::new().only_v6(false)
+    }
+
+    /// Sets the `IPV6_V6ONLY` option. This is ignored when binding to an IPv4 address.
+    pub fn only_v6(mut self, only_v6: bool) -> Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// Sets the maximum number of pending connections (defaults to 1024).
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+}
+
+/// Returns the unspecified IPv6 address with the given port, which combined with
+/// `BindOptions::dual_stack` listens on all IPv4 and IPv6 interfaces.
+pub fn dual_stack_addr(port: u16) -> SocketAddr {
+    SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))
+}
+
+/// Binds a `TcpListener` to the first address `addr` resolves to, applying the given options.
+pub async fn bind<A>(addr: A, options: BindOptions) -> io::Result<TcpListener>
+where
+    A: ToSocketAddrs,
+{
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other("unable to resolve listener address"))?;
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if let (SocketAddr::V6(_), Some(only_v6)) = (addr, options.only_v6) {
+        socket.set_only_v6(only_v6)?;
+    }
+    // mirrors `tokio::net::TcpListener::bind`, which allows rebinding a port in TIME_WAIT
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(options.backlog)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn only_v6_is_ignored_for_ipv4() {
+        let listener = bind("127.0.0.1:0", BindOptions::new().only_v6(true))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.is_ipv4());
+
+        let (connected, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        connected.unwrap();
+        accepted.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dual_stack_accepts_ipv4() {
+        // IPv6 may be unavailable in the environment running the tests
+        let listener = match bind(dual_stack_addr(0), BindOptions::dual_stack()).await {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        let (connected, accepted) = tokio::join!(
+            TcpStream::connect((Ipv4Addr::LOCALHOST, port)),
+            listener.accept()
+        );
+        connected.unwrap();
+        accepted.unwrap();
+    }
+
+    #[tokio::test]
+    async fn v6_only_rejects_ipv4() {
+        let listener = match bind(dual_stack_addr(0), BindOptions::new().only_v6(true)).await {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .is_err());
+    }
+}
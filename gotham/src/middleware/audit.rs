@@ -0,0 +1,472 @@
+//! Recording an audit trail of requests changing data.
+//!
+//! The `AuditMiddleware` records an `AuditEvent` for every request with a mutating method (`POST`,
+//! `PUT`, `PATCH` and `DELETE` by default): who made it, to which route and resource, and how it
+//! ended. Events are passed to an `AuditSink`, which can write them to a log, a database or a
+//! message queue.
+//!
+//! An event is recorded for every audited request, including requests whose handler failed and
+//! requests which never completed, e.g. because the client disconnected, a `TimeoutMiddleware`
+//! cancelled the handler, or the handler panicked. These are recorded with the
+//! `AuditOutcome::Aborted` outcome when the request is dropped.
+use std::fmt;
+use std::net::SocketAddr;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use futures_util::future::FutureExt;
+use hyper::{Method, StatusCode, Uri};
+use log::info;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{client_addr, request_id, FromState, State};
+
+/// How an audited request ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuditOutcome {
+    /// The request was answered with a response of the given status.
+    Completed(StatusCode),
+    /// The handler or a middleware failed with a `HandlerError`.
+    Failed {
+        /// The status of the error response.
+        status: StatusCode,
+        /// The cause of the error.
+        error: String,
+    },
+    /// The request was dropped before it completed.
+    Aborted,
+}
+
+impl AuditOutcome {
+    /// Returns whether the request completed with a `2xx` or `3xx` response.
+    pub fn is_success(&self) -> bool {
+        match self {
+            AuditOutcome::Completed(status) => status.is_success() || status.is_redirection(),
+            _ => false,
+        }
+    }
+
+    /// Returns the status of the response, unless the request was aborted.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            AuditOutcome::Completed(status) | AuditOutcome::Failed { status, .. } => Some(*status),
+            AuditOutcome::Aborted => None,
+        }
+    }
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOutcome::Completed(status) => write!(f, "{}", status.as_u16()),
+            AuditOutcome::Failed { status, error } => {
+                write!(f, "{} ({})", status.as_u16(), error)
+            }
+            AuditOutcome::Aborted => f.write_str("aborted"),
+        }
+    }
+}
+
+/// The record of an audited request.
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    request_id: String,
+    timestamp: SystemTime,
+    method: Method,
+    path: String,
+    route: Option<String>,
+    principal: Option<String>,
+    resource: Option<String>,
+    client_addr: Option<SocketAddr>,
+    outcome: AuditOutcome,
+}
+
+impl AuditEvent {
+    /// Returns the ID of the request.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Returns the time the request was received at.
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Returns the method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the path of the request.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the name of the route set with `AuditMiddleware::with_route`.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+
+    /// Returns the user or client who made the request.
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    /// Returns the ID of the resource the request acted on.
+    pub fn resource(&self) -> Option<&str> {
+        self.resource.as_deref()
+    }
+
+    /// Returns the address of the client.
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        self.client_addr
+    }
+
+    /// Returns how the request ended.
+    pub fn outcome(&self) -> &AuditOutcome {
+        &self.outcome
+    }
+}
+
+/// An `AuditSink` receives the events recorded by `AuditMiddleware`.
+///
+/// Events are recorded when a request completes, or when it is dropped, so `record` can't wait
+/// for I/O. Sinks writing to a database or message queue should hand events to a background task,
+/// e.g. through a channel.
+///
+/// Functions and closures taking an `AuditEvent` can be used as `AuditSink`.
+pub trait AuditSink: Send + Sync + RefUnwindSafe {
+    /// Records an event.
+    fn record(&self, event: AuditEvent);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(AuditEvent) + Send + Sync + RefUnwindSafe,
+{
+    fn record(&self, event: AuditEvent) {
+        self(event)
+    }
+}
+
+/// An `AuditSink` writing events to the log at the info level, with the target `gotham::audit`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+impl AuditSink for LogSink {
+    fn record(&self, event: AuditEvent) {
+        info!(
+            target: "gotham::audit",
+            "[{}] {} {} route={} principal={} resource={} outcome={}",
+            event.request_id,
+            event.method,
+            event.path,
+            event.route.as_deref().unwrap_or("-"),
+            event.principal.as_deref().unwrap_or("-"),
+            event.resource.as_deref().unwrap_or("-"),
+            event.outcome
+        );
+    }
+}
+
+type Extract = dyn Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe;
+
+/// A `Middleware` which records an `AuditEvent` for every request with a mutating method.
+///
+/// The principal defaults to the ID of the `AuthenticatedUser`, and is looked up again once the
+/// request completed, so authentication middleware may come after the `AuditMiddleware` in the
+/// pipeline. The resource is extracted with the function set with `with_resource` before the
+/// request is passed on, while path parameters are still in `State`. Pipelines of different
+/// routes can use differently configured `AuditMiddleware`.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// use gotham::hyper::Uri;
+/// use gotham::middleware::audit::{AuditMiddleware, LogSink};
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let audit = AuditMiddleware::new(LogSink)
+///     .with_route("documents.update")
+///     .with_resource(|state| {
+///         let path = Uri::borrow_from(state).path();
+///         path.rsplit('/').next().map(str::to_owned)
+///     });
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(audit).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.put("/documents/:id").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AuditMiddleware {
+    sink: Arc<dyn AuditSink>,
+    methods: Vec<Method>,
+    route: Option<String>,
+    principal: Arc<Extract>,
+    resource: Option<Arc<Extract>>,
+}
+
+impl AuditMiddleware {
+    /// Creates a new `AuditMiddleware` recording `POST`, `PUT`, `PATCH` and `DELETE` requests to
+    /// `sink`.
+    pub fn new<S: AuditSink + 'static>(sink: S) -> Self {
+        AuditMiddleware {
+            sink: Arc::new(sink),
+            methods: vec![Method::POST, Method::PUT, Method::PATCH, Method::DELETE],
+            route: None,
+            principal: Arc::new(|state| {
+                AuthenticatedUser::try_borrow_from(state).map(|user| user.id().to_owned())
+            }),
+            resource: None,
+        }
+    }
+
+    /// Sets the methods of the requests which are recorded.
+    pub fn with_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Sets the name of the route recorded in events, like `/documents/:id` or
+    /// `documents.update`.
+    pub fn with_route<S: Into<String>>(mut self, route: S) -> Self {
+        self.route = Some(route.into());
+        self
+    }
+
+    /// Sets the function returning the user or client who made a request.
+    pub fn with_principal<F>(mut self, principal: F) -> Self
+    where
+        F: Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.principal = Arc::new(principal);
+        self
+    }
+
+    /// Sets the function returning the ID of the resource a request acts on.
+    pub fn with_resource<F>(mut self, resource: F) -> Self
+    where
+        F: Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.resource = Some(Arc::new(resource));
+        self
+    }
+}
+
+impl fmt::Debug for AuditMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditMiddleware")
+            .field("methods", &self.methods)
+            .field("route", &self.route)
+            .finish()
+    }
+}
+
+impl NewMiddleware for AuditMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+// Records the event when dropped, so requests which never complete are recorded as aborted.
+struct PendingEvent {
+    sink: Arc<dyn AuditSink>,
+    event: Option<AuditEvent>,
+}
+
+impl Drop for PendingEvent {
+    fn drop(&mut self) {
+        if let Some(event) = self.event.take() {
+            self.sink.record(event);
+        }
+    }
+}
+
+impl Middleware for AuditMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let method = Method::borrow_from(&state);
+        if !self.methods.contains(method) {
+            return chain(state);
+        }
+
+        let event = AuditEvent {
+            request_id: request_id(&state).to_owned(),
+            timestamp: SystemTime::now(),
+            method: method.clone(),
+            path: Uri::borrow_from(&state).path().to_owned(),
+            route: self.route,
+            principal: (self.principal)(&state),
+            resource: self.resource.and_then(|resource| resource(&state)),
+            client_addr: client_addr(&state),
+            outcome: AuditOutcome::Aborted,
+        };
+        let mut pending = PendingEvent {
+            sink: self.sink,
+            event: Some(event),
+        };
+        let principal = self.principal;
+
+        async move {
+            let result = chain(state).await;
+            if let Some(event) = &mut pending.event {
+                let (state, outcome) = match &result {
+                    Ok((state, response)) => (state, AuditOutcome::Completed(response.status())),
+                    Err((state, err)) => (
+                        state,
+                        AuditOutcome::Failed {
+                            status: err.status(),
+                            error: err.cause().to_string(),
+                        },
+                    ),
+                };
+                if let Some(principal) = principal(state) {
+                    event.principal = Some(principal);
+                }
+                event.outcome = outcome;
+            }
+            drop(pending);
+            result
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::{HandlerError, HandlerResult};
+    use crate::helpers::http::response::create_empty_response;
+    use crate::middleware::timeout::TimeoutMiddleware;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::{Body, Response};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn recorder() -> (Arc<Mutex<Vec<AuditEvent>>>, impl AuditSink) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let events = events.clone();
+            move |event: AuditEvent| events.lock().unwrap().push(event)
+        };
+        (events, sink)
+    }
+
+    fn update(mut state: State) -> (State, Response<Body>) {
+        state.put(AuthenticatedUser::new("alice"));
+        let response = create_empty_response(&state, StatusCode::NO_CONTENT);
+        (state, response)
+    }
+
+    async fn fail(state: State) -> HandlerResult {
+        let err = HandlerError::from(anyhow::anyhow!("conflict")).with_status(StatusCode::CONFLICT);
+        Err((state, err))
+    }
+
+    async fn slow(state: State) -> HandlerResult {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok((state, Response::new(Body::empty())))
+    }
+
+    #[test]
+    fn records_mutating_requests() {
+        let (events, sink) = recorder();
+        let audit = AuditMiddleware::new(sink)
+            .with_route("documents.update")
+            .with_resource(|state| {
+                let path = Uri::borrow_from(state).path();
+                path.rsplit('/').next().map(str::to_owned)
+            });
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(audit).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/documents/:id").to(update);
+            route.put("/documents/:id").to(update);
+            route.delete("/documents/:id").to_async(fail);
+        }))
+        .unwrap();
+
+        let client = test_server.client();
+        client
+            .get("http://localhost/documents/7")
+            .perform()
+            .unwrap();
+        client
+            .put("http://localhost/documents/7", "", mime::TEXT_PLAIN)
+            .perform()
+            .unwrap();
+        let response = client
+            .delete("http://localhost/documents/8")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(*events[0].method(), Method::PUT);
+        assert_eq!(events[0].route(), Some("documents.update"));
+        assert_eq!(events[0].principal(), Some("alice"));
+        assert_eq!(events[0].resource(), Some("7"));
+        assert_eq!(
+            events[0].outcome(),
+            &AuditOutcome::Completed(StatusCode::NO_CONTENT)
+        );
+        assert!(events[0].outcome().is_success());
+
+        assert_eq!(*events[1].method(), Method::DELETE);
+        assert_eq!(events[1].principal(), None);
+        assert_eq!(events[1].resource(), Some("8"));
+        assert_eq!(
+            events[1].outcome(),
+            &AuditOutcome::Failed {
+                status: StatusCode::CONFLICT,
+                error: "conflict".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn records_aborted_requests() {
+        let (events, sink) = recorder();
+        let (chain, pipelines) = single_pipeline(
+            new_pipeline()
+                .add(TimeoutMiddleware::new(Duration::from_millis(20)))
+                .add(AuditMiddleware::new(sink))
+                .build(),
+        );
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.post("/").to_async(slow);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .post("http://localhost/", "", mime::TEXT_PLAIN)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].outcome(), &AuditOutcome::Aborted);
+        assert_eq!(events[0].outcome().status(), None);
+    }
+}
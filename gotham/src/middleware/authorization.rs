@@ -0,0 +1,444 @@
+//! Declarative authorization of requests.
+//!
+//! A `Policy` declares the roles and permissions a request must have, and an
+//! `AuthorizationMiddleware` checks it before the handler runs, with a `PolicyEvaluator`. Requests
+//! without an authenticated principal are answered with `401 Unauthorized`, and requests whose
+//! principal doesn't meet the policy with `403 Forbidden`, so every protected route responds the
+//! same way.
+//!
+//! The middleware is added to the pipeline of the routes or scopes it protects, so each of them
+//! declares its own policy. The `PrincipalEvaluator` checks policies against a `Principal` placed
+//! into `State` by an authentication middleware, while applications with other rules, like ones
+//! loaded from a database, implement `PolicyEvaluator` themselves. Plain functions and closures
+//! are evaluators which decide synchronously.
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::{self, FutureExt};
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
+use hyper::StatusCode;
+use log::{debug, error};
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, State, StateData};
+
+/// The roles and permissions required to access a route.
+///
+/// A request must have every role added with `with_role`, at least one of the roles added with
+/// `with_any_role`, and every permission added with `with_permission`. An empty policy only
+/// requires the request to be authenticated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    roles: Vec<String>,
+    any_roles: Vec<String>,
+    permissions: Vec<String>,
+}
+
+impl Policy {
+    /// Creates a new `Policy`, which only requires the request to be authenticated.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the given role.
+    pub fn with_role<R: Into<String>>(mut self, role: R) -> Self {
+        self.roles.push(role.into());
+        self
+    }
+
+    /// Requires at least one of the given roles, in addition to the roles required so far.
+    pub fn with_any_role(mut self, roles: &[&str]) -> Self {
+        self.any_roles
+            .extend(roles.iter().map(|role| (*role).to_owned()));
+        self
+    }
+
+    /// Requires the given permission.
+    pub fn with_permission<P: Into<String>>(mut self, permission: P) -> Self {
+        self.permissions.push(permission.into());
+        self
+    }
+
+    /// Returns the roles which are all required.
+    pub fn roles(&self) -> &[String] {
+        &self.roles
+    }
+
+    /// Returns the roles of which one is required, if any.
+    pub fn any_roles(&self) -> &[String] {
+        &self.any_roles
+    }
+
+    /// Returns the permissions which are all required.
+    pub fn permissions(&self) -> &[String] {
+        &self.permissions
+    }
+
+    /// Checks whether a principal with the given roles and permissions meets the policy.
+    pub fn is_met_by<P: Principal + ?Sized>(&self, principal: &P) -> bool {
+        self.roles.iter().all(|role| principal.has_role(role))
+            && (self.any_roles.is_empty()
+                || self.any_roles.iter().any(|role| principal.has_role(role)))
+            && self
+                .permissions
+                .iter()
+                .all(|permission| principal.has_permission(permission))
+    }
+}
+
+/// The outcome of evaluating a `Policy` for a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Decision {
+    /// The request meets the policy, and is passed on.
+    Allow,
+    /// The request is not authenticated, and is answered with `401 Unauthorized`.
+    Unauthenticated,
+    /// The request is authenticated, but doesn't meet the policy, and is answered with
+    /// `403 Forbidden`.
+    Forbidden,
+}
+
+/// An authenticated principal, like a user or a service, as placed into `State` by an
+/// authentication middleware.
+pub trait Principal: StateData {
+    /// Checks whether the principal has the given role.
+    fn has_role(&self, role: &str) -> bool;
+
+    /// Checks whether the principal has the given permission.
+    fn has_permission(&self, permission: &str) -> bool;
+}
+
+/// Type alias for the trait objects returned by `PolicyEvaluator`.
+pub type EvaluateFuture = dyn Future<Output = anyhow::Result<Decision>> + Send;
+
+/// A `PolicyEvaluator` decides whether a request meets a `Policy`.
+///
+/// The returned future can't borrow the `State`, so data needed to decide asynchronously must be
+/// taken from the `State` before creating it. Functions and closures taking the `State` and the
+/// `Policy` and returning a `Decision` are evaluators which decide synchronously.
+pub trait PolicyEvaluator: Send + Sync + RefUnwindSafe {
+    /// Evaluates `policy` for the request of `state`.
+    fn evaluate(&self, state: &State, policy: &Policy) -> Pin<Box<EvaluateFuture>>;
+}
+
+impl<F> PolicyEvaluator for F
+where
+    F: Fn(&State, &Policy) -> Decision + Send + Sync + RefUnwindSafe,
+{
+    fn evaluate(&self, state: &State, policy: &Policy) -> Pin<Box<EvaluateFuture>> {
+        future::ok(self(state, policy)).boxed()
+    }
+}
+
+/// A `PolicyEvaluator` checking policies against the `Principal` of type `P` in `State`. Requests
+/// without a `P` are unauthenticated.
+pub struct PrincipalEvaluator<P> {
+    _principal: PhantomData<fn() -> P>,
+}
+
+impl<P: Principal> PrincipalEvaluator<P> {
+    /// Creates a new `PrincipalEvaluator`.
+    pub fn new() -> Self {
+        PrincipalEvaluator {
+            _principal: PhantomData,
+        }
+    }
+}
+
+impl<P: Principal> Default for PrincipalEvaluator<P> {
+    fn default() -> Self {
+        PrincipalEvaluator::new()
+    }
+}
+
+impl<P: Principal> PolicyEvaluator for PrincipalEvaluator<P> {
+    fn evaluate(&self, state: &State, policy: &Policy) -> Pin<Box<EvaluateFuture>> {
+        let decision = match state.try_borrow::<P>() {
+            None => Decision::Unauthenticated,
+            Some(principal) if policy.is_met_by(principal) => Decision::Allow,
+            Some(_) => Decision::Forbidden,
+        };
+        future::ok(decision).boxed()
+    }
+}
+
+/// A `Middleware` which checks a `Policy` before passing the request on, answering requests which
+/// don't meet it with `401 Unauthorized` or `403 Forbidden`. If the evaluator fails, the request
+/// is answered with `500 Internal Server Error`.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::authorization::{AuthorizationMiddleware, Policy, Principal};
+/// use gotham::state::StateData;
+///
+/// struct User {
+///     roles: Vec<String>,
+/// }
+///
+/// impl StateData for User {}
+///
+/// impl Principal for User {
+///     fn has_role(&self, role: &str) -> bool {
+///         self.roles.iter().any(|r| r == role)
+///     }
+///
+///     fn has_permission(&self, _permission: &str) -> bool {
+///         false
+///     }
+/// }
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let admins = AuthorizationMiddleware::for_principal::<User>(Policy::new().with_role("admin"));
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(admins).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/admin").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AuthorizationMiddleware {
+    policy: Arc<Policy>,
+    evaluator: Arc<dyn PolicyEvaluator>,
+    challenge: Option<HeaderValue>,
+}
+
+impl AuthorizationMiddleware {
+    /// Creates a new `AuthorizationMiddleware` checking `policy` with `evaluator`.
+    pub fn new<E: PolicyEvaluator + 'static>(policy: Policy, evaluator: E) -> Self {
+        AuthorizationMiddleware {
+            policy: Arc::new(policy),
+            evaluator: Arc::new(evaluator),
+            challenge: None,
+        }
+    }
+
+    /// Creates a new `AuthorizationMiddleware` checking `policy` against the `Principal` of type
+    /// `P` in `State`.
+    pub fn for_principal<P: Principal>(policy: Policy) -> Self {
+        AuthorizationMiddleware::new(policy, PrincipalEvaluator::<P>::new())
+    }
+
+    /// Sets the `WWW-Authenticate` header of `401 Unauthorized` responses, like
+    /// `Bearer realm="api"`, which tells clients how to authenticate.
+    pub fn with_challenge(mut self, challenge: &'static str) -> Self {
+        self.challenge = Some(HeaderValue::from_static(challenge));
+        self
+    }
+}
+
+impl fmt::Debug for AuthorizationMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthorizationMiddleware")
+            .field("policy", &self.policy)
+            .field("challenge", &self.challenge)
+            .finish()
+    }
+}
+
+impl NewMiddleware for AuthorizationMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for AuthorizationMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let decision = self.evaluator.evaluate(&state, &self.policy);
+        async move {
+            let status = match decision.await {
+                Ok(Decision::Allow) => return chain(state).await,
+                Ok(Decision::Unauthenticated) => StatusCode::UNAUTHORIZED,
+                Ok(Decision::Forbidden) => StatusCode::FORBIDDEN,
+                Err(err) => {
+                    error!(
+                        "[{}] failed to evaluate authorization policy: {:#}",
+                        request_id(&state),
+                        err
+                    );
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            debug!(
+                "[{}] request denied with {} by {:?}",
+                request_id(&state),
+                status,
+                self.policy
+            );
+            let mut response = create_empty_response(&state, status);
+            if let (StatusCode::UNAUTHORIZED, Some(challenge)) = (status, self.challenge) {
+                response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+            }
+            Ok((state, response))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::HeaderMap;
+
+    struct User {
+        roles: &'static [&'static str],
+        permissions: &'static [&'static str],
+    }
+
+    impl StateData for User {}
+
+    impl Principal for User {
+        fn has_role(&self, role: &str) -> bool {
+            self.roles.contains(&role)
+        }
+
+        fn has_permission(&self, permission: &str) -> bool {
+            self.permissions.contains(&permission)
+        }
+    }
+
+    // Authenticates requests with an `x-user` header as `editor` or `viewer`.
+    #[derive(Clone)]
+    struct Authenticate;
+
+    impl NewMiddleware for Authenticate {
+        type Instance = Self;
+
+        fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+            Ok(self.clone())
+        }
+    }
+
+    impl Middleware for Authenticate {
+        fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+        where
+            Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+        {
+            let user = match state.borrow::<HeaderMap>().get("x-user") {
+                Some(name) if name == "editor" => Some(User {
+                    roles: &["staff", "editor"],
+                    permissions: &["posts:write"],
+                }),
+                Some(_) => Some(User {
+                    roles: &["viewer"],
+                    permissions: &[],
+                }),
+                None => None,
+            };
+            if let Some(user) = user {
+                state.put(user);
+            }
+            chain(state)
+        }
+    }
+
+    fn handler(state: State) -> (State, &'static str) {
+        (state, "allowed")
+    }
+
+    #[test]
+    fn evaluates_policies() {
+        let editor = User {
+            roles: &["staff", "editor"],
+            permissions: &["posts:write"],
+        };
+        assert!(Policy::new().is_met_by(&editor));
+        assert!(Policy::new()
+            .with_role("staff")
+            .with_any_role(&["admin", "editor"])
+            .with_permission("posts:write")
+            .is_met_by(&editor));
+        assert!(!Policy::new().with_role("admin").is_met_by(&editor));
+        assert!(!Policy::new()
+            .with_any_role(&["admin", "owner"])
+            .is_met_by(&editor));
+        assert!(!Policy::new()
+            .with_permission("posts:delete")
+            .is_met_by(&editor));
+    }
+
+    #[test]
+    fn answers_denied_requests() {
+        let policy = Policy::new().with_permission("posts:write");
+        let authorization =
+            AuthorizationMiddleware::for_principal::<User>(policy).with_challenge("Bearer");
+        let (chain, pipelines) =
+            single_pipeline(new_pipeline().add(Authenticate).add(authorization).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        }))
+        .unwrap();
+        let get = |user: Option<&str>| {
+            let client = test_server.client();
+            let mut request = client.get("http://localhost/");
+            if let Some(user) = user {
+                request = request.with_header("x-user", user.parse().unwrap());
+            }
+            request.perform().unwrap()
+        };
+
+        let response = get(None);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+
+        let response = get(Some("viewer"));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response.headers().get(WWW_AUTHENTICATE).is_none());
+
+        let response = get(Some("editor"));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "allowed");
+    }
+
+    #[test]
+    fn uses_custom_evaluators() {
+        let evaluator = |state: &State, _: &Policy| match state.borrow::<HeaderMap>().get("x-key") {
+            Some(key) if key == "secret" => Decision::Allow,
+            Some(_) => Decision::Forbidden,
+            None => Decision::Unauthenticated,
+        };
+        let authorization = AuthorizationMiddleware::new(Policy::new(), evaluator);
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(authorization).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header("x-key", "secret".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header("x-key", "guess".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
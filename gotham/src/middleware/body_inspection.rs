@@ -0,0 +1,439 @@
+//! Middleware for debugging the bodies of requests and responses, available with the
+//! `body-inspection` feature.
+//!
+//! Integration issues with clients are often caused by the exact content of a request or
+//! response, which is hard to observe in a deployed service. The `BodyInspector` copies the
+//! request and response bodies, up to a size cap, as they are streamed, and logs them or records
+//! them in an `InspectionBuffer`, which can be served on an admin endpoint.
+//!
+//! Bodies may contain credentials and personal data, so this middleware is intended for
+//! development and should not be enabled in production.
+use bytes::Bytes;
+use futures_util::future::{self, FutureExt, TryFutureExt};
+use futures_util::stream::Stream;
+use hyper::{Body, Method, Response, StatusCode, Uri};
+use log::{log, log_enabled, Level};
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::handler::{Handler, HandlerFuture, NewHandler};
+use crate::helpers::buffer::{self, PooledBuffer};
+use crate::helpers::http::response::create_response;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, FromState, State};
+
+const DEFAULT_SIZE_CAP: usize = 4096;
+
+/// A captured body, cut off at the size cap of the `BodyInspector`.
+#[derive(Clone, Debug, Default)]
+pub struct CapturedBody {
+    /// The captured bytes of the body.
+    pub bytes: Bytes,
+    /// Whether the body was longer than the size cap.
+    pub truncated: bool,
+}
+
+impl fmt::Display for CapturedBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.bytes))?;
+        if self.truncated {
+            f.write_str("... (truncated)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A request and its response, as recorded by the `BodyInspector`.
+#[derive(Clone, Debug)]
+pub struct Exchange {
+    /// The ID of the request.
+    pub request_id: String,
+    /// The method of the request.
+    pub method: Method,
+    /// The URI of the request.
+    pub uri: Uri,
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The request body, or `None` if the handler did not read it.
+    pub request_body: Option<CapturedBody>,
+    /// The response body.
+    pub response_body: CapturedBody,
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "[{}] {} {} -> {}",
+            self.request_id, self.method, self.uri, self.status
+        )?;
+        match &self.request_body {
+            Some(body) => writeln!(f, "request body: {}", body)?,
+            None => writeln!(f, "request body: (not read)")?,
+        }
+        write!(f, "response body: {}", self.response_body)
+    }
+}
+
+/// A ring buffer holding the most recent exchanges recorded by a `BodyInspector`.
+///
+/// `InspectionBuffer` is also a `Handler`, which responds with the recorded exchanges as plain
+/// text, most recent first, so it can be mounted on an admin endpoint.
+#[derive(Clone)]
+pub struct InspectionBuffer {
+    capacity: usize,
+    exchanges: Arc<Mutex<VecDeque<Exchange>>>,
+}
+
+impl InspectionBuffer {
+    /// Creates a new `InspectionBuffer` holding up to `capacity` exchanges.
+    pub fn new(capacity: usize) -> Self {
+        InspectionBuffer {
+            capacity,
+            exchanges: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Returns the recorded exchanges, most recent first.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges.iter().rev().cloned().collect()
+    }
+
+    /// Removes all recorded exchanges.
+    pub fn clear(&self) {
+        self.exchanges.lock().unwrap().clear();
+    }
+
+    fn push(&self, exchange: Exchange) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut exchanges = self.exchanges.lock().unwrap();
+        if exchanges.len() == self.capacity {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+}
+
+impl NewHandler for InspectionBuffer {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for InspectionBuffer {
+    fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
+        let mut body = String::new();
+        for exchange in self.exchanges() {
+            let _ = writeln!(body, "{}\n", exchange);
+        }
+        let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN_UTF_8, body);
+        future::ok((state, response)).boxed()
+    }
+}
+
+/// Middleware which captures the bodies of requests and responses, and logs them or records them
+/// in an `InspectionBuffer`.
+///
+/// Bodies are passed on unchanged while they are streamed, and only the first bytes up to the
+/// size cap are copied. An exchange is logged or recorded once the response body was sent. The
+/// request body is only captured as far as the handler read it.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::body_inspection::{BodyInspector, InspectionBuffer};
+/// use log::Level;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let buffer = InspectionBuffer::new(100);
+/// let (chain, pipelines) = single_pipeline(
+///     new_pipeline()
+///         .add(
+///             BodyInspector::new()
+///                 .with_log_level(Level::Debug)
+///                 .with_buffer(buffer.clone()),
+///         )
+///         .build(),
+/// );
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.post("/api").to(handler);
+///     route.get("/admin/exchanges").to_new_handler(buffer);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct BodyInspector {
+    size_cap: usize,
+    level: Option<Level>,
+    buffer: Option<InspectionBuffer>,
+}
+
+impl Default for BodyInspector {
+    fn default() -> Self {
+        BodyInspector {
+            size_cap: DEFAULT_SIZE_CAP,
+            level: None,
+            buffer: None,
+        }
+    }
+}
+
+impl BodyInspector {
+    /// Constructs a new `BodyInspector`, which neither logs nor records exchanges until
+    /// configured to do so.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of bytes captured of each body (defaults to 4096).
+    pub fn with_size_cap(mut self, size_cap: usize) -> Self {
+        self.size_cap = size_cap;
+        self
+    }
+
+    /// Logs each exchange at the given level.
+    pub fn with_log_level(mut self, level: Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Records each exchange in the given buffer.
+    pub fn with_buffer(mut self, buffer: InspectionBuffer) -> Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.buffer.is_some() || self.level.map(|level| log_enabled!(level)).unwrap_or(false)
+    }
+}
+
+impl NewMiddleware for BodyInspector {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for BodyInspector {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        if !self.is_enabled() {
+            return chain(state);
+        }
+
+        let request_body = Arc::new(Mutex::new(None));
+        if let Some(body) = state.try_take::<Body>() {
+            let captured = request_body.clone();
+            let tee = TeeBody::new(body, self.size_cap, move |body| {
+                *captured.lock().unwrap() = Some(body);
+            });
+            state.put(Body::wrap_stream(tee));
+        }
+
+        let request_id = request_id(&state).to_owned();
+        let method = Method::borrow_from(&state).clone();
+        let uri = Uri::borrow_from(&state).clone();
+
+        chain(state)
+            .and_then(move |(state, response)| {
+                let status = response.status();
+                let size_cap = self.size_cap;
+                let response = map_body(response, move |body| {
+                    let tee = TeeBody::new(body, size_cap, move |response_body| {
+                        let exchange = Exchange {
+                            request_id,
+                            method,
+                            uri,
+                            status,
+                            request_body: request_body.lock().unwrap().take(),
+                            response_body,
+                        };
+                        if let Some(level) = self.level {
+                            log!(level, "{}", exchange);
+                        }
+                        if let Some(buffer) = &self.buffer {
+                            buffer.push(exchange);
+                        }
+                    });
+                    Body::wrap_stream(tee)
+                });
+                future::ok((state, response))
+            })
+            .boxed()
+    }
+}
+
+fn map_body<F>(response: Response<Body>, f: F) -> Response<Body>
+where
+    F: FnOnce(Body) -> Body,
+{
+    let (parts, body) = response.into_parts();
+    Response::from_parts(parts, f(body))
+}
+
+type OnDone = Box<dyn FnOnce(CapturedBody) + Send>;
+
+// Passes on the chunks of a body, copying them up to the size cap. The copy is handed to
+// `on_done` when the body ends, or when it is dropped before, e.g. when the client disconnects.
+struct TeeBody {
+    inner: Body,
+    size_cap: usize,
+    captured: PooledBuffer,
+    truncated: bool,
+    on_done: Option<OnDone>,
+}
+
+impl TeeBody {
+    fn new<F>(inner: Body, size_cap: usize, on_done: F) -> Self
+    where
+        F: FnOnce(CapturedBody) + Send + 'static,
+    {
+        TeeBody {
+            inner,
+            size_cap,
+            captured: buffer::acquire(cmp::min(size_cap, buffer::SIZE_CLASSES[0])),
+            truncated: false,
+            on_done: Some(Box::new(on_done)),
+        }
+    }
+
+    fn capture(&mut self, chunk: &[u8]) {
+        let remaining = self.size_cap.saturating_sub(self.captured.len());
+        if chunk.len() > remaining {
+            self.truncated = true;
+        }
+        self.captured
+            .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+    }
+
+    fn finish(&mut self) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done(CapturedBody {
+                bytes: self.captured.split().freeze(),
+                truncated: self.truncated,
+            });
+        }
+    }
+}
+
+impl Stream for TeeBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => self.capture(chunk),
+            Poll::Ready(Some(Err(_))) | Poll::Ready(None) => self.finish(),
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl Drop for TeeBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use futures_executor::block_on;
+    use futures_util::future::FutureExt;
+    use futures_util::stream::StreamExt;
+
+    fn echo(mut state: State) -> Pin<Box<HandlerFuture>> {
+        let body = state.take::<Body>();
+        async move {
+            let body = hyper::body::to_bytes(body).await.unwrap();
+            let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, body);
+            Ok((state, response))
+        }
+        .boxed()
+    }
+
+    #[test]
+    fn records_exchanges() {
+        let buffer = InspectionBuffer::new(1);
+        let inspector = BodyInspector::new()
+            .with_size_cap(5)
+            .with_buffer(buffer.clone());
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(inspector).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.post("/echo").to(echo);
+            route.get("/exchanges").to_new_handler(buffer.clone());
+        });
+        let server = TestServer::new(router).unwrap();
+
+        for body in &["hello", "hello world"] {
+            let response = server
+                .client()
+                .post("http://localhost/echo", *body, mime::TEXT_PLAIN)
+                .perform()
+                .unwrap();
+            // bodies are passed on unchanged
+            assert_eq!(response.read_utf8_body().unwrap(), *body);
+        }
+
+        // only the most recent exchange is kept
+        let exchanges = buffer.exchanges();
+        assert_eq!(exchanges.len(), 1);
+        let exchange = &exchanges[0];
+        assert_eq!(exchange.method, Method::POST);
+        assert_eq!(exchange.status, StatusCode::OK);
+        let request_body = exchange.request_body.as_ref().unwrap();
+        assert_eq!(request_body.bytes, "hello");
+        assert!(request_body.truncated);
+        assert_eq!(exchange.response_body.to_string(), "hello... (truncated)");
+
+        let response = server
+            .client()
+            .get("http://localhost/exchanges")
+            .perform()
+            .unwrap();
+        let body = response.read_utf8_body().unwrap();
+        assert!(body.contains("POST /echo -> 200 OK"));
+    }
+
+    #[test]
+    fn captures_body_when_dropped() {
+        let captured = Arc::new(Mutex::new(None));
+        let sink = captured.clone();
+        let mut tee = TeeBody::new(Body::from("abc"), 10, move |body| {
+            *sink.lock().unwrap() = Some(body);
+        });
+
+        let chunk = block_on(tee.next()).unwrap().unwrap();
+        assert_eq!(chunk, "abc");
+        assert!(captured.lock().unwrap().is_none());
+
+        drop(tee);
+        let captured = captured.lock().unwrap().take().unwrap();
+        assert_eq!(captured.bytes, "abc");
+        assert!(!captured.truncated);
+    }
+}
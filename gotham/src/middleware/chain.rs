@@ -103,7 +103,10 @@ where
         //      })
         //  }
         //
-        // The resulting function is called by `<() as MiddlewareChain>::call`
+        // The resulting function is called by `<() as MiddlewareChain>::call`. As each closure
+        // has its own type, the nested functions are monomorphized into a single call. The
+        // futures are not: `Middleware::call` returns a boxed future, so each `Middleware` and
+        // the `Handler` still allocate one per request.
         trace!("[{}] executing middleware", request_id(&state));
         p.call(state, move |state| m.call(state, f))
     }
@@ -0,0 +1,243 @@
+//! Rejecting request bodies which don't match their digest.
+//!
+//! Clients uploading artifacts can send a digest of the body in a `Content-Digest`, `Digest` or
+//! `Content-MD5` header (see `gotham::helpers::http::checksum`). The `ChecksumMiddleware` reads
+//! the body of such requests while computing its digest, answers `400 Bad Request` if they don't
+//! match, and passes the verified body on to the handler otherwise.
+//!
+//! Handlers streaming large uploads to disk should use `upload_to_file` instead, which verifies
+//! the digest without buffering the body in memory.
+use std::cmp;
+use std::pin::Pin;
+
+use futures_util::future::FutureExt;
+use hyper::body::HttpBody;
+use hyper::{Body, HeaderMap, StatusCode};
+use log::debug;
+
+use crate::handler::HandlerFuture;
+use crate::helpers::buffer;
+use crate::helpers::http::checksum::ExpectedChecksum;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, FromState, State};
+
+/// A `Middleware` which verifies the bodies of requests with a digest header, answering
+/// `400 Bad Request` if the header is invalid or the body doesn't match it, and
+/// `413 Payload Too Large` if the body is larger than the maximum size.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::checksum::ChecksumMiddleware;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let checksum = ChecksumMiddleware::new()
+///     .with_max_size(64 * 1024 * 1024)
+///     .with_required(true);
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(checksum).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.put("/artifacts/:name").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ChecksumMiddleware {
+    max_size: usize,
+    required: bool,
+}
+
+impl Default for ChecksumMiddleware {
+    fn default() -> Self {
+        ChecksumMiddleware {
+            max_size: 16 * 1024 * 1024,
+            required: false,
+        }
+    }
+}
+
+impl ChecksumMiddleware {
+    /// Creates a new `ChecksumMiddleware` verifying bodies of up to 16MiB, and passing on
+    /// requests without a digest header unchanged.
+    pub fn new() -> Self {
+        ChecksumMiddleware::default()
+    }
+
+    /// Sets the maximum size of bodies which are read to be verified.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets whether requests without a digest header of a supported algorithm are rejected with
+    /// `400 Bad Request` (defaults to `false`).
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+}
+
+impl NewMiddleware for ChecksumMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for ChecksumMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let expected = match HeaderMap::try_borrow_from(&state).map(ExpectedChecksum::from_headers)
+        {
+            Some(Ok(Some(expected))) => expected,
+            Some(Err(err)) => {
+                debug!("[{}] {}", request_id(&state), err);
+                return reject(state, err.status());
+            }
+            _ if self.required => {
+                debug!("[{}] request has no digest header", request_id(&state));
+                return reject(state, StatusCode::BAD_REQUEST);
+            }
+            _ => return chain(state),
+        };
+
+        async move {
+            let mut body = state.try_take::<Body>().unwrap_or_else(Body::empty);
+            let mut verifier = expected.verifier();
+            let mut buffered = buffer::acquire(cmp::min(self.max_size, buffer::SIZE_CLASSES[0]));
+            while let Some(chunk) = body.data().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        debug!(
+                            "[{}] unable to read request body: {}",
+                            request_id(&state),
+                            err
+                        );
+                        let response = create_empty_response(&state, StatusCode::BAD_REQUEST);
+                        return Ok((state, response));
+                    }
+                };
+                if buffered.len() + chunk.len() > self.max_size {
+                    let response = create_empty_response(&state, StatusCode::PAYLOAD_TOO_LARGE);
+                    return Ok((state, response));
+                }
+                verifier.update(&chunk);
+                buffered.extend_from_slice(&chunk);
+            }
+
+            if let Err(err) = verifier.verify() {
+                debug!("[{}] {}", request_id(&state), err);
+                let response = create_empty_response(&state, err.status());
+                return Ok((state, response));
+            }
+            state.put(Body::from(buffered.split().freeze()));
+            chain(state).await
+        }
+        .boxed()
+    }
+}
+
+fn reject(state: State, status: StatusCode) -> Pin<Box<HandlerFuture>> {
+    let response = create_empty_response(&state, status);
+    async move { Ok((state, response)) }.boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::HandlerResult;
+    use crate::helpers::http::header::{CONTENT_DIGEST, DIGEST};
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::Response;
+
+    const HELLO_SHA256: &str = "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=";
+
+    async fn echo(mut state: State) -> HandlerResult {
+        let body = hyper::body::to_bytes(Body::take_from(&mut state))
+            .await
+            .unwrap();
+        Ok((state, Response::new(Body::from(body))))
+    }
+
+    fn test_server(checksum: ChecksumMiddleware) -> TestServer {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(checksum).build());
+        TestServer::new(build_router(chain, pipelines, |route| {
+            route.put("/").to_async(echo);
+        }))
+        .unwrap()
+    }
+
+    fn put(
+        test_server: &TestServer,
+        body: &'static str,
+        header: Option<(&'static str, String)>,
+    ) -> StatusCode {
+        let client = test_server.client();
+        let mut request = client.put("http://localhost/", body, mime::TEXT_PLAIN);
+        if let Some((name, value)) = header {
+            request = request.with_header(name, value.parse().unwrap());
+        }
+        request.perform().unwrap().status()
+    }
+
+    #[test]
+    fn verifies_bodies_with_digest() {
+        let test_server = test_server(ChecksumMiddleware::new());
+        let digest = || Some((DIGEST, format!("SHA-256={}", HELLO_SHA256)));
+
+        let response = test_server
+            .client()
+            .put("http://localhost/", "hello", mime::TEXT_PLAIN)
+            .with_header(
+                CONTENT_DIGEST,
+                format!("sha-256=:{}:", HELLO_SHA256).parse().unwrap(),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "hello");
+
+        assert_eq!(
+            put(&test_server, "hullo", digest()),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            put(
+                &test_server,
+                "hello",
+                Some((DIGEST, "SHA-256=abc".to_owned()))
+            ),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(put(&test_server, "anything", None), StatusCode::OK);
+    }
+
+    #[test]
+    fn enforces_size_and_required_digest() {
+        let test_server = test_server(
+            ChecksumMiddleware::new()
+                .with_max_size(4)
+                .with_required(true),
+        );
+        let digest = Some((DIGEST, format!("SHA-256={}", HELLO_SHA256)));
+
+        assert_eq!(
+            put(&test_server, "hello", digest),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(put(&test_server, "hey", None), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,416 @@
+//! Resolving the address of clients behind proxies, and filtering requests by it.
+//!
+//! Behind a load balancer or reverse proxy, the peer of the connection reported by hyper is the
+//! proxy instead of the client, which passes the address of the client on in a `Forwarded` or
+//! `X-Forwarded-For` header. As clients can send these headers themselves, the
+//! `ClientIpMiddleware` only honors them on connections from trusted proxies, and replaces the
+//! address returned by `gotham::state::client_addr` with the one it resolved. Middleware and
+//! handlers later in the pipeline, like the rate limiter and logger, use the resolved address.
+//!
+//! The middleware can also reject requests from addresses outside an allow list, or inside a
+//! deny list, with `403 Forbidden`.
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures_util::future::{self, FutureExt};
+use hyper::header::{HeaderMap, FORWARDED};
+use hyper::StatusCode;
+use log::debug;
+use thiserror::Error;
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::header::X_FORWARDED_FOR;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::client_addr::put_client_addr;
+use crate::state::{client_addr, request_id, FromState, State};
+
+/// The error returned when parsing an `IpCidr` fails.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CidrError {
+    /// The address before the prefix length is not a valid IP address.
+    #[error("invalid IP address in CIDR block")]
+    InvalidAddress,
+    /// The prefix length is not a number, or longer than the address.
+    #[error("invalid prefix length in CIDR block")]
+    InvalidPrefix,
+}
+
+/// A block of IP addresses in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`.
+///
+/// A single address without a prefix length, e.g. `192.0.2.1`, is parsed as a block containing
+/// only that address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// Creates the block of addresses sharing the first `prefix` bits with `addr`.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, CidrError> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return Err(CidrError::InvalidPrefix);
+        }
+        Ok(IpCidr { addr, prefix })
+    }
+
+    /// Returns whether `ip` is inside this block. IPv4 addresses mapped to IPv6, as reported by
+    /// dual stack sockets, are compared as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(net).into(), u32::from(ip).into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(net), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| CidrError::InvalidAddress)?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| CidrError::InvalidPrefix)?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        IpCidr::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, prefix: u8, bits: u32) -> bool {
+    let prefix = u32::from(prefix);
+    prefix == 0 || (net ^ ip) >> (bits - prefix) == 0
+}
+
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// A `Middleware` which resolves the address of clients connecting through trusted proxies, and
+/// optionally rejects requests by the resolved address.
+///
+/// The forwarding headers are read from right to left, skipping the addresses of trusted
+/// proxies, and the first untrusted address is taken as the client. A `Forwarded` header takes
+/// precedence over `X-Forwarded-For`. Addresses from `X-Forwarded-For` carry no port, so the
+/// resolved `SocketAddr` has port `0` unless a `Forwarded` header includes it.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::client_ip::ClientIpMiddleware;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let client_ip = ClientIpMiddleware::new()
+///     .with_trusted_proxy("10.0.0.0/8".parse().unwrap())
+///     .with_denied("192.0.2.0/24".parse().unwrap());
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(client_ip).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ClientIpMiddleware {
+    trusted_proxies: Arc<Vec<IpCidr>>,
+    allowed: Arc<Vec<IpCidr>>,
+    denied: Arc<Vec<IpCidr>>,
+}
+
+impl ClientIpMiddleware {
+    /// Creates a new `ClientIpMiddleware` which trusts no proxies and allows every address.
+    pub fn new() -> Self {
+        ClientIpMiddleware::default()
+    }
+
+    /// Trusts the forwarding headers of requests from the addresses in `cidr`.
+    pub fn with_trusted_proxy(mut self, cidr: IpCidr) -> Self {
+        Arc::make_mut(&mut self.trusted_proxies).push(cidr);
+        self
+    }
+
+    /// Allows requests from the addresses in `cidr`. Once an address block is allowed, requests
+    /// from addresses outside all allowed blocks are rejected.
+    pub fn with_allowed(mut self, cidr: IpCidr) -> Self {
+        Arc::make_mut(&mut self.allowed).push(cidr);
+        self
+    }
+
+    /// Rejects requests from the addresses in `cidr`, even if they are allowed as well.
+    pub fn with_denied(mut self, cidr: IpCidr) -> Self {
+        Arc::make_mut(&mut self.denied).push(cidr);
+        self
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    fn is_permitted(&self, ip: Option<IpAddr>) -> bool {
+        match ip {
+            Some(ip) => {
+                !self.denied.iter().any(|cidr| cidr.contains(ip))
+                    && (self.allowed.is_empty()
+                        || self.allowed.iter().any(|cidr| cidr.contains(ip)))
+            }
+            None => self.allowed.is_empty(),
+        }
+    }
+
+    fn resolve(&self, peer: SocketAddr, headers: &HeaderMap) -> SocketAddr {
+        if !self.is_trusted(peer.ip()) {
+            return peer;
+        }
+
+        let mut client = peer;
+        for addr in forwarded_for(headers).into_iter().rev() {
+            match addr {
+                Some(addr) => client = addr,
+                // an obfuscated or malformed address can't be checked against the trusted proxies
+                None => break,
+            }
+            if !self.is_trusted(client.ip()) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Returns the addresses of the `for` parameters in the `Forwarded` headers, or the addresses in
+/// the `X-Forwarded-For` headers if there is no `Forwarded` header, in the order they were added
+/// by the proxies. Addresses which can't be parsed are `None`.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<SocketAddr>> {
+    if headers.contains_key(FORWARDED) {
+        headers
+            .get_all(FORWARDED)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or("").split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (name, value) = pair.split_once('=')?;
+                    if name.trim().eq_ignore_ascii_case("for") {
+                        Some(parse_node(value.trim().trim_matches('"')))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect()
+    } else {
+        headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or("").split(','))
+            .map(|addr| parse_node(addr.trim()))
+            .collect()
+    }
+}
+
+/// Parses a node of a forwarding header, which is an IP address with an optional port, and IPv6
+/// addresses in brackets if there is a port.
+fn parse_node(node: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    let ip = match node
+        .strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'))
+    {
+        Some(ip) => ip.parse::<Ipv6Addr>().ok().map(IpAddr::V6),
+        None => node.parse::<IpAddr>().ok(),
+    }?;
+    Some(SocketAddr::new(ip, 0))
+}
+
+impl NewMiddleware for ClientIpMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for ClientIpMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        if let Some(peer) = client_addr(&state) {
+            let client = match HeaderMap::try_borrow_from(&state) {
+                Some(headers) => self.resolve(peer, headers),
+                None => peer,
+            };
+            if client != peer {
+                debug!(
+                    "[{}] resolved client {} forwarded by {}",
+                    request_id(&state),
+                    client,
+                    peer
+                );
+                put_client_addr(&mut state, client);
+            }
+        }
+
+        let ip = client_addr(&state).map(|addr| addr.ip());
+        if !self.is_permitted(ip) {
+            debug!(
+                "[{}] rejecting request from {}",
+                request_id(&state),
+                ip.map_or_else(|| "unknown address".to_owned(), |ip| ip.to_string())
+            );
+            let response = create_empty_response(&state, StatusCode::FORBIDDEN);
+            return future::ok((state, response)).boxed();
+        }
+
+        chain(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::header::HeaderValue;
+    use std::net::Ipv4Addr;
+
+    fn cidr(s: &str) -> IpCidr {
+        s.parse().unwrap()
+    }
+
+    fn addr(state: State) -> (State, String) {
+        let addr = client_addr(&state).unwrap().to_string();
+        (state, addr)
+    }
+
+    fn test_server(middleware: ClientIpMiddleware) -> TestServer {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(addr);
+        }))
+        .unwrap()
+    }
+
+    fn get(test_server: &TestServer, header: Option<(&'static str, &'static str)>) -> String {
+        let client = test_server.client();
+        let mut request = client.get("http://localhost/");
+        if let Some((name, value)) = header {
+            request = request.with_header(name, HeaderValue::from_static(value));
+        }
+        let response = request.perform().unwrap();
+        match response.status() {
+            StatusCode::OK => response.read_utf8_body().unwrap(),
+            status => status.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_and_matches_cidrs() {
+        let net = cidr("10.1.0.0/16");
+        assert!(net.contains("10.1.255.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(cidr("0.0.0.0/0").contains(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(cidr("2001:db8::/32").contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!cidr("2001:db8::/32").contains("10.1.0.1".parse().unwrap()));
+        assert_eq!(cidr("192.0.2.1").to_string(), "192.0.2.1/32");
+
+        assert!(matches!(
+            "10.0.0.0/33".parse::<IpCidr>(),
+            Err(CidrError::InvalidPrefix)
+        ));
+        assert!(matches!(
+            "10.0.0/8".parse::<IpCidr>(),
+            Err(CidrError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn resolves_clients_of_trusted_proxies() {
+        let untrusted = test_server(ClientIpMiddleware::new());
+        assert!(get(&untrusted, Some((X_FORWARDED_FOR, "203.0.113.7"))).starts_with("127.0.0.1:"));
+
+        let trusted = test_server(
+            ClientIpMiddleware::new()
+                .with_trusted_proxy(cidr("127.0.0.0/8"))
+                .with_trusted_proxy(cidr("10.0.0.0/8")),
+        );
+        assert_eq!(
+            get(
+                &trusted,
+                Some((X_FORWARDED_FOR, "198.51.100.1, 203.0.113.7, 10.0.0.2"))
+            ),
+            "203.0.113.7:0"
+        );
+        assert_eq!(
+            get(
+                &trusted,
+                Some((
+                    "forwarded",
+                    "for=198.51.100.1, for=\"[2001:db8::1]:4711\";proto=https"
+                ))
+            ),
+            "[2001:db8::1]:4711"
+        );
+        // the proxy forwarding an unknown address is the closest known client
+        assert!(
+            get(&trusted, Some((X_FORWARDED_FOR, "203.0.113.7, unknown")))
+                .starts_with("127.0.0.1:")
+        );
+    }
+
+    #[test]
+    fn filters_resolved_addresses() {
+        let filtered = test_server(
+            ClientIpMiddleware::new()
+                .with_trusted_proxy(cidr("127.0.0.1"))
+                .with_allowed(cidr("203.0.113.0/24"))
+                .with_denied(cidr("203.0.113.128/25")),
+        );
+        assert_eq!(
+            get(&filtered, Some((X_FORWARDED_FOR, "203.0.113.7"))),
+            "203.0.113.7:0"
+        );
+        assert_eq!(
+            get(&filtered, Some((X_FORWARDED_FOR, "203.0.113.200"))),
+            "403 Forbidden"
+        );
+        assert_eq!(get(&filtered, None), "403 Forbidden");
+    }
+}
@@ -0,0 +1,303 @@
+//! Compression of responses, available with the `compression` feature.
+//!
+//! The `CompressionMiddleware` compresses the responses of all handlers behind it with brotli,
+//! gzip or deflate, depending on the encodings accepted by the client. Static assets are better
+//! served with `FileOptions::with_compression`, which also takes pre-compressed files into account.
+use std::io;
+use std::pin::Pin;
+
+use futures_util::future::{FutureExt, TryFutureExt};
+use futures_util::stream::TryStreamExt;
+use hyper::body::HttpBody;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING,
+    CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+};
+use hyper::{Body, Method, Response, StatusCode};
+use mime::Mime;
+
+use crate::handler::assets::accepted_encoding::{accepted_encodings, AcceptedEncoding};
+use crate::handler::assets::compression::{
+    compress_with_level, is_compressible, negotiate, Encoding, DEFAULT_LEVEL, DEFAULT_MIN_SIZE,
+    DEFAULT_TYPES,
+};
+use crate::handler::HandlerFuture;
+use crate::helpers::http::vary::add_vary;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{FromState, State};
+
+const SUPPORTED: &[Encoding] = &[Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+/// A `Middleware` which compresses responses with an encoding accepted by the client.
+///
+/// Of brotli, gzip and deflate, the encoding with the highest quality in the `Accept-Encoding`
+/// header of the request is used. Only responses with a compressible content type, which isn't
+/// already encoded and has a known length of at least the minimum size are compressed. Responses
+/// to `HEAD` requests, partial content and responses with `Cache-Control: no-transform` are left
+/// unchanged.
+///
+/// Compressible responses get `Accept-Encoding` added to their `Vary` header, even when they
+/// aren't compressed, so caches don't serve a compressed response to clients which don't accept
+/// it or vice versa. Strong entity tags of compressed responses are made weak, since the
+/// compressed body differs from the original one.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::compression::CompressionMiddleware;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let compression = CompressionMiddleware::new()
+///     .with_level(4)
+///     .with_min_size(512)
+///     .with_content_types(&["text/*", "application/json"]);
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(compression).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompressionMiddleware {
+    level: u32,
+    min_size: u64,
+    content_types: Vec<String>,
+}
+
+impl CompressionMiddleware {
+    /// Creates a new `CompressionMiddleware` with the default level, minimum size and content
+    /// types.
+    pub fn new() -> Self {
+        CompressionMiddleware {
+            level: DEFAULT_LEVEL,
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    /// Sets the compression level from 0 to 9, trading speed for size (defaults to 6).
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Sets the minimum size of responses to compress, in bytes (defaults to 1 KiB). Responses
+    /// with an unknown length, like streamed ones, are always compressed.
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Sets the content types of responses to compress, either full types like
+    /// `application/json` or types with a wildcard subtype like `text/*` (defaults to text,
+    /// JSON, JavaScript, XML and SVG).
+    pub fn with_content_types(mut self, types: &[&str]) -> Self {
+        self.content_types = types.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    // Checks whether the response may be compressed at all, ignoring the request.
+    fn is_compressible(&self, response: &Response<Body>) -> bool {
+        let status = response.status();
+        let headers = response.headers();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::PARTIAL_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || headers.contains_key(CONTENT_RANGE)
+            || headers.contains_key(CONTENT_ENCODING)
+            || has_token(headers, &CACHE_CONTROL, "no-transform")
+        {
+            return false;
+        }
+        headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Mime>().ok())
+            .is_some_and(|mime| is_compressible(&mime, &self.content_types))
+    }
+
+    fn compress(
+        &self,
+        mut response: Response<Body>,
+        accepted: &[AcceptedEncoding],
+        head: bool,
+    ) -> Response<Body> {
+        if !self.is_compressible(&response) {
+            return response;
+        }
+        // responses built without a `Content-Length` header may still have a body of known size
+        let length = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .or_else(|| response.body().size_hint().exact());
+        let headers = response.headers_mut();
+        add_vary(headers, ACCEPT_ENCODING);
+        if head || length.is_some_and(|length| length < self.min_size) {
+            return response;
+        }
+        let encoding = match negotiate(accepted, SUPPORTED) {
+            Some(encoding) => encoding,
+            None => return response,
+        };
+
+        headers.remove(CONTENT_LENGTH);
+        headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        let weak_etag = headers
+            .get(ETAG)
+            .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+            .and_then(|etag| HeaderValue::from_bytes(&[b"W/", etag.as_bytes()].concat()).ok());
+        if let Some(etag) = weak_etag {
+            headers.insert(ETAG, etag);
+        }
+
+        let level = self.level;
+        response.map(|body| {
+            let body = TryStreamExt::map_err(body, io::Error::other);
+            Body::wrap_stream(compress_with_level(body, encoding, level).into_stream())
+        })
+    }
+}
+
+impl Default for CompressionMiddleware {
+    fn default() -> Self {
+        CompressionMiddleware::new()
+    }
+}
+
+// Checks whether one of the comma separated values of the header is `token`.
+fn has_token(headers: &HeaderMap, name: &HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+impl NewMiddleware for CompressionMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for CompressionMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        let accepted = accepted_encodings(HeaderMap::borrow_from(&state));
+        let head = Method::borrow_from(&state) == Method::HEAD;
+        chain(state)
+            .map_ok(move |(state, response)| {
+                let response = self.compress(response, &accepted, head);
+                (state, response)
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use flate2::read::GzDecoder;
+    use hyper::header::VARY;
+    use std::io::Read;
+
+    fn text(state: State) -> (State, Response<Body>) {
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "text/plain")
+            .header(ETAG, "\"abc\"")
+            .body(Body::from("hello ".repeat(500)))
+            .unwrap();
+        (state, response)
+    }
+
+    fn small(state: State) -> (State, Response<Body>) {
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "text/plain")
+            .body(Body::from("hello"))
+            .unwrap();
+        (state, response)
+    }
+
+    fn png(state: State) -> (State, Response<Body>) {
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "image/png")
+            .body(Body::from(vec![0; 2048]))
+            .unwrap();
+        (state, response)
+    }
+
+    #[test]
+    fn compresses_responses() {
+        let (chain, pipelines) =
+            single_pipeline(new_pipeline().add(CompressionMiddleware::new()).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/text").to(text);
+            route.get("/small").to(small);
+            route.get("/png").to(png);
+        }))
+        .unwrap();
+        let get = |uri: &str, accept: &str| {
+            test_server
+                .client()
+                .get(uri)
+                .with_header(ACCEPT_ENCODING, accept.parse().unwrap())
+                .perform()
+                .unwrap()
+        };
+
+        let response = get("http://localhost/text", "gzip, deflate;q=0.5");
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+        assert_eq!(response.headers()[ETAG], "W/\"abc\"");
+        let body = response.read_body().unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "hello ".repeat(500));
+
+        let response = get("http://localhost/text", "br;q=0.8, gzip;q=0.5");
+        assert_eq!(response.headers()[CONTENT_ENCODING], "br");
+        let body = response.read_body().unwrap();
+        let mut decompressed = String::new();
+        brotli::Decompressor::new(&body[..], 4096)
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "hello ".repeat(500));
+
+        // compressible responses vary on the accepted encodings, even when not compressed
+        let response = get("http://localhost/text", "identity");
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+        assert_eq!(response.headers()[ETAG], "\"abc\"");
+
+        let response = get("http://localhost/small", "gzip");
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.read_utf8_body().unwrap(), "hello");
+
+        let response = get("http://localhost/png", "gzip");
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert!(response.headers().get(VARY).is_none());
+        assert_eq!(response.read_body().unwrap().len(), 2048);
+    }
+}
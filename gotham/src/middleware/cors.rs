@@ -0,0 +1,409 @@
+//! Cross-origin resource sharing (CORS), allowing browsers to call an API from web pages served
+//! by other origins.
+//!
+//! Browsers only let scripts read responses of other origins which carry `Access-Control-*`
+//! headers allowing it, and ask the server with a preflight `OPTIONS` request before sending
+//! requests which aren't "simple", like ones with a JSON body or custom headers. The
+//! `CorsMiddleware` answers preflights itself and adds the headers to all other responses.
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_util::future::{self, FutureExt, TryFutureExt};
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+};
+use hyper::{Method, StatusCode};
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::response::create_empty_response;
+use crate::helpers::http::vary::add_vary;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{FromState, State};
+
+/// A `Middleware` which answers CORS preflight requests and adds the `Access-Control-*` headers
+/// to responses of requests from allowed origins.
+///
+/// Preflight requests, which are `OPTIONS` requests with an `Origin` and an
+/// `Access-Control-Request-Method` header, are answered with `204 No Content` if the origin,
+/// method and headers are allowed, and with `403 Forbidden` otherwise, without calling the
+/// handler. Since middleware only runs for requests matching a route, the routes must accept
+/// `OPTIONS` requests for preflights to reach the middleware. Responses to other requests from
+/// allowed origins get the `Access-Control-Allow-Origin`, `Access-Control-Allow-Credentials` and
+/// `Access-Control-Expose-Headers` headers, while requests from other origins are passed on
+/// unchanged, so the browser hides their responses from the calling script.
+///
+/// By default, all origins are allowed, along with the methods `GET`, `HEAD`, `POST`, `PUT`,
+/// `PATCH` and `DELETE`, but no headers beyond the ones browsers always allow, and no
+/// credentials.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::hyper::Method;
+/// use gotham::middleware::cors::CorsMiddleware;
+/// use std::time::Duration;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let cors = CorsMiddleware::new()
+///     .with_origins(&["https://app.example.com"])
+///     .with_methods(&[Method::GET, Method::PUT])
+///     .with_headers(&["content-type", "authorization"])
+///     .with_credentials(true)
+///     .with_max_age(Duration::from_secs(3600));
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(cors).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route
+///         .request(vec![Method::GET, Method::PUT, Method::OPTIONS], "/api/items")
+///         .to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsMiddleware {
+    // all origins are allowed if `None`
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl CorsMiddleware {
+    /// Creates a new `CorsMiddleware` allowing all origins.
+    pub fn new() -> Self {
+        CorsMiddleware {
+            origins: None,
+            methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            headers: Vec::new(),
+            exposed_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Restricts the allowed origins to the given ones, like `https://app.example.com`.
+    pub fn with_origins(mut self, origins: &[&str]) -> Self {
+        self.origins = Some(
+            origins
+                .iter()
+                .map(|origin| origin.trim_end_matches('/').to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Sets the methods allowed in preflight requests.
+    pub fn with_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Sets the request headers allowed in preflight requests, in addition to the ones browsers
+    /// always allow, like `accept` or `content-language`.
+    pub fn with_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Sets the response headers which scripts may read, in addition to the ones browsers always
+    /// expose, like `content-type` or `cache-control`.
+    pub fn with_exposed_headers(mut self, headers: &[&str]) -> Self {
+        self.exposed_headers = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// If `true`, requests may include credentials like cookies, and responses are readable
+    /// despite them (defaults to `false`). The origin of the request is then allowed by name even
+    /// if all origins are allowed, since browsers reject `*` with credentials.
+    pub fn with_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Sets for how long browsers may cache the answer to a preflight request (defaults to the
+    /// browser's default of a few seconds).
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // Whether the headers of responses depend on the origin of the request.
+    fn varies_by_origin(&self) -> bool {
+        self.origins.is_some() || self.credentials
+    }
+
+    // Returns the value of the `Access-Control-Allow-Origin` header for an allowed origin.
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.origins {
+            None if self.credentials => Some(origin.clone()),
+            None => Some(HeaderValue::from_static("*")),
+            Some(origins) => {
+                let origin_str = origin.to_str().ok()?;
+                origins
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+                    .then(|| origin.clone())
+            }
+        }
+    }
+
+    // Checks whether the method and all headers requested by a preflight are allowed.
+    fn allows_request(&self, headers: &HeaderMap) -> bool {
+        let method = headers
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Method>().ok());
+        if !method.is_some_and(|method| self.methods.contains(&method)) {
+            return false;
+        }
+        headers
+            .get_all(ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .all(|value| match value.to_str() {
+                Ok(value) => value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .all(|name| {
+                        self.headers
+                            .iter()
+                            .any(|allowed| allowed.eq_ignore_ascii_case(name))
+                    }),
+                Err(_) => false,
+            })
+    }
+
+    // Answers a preflight request without calling the handler.
+    fn preflight(&self, state: State, origin: &HeaderValue) -> Pin<Box<HandlerFuture>> {
+        let allow_origin = match self.allow_origin(origin) {
+            Some(allow_origin) if self.allows_request(HeaderMap::borrow_from(&state)) => {
+                allow_origin
+            }
+            _ => {
+                let response = create_empty_response(&state, StatusCode::FORBIDDEN);
+                return future::ok((state, response)).boxed();
+            }
+        };
+
+        let mut response = create_empty_response(&state, StatusCode::NO_CONTENT);
+        let headers = response.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        headers.insert(ACCESS_CONTROL_ALLOW_METHODS, join(&self.methods));
+        if !self.headers.is_empty() {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, join(&self.headers));
+        }
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        if self.varies_by_origin() {
+            add_vary(headers, ORIGIN);
+        }
+        add_vary(headers, ACCESS_CONTROL_REQUEST_METHOD);
+        add_vary(headers, ACCESS_CONTROL_REQUEST_HEADERS);
+        future::ok((state, response)).boxed()
+    }
+}
+
+impl Default for CorsMiddleware {
+    fn default() -> Self {
+        CorsMiddleware::new()
+    }
+}
+
+// Joins the values into a comma separated header value.
+fn join<T: AsRef<str>>(values: &[T]) -> HeaderValue {
+    let values: Vec<&str> = values.iter().map(AsRef::as_ref).collect();
+    HeaderValue::from_str(&values.join(", ")).unwrap()
+}
+
+impl NewMiddleware for CorsMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for CorsMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        let headers = HeaderMap::borrow_from(&state);
+        let origin = headers.get(ORIGIN).cloned();
+        if let Some(origin) = &origin {
+            if Method::borrow_from(&state) == Method::OPTIONS
+                && headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+            {
+                return self.preflight(state, origin);
+            }
+        }
+
+        let allow_origin = origin.and_then(|origin| self.allow_origin(&origin));
+        chain(state)
+            .map_ok(move |(state, mut response)| {
+                let headers = response.headers_mut();
+                if self.varies_by_origin() {
+                    add_vary(headers, ORIGIN);
+                }
+                if let Some(allow_origin) = allow_origin {
+                    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+                    if self.credentials {
+                        headers.insert(
+                            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                            HeaderValue::from_static("true"),
+                        );
+                    }
+                    if !self.exposed_headers.is_empty() {
+                        headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, join(&self.exposed_headers));
+                    }
+                }
+                (state, response)
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use crate::test::TestServer;
+    use hyper::header::VARY;
+
+    fn handler(state: State) -> (State, &'static str) {
+        (state, "items")
+    }
+
+    fn router(cors: CorsMiddleware) -> Router {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(cors).build());
+        build_router(chain, pipelines, |route| {
+            route
+                .request(vec![Method::GET, Method::PUT, Method::OPTIONS], "/items")
+                .to(handler);
+        })
+    }
+
+    #[test]
+    fn answers_preflights() {
+        let cors = CorsMiddleware::new()
+            .with_origins(&["https://app.example.com"])
+            .with_methods(&[Method::GET, Method::PUT])
+            .with_headers(&["Content-Type"])
+            .with_credentials(true)
+            .with_max_age(Duration::from_secs(600));
+        let test_server = TestServer::new(router(cors)).unwrap();
+        let preflight = |origin: &str, method: &str, headers: &str| {
+            test_server
+                .client()
+                .options("http://localhost/items")
+                .with_header(ORIGIN, origin.parse().unwrap())
+                .with_header(ACCESS_CONTROL_REQUEST_METHOD, method.parse().unwrap())
+                .with_header(ACCESS_CONTROL_REQUEST_HEADERS, headers.parse().unwrap())
+                .perform()
+                .unwrap()
+        };
+
+        let response = preflight("https://app.example.com", "PUT", "content-type");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(response.read_body().unwrap().len(), 0);
+
+        let response = preflight("https://evil.example.com", "PUT", "content-type");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+        let response = preflight("https://app.example.com", "DELETE", "");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = preflight("https://app.example.com", "PUT", "x-custom");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // plain OPTIONS requests reach the handler
+        let response = test_server
+            .client()
+            .options("http://localhost/items")
+            .perform()
+            .unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "items");
+    }
+
+    #[test]
+    fn decorates_responses() {
+        let cors = CorsMiddleware::new()
+            .with_origins(&["https://app.example.com/"])
+            .with_exposed_headers(&["x-total-count"]);
+        let test_server = TestServer::new(router(cors)).unwrap();
+        let get = |origin: &str| {
+            test_server
+                .client()
+                .get("http://localhost/items")
+                .with_header(ORIGIN, origin.parse().unwrap())
+                .perform()
+                .unwrap()
+        };
+
+        let response = get("https://app.example.com");
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_EXPOSE_HEADERS], "x-total-count");
+        assert_eq!(headers[VARY], "origin");
+        assert!(headers.get(ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+        assert_eq!(response.read_utf8_body().unwrap(), "items");
+
+        let response = get("https://evil.example.com");
+        assert!(response
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+        assert_eq!(response.read_utf8_body().unwrap(), "items");
+
+        // all origins are allowed by default
+        let test_server = TestServer::new(router(CorsMiddleware::new())).unwrap();
+        let response = test_server
+            .client()
+            .get("http://localhost/items")
+            .with_header(ORIGIN, "https://any.example.com".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(response.headers().get(VARY).is_none());
+    }
+}
@@ -0,0 +1,240 @@
+//! Per-request deadlines, so the timeout budget of a request flows through every layer serving
+//! it instead of each layer guessing its own timeout.
+//!
+//! `DeadlineMiddleware` places a `Deadline` into the `State`, derived from its timeout and
+//! optionally from a timeout sent by the client. Handlers bound downstream work by the remaining
+//! time with `Deadline::bound`, and outbound requests of the `HttpClient` time out no later than
+//! the deadline.
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderMap, HeaderName};
+use thiserror::Error;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{FromState, State, StateData};
+
+/// The error returned when work bounded by a `Deadline` did not complete in time.
+#[derive(Debug, Error)]
+#[error("the deadline of the request was exceeded")]
+pub struct DeadlineExceeded;
+
+/// The point in time by which a request should be answered.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use gotham::handler::HandlerResult;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::hyper::StatusCode;
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::middleware::deadline::{Deadline, DeadlineMiddleware};
+/// use gotham::state::{FromState, State};
+///
+/// async fn query_database() -> &'static str {
+///     "rows"
+/// }
+///
+/// async fn handler(state: State) -> HandlerResult {
+///     let deadline = *Deadline::borrow_from(&state);
+///     let response = match deadline.bound(query_database()).await {
+///         Ok(rows) => create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, rows),
+///         Err(_) => create_response(&state, StatusCode::GATEWAY_TIMEOUT, mime::TEXT_PLAIN, ""),
+///     };
+///     Ok((state, response))
+/// }
+///
+/// # fn main() {
+/// let deadlines = DeadlineMiddleware::new(Duration::from_secs(5));
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(deadlines).build());
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to_async(handler);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// # assert_eq!(response.read_utf8_body().unwrap(), "rows");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl StateData for Deadline {}
+
+impl Deadline {
+    /// Creates a `Deadline` at the given point in time.
+    pub fn at(at: Instant) -> Self {
+        Deadline { at }
+    }
+
+    /// Creates a `Deadline` once `timeout` has passed from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline::at(Instant::now() + timeout)
+    }
+
+    /// Returns the point in time of the deadline.
+    pub fn instant(&self) -> Instant {
+        self.at
+    }
+
+    /// Returns the time left until the deadline, which is zero once it passed.
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns whether the deadline passed.
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Duration::ZERO
+    }
+
+    /// Returns the earlier of this deadline and `other`.
+    pub fn min(self, other: Deadline) -> Self {
+        Deadline::at(self.at.min(other.at))
+    }
+
+    /// Returns the timeout for work which should complete within `timeout`, but no later than the
+    /// deadline.
+    pub fn bound_timeout(&self, timeout: Option<Duration>) -> Duration {
+        let remaining = self.remaining();
+        timeout.map_or(remaining, |timeout| timeout.min(remaining))
+    }
+
+    /// Runs `future` until it completes, or fails with `DeadlineExceeded` once the deadline
+    /// passed, e.g. to bound database queries by the time left to answer the request.
+    pub async fn bound<F: Future>(&self, future: F) -> Result<F::Output, DeadlineExceeded> {
+        tokio::time::timeout_at(self.at.into(), future)
+            .await
+            .map_err(|_| DeadlineExceeded)
+    }
+}
+
+/// A `Middleware` which places a `Deadline` into the `State` of every request.
+///
+/// The deadline is the configured timeout after the request passed the middleware. If the client
+/// sent a timeout in milliseconds in the header configured with `with_client_header`, or an
+/// outer middleware already set an earlier deadline, the earlier deadline applies, so deadlines
+/// of nested pipelines can only tighten the budget.
+///
+/// The middleware does not abort requests exceeding the deadline, which is left to the handler.
+#[derive(Clone, Debug)]
+pub struct DeadlineMiddleware {
+    timeout: Duration,
+    client_header: Option<HeaderName>,
+}
+
+impl DeadlineMiddleware {
+    /// Creates a new `DeadlineMiddleware` setting deadlines `timeout` after the request.
+    pub fn new(timeout: Duration) -> Self {
+        DeadlineMiddleware {
+            timeout,
+            client_header: None,
+        }
+    }
+
+    /// Uses the timeout in milliseconds sent by the client in the given header, like
+    /// `x-request-timeout-ms`, if it is shorter than the configured timeout (defaults to none).
+    pub fn with_client_header(mut self, name: HeaderName) -> Self {
+        self.client_header = Some(name);
+        self
+    }
+
+    fn deadline(&self, state: &State) -> Deadline {
+        let mut deadline = Deadline::after(self.timeout);
+        let client_timeout = self.client_header.as_ref().and_then(|name| {
+            HeaderMap::borrow_from(state)
+                .get(name)?
+                .to_str()
+                .ok()?
+                .trim()
+                .parse::<u64>()
+                .ok()
+        });
+        if let Some(millis) = client_timeout {
+            deadline = deadline.min(Deadline::after(Duration::from_millis(millis)));
+        }
+        match Deadline::try_borrow_from(state) {
+            Some(outer) => deadline.min(*outer),
+            None => deadline,
+        }
+    }
+}
+
+impl NewMiddleware for DeadlineMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for DeadlineMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        let deadline = self.deadline(&state);
+        state.put(deadline);
+        chain(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use futures_util::future;
+
+    fn remaining(state: State) -> (State, String) {
+        let remaining = Deadline::borrow_from(&state).remaining().as_millis();
+        (state, remaining.to_string())
+    }
+
+    #[test]
+    fn sets_earliest_deadline() {
+        let outer = DeadlineMiddleware::new(Duration::from_secs(60))
+            .with_client_header(HeaderName::from_static("x-request-timeout-ms"));
+        let inner = DeadlineMiddleware::new(Duration::from_secs(120));
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(outer).add(inner).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(remaining);
+        }))
+        .unwrap();
+
+        let remaining = |client_timeout: Option<&str>| {
+            let client = test_server.client();
+            let mut request = client.get("http://localhost/");
+            if let Some(timeout) = client_timeout {
+                request = request.with_header("x-request-timeout-ms", timeout.parse().unwrap());
+            }
+            let body = request.perform().unwrap().read_utf8_body().unwrap();
+            body.parse::<u64>().unwrap()
+        };
+        assert!((59_000..=60_000).contains(&remaining(None)));
+        assert!((4_000..=5_000).contains(&remaining(Some("5000"))));
+        assert!((59_000..=60_000).contains(&remaining(Some("invalid"))));
+    }
+
+    #[tokio::test]
+    async fn bounds_futures() {
+        let deadline = Deadline::after(Duration::from_millis(10));
+        assert_eq!(deadline.bound(future::ready(1)).await.unwrap(), 1);
+        assert!(deadline.bound(future::pending::<()>()).await.is_err());
+        assert!(deadline.is_expired());
+        assert_eq!(deadline.bound_timeout(None), Duration::ZERO);
+
+        let deadline = Deadline::after(Duration::from_secs(60));
+        assert_eq!(
+            deadline.bound_timeout(Some(Duration::from_secs(1))),
+            Duration::from_secs(1)
+        );
+    }
+}
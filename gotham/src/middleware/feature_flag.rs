@@ -0,0 +1,474 @@
+//! Feature flags for dark launches and gradual rollouts.
+//!
+//! A `FlagProvider` decides whether a flag is enabled for a `FlagContext`, which identifies the
+//! user or client a request is made by. Flags are kept in memory by a `MemoryFlags` provider,
+//! which can be changed while the server is running, or can be read from a feature management
+//! service like LaunchDarkly or Unleash by implementing `FlagProvider`.
+//!
+//! The `FeatureFlagMiddleware` places the `FeatureFlags` of the request into `State`, so handlers
+//! can check flags for the current user, and can answer requests to routes whose flag is
+//! disabled with `404 Not Found` (or `503 Service Unavailable`), so a route can be deployed
+//! before it is launched.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use futures_util::future::{self, FutureExt};
+use hyper::StatusCode;
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{client_addr, request_id, FromState, State, StateData};
+
+/// The user or client a flag is evaluated for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlagContext {
+    key: Option<String>,
+    attributes: HashMap<String, String>,
+}
+
+impl FlagContext {
+    /// Creates a new, anonymous `FlagContext`.
+    pub fn new() -> Self {
+        FlagContext::default()
+    }
+
+    /// Sets the key identifying the user or client, which percentage rollouts are based on.
+    pub fn with_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Adds an attribute, like the country or plan of the user, for providers targeting flags by
+    /// attributes.
+    pub fn with_attribute<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.attributes.insert(name.into(), value.into());
+        self
+    }
+
+    /// Returns the key identifying the user or client.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// Returns the value of an attribute.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+}
+
+/// Type alias for the trait objects returned by `FlagProvider`.
+pub type FlagFuture = dyn Future<Output = anyhow::Result<bool>> + Send;
+
+/// A `FlagProvider` decides whether feature flags are enabled, e.g. from flags kept in memory or
+/// by a feature management service.
+pub trait FlagProvider: Send + Sync + RefUnwindSafe {
+    /// Returns whether `flag` is enabled for `context`. Unknown flags should be disabled.
+    fn is_enabled(&self, flag: &str, context: &FlagContext) -> Pin<Box<FlagFuture>>;
+}
+
+impl<P: FlagProvider + ?Sized> FlagProvider for Arc<P> {
+    fn is_enabled(&self, flag: &str, context: &FlagContext) -> Pin<Box<FlagFuture>> {
+        (**self).is_enabled(flag, context)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Rule {
+    Enabled(bool),
+    Rollout(u8),
+}
+
+/// A `FlagProvider` keeping flags in memory.
+///
+/// Flags can be changed while the server is running, by sharing the provider in an `Arc` with
+/// e.g. an admin endpoint. A flag rolled out to a percentage of users is enabled for the same
+/// users for as long as the percentage doesn't shrink, and never for contexts without a key.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use gotham::middleware::feature_flag::{FeatureFlagMiddleware, MemoryFlags};
+///
+/// let flags = Arc::new(
+///     MemoryFlags::new()
+///         .with_flag("new-search", true)
+///         .with_rollout("new-checkout", 10),
+/// );
+/// let middleware = FeatureFlagMiddleware::new(flags.clone());
+///
+/// // later, e.g. in an admin handler
+/// flags.set_rollout("new-checkout", 50);
+/// # let _ = middleware;
+/// ```
+#[derive(Default)]
+pub struct MemoryFlags {
+    flags: RwLock<HashMap<String, Rule>>,
+}
+
+impl MemoryFlags {
+    /// Creates a new `MemoryFlags` without flags.
+    pub fn new() -> Self {
+        MemoryFlags::default()
+    }
+
+    /// Enables or disables a flag for everyone.
+    pub fn with_flag<S: Into<String>>(self, flag: S, enabled: bool) -> Self {
+        self.set(flag, enabled);
+        self
+    }
+
+    /// Enables a flag for a percentage of users, see `set_rollout`.
+    pub fn with_rollout<S: Into<String>>(self, flag: S, percent: u8) -> Self {
+        self.set_rollout(flag, percent);
+        self
+    }
+
+    /// Enables or disables a flag for everyone.
+    pub fn set<S: Into<String>>(&self, flag: S, enabled: bool) {
+        let mut flags = self.flags.write().unwrap();
+        flags.insert(flag.into(), Rule::Enabled(enabled));
+    }
+
+    /// Enables a flag for `percent` percent of the keys of `FlagContext`s, chosen by a hash of
+    /// the flag and key. Percentages above 100 enable the flag for everyone.
+    pub fn set_rollout<S: Into<String>>(&self, flag: S, percent: u8) {
+        let mut flags = self.flags.write().unwrap();
+        flags.insert(flag.into(), Rule::Rollout(percent.min(100)));
+    }
+
+    /// Removes a flag, which disables it.
+    pub fn remove(&self, flag: &str) {
+        self.flags.write().unwrap().remove(flag);
+    }
+
+    fn evaluate(&self, flag: &str, context: &FlagContext) -> bool {
+        let rule = self.flags.read().unwrap().get(flag).copied();
+        match (rule, context.key()) {
+            (Some(Rule::Enabled(enabled)), _) => enabled,
+            (Some(Rule::Rollout(100)), _) => true,
+            (Some(Rule::Rollout(percent)), Some(key)) => bucket(flag, key) < percent,
+            _ => false,
+        }
+    }
+}
+
+// Assigns a key to one of 100 buckets, differently for every flag so the same users aren't
+// always the first to get new features.
+fn bucket(flag: &str, key: &str) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(flag)
+        .chain_update([0u8])
+        .chain_update(key)
+        .finalize();
+    (u16::from_be_bytes([digest[0], digest[1]]) % 100) as u8
+}
+
+impl fmt::Debug for MemoryFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryFlags")
+            .field("flags", &*self.flags.read().unwrap())
+            .finish()
+    }
+}
+
+impl FlagProvider for MemoryFlags {
+    fn is_enabled(&self, flag: &str, context: &FlagContext) -> Pin<Box<FlagFuture>> {
+        future::ok(self.evaluate(flag, context)).boxed()
+    }
+}
+
+/// The feature flags of the current request, placed into `State` by `FeatureFlagMiddleware`.
+///
+/// ```rust
+/// # use gotham::handler::HandlerResult;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::hyper::StatusCode;
+/// use gotham::middleware::feature_flag::FeatureFlags;
+///
+/// # #[allow(dead_code)]
+/// async fn search(state: State) -> HandlerResult {
+///     let flags = FeatureFlags::borrow_from(&state).clone();
+///     let engine = if flags.is_enabled("new-search").await.unwrap_or(false) {
+///         "new"
+///     } else {
+///         "old"
+///     };
+///     let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, engine);
+///     Ok((state, response))
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct FeatureFlags {
+    provider: Arc<dyn FlagProvider>,
+    context: FlagContext,
+}
+
+impl StateData for FeatureFlags {}
+
+impl FeatureFlags {
+    /// Returns whether `flag` is enabled for the user or client making the request.
+    pub fn is_enabled(&self, flag: &str) -> Pin<Box<FlagFuture>> {
+        self.provider.is_enabled(flag, &self.context)
+    }
+
+    /// Returns the context flags are evaluated for.
+    pub fn context(&self) -> &FlagContext {
+        &self.context
+    }
+}
+
+impl fmt::Debug for FeatureFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeatureFlags")
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+type Context = dyn Fn(&State) -> FlagContext + Send + Sync + RefUnwindSafe;
+
+/// A `Middleware` which places the `FeatureFlags` of the request into `State`, and answers
+/// requests with `404 Not Found` if a flag required by the route is disabled.
+///
+/// Flags are evaluated for the ID of the `AuthenticatedUser` if the request has been
+/// authenticated, or for the IP address of the client otherwise, unless a different context is
+/// set with `with_context`. If the provider fails, the required flag is treated as disabled.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::feature_flag::{FeatureFlagMiddleware, MemoryFlags};
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let flags = Arc::new(MemoryFlags::new().with_rollout("new-checkout", 5));
+/// let (chain, pipelines) = single_pipeline(
+///     new_pipeline()
+///         .add(FeatureFlagMiddleware::new(flags).with_required_flag("new-checkout"))
+///         .build(),
+/// );
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/checkout/v2").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct FeatureFlagMiddleware {
+    provider: Arc<dyn FlagProvider>,
+    context: Arc<Context>,
+    required: Option<String>,
+    status: StatusCode,
+}
+
+impl FeatureFlagMiddleware {
+    /// Creates a new `FeatureFlagMiddleware` evaluating flags with `provider`, which doesn't
+    /// require any flag.
+    pub fn new<P: FlagProvider + 'static>(provider: P) -> Self {
+        FeatureFlagMiddleware {
+            provider: Arc::new(provider),
+            context: Arc::new(default_context),
+            required: None,
+            status: StatusCode::NOT_FOUND,
+        }
+    }
+
+    /// Requires `flag` to be enabled, answering requests with the status set with `with_status`
+    /// otherwise.
+    pub fn with_required_flag<S: Into<String>>(mut self, flag: S) -> Self {
+        self.required = Some(flag.into());
+        self
+    }
+
+    /// Sets the status of responses to requests whose required flag is disabled, e.g.
+    /// `503 Service Unavailable` for a feature that is temporarily switched off (defaults to
+    /// `404 Not Found`).
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Sets the function creating the context flags are evaluated for.
+    pub fn with_context<F>(mut self, context: F) -> Self
+    where
+        F: Fn(&State) -> FlagContext + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.context = Arc::new(context);
+        self
+    }
+}
+
+fn default_context(state: &State) -> FlagContext {
+    if let Some(user) = AuthenticatedUser::try_borrow_from(state) {
+        FlagContext::new().with_key(user.id())
+    } else if let Some(addr) = client_addr(state) {
+        FlagContext::new().with_key(addr.ip().to_string())
+    } else {
+        FlagContext::new()
+    }
+}
+
+impl fmt::Debug for FeatureFlagMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeatureFlagMiddleware")
+            .field("required", &self.required)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+impl NewMiddleware for FeatureFlagMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for FeatureFlagMiddleware {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let flags = FeatureFlags {
+            provider: self.provider,
+            context: (self.context)(&state),
+        };
+        let flag = match self.required {
+            Some(flag) => flag,
+            None => {
+                state.put(flags);
+                return chain(state);
+            }
+        };
+
+        let enabled = flags.is_enabled(&flag);
+        state.put(flags);
+        let status = self.status;
+        async move {
+            let enabled = enabled.await.unwrap_or_else(|err| {
+                warn!(
+                    "[{}] failed to evaluate flag {}: {}",
+                    request_id(&state),
+                    flag,
+                    err
+                );
+                false
+            });
+            if enabled {
+                chain(state).await
+            } else {
+                debug!("[{}] flag {} is disabled", request_id(&state), flag);
+                let response = create_empty_response(&state, status);
+                Ok((state, response))
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::HandlerResult;
+    use crate::helpers::http::response::create_response;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+
+    #[test]
+    fn evaluates_memory_flags() {
+        let flags = MemoryFlags::new()
+            .with_flag("on", true)
+            .with_flag("off", false)
+            .with_rollout("half", 50);
+        let anonymous = FlagContext::new();
+        assert!(flags.evaluate("on", &anonymous));
+        assert!(!flags.evaluate("off", &anonymous));
+        assert!(!flags.evaluate("unknown", &anonymous));
+        assert!(!flags.evaluate("half", &anonymous));
+
+        let contexts: Vec<FlagContext> = (0..1000)
+            .map(|i| FlagContext::new().with_key(format!("user-{}", i)))
+            .collect();
+        let enabled = |flags: &MemoryFlags| {
+            contexts
+                .iter()
+                .filter(|context| flags.evaluate("half", context))
+                .count()
+        };
+        let half = enabled(&flags);
+        assert!((400..600).contains(&half), "{} of 1000 enabled", half);
+
+        // growing a rollout keeps it enabled for the same users
+        let before: Vec<bool> = contexts.iter().map(|c| flags.evaluate("half", c)).collect();
+        flags.set_rollout("half", 80);
+        for (context, was_enabled) in contexts.iter().zip(before) {
+            assert!(!was_enabled || flags.evaluate("half", context));
+        }
+        flags.set_rollout("half", 100);
+        assert_eq!(enabled(&flags), 1000);
+        flags.remove("half");
+        assert_eq!(enabled(&flags), 0);
+    }
+
+    async fn handler(state: State) -> HandlerResult {
+        let flags = FeatureFlags::borrow_from(&state).clone();
+        let body = if flags.is_enabled("beta").await.unwrap() {
+            "beta"
+        } else {
+            "stable"
+        };
+        let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, body);
+        Ok((state, response))
+    }
+
+    #[test]
+    fn gates_routes_on_flags() {
+        let flags = Arc::new(MemoryFlags::new().with_flag("launch", false));
+        let middleware = FeatureFlagMiddleware::new(flags.clone())
+            .with_required_flag("launch")
+            .with_status(StatusCode::SERVICE_UNAVAILABLE);
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to_async(handler);
+        }))
+        .unwrap();
+        let get = || {
+            test_server
+                .client()
+                .get("http://localhost/")
+                .perform()
+                .unwrap()
+        };
+
+        assert_eq!(get().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        flags.set("launch", true);
+        let response = get();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "stable");
+
+        flags.set("beta", true);
+        assert_eq!(get().read_utf8_body().unwrap(), "beta");
+    }
+}
@@ -0,0 +1,132 @@
+//! Middleware for debugging the contents of the `State`, available with the `state-inspection`
+//! feature.
+//!
+//! When a handler fails because some state data is missing, it is often unclear which middleware
+//! was expected to provide it. The `StateInventoryLogger` logs the names of the types stored in
+//! the `State` whenever a request results in an error response, which shows what was available
+//! when the request failed.
+use futures_util::future::{FutureExt, TryFutureExt};
+use hyper::StatusCode;
+use log::{log, log_enabled, Level};
+use std::pin::Pin;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, State};
+
+/// Middleware which logs the names of the types stored in the `State` for responses with a
+/// client or server error status, and for handler errors.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::inspection::StateInventoryLogger;
+/// use log::Level;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let (chain, pipelines) = single_pipeline(
+///     new_pipeline()
+///         .add(StateInventoryLogger::new(Level::Debug))
+///         .build(),
+/// );
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Copy, Clone)]
+pub struct StateInventoryLogger {
+    level: Level,
+    client_errors: bool,
+}
+
+impl StateInventoryLogger {
+    /// Constructs a new `StateInventoryLogger`, logging at the given level.
+    pub fn new(level: Level) -> Self {
+        StateInventoryLogger {
+            level,
+            client_errors: true,
+        }
+    }
+
+    /// Sets whether responses with a client error status (`4xx`) are logged. Server errors are
+    /// always logged.
+    pub fn with_client_errors(mut self, client_errors: bool) -> Self {
+        self.client_errors = client_errors;
+        self
+    }
+
+    fn should_log(&self, status: StatusCode) -> bool {
+        status.is_server_error() || (self.client_errors && status.is_client_error())
+    }
+
+    fn log(&self, state: &State, status: StatusCode) {
+        log!(
+            self.level,
+            "[{}] {} response, State contains: {}",
+            request_id(state),
+            status,
+            state.type_names().join(", ")
+        );
+    }
+}
+
+impl NewMiddleware for StateInventoryLogger {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(*self)
+    }
+}
+
+impl Middleware for StateInventoryLogger {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        // skip everything if logging is disabled
+        if !log_enabled!(self.level) {
+            return chain(state);
+        }
+
+        chain(state)
+            .map_ok(move |(state, response)| {
+                if self.should_log(response.status()) {
+                    self.log(&state, response.status());
+                }
+                (state, response)
+            })
+            .map_err(move |(state, err)| {
+                if self.should_log(err.status()) {
+                    self.log(&state, err.status());
+                }
+                (state, err)
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_error_statuses() {
+        let logger = StateInventoryLogger::new(Level::Info);
+        assert!(logger.should_log(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(logger.should_log(StatusCode::NOT_FOUND));
+        assert!(!logger.should_log(StatusCode::OK));
+        assert!(!logger.should_log(StatusCode::FOUND));
+
+        let logger = logger.with_client_errors(false);
+        assert!(logger.should_log(StatusCode::BAD_GATEWAY));
+        assert!(!logger.should_log(StatusCode::NOT_FOUND));
+    }
+}
@@ -2,11 +2,18 @@
 //!
 //! This module contains several logging implementations, with varying degrees
 //! of complexity. The default `RequestLogger` will log out using the standard
-//! [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format) (CLF).
+//! [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format) (CLF),
+//! or alternatively the Combined Log Format or structured `key=value` records,
+//! as selected by its `LogFormat`.
 //!
 //! There is also a `SimpleLogger` which emits only basic request logs.
+//!
+//! High-traffic services can limit the overhead of the `RequestLogger` by sampling requests,
+//! with a default rate, rates for individual routes, and all failed requests being logged
+//! regardless of the rate.
 use futures_util::future::{self, FutureExt, TryFutureExt};
-use hyper::header::CONTENT_LENGTH;
+use hyper::header::{HeaderName, CONTENT_LENGTH, REFERER, USER_AGENT};
+use hyper::HeaderMap;
 use hyper::{Method, Uri, Version};
 use log::{log, log_enabled, Level};
 use std::pin::Pin;
@@ -16,6 +23,24 @@ use crate::helpers::timing::Timer;
 use crate::middleware::{Middleware, NewMiddleware};
 use crate::state::{client_addr, request_id, FromState, State};
 
+/// The format of the records emitted by a `RequestLogger`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogFormat {
+    /// The Common Log Format, followed by the duration of the request:
+    ///
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326 - 1.2ms`
+    #[default]
+    Common,
+    /// The Combined Log Format, which adds the `Referer` and `User-Agent` headers of the request
+    /// to the Common Log Format, followed by the duration of the request.
+    Combined,
+    /// Structured records of `key=value` pairs, which are easily parsed by log aggregators:
+    ///
+    /// `request_id=.. ip=127.0.0.1 method=GET path=/index.html status=200 bytes=2326 duration=1.2ms`
+    Structured,
+}
+
 /// A struct that can act as a logging middleware for Gotham.
 ///
 /// We implement `NewMiddleware` here for Gotham to allow us to work with the request
@@ -23,12 +48,61 @@ use crate::state::{client_addr, request_id, FromState, State};
 #[derive(Copy, Clone)]
 pub struct RequestLogger {
     level: Level,
+    format: LogFormat,
+    sample_rate: f64,
+    route_sample_rates: &'static [(&'static str, f64)],
+    sample_errors: bool,
 }
 
 impl RequestLogger {
     /// Constructs a new `RequestLogger` instance.
     pub fn new(level: Level) -> Self {
-        RequestLogger { level }
+        RequestLogger {
+            level,
+            format: LogFormat::Common,
+            sample_rate: 1.0,
+            route_sample_rates: &[],
+            sample_errors: true,
+        }
+    }
+
+    /// Sets the format of the emitted records (defaults to `LogFormat::Common`).
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the fraction of requests which are logged, between `0.0` and `1.0` (defaults to
+    /// `1.0`, logging every request). Whether a request is logged is decided when it is
+    /// received, so requests which are not sampled skip the logger entirely.
+    pub fn with_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets sample rates for requests whose path starts with the given prefix, like
+    /// `&[("/health", 0.0), ("/api/payments", 1.0)]`, overriding the default rate. The first
+    /// matching prefix applies.
+    pub fn with_route_sample_rates(mut self, rates: &'static [(&'static str, f64)]) -> Self {
+        self.route_sample_rates = rates;
+        self
+    }
+
+    /// If `true`, requests answered with a server error are logged even if they were not
+    /// sampled (defaults to true).
+    pub fn with_error_sampling(mut self, sample_errors: bool) -> Self {
+        self.sample_errors = sample_errors;
+        self
+    }
+
+    // Decides whether a request for the given path is sampled.
+    fn is_sampled(&self, path: &str) -> bool {
+        let rate = self
+            .route_sample_rates
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix))
+            .map_or(self.sample_rate, |(_, rate)| *rate);
+        rate >= 1.0 || (rate > 0.0 && rand::random::<f64>() < rate)
     }
 }
 
@@ -57,11 +131,20 @@ impl Middleware for RequestLogger {
             return chain(state);
         }
 
+        // skip requests which are not sampled, unless they may be logged on error
+        let sampled = self.is_sampled(Uri::borrow_from(&state).path());
+        if !sampled && !self.sample_errors {
+            return chain(state);
+        }
+
         // extract the current time
         let timer = Timer::new();
 
         // hook onto the end of the request to log the access
         let f = chain(state).and_then(move |(state, response)| {
+            if !sampled && !response.status().is_server_error() {
+                return future::ok((state, response));
+            }
 
             // format the start time to the CLF formats
             let datetime = {
@@ -74,7 +157,8 @@ impl Middleware for RequestLogger {
             };
 
             // grab the ip address from the state
-            let ip = client_addr(&state).unwrap().ip();
+            // connections over Unix domain sockets have no address
+            let ip = client_addr(&state).map_or_else(|| "-".to_owned(), |addr| addr.ip().to_string());
 
             {
                 // borrows from the state
@@ -91,18 +175,54 @@ impl Middleware for RequestLogger {
                     .unwrap_or("0");
 
                 // log out
-                log!(
-                    self.level,
-                    "{} - - [{}] \"{} {} {:?}\" {} {} - {}",
-                    ip,
-                    datetime,
-                    method,
-                    path,
-                    version,
-                    status,
-                    length,
-                    timer.elapsed()
-                );
+                match self.format {
+                    LogFormat::Common => log!(
+                        self.level,
+                        "{} - - [{}] \"{} {} {:?}\" {} {} - {}",
+                        ip,
+                        datetime,
+                        method,
+                        path,
+                        version,
+                        status,
+                        length,
+                        timer.elapsed()
+                    ),
+                    LogFormat::Combined => {
+                        let headers = HeaderMap::borrow_from(&state);
+                        let header = |name: HeaderName| {
+                            headers
+                                .get(name)
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or("-")
+                        };
+                        log!(
+                            self.level,
+                            "{} - - [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\" - {}",
+                            ip,
+                            datetime,
+                            method,
+                            path,
+                            version,
+                            status,
+                            length,
+                            header(REFERER).replace('"', "\\\""),
+                            header(USER_AGENT).replace('"', "\\\""),
+                            timer.elapsed()
+                        )
+                    }
+                    LogFormat::Structured => log!(
+                        self.level,
+                        "request_id={} ip={} method={} path={:?} status={} bytes={} duration={}",
+                        request_id(&state),
+                        ip,
+                        method,
+                        path.to_string(),
+                        status,
+                        length,
+                        timer.elapsed()
+                    ),
+                }
             }
 
             // continue the response chain
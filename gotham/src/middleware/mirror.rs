@@ -0,0 +1,271 @@
+//! Mirroring of requests to a shadow upstream, available with the `client` feature.
+//!
+//! A new version of a service can be validated against production traffic by sending it copies
+//! of real requests, without its responses reaching any client. The `RequestMirror` sends a copy
+//! of a sample of the requests passing through it to a shadow upstream in the background, and
+//! ignores its responses, so the shadow can neither slow down nor break the requests it mirrors.
+use std::cmp;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures_util::future;
+use futures_util::stream::{self, StreamExt};
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, CONNECTION, CONTENT_LENGTH, HOST, TRANSFER_ENCODING};
+use hyper::{Body, Method, Uri};
+use log::debug;
+
+use crate::client::HttpClient;
+use crate::handler::HandlerFuture;
+use crate::helpers::buffer;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, FromState, State};
+
+const DEFAULT_BODY_CAP: usize = 64 * 1024;
+
+/// A `Middleware` which sends copies of a sample of requests to a shadow upstream.
+///
+/// Copies carry the method, path, query string and headers of the request, and are sent with the
+/// `HttpClient`, so they keep the request ID of the original request. To copy the request body,
+/// it is read into memory before the request is passed on, up to the body cap. Requests with a
+/// longer body are passed on unchanged, but not mirrored, since the shadow would only receive a
+/// part of the body.
+///
+/// Copies are sent after the request was passed on, and failures of the shadow are only logged.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::mirror::RequestMirror;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let mirror = RequestMirror::new("http://orders-v2.internal:8080")
+///     .with_sample_rate(0.05)
+///     .with_body_cap(16 * 1024);
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(mirror).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.post("/orders").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestMirror {
+    upstream: String,
+    client: HttpClient,
+    sample_rate: f64,
+    body_cap: usize,
+}
+
+impl RequestMirror {
+    /// Creates a new `RequestMirror` sending copies of all requests to the given upstream, like
+    /// `http://shadow.internal:8080`. The path and query string of the request are appended to it.
+    pub fn new<U: Into<String>>(upstream: U) -> Self {
+        RequestMirror {
+            upstream: upstream.into().trim_end_matches('/').to_owned(),
+            client: HttpClient::new(),
+            sample_rate: 1.0,
+            body_cap: DEFAULT_BODY_CAP,
+        }
+    }
+
+    /// Sets the client used to send copies, e.g. one with a shorter timeout (defaults to
+    /// `HttpClient::new()`).
+    pub fn with_client(mut self, client: HttpClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets the share of requests which are mirrored, from 0 to 1 (defaults to 1).
+    pub fn with_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets the maximum size of request bodies which are mirrored, in bytes (defaults to 64 KiB).
+    pub fn with_body_cap(mut self, body_cap: usize) -> Self {
+        self.body_cap = body_cap;
+        self
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.sample_rate >= 1.0
+            || (self.sample_rate > 0.0 && rand::random::<f64>() < self.sample_rate)
+    }
+
+    // Sends a copy of the request in `state` with the given body in the background.
+    fn mirror(&self, state: &State, body: Bytes) {
+        let uri = Uri::borrow_from(state);
+        let path = uri.path_and_query().map_or("/", |path| path.as_str());
+        let mut request = self.client.request(
+            state,
+            Method::borrow_from(state).clone(),
+            format!("{}{}", self.upstream, path),
+        );
+        for (name, value) in HeaderMap::borrow_from(state) {
+            // the request ID is already sent by the client
+            if ![HOST, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING].contains(name)
+                && name != "x-request-id"
+            {
+                request = request.header(name, value);
+            }
+        }
+        let request_id = request_id(state).to_owned();
+        tokio::spawn(async move {
+            if let Err(err) = request.body(body).send().await {
+                debug!("[{}] failed to mirror request: {}", request_id, err);
+            }
+        });
+    }
+}
+
+impl NewMiddleware for RequestMirror {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for RequestMirror {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        if !self.is_sampled() {
+            return chain(state);
+        }
+        Box::pin(async move {
+            let mut body = match state.try_take::<Body>() {
+                Some(body) => body,
+                None => return chain(state).await,
+            };
+            let mut buffered = buffer::acquire(cmp::min(self.body_cap, buffer::SIZE_CLASSES[0]));
+            let mut complete = true;
+            while let Some(chunk) = body.data().await {
+                match chunk {
+                    Ok(chunk) => buffered.extend_from_slice(&chunk),
+                    Err(err) => {
+                        // the handler sees the error when reading the remaining body
+                        state.put(Body::wrap_stream(stream::iter(vec![
+                            Ok(buffered.split().freeze()),
+                            Err(err),
+                        ])));
+                        return chain(state).await;
+                    }
+                }
+                if buffered.len() > self.body_cap {
+                    complete = false;
+                    break;
+                }
+            }
+
+            if complete {
+                let buffered = buffered.split().freeze();
+                self.mirror(&state, buffered.clone());
+                state.put(Body::from(buffered));
+            } else {
+                debug!(
+                    "[{}] not mirroring request with a body of more than {} bytes",
+                    request_id(&state),
+                    self.body_cap
+                );
+                let buffered = stream::once(future::ok(buffered.split().freeze()));
+                state.put(Body::wrap_stream(buffered.chain(body)));
+            }
+            chain(state).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Request, Response, Server};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    // Starts a shadow upstream, which reports the method, URI, a header and the body of every
+    // request it receives.
+    fn shadow() -> (SocketAddr, mpsc::Receiver<String>) {
+        let (addr_tx, addr_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let tx = tx.clone();
+                    future::ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let tx = tx.clone();
+                        async move {
+                            let tenant = req.headers()["x-tenant"].to_str().unwrap().to_owned();
+                            let line = format!("{} {} {} ", req.method(), req.uri(), tenant);
+                            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                            tx.send(line + &String::from_utf8_lossy(&body)).unwrap();
+                            Ok::<_, Infallible>(Response::new(Body::from("ignored")))
+                        }
+                    }))
+                });
+                let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+                addr_tx.send(server.local_addr()).unwrap();
+                server.await.unwrap();
+            });
+        });
+        (addr_rx.recv().unwrap(), rx)
+    }
+
+    fn echo(mut state: State) -> Pin<Box<HandlerFuture>> {
+        Box::pin(async move {
+            let body = hyper::body::to_bytes(Body::take_from(&mut state))
+                .await
+                .unwrap();
+            let response = Response::new(Body::from(body));
+            Ok((state, response))
+        })
+    }
+
+    #[test]
+    fn mirrors_requests() {
+        let (addr, mirrored) = shadow();
+        let mirror = RequestMirror::new(format!("http://{}/", addr)).with_body_cap(8);
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(mirror).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.post("/orders").to(echo);
+        }))
+        .unwrap();
+
+        let post = |body: &'static str| {
+            test_server
+                .client()
+                .post("http://localhost/orders?dry=1", body, mime::TEXT_PLAIN)
+                .with_header("x-tenant", "acme".parse().unwrap())
+                .perform()
+                .unwrap()
+                .read_utf8_body()
+                .unwrap()
+        };
+
+        assert_eq!(post("small"), "small");
+        assert_eq!(
+            mirrored.recv_timeout(Duration::from_secs(5)).unwrap(),
+            "POST /orders?dry=1 acme small"
+        );
+
+        // longer bodies are passed on completely, but not mirrored
+        assert_eq!(post("a longer body"), "a longer body");
+        assert!(mirrored.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
@@ -7,13 +7,33 @@ use std::pin::Pin;
 use crate::handler::HandlerFuture;
 use crate::state::State;
 
+pub mod audit;
+pub mod auth;
+pub mod authorization;
+#[cfg(feature = "body-inspection")]
+pub mod body_inspection;
 pub mod chain;
+pub mod checksum;
+pub mod client_ip;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod cookie;
+pub mod cors;
+pub mod deadline;
+pub mod feature_flag;
+#[cfg(feature = "state-inspection")]
+pub mod inspection;
 pub mod logger;
+#[cfg(feature = "client")]
+pub mod mirror;
+pub mod rate_limit;
+pub mod request_id;
+pub mod response;
 pub mod security;
 #[cfg(feature = "session")]
 pub mod session;
 pub mod state;
+pub mod timeout;
 pub mod timer;
 
 #[cfg(feature = "derive")]
@@ -138,7 +158,8 @@ pub use gotham_derive::NewMiddleware;
 /// # }
 /// ```
 ///
-/// Decorating the response after the request has completed:
+/// Decorating the response after the request has completed (middleware which only processes
+/// the response can also implement `ResponseMiddleware`, see the `response` module):
 ///
 /// ```rust
 /// # #[macro_use]
@@ -317,6 +338,12 @@ pub trait Middleware {
     /// * Not modify any request components added to `State` by Gotham.
     /// * Avoid modifying parts of the `State` that don't strictly need to be modified to perform
     ///   its function.
+    ///
+    /// The middleware of a pipeline are composed at compile time, but each of them returns a
+    /// boxed future, so every middleware wrapping the response future costs an allocation per
+    /// request. A middleware which does not need to process the response, or only in some cases,
+    /// should return the future returned by `chain` as is, rather than wrapping it into another
+    /// boxed future.
     fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
     where
         Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
@@ -0,0 +1,436 @@
+//! Rate limiting of requests with token buckets.
+//!
+//! Each client gets a bucket of tokens, identified by a key which defaults to its IP address.
+//! Every request takes a token from the bucket of its client, and the bucket is refilled at a
+//! constant rate up to its capacity, which allows short bursts of requests while limiting the
+//! average rate. Requests finding their bucket empty are answered with `429 Too Many Requests`
+//! and a `Retry-After` header.
+//!
+//! Buckets are kept in memory by default, which limits the rate per server. Applications running
+//! several servers can keep the buckets in a shared store like Redis instead, by implementing
+//! `RateLimitStore`.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::{self, FutureExt};
+use hyper::header::{HeaderValue, RETRY_AFTER};
+use hyper::StatusCode;
+use log::{debug, warn};
+
+use crate::handler::HandlerFuture;
+use crate::helpers::clock;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{client_addr, request_id, State};
+
+const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// The number of requests allowed per key, as capacity of a token bucket and the interval at
+/// which a token is added.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    burst: u32,
+    interval: Duration,
+}
+
+impl Quota {
+    /// Allows `n` requests per second, all of which may be made at once.
+    pub fn per_second(n: u32) -> Self {
+        Quota::per_period(n, Duration::from_secs(1))
+    }
+
+    /// Allows `n` requests per minute, all of which may be made at once.
+    pub fn per_minute(n: u32) -> Self {
+        Quota::per_period(n, Duration::from_secs(60))
+    }
+
+    /// Allows `n` requests per hour, all of which may be made at once.
+    pub fn per_hour(n: u32) -> Self {
+        Quota::per_period(n, Duration::from_secs(3600))
+    }
+
+    fn per_period(n: u32, period: Duration) -> Self {
+        let n = n.max(1);
+        Quota {
+            burst: n,
+            interval: period / n,
+        }
+    }
+
+    /// Sets the number of requests which may be made at once, i.e. the capacity of the bucket,
+    /// without changing the average rate.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Returns the number of requests which may be made at once.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Returns the interval at which a token is added to a bucket.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// The outcome of taking a token from a bucket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Decision {
+    /// The request is allowed, with the given number of tokens left in the bucket.
+    Allowed {
+        /// The number of tokens left in the bucket.
+        remaining: u32,
+    },
+    /// The bucket is empty, and will have a token again after the given duration.
+    Limited {
+        /// The time until the bucket has a token again.
+        retry_after: Duration,
+    },
+}
+
+/// Type alias for the trait objects returned by `RateLimitStore`.
+pub type AcquireFuture = dyn Future<Output = anyhow::Result<Decision>> + Send;
+
+/// A `RateLimitStore` keeps the token buckets of all keys, e.g. in memory or in a store shared by
+/// several servers.
+pub trait RateLimitStore: Send + Sync + RefUnwindSafe {
+    /// Takes a token from the bucket of `key`, which is created full if it doesn't exist yet.
+    ///
+    /// `now` is the current time as seen by the request, see `helpers::clock`. Stores shared by
+    /// several servers may use their own clock instead.
+    fn acquire(&self, key: &str, quota: Quota, now: Instant) -> Pin<Box<AcquireFuture>>;
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A `RateLimitStore` keeping the token buckets in memory, which limits the rate of requests per
+/// server.
+///
+/// Buckets which are full again are dropped once the store holds more than `max_keys` buckets,
+/// so clients which stopped sending requests don't occupy memory. Dropping them takes time
+/// proportional to the number of buckets, so if too few can be dropped, the store waits until it
+/// has doubled in size before trying again.
+pub struct MemoryStore {
+    max_keys: usize,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    map: HashMap<String, Bucket>,
+    // the number of buckets at which full buckets are dropped next
+    sweep_at: usize,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        MemoryStore {
+            max_keys: DEFAULT_MAX_KEYS,
+            buckets: Mutex::new(Buckets {
+                map: HashMap::new(),
+                sweep_at: DEFAULT_MAX_KEYS,
+            }),
+        }
+    }
+
+    /// Sets the number of buckets above which full buckets are dropped (defaults to 100,000).
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys;
+        self.buckets.get_mut().unwrap().sweep_at = max_keys;
+        self
+    }
+
+    fn take(&self, key: &str, quota: Quota, now: Instant) -> Decision {
+        let burst = f64::from(quota.burst);
+        let rate = 1.0 / quota.interval.as_secs_f64().max(f64::EPSILON);
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * rate).min(burst)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { map, sweep_at } = &mut *buckets;
+        if map.len() >= *sweep_at && !map.contains_key(key) {
+            map.retain(|_, bucket| refill(bucket) < burst);
+            *sweep_at = self.max_keys.max(map.len() * 2);
+        }
+        let bucket = map.entry(key.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allowed {
+                remaining: bucket.tokens as u32,
+            }
+        } else {
+            Decision::Limited {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / rate),
+            }
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new()
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("max_keys", &self.max_keys)
+            .field("len", &self.buckets.lock().unwrap().map.len())
+            .finish()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire(&self, key: &str, quota: Quota, now: Instant) -> Pin<Box<AcquireFuture>> {
+        future::ok(self.take(key, quota, now)).boxed()
+    }
+}
+
+type Key = dyn Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe;
+
+/// A `Middleware` which limits the rate of requests per client, answering requests above the
+/// limit with `429 Too Many Requests`.
+///
+/// Clients are identified by their IP address, or by the key returned by a function set with
+/// `with_key`, e.g. the ID of an authenticated user. Requests without a key aren't limited. If
+/// the store fails, the request is passed on, so an outage of a shared store doesn't take down
+/// the application.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::rate_limit::{Quota, RateLimitMiddleware};
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// // 60 requests per minute, up to 10 of which at once
+/// let rate_limit = RateLimitMiddleware::new(Quota::per_minute(60).with_burst(10));
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(rate_limit).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RateLimitMiddleware {
+    quota: Quota,
+    store: Arc<dyn RateLimitStore>,
+    key: Arc<Key>,
+}
+
+impl RateLimitMiddleware {
+    /// Creates a new `RateLimitMiddleware` limiting requests per client IP address to the given
+    /// quota, keeping the buckets in a `MemoryStore`.
+    pub fn new(quota: Quota) -> Self {
+        RateLimitMiddleware {
+            quota,
+            store: Arc::new(MemoryStore::new()),
+            key: Arc::new(|state| client_addr(state).map(|addr| addr.ip().to_string())),
+        }
+    }
+
+    /// Sets the store keeping the buckets.
+    pub fn with_store<S: RateLimitStore + 'static>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Sets the function returning the key of the bucket a request takes its token from, or
+    /// `None` if the request isn't limited.
+    pub fn with_key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+}
+
+impl fmt::Debug for RateLimitMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitMiddleware")
+            .field("quota", &self.quota)
+            .finish()
+    }
+}
+
+impl NewMiddleware for RateLimitMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for RateLimitMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let key = match (self.key)(&state) {
+            Some(key) => key,
+            None => return chain(state),
+        };
+        let decision = self.store.acquire(&key, self.quota, clock::now(&state));
+        async move {
+            match decision.await {
+                Ok(Decision::Limited { retry_after }) => {
+                    debug!("[{}] rate limit of {} exceeded", request_id(&state), key);
+                    let mut response = create_empty_response(&state, StatusCode::TOO_MANY_REQUESTS);
+                    // Retry-After has a resolution of seconds, so round up
+                    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    response
+                        .headers_mut()
+                        .insert(RETRY_AFTER, HeaderValue::from(secs));
+                    Ok((state, response))
+                }
+                Ok(_) => chain(state).await,
+                Err(err) => {
+                    warn!(
+                        "[{}] failed to apply rate limit: {}",
+                        request_id(&state),
+                        err
+                    );
+                    chain(state).await
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::clock::MockClock;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::state::FromState;
+    use crate::test::TestServer;
+    use hyper::HeaderMap;
+
+    #[test]
+    fn refills_buckets() {
+        let store = MemoryStore::new().with_max_keys(1);
+        let quota = Quota::per_second(2);
+        let now = Instant::now();
+        assert_eq!(
+            store.take("a", quota, now),
+            Decision::Allowed { remaining: 1 }
+        );
+        assert_eq!(
+            store.take("a", quota, now),
+            Decision::Allowed { remaining: 0 }
+        );
+        assert_eq!(
+            store.take("a", quota, now),
+            Decision::Limited {
+                retry_after: Duration::from_millis(500)
+            }
+        );
+        let later = now + Duration::from_millis(500);
+        assert_eq!(
+            store.take("a", quota, later),
+            Decision::Allowed { remaining: 0 }
+        );
+
+        // full buckets make room for new keys
+        let much_later = later + Duration::from_secs(10);
+        assert_eq!(
+            store.take("b", quota, much_later),
+            Decision::Allowed { remaining: 1 }
+        );
+        assert_eq!(store.buckets.lock().unwrap().map.len(), 1);
+    }
+
+    #[test]
+    fn sweeps_again_once_doubled() {
+        let store = MemoryStore::new().with_max_keys(2);
+        let quota = Quota::per_hour(2);
+        let now = Instant::now();
+        for key in ["a", "b", "c", "d"] {
+            store.take(key, quota, now);
+        }
+        // none of the buckets is full, so the store grows until it has doubled
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.map.len(), 4);
+        assert_eq!(buckets.sweep_at, 4);
+    }
+
+    fn handler(state: State) -> (State, &'static str) {
+        (state, "ok")
+    }
+
+    #[test]
+    fn limits_requests() {
+        let rate_limit = RateLimitMiddleware::new(Quota::per_hour(2)).with_key(|state| {
+            HeaderMap::borrow_from(state)
+                .get("x-api-key")
+                .and_then(|key| key.to_str().ok())
+                .map(ToOwned::to_owned)
+        });
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(rate_limit).build());
+        let clock = MockClock::new();
+        let test_server = TestServer::with_mock_clock(
+            build_router(chain, pipelines, |route| {
+                route.get("/").to(handler);
+            }),
+            clock.clone(),
+        )
+        .unwrap();
+        let get = |key: Option<&str>| {
+            let client = test_server.client();
+            let mut request = client.get("http://localhost/");
+            if let Some(key) = key {
+                request = request.with_header("x-api-key", key.parse().unwrap());
+            }
+            request.perform().unwrap()
+        };
+
+        assert_eq!(get(Some("a")).status(), StatusCode::OK);
+        assert_eq!(get(Some("a")).status(), StatusCode::OK);
+        let response = get(Some("a"));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 1700 && retry_after <= 1800);
+
+        // other keys have their own bucket, and requests without a key aren't limited
+        assert_eq!(get(Some("b")).status(), StatusCode::OK);
+        for _ in 0..3 {
+            assert_eq!(get(None).status(), StatusCode::OK);
+        }
+
+        // buckets refill as the request's clock moves on
+        clock.advance(Duration::from_secs(1800));
+        assert_eq!(get(Some("a")).status(), StatusCode::OK);
+    }
+}
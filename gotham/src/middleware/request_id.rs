@@ -0,0 +1,154 @@
+//! Propagation of request IDs to responses.
+//!
+//! Every request is assigned a `RequestId` before the `Router` is invoked, which is taken from the
+//! `X-Request-ID` header of the request if present, so a request can be followed through the
+//! logs of all services it passes. The `RequestIdMiddleware` returns the ID to the client in the
+//! response, so the client can refer to the request as well.
+use std::pin::Pin;
+
+use futures_util::future::{FutureExt, TryFutureExt};
+use hyper::header::{HeaderName, HeaderValue};
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::header::X_REQUEST_ID;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, State};
+
+/// A `Middleware` which adds the ID of the request to the response headers.
+///
+/// Responses created with the `create_response` helpers already carry an `X-Request-ID` header,
+/// which is kept. Other responses, like ones built by hand, get the header added.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// # use gotham::hyper::{Body, Response};
+/// use gotham::middleware::request_id::RequestIdMiddleware;
+///
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("built by hand")))
+/// }
+///
+/// # fn main() {
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(RequestIdMiddleware::new()).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .get("http://localhost/")
+/// #     .with_header("x-request-id", "abc".parse().unwrap())
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.headers()["x-request-id"], "abc");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestIdMiddleware {
+    header: HeaderName,
+}
+
+impl RequestIdMiddleware {
+    /// Creates a new `RequestIdMiddleware` adding the `X-Request-ID` header.
+    pub fn new() -> Self {
+        RequestIdMiddleware {
+            header: HeaderName::from_static(X_REQUEST_ID),
+        }
+    }
+
+    /// Sets the name of the response header carrying the request ID, e.g. `x-correlation-id`
+    /// (defaults to `x-request-id`). The name must be lowercase.
+    pub fn with_header(mut self, name: &'static str) -> Self {
+        self.header = HeaderName::from_static(name);
+        self
+    }
+}
+
+impl Default for RequestIdMiddleware {
+    fn default() -> Self {
+        RequestIdMiddleware::new()
+    }
+}
+
+impl NewMiddleware for RequestIdMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for RequestIdMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        chain(state)
+            .map_ok(move |(state, mut response)| {
+                if !response.headers().contains_key(&self.header) {
+                    // request IDs are always visible ASCII
+                    if let Ok(value) = HeaderValue::from_str(request_id(&state)) {
+                        response.headers_mut().insert(self.header, value);
+                    }
+                }
+                (state, response)
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::{Body, Response};
+
+    fn handler(state: State) -> (State, Response<Body>) {
+        (state, Response::new(Body::empty()))
+    }
+
+    #[test]
+    fn adds_request_id_to_responses() {
+        let middleware = RequestIdMiddleware::new().with_header("x-correlation-id");
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header(X_REQUEST_ID, "1-2-3".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.headers()["x-correlation-id"], "1-2-3");
+
+        // generated IDs differ between requests
+        let id = |response: crate::test::TestResponse| {
+            response.headers()["x-correlation-id"]
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+        let first = id(test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap());
+        let second = id(test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap());
+        assert!(!first.is_empty());
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,195 @@
+//! Post-processing of responses without future combinators.
+//!
+//! Middleware which only needs to inspect or change the response after the handler ran, like
+//! adding headers or recording the size of the body, implements `ResponseMiddleware` and is added
+//! to a pipeline wrapped in `OnResponse`, which takes care of waiting for the handler.
+use std::fmt;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::FutureExt;
+use hyper::{Body, Response};
+
+use crate::handler::{HandlerFuture, IntoResponse};
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::State;
+
+/// Processes the response to a request after the handler and the middleware after it in the
+/// pipeline ran.
+///
+/// Functions and closures taking the `State` and response can be used as `ResponseMiddleware`.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// use gotham::hyper::body::HttpBody;
+/// use gotham::hyper::header::{HeaderValue, CACHE_CONTROL};
+/// use gotham::hyper::{Body, Response};
+/// use gotham::middleware::response::OnResponse;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "hello")
+/// # }
+/// #
+/// fn no_store(_state: &mut State, response: &mut Response<Body>) {
+///     // the size of the body, unless it is streamed
+///     if response.body().size_hint().exact().is_some() {
+///         response
+///             .headers_mut()
+///             .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+///     }
+/// }
+///
+/// # fn main() {
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(OnResponse::new(no_store)).build());
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// # assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+/// # }
+/// ```
+pub trait ResponseMiddleware: Send + Sync + RefUnwindSafe {
+    /// Inspects or changes the response to the request in `state`.
+    fn on_response(&self, state: &mut State, response: &mut Response<Body>);
+}
+
+impl<F> ResponseMiddleware for F
+where
+    F: Fn(&mut State, &mut Response<Body>) + Send + Sync + RefUnwindSafe,
+{
+    fn on_response(&self, state: &mut State, response: &mut Response<Body>) {
+        self(state, response)
+    }
+}
+
+/// A `Middleware` which passes the response to every request to a `ResponseMiddleware`.
+///
+/// A `HandlerError` returned by the handler or later middleware is converted into its response
+/// before it is passed on, so the `ResponseMiddleware` also processes error responses, and
+/// middleware earlier in the pipeline receive the processed response instead of the error.
+pub struct OnResponse<M> {
+    middleware: Arc<M>,
+}
+
+impl<M: ResponseMiddleware> OnResponse<M> {
+    /// Wraps `middleware` for adding it to a pipeline.
+    pub fn new(middleware: M) -> Self {
+        OnResponse {
+            middleware: Arc::new(middleware),
+        }
+    }
+}
+
+impl<M> Clone for OnResponse<M> {
+    fn clone(&self) -> Self {
+        OnResponse {
+            middleware: self.middleware.clone(),
+        }
+    }
+}
+
+impl<M> fmt::Debug for OnResponse<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnResponse").finish()
+    }
+}
+
+impl<M: ResponseMiddleware + 'static> NewMiddleware for OnResponse<M> {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<M: ResponseMiddleware + 'static> Middleware for OnResponse<M> {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        chain(state)
+            .map(move |result| {
+                let (mut state, mut response) = match result {
+                    Ok((state, response)) => (state, response),
+                    Err((state, err)) => {
+                        let response = err.into_response(&state);
+                        (state, response)
+                    }
+                };
+                self.middleware.on_response(&mut state, &mut response);
+                Ok((state, response))
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::HandlerError;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::body::HttpBody;
+    use hyper::header::HeaderValue;
+    use hyper::StatusCode;
+    use std::sync::Mutex;
+
+    fn hello(state: State) -> (State, &'static str) {
+        (state, "hello")
+    }
+
+    async fn fail(state: State) -> Result<(State, Response<Body>), (State, HandlerError)> {
+        let err = HandlerError::from(anyhow::anyhow!("failed")).with_status(StatusCode::CONFLICT);
+        Err((state, err))
+    }
+
+    #[test]
+    fn processes_responses_and_errors() {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let middleware = OnResponse::new({
+            let sizes = sizes.clone();
+            move |_state: &mut State, response: &mut Response<Body>| {
+                sizes
+                    .lock()
+                    .unwrap()
+                    .push((response.status(), response.body().size_hint().exact()));
+                response
+                    .headers_mut()
+                    .insert("x-processed", HeaderValue::from_static("1"));
+            }
+        });
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(hello);
+            route.get("/fail").to_async(fail);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.headers()["x-processed"], "1");
+        assert_eq!(response.read_utf8_body().unwrap(), "hello");
+
+        let response = test_server
+            .client()
+            .get("http://localhost/fail")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(response.headers()["x-processed"], "1");
+
+        assert_eq!(
+            *sizes.lock().unwrap(),
+            vec![(StatusCode::OK, Some(5)), (StatusCode::CONFLICT, Some(0))]
+        );
+    }
+}
@@ -12,40 +12,163 @@
 //!
 //! More may be added in future, but these headers provide compatibility with
 //! previous versions of Gotham.
+//!
+//! A Content-Security-Policy can be configured in addition. When the policy
+//! contains the `{nonce}` placeholder, a fresh `CspNonce` is generated for
+//! every request and put into `State`, so templates can render inline
+//! scripts and styles with a `nonce` attribute matching the policy.
 use crate::handler::HandlerFuture;
 use crate::middleware::{Middleware, NewMiddleware};
-use crate::state::State;
+use crate::state::{State, StateData};
 
+use base64::prelude::*;
 use futures_util::future::{self, FutureExt, TryFutureExt};
-use hyper::header::{HeaderValue, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS, X_XSS_PROTECTION};
+use hyper::header::{
+    HeaderValue, CONTENT_SECURITY_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS, X_XSS_PROTECTION,
+};
+use log::warn;
+use std::fmt;
 use std::pin::Pin;
+use std::sync::Arc;
 
 // constant strings to be used as header values
 const XFO_VALUE: &str = "DENY";
 const XXP_VALUE: &str = "1; mode=block";
 const XCTO_VALUE: &str = "nosniff";
 
+// placeholder in the policy which is replaced by the nonce source of a request
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// A random value generated for every request by the `SecurityMiddleware` when its
+/// Content-Security-Policy contains a `{nonce}` placeholder.
+///
+/// Inline scripts and styles are allowed by the policy when rendered with the nonce:
+///
+/// ```rust
+/// # use gotham::state::{FromState, State};
+/// use gotham::middleware::security::CspNonce;
+///
+/// # #[allow(dead_code)]
+/// fn handler(state: State) -> (State, String) {
+///     let body = format!(
+///         "<script nonce=\"{}\">console.log('hello')</script>",
+///         CspNonce::borrow_from(&state)
+///     );
+///     (state, body)
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    /// Generates a new nonce from 16 random bytes.
+    pub fn generate() -> Self {
+        CspNonce(BASE64_STANDARD.encode(rand::random::<[u8; 16]>()))
+    }
+
+    /// Returns the value for the `nonce` attribute of an inline script or style.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the source expression for the nonce in a Content-Security-Policy.
+    pub fn source(&self) -> String {
+        format!("'nonce-{}'", self.0)
+    }
+}
+
+impl fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StateData for CspNonce {}
+
 /// Middleware binding for the Gotham security handlers.
 ///
-/// This acts as nothing more than a trait implementation for the time
-/// being; there are no fields on the struct in use (yet).
-#[derive(Clone)]
-pub struct SecurityMiddleware;
+/// Without further configuration, only the headers listed in the module
+/// documentation are set.
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::middleware::security::SecurityMiddleware;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let security = SecurityMiddleware::new()
+///     .with_content_security_policy("default-src 'self'; script-src 'self' {nonce}");
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(security).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SecurityMiddleware {
+    content_security_policy: Option<Arc<str>>,
+}
+
+impl SecurityMiddleware {
+    /// Creates a new `SecurityMiddleware` setting the default security headers.
+    pub fn new() -> Self {
+        SecurityMiddleware::default()
+    }
+
+    /// Sets the `Content-Security-Policy` header of responses which don't have one already.
+    ///
+    /// Every `{nonce}` placeholder in `policy` is replaced by the source expression of a
+    /// `CspNonce`, which is generated for each request and put into `State`.
+    pub fn with_content_security_policy<S: Into<String>>(mut self, policy: S) -> Self {
+        self.content_security_policy = Some(policy.into().into());
+        self
+    }
+}
 
 /// `Middleware` trait implementation.
 impl Middleware for SecurityMiddleware {
     /// Attaches security headers to the response.
-    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
     where
         Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
     {
-        let f = chain(state).and_then(|(state, mut response)| {
+        let policy = self.content_security_policy;
+        if policy
+            .as_deref()
+            .is_some_and(|policy| policy.contains(NONCE_PLACEHOLDER))
+        {
+            state.put(CspNonce::generate());
+        }
+
+        let f = chain(state).and_then(move |(state, mut response)| {
             {
                 let headers = response.headers_mut();
 
                 headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static(XFO_VALUE));
                 headers.insert(X_XSS_PROTECTION, HeaderValue::from_static(XXP_VALUE));
                 headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static(XCTO_VALUE));
+
+                if let Some(policy) =
+                    policy.filter(|_| !headers.contains_key(CONTENT_SECURITY_POLICY))
+                {
+                    let policy = match state.try_borrow::<CspNonce>() {
+                        Some(nonce) => policy.replace(NONCE_PLACEHOLDER, &nonce.source()),
+                        None => policy.to_string(),
+                    };
+                    match HeaderValue::from_str(&policy) {
+                        Ok(value) => {
+                            headers.insert(CONTENT_SECURITY_POLICY, value);
+                        }
+                        Err(_) => warn!("invalid Content-Security-Policy: {}", policy),
+                    }
+                }
             }
             future::ok((state, response))
         });
@@ -63,3 +186,64 @@ impl NewMiddleware for SecurityMiddleware {
         Ok(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::state::FromState;
+    use crate::test::TestServer;
+    use hyper::{Body, Response};
+
+    fn nonced(state: State) -> (State, String) {
+        let nonce = CspNonce::borrow_from(&state).to_string();
+        (state, nonce)
+    }
+
+    fn own_policy(state: State) -> (State, Response<Body>) {
+        let response = Response::builder()
+            .header(CONTENT_SECURITY_POLICY, "default-src 'none'")
+            .body(Body::empty())
+            .unwrap();
+        (state, response)
+    }
+
+    #[test]
+    fn sets_policy_with_request_nonce() {
+        let security = SecurityMiddleware::new()
+            .with_content_security_policy("script-src 'self' {nonce}; style-src {nonce}");
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(security).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(nonced);
+            route.get("/own").to(own_policy);
+        }))
+        .unwrap();
+        let client = test_server.client();
+
+        let response = client.get("http://localhost/").perform().unwrap();
+        assert_eq!(response.headers()[X_FRAME_OPTIONS], XFO_VALUE);
+        let policy = response.headers()[CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let nonce = response.read_utf8_body().unwrap();
+        assert_eq!(BASE64_STANDARD.decode(&nonce).unwrap().len(), 16);
+        assert_eq!(
+            policy,
+            format!(
+                "script-src 'self' 'nonce-{}'; style-src 'nonce-{}'",
+                nonce, nonce
+            )
+        );
+
+        let response = client.get("http://localhost/").perform().unwrap();
+        assert_ne!(response.read_utf8_body().unwrap(), nonce);
+
+        let response = client.get("http://localhost/own").perform().unwrap();
+        assert_eq!(
+            response.headers()[CONTENT_SECURITY_POLICY],
+            "default-src 'none'"
+        );
+    }
+}
@@ -7,6 +7,7 @@ use futures_util::future::{self, FutureExt};
 use linked_hash_map::LinkedHashMap;
 use log::trace;
 
+use crate::helpers::clock;
 use crate::middleware::session::backend::{
     Backend, GetSessionFuture, NewBackend, SetSessionFuture,
 };
@@ -36,6 +37,7 @@ pub struct MemoryBackend {
     // might show a need to replace this with a smarter implementation, but today there's very
     // little overhead here.
     storage: Arc<MemoryMap>,
+    ttl: Duration,
 }
 
 impl MemoryBackend {
@@ -62,7 +64,7 @@ impl MemoryBackend {
             thread::spawn(move || cleanup_loop(storage, ttl));
         }
 
-        MemoryBackend { storage }
+        MemoryBackend { storage, ttl }
     }
 }
 
@@ -83,13 +85,13 @@ impl NewBackend for MemoryBackend {
 impl Backend for MemoryBackend {
     fn persist_session(
         &self,
-        _: &State,
+        state: &State,
         identifier: SessionIdentifier,
         content: &[u8],
     ) -> Pin<Box<SetSessionFuture>> {
         match self.storage.lock() {
             Ok(mut storage) => {
-                storage.insert(identifier.value, (Instant::now(), Vec::from(content)));
+                storage.insert(identifier.value, (clock::now(state), Vec::from(content)));
                 Box::pin(future::ok(()))
             }
             Err(PoisonError { .. }) => {
@@ -98,11 +100,21 @@ impl Backend for MemoryBackend {
         }
     }
 
-    fn read_session(&self, _: &State, identifier: SessionIdentifier) -> Pin<Box<GetSessionFuture>> {
+    fn read_session(
+        &self,
+        state: &State,
+        identifier: SessionIdentifier,
+    ) -> Pin<Box<GetSessionFuture>> {
+        let now = clock::now(state);
         match self.storage.lock() {
             Ok(mut storage) => match storage.get_refresh(&identifier.value) {
+                // expired sessions may not have been cleaned up yet
+                Some(&mut (instant, _)) if now.saturating_duration_since(instant) >= self.ttl => {
+                    storage.remove(&identifier.value);
+                    future::ok(None).boxed()
+                }
                 Some(&mut (ref mut instant, ref value)) => {
-                    *instant = Instant::now();
+                    *instant = now;
                     future::ok(Some(value.clone())).boxed()
                 }
                 None => future::ok(None).boxed(),
@@ -196,6 +208,7 @@ fn cleanup_once(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::helpers::clock::MockClock;
 
     #[test]
     fn cleanup_test() {
@@ -307,4 +320,31 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn expires_sessions_by_request_clock() {
+        let backend = MemoryBackend::new(Duration::from_secs(60));
+        let clock = MockClock::new();
+        let mut state = State::new();
+        state.put(clock.clone());
+        let identifier = SessionIdentifier {
+            value: "mock_clock_identifier".to_owned(),
+        };
+
+        futures_executor::block_on(backend.persist_session(&state, identifier.clone(), b"data"))
+            .expect("failed to persist");
+
+        clock.advance(Duration::from_secs(59));
+        let read = futures_executor::block_on(backend.read_session(&state, identifier.clone()));
+        assert_eq!(read.expect("failed to read"), Some(b"data".to_vec()));
+
+        // reading refreshed the session
+        clock.advance(Duration::from_secs(59));
+        let read = futures_executor::block_on(backend.read_session(&state, identifier.clone()));
+        assert!(read.expect("failed to read").is_some());
+
+        clock.advance(Duration::from_secs(60));
+        let read = futures_executor::block_on(backend.read_session(&state, identifier));
+        assert_eq!(read.expect("failed to read"), None);
+    }
 }
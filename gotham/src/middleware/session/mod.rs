@@ -25,10 +25,13 @@ use crate::state::{self, FromState, State, StateData};
 
 mod backend;
 mod rng;
+mod version;
 
 pub use self::backend::memory::MemoryBackend;
 pub use self::backend::{Backend, GetSessionFuture, NewBackend, SetSessionFuture};
 
+use self::version::{Decoded, SessionVersioning};
+
 const SECURE_COOKIE_PREFIX: &str = "__Secure-";
 const HOST_COOKIE_PREFIX: &str = "__Host-";
 
@@ -273,6 +276,7 @@ where
     identifier: SessionIdentifier,
     backend: Box<dyn Backend + Send>,
     cookie_config: Arc<SessionCookieConfig>,
+    versioning: Arc<SessionVersioning<T>>,
 }
 
 struct SessionDropData {
@@ -306,6 +310,7 @@ where
         let value = T::default();
         let backend = Box::new(middleware.backend);
         let cookie_config = middleware.cookie_config;
+        let versioning = middleware.versioning;
 
         trace!(
             " no existing session, assigning new identifier ({})",
@@ -319,6 +324,7 @@ where
             identifier,
             backend,
             cookie_config,
+            versioning,
         }
     }
 
@@ -332,41 +338,44 @@ where
         B: Backend + Send + 'static,
     {
         let cookie_state = SessionCookieState::Existing;
-        let state = SessionDataState::Clean;
-
-        match val {
-            Some(val) => {
-                match bincode::deserialize::<T>(&val[..]) {
-                    Ok(value) => {
-                        let backend = Box::new(middleware.backend);
-                        let cookie_config = middleware.cookie_config;
-
-                        trace!(
-                            " successfully deserialized session data ({})",
-                            identifier.value
-                        );
-
-                        SessionData {
-                            value,
-                            cookie_state,
-                            state,
-                            identifier,
-                            backend,
-                            cookie_config,
-                        }
-                    }
-                    Err(_) => {
-                        // This is most likely caused by the application changing their session
-                        // struct but the backend not being purged of sessions.
-                        warn!(
-                            " failed to deserialize session data ({}), falling back to new session",
-                            identifier.value
-                        );
-                        SessionData::new(middleware)
-                    }
-                }
+
+        let val = match val {
+            Some(val) => val,
+            None => return SessionData::new(middleware),
+        };
+        let (value, state) = match middleware.versioning.decode(&val[..]) {
+            Decoded::Current(value) => {
+                trace!(
+                    " successfully deserialized session data ({})",
+                    identifier.value
+                );
+                (value, SessionDataState::Clean)
             }
-            None => SessionData::new(middleware),
+            Decoded::Migrated(value) => {
+                // Persist the migrated session, so it is stored with the current version
+                trace!(" successfully migrated session data ({})", identifier.value);
+                (value, SessionDataState::Dirty)
+            }
+            Decoded::Invalid => {
+                // This is most likely caused by the application changing their session struct
+                // but the backend not being purged of sessions, and no migration being
+                // registered for the stored version.
+                warn!(
+                    " failed to deserialize session data ({}), falling back to new session",
+                    identifier.value
+                );
+                return SessionData::new(middleware);
+            }
+        };
+
+        SessionData {
+            value,
+            cookie_state,
+            state,
+            identifier,
+            backend: Box::new(middleware.backend),
+            cookie_config: middleware.cookie_config,
+            versioning: middleware.versioning,
         }
     }
 }
@@ -461,6 +470,7 @@ where
     new_backend: B,
     identifier_rng: Arc<Mutex<rng::SessionIdentifierRng>>,
     cookie_config: Arc<SessionCookieConfig>,
+    versioning: Arc<SessionVersioning<T>>,
     phantom: PhantomData<dyn SessionTypePhantom<T>>,
 }
 
@@ -475,6 +485,7 @@ where
     backend: B,
     identifier_rng: Arc<Mutex<rng::SessionIdentifierRng>>,
     cookie_config: Arc<SessionCookieConfig>,
+    versioning: Arc<SessionVersioning<T>>,
     phantom: PhantomData<T>,
 }
 
@@ -492,6 +503,7 @@ where
                 backend,
                 identifier_rng: self.identifier_rng.clone(),
                 cookie_config: self.cookie_config.clone(),
+                versioning: self.versioning.clone(),
                 phantom: PhantomData,
             })
     }
@@ -507,6 +519,7 @@ where
             new_backend: self.new_backend.clone(),
             identifier_rng: self.identifier_rng.clone(),
             cookie_config: self.cookie_config.clone(),
+            versioning: self.versioning.clone(),
             phantom: PhantomData,
         }
     }
@@ -529,6 +542,7 @@ where
             new_backend: b,
             identifier_rng: Arc::new(Mutex::new(rng::session_identifier_rng())),
             cookie_config: Arc::new(SessionCookieConfig::default()),
+            versioning: Arc::new(SessionVersioning::default()),
             phantom: PhantomData,
         }
     }
@@ -776,9 +790,89 @@ where
             new_backend: self.new_backend,
             identifier_rng: self.identifier_rng,
             cookie_config: self.cookie_config,
+            versioning: Arc::new(SessionVersioning::default()),
             phantom: PhantomData,
         }
     }
+
+    /// Sets the version of the session type, which is stored along with the session data
+    /// (defaults to 0). When the session type changes, increasing the version and registering a
+    /// migration with `with_session_migration` upgrades existing sessions instead of discarding
+    /// them. Sessions of version 0 are stored in the same format as before versions were
+    /// introduced.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// #
+    /// # use gotham::middleware::session::NewSessionMiddleware;
+    /// # use serde::{Deserialize, Serialize};
+    /// #
+    /// #[derive(Default, Serialize, Deserialize)]
+    /// struct MySessionType {
+    ///     items: Vec<String>,
+    /// }
+    ///
+    /// # fn main() {
+    /// NewSessionMiddleware::default()
+    ///     .with_session_type::<MySessionType>()
+    ///     .with_session_version(1)
+    /// # ;}
+    /// ```
+    pub fn with_session_version(self, version: u32) -> NewSessionMiddleware<B, T> {
+        let mut versioning = (*self.versioning).clone();
+        versioning.set_version(version);
+        NewSessionMiddleware {
+            versioning: Arc::new(versioning),
+            ..self
+        }
+    }
+
+    /// Registers a migration for sessions stored with an earlier version of the session type.
+    /// Such sessions are deserialized as the previous type `O`, converted by the migration and
+    /// stored again with the current version. Sessions of a version without a migration are
+    /// discarded, and a new session is started.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// #
+    /// # use gotham::middleware::session::NewSessionMiddleware;
+    /// # use serde::{Deserialize, Serialize};
+    /// #
+    /// #[derive(Deserialize)]
+    /// struct MySessionTypeV0 {
+    ///     item: String,
+    /// }
+    ///
+    /// #[derive(Default, Serialize, Deserialize)]
+    /// struct MySessionType {
+    ///     items: Vec<String>,
+    /// }
+    ///
+    /// # fn main() {
+    /// NewSessionMiddleware::default()
+    ///     .with_session_type::<MySessionType>()
+    ///     .with_session_version(1)
+    ///     .with_session_migration(0, |old: MySessionTypeV0| MySessionType {
+    ///         items: vec![old.item],
+    ///     })
+    /// # ;}
+    /// ```
+    pub fn with_session_migration<O, F>(
+        self,
+        from_version: u32,
+        migrate: F,
+    ) -> NewSessionMiddleware<B, T>
+    where
+        O: for<'de> Deserialize<'de>,
+        F: Fn(O) -> T + Send + Sync + RefUnwindSafe + 'static,
+    {
+        let mut versioning = (*self.versioning).clone();
+        versioning.add_migration(from_version, migrate);
+        NewSessionMiddleware {
+            versioning: Arc::new(versioning),
+            ..self
+        }
+    }
 }
 
 impl<B, T> Middleware for SessionMiddleware<B, T>
@@ -927,7 +1021,7 @@ fn write_session<T>(
 where
     T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
 {
-    let bytes = match bincode::serialize(&session_data.value) {
+    let bytes = match session_data.versioning.encode(&session_data.value) {
         Ok(bytes) => bytes,
         Err(e) => {
             error!(
@@ -1000,10 +1094,7 @@ where
                     e
                 );
 
-                let e = io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("backend failed to return session: {:?}", e),
-                );
+                let e = io::Error::other(format!("backend failed to return session: {:?}", e));
 
                 future::err((state, e.into()))
             }
@@ -1249,4 +1340,57 @@ mod tests {
         let data = futures_executor::block_on(m.backend.read_session(&state, identifier)).unwrap();
         assert_eq!(data, None);
     }
+
+    #[test]
+    fn migrated_session() {
+        #[derive(Serialize, Deserialize)]
+        struct LegacySession {
+            val: u32,
+        }
+
+        let nm = NewSessionMiddleware::default()
+            .with_session_type::<TestSession>()
+            .with_session_version(1)
+            .with_session_migration(0, |old: LegacySession| TestSession {
+                val: u64::from(old.val) * 2,
+            });
+        let m = nm.new_middleware().unwrap();
+        let mut state = State::new();
+
+        let identifier = m.random_identifier();
+        let bytes = bincode::serialize(&LegacySession { val: 21 }).unwrap();
+        futures_executor::block_on(
+            m.backend
+                .persist_session(&state, identifier.clone(), &bytes),
+        )
+        .unwrap();
+
+        let received: Arc<Mutex<Option<u64>>> = Arc::new(Mutex::new(None));
+        let r = received.clone();
+        let handler = move |state: State| {
+            *r.lock().unwrap() = Some(state.borrow::<SessionData<TestSession>>().val);
+            future::ok((state, Response::new(Body::empty()))).boxed()
+        };
+
+        let mut headers = HeaderMap::new();
+        let cookie = Cookie::build("_gotham_session", identifier.value.clone()).finish();
+        headers.insert(COOKIE, cookie.to_string().parse().unwrap());
+        state.put(headers);
+
+        if let Err((_, e)) = futures_executor::block_on(m.call(state, handler)) {
+            panic!("error: {:?}", e);
+        }
+        assert_eq!(*received.lock().unwrap(), Some(42));
+
+        // the migrated session was stored with the current version
+        let state = State::new();
+        let m = nm.new_middleware().unwrap();
+        let bytes = futures_executor::block_on(m.backend.read_session(&state, identifier))
+            .unwrap()
+            .unwrap();
+        match m.versioning.decode(&bytes) {
+            Decoded::Current(session) => assert_eq!(session.val, 42),
+            _ => panic!("session was not stored with the current version"),
+        }
+    }
 }
@@ -0,0 +1,173 @@
+//! Versioning of serialized session data, so sessions of an earlier session type can be migrated.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+// Prefixes serialized session data of a version other than 0, followed by the version as a
+// little-endian `u32`. Session data of version 0 is stored without a prefix, as it was before
+// versions were introduced.
+const VERSION_PREFIX: &[u8; 4] = b"\xffGSV";
+
+type Migration<T> = Arc<dyn Fn(&[u8]) -> Option<T> + Send + Sync + RefUnwindSafe>;
+
+/// The outcome of reading serialized session data.
+pub(super) enum Decoded<T> {
+    /// The session data was stored with the current version.
+    Current(T),
+    /// The session data was stored with an earlier version, and has been migrated.
+    Migrated(T),
+    /// The session data could not be read, or there is no migration for its version.
+    Invalid,
+}
+
+/// The current version of a session type, and the migrations from earlier versions.
+pub(super) struct SessionVersioning<T> {
+    version: u32,
+    migrations: HashMap<u32, Migration<T>>,
+}
+
+impl<T> Default for SessionVersioning<T> {
+    fn default() -> Self {
+        SessionVersioning {
+            version: 0,
+            migrations: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Clone for SessionVersioning<T> {
+    fn clone(&self) -> Self {
+        SessionVersioning {
+            version: self.version,
+            migrations: self.migrations.clone(),
+        }
+    }
+}
+
+impl<T> SessionVersioning<T>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    pub(super) fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    pub(super) fn add_migration<O, F>(&mut self, from_version: u32, migrate: F)
+    where
+        O: for<'de> Deserialize<'de>,
+        F: Fn(O) -> T + Send + Sync + RefUnwindSafe + 'static,
+    {
+        let migration = move |bytes: &[u8]| bincode::deserialize::<O>(bytes).ok().map(&migrate);
+        self.migrations.insert(from_version, Arc::new(migration));
+    }
+
+    pub(super) fn encode(&self, value: &T) -> bincode::Result<Vec<u8>> {
+        let payload = bincode::serialize(value)?;
+        if self.version == 0 {
+            return Ok(payload);
+        }
+        let mut bytes = Vec::with_capacity(VERSION_PREFIX.len() + 4 + payload.len());
+        bytes.extend_from_slice(VERSION_PREFIX);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    pub(super) fn decode(&self, bytes: &[u8]) -> Decoded<T> {
+        let (version, payload) = split_version(bytes);
+        if version == self.version {
+            return match bincode::deserialize(payload) {
+                Ok(value) => Decoded::Current(value),
+                Err(_) => Decoded::Invalid,
+            };
+        }
+        match self.migrations.get(&version).and_then(|m| m(payload)) {
+            Some(value) => Decoded::Migrated(value),
+            None => Decoded::Invalid,
+        }
+    }
+}
+
+// Splits serialized session data into its version and the serialized session value.
+fn split_version(bytes: &[u8]) -> (u32, &[u8]) {
+    let header_len = VERSION_PREFIX.len() + 4;
+    if bytes.len() >= header_len && bytes.starts_with(VERSION_PREFIX) {
+        let version = bytes[VERSION_PREFIX.len()..header_len].try_into().unwrap();
+        (u32::from_le_bytes(version), &bytes[header_len..])
+    } else {
+        (0, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct V1 {
+        count: u32,
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct V2 {
+        count: u64,
+        name: String,
+    }
+
+    #[test]
+    fn unversioned_sessions_are_unchanged() {
+        let versioning = SessionVersioning::<V1>::default();
+        let bytes = versioning.encode(&V1 { count: 3 }).unwrap();
+        assert_eq!(bytes, bincode::serialize(&V1 { count: 3 }).unwrap());
+        assert!(matches!(
+            versioning.decode(&bytes),
+            Decoded::Current(V1 { count: 3 })
+        ));
+    }
+
+    #[test]
+    fn migrates_earlier_versions() {
+        let legacy = bincode::serialize(&V1 { count: 3 }).unwrap();
+
+        let mut versioning = SessionVersioning::<V2>::default();
+        versioning.set_version(2);
+        versioning.add_migration(0, |old: V1| V2 {
+            count: u64::from(old.count),
+            name: "migrated".to_owned(),
+        });
+
+        match versioning.decode(&legacy) {
+            Decoded::Migrated(value) => {
+                assert_eq!(value.count, 3);
+                assert_eq!(value.name, "migrated");
+            }
+            _ => panic!("session was not migrated"),
+        }
+
+        let value = V2 {
+            count: 4,
+            name: "current".to_owned(),
+        };
+        let bytes = versioning.encode(&value).unwrap();
+        assert!(bytes.starts_with(VERSION_PREFIX));
+        match versioning.decode(&bytes) {
+            Decoded::Current(decoded) => assert_eq!(decoded, value),
+            _ => panic!("session was not read"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut old = SessionVersioning::<V1>::default();
+        old.set_version(1);
+        let bytes = old.encode(&V1 { count: 3 }).unwrap();
+
+        let mut versioning = SessionVersioning::<V2>::default();
+        versioning.set_version(2);
+        assert!(matches!(versioning.decode(&bytes), Decoded::Invalid));
+    }
+}
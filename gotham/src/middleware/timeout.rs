@@ -0,0 +1,227 @@
+//! Aborting requests which take too long.
+//!
+//! A handler waiting on a stuck backend holds on to its connection and memory for as long as the
+//! backend takes, or forever. The `TimeoutMiddleware` races the rest of the pipeline and the
+//! handler against a timer, and answers the request with `503 Service Unavailable` once the
+//! timeout elapsed, dropping the handler future so its work is cancelled.
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_util::future::FutureExt;
+use hyper::{HeaderMap, Method, StatusCode, Uri, Version};
+use log::warn;
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::deadline::Deadline;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::client_addr::put_client_addr;
+use crate::state::{client_addr, request_id, FromState, RequestId, State};
+
+/// A `Middleware` which answers requests with `503 Service Unavailable` if the rest of the
+/// pipeline and the handler don't respond within the timeout, or before the `Deadline` set by a
+/// `DeadlineMiddleware` earlier in the pipeline.
+///
+/// The `State` of the request is moved into the handler, and dropped with it on timeout. The
+/// response is created with a new `State` holding the method, URI, version, headers, request ID
+/// and client address of the request, so middleware earlier in the pipeline can still process
+/// it, but data placed into the `State` by later middleware or the handler is lost.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// use gotham::hyper::StatusCode;
+/// use gotham::middleware::timeout::TimeoutMiddleware;
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "")
+/// # }
+/// #
+/// # fn main() {
+/// let timeout = TimeoutMiddleware::new(Duration::from_secs(10))
+///     .with_status(StatusCode::GATEWAY_TIMEOUT);
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(timeout).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// # let _ = router;
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TimeoutMiddleware {
+    timeout: Duration,
+    status: StatusCode,
+}
+
+impl TimeoutMiddleware {
+    /// Creates a new `TimeoutMiddleware` answering requests not handled within `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        TimeoutMiddleware {
+            timeout,
+            status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Sets the status of responses to requests which timed out, e.g. `504 Gateway Timeout` for
+    /// handlers waiting on upstream services (defaults to `503 Service Unavailable`).
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl NewMiddleware for TimeoutMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for TimeoutMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let timeout = match Deadline::try_borrow_from(&state) {
+            Some(deadline) => deadline.bound_timeout(Some(self.timeout)),
+            None => self.timeout,
+        };
+        let head = RequestHead::from_state(&state);
+        let response = chain(state);
+
+        async move {
+            match tokio::time::timeout(timeout, response).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let state = head.into_state();
+                    warn!(
+                        "[{}] request timed out after {:?}",
+                        request_id(&state),
+                        timeout
+                    );
+                    let response = create_empty_response(&state, self.status);
+                    Ok((state, response))
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+// The request data kept to create a `State` for the response if the request times out.
+struct RequestHead {
+    method: Option<Method>,
+    uri: Option<Uri>,
+    version: Option<Version>,
+    headers: Option<HeaderMap>,
+    request_id: Option<RequestId>,
+    client_addr: Option<SocketAddr>,
+}
+
+impl RequestHead {
+    fn from_state(state: &State) -> Self {
+        RequestHead {
+            method: Method::try_borrow_from(state).cloned(),
+            uri: Uri::try_borrow_from(state).cloned(),
+            version: Version::try_borrow_from(state).copied(),
+            headers: HeaderMap::try_borrow_from(state).cloned(),
+            request_id: RequestId::try_borrow_from(state).cloned(),
+            client_addr: client_addr(state),
+        }
+    }
+
+    fn into_state(self) -> State {
+        let mut state = State::new();
+        if let Some(method) = self.method {
+            state.put(method);
+        }
+        if let Some(uri) = self.uri {
+            state.put(uri);
+        }
+        if let Some(version) = self.version {
+            state.put(version);
+        }
+        if let Some(headers) = self.headers {
+            state.put(headers);
+        }
+        if let Some(request_id) = self.request_id {
+            state.put(request_id);
+        }
+        if let Some(addr) = self.client_addr {
+            put_client_addr(&mut state, addr);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::HandlerResult;
+    use crate::middleware::deadline::DeadlineMiddleware;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::Body;
+
+    async fn slow(state: State) -> HandlerResult {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok((state, hyper::Response::new(Body::empty())))
+    }
+
+    fn fast(state: State) -> (State, &'static str) {
+        (state, "fast")
+    }
+
+    #[test]
+    fn times_out_slow_handlers() {
+        let timeout = TimeoutMiddleware::new(Duration::from_millis(20))
+            .with_status(StatusCode::GATEWAY_TIMEOUT);
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(timeout).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/slow").to_async(slow);
+            route.get("/fast").to(fast);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/slow")
+            .with_header("x-request-id", "slow-1".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(response.headers()["x-request-id"], "slow-1");
+
+        let response = test_server
+            .client()
+            .get("http://localhost/fast")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "fast");
+    }
+
+    #[test]
+    fn times_out_at_deadline() {
+        let deadline = DeadlineMiddleware::new(Duration::from_millis(20));
+        let timeout = TimeoutMiddleware::new(Duration::from_secs(60));
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(deadline).add(timeout).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to_async(slow);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -9,6 +9,7 @@ use std::pin::Pin;
 
 use crate::handler::HandlerFuture;
 use crate::middleware::chain::NewMiddlewareChain;
+use crate::middleware::{Middleware, NewMiddleware};
 use crate::pipeline::set::PipelineSet;
 use crate::pipeline::Pipeline;
 use crate::state::{request_id, State};
@@ -58,6 +59,42 @@ where
     }
 }
 
+/// A single `NewMiddleware` attached to a route with `DefineSingleRoute::with_middleware`, which is
+/// invoked after the pipelines in the rest of the `PipelineHandleChain`.
+#[derive(Clone, Copy, Debug)]
+pub struct RouteMiddleware<NM> {
+    new_middleware: NM,
+}
+
+impl<NM> RouteMiddleware<NM> {
+    pub(crate) fn new(new_middleware: NM) -> Self {
+        RouteMiddleware { new_middleware }
+    }
+}
+
+/// Part of a `PipelineHandleChain` which invokes a single `Middleware` and continues with a tail
+/// element.
+impl<P, NM, U> PipelineHandleChain<P> for (RouteMiddleware<NM>, U)
+where
+    NM: NewMiddleware,
+    NM::Instance: Send + 'static,
+    U: PipelineHandleChain<P>,
+{
+    fn call<F>(&self, pipelines: &PipelineSet<P>, state: State, f: F) -> Pin<Box<HandlerFuture>>
+    where
+        F: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let (ref route_middleware, ref chain) = *self;
+        match route_middleware.new_middleware.new_middleware() {
+            Ok(m) => chain.call(pipelines, state, move |state| m.call(state, f)),
+            Err(e) => {
+                trace!("[{}] error creating route middleware", request_id(&state));
+                future::err((state, e.into())).boxed()
+            }
+        }
+    }
+}
+
 /// The marker for the end of a `PipelineHandleChain`.
 impl<P> PipelineHandleChain<P> for () {
     fn call<F>(&self, _: &PipelineSet<P>, state: State, f: F) -> Pin<Box<HandlerFuture>>
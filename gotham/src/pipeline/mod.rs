@@ -1,7 +1,7 @@
 //! Defines types for a middleware pipeline
 
 mod chain;
-pub use chain::PipelineHandleChain;
+pub use chain::{PipelineHandleChain, RouteMiddleware};
 
 mod set;
 pub use set::{finalize_pipeline_set, new_pipeline_set, EditablePipelineSet, PipelineSet};
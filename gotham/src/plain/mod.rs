@@ -3,6 +3,7 @@ use log::info;
 use std::net::ToSocketAddrs;
 
 use super::handler::NewHandler;
+use super::listener::BindOptions;
 use super::{bind_server, new_runtime, tcp_listener, StartError};
 
 #[cfg(feature = "testing")]
@@ -31,6 +32,20 @@ where
     runtime.block_on(init_server(addr, new_handler))
 }
 
+/// Starts a Gotham application, binding the listening socket with the given `BindOptions`.
+pub fn start_with_options<NH, A>(
+    addr: A,
+    new_handler: NH,
+    options: BindOptions,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static + Send,
+{
+    let runtime = new_runtime(num_cpus::get());
+    runtime.block_on(init_server_with_options(addr, new_handler, options))
+}
+
 /// Returns a `Future` used to spawn an Gotham application.
 ///
 /// This is used internally, but exposed in case the developer intends on doing any
@@ -41,7 +56,21 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs + 'static + Send,
 {
-    let listener = tcp_listener(addr).await?;
+    init_server_with_options(addr, new_handler, BindOptions::default()).await
+}
+
+/// Returns a `Future` used to spawn an Gotham application, binding the listening socket with the
+/// given `BindOptions`.
+pub async fn init_server_with_options<NH, A>(
+    addr: A,
+    new_handler: NH,
+    options: BindOptions,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static + Send,
+{
+    let listener = tcp_listener(addr, options).await?;
     let addr = listener.local_addr().unwrap();
 
     info! {
@@ -9,6 +9,7 @@ use std::task::{Context, Poll};
 
 use futures_util::future::{self, BoxFuture};
 use futures_util::FutureExt;
+use hyper::client::{self, connect::Connect};
 use hyper::service::Service;
 use hyper::Uri;
 use log::info;
@@ -16,6 +17,7 @@ use tokio::net::TcpStream;
 use tokio::time::Sleep;
 
 use crate::handler::NewHandler;
+use crate::helpers::clock::MockClock;
 use crate::test::async_test::{AsyncTestClient, AsyncTestServerInner};
 use crate::test::{self, TestClient, TestServerData};
 use std::time::Duration;
@@ -86,11 +88,153 @@ impl TestServer {
         })
     }
 
+    /// Creates a `TestServer` whose requests see the time of `clock`, as returned by
+    /// `gotham::helpers::clock::now`. Advancing the clock during a test lets time-dependent
+    /// behaviour, such as session expiry, be tested without sleeping.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use gotham::state::State;
+    /// # use gotham::router::builder::*;
+    /// use gotham::helpers::clock::{self, MockClock};
+    /// use gotham::test::TestServer;
+    ///
+    /// fn session_status(state: State) -> (State, String) {
+    ///     // e.g. compares the current time with the session's expiry
+    ///     let now = clock::now(&state);
+    ///     # let _ = now;
+    ///     (state, "active".to_owned())
+    /// }
+    ///
+    /// # fn main() {
+    /// let clock = MockClock::new();
+    /// let router = build_simple_router(|route| route.get("/").to(session_status));
+    /// let test_server = TestServer::with_mock_clock(router, clock.clone()).unwrap();
+    ///
+    /// clock.advance(Duration::from_secs(3600));
+    /// let response = test_server.client().get("http://localhost/").perform().unwrap();
+    /// assert_eq!(response.read_utf8_body().unwrap(), "active");
+    /// # }
+    /// ```
+    pub fn with_mock_clock<NH: NewHandler + 'static>(
+        new_handler: NH,
+        clock: MockClock,
+    ) -> anyhow::Result<TestServer> {
+        TestServer::new(test::clock::WithClock::new(new_handler, clock))
+    }
+
+    /// Creates a TLS-enabled test server for the `Handler` spawned by `new_handler`, using a
+    /// self-signed certificate generated on the fly. Its clients trust the certificate, so routes
+    /// and middleware depending on secure connections can be tested with `https` URIs.
+    ///
+    /// Requires the `rustls` and `rcgen` features. See
+    /// `gotham::tls::test::TestServer::with_self_signed_cert`.
+    #[cfg(all(feature = "rustls", feature = "rcgen"))]
+    pub fn with_tls<NH: NewHandler + 'static>(
+        new_handler: NH,
+    ) -> anyhow::Result<crate::tls::test::TestServer> {
+        crate::tls::test::TestServer::with_self_signed_cert(new_handler)
+    }
+
     /// Returns a client connected to the `TestServer`. The transport is handled internally.
     pub fn client(&self) -> TestClient<Self, TestConnect> {
         self.data.client(self)
     }
 
+    /// Returns a client connected to the `TestServer`, built by `builder`. This allows tests to
+    /// configure the client, e.g. to use HTTP/2 or to change its connection pool settings.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::hyper::{Client, Version};
+    /// # use gotham::state::State;
+    /// use gotham::test::TestServer;
+    ///
+    /// # fn handler(state: State) -> (State, &'static str) {
+    /// #     (state, "Hello!")
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+    /// let client = test_server.client_with_config(Client::builder().http2_only(true));
+    ///
+    /// let response = client.get("http://localhost/").perform().unwrap();
+    /// assert_eq!(response.version(), Version::HTTP_2);
+    /// # }
+    /// ```
+    pub fn client_with_config(&self, builder: &client::Builder) -> TestClient<Self, TestConnect> {
+        self.client_with_connector(builder, TestConnect::from(self.data.addr))
+    }
+
+    /// Returns a client using `connector` to connect to the `TestServer`, built by `builder`. The
+    /// connector is responsible for connecting to the address returned by `addr`, regardless of
+    /// the requested URI.
+    pub fn client_with_connector<C>(
+        &self,
+        builder: &client::Builder,
+        connector: C,
+    ) -> TestClient<Self, C>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        self.data.client_with(self, connector, builder)
+    }
+
+    /// Sends `count` requests concurrently, each built by `build` from its index and a client
+    /// shared by all requests, and awaits their responses. The responses are returned in the
+    /// order of the indices. Fails if the responses don't arrive before the timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::state::State;
+    /// use gotham::test::TestServer;
+    ///
+    /// # fn handler(state: State) -> (State, &'static str) {
+    /// #     (state, "Hello!")
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+    /// let responses = test_server
+    ///     .perform_concurrently(5, |i, client| client.get(format!("http://localhost/?i={}", i)))
+    ///     .unwrap();
+    /// assert_eq!(responses.len(), 5);
+    /// # }
+    /// ```
+    pub fn perform_concurrently<F>(
+        &self,
+        count: usize,
+        build: F,
+    ) -> anyhow::Result<Vec<anyhow::Result<test::TestResponse>>>
+    where
+        F: for<'a> Fn(
+            usize,
+            &'a TestClient<Self, TestConnect>,
+        ) -> test::TestRequest<'a, Self, TestConnect>,
+    {
+        let client = self.client();
+        let requests = (0..count).map(|i| build(i, &client)).collect::<Vec<_>>();
+        client.perform_all(requests)
+    }
+
+    /// Returns the address the `TestServer` is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.data.addr
+    }
+
+    /// Opens a WebSocket connection to the route at `uri`, which must use the `ws` scheme. Fails
+    /// if the route does not accept the connection.
+    #[cfg(feature = "websocket")]
+    pub fn websocket(&self, uri: &str) -> anyhow::Result<test::TestWebSocket<Self, TcpStream>> {
+        let connect = test::websocket::connect(self.data.addr, uri.to_owned()).boxed();
+        let stream = test::Server::run_request(self, connect)?;
+        Ok(test::TestWebSocket::new(self.clone(), stream))
+    }
+
     /// Spawns the given future on the `TestServer`'s internal runtime.
     /// This allows you to spawn more futures ontop of the `TestServer` in your
     /// tests.
@@ -161,6 +305,16 @@ impl AsyncTestServer {
     pub fn client(&self) -> AsyncTestClient<super::test::TestConnect> {
         self.inner.client()
     }
+
+    /// Opens a WebSocket connection to the route at `uri`, which must use the `ws` scheme. Fails
+    /// if the route does not accept the connection.
+    #[cfg(feature = "websocket")]
+    pub async fn websocket(
+        &self,
+        uri: &str,
+    ) -> anyhow::Result<tokio_tungstenite::WebSocketStream<TcpStream>> {
+        self.inner.websocket(uri).await
+    }
 }
 
 /// `TestConnect` represents the connection between a test client and the `TestServer` instance
@@ -204,6 +358,129 @@ mod tests {
         test::common_tests::serves_requests(TestServer::new, TestServer::client)
     }
 
+    #[test]
+    fn test_server_performs_concurrent_requests() {
+        use crate::handler::HandlerError;
+        use crate::helpers::http::response::create_response;
+        use crate::state::State;
+        use hyper::StatusCode;
+        use std::panic::AssertUnwindSafe;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::sync::Barrier;
+
+        // every request waits until all of them have arrived, so this only completes if they
+        // are handled concurrently
+        const COUNT: usize = 4;
+        let barrier = AssertUnwindSafe(Arc::new(Barrier::new(COUNT)));
+        let arrived = Arc::new(AtomicUsize::new(0));
+        let server = TestServer::new(move || {
+            let barrier = barrier.clone();
+            let arrived = arrived.clone();
+            Ok(move |state: State| {
+                async move {
+                    let index = arrived.fetch_add(1, Ordering::SeqCst);
+                    barrier.wait().await;
+                    let body = format!("{}", index);
+                    let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, body);
+                    Ok::<_, (State, HandlerError)>((state, response))
+                }
+                .boxed()
+            })
+        })
+        .unwrap();
+
+        let responses = server
+            .perform_concurrently(COUNT, |_, client| client.get("http://localhost/"))
+            .unwrap();
+        let mut indices = responses
+            .into_iter()
+            .map(|response| response.unwrap().text())
+            .collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(indices, vec!["0", "1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_server_serves_clients_with_config() {
+        let server = TestServer::new(TestHandler::from("configured")).unwrap();
+        let mut builder = hyper::Client::builder();
+        builder.pool_max_idle_per_host(0);
+
+        let client = server.client_with_connector(&builder, TestConnect::from(server.addr()));
+        let response = client.get("http://localhost/").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "configured");
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn test_server_serves_http2_clients() {
+        let server = TestServer::new(TestHandler::from("h2")).unwrap();
+        let client = server.client_with_config(hyper::Client::builder().http2_only(true));
+
+        let response = client.get("http://localhost/").perform().unwrap();
+        assert_eq!(response.version(), hyper::Version::HTTP_2);
+        assert_eq!(response.read_utf8_body().unwrap(), "h2");
+    }
+
+    #[test]
+    fn test_server_uses_mock_clock() {
+        use crate::helpers::clock;
+        use crate::state::State;
+        use std::time::Instant;
+
+        let start = Instant::now();
+        let clock = MockClock::new();
+        let server = TestServer::with_mock_clock(
+            move || {
+                Ok(move |state: State| {
+                    let elapsed = clock::now(&state).saturating_duration_since(start);
+                    (state, format!("{}", elapsed.as_secs() / 3600))
+                })
+            },
+            clock.clone(),
+        )
+        .unwrap();
+
+        clock.advance(Duration::from_secs(7200));
+        let response = server.client().get("http://localhost/").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "2");
+    }
+
+    #[tokio::test]
+    async fn test_server_performs_async_requests() {
+        let server = TestServer::new(TestHandler::from("hello")).unwrap();
+        let client = server.client();
+
+        let (first, second) = future::join(
+            client.get("http://localhost/").perform_async(),
+            client
+                .post("http://localhost/echo", "echo", mime::TEXT_PLAIN)
+                .perform_async(),
+        )
+        .await;
+        assert_eq!(first.unwrap().read_utf8_body().await.unwrap(), "hello");
+        assert_eq!(second.unwrap().read_utf8_body().await.unwrap(), "echo");
+    }
+
+    #[tokio::test]
+    async fn test_server_times_out_async_requests() {
+        let server = TestServer::with_timeout(TestHandler::default(), 1).unwrap();
+        let result = server
+            .client()
+            .get("http://localhost/timeout")
+            .perform_async()
+            .await;
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+    }
+
+    #[cfg(all(feature = "rustls", feature = "rcgen"))]
+    #[test]
+    fn test_server_serves_requests_with_tls() {
+        let server = TestServer::with_tls(TestHandler::from("secure")).unwrap();
+        let response = server.client().get("https://localhost/").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "secure");
+    }
+
     #[test]
     fn test_server_times_out() {
         test::common_tests::times_out(TestServer::with_timeout, TestServer::client)
@@ -258,6 +535,48 @@ mod tests {
         async_test::common_tests::echo(AsyncTestServer::new, AsyncTestServer::client).await;
     }
 
+    #[tokio::test]
+    async fn async_test_server_sends_form_bodies() {
+        let server = AsyncTestServer::new(TestHandler::default()).await.unwrap();
+        let response = server
+            .client()
+            .post("http://localhost/echo")
+            .form(&[("greeting", "hello world")])
+            .perform()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.read_utf8_body().await.unwrap(),
+            "greeting=hello+world"
+        );
+    }
+
+    #[tokio::test]
+    async fn async_test_server_reads_chunks() {
+        use crate::state::State;
+        use futures_util::stream::{self, StreamExt};
+        use hyper::{Body, Response};
+
+        fn handler(state: State) -> (State, Response<Body>) {
+            let chunks =
+                stream::iter(vec![Ok::<_, std::io::Error>("first")]).chain(stream::pending());
+            (state, Response::new(Body::wrap_stream(chunks)))
+        }
+
+        let server = AsyncTestServer::new(|| Ok(handler)).await.unwrap();
+        let mut response = server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .await
+            .unwrap();
+
+        let timeout = Duration::from_millis(200);
+        let chunk = response.next_chunk(timeout).await.unwrap().unwrap();
+        assert_eq!(chunk, "first");
+        assert!(response.next_chunk(timeout).await.is_err());
+    }
+
     #[tokio::test]
     async fn async_test_server_supports_multiple_servers() {
         async_test::common_tests::supports_multiple_servers(
@@ -353,6 +353,29 @@ where
         self.request(vec![Method::OPTIONS], path)
     }
 
+    /// Creates a route which matches `CONNECT` requests. As the request target of a `CONNECT`
+    /// request is an authority (`host:port`) rather than a path, these are matched as requests
+    /// for the root path `/`. Such routes are usually terminated with `to_tunnel`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::tunnel::Forward;
+    /// #
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.connect("/").to_tunnel(Forward::new(["example.com:443"]));
+    /// })
+    /// # }
+    /// #
+    /// # fn main() { router(); }
+    /// ```
+    fn connect<'b>(&'b mut self, path: &str) -> DefaultSingleRouteBuilder<'b, C, P> {
+        self.request(vec![Method::CONNECT], path)
+    }
+
     /// Creates a single route which matches any requests to the given `path` with one of the
     /// given `methods`. The `path` can consist of static or dynamic segments, for example:
     ///
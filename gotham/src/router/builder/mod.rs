@@ -7,6 +7,7 @@ mod single;
 
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
+use std::sync::Arc;
 
 use hyper::{Body, StatusCode};
 
@@ -21,10 +22,14 @@ use crate::router::route::{Delegation, Extractors, RouteImpl};
 use crate::router::tree::node::Node;
 use crate::router::tree::Tree;
 use crate::router::Router;
+use crate::state::AppData;
 
 pub use self::associated::{AssociatedRouteBuilder, AssociatedSingleRouteBuilder};
 pub use self::draw::DrawRoutes;
-pub use self::modify::{ExtendRouteMatcher, ReplacePathExtractor, ReplaceQueryStringExtractor};
+pub use self::modify::{
+    AllowHead, AttachMiddleware, ExtendRouteMatcher, ReplacePathExtractor,
+    ReplaceQueryStringExtractor,
+};
 pub use self::single::DefineSingleRoute;
 
 /// Builds a `Router` using the provided closure. Routes are defined using the `RouterBuilder`
@@ -83,20 +88,24 @@ where
 {
     let mut tree = Tree::new();
 
-    let response_finalizer = {
+    let (response_finalizer, app_data) = {
         let mut builder = RouterBuilder {
             node_builder: tree.borrow_root_mut(),
             pipeline_chain,
             pipelines,
             response_finalizer_builder: ResponseFinalizerBuilder::new(),
+            app_data: AppData::new(),
         };
 
         f(&mut builder);
 
-        builder.response_finalizer_builder.finalize()
+        (
+            builder.response_finalizer_builder.finalize(),
+            builder.app_data,
+        )
     };
 
-    Router::new(tree, response_finalizer)
+    Router::new(tree, response_finalizer, app_data)
 }
 
 /// Builds a `Router` with **no** middleware using the provided closure. Routes are defined using
@@ -151,6 +160,7 @@ where
     pipeline_chain: C,
     pipelines: PipelineSet<P>,
     response_finalizer_builder: ResponseFinalizerBuilder,
+    app_data: AppData,
 }
 
 impl<'a, C, P> RouterBuilder<'a, C, P>
@@ -212,6 +222,49 @@ where
         self.response_finalizer_builder
             .add(status_code, Box::new(extender))
     }
+
+    /// Registers a shared value, which the `Router` places into the `State` of every request as a
+    /// `Data<T>`. See `AppData` for details.
+    ///
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// use gotham::state::{Data, FromState, State};
+    ///
+    /// struct Config {
+    ///     name: String,
+    /// }
+    ///
+    /// fn handler(state: State) -> (State, String) {
+    ///     let name = Data::<Config>::borrow_from(&state).name.clone();
+    ///     (state, name)
+    /// }
+    ///
+    /// fn router(config: Config) -> Router {
+    ///     build_simple_router(|route| {
+    ///         route.add_data(Arc::new(config));
+    ///         route.get("/").to(handler);
+    ///     })
+    /// }
+    /// #
+    /// # fn main() {
+    /// #   let config = Config { name: "gotham".to_owned() };
+    /// #   let test_server = TestServer::new(router(config)).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .get("https://example.com/")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.read_utf8_body().unwrap(), "gotham");
+    /// # }
+    /// ```
+    pub fn add_data<T>(&mut self, value: Arc<T>)
+    where
+        T: ?Sized + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.app_data.insert(value)
+    }
 }
 
 /// A scoped builder, which is created by `DrawRoutes::scope` and passed to the provided closure.
@@ -626,4 +679,122 @@ mod tests {
         let response = call(Request::get("/trailing-slash").body(Body::empty()).unwrap());
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[test]
+    fn add_data_test() {
+        use crate::state::{Data, FromState};
+        use crate::test::TestServer;
+
+        struct Greeting(&'static str);
+
+        fn handler(state: State) -> (State, String) {
+            let greeting = Data::<Greeting>::borrow_from(&state).0;
+            let count = **Data::<u32>::borrow_from(&state);
+            (state, format!("{} {}", greeting, count))
+        }
+
+        let delegated_router = build_simple_router(|route| {
+            route.add_data(Arc::new(2u32));
+            route.get("/").to(handler);
+        });
+
+        let router = build_simple_router(|route| {
+            route.add_data(Arc::new(Greeting("Hello")));
+            route.add_data(Arc::new(1u32));
+            route.get("/").to(handler);
+            route.delegate("/delegated").to_router(delegated_router);
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+        let client = test_server.client();
+
+        let response = client.get("http://localhost/").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "Hello 1");
+
+        // the delegated router replaces the values it registered itself
+        let response = client.get("http://localhost/delegated").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "Hello 2");
+    }
+
+    #[test]
+    fn with_middleware_test() {
+        use crate::handler::HandlerFuture;
+        use crate::helpers::http::response::create_empty_response;
+        use crate::middleware::response::{OnResponse, ResponseMiddleware};
+        use crate::middleware::{Middleware, NewMiddleware};
+        use crate::pipeline::single_pipeline;
+        use crate::state::FromState;
+        use crate::test::TestServer;
+        use futures_util::future::{self, FutureExt};
+        use hyper::header::{HeaderValue, AUTHORIZATION};
+        use hyper::HeaderMap;
+        use std::pin::Pin;
+
+        #[derive(Clone)]
+        struct RequireAuthorization;
+
+        impl NewMiddleware for RequireAuthorization {
+            type Instance = Self;
+
+            fn new_middleware(&self) -> anyhow::Result<Self> {
+                Ok(self.clone())
+            }
+        }
+
+        impl Middleware for RequireAuthorization {
+            fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+            where
+                Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+            {
+                if HeaderMap::borrow_from(&state).contains_key(AUTHORIZATION) {
+                    chain(state)
+                } else {
+                    let response = create_empty_response(&state, StatusCode::UNAUTHORIZED);
+                    future::ok((state, response)).boxed()
+                }
+            }
+        }
+
+        fn tag(name: &'static str) -> OnResponse<impl ResponseMiddleware> {
+            OnResponse::new(move |_state: &mut State, response: &mut Response<Body>| {
+                let value = match response.headers().get("x-tags") {
+                    Some(tags) => format!("{},{}", tags.to_str().unwrap(), name),
+                    None => name.to_owned(),
+                };
+                response
+                    .headers_mut()
+                    .insert("x-tags", HeaderValue::from_str(&value).unwrap());
+            })
+        }
+
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(tag("pipeline")).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.get("/").to(welcome::index);
+            route
+                .get("/admin")
+                .with_middleware(tag("first"))
+                .with_middleware(tag("second"))
+                .with_middleware(RequireAuthorization)
+                .to(welcome::index);
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+        let client = test_server.client();
+
+        let response = client.get("http://localhost/").perform().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-tags"], "pipeline");
+
+        let response = client.get("http://localhost/admin").perform().unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // responses pass through the route middleware before the pipeline, in reverse order
+        let response = client
+            .get("http://localhost/admin")
+            .with_header(AUTHORIZATION, HeaderValue::from_static("Bearer token"))
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-tags"], "second,first,pipeline");
+    }
 }
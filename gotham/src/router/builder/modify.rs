@@ -3,10 +3,11 @@ use hyper::Body;
 use std::panic::RefUnwindSafe;
 
 use crate::extractor::{PathExtractor, QueryStringExtractor};
-use crate::pipeline::PipelineHandleChain;
+use crate::middleware::NewMiddleware;
+use crate::pipeline::{PipelineHandleChain, RouteMiddleware};
 use crate::router::builder::single::DefineSingleRoute;
 use crate::router::builder::SingleRouteBuilder;
-use crate::router::route::matcher::{AndRouteMatcher, RouteMatcher};
+use crate::router::route::matcher::{AndRouteMatcher, HeadRouteMatcher, RouteMatcher};
 
 /// Describes the operation of replacing a `PathExtractor` on a route. This trait exists to remove
 /// type clutter from the documentation of `SingleRouteBuilder::with_path_extractor`.
@@ -109,3 +110,74 @@ where
         }
     }
 }
+
+/// Describes the operation of extending a route to also match `HEAD` requests wherever it matches
+/// `GET` requests. This trait exists to remove type clutter from the documentation of
+/// `DefineSingleRoute::to_file` and `DefineSingleRoute::to_dir`.
+pub trait AllowHead {
+    /// The type returned when wrapping the existing `RouteMatcher` in a `HeadRouteMatcher`.
+    type Output: DefineSingleRoute;
+
+    #[doc(hidden)]
+    /// Wraps the existing `RouteMatcher` in a `HeadRouteMatcher`
+    fn allow_head(self) -> Self::Output;
+}
+
+impl<'a, M, C, P, PE, QSE> AllowHead for SingleRouteBuilder<'a, M, C, P, PE, QSE>
+where
+    M: RouteMatcher + Send + Sync + 'static,
+    C: PipelineHandleChain<P> + Send + Sync + 'static,
+    P: RefUnwindSafe + Send + Sync + 'static,
+    PE: PathExtractor<Body> + Send + Sync + 'static,
+    QSE: QueryStringExtractor<Body> + Send + Sync + 'static,
+{
+    type Output = SingleRouteBuilder<'a, HeadRouteMatcher<M>, C, P, PE, QSE>;
+
+    fn allow_head(self) -> Self::Output {
+        SingleRouteBuilder {
+            matcher: HeadRouteMatcher::new(self.matcher),
+            phantom: self.phantom,
+            node_builder: self.node_builder,
+            pipeline_chain: self.pipeline_chain,
+            pipelines: self.pipelines,
+        }
+    }
+}
+
+/// Describes the operation of attaching a `Middleware` to a route. This trait exists to remove
+/// type clutter from the documentation of `DefineSingleRoute::with_middleware`.
+pub trait AttachMiddleware<NM>
+where
+    NM: NewMiddleware + Send + 'static,
+{
+    /// The type returned when attaching the `NewMiddleware` to the route.
+    type Output: DefineSingleRoute;
+
+    #[doc(hidden)]
+    /// Appends the `NewMiddleware` to the `PipelineHandleChain` of the route, so that it is
+    /// invoked after the existing pipelines and middleware.
+    fn attach_middleware(self, middleware: NM) -> Self::Output;
+}
+
+impl<'a, M, NM, C, P, PE, QSE> AttachMiddleware<NM> for SingleRouteBuilder<'a, M, C, P, PE, QSE>
+where
+    M: RouteMatcher + Send + Sync + 'static,
+    NM: NewMiddleware + Send + 'static,
+    NM::Instance: Send + 'static,
+    C: PipelineHandleChain<P> + Send + Sync + 'static,
+    P: RefUnwindSafe + Send + Sync + 'static,
+    PE: PathExtractor<Body> + Send + Sync + 'static,
+    QSE: QueryStringExtractor<Body> + Send + Sync + 'static,
+{
+    type Output = SingleRouteBuilder<'a, M, (RouteMiddleware<NM>, C), P, PE, QSE>;
+
+    fn attach_middleware(self, middleware: NM) -> Self::Output {
+        SingleRouteBuilder {
+            matcher: self.matcher,
+            phantom: self.phantom,
+            node_builder: self.node_builder,
+            pipeline_chain: (RouteMiddleware::new(middleware), self.pipeline_chain),
+            pipelines: self.pipelines,
+        }
+    }
+}
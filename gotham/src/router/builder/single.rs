@@ -7,17 +7,23 @@ use std::pin::Pin;
 
 use crate::extractor::{PathExtractor, QueryStringExtractor};
 use crate::handler::{
-    DirHandler, FileHandler, FileOptions, FilePathExtractor, Handler, HandlerError, HandlerFuture,
-    HandlerResult, IntoResponse, NewHandler,
+    DirHandler, FileHandler, FileOptions, FilePathExtractor, Handler, HandlerFuture, HandlerResult,
+    IntoHandlerError, IntoResponse, NewHandler,
 };
+use crate::middleware::NewMiddleware;
 use crate::pipeline::PipelineHandleChain;
 use crate::router::builder::{
-    ExtendRouteMatcher, ReplacePathExtractor, ReplaceQueryStringExtractor, SingleRouteBuilder,
+    AllowHead, AttachMiddleware, ExtendRouteMatcher, ReplacePathExtractor,
+    ReplaceQueryStringExtractor, SingleRouteBuilder,
 };
 use crate::router::route::dispatch::DispatcherImpl;
 use crate::router::route::matcher::RouteMatcher;
 use crate::router::route::{Delegation, Extractors, RouteImpl};
 use crate::state::State;
+use crate::tunnel::{TunnelHandler, TunnelRoute};
+use crate::upgrade::{UpgradeHandler, UpgradeRoute};
+#[cfg(feature = "websocket")]
+use crate::websocket::{WebSocketHandler, WebSocketRoute};
 
 pub trait HandlerMarker {
     fn call_and_wrap(self, state: State) -> Pin<Box<HandlerFuture>>;
@@ -25,27 +31,31 @@ pub trait HandlerMarker {
 
 pub trait AsyncHandlerFn<'a> {
     type Res: IntoResponse + 'static;
-    type Fut: std::future::Future<Output = Result<Self::Res, HandlerError>> + Send + 'a;
+    type Err: IntoHandlerError + 'static;
+    type Fut: std::future::Future<Output = Result<Self::Res, Self::Err>> + Send + 'a;
     fn call(self, arg: &'a mut State) -> Self::Fut;
 }
 
-impl<'a, Fut, R, F> AsyncHandlerFn<'a> for F
+impl<'a, Fut, R, E, F> AsyncHandlerFn<'a> for F
 where
     F: FnOnce(&'a mut State) -> Fut,
     R: IntoResponse + 'static,
-    Fut: std::future::Future<Output = Result<R, HandlerError>> + Send + 'a,
+    E: IntoHandlerError + 'static,
+    Fut: std::future::Future<Output = Result<R, E>> + Send + 'a,
 {
     type Res = R;
+    type Err = E;
     type Fut = Fut;
     fn call(self, state: &'a mut State) -> Fut {
         self(state)
     }
 }
 
-impl<F, R> HandlerMarker for F
+impl<F, R, E> HandlerMarker for F
 where
     R: IntoResponse + 'static,
-    for<'a> F: AsyncHandlerFn<'a, Res = R> + Send + 'static,
+    E: IntoHandlerError + 'static,
+    for<'a> F: AsyncHandlerFn<'a, Res = R, Err = E> + Send + 'static,
 {
     fn call_and_wrap(self, mut state: State) -> Pin<Box<HandlerFuture>> {
         async move {
@@ -56,7 +66,7 @@ where
                     let response = data.into_response(&state);
                     Ok((state, response))
                 }
-                Err(err) => Err((state, err)),
+                Err(err) => Err((state, err.into_handler_error())),
             }
         }
         .boxed()
@@ -199,7 +209,8 @@ pub trait DefineSingleRoute {
     /// [rust-lang/rust#70263](https://github.com/rust-lang/rust/issues/70263).
     ///
     /// On the other hand, one can easily use the `?` operator for error handling
-    /// in these async functions.
+    /// in these async functions. Besides `HandlerError`, they may return any error implementing
+    /// `IntoHandlerError`, such as application error enums deriving `HttpError`.
     ///
     /// # Examples
     ///
@@ -308,6 +319,8 @@ pub trait DefineSingleRoute {
     /// The route must contain a trailing glob segment, which will be used
     /// to serve any matching names under the given path.
     ///
+    /// Routes defined with `get` also answer `HEAD` requests with the headers of the file.
+    ///
     /// # Examples
     ///
     /// ```rust
@@ -340,16 +353,21 @@ pub trait DefineSingleRoute {
     /// ```
     fn to_dir<P>(self, options: P)
     where
-        Self: ReplacePathExtractor<FilePathExtractor> + Sized,
-        Self::Output: DefineSingleRoute,
+        Self: AllowHead + Sized,
+        <Self as AllowHead>::Output: ReplacePathExtractor<FilePathExtractor>,
+        <<Self as AllowHead>::Output as ReplacePathExtractor<FilePathExtractor>>::Output:
+            DefineSingleRoute,
         FileOptions: From<P>,
     {
-        self.with_path_extractor::<FilePathExtractor>()
+        self.allow_head()
+            .with_path_extractor::<FilePathExtractor>()
             .to_new_handler(DirHandler::new(options));
     }
 
     /// Directs the route to serve a single static file from the given path.
     ///
+    /// Routes defined with `get` also answer `HEAD` requests with the headers of the file.
+    ///
     /// # Examples
     ///
     /// ```rust
@@ -382,10 +400,106 @@ pub trait DefineSingleRoute {
     /// ```
     fn to_file<P>(self, options: P)
     where
-        Self: Sized,
+        Self: AllowHead + Sized,
         FileOptions: From<P>,
     {
-        self.to_new_handler(FileHandler::new(options));
+        self.allow_head().to_new_handler(FileHandler::new(options));
+    }
+
+    /// Directs the route to accept WebSocket connections, passing each established connection to
+    /// the given `WebSocketHandler`. Requests which are not a valid WebSocket handshake are
+    /// answered with `400 Bad Request`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use futures_util::StreamExt;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// use gotham::websocket::WebSocket;
+    ///
+    /// async fn ws_handler(mut ws: WebSocket) {
+    ///     while let Some(Ok(_message)) = ws.next().await {
+    ///         // Implementation elided.
+    ///     }
+    /// }
+    ///
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.get("/ws").to_websocket(ws_handler);
+    /// })
+    /// # }
+    /// # fn main() { router(); }
+    /// ```
+    #[cfg(feature = "websocket")]
+    fn to_websocket<H>(self, handler: H)
+    where
+        Self: Sized,
+        H: WebSocketHandler,
+    {
+        self.to_new_handler(WebSocketRoute::new(handler));
+    }
+
+    /// Directs the route to establish `CONNECT` tunnels, passing the client's connection to the
+    /// given `TunnelHandler` once it has opened the connection to the requested authority.
+    /// Requests using any other method are answered with `405 Method Not Allowed`.
+    ///
+    /// `tunnel::Forward` only connects to the authorities on its allow-list, so that the route
+    /// is not an open proxy.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// use gotham::tunnel::Forward;
+    ///
+    /// fn router() -> Router {
+    ///     build_simple_router(|route| {
+    ///         route.connect("/").to_tunnel(Forward::new(["example.com:443"]));
+    ///     })
+    /// }
+    /// #
+    /// # fn main() { router(); }
+    /// ```
+    fn to_tunnel<H>(self, handler: H)
+    where
+        Self: Sized,
+        H: TunnelHandler,
+    {
+        self.to_new_handler(TunnelRoute::new(handler));
+    }
+
+    /// Directs the route to upgrade connections to `protocol`, passing each upgraded connection
+    /// to the given `UpgradeHandler`. Requests which do not ask for the upgrade are answered with
+    /// `426 Upgrade Required`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// use gotham::upgrade::Upgraded;
+    ///
+    /// async fn my_protocol(io: Upgraded) {
+    ///     // Implementation elided.
+    /// #   drop(io);
+    /// }
+    ///
+    /// fn router() -> Router {
+    ///     build_simple_router(|route| {
+    ///         route.get("/").to_upgrade("my-protocol", my_protocol);
+    ///     })
+    /// }
+    /// #
+    /// # fn main() { router(); }
+    /// ```
+    fn to_upgrade<H>(self, protocol: &'static str, handler: H)
+    where
+        Self: Sized,
+        H: UpgradeHandler,
+    {
+        self.to_new_handler(UpgradeRoute::new(protocol, handler));
     }
 
     /// Applies a `PathExtractor` type to the current route, to extract path parameters into
@@ -559,6 +673,61 @@ pub trait DefineSingleRoute {
         NRM: RouteMatcher + Send + Sync + 'static,
         Self: ExtendRouteMatcher<NRM>,
         Self::Output: DefineSingleRoute;
+
+    /// Attaches a `Middleware` to the current route, which is invoked after the pipelines of the
+    /// route and before the handler. Middleware attached by multiple calls is invoked in the order
+    /// in which it was attached.
+    ///
+    /// ```
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use hyper::header::{HeaderValue, CACHE_CONTROL};
+    /// # use gotham::middleware::response::OnResponse;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::builder().status(StatusCode::ACCEPTED).body(Body::empty()).unwrap())
+    /// # }
+    /// #
+    /// fn no_store(_state: &mut State, response: &mut Response<Body>) {
+    ///     response
+    ///         .headers_mut()
+    ///         .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    /// }
+    ///
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.get("/public").to(my_handler);
+    ///     route.get("/private")
+    ///          .with_middleware(OnResponse::new(no_store))
+    ///          .to(my_handler);
+    /// })
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #
+    /// #   let response = test_server.client()
+    /// #       .get("https://example.com/private")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+    /// #
+    /// #   let response = test_server.client()
+    /// #       .get("https://example.com/public")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert!(response.headers().get(CACHE_CONTROL).is_none());
+    /// # }
+    /// ```
+    fn with_middleware<NM>(self, middleware: NM) -> <Self as AttachMiddleware<NM>>::Output
+    where
+        NM: NewMiddleware + Send + 'static,
+        NM::Instance: Send + 'static,
+        Self: AttachMiddleware<NM>,
+        Self::Output: DefineSingleRoute;
 }
 
 impl<'a, M, C, P, PE, QSE> DefineSingleRoute for SingleRouteBuilder<'a, M, C, P, PE, QSE>
@@ -629,4 +798,12 @@ where
     {
         self.extend_route_matcher(matcher)
     }
+
+    fn with_middleware<NM>(self, middleware: NM) -> <Self as AttachMiddleware<NM>>::Output
+    where
+        NM: NewMiddleware + Send + 'static,
+        NM::Instance: Send + 'static,
+    {
+        self.attach_middleware(middleware)
+    }
 }
@@ -22,21 +22,23 @@ use crate::handler::{Handler, HandlerFuture, IntoResponse, NewHandler};
 use crate::helpers::http::request::path::RequestPathSegments;
 use crate::helpers::http::response::create_empty_response;
 use crate::router::response::ResponseFinalizer;
-use crate::router::route::{Delegation, Route};
+use crate::router::route::{Delegation, Route, RouteMetadata};
 use crate::router::tree::segment::SegmentMapping;
 use crate::router::tree::Tree;
-use crate::state::{request_id, State};
+use crate::state::{request_id, AppData, State};
 
 struct RouterData {
     tree: Tree,
     response_finalizer: ResponseFinalizer,
+    app_data: AppData,
 }
 
 impl RouterData {
-    fn new(tree: Tree, response_finalizer: ResponseFinalizer) -> RouterData {
+    fn new(tree: Tree, response_finalizer: ResponseFinalizer, app_data: AppData) -> RouterData {
         RouterData {
             tree,
             response_finalizer,
+            app_data,
         }
     }
 }
@@ -73,6 +75,7 @@ impl Handler for Router {
     /// any path related variables in `State` and dispatching to the associated `Handler`.
     fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
         trace!("[{}] starting", request_id(&state));
+        self.data.app_data.put_into(&mut state);
 
         let future = match state.try_take::<RequestPathSegments>() {
             Some(rps) => {
@@ -124,14 +127,59 @@ impl Handler for Router {
 }
 
 impl Router {
-    /// Manually assembles a `Router` instance from a provided `Tree`.
-    fn new(tree: Tree, response_finalizer: ResponseFinalizer) -> Router {
-        let router_data = RouterData::new(tree, response_finalizer);
+    /// Manually assembles a `Router` instance from a provided `Tree`, which places the `AppData`
+    /// into the `State` of each request.
+    fn new(tree: Tree, response_finalizer: ResponseFinalizer, app_data: AppData) -> Router {
+        let router_data = RouterData::new(tree, response_finalizer, app_data);
         Router {
             data: Arc::new(router_data),
         }
     }
 
+    /// Describes the routes of this `Router`, with the path, the request methods and the types of
+    /// the extractors of each route. Routes of delegated `Router` instances are not included, but
+    /// the delegating route is listed with `Delegation::External`.
+    ///
+    /// ```rust
+    /// # use gotham::prelude::*;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::state::State;
+    /// # use hyper::Method;
+    /// # use serde::Deserialize;
+    /// #
+    /// #[derive(Deserialize, StateData, StaticResponseExtender)]
+    /// struct UserPath {
+    /// #   #[allow(dead_code)]
+    ///     id: u64,
+    /// }
+    ///
+    /// # fn handler(state: State) -> (State, &'static str) {
+    /// #   (state, "")
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let router = build_simple_router(|route| {
+    ///     route.get("/").to(handler);
+    ///     route
+    ///         .post("/users/:id")
+    ///         .with_path_extractor::<UserPath>()
+    ///         .to(handler);
+    /// });
+    ///
+    /// for route in router.routes() {
+    ///     println!("{:?} {} {:?}", route.methods(), route.path(), route.path_extractor());
+    /// }
+    ///
+    /// let routes = router.routes();
+    /// assert_eq!(routes[1].path(), "/users/:id");
+    /// assert_eq!(routes[1].methods(), Some(&[Method::POST][..]));
+    /// assert!(routes[1].path_extractor().unwrap().ends_with("UserPath"));
+    /// # }
+    /// ```
+    pub fn routes(&self) -> Vec<RouteMetadata> {
+        self.data.tree.routes()
+    }
+
     fn dispatch<'a>(
         &self,
         mut state: State,
@@ -242,7 +290,11 @@ mod tests {
     #[test]
     fn internal_server_error_if_no_request_path_segments() {
         let tree = Tree::new();
-        let router = Router::new(tree, ResponseFinalizerBuilder::new().finalize());
+        let router = Router::new(
+            tree,
+            ResponseFinalizerBuilder::new().finalize(),
+            AppData::new(),
+        );
 
         let method = Method::GET;
         let uri = Uri::from_str("https://test.gotham.rs").unwrap();
@@ -264,7 +316,11 @@ mod tests {
     #[test]
     fn not_found_error_if_request_path_is_not_found() {
         let tree = Tree::new();
-        let router = Router::new(tree, ResponseFinalizerBuilder::new().finalize());
+        let router = Router::new(
+            tree,
+            ResponseFinalizerBuilder::new().finalize(),
+            AppData::new(),
+        );
 
         match send_request(router, Method::GET, "https://test.gotham.rs") {
             Ok((_state, res)) => {
@@ -292,7 +348,11 @@ mod tests {
             Box::new(route)
         };
         tree.add_route(route);
-        let router = Router::new(tree, ResponseFinalizerBuilder::new().finalize());
+        let router = Router::new(
+            tree,
+            ResponseFinalizerBuilder::new().finalize(),
+            AppData::new(),
+        );
 
         match send_request(router.clone(), Method::GET, "https://test.gotham.rs") {
             Ok((_state, res)) => {
@@ -333,7 +393,11 @@ mod tests {
             Box::new(route)
         };
         tree.add_route(route);
-        let router = Router::new(tree, ResponseFinalizerBuilder::new().finalize());
+        let router = Router::new(
+            tree,
+            ResponseFinalizerBuilder::new().finalize(),
+            AppData::new(),
+        );
 
         match send_request(router, Method::GET, "https://test.gotham.rs") {
             Ok((_state, res)) => {
@@ -360,7 +424,11 @@ mod tests {
             };
             tree.add_route(route);
 
-            Router::new(tree, ResponseFinalizerBuilder::new().finalize())
+            Router::new(
+                tree,
+                ResponseFinalizerBuilder::new().finalize(),
+                AppData::new(),
+            )
         };
 
         let pipeline_set = finalize_pipeline_set(new_pipeline_set());
@@ -379,7 +447,11 @@ mod tests {
 
         delegated_node.add_route(route);
         tree.add_child(delegated_node);
-        let router = Router::new(tree, ResponseFinalizerBuilder::new().finalize());
+        let router = Router::new(
+            tree,
+            ResponseFinalizerBuilder::new().finalize(),
+            AppData::new(),
+        );
 
         // Ensure that top level tree has no route
         match send_request(router.clone(), Method::GET, "https://test.gotham.rs") {
@@ -398,6 +470,49 @@ mod tests {
         };
     }
 
+    #[test]
+    fn describes_routes() {
+        use crate::router::builder::*;
+
+        let api = build_simple_router(|route| {
+            route.get("/status").to(handler);
+        });
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.scope("/users", |route| {
+                route
+                    .request(vec![Method::PUT, Method::PATCH], "/:id:[0-9]+")
+                    .to(handler);
+            });
+            route.get("/assets/*").to_dir("resources/test/assets");
+            route.delegate("/api").to_router(api);
+        });
+
+        let routes: Vec<_> = router
+            .routes()
+            .into_iter()
+            .map(|route| (route.path().to_owned(), route.methods().map(<[_]>::to_vec)))
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                ("/".to_owned(), Some(vec![Method::GET])),
+                ("/api".to_owned(), None),
+                (
+                    "/assets/*".to_owned(),
+                    Some(vec![Method::GET, Method::HEAD])
+                ),
+                (
+                    "/users/:id:[0-9]+".to_owned(),
+                    Some(vec![Method::PUT, Method::PATCH])
+                ),
+            ]
+        );
+        let api = &router.routes()[1];
+        assert_eq!(api.delegation(), Delegation::External);
+        assert_eq!(api.path_extractor(), None);
+    }
+
     #[test]
     fn executes_response_finalizer_when_present() {
         let tree = Tree::new();
@@ -409,7 +524,7 @@ mod tests {
         };
         response_finalizer_builder.add(StatusCode::NOT_FOUND, Box::new(not_found_extender));
         let response_finalizer = response_finalizer_builder.finalize();
-        let router = Router::new(tree, response_finalizer);
+        let router = Router::new(tree, response_finalizer, AppData::new());
 
         match send_request(router, Method::GET, "https://test.gotham.rs/api") {
             Ok((_state, res)) => {
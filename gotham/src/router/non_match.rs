@@ -121,7 +121,7 @@ impl RouteNonMatch {
         RouteNonMatch { status, allow }
     }
 
-    pub(super) fn deconstruct(self) -> (StatusCode, Vec<Method>) {
+    pub(crate) fn deconstruct(self) -> (StatusCode, Vec<Method>) {
         (self.status, self.allow.into())
     }
 }
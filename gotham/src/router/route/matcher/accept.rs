@@ -1,14 +1,15 @@
 //! Defines the `AcceptHeaderRouterMatcher`.
 
-use hyper::header::{HeaderMap, ACCEPT};
+use hyper::header::ACCEPT;
 use hyper::StatusCode;
 use log::trace;
 use mime::Mime;
 
 use super::{LookupTable, LookupTableFromTypes};
+use crate::helpers::http::request::negotiation::parse_cached;
 use crate::router::route::RouteMatcher;
 use crate::router::RouteNonMatch;
-use crate::state::{request_id, FromState, State};
+use crate::state::{request_id, State};
 
 /// A mime type that is optionally weighted with a quality.
 struct QMime {
@@ -86,7 +87,7 @@ impl core::str::FromStr for QMime {
 /// headers.insert(ACCEPT, "application/json".parse().unwrap());
 /// state.put(headers);
 /// assert!(matcher.is_match(&state).is_ok());
-
+///
 /// // Accept header of `image/*`
 /// let mut headers = HeaderMap::new();
 /// headers.insert(ACCEPT, "image/*".parse().unwrap());
@@ -131,53 +132,56 @@ impl RouteMatcher for AcceptHeaderRouteMatcher {
     /// Quality values within `Accept` header values are not considered by the matcher, as the
     /// matcher is only able to indicate whether a successful match has been found.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch> {
-        HeaderMap::borrow_from(state)
-            .get(ACCEPT)
-            .map(|header| {
-                // parse mime types from the accept header
-                let acceptable = header
+        // parse mime types from the accept header, or `None` if there is no accept header
+        let acceptable = parse_cached(state, ACCEPT, |headers| {
+            headers.get(ACCEPT).map(|header| {
+                header
                     .to_str()
-                    .map_err(|_| err(state))?
+                    .ok()?
                     .split(',')
                     .map(|str| str.trim().parse())
                     .collect::<Result<Vec<QMime>, _>>()
-                    .map_err(|_| err(state))?;
+                    .ok()
+            })
+        });
 
-                for qmime in acceptable {
-                    // get mime type candidates from the lookup table
-                    let essence = qmime.mime.essence_str();
-                    let candidates = match self.lookup_table.get(essence) {
-                        Some(candidates) => candidates,
-                        None => continue,
-                    };
-                    for i in candidates {
-                        let candidate = &self.supported_media_types[*i];
+        let acceptable = match acceptable.as_ref() {
+            Some(Some(acceptable)) => acceptable,
+            Some(None) => return Err(err(state)),
+            // no accept header - assume all types are acceptable
+            None => return Ok(()),
+        };
 
-                        // check that the candidates have the same suffix - this is not included in the
-                        // essence string
-                        if candidate.suffix() != qmime.mime.suffix() && qmime.mime.subtype() != "*"
-                        {
-                            continue;
-                        }
+        for qmime in acceptable {
+            // get mime type candidates from the lookup table
+            let essence = qmime.mime.essence_str();
+            let candidates = match self.lookup_table.get(essence) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+            for i in candidates {
+                let candidate = &self.supported_media_types[*i];
 
-                        // this candidate matches - params don't play a role in accept header matching
-                        return Ok(());
-                    }
+                // check that the candidates have the same suffix - this is not included in the
+                // essence string
+                if candidate.suffix() != qmime.mime.suffix() && qmime.mime.subtype() != "*" {
+                    continue;
                 }
 
-                // no candidates found
-                Err(err(state))
-            })
-            .unwrap_or_else(|| {
-                // no accept header - assume all types are acceptable
-                Ok(())
-            })
+                // this candidate matches - params don't play a role in accept header matching
+                return Ok(());
+            }
+        }
+
+        // no candidates found
+        Err(err(state))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use hyper::HeaderMap;
 
     fn with_state<F>(accept: Option<&str>, block: F)
     where
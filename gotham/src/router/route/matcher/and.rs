@@ -1,5 +1,7 @@
 //! Defines the type `AndRouteMatcher`
 
+use hyper::Method;
+
 use crate::router::non_match::RouteNonMatch;
 use crate::router::route::RouteMatcher;
 use crate::state::State;
@@ -79,4 +81,24 @@ where
             (Err(e), Err(e1)) => Err(e.intersection(e1)),
         }
     }
+
+    fn is_match_for_method(&self, state: &State, method: &Method) -> Result<(), RouteNonMatch> {
+        match (
+            self.t.is_match_for_method(state, method),
+            self.u.is_match_for_method(state, method),
+        ) {
+            (Ok(_), Ok(_)) => Ok(()),
+            (Err(e), Ok(_)) => Err(e),
+            (Ok(_), Err(e)) => Err(e),
+            (Err(e), Err(e1)) => Err(e.intersection(e1)),
+        }
+    }
+
+    fn methods(&self) -> Option<Vec<Method>> {
+        match (self.t.methods(), self.u.methods()) {
+            (Some(t), Some(u)) => Some(t.into_iter().filter(|m| u.contains(m)).collect()),
+            (t, None) => t,
+            (None, u) => u,
+        }
+    }
 }
@@ -0,0 +1,164 @@
+//! Defines the type `HeadRouteMatcher`
+
+use hyper::{Method, StatusCode};
+
+use crate::router::non_match::RouteNonMatch;
+use crate::router::route::RouteMatcher;
+use crate::state::{FromState, State};
+
+/// Extends another `RouteMatcher` to also match `HEAD` requests wherever it permits `GET`
+/// requests. This is used by the `to_file` and `to_dir` routes, so static assets answer `HEAD`
+/// requests without registering the method explicitly.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn main() {
+/// #   use hyper::Method;
+/// #   use gotham::state::State;
+/// #   use gotham::router::route::matcher::{HeadRouteMatcher, MethodOnlyRouteMatcher, RouteMatcher};
+/// #
+/// #   State::with_new(|state| {
+/// #
+/// let matcher = HeadRouteMatcher::new(MethodOnlyRouteMatcher::new(vec![Method::GET]));
+///
+/// state.put(Method::GET);
+/// assert!(matcher.is_match(&state).is_ok());
+///
+/// state.put(Method::HEAD);
+/// assert!(matcher.is_match(&state).is_ok());
+///
+/// state.put(Method::POST);
+/// assert!(matcher.is_match(&state).is_err());
+/// #
+/// #   });
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct HeadRouteMatcher<M>
+where
+    M: RouteMatcher,
+{
+    matcher: M,
+}
+
+impl<M> HeadRouteMatcher<M>
+where
+    M: RouteMatcher,
+{
+    /// Creates a new `HeadRouteMatcher` wrapping the given `RouteMatcher`.
+    pub fn new(matcher: M) -> Self {
+        HeadRouteMatcher { matcher }
+    }
+}
+
+impl<M> RouteMatcher for HeadRouteMatcher<M>
+where
+    M: RouteMatcher,
+{
+    fn is_match(&self, state: &State) -> Result<(), RouteNonMatch> {
+        self.is_match_for_method(state, Method::borrow_from(state))
+    }
+
+    fn is_match_for_method(&self, state: &State, method: &Method) -> Result<(), RouteNonMatch> {
+        let err = match self.matcher.is_match_for_method(state, method) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        let (mut status, mut allow) = err.deconstruct();
+        if !allow.contains(&Method::GET) {
+            return Err(RouteNonMatch::new(status).with_allow_list(&allow));
+        }
+        if status == StatusCode::METHOD_NOT_ALLOWED && *method == Method::HEAD {
+            // the remaining conditions of the route must hold just as for a GET request
+            match self.matcher.is_match_for_method(state, &Method::GET) {
+                Ok(()) => return Ok(()),
+                Err(err) => (status, allow) = err.deconstruct(),
+            }
+        }
+        if allow.contains(&Method::GET) && !allow.contains(&Method::HEAD) {
+            allow.push(Method::HEAD);
+        }
+        Err(RouteNonMatch::new(status).with_allow_list(&allow))
+    }
+
+    fn methods(&self) -> Option<Vec<Method>> {
+        let mut methods = self.matcher.methods()?;
+        if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+            methods.push(Method::HEAD);
+        }
+        Some(methods)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::route::matcher::{
+        AndRouteMatcher, ContentTypeHeaderRouteMatcher, MethodOnlyRouteMatcher,
+    };
+    use hyper::header::{HeaderMap, CONTENT_TYPE};
+
+    #[test]
+    fn adds_head_to_allow_list() {
+        let matcher = HeadRouteMatcher::new(MethodOnlyRouteMatcher::new(vec![Method::GET]));
+        State::with_new(|state| {
+            state.put(Method::POST);
+            let (status, allow) = matcher.is_match(state).unwrap_err().deconstruct();
+            assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(allow, vec![Method::GET, Method::HEAD]);
+        });
+    }
+
+    #[test]
+    fn ignores_routes_without_get() {
+        let matcher = HeadRouteMatcher::new(MethodOnlyRouteMatcher::new(vec![Method::POST]));
+        State::with_new(|state| {
+            state.put(Method::HEAD);
+            let (status, allow) = matcher.is_match(state).unwrap_err().deconstruct();
+            assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(allow, vec![Method::POST]);
+        });
+    }
+
+    #[test]
+    fn keeps_other_non_matches() {
+        let matcher = HeadRouteMatcher::new(AndRouteMatcher::new(
+            MethodOnlyRouteMatcher::new(vec![Method::GET]),
+            ContentTypeHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]),
+        ));
+        State::with_new(|state| {
+            state.put(Method::GET);
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, mime::TEXT_PLAIN.to_string().parse().unwrap());
+            state.put(headers);
+            let (status, _) = matcher.is_match(state).unwrap_err().deconstruct();
+            assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        });
+    }
+
+    #[test]
+    fn checks_other_conditions_for_head() {
+        let matcher = HeadRouteMatcher::new(AndRouteMatcher::new(
+            MethodOnlyRouteMatcher::new(vec![Method::GET]),
+            ContentTypeHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]),
+        ));
+        State::with_new(|state| {
+            state.put(Method::HEAD);
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, mime::TEXT_PLAIN.to_string().parse().unwrap());
+            state.put(headers);
+            let (status, _) = matcher.is_match(state).unwrap_err().deconstruct();
+            assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                CONTENT_TYPE,
+                mime::APPLICATION_JSON.to_string().parse().unwrap(),
+            );
+            state.put(headers);
+            assert!(matcher.is_match(state).is_ok());
+        });
+    }
+}
@@ -5,12 +5,14 @@ mod access_control_request_method;
 mod and;
 mod any;
 mod content_type;
+mod head;
 
 pub use self::accept::AcceptHeaderRouteMatcher;
 pub use self::access_control_request_method::AccessControlRequestMethodMatcher;
 pub use self::and::AndRouteMatcher;
 pub use self::any::AnyRouteMatcher;
 pub use self::content_type::ContentTypeHeaderRouteMatcher;
+pub use self::head::HeadRouteMatcher;
 
 mod lookup_table;
 use self::lookup_table::{LookupTable, LookupTableFromTypes};
@@ -28,6 +30,21 @@ use crate::state::{request_id, FromState, State};
 pub trait RouteMatcher: RefUnwindSafe + Clone {
     /// Determines if the `Request` meets pre-defined conditions.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch>;
+
+    /// Determines if the `Request` would meet pre-defined conditions if it had been made with the
+    /// given method. This lets `HeadRouteMatcher` check `HEAD` requests against every condition
+    /// of a `GET` route. The default implementation ignores the method, which suits matchers not
+    /// restricting it, and must be overridden by matchers which do or which wrap other matchers.
+    fn is_match_for_method(&self, state: &State, method: &Method) -> Result<(), RouteNonMatch> {
+        let _ = method;
+        self.is_match(state)
+    }
+
+    /// Returns the request methods this matcher is restricted to, or `None` if it doesn't restrict
+    /// the method. Used to describe routes, see `Router::routes`.
+    fn methods(&self) -> Option<Vec<Method>> {
+        None
+    }
 }
 
 /// Allow various types to represent themselves as a `RouteMatcher`
@@ -99,7 +116,10 @@ impl MethodOnlyRouteMatcher {
 impl RouteMatcher for MethodOnlyRouteMatcher {
     /// Determines if the `Request` was made using a `Method` the instance contains.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch> {
-        let method = Method::borrow_from(state);
+        self.is_match_for_method(state, Method::borrow_from(state))
+    }
+
+    fn is_match_for_method(&self, state: &State, method: &Method) -> Result<(), RouteNonMatch> {
         if self.methods.iter().any(|m| m == method) {
             trace!(
                 "[{}] matched request method {} to permitted method",
@@ -117,4 +137,8 @@ impl RouteMatcher for MethodOnlyRouteMatcher {
                 .with_allow_list(self.methods.as_slice()))
         }
     }
+
+    fn methods(&self) -> Option<Vec<Method>> {
+        Some(self.methods.clone())
+    }
 }
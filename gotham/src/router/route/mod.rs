@@ -7,14 +7,17 @@
 pub mod dispatch;
 pub mod matcher;
 
+use std::any::{self, TypeId};
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
 use std::pin::Pin;
 
-use hyper::{Body, Response, Uri};
+use hyper::{Body, Method, Response, Uri};
 use log::debug;
 
-use crate::extractor::{self, PathExtractor, QueryStringExtractor};
+use crate::extractor::{
+    self, NoopPathExtractor, NoopQueryStringExtractor, PathExtractor, QueryStringExtractor,
+};
 use crate::handler::HandlerFuture;
 use crate::helpers::http::request::query_string;
 use crate::router::non_match::RouteNonMatch;
@@ -23,7 +26,7 @@ use crate::router::route::matcher::RouteMatcher;
 use crate::router::tree::segment::SegmentMapping;
 use crate::state::{request_id, State};
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 /// Indicates whether this `Route` will dispatch the request to an inner `Router` instance. To
 /// support inner `Router` instances which handle a subtree, the `Dispatcher` stores additional
 /// context information.
@@ -57,6 +60,9 @@ pub trait Route: RefUnwindSafe {
     /// Determines if this `Route` should be invoked, based on the request data in `State.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch>;
 
+    /// Describes this `Route`, as listed by `Router::routes`.
+    fn metadata(&self) -> RouteMetadata;
+
     /// Determines if this `Route` intends to delegate requests to a secondary `Router` instance.
     fn delegation(&self) -> Delegation;
 
@@ -103,6 +109,46 @@ where
     delegation: Delegation,
 }
 
+/// Describes a route defined with the `gotham::router::builder` API: the path it is defined at,
+/// the request methods it matches and the types of its extractors. Returned by `Router::routes`,
+/// so route listings, documentation or URL generation can be derived from the routes themselves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteMetadata {
+    pub(crate) path: String,
+    methods: Option<Vec<Method>>,
+    path_extractor: Option<&'static str>,
+    query_string_extractor: Option<&'static str>,
+    delegation: Delegation,
+}
+
+impl RouteMetadata {
+    /// Returns the path the route is defined at, in the syntax of the builder API, like
+    /// `/users/:id` or `/assets/*`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the request methods the route matches, or `None` if it matches any method.
+    pub fn methods(&self) -> Option<&[Method]> {
+        self.methods.as_deref()
+    }
+
+    /// Returns the type name of the `PathExtractor` of the route, if any.
+    pub fn path_extractor(&self) -> Option<&'static str> {
+        self.path_extractor
+    }
+
+    /// Returns the type name of the `QueryStringExtractor` of the route, if any.
+    pub fn query_string_extractor(&self) -> Option<&'static str> {
+        self.query_string_extractor
+    }
+
+    /// Returns whether the route delegates to another `Router`, whose routes are not listed.
+    pub fn delegation(&self) -> Delegation {
+        self.delegation
+    }
+}
+
 /// Extractors used by `RouteImpl` to acquire request data and change into a type safe form
 /// for use by `Middleware` and `Handler` implementations.
 pub struct Extractors<PE, QSE>
@@ -166,6 +212,27 @@ where
         self.delegation
     }
 
+    fn metadata(&self) -> RouteMetadata {
+        let path_extractor = if TypeId::of::<PE>() == TypeId::of::<NoopPathExtractor>() {
+            None
+        } else {
+            Some(any::type_name::<PE>())
+        };
+        let query_string_extractor =
+            if TypeId::of::<QSE>() == TypeId::of::<NoopQueryStringExtractor>() {
+                None
+            } else {
+                Some(any::type_name::<QSE>())
+            };
+        RouteMetadata {
+            path: String::new(),
+            methods: self.matcher.methods(),
+            path_extractor,
+            query_string_extractor,
+            delegation: self.delegation,
+        }
+    }
+
     fn dispatch(&self, state: State) -> Pin<Box<HandlerFuture>> {
         self.dispatcher.dispatch(state)
     }
@@ -1,7 +1,7 @@
 //! Defines a hierarchial `Tree` with subtrees of `Node`.
 
 use crate::helpers::http::PercentDecoded;
-use crate::router::route::Route;
+use crate::router::route::{Route, RouteMetadata};
 use crate::router::tree::node::Node;
 use crate::router::tree::segment::{SegmentMapping, SegmentType};
 use hyper::Body;
@@ -52,11 +52,18 @@ impl Tree {
         self.root.has_child(segment, segment_type)
     }
 
+    /// Describes the routes of the `Tree`, ordered by path like the `Router` matches them.
+    pub(crate) fn routes(&self) -> Vec<RouteMetadata> {
+        let mut routes = Vec::new();
+        self.root.collect_metadata("", &mut routes);
+        routes
+    }
+
     /// Attempt to acquire a path from the `Tree` which matches the `Request` path and is routable.
     pub(crate) fn traverse<'a>(
         &'a self,
         req_path_segments: &'a [PercentDecoded],
-    ) -> Option<(&Node, SegmentMapping<'a>, usize)> {
+    ) -> Option<(&'a Node, SegmentMapping<'a>, usize)> {
         trace!(" starting tree traversal");
         self.root.match_node(req_path_segments)
     }
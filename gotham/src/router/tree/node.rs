@@ -5,7 +5,7 @@ use log::trace;
 
 use crate::helpers::http::PercentDecoded;
 use crate::router::non_match::RouteNonMatch;
-use crate::router::route::{Delegation, Route};
+use crate::router::route::{Delegation, Route, RouteMetadata};
 use crate::router::tree::segment::{SegmentMapping, SegmentType};
 use crate::state::{request_id, State};
 
@@ -106,6 +106,34 @@ impl Node {
             .map(|node| (node, params, processed))
     }
 
+    /// Appends the metadata of the routes of this `Node` and its children to `routes`, where
+    /// `path` is the path of this `Node` in the syntax of the builder API.
+    pub(crate) fn collect_metadata(&self, path: &str, routes: &mut Vec<RouteMetadata>) {
+        for route in &self.routes {
+            let mut metadata = route.metadata();
+            metadata.path = if path.is_empty() { "/" } else { path }.to_owned();
+            routes.push(metadata);
+        }
+        for child in &self.children {
+            let segment = match &child.segment_type {
+                SegmentType::Static if child.segment.starts_with(&[':', '*', '\\'][..]) => {
+                    format!("\\{}", child.segment)
+                }
+                SegmentType::Static => child.segment.clone(),
+                SegmentType::Constrained { regex } => {
+                    let pattern = regex.as_str();
+                    let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+                    let pattern = pattern.strip_suffix('$').unwrap_or(pattern);
+                    format!(":{}:{}", child.segment, pattern)
+                }
+                SegmentType::Dynamic => format!(":{}", child.segment),
+                SegmentType::Glob if child.segment == "*" => "*".to_owned(),
+                SegmentType::Glob => format!("*{}", child.segment),
+            };
+            child.collect_metadata(&format!("{}/{}", path, segment), routes);
+        }
+    }
+
     /// Retrieves a reference to the contained segment value.
     ///
     /// This is required for lifetime related annotations.
@@ -0,0 +1,632 @@
+//! Support for the HTTP/1.1 to cleartext HTTP/2 upgrade (`Upgrade: h2c`) described in RFC 7540,
+//! section 3.2.
+//!
+//! Hyper serves HTTP/2 "prior knowledge" connections by itself, but has no support for upgrading
+//! an HTTP/1.1 connection. After responding with `101 Switching Protocols`, the server has to
+//! answer the upgrade request as stream 1 of the new HTTP/2 connection. This is achieved by
+//! encoding the upgrade request as a `HEADERS` frame, which is handed to Hyper's HTTP/2 server
+//! right after the client's connection preface as if the client had sent it.
+//!
+//! The settings sent by the client in the `HTTP2-Settings` header are merged into the `SETTINGS`
+//! frame following its connection preface, so they apply before any other frame and are
+//! acknowledged once, as the client expects.
+//!
+//! Only upgrade requests without a body are upgraded; others are served over HTTP/1.1, which is
+//! permitted by the RFC. Upgrades are only offered on plaintext connections of servers started
+//! with `bind_server_with_h2c`, as clients negotiate HTTP/2 over TLS with ALPN instead.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use base64::prelude::*;
+use futures_util::ready;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, HOST, TE, TRANSFER_ENCODING,
+    UPGRADE,
+};
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use hyper::{Body, Request, Response, StatusCode, Version};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const PROTO_H2C: &str = "h2c";
+const HTTP2_SETTINGS: &str = "http2-settings";
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const FRAME_HEADER_LEN: usize = 9;
+const SETTING_LEN: usize = 6;
+// The initial SETTINGS_MAX_FRAME_SIZE, which applies until the server's settings are
+// acknowledged.
+const MAX_FRAME_SIZE: usize = 16_384;
+
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_CONTINUATION: u8 = 0x9;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+/// Returns `true` if `req` is a valid h2c upgrade request which can be upgraded.
+pub(crate) fn is_upgrade_request(req: &Request<Body>) -> bool {
+    let headers = req.headers();
+    req.version() == Version::HTTP_11
+        && has_token(headers, &UPGRADE, PROTO_H2C)
+        && has_token(headers, &CONNECTION, "upgrade")
+        && has_token(headers, &CONNECTION, HTTP2_SETTINGS)
+        && decode_settings(headers).is_some()
+        && !headers.contains_key(TRANSFER_ENCODING)
+        && headers
+            .get(CONTENT_LENGTH)
+            .map(|len| len == "0")
+            .unwrap_or(true)
+}
+
+/// The h2c upgrade requested on a connection, which is served by the connection itself once
+/// Hyper has handed over the upgraded IO.
+#[derive(Clone, Default)]
+pub(crate) struct PendingUpgrade(Arc<Mutex<Option<Upgrade>>>);
+
+struct Upgrade {
+    on_upgrade: OnUpgrade,
+    settings: Vec<u8>,
+    headers: Vec<u8>,
+}
+
+impl PendingUpgrade {
+    /// Waits for the upgraded IO, if an upgrade was requested on the connection. The upgrade
+    /// request is replayed on it as stream 1 of the HTTP/2 connection.
+    pub(crate) async fn upgraded(&self) -> Option<hyper::Result<H2cStream<Upgraded>>> {
+        let Upgrade {
+            on_upgrade,
+            settings,
+            headers,
+        } = self.0.lock().unwrap().take()?;
+        Some(
+            on_upgrade
+                .await
+                .map(|io| H2cStream::new(io, settings, headers)),
+        )
+    }
+}
+
+/// Responds to an h2c upgrade request with `101 Switching Protocols`, recording the upgrade in
+/// `pending` for the connection to serve it over HTTP/2, including the response to `req` itself.
+pub(crate) fn upgrade(mut req: Request<Body>, pending: &PendingUpgrade) -> Response<Body> {
+    let settings = decode_settings(req.headers()).unwrap_or_default();
+    let headers = encode_headers_frames(&req);
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    *pending.0.lock().unwrap() = Some(Upgrade {
+        on_upgrade,
+        settings,
+        headers,
+    });
+
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = response.headers_mut();
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, HeaderValue::from_static(PROTO_H2C));
+    response
+}
+
+fn has_token(headers: &HeaderMap, name: &HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+// Decodes the single `HTTP2-Settings` header, the base64url encoded payload of a SETTINGS frame.
+fn decode_settings(headers: &HeaderMap) -> Option<Vec<u8>> {
+    let mut values = headers.get_all(HTTP2_SETTINGS).iter();
+    let value = values.next().filter(|_| values.next().is_none())?;
+    // the padding is meant to be omitted, but is tolerated
+    let value = value.to_str().ok()?.trim_end_matches('=');
+    BASE64_URL_SAFE_NO_PAD
+        .decode(value)
+        .ok()
+        .filter(|settings| settings.len() % SETTING_LEN == 0)
+}
+
+// Encodes `req` as the HEADERS (and if necessary CONTINUATION) frames opening stream 1.
+fn encode_headers_frames(req: &Request<Body>) -> Vec<u8> {
+    let headers = req.headers();
+    let authority = headers
+        .get(HOST)
+        .map(HeaderValue::as_bytes)
+        .or_else(|| req.uri().authority().map(|a| a.as_str().as_bytes()))
+        .unwrap_or_default();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    // requests in absolute form carry their scheme, others were sent over plaintext
+    let scheme = req.uri().scheme_str().unwrap_or("http");
+
+    let mut block = Vec::new();
+    encode_field(&mut block, b":method", req.method().as_str().as_bytes());
+    encode_field(&mut block, b":scheme", scheme.as_bytes());
+    encode_field(&mut block, b":authority", authority);
+    encode_field(&mut block, b":path", path.as_bytes());
+
+    for (name, value) in headers {
+        if is_connection_specific(headers, name) {
+            continue;
+        }
+        // HTTP/2 only allows the "trailers" transfer coding in TE, which gRPC relies on
+        if *name == TE && value != "trailers" {
+            continue;
+        }
+        encode_field(&mut block, name.as_str().as_bytes(), value.as_bytes());
+    }
+
+    let mut frames = Vec::with_capacity(block.len() + FRAME_HEADER_LEN);
+    let mut chunks = block.chunks(MAX_FRAME_SIZE).peekable();
+    let mut frame_type = FRAME_HEADERS;
+    while let Some(chunk) = chunks.next() {
+        let mut flags = if frame_type == FRAME_HEADERS {
+            FLAG_END_STREAM
+        } else {
+            0
+        };
+        if chunks.peek().is_none() {
+            flags |= FLAG_END_HEADERS;
+        }
+
+        let len = chunk.len() as u32;
+        frames.extend_from_slice(&len.to_be_bytes()[1..]);
+        frames.push(frame_type);
+        frames.push(flags);
+        frames.extend_from_slice(&1u32.to_be_bytes());
+        frames.extend_from_slice(chunk);
+        frame_type = FRAME_CONTINUATION;
+    }
+    frames
+}
+
+fn is_connection_specific(headers: &HeaderMap, name: &HeaderName) -> bool {
+    *name == CONNECTION
+        || *name == UPGRADE
+        || *name == HOST
+        || *name == TRANSFER_ENCODING
+        || name == HTTP2_SETTINGS
+        || name == "keep-alive"
+        || name == "proxy-connection"
+        || has_token(headers, &CONNECTION, name.as_str())
+}
+
+// Encodes a "Literal Header Field without Indexing -- New Name" (RFC 7541, section 6.2.2)
+// without Huffman coding.
+fn encode_field(block: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    block.push(0);
+    encode_string(block, name);
+    encode_string(block, value);
+}
+
+fn encode_string(block: &mut Vec<u8>, s: &[u8]) {
+    encode_integer(block, s.len(), 7);
+    block.extend_from_slice(s);
+}
+
+// RFC 7541, section 5.1, with the remaining bits of the first octet set to zero.
+fn encode_integer(block: &mut Vec<u8>, mut value: usize, prefix_bits: u32) {
+    let max_prefix = (1 << prefix_bits) - 1;
+    if value < max_prefix {
+        block.push(value as u8);
+        return;
+    }
+
+    block.push(max_prefix as u8);
+    value -= max_prefix;
+    while value >= 128 {
+        block.push((value % 128 + 128) as u8);
+        value /= 128;
+    }
+    block.push(value as u8);
+}
+
+enum Preface {
+    // Buffering the client's connection preface, followed by its SETTINGS frame.
+    Reading(Vec<u8>),
+    // Replaying the preface with the upgrade request's settings and frames inserted.
+    Replaying(Vec<u8>, usize),
+    Done,
+}
+
+/// The upgraded connection, injecting the upgrade request into the data read from the client.
+pub(crate) struct H2cStream<IO> {
+    io: IO,
+    settings: Vec<u8>,
+    headers: Vec<u8>,
+    preface: Preface,
+}
+
+impl<IO> H2cStream<IO> {
+    fn new(io: IO, settings: Vec<u8>, headers: Vec<u8>) -> Self {
+        H2cStream {
+            io,
+            settings,
+            headers,
+            preface: Preface::Reading(Vec::with_capacity(PREFACE.len() + FRAME_HEADER_LEN)),
+        }
+    }
+}
+
+// Returns the data to replay for `received`, which starts with the preface and SETTINGS frame of
+// length `len`: the upgrade's `settings` are prepended to the client's, and the upgrade request's
+// `headers` frames follow.
+fn replay(received: &[u8], len: usize, settings: &[u8], headers: &[u8]) -> Vec<u8> {
+    let payload = PREFACE.len() + FRAME_HEADER_LEN;
+    let settings_len = (settings.len() + len - payload) as u32;
+
+    let mut replay = Vec::with_capacity(received.len() + settings.len() + headers.len());
+    replay.extend_from_slice(PREFACE);
+    replay.extend_from_slice(&settings_len.to_be_bytes()[1..]);
+    replay.extend_from_slice(&received[PREFACE.len() + 3..payload]);
+    replay.extend_from_slice(settings);
+    replay.extend_from_slice(&received[payload..len]);
+    replay.extend_from_slice(headers);
+    replay.extend_from_slice(&received[len..]);
+    replay
+}
+
+// Returns the length of the preface and SETTINGS frame at the start of `buf` once these have
+// been fully received, or `Some(0)` if `buf` does not start with a valid preface.
+fn preface_len(buf: &[u8]) -> Option<usize> {
+    let check = buf.len().min(PREFACE.len());
+    if buf[..check] != PREFACE[..check] {
+        return Some(0);
+    }
+
+    // the client's first SETTINGS frame is never an acknowledgement
+    let header = buf.get(PREFACE.len()..PREFACE.len() + FRAME_HEADER_LEN)?;
+    if header[3] != FRAME_SETTINGS || header[4] != 0 {
+        return Some(0);
+    }
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    let total = PREFACE.len() + FRAME_HEADER_LEN + len;
+    if buf.len() >= total {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for H2cStream<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.preface {
+                Preface::Reading(received) => {
+                    let mut chunk = [0u8; 512];
+                    let mut chunk_buf = ReadBuf::new(&mut chunk);
+                    ready!(Pin::new(&mut this.io).poll_read(cx, &mut chunk_buf))?;
+                    let eof = chunk_buf.filled().is_empty();
+                    received.extend_from_slice(chunk_buf.filled());
+
+                    let replay = match preface_len(received) {
+                        // a malformed preface is passed on as is, for Hyper to reject
+                        Some(0) => std::mem::take(received),
+                        Some(len) => replay(received, len, &this.settings, &this.headers),
+                        None if eof => std::mem::take(received),
+                        None => continue,
+                    };
+                    this.settings = Vec::new();
+                    this.headers = Vec::new();
+                    this.preface = Preface::Replaying(replay, 0);
+                }
+                Preface::Replaying(replay, pos) => {
+                    if *pos == replay.len() {
+                        this.preface = Preface::Done;
+                        continue;
+                    }
+                    let n = buf.remaining().min(replay.len() - *pos);
+                    buf.put_slice(&replay[*pos..*pos + n]);
+                    *pos += n;
+                    return Poll::Ready(Ok(()));
+                }
+                Preface::Done => return Pin::new(&mut this.io).poll_read(cx, buf),
+            }
+        }
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for H2cStream<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use crate::state::{FromState, State};
+    use futures_util::future;
+    use hyper::body::HttpBody;
+    use hyper::Client;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn hello(state: State) -> (State, &'static str) {
+        (state, "hello")
+    }
+
+    // Responds with the request's TE header, and a grpc-status trailer.
+    fn grpc(state: State) -> (State, Response<Body>) {
+        let te = HeaderMap::borrow_from(&state).get(TE).cloned();
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data("reply".into()).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            sender.send_trailers(trailers).await.unwrap();
+        });
+
+        let mut response = Response::new(body);
+        if let Some(te) = te {
+            response.headers_mut().insert("x-te", te);
+        }
+        (state, response)
+    }
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route.get("/hello").to(hello);
+            route.post("/grpc").to(grpc);
+        })
+    }
+
+    async fn serve() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            crate::bind_server_with_h2c(listener, router()).await;
+        });
+        addr
+    }
+
+    const UPGRADE_REQUEST: &[u8] = b"GET /hello HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Connection: Upgrade, HTTP2-Settings\r\n\
+        Upgrade: h2c\r\n\
+        HTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n";
+
+    async fn read_head(stream: &mut TcpStream) -> Vec<u8> {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        head
+    }
+
+    fn upgrade_request() -> Request<Body> {
+        Request::get("/hello")
+            .header(HOST, "localhost")
+            .header(CONNECTION, "Upgrade, HTTP2-Settings")
+            .header(UPGRADE, "h2c")
+            .header(HTTP2_SETTINGS, "AAMAAABkAAQAAP__")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn detects_upgrade_requests() {
+        assert!(is_upgrade_request(&upgrade_request()));
+
+        let mut req = upgrade_request();
+        req.headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        assert!(!is_upgrade_request(&req));
+
+        let mut req = upgrade_request();
+        req.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from_static("5"));
+        assert!(!is_upgrade_request(&req));
+
+        let mut req = upgrade_request();
+        req.headers_mut()
+            .insert(UPGRADE, HeaderValue::from_static("websocket"));
+        assert!(!is_upgrade_request(&req));
+
+        let mut req = upgrade_request();
+        req.headers_mut()
+            .insert(HTTP2_SETTINGS, HeaderValue::from_static("AAMAAABk!"));
+        assert!(!is_upgrade_request(&req));
+    }
+
+    #[test]
+    fn decodes_settings() {
+        let mut headers = HeaderMap::new();
+        headers.insert(HTTP2_SETTINGS, HeaderValue::from_static("AAMAAABkAAQAAP__"));
+        assert_eq!(
+            decode_settings(&headers).unwrap(),
+            [0, 3, 0, 0, 0, 100, 0, 4, 0, 0, 255, 255]
+        );
+
+        headers.insert(HTTP2_SETTINGS, HeaderValue::from_static("AAQAAAAC=="));
+        assert_eq!(decode_settings(&headers).unwrap(), [0, 4, 0, 0, 0, 2]);
+
+        // not a whole number of settings
+        headers.insert(HTTP2_SETTINGS, HeaderValue::from_static("AAQAAA"));
+        assert!(decode_settings(&headers).is_none());
+    }
+
+    #[tokio::test]
+    async fn merges_upgrade_settings_into_client_settings() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut stream = H2cStream::new(server, vec![0, 4, 0, 0, 0, 2], b"frames".to_vec());
+
+        client.write_all(PREFACE).await.unwrap();
+        client
+            .write_all(&[0, 0, 6, 4, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 100])
+            .await
+            .unwrap();
+
+        let mut expected = PREFACE.to_vec();
+        expected.extend_from_slice(&[0, 0, 12, 4, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0, 4, 0, 0, 0, 2, 0, 3, 0, 0, 0, 100]);
+        expected.extend_from_slice(b"frames");
+
+        let mut replayed = vec![0u8; expected.len()];
+        stream.read_exact(&mut replayed).await.unwrap();
+        assert_eq!(replayed, expected);
+    }
+
+    #[test]
+    fn encodes_integers() {
+        let mut block = Vec::new();
+        encode_integer(&mut block, 10, 5);
+        assert_eq!(block, [10]);
+
+        // From RFC 7541, appendix C.1.2
+        let mut block = Vec::new();
+        encode_integer(&mut block, 1337, 5);
+        assert_eq!(block, [31, 154, 10]);
+    }
+
+    #[test]
+    fn encodes_upgrade_request_as_headers_frame() {
+        let frames = encode_headers_frames(&upgrade_request());
+        assert_eq!(frames[3], FRAME_HEADERS);
+        assert_eq!(frames[4], FLAG_END_STREAM | FLAG_END_HEADERS);
+        assert_eq!(&frames[5..9], &[0, 0, 0, 1]);
+
+        let len = u32::from_be_bytes([0, frames[0], frames[1], frames[2]]) as usize;
+        assert_eq!(frames.len(), FRAME_HEADER_LEN + len);
+
+        let block = &frames[FRAME_HEADER_LEN..];
+        let mut expected = Vec::new();
+        encode_field(&mut expected, b":method", b"GET");
+        encode_field(&mut expected, b":scheme", b"http");
+        encode_field(&mut expected, b":authority", b"localhost");
+        encode_field(&mut expected, b":path", b"/hello");
+        assert_eq!(block, &expected[..]);
+
+        let mut req = upgrade_request();
+        *req.uri_mut() = "https://localhost/hello".parse().unwrap();
+        let frames = encode_headers_frames(&req);
+        let mut scheme = Vec::new();
+        encode_field(&mut scheme, b":scheme", b"https");
+        assert!(frames.windows(scheme.len()).any(|field| field == scheme));
+    }
+
+    #[test]
+    fn splits_large_header_blocks() {
+        let mut req = upgrade_request();
+        let large = "x".repeat(MAX_FRAME_SIZE);
+        req.headers_mut()
+            .insert("x-large", HeaderValue::from_str(&large).unwrap());
+
+        let frames = encode_headers_frames(&req);
+        assert_eq!(&frames[..3], &[0x00, 0x40, 0x00]);
+        assert_eq!(frames[3], FRAME_HEADERS);
+        assert_eq!(frames[4], FLAG_END_STREAM);
+
+        let continuation = &frames[FRAME_HEADER_LEN + MAX_FRAME_SIZE..];
+        assert_eq!(continuation[3], FRAME_CONTINUATION);
+        assert_eq!(continuation[4], FLAG_END_HEADERS);
+    }
+
+    #[tokio::test]
+    async fn upgrades_only_when_enabled() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            crate::bind_server(listener, router(), future::ok).await;
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(UPGRADE_REQUEST).await.unwrap();
+        assert!(read_head(&mut stream).await.starts_with(b"HTTP/1.1 200"));
+    }
+
+    #[tokio::test]
+    async fn serves_upgrade_request_over_http2() {
+        let addr = serve().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(UPGRADE_REQUEST).await.unwrap();
+        assert!(read_head(&mut stream).await.starts_with(b"HTTP/1.1 101"));
+
+        stream.write_all(PREFACE).await.unwrap();
+        stream
+            .write_all(&[0, 0, 0, 4, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+
+        let mut status = None;
+        let mut body = Vec::new();
+        loop {
+            let mut header = [0u8; FRAME_HEADER_LEN];
+            stream.read_exact(&mut header).await.unwrap();
+            let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let mut payload = vec![0u8; len];
+            stream.read_exact(&mut payload).await.unwrap();
+
+            let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+            match header[3] {
+                FRAME_SETTINGS if header[4] == 0 => {
+                    // acknowledge the server's settings
+                    stream
+                        .write_all(&[0, 0, 0, 4, 1, 0, 0, 0, 0])
+                        .await
+                        .unwrap();
+                }
+                FRAME_HEADERS if stream_id == 1 => status = Some(payload[0]),
+                0x0 if stream_id == 1 => {
+                    body.extend_from_slice(&payload);
+                    if header[4] & FLAG_END_STREAM != 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // ":status: 200" is entry 8 of the HPACK static table
+        assert_eq!(status, Some(0x88));
+        assert_eq!(body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn preserves_te_and_trailers_over_http2() {
+        let addr = serve().await;
+        let client = Client::builder().http2_only(true).build_http::<Body>();
+
+        let request = Request::post(format!("http://{}/grpc", addr))
+            .header(TE, "trailers")
+            .header("content-type", "application/grpc")
+            .body(Body::from("request"))
+            .unwrap();
+        let mut response = client.request(request).await.unwrap();
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert_eq!(response.headers()["x-te"], "trailers");
+
+        let body = hyper::body::to_bytes(response.body_mut()).await.unwrap();
+        assert_eq!(body, "reply");
+        let trailers = response.body_mut().trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "0");
+    }
+}
@@ -3,16 +3,24 @@
 
 use std::net::SocketAddr;
 use std::panic::AssertUnwindSafe;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::task::{self, Poll};
+use std::time::{Duration, Instant};
 
 use futures_util::future::{BoxFuture, FutureExt};
 use hyper::service::Service;
 use hyper::{Body, Request, Response};
 
 use crate::handler::NewHandler;
-use crate::state::State;
+use crate::helpers::http::request::negotiation::NegotiationCache;
+use crate::state::client_disconnect::DisconnectGuard;
+use crate::state::{ClientDisconnect, RequestContext, State};
+use crate::throttle::ConnectionThrottle;
+#[cfg(unix)]
+use crate::unix::PeerCredentials;
 
+#[cfg(feature = "http2")]
+pub(crate) mod h2c;
 mod trap;
 
 pub use trap::call_handler;
@@ -37,21 +45,105 @@ where
     }
 
     pub(crate) fn connect(&self, client_addr: SocketAddr) -> ConnectedGothamService<T> {
+        self.connect_from(Some(client_addr))
+    }
+
+    /// Connects the service to a client without an address, like one connected over a Unix
+    /// domain socket.
+    pub(crate) fn connect_from(
+        &self,
+        client_addr: Option<SocketAddr>,
+    ) -> ConnectedGothamService<T> {
         ConnectedGothamService {
             client_addr,
             handler: self.handler.clone(),
+            negotiation: NegotiationCache::new(),
+            throttle: None,
+            activity: None,
+            #[cfg(feature = "http2")]
+            h2c: None,
+            #[cfg(unix)]
+            peer_credentials: None,
         }
     }
 }
 
 /// A `GothamService` which has been connected to a client. The major difference is that a
-/// `client_addr` has been assigned (as this isn't available from Hyper).
+/// `client_addr` has been assigned (as this isn't available from Hyper), unless the client has
+/// no address.
 pub(crate) struct ConnectedGothamService<T>
 where
     T: NewHandler + 'static,
 {
     handler: Arc<T>,
-    client_addr: SocketAddr,
+    client_addr: Option<SocketAddr>,
+    negotiation: NegotiationCache,
+    throttle: Option<ConnectionThrottle>,
+    activity: Option<Arc<ConnectionActivity>>,
+    #[cfg(feature = "http2")]
+    h2c: Option<h2c::PendingUpgrade>,
+    #[cfg(unix)]
+    peer_credentials: Option<PeerCredentials>,
+}
+
+impl<T> Clone for ConnectedGothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    fn clone(&self) -> Self {
+        ConnectedGothamService {
+            handler: self.handler.clone(),
+            client_addr: self.client_addr,
+            negotiation: self.negotiation.clone(),
+            throttle: self.throttle.clone(),
+            activity: self.activity.clone(),
+            #[cfg(feature = "http2")]
+            h2c: self.h2c.clone(),
+            #[cfg(unix)]
+            peer_credentials: self.peer_credentials,
+        }
+    }
+}
+
+impl<T> ConnectedGothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    /// Makes the bandwidth limits of the connection available to every request served by it.
+    pub(crate) fn with_throttle(self, throttle: ConnectionThrottle) -> Self {
+        ConnectedGothamService {
+            throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Records the requests served by the connection in `activity`, so it can be closed once idle.
+    pub(crate) fn with_activity(self, activity: Arc<ConnectionActivity>) -> Self {
+        ConnectedGothamService {
+            activity: Some(activity),
+            ..self
+        }
+    }
+
+    /// Upgrades the connection to HTTP/2 when requested with `Upgrade: h2c`, recording the upgrade
+    /// in `pending` for the connection to serve. Only plaintext connections may be upgraded.
+    #[cfg(feature = "http2")]
+    pub(crate) fn with_h2c(self, pending: h2c::PendingUpgrade) -> Self {
+        ConnectedGothamService {
+            h2c: Some(pending),
+            ..self
+        }
+    }
+
+    /// Makes the credentials of the process connected over a Unix domain socket available to
+    /// every request served by the connection.
+    #[cfg(unix)]
+    pub(crate) fn with_peer_credentials(self, peer_credentials: PeerCredentials) -> Self {
+        ConnectedGothamService {
+            peer_credentials: Some(peer_credentials),
+            ..self
+        }
+    }
 }
 
 impl<T> Service<Request<Body>> for ConnectedGothamService<T>
@@ -70,8 +162,91 @@ where
     }
 
     fn call<'a>(&'a mut self, req: Request<Body>) -> Self::Future {
-        let state = State::from_request(req, self.client_addr);
-        call_handler(self.handler.clone(), AssertUnwindSafe(state)).boxed()
+        #[cfg(feature = "http2")]
+        {
+            if let Some(pending) = &self.h2c {
+                if h2c::is_upgrade_request(&req) {
+                    return futures_util::future::ok(h2c::upgrade(req, pending)).boxed();
+                }
+            }
+        }
+
+        let mut state = State::from_connection(req, self.client_addr);
+        state.put(self.negotiation.clone());
+        let disconnect = ClientDisconnect::new();
+        state.put(disconnect.clone());
+        if let Some(throttle) = &self.throttle {
+            state.put(throttle.clone());
+        }
+        #[cfg(unix)]
+        {
+            if let Some(peer_credentials) = self.peer_credentials {
+                state.put(peer_credentials);
+            }
+        }
+        // the future is dropped before completion if the client goes away
+        let guard = DisconnectGuard::new(disconnect);
+        let request = self.activity.clone().map(ActiveRequest::new);
+        // log records emitted while serving the request carry its context
+        let context = RequestContext::new(&state);
+        let response = context.scope(call_handler(self.handler.clone(), AssertUnwindSafe(state)));
+        async move {
+            let response = response.await;
+            guard.complete();
+            drop(request);
+            response
+        }
+        .boxed()
+    }
+}
+
+/// Tracks the requests in progress on a connection, and when the connection became idle.
+pub(crate) struct ConnectionActivity {
+    // the number of requests in progress, and the instant the last one completed
+    state: Mutex<(usize, Instant)>,
+}
+
+impl ConnectionActivity {
+    pub(crate) fn new() -> Self {
+        ConnectionActivity {
+            state: Mutex::new((0, Instant::now())),
+        }
+    }
+
+    /// Completes once no request was in progress for `timeout`.
+    pub(crate) async fn idle_for(&self, timeout: Duration) {
+        loop {
+            let (active, idle_since) = *self.state.lock().unwrap();
+            let deadline = idle_since + timeout;
+            if active == 0 && deadline <= Instant::now() {
+                return;
+            }
+            // requests in progress are checked again after the timeout
+            let deadline = if active == 0 {
+                deadline
+            } else {
+                Instant::now() + timeout
+            };
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+}
+
+// Marks a request as in progress until it is dropped.
+struct ActiveRequest(Arc<ConnectionActivity>);
+
+impl ActiveRequest {
+    fn new(activity: Arc<ConnectionActivity>) -> Self {
+        activity.state.lock().unwrap().0 += 1;
+        ActiveRequest(activity)
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.0 -= 1;
+        state.1 = Instant::now();
     }
 }
 
@@ -8,6 +8,7 @@ use hyper::{Body, Response, StatusCode};
 use log::error;
 
 use crate::handler::{Handler, HandlerError, IntoResponse, NewHandler};
+use crate::helpers::http::vary::apply_vary;
 use crate::state::{request_id, State};
 
 async fn handle<H>(
@@ -37,7 +38,10 @@ where
                 .catch_unwind()
                 .await;
             let result = match unwind_result {
-                Ok(result) => result.map(|(_, res)| res),
+                Ok(result) => result.map(|(state, mut res)| {
+                    apply_vary(&state, res.headers_mut());
+                    res
+                }),
                 Err(_) => Ok(finalize_panic_response()),
             };
             Ok(match result {
@@ -53,7 +57,9 @@ where
 fn finalize_error_response(state: State, err: HandlerError) -> Response<Body> {
     error!("[ERROR][{}][Error: {:?}]", request_id(&state), err);
 
-    err.into_response(&state)
+    let mut res = err.into_response(&state);
+    apply_vary(&state, res.headers_mut());
+    res
 }
 
 fn finalize_panic_response() -> Response<Body> {
@@ -0,0 +1,377 @@
+//! Server-Sent Events (SSE) responses, and a `Broadcaster` for fanning events out to many
+//! subscribers.
+//!
+//! `create_sse_response` turns any `Stream` of `Event`s into a `text/event-stream` response. For
+//! live endpoints where every connected client receives the same events, a `Broadcaster` can be
+//! stored in the application state (e.g. via `StateMiddleware`) and cloned freely; each request
+//! subscribes to it and receives all events sent afterwards.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use gotham::middleware::state::StateMiddleware;
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! # use gotham::state::{FromState, State};
+//! # use hyper::{Body, Response};
+//! use gotham::sse::{Broadcaster, Event};
+//!
+//! fn subscribe(state: State) -> (State, Response<Body>) {
+//!     let response = Broadcaster::borrow_from(&state).response(&state);
+//!     (state, response)
+//! }
+//!
+//! # fn main() {
+//! let broadcaster = Broadcaster::new(64);
+//! let (chain, pipelines) = single_pipeline(
+//!     new_pipeline()
+//!         .add(StateMiddleware::new(broadcaster.clone()))
+//!         .build(),
+//! );
+//! let router = build_router(chain, pipelines, |route| {
+//!     route.get("/events").to(subscribe);
+//! });
+//! # let _ = router;
+//!
+//! // somewhere else in the application
+//! broadcaster.send(Event::new("something happened").with_event("update"));
+//! # }
+//! ```
+
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use hyper::header::{HeaderValue, CACHE_CONTROL};
+use hyper::{Body, Response, StatusCode};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::helpers::http::response::create_response;
+use crate::state::{State, StateData};
+
+/// A single event sent to the client of an SSE response.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Creates a new unnamed event carrying `data`. Multi-line data is sent as multiple `data`
+    /// fields, which the client joins back together.
+    pub fn new<D: Into<String>>(data: D) -> Self {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    /// Sets the event type, dispatched by browsers to listeners registered for that name.
+    pub fn with_event<E: Into<String>>(mut self, event: E) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Sets the event id, which the client sends back in `Last-Event-ID` when reconnecting.
+    pub fn with_id<I: Into<String>>(mut self, id: I) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the reconnection delay the client should use if the connection is lost.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Returns the data carried by this event.
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// Returns the event type, if any.
+    pub fn event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+
+    /// Returns the event id, if any.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Encodes the event in the `text/event-stream` format.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = String::with_capacity(self.data.len() + 16);
+        // field values cannot contain line breaks, so these are stripped from single line fields
+        if let Some(event) = &self.event {
+            let _ = writeln!(buf, "event: {}", single_line(event));
+        }
+        if let Some(id) = &self.id {
+            let _ = writeln!(buf, "id: {}", single_line(id));
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(buf, "retry: {}", retry.as_millis());
+        }
+        // clients accept CRLF, a lone CR and a lone LF as line endings
+        for line in self
+            .data
+            .split("\r\n")
+            .flat_map(|line| line.split(['\r', '\n']))
+        {
+            let _ = writeln!(buf, "data: {}", line);
+        }
+        buf.push('\n');
+        Bytes::from(buf)
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// Creates a `200 OK` response streaming the given events to the client.
+///
+/// The response is marked as uncacheable, and ends when the stream does.
+pub fn create_sse_response<S>(state: &State, events: S) -> Response<Body>
+where
+    S: Stream<Item = Event> + Send + 'static,
+{
+    let body = Body::wrap_stream(events.map(|event| Ok::<_, Infallible>(event.to_bytes())));
+    let mut response = create_response(state, StatusCode::OK, mime::TEXT_EVENT_STREAM, body);
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+/// Determines what happens to a subscriber which falls behind by more events than fit in the
+/// buffer of its `Broadcaster`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LagPolicy {
+    /// Silently drop the missed events and continue with the oldest buffered one.
+    Skip,
+    /// Drop the missed events, then send a `lagged` event whose data is the number of events
+    /// missed, allowing the client to resynchronise.
+    Notify,
+    /// End the subscriber's stream, closing the response. Browsers reconnect automatically.
+    Disconnect,
+}
+
+/// A clonable sender fanning out `Event`s to any number of SSE subscribers.
+///
+/// Every subscriber has its own view of a shared ring buffer of `capacity` events. Sending never
+/// blocks; a subscriber which does not keep up is handled according to the `LagPolicy`.
+#[derive(Debug)]
+pub struct Broadcaster {
+    // Depending on the features of tokio, the channel is not `RefUnwindSafe`, but events are only
+    // ever sent and received as a whole.
+    sender: AssertUnwindSafe<broadcast::Sender<Event>>,
+    lag_policy: LagPolicy,
+}
+
+impl Clone for Broadcaster {
+    fn clone(&self) -> Self {
+        Broadcaster {
+            sender: AssertUnwindSafe(self.sender.0.clone()),
+            lag_policy: self.lag_policy,
+        }
+    }
+}
+
+impl StateData for Broadcaster {}
+
+impl Broadcaster {
+    /// Creates a new `Broadcaster` buffering up to `capacity` events per subscriber. Lagging
+    /// subscribers skip the missed events.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Broadcaster {
+            sender: AssertUnwindSafe(sender),
+            lag_policy: LagPolicy::Skip,
+        }
+    }
+
+    /// Sets the policy for subscribers created from now on which fall behind.
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Sends an event to all current subscribers, returning the number of subscribers it was
+    /// queued for.
+    pub fn send(&self, event: Event) -> usize {
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    /// Returns the number of currently connected subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Creates a new subscriber, receiving all events sent from now on.
+    pub fn subscribe(&self) -> Subscriber {
+        let lag_policy = self.lag_policy;
+        let events = stream::unfold(self.sender.subscribe(), move |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Closed) => return None,
+                    Err(RecvError::Lagged(missed)) => match lag_policy {
+                        LagPolicy::Skip => continue,
+                        LagPolicy::Notify => {
+                            let event = Event::new(missed.to_string()).with_event("lagged");
+                            return Some((event, receiver));
+                        }
+                        LagPolicy::Disconnect => return None,
+                    },
+                }
+            }
+        });
+
+        Subscriber {
+            events: events.boxed(),
+        }
+    }
+
+    /// Subscribes to this `Broadcaster`, returning an SSE response streaming the events.
+    pub fn response(&self, state: &State) -> Response<Body> {
+        create_sse_response(state, self.subscribe())
+    }
+}
+
+/// A `Stream` of the events sent by a `Broadcaster`, created by `Broadcaster::subscribe`.
+///
+/// The stream ends once all clones of the `Broadcaster` have been dropped.
+pub struct Subscriber {
+    events: Pin<Box<dyn Stream<Item = Event> + Send>>,
+}
+
+impl Stream for Subscriber {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.events.as_mut().poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use hyper::header::CONTENT_TYPE;
+
+    #[test]
+    fn encodes_events() {
+        let event = Event::new("first\nsecond")
+            .with_event("update")
+            .with_id("42")
+            .with_retry(Duration::from_secs(3));
+        assert_eq!(
+            event.to_bytes(),
+            "event: update\nid: 42\nretry: 3000\ndata: first\ndata: second\n\n"
+        );
+        assert_eq!(Event::new("").to_bytes(), "data: \n\n");
+    }
+
+    #[test]
+    fn splits_data_on_any_line_ending() {
+        let event = Event::new("a\r\nb\rc\nd\r");
+        assert_eq!(
+            event.to_bytes(),
+            "data: a\ndata: b\ndata: c\ndata: d\ndata: \n\n"
+        );
+    }
+
+    #[test]
+    fn strips_line_breaks_from_fields() {
+        let event = Event::new("data").with_event("a\nb");
+        assert_eq!(event.to_bytes(), "event: ab\ndata: data\n\n");
+    }
+
+    #[tokio::test]
+    async fn fans_out_to_subscribers() {
+        let broadcaster = Broadcaster::new(4);
+        let mut first = broadcaster.subscribe();
+        let mut second = broadcaster.subscribe();
+        assert_eq!(broadcaster.subscriber_count(), 2);
+
+        assert_eq!(broadcaster.send(Event::new("hello")), 2);
+        assert_eq!(first.next().await, Some(Event::new("hello")));
+        assert_eq!(second.next().await, Some(Event::new("hello")));
+
+        drop(broadcaster);
+        assert_eq!(first.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_skip_events() {
+        let broadcaster = Broadcaster::new(2);
+        let mut subscriber = broadcaster.subscribe();
+        for i in 0..4 {
+            broadcaster.send(Event::new(i.to_string()));
+        }
+        assert_eq!(subscriber.next().await, Some(Event::new("2")));
+        assert_eq!(subscriber.next().await, Some(Event::new("3")));
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_are_notified() {
+        let broadcaster = Broadcaster::new(2).with_lag_policy(LagPolicy::Notify);
+        let mut subscriber = broadcaster.subscribe();
+        for i in 0..4 {
+            broadcaster.send(Event::new(i.to_string()));
+        }
+        assert_eq!(
+            subscriber.next().await,
+            Some(Event::new("2").with_event("lagged"))
+        );
+        assert_eq!(subscriber.next().await, Some(Event::new("2")));
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_are_disconnected() {
+        let broadcaster = Broadcaster::new(2).with_lag_policy(LagPolicy::Disconnect);
+        let mut subscriber = broadcaster.subscribe();
+        for i in 0..4 {
+            broadcaster.send(Event::new(i.to_string()));
+        }
+        assert_eq!(subscriber.next().await, None);
+    }
+
+    #[test]
+    fn streams_events_in_response() {
+        let router = build_simple_router(|route| {
+            route.get("/").to(|state: State| {
+                let events = stream::iter(vec![Event::new("one"), Event::new("two")]);
+                let response = create_sse_response(&state, events);
+                (state, response)
+            })
+        });
+        let test_server = TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+        assert_eq!(
+            response.read_utf8_body().unwrap(),
+            "data: one\n\ndata: two\n\n"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Defines types for sharing application data, such as a database pool or configuration, between
+//! all requests.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{State, StateData};
+
+/// A shared value of type `T`, registered with `AppData` and placed into the `State` of every
+/// request.
+///
+/// Cloning a `Data<T>` only clones the `Arc` holding the value.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::state::{Data, FromState, State};
+///
+/// struct Config {
+///     greeting: String,
+/// }
+///
+/// fn handler(state: State) -> (State, String) {
+///     let greeting = Data::<Config>::borrow_from(&state).greeting.clone();
+///     (state, greeting)
+/// }
+///
+/// # fn main() {
+/// let config = Config {
+///     greeting: "Hello!".to_owned(),
+/// };
+///
+/// let router = build_simple_router(|route| {
+///     route.add_data(Arc::new(config));
+///     route.get("/").to(handler);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// # assert_eq!(response.read_utf8_body().unwrap(), "Hello!");
+/// # }
+/// ```
+pub struct Data<T: ?Sized>(Arc<T>);
+
+impl<T: ?Sized> Data<T> {
+    /// Creates a new `Data<T>` from a shared value.
+    pub fn new(value: Arc<T>) -> Self {
+        Data(value)
+    }
+
+    /// Returns the `Arc` holding the shared value.
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T: ?Sized> Clone for Data<T> {
+    fn clone(&self) -> Self {
+        Data(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Deref for Data<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Data<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Data").field(&self.0).finish()
+    }
+}
+
+impl<T: ?Sized + Send + Sync + 'static> StateData for Data<T> {}
+
+type PutData = dyn Fn(&mut State) + Send + Sync + RefUnwindSafe;
+
+/// A collection of shared values, each of which is placed into the `State` of a request as a
+/// `Data<T>`, where handlers can borrow it.
+///
+/// Values are usually registered with `RouterBuilder::add_data` while building the router, but
+/// `AppData` can also be added to a pipeline as middleware. Only one value is kept per type, so
+/// registering another value of the same type replaces the previous one.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::state::{AppData, Data, FromState, State};
+///
+/// struct Counter(std::sync::atomic::AtomicUsize);
+///
+/// fn handler(state: State) -> (State, String) {
+///     let counter = Data::<Counter>::borrow_from(&state);
+///     let count = counter.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+///     (state, count.to_string())
+/// }
+///
+/// # fn main() {
+/// let app_data = AppData::new().with(Arc::new(Counter(Default::default())));
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(app_data).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// # assert_eq!(response.read_utf8_body().unwrap(), "1");
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct AppData {
+    values: HashMap<TypeId, Arc<PutData>>,
+}
+
+impl AppData {
+    /// Creates an empty `AppData`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a shared value, which handlers can borrow as `Data<T>`.
+    pub fn with<T>(mut self, value: Arc<T>) -> Self
+    where
+        T: ?Sized + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.insert(value);
+        self
+    }
+
+    /// Registers a shared value, which handlers can borrow as `Data<T>`.
+    pub fn insert<T>(&mut self, value: Arc<T>)
+    where
+        T: ?Sized + Send + Sync + RefUnwindSafe + 'static,
+    {
+        let put = move |state: &mut State| state.put(Data(value.clone()));
+        self.values.insert(TypeId::of::<Data<T>>(), Arc::new(put));
+    }
+
+    /// Determines if a value of type `T` has been registered.
+    pub fn contains<T>(&self) -> bool
+    where
+        T: ?Sized + 'static,
+    {
+        self.values.contains_key(&TypeId::of::<Data<T>>())
+    }
+
+    /// Returns the number of registered values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Determines if no values have been registered.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Places all registered values into the `State`.
+    pub(crate) fn put_into(&self, state: &mut State) {
+        for put in self.values.values() {
+            put(state);
+        }
+    }
+}
+
+impl fmt::Debug for AppData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppData")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+impl Middleware for AppData {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        self.put_into(&mut state);
+        chain(state)
+    }
+}
+
+impl NewMiddleware for AppData {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FromState;
+
+    #[derive(Debug, PartialEq)]
+    struct Config {
+        name: &'static str,
+    }
+
+    trait Greeter: Send + Sync + RefUnwindSafe {
+        fn greet(&self) -> String;
+    }
+
+    struct English;
+
+    impl Greeter for English {
+        fn greet(&self) -> String {
+            "Hello".to_owned()
+        }
+    }
+
+    #[test]
+    fn puts_values_into_state() {
+        let app_data = AppData::new()
+            .with(Arc::new(Config { name: "gotham" }))
+            .with(Arc::new(42u32))
+            .with::<dyn Greeter>(Arc::new(English));
+
+        assert_eq!(app_data.len(), 3);
+        assert!(app_data.contains::<Config>());
+        assert!(app_data.contains::<dyn Greeter>());
+        assert!(!app_data.contains::<u64>());
+
+        State::with_new(|state| {
+            app_data.put_into(state);
+            assert_eq!(Data::<Config>::borrow_from(state).name, "gotham");
+            assert_eq!(**Data::<u32>::borrow_from(state), 42);
+            assert_eq!(Data::<dyn Greeter>::borrow_from(state).greet(), "Hello");
+        });
+    }
+
+    #[test]
+    fn replaces_values_of_same_type() {
+        let shared = Arc::new(Config { name: "second" });
+        let app_data = AppData::new()
+            .with(Arc::new(Config { name: "first" }))
+            .with(shared.clone());
+        assert_eq!(app_data.len(), 1);
+
+        State::with_new(|state| {
+            app_data.put_into(state);
+            let data = Data::<Config>::take_from(state);
+            assert!(Arc::ptr_eq(&data.into_inner(), &shared));
+        });
+    }
+}
@@ -16,6 +16,9 @@ pub(crate) fn put_client_addr(state: &mut State, addr: SocketAddr) {
 /// Returns the client `SocketAddr` as reported by hyper, if one was present. Certain connections
 /// do not report a client address, in which case this will return `None`.
 ///
+/// Behind a load balancer or reverse proxy, this is the address of the proxy unless
+/// `gotham::middleware::client_ip::ClientIpMiddleware` resolved the address of the client.
+///
 /// # Examples
 ///
 /// ```rust
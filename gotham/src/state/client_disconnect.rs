@@ -0,0 +1,149 @@
+//! Defines a signal for requests whose client went away before they were answered.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+use crate::state::StateData;
+
+/// Signals that the client of a request went away before the request was answered.
+///
+/// Gotham places a `ClientDisconnect` into the `State` of every request. Once the connection of
+/// the client is closed, or the request is cancelled by an HTTP/2 client, Hyper drops the future
+/// serving the request, which triggers the signal. Work which outlives the handler future, like
+/// an expensive export spawned onto the runtime, or the producer of a streaming response, can
+/// check or await the signal to stop early instead of computing a response nobody will receive.
+///
+/// The signal is not triggered once the handler produced its response, even if the client goes
+/// away while the response body is sent.
+///
+/// # Examples
+///
+/// ```rust
+/// # use gotham::test::TestServer;
+/// use gotham::state::{ClientDisconnect, FromState, State};
+///
+/// fn handler(state: State) -> (State, &'static str) {
+///     let disconnect = ClientDisconnect::borrow_from(&state).clone();
+///
+///     tokio::spawn(async move {
+///         tokio::select! {
+///             _ = disconnect.disconnected() => println!("client went away, export cancelled"),
+///             _ = tokio::time::sleep(std::time::Duration::from_millis(10)) => println!("exported"),
+///         }
+///     });
+///
+///     (state, "export started")
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// #   let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// #   assert_eq!(response.status(), 200);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ClientDisconnect {
+    inner: Arc<Signal>,
+}
+
+#[derive(Default)]
+struct Signal {
+    disconnected: AtomicBool,
+    notify: Notify,
+}
+
+impl StateData for ClientDisconnect {}
+
+impl ClientDisconnect {
+    pub(crate) fn new() -> Self {
+        ClientDisconnect {
+            inner: Arc::new(Signal::default()),
+        }
+    }
+
+    /// Returns whether the client went away before the request was answered.
+    pub fn is_disconnected(&self) -> bool {
+        self.inner.disconnected.load(Ordering::Acquire)
+    }
+
+    /// Completes once the client went away before the request was answered, which may be never.
+    pub async fn disconnected(&self) {
+        loop {
+            // created before checking the flag, so a signal in between is not missed
+            let notified = self.inner.notify.notified();
+            if self.is_disconnected() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn trigger(&self) {
+        self.inner.disconnected.store(true, Ordering::Release);
+        self.inner.notify.notify_waiters();
+    }
+}
+
+/// Triggers the `ClientDisconnect` of a request if it is dropped before the request was answered.
+pub(crate) struct DisconnectGuard {
+    disconnect: Option<ClientDisconnect>,
+}
+
+impl DisconnectGuard {
+    pub(crate) fn new(disconnect: ClientDisconnect) -> Self {
+        DisconnectGuard {
+            disconnect: Some(disconnect),
+        }
+    }
+
+    /// Marks the request as answered.
+    pub(crate) fn complete(mut self) {
+        self.disconnect = None;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if let Some(disconnect) = self.disconnect.take() {
+            disconnect.trigger();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn signals_dropped_requests() {
+        let disconnect = ClientDisconnect::new();
+        let waiting = tokio::spawn({
+            let disconnect = disconnect.clone();
+            async move { disconnect.disconnected().await }
+        });
+        let guard = DisconnectGuard::new(disconnect.clone());
+        assert!(!disconnect.is_disconnected());
+
+        drop(guard);
+        assert!(disconnect.is_disconnected());
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("disconnect signalled")
+            .unwrap();
+        // completes immediately once disconnected
+        disconnect.disconnected().await;
+    }
+
+    #[tokio::test]
+    async fn ignores_answered_requests() {
+        let disconnect = ClientDisconnect::new();
+        DisconnectGuard::new(disconnect.clone()).complete();
+        assert!(!disconnect.is_disconnected());
+        let waited =
+            tokio::time::timeout(Duration::from_millis(10), disconnect.disconnected()).await;
+        assert!(waited.is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Defines an owned snapshot of request data which can be moved into spawned futures.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::state::{client_addr, request_id, FromState, State, StateData};
+
+tokio::task_local! {
+    static CURRENT: RequestContext;
+}
+
+/// An owned snapshot of selected items of the `State`, which can outlive the handler.
+///
+/// `State` is owned by the request, and can't be moved into work which is spawned onto the
+/// runtime in the background. A `RequestContext` captures the request ID and client address of a
+/// request, along with any other state data selected with `with`, such as a trace context or the
+/// authenticated principal. Values are shared, so cloning a `RequestContext` is cheap.
+///
+/// Gotham serves every request within the scope of its context, so `RequestContext::current`
+/// returns it to any code called while handling the request. Spawned work can either take the
+/// context as an argument, or run within `RequestContext::scope` to make it available through
+/// `RequestContext::current` to any code called from it, e.g. for logging.
+///
+/// # Examples
+///
+/// ```rust
+/// # use gotham::test::TestServer;
+/// use gotham::state::{RequestContext, State, StateData};
+///
+/// #[derive(Clone, StateData)]
+/// struct Principal {
+///     name: String,
+/// }
+///
+/// fn handler(mut state: State) -> (State, &'static str) {
+///     # state.put(Principal { name: "alice".to_owned() });
+///     let context = RequestContext::new(&state).with::<Principal>(&state);
+///
+///     tokio::spawn(context.scope(async {
+///         RequestContext::with_current(|context| {
+///             let principal = context.get::<Principal>().unwrap();
+///             println!("[{}] sending mail to {}", context.request_id(), principal.name);
+///         });
+///     }));
+///
+///     (state, "Mail will be sent shortly")
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// #   let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// #   assert_eq!(response.status(), 200);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestContext {
+    request_id: Arc<str>,
+    client_addr: Option<SocketAddr>,
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl RequestContext {
+    /// Creates a new `RequestContext` from the request ID and client address stored in the
+    /// `State`.
+    ///
+    /// # Panics
+    ///
+    /// If the `State` does not contain a request ID, which Gotham always populates before invoking
+    /// application code.
+    pub fn new(state: &State) -> Self {
+        RequestContext {
+            request_id: request_id(state).into(),
+            client_addr: client_addr(state),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Adds a clone of the value of type `T` stored in the `State` to the context. Nothing is
+    /// added if the `State` does not contain such a value.
+    pub fn with<T>(mut self, state: &State) -> Self
+    where
+        T: StateData + Clone + Sync,
+    {
+        if let Some(value) = T::try_borrow_from(state) {
+            self.insert(value.clone());
+        }
+        self
+    }
+
+    /// Adds a value to the context, replacing any existing value of the same type.
+    pub fn insert<T>(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns the value of type `T`, if the context contains one.
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns the ID of the request this context was created from.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Returns the address of the client of the request this context was created from.
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        self.client_addr
+    }
+
+    /// Runs `future` with this context as the current context, which is returned by
+    /// `RequestContext::current` while the future is polled.
+    pub fn scope<F>(self, future: F) -> impl Future<Output = F::Output>
+    where
+        F: Future,
+    {
+        CURRENT.scope(self, future)
+    }
+
+    /// Returns a clone of the current context, if called within `RequestContext::scope`.
+    pub fn current() -> Option<RequestContext> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Calls `f` with the current context, if called within `RequestContext::scope`.
+    pub fn with_current<F, R>(f: F) -> Option<R>
+    where
+        F: FnOnce(&RequestContext) -> R,
+    {
+        CURRENT.try_with(f).ok()
+    }
+}
+
+impl fmt::Debug for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestContext")
+            .field("request_id", &self.request_id)
+            .field("client_addr", &self.client_addr)
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::client_addr::put_client_addr;
+    use crate::state::set_request_id;
+    use hyper::HeaderMap;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Principal(&'static str);
+
+    impl StateData for Principal {}
+
+    fn state_context() -> RequestContext {
+        let mut context = None;
+        State::with_new(|state| {
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Request-ID", "abc".parse().unwrap());
+            state.put(headers);
+            set_request_id(state);
+            put_client_addr(state, "127.0.0.1:8080".parse().unwrap());
+            state.put(Principal("alice"));
+
+            context = Some(
+                RequestContext::new(state)
+                    .with::<Principal>(state)
+                    .with::<Missing>(state),
+            );
+        });
+        context.unwrap()
+    }
+
+    #[derive(Clone)]
+    struct Missing;
+
+    impl StateData for Missing {}
+
+    #[test]
+    fn captures_state_items() {
+        let context = state_context();
+        assert_eq!(context.request_id(), "abc");
+        assert_eq!(
+            context.client_addr(),
+            Some("127.0.0.1:8080".parse().unwrap())
+        );
+        assert_eq!(context.get::<Principal>(), Some(&Principal("alice")));
+        assert!(context.get::<Missing>().is_none());
+    }
+
+    #[tokio::test]
+    async fn provides_current_context_in_scope() {
+        assert!(RequestContext::current().is_none());
+
+        let context = state_context();
+        let principal = tokio::spawn(context.scope(async {
+            tokio::task::yield_now().await;
+            RequestContext::with_current(|context| context.get::<Principal>().cloned())
+        }))
+        .await
+        .unwrap();
+        assert_eq!(principal, Some(Some(Principal("alice"))));
+        assert!(RequestContext::current().is_none());
+    }
+}
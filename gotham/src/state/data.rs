@@ -12,20 +12,36 @@ pub use gotham_derive::StateData;
 
 /// A marker trait for types that can be stored in `State`.
 ///
-/// This is typically implemented using `#[derive(StateData)]`.
+/// This is typically implemented using `#[derive(StateData)]`. With `#[state_data(accessors)]`, the
+/// derive also generates the associated functions `borrow`, `try_borrow`, `borrow_mut`,
+/// `try_borrow_mut`, `take` and `try_take` on the type, with the same visibility as the type.
+/// These are opt-in, as they may collide with methods of the type or of traits it implements.
+/// When the value is not present in `State`, the panic message names the missing type.
 ///
 /// ```rust
 /// # use gotham::state::{FromState, State};
 /// use gotham::state::StateData;
 ///
 /// #[derive(StateData)]
+/// #[state_data(accessors)]
 /// struct MyStateData {
 ///     x: u32,
 /// }
+/// #
+/// # // without the accessors, the type may have methods of the same name
+/// # #[derive(StateData)]
+/// # struct Queue;
+/// # impl Queue {
+/// #     fn take(&self) {}
+/// # }
 /// # fn main() {
+/// #   Queue.take();
 /// #   State::with_new(|state| {
+/// #       assert!(MyStateData::try_borrow(state).is_none());
 /// #       state.put(MyStateData { x: 1 });
 /// #       assert_eq!(MyStateData::borrow_from(state).x, 1);
+/// #       MyStateData::borrow_mut(state).x += 1;
+/// #       assert_eq!(MyStateData::take(state).x, 2);
 /// #   });
 /// # }
 /// ```
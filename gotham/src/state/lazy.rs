@@ -0,0 +1,272 @@
+//! Defines types for values which are computed at most once per request.
+
+use std::fmt;
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::{BoxFuture, FutureExt};
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{State, StateData};
+
+type Init<T> = dyn Fn(&State) -> BoxFuture<'static, T> + Send + Sync + RefUnwindSafe;
+
+/// A value of type `T` stored in the `State`, which is computed by an async initializer the first
+/// time it is requested, and then kept for the remainder of the request. This avoids loading the
+/// same data, such as the current user, several times within a single request.
+///
+/// The initializer receives the `State` when it is called, and returns a future which must not
+/// borrow from it. To express failure, use a `Result` or `Option` as `T`.
+///
+/// A `Lazy<T>` is usually placed into the `State` of every request by a `LazyMiddleware`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::handler::{HandlerResult, IntoResponse};
+/// use gotham::state::{Lazy, LazyMiddleware, State};
+///
+/// struct User {
+///     name: String,
+/// }
+///
+/// async fn handler(mut state: State) -> HandlerResult {
+///     // the user is only loaded once, however often this is called
+///     let name = Lazy::<User>::get(&mut state).await.name.clone();
+///     let response = name.into_response(&state);
+///     Ok((state, response))
+/// }
+///
+/// # fn main() {
+/// let load_user = LazyMiddleware::new(|_state: &State| async {
+///     User {
+///         name: "alice".to_owned(),
+///     }
+/// });
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(load_user).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to_async(handler);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// # assert_eq!(response.read_utf8_body().unwrap(), "alice");
+/// # }
+/// ```
+pub struct Lazy<T> {
+    value: Option<T>,
+    init: Arc<Init<T>>,
+}
+
+impl<T> Lazy<T>
+where
+    T: Send + 'static,
+{
+    /// Creates a new `Lazy<T>`, which is computed by `init` when it is first requested.
+    pub fn new<F, Fut>(init: F) -> Self
+    where
+        F: Fn(&State) -> Fut + Send + Sync + RefUnwindSafe + 'static,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        Lazy {
+            value: None,
+            init: Arc::new(move |state: &State| init(state).boxed()),
+        }
+    }
+
+    /// Returns the value stored in the `State`, running the initializer if this is the first time
+    /// it is requested during the request.
+    ///
+    /// # Panics
+    ///
+    /// If no `Lazy<T>` is present in the `State`.
+    pub async fn get(state: &mut State) -> &T {
+        let lazy = state.borrow_mut::<Lazy<T>>();
+        if lazy.value.is_none() {
+            let init = lazy.init.clone();
+            let value = init(state).await;
+            state.borrow_mut::<Lazy<T>>().value = Some(value);
+        }
+
+        match state.borrow::<Lazy<T>>().value {
+            Some(ref value) => value,
+            None => unreachable!("lazy value was initialized above"),
+        }
+    }
+
+    /// Returns the value stored in the `State` if it has already been computed, without running
+    /// the initializer. Returns `None` if it has not, or if no `Lazy<T>` is present in the `State`.
+    pub fn peek(state: &State) -> Option<&T> {
+        state
+            .try_borrow::<Lazy<T>>()
+            .and_then(|lazy| lazy.value.as_ref())
+    }
+
+    /// Determines if the value has already been computed.
+    pub fn is_initialized(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+impl<T> fmt::Debug for Lazy<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy").field("value", &self.value).finish()
+    }
+}
+
+impl<T> StateData for Lazy<T> where T: Send + 'static {}
+
+/// Middleware which places a `Lazy<T>` into the `State` of every request, computing the value
+/// with the initializer at most once per request. See `Lazy` for an example.
+pub struct LazyMiddleware<T> {
+    init: Arc<Init<T>>,
+}
+
+impl<T> LazyMiddleware<T>
+where
+    T: Send + 'static,
+{
+    /// Creates a new `LazyMiddleware`, using `init` to compute the value of each request.
+    pub fn new<F, Fut>(init: F) -> Self
+    where
+        F: Fn(&State) -> Fut + Send + Sync + RefUnwindSafe + 'static,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        LazyMiddleware {
+            init: Lazy::new(init).init,
+        }
+    }
+}
+
+impl<T> Clone for LazyMiddleware<T> {
+    fn clone(&self) -> Self {
+        LazyMiddleware {
+            init: self.init.clone(),
+        }
+    }
+}
+
+impl<T> Middleware for LazyMiddleware<T>
+where
+    T: Send + 'static,
+{
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        state.put(Lazy {
+            value: None,
+            init: self.init,
+        });
+        chain(state)
+    }
+}
+
+impl<T> NewMiddleware for LazyMiddleware<T>
+where
+    T: Send + 'static,
+{
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, PartialEq)]
+    struct User(usize);
+
+    #[test]
+    fn initializes_at_most_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let lazy = Lazy::new(move |_state: &State| {
+            let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { User(call) }
+        });
+        assert!(!lazy.is_initialized());
+
+        State::with_new(|state| {
+            state.put(lazy);
+            assert_eq!(Lazy::<User>::peek(state), None);
+
+            futures_executor::block_on(async {
+                assert_eq!(Lazy::<User>::get(state).await, &User(1));
+                assert_eq!(Lazy::<User>::get(state).await, &User(1));
+            });
+
+            assert_eq!(Lazy::<User>::peek(state), Some(&User(1)));
+            assert!(state.borrow::<Lazy<User>>().is_initialized());
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn initializer_reads_state() {
+        struct SessionId(&'static str);
+        impl StateData for SessionId {}
+
+        let lazy = Lazy::new(|state: &State| {
+            let id = state.borrow::<SessionId>().0;
+            async move { format!("user of {}", id) }
+        });
+
+        State::with_new(|state| {
+            state.put(SessionId("abc"));
+            state.put(lazy);
+            let user = futures_executor::block_on(Lazy::<String>::get(state));
+            assert_eq!(user, "user of abc");
+        });
+    }
+
+    #[test]
+    fn middleware_creates_value_per_request() {
+        use crate::handler::{HandlerResult, IntoResponse};
+        use crate::pipeline::{new_pipeline, single_pipeline};
+        use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+        use crate::test::TestServer;
+
+        async fn handler(mut state: State) -> HandlerResult {
+            let first = Lazy::<User>::get(&mut state).await.0;
+            let second = Lazy::<User>::get(&mut state).await.0;
+            let response = format!("{} {}", first, second).into_response(&state);
+            Ok((state, response))
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let middleware = LazyMiddleware::new(move |_state: &State| {
+            let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { User(call) }
+        });
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.get("/").to_async(handler);
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+        for expected in &["1 1", "2 2"] {
+            let response = test_server
+                .client()
+                .get("http://localhost/")
+                .perform()
+                .unwrap();
+            assert_eq!(&response.read_utf8_body().unwrap(), expected);
+        }
+    }
+}
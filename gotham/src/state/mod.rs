@@ -1,9 +1,14 @@
 //! Defines types for passing request state through `Middleware` and `Handler` implementations
 
+mod app_data;
 pub(crate) mod client_addr;
+pub(crate) mod client_disconnect;
+mod context;
 mod data;
 mod from_state;
+mod lazy;
 mod request_id;
+mod tenant;
 
 use hyper::http::request;
 use hyper::upgrade::OnUpgrade;
@@ -14,10 +19,15 @@ use std::collections::HashMap;
 use std::hash::{BuildHasherDefault, Hasher};
 use std::net::SocketAddr;
 
+pub use crate::state::app_data::{AppData, Data};
 pub use crate::state::client_addr::client_addr;
+pub use crate::state::client_disconnect::ClientDisconnect;
+pub use crate::state::context::RequestContext;
 pub use crate::state::data::StateData;
 pub use crate::state::from_state::FromState;
-pub use crate::state::request_id::request_id;
+pub use crate::state::lazy::{Lazy, LazyMiddleware};
+pub use crate::state::request_id::{request_id, RequestId};
+pub use crate::state::tenant::{TenantMiddleware, TenantRegistry, TenantState};
 
 use crate::helpers::http::request::path::RequestPathSegments;
 use crate::state::client_addr::put_client_addr;
@@ -70,6 +80,8 @@ impl Hasher for IdHasher {
 /// ```
 pub struct State {
     data: HashMap<TypeId, Box<dyn Any + Send>, BuildHasherDefault<IdHasher>>,
+    #[cfg(feature = "state-inspection")]
+    type_names: HashMap<TypeId, &'static str, BuildHasherDefault<IdHasher>>,
 }
 
 impl State {
@@ -79,6 +91,8 @@ impl State {
     pub(crate) fn new() -> State {
         State {
             data: HashMap::default(),
+            #[cfg(feature = "state-inspection")]
+            type_names: HashMap::default(),
         }
     }
 
@@ -96,9 +110,17 @@ impl State {
     /// Instantiate a new `State` for a given `Request`. This is primarily useful if you're calling
     /// Gotham from your own Hyper service.
     pub fn from_request(req: Request<Body>, client_addr: SocketAddr) -> Self {
+        Self::from_connection(req, Some(client_addr))
+    }
+
+    /// Instantiates a new `State` for a `Request` received on a connection which may not have a
+    /// client address, like one over a Unix domain socket.
+    pub(crate) fn from_connection(req: Request<Body>, client_addr: Option<SocketAddr>) -> Self {
         let mut state = Self::new();
 
-        put_client_addr(&mut state, client_addr);
+        if let Some(client_addr) = client_addr {
+            put_client_addr(&mut state, client_addr);
+        }
 
         let (
             request::Parts {
@@ -179,6 +201,8 @@ impl State {
         let type_id = TypeId::of::<T>();
         trace!(" inserting record to state for type_id `{:?}`", type_id);
         self.data.insert(type_id, Box::new(t));
+        #[cfg(feature = "state-inspection")]
+        self.type_names.insert(type_id, std::any::type_name::<T>());
     }
 
     /// Determines if the current value exists in `State` storage.
@@ -218,7 +242,7 @@ impl State {
         T: StateData,
     {
         let type_id = TypeId::of::<T>();
-        self.data.get(&type_id).is_some()
+        self.data.contains_key(&type_id)
     }
 
     /// Tries to borrow a value from the `State` storage.
@@ -266,7 +290,8 @@ impl State {
     ///
     /// # Panics
     ///
-    /// If a value of type `T` is not present in `State`.
+    /// If a value of type `T` is not present in `State`. The panic message includes the name of
+    /// the type.
     ///
     /// # Examples
     ///
@@ -295,8 +320,7 @@ impl State {
     where
         T: StateData,
     {
-        self.try_borrow()
-            .expect("required type is not present in State container")
+        self.try_borrow().unwrap_or_else(|| self.missing::<T>())
     }
 
     /// Tries to mutably borrow a value from the `State` storage.
@@ -348,7 +372,8 @@ impl State {
     ///
     /// # Panics
     ///
-    /// If a value of type `T` is not present in `State`.
+    /// If a value of type `T` is not present in `State`. The panic message includes the name of
+    /// the type.
     ///
     /// # Examples
     ///
@@ -388,8 +413,11 @@ impl State {
     where
         T: StateData,
     {
+        if !self.has::<T>() {
+            self.missing::<T>()
+        }
         self.try_borrow_mut()
-            .expect("required type is not present in State container")
+            .expect("present type could not be borrowed from State container")
     }
 
     /// Tries to move a value out of the `State` storage and return ownership.
@@ -436,6 +464,8 @@ impl State {
             " taking ownership from state data for type_id `{:?}`",
             type_id
         );
+        #[cfg(feature = "state-inspection")]
+        self.type_names.remove(&type_id);
         self.data
             .remove(&type_id)
             .and_then(|b| b.downcast::<T>().ok())
@@ -446,7 +476,8 @@ impl State {
     ///
     /// # Panics
     ///
-    /// If a value of type `T` is not present in `State`.
+    /// If a value of type `T` is not present in `State`. The panic message includes the name of
+    /// the type.
     ///
     /// # Examples
     ///
@@ -479,7 +510,115 @@ impl State {
     where
         T: StateData,
     {
-        self.try_take()
-            .expect("required type is not present in State container")
+        self.try_take().unwrap_or_else(|| self.missing::<T>())
+    }
+}
+
+impl State {
+    /// Lists the names of the types currently stored in the `State`, in alphabetical order. This
+    /// is intended for debugging, as the names are not guaranteed to be stable.
+    ///
+    /// This requires the `state-inspection` feature, which makes `State` keep track of the names.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::state::{State, StateData};
+    /// #
+    /// # #[derive(StateData)]
+    /// # struct MyStruct {
+    /// #     value: i32
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #   State::with_new(|state| {
+    /// #
+    /// state.put(MyStruct { value: 1 });
+    /// # assert_eq!(state.borrow::<MyStruct>().value, 1);
+    /// assert!(state.type_names()[0].ends_with("MyStruct"));
+    /// #
+    /// #   });
+    /// # }
+    /// ```
+    #[cfg(feature = "state-inspection")]
+    pub fn type_names(&self) -> Vec<&'static str> {
+        let mut type_names: Vec<_> = self.type_names.values().copied().collect();
+        type_names.sort_unstable();
+        type_names
+    }
+
+    #[cold]
+    fn missing<T>(&self) -> ! {
+        #[cfg(feature = "state-inspection")]
+        panic!(
+            "required type `{}` is not present in State container, which contains: {}",
+            std::any::type_name::<T>(),
+            self.type_names().join(", ")
+        );
+
+        #[cfg(not(feature = "state-inspection"))]
+        panic!(
+            "required type `{}` is not present in State container",
+            std::any::type_name::<T>()
+        )
+    }
+}
+
+#[cfg(feature = "state-inspection")]
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.type_names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "state-inspection")]
+    use hyper::Method;
+
+    struct Missing;
+
+    impl StateData for Missing {}
+
+    #[test]
+    #[should_panic(expected = "Missing` is not present")]
+    fn panics_with_type_name() {
+        State::with_new(|state| {
+            state.borrow::<Missing>();
+        });
+    }
+
+    #[cfg(feature = "state-inspection")]
+    #[test]
+    fn lists_type_names() {
+        State::with_new(|state| {
+            state.put(Method::GET);
+            state.put(Missing);
+            let mut expected = vec![
+                std::any::type_name::<Missing>(),
+                std::any::type_name::<Method>(),
+            ];
+            expected.sort_unstable();
+            assert_eq!(state.type_names(), expected);
+            assert_eq!(
+                format!("{:?}", state),
+                format!("{{{:?}, {:?}}}", expected[0], expected[1])
+            );
+
+            state.take::<Missing>();
+            assert_eq!(state.type_names(), vec![std::any::type_name::<Method>()]);
+        });
+    }
+
+    #[cfg(feature = "state-inspection")]
+    #[test]
+    // only the listed contents mention `Method`
+    #[should_panic(expected = "Method")]
+    fn panics_with_type_names() {
+        State::with_new(|state| {
+            state.put(Method::GET);
+            state.borrow_mut::<Missing>();
+        });
     }
 }
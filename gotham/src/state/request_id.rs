@@ -4,27 +4,74 @@ use hyper::header::HeaderMap;
 use log::trace;
 use uuid::Uuid;
 
+use std::fmt;
+
 use crate::state::{FromState, State};
 
-/// A container type for the value returned by `request_id`.
-pub(super) struct RequestId {
+/// The unique identifier of a request, which is placed into the `State` of every request before
+/// the `Router` is invoked. Handlers can borrow it, or use `request_id` to get it as `&str`.
+///
+/// ```rust
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::state::{FromState, RequestId, State};
+///
+/// fn handler(state: State) -> (State, String) {
+///     let id = RequestId::borrow_from(&state).to_string();
+///     (state, id)
+/// }
+///
+/// # fn main() {
+/// # let test_server = TestServer::new(build_simple_router(|route| {
+/// #     route.get("/").to(handler);
+/// # }))
+/// # .unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .get("http://localhost/")
+/// #     .with_header("x-request-id", "abc".parse().unwrap())
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.read_utf8_body().unwrap(), "abc");
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId {
     val: String,
 }
 
+impl RequestId {
+    /// Returns the request ID as string.
+    pub fn as_str(&self) -> &str {
+        &self.val
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.val)
+    }
+}
+
 /// Sets a unique identifier for the request if it has not already been stored.
 ///
 /// The unique identifier chosen depends on the the request headers:
 ///
-/// 1. If the header `X-Request-ID` is provided this value is used as-is;
+/// 1. If the header `X-Request-ID` is provided with a non-empty, visible ASCII value, this value
+///    is used as-is;
 /// 2. Alternatively creates and stores a UUID v4 value.
 ///
 /// This function is invoked by `GothamService` before handing control to its `Router`, to ensure
 /// that a value for `RequestId` is always available.
 pub(crate) fn set_request_id<'a>(state: &'a mut State) -> &'a str {
     if !state.has::<RequestId>() {
-        let request_id = match HeaderMap::borrow_from(state).get("X-Request-ID") {
+        let external = HeaderMap::borrow_from(state)
+            .get("X-Request-ID")
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty() && value.bytes().all(|b| b.is_ascii_graphic()));
+        let request_id = match external {
             Some(ex_req_id) => {
-                let id = String::from_utf8(ex_req_id.as_bytes().into()).unwrap();
+                let id = ex_req_id.to_owned();
                 trace!(
                     "[{}] RequestId set from external source via X-Request-ID header",
                     id
@@ -61,6 +108,7 @@ pub fn request_id(state: &State) -> &str {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hyper::header::HeaderValue;
 
     #[test]
     #[should_panic(expected = "RequestId must be populated before application code is invoked")]
@@ -84,6 +132,18 @@ mod tests {
         assert_eq!("1-2-3-4", request_id(&state));
     }
 
+    #[test]
+    fn replaces_an_invalid_external_request_id() {
+        let mut state = State::new();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Request-ID", HeaderValue::from_bytes(b"a b\xff").unwrap());
+        state.put(headers);
+
+        let r = set_request_id(&mut state);
+        assert_eq!(4, Uuid::parse_str(r).unwrap().get_version_num());
+    }
+
     #[test]
     fn sets_a_unique_request_id() {
         let mut state = State::new();
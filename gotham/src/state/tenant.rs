@@ -0,0 +1,379 @@
+//! Defines types for partitioning shared resources between the tenants of a multi-tenant
+//! application.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use futures_util::future::{self, FutureExt};
+use hyper::header::{HeaderMap, HeaderName, HOST};
+use hyper::{StatusCode, Uri};
+use log::debug;
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::response::create_empty_response;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{request_id, FromState, State, StateData};
+
+type Resolve = dyn Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe;
+
+/// The resources of the tenant a request belongs to, placed into the `State` by a
+/// `TenantMiddleware`.
+///
+/// `T` holds everything scoped to a tenant, like its configuration, database pool or rate
+/// limits. Cloning a `TenantState<T>` only clones the `Arc` holding the resources.
+pub struct TenantState<T: ?Sized> {
+    id: Arc<str>,
+    resources: Arc<T>,
+}
+
+impl<T: ?Sized> TenantState<T> {
+    /// Returns the identifier of the tenant, as resolved from the request and converted to
+    /// lowercase.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the `Arc` holding the resources of the tenant.
+    pub fn resources(&self) -> &Arc<T> {
+        &self.resources
+    }
+}
+
+impl<T: ?Sized> Clone for TenantState<T> {
+    fn clone(&self) -> Self {
+        TenantState {
+            id: self.id.clone(),
+            resources: self.resources.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for TenantState<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resources
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TenantState<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantState")
+            .field("id", &self.id)
+            .field("resources", &self.resources)
+            .finish()
+    }
+}
+
+impl<T: ?Sized + Send + Sync + 'static> StateData for TenantState<T> {}
+
+/// The resources of all known tenants, keyed by their identifiers.
+///
+/// Clones of a registry share the same tenants, so tenants can be added or removed while the
+/// application is running, e.g. when a customer signs up.
+pub struct TenantRegistry<T: ?Sized> {
+    tenants: Arc<RwLock<HashMap<String, Arc<T>>>>,
+}
+
+impl<T: ?Sized> TenantRegistry<T> {
+    /// Creates an empty `TenantRegistry`.
+    pub fn new() -> Self {
+        TenantRegistry {
+            tenants: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Registers the resources of a tenant.
+    pub fn with<S: Into<String>>(self, id: S, resources: Arc<T>) -> Self {
+        self.insert(id, resources);
+        self
+    }
+
+    /// Registers the resources of a tenant, replacing any previous resources of the same tenant.
+    pub fn insert<S: Into<String>>(&self, id: S, resources: Arc<T>) {
+        self.tenants
+            .write()
+            .unwrap()
+            .insert(id.into().to_ascii_lowercase(), resources);
+    }
+
+    /// Removes a tenant, returning its resources. Requests already being served keep the
+    /// resources until they finish.
+    pub fn remove(&self, id: &str) -> Option<Arc<T>> {
+        self.tenants
+            .write()
+            .unwrap()
+            .remove(&id.to_ascii_lowercase())
+    }
+
+    /// Returns the resources of a tenant. Identifiers are compared case-insensitively.
+    pub fn get(&self, id: &str) -> Option<Arc<T>> {
+        self.tenants
+            .read()
+            .unwrap()
+            .get(&id.to_ascii_lowercase())
+            .cloned()
+    }
+
+    /// Returns the number of registered tenants.
+    pub fn len(&self) -> usize {
+        self.tenants.read().unwrap().len()
+    }
+
+    /// Determines if no tenants have been registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: ?Sized> Clone for TenantRegistry<T> {
+    fn clone(&self) -> Self {
+        TenantRegistry {
+            tenants: self.tenants.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> Default for TenantRegistry<T> {
+    fn default() -> Self {
+        TenantRegistry::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for TenantRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantRegistry")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A `Middleware` which resolves the tenant of a request, and places its resources from a
+/// `TenantRegistry` into the `State` as a `TenantState<T>`.
+///
+/// Requests without a tenant are answered with `400 Bad Request`, and requests of tenants missing
+/// from the registry with `404 Not Found`, without calling the handler.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// use gotham::state::{FromState, State, TenantMiddleware, TenantRegistry, TenantState};
+///
+/// struct Tenant {
+///     name: String,
+/// }
+///
+/// fn handler(state: State) -> (State, String) {
+///     let tenant = TenantState::<Tenant>::borrow_from(&state);
+///     let greeting = format!("Hello, {}!", tenant.name);
+///     (state, greeting)
+/// }
+///
+/// # fn main() {
+/// let registry = TenantRegistry::new()
+///     .with("acme", Arc::new(Tenant { name: "ACME".to_owned() }))
+///     .with("globex", Arc::new(Tenant { name: "Globex".to_owned() }));
+/// let tenants = TenantMiddleware::from_header(registry, "x-tenant");
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(tenants).build());
+///
+/// let router = build_router(chain, pipelines, |route| {
+///     route.get("/").to(handler);
+/// });
+/// #
+/// # let test_server = TestServer::new(router).unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .get("http://localhost/")
+/// #     .with_header("x-tenant", "acme".parse().unwrap())
+/// #     .perform()
+/// #     .unwrap();
+/// # assert_eq!(response.read_utf8_body().unwrap(), "Hello, ACME!");
+/// # }
+/// ```
+pub struct TenantMiddleware<T: ?Sized> {
+    registry: TenantRegistry<T>,
+    resolve: Arc<Resolve>,
+}
+
+impl<T: ?Sized> TenantMiddleware<T> {
+    /// Creates a new `TenantMiddleware` resolving the tenant with the given function, e.g. from a
+    /// claim of a token which was verified by an earlier middleware.
+    pub fn new<F>(registry: TenantRegistry<T>, resolve: F) -> Self
+    where
+        F: Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    {
+        TenantMiddleware {
+            registry,
+            resolve: Arc::new(resolve),
+        }
+    }
+
+    /// Creates a new `TenantMiddleware` using the host of the request as tenant, without the
+    /// port, like `acme.example.com`.
+    pub fn from_host(registry: TenantRegistry<T>) -> Self {
+        TenantMiddleware::new(registry, |state| {
+            let host = match HeaderMap::borrow_from(state)
+                .get(HOST)
+                .and_then(|host| host.to_str().ok())
+            {
+                Some(host) => host.to_owned(),
+                None => Uri::borrow_from(state).host()?.to_owned(),
+            };
+            // strip the port, unless the colon is part of an IPv6 address
+            let host = match host.rfind(':') {
+                Some(i) if !host[i..].contains(']') => host[..i].to_owned(),
+                _ => host,
+            };
+            Some(host)
+        })
+    }
+
+    /// Creates a new `TenantMiddleware` using the value of the given request header as tenant. The
+    /// name of the header must be lowercase.
+    pub fn from_header(registry: TenantRegistry<T>, name: &'static str) -> Self {
+        let name = HeaderName::from_static(name);
+        TenantMiddleware::new(registry, move |state| {
+            HeaderMap::borrow_from(state)
+                .get(&name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+        })
+    }
+}
+
+impl<T: ?Sized> Clone for TenantMiddleware<T> {
+    fn clone(&self) -> Self {
+        TenantMiddleware {
+            registry: self.registry.clone(),
+            resolve: self.resolve.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for TenantMiddleware<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantMiddleware")
+            .field("registry", &self.registry)
+            .finish()
+    }
+}
+
+impl<T> Middleware for TenantMiddleware<T>
+where
+    T: ?Sized + Send + Sync + RefUnwindSafe + 'static,
+{
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        let id = match (self.resolve)(&state) {
+            Some(id) => id.to_ascii_lowercase(),
+            None => {
+                debug!("[{}] request without tenant", request_id(&state));
+                let response = create_empty_response(&state, StatusCode::BAD_REQUEST);
+                return future::ok((state, response)).boxed();
+            }
+        };
+        match self.registry.get(&id) {
+            Some(resources) => {
+                state.put(TenantState {
+                    id: id.into(),
+                    resources,
+                });
+                chain(state)
+            }
+            None => {
+                debug!("[{}] unknown tenant {:?}", request_id(&state), id);
+                let response = create_empty_response(&state, StatusCode::NOT_FOUND);
+                future::ok((state, response)).boxed()
+            }
+        }
+    }
+}
+
+impl<T> NewMiddleware for TenantMiddleware<T>
+where
+    T: ?Sized + Send + Sync + RefUnwindSafe + 'static,
+{
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+
+    struct Config {
+        greeting: &'static str,
+    }
+
+    fn handler(state: State) -> (State, String) {
+        let tenant = TenantState::<Config>::borrow_from(&state);
+        let body = format!("{} {}", tenant.greeting, tenant.id());
+        (state, body)
+    }
+
+    #[test]
+    fn resolves_tenants_from_host() {
+        let registry =
+            TenantRegistry::new().with("acme.example.com", Arc::new(Config { greeting: "hello" }));
+        let middleware = TenantMiddleware::from_host(registry.clone());
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        }))
+        .unwrap();
+        let get = |uri: &str| test_server.client().get(uri).perform().unwrap();
+
+        let response = get("http://ACME.example.com:8080/");
+        assert_eq!(response.read_utf8_body().unwrap(), "hello acme.example.com");
+        let response = get("http://globex.example.com/");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // tenants can be added at runtime
+        registry.insert("globex.example.com", Arc::new(Config { greeting: "hi" }));
+        let response = get("http://globex.example.com/");
+        assert_eq!(response.read_utf8_body().unwrap(), "hi globex.example.com");
+    }
+
+    #[test]
+    fn resolves_tenants_from_header() {
+        let registry = TenantRegistry::new().with("acme", Arc::new(Config { greeting: "hello" }));
+        let middleware = TenantMiddleware::from_header(registry, "x-tenant");
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .with_header("x-tenant", "acme".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "hello acme");
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
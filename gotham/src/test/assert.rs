@@ -0,0 +1,102 @@
+//! Assertions shared by `TestResponse` and `AsyncTestResponse`.
+
+use hyper::header::AsHeaderName;
+use hyper::{HeaderMap, StatusCode};
+use serde::de::DeserializeOwned;
+
+#[track_caller]
+pub(crate) fn assert_status(actual: StatusCode, expected: StatusCode) {
+    assert!(
+        actual == expected,
+        "expected response status {}, got {}",
+        expected,
+        actual
+    );
+}
+
+#[track_caller]
+pub(crate) fn assert_header<N: AsHeaderName + Clone + std::fmt::Display>(
+    headers: &HeaderMap,
+    name: N,
+    expected: &str,
+) {
+    let values = headers
+        .get_all(name.clone())
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        .collect::<Vec<_>>();
+    assert!(
+        values.iter().any(|value| value == expected),
+        "expected response header `{}: {}`, got {}",
+        name,
+        expected,
+        if values.is_empty() {
+            "no such header".to_owned()
+        } else {
+            format!("{:?}", values)
+        }
+    );
+}
+
+#[track_caller]
+pub(crate) fn text(body: Vec<u8>) -> String {
+    match String::from_utf8(body) {
+        Ok(text) => text,
+        Err(err) => panic!(
+            "response body is not valid UTF-8 ({}): {:?}",
+            err.utf8_error(),
+            String::from_utf8_lossy(err.as_bytes())
+        ),
+    }
+}
+
+#[track_caller]
+pub(crate) fn json<T: DeserializeOwned>(body: Vec<u8>) -> T {
+    match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(err) => panic!(
+            "unable to deserialize response body as {} ({}): {}",
+            std::any::type_name::<T>(),
+            err,
+            String::from_utf8_lossy(&body)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{HeaderValue, CONTENT_TYPE, VARY};
+
+    #[test]
+    fn accepts_matching_responses() {
+        let mut headers = HeaderMap::new();
+        headers.append(VARY, HeaderValue::from_static("accept"));
+        headers.append(VARY, HeaderValue::from_static("cookie"));
+
+        assert_status(StatusCode::OK, StatusCode::OK);
+        assert_header(&headers, VARY, "cookie");
+        assert_eq!(text(b"hello".to_vec()), "hello");
+        assert_eq!(json::<Vec<u32>>(b"[1, 2]".to_vec()), vec![1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected response status 200 OK, got 404 Not Found")]
+    fn reports_status_mismatch() {
+        assert_status(StatusCode::NOT_FOUND, StatusCode::OK);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "expected response header `content-type: text/plain`, got no such header"
+    )]
+    fn reports_missing_header() {
+        assert_header(&HeaderMap::new(), CONTENT_TYPE, "text/plain");
+    }
+
+    #[test]
+    #[should_panic(expected = "unable to deserialize response body as alloc::vec::Vec<u32>")]
+    fn reports_invalid_json() {
+        json::<Vec<u32>>(b"{}".to_vec());
+    }
+}
@@ -1,14 +1,21 @@
 //! Behavior and helpers shared between [`tls::async_test::AsyncTestServer`]
 //! and [`plain::async_test::AsyncTestServer`].
 use crate::handler::NewHandler;
+use crate::test::assert;
+use crate::test::multipart::{self, Part};
+use anyhow::anyhow;
+use bytes::Bytes;
+use hyper::body::HttpBody;
 use hyper::client::connect::Connect;
-use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::header::{AsHeaderName, HeaderName, HeaderValue, CONTENT_TYPE};
 use hyper::http::{self, request};
-use hyper::{Body, Client, Method, Request, Response, Uri, Version};
+use hyper::{Body, Client, Method, Request, Response, StatusCode, Uri, Version};
 use mime::Mime;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::any::Any;
 use std::convert::TryFrom;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{self, Debug, Formatter};
 use std::future::Future;
 use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
@@ -61,6 +68,15 @@ impl AsyncTestServerInner {
         let client = Client::builder().build(test_connect);
         AsyncTestClient::new(client, self.timeout, self.clone())
     }
+
+    #[cfg(feature = "websocket")]
+    pub(crate) async fn websocket(
+        &self,
+        uri: &str,
+    ) -> anyhow::Result<tokio_tungstenite::WebSocketStream<TcpStream>> {
+        let connect = super::websocket::connect(self.addr, uri.to_owned());
+        timeout(self.timeout, connect).await?
+    }
 }
 
 impl Drop for AsyncTestServerInner {
@@ -235,6 +251,31 @@ impl<'client, C: Connect + Clone + Send + Sync + 'static> AsyncTestRequestBuilde
         self
     }
 
+    /// Set the body of this request to `value` serialized as JSON, along with the matching
+    /// `content-type` header.
+    ///
+    /// # Panics
+    ///
+    /// If `value` cannot be serialized.
+    pub fn json<T: Serialize + ?Sized>(self, value: &T) -> Self {
+        let (mime, body) = crate::test::request::json_body(value);
+        self.mime(mime).body(body)
+    }
+
+    /// Set the body of this request to the URL encoded `fields`, along with the matching
+    /// `content-type` header.
+    pub fn form<K: AsRef<str>, V: AsRef<str>>(self, fields: &[(K, V)]) -> Self {
+        let (mime, body) = crate::test::request::form_body(fields);
+        self.mime(mime).body(body)
+    }
+
+    /// Set the body of this request to a `multipart/form-data` body made of `parts`, along with
+    /// the matching `content-type` header.
+    pub fn multipart(self, parts: &[Part]) -> Self {
+        let (mime, body) = multipart::encode(parts);
+        self.mime(mime).body(body)
+    }
+
     /// Add a custom value to this request. See [`http::request::Builder::extension`]
     pub fn extension<T>(self, extension: T) -> Self
     where
@@ -314,12 +355,66 @@ impl AsyncTestResponse {
         Ok(bytes.to_vec())
     }
 
+    /// Awaits the next chunk of the body of the underlying [`Response`], returning `None` at the
+    /// end of the body. Fails if the chunk does not arrive within `timeout`.
+    pub async fn next_chunk(&mut self, timeout: Duration) -> anyhow::Result<Option<Bytes>> {
+        match tokio::time::timeout(timeout, self.response.body_mut().data()).await {
+            Ok(chunk) => Ok(chunk.transpose()?),
+            Err(_) => Err(anyhow!("timed out waiting for the next chunk")),
+        }
+    }
+
     /// Awaits the UTF-8 encoded body of the underlying [`Response`] and returns it as a [`String`].
     /// This will run until all data has been received.
     pub async fn read_utf8_body(self) -> anyhow::Result<String> {
         let bytes = self.read_body().await?;
         Ok(String::from_utf8(bytes)?)
     }
+
+    /// Asserts that the response has the given status code, panicking with both status codes
+    /// otherwise.
+    #[track_caller]
+    pub fn assert_status(&self, status: StatusCode) -> &Self {
+        assert::assert_status(self.status(), status);
+        self
+    }
+
+    /// Asserts that the response has a header `name` with the given value, panicking with the
+    /// values present otherwise. Headers with multiple values pass if any of them matches.
+    #[track_caller]
+    pub fn assert_header<N>(&self, name: N, value: &str) -> &Self
+    where
+        N: AsHeaderName + Clone + fmt::Display,
+    {
+        assert::assert_header(self.headers(), name, value);
+        self
+    }
+
+    /// Awaits the body of the response as UTF-8 text.
+    ///
+    /// # Panics
+    ///
+    /// If the body cannot be read or is not valid UTF-8.
+    pub async fn text(self) -> String {
+        assert::text(
+            self.read_body()
+                .await
+                .expect("unable to read response body"),
+        )
+    }
+
+    /// Awaits the body of the response and deserializes it from JSON.
+    ///
+    /// # Panics
+    ///
+    /// If the body cannot be read or deserialized, with the body in the panic message.
+    pub async fn json<T: DeserializeOwned>(self) -> T {
+        assert::json(
+            self.read_body()
+                .await
+                .expect("unable to read response body"),
+        )
+    }
 }
 
 impl From<Response<Body>> for AsyncTestResponse {
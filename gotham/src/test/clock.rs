@@ -0,0 +1,42 @@
+//! Running test servers with a `MockClock`.
+
+use std::pin::Pin;
+
+use crate::handler::{Handler, HandlerFuture, NewHandler};
+use crate::helpers::clock::MockClock;
+use crate::state::State;
+
+/// Wraps a `NewHandler`, putting the `MockClock` into the `State` of every request.
+pub(crate) struct WithClock<NH> {
+    new_handler: NH,
+    clock: MockClock,
+}
+
+impl<NH: NewHandler> WithClock<NH> {
+    pub(crate) fn new(new_handler: NH, clock: MockClock) -> Self {
+        WithClock { new_handler, clock }
+    }
+}
+
+impl<NH: NewHandler> NewHandler for WithClock<NH> {
+    type Instance = WithClockHandler<NH::Instance>;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(WithClockHandler {
+            handler: self.new_handler.new_handler()?,
+            clock: self.clock.clone(),
+        })
+    }
+}
+
+pub(crate) struct WithClockHandler<H> {
+    handler: H,
+    clock: MockClock,
+}
+
+impl<H: Handler> Handler for WithClockHandler<H> {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        state.put(self.clock);
+        self.handler.handle(state)
+    }
+}
@@ -0,0 +1,171 @@
+//! Persisting cookies between the requests of a test client.
+
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cookie::{Cookie, CookieJar};
+use hyper::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+use hyper::Uri;
+use log::warn;
+
+/// The cookies stored by a test client, shared between its clones.
+#[derive(Clone, Default)]
+pub(crate) struct TestCookieJar {
+    jar: Arc<Mutex<CookieJar>>,
+}
+
+impl TestCookieJar {
+    /// Returns the stored cookies which are not expired.
+    pub(crate) fn cookies(&self) -> Vec<Cookie<'static>> {
+        let now = unix_now();
+        self.jar
+            .lock()
+            .expect("cookie jar poisoned")
+            .iter()
+            .filter(|cookie| !is_expired(cookie, now))
+            .cloned()
+            .collect()
+    }
+
+    /// Adds a `Cookie` header for the stored cookies matching `uri` to `headers`, unless a
+    /// `Cookie` header has been set already.
+    pub(crate) fn apply(&self, uri: &Uri, headers: &mut HeaderMap) {
+        if headers.contains_key(COOKIE) {
+            return;
+        }
+
+        let secure = uri.scheme_str() == Some("https");
+        let path = uri.path();
+        let value = self
+            .cookies()
+            .iter()
+            .filter(|cookie| secure || !cookie.secure().unwrap_or(false))
+            .filter(|cookie| path_matches(cookie.path().unwrap_or("/"), path))
+            .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if !value.is_empty() {
+            match HeaderValue::from_str(&value) {
+                Ok(value) => {
+                    headers.insert(COOKIE, value);
+                }
+                Err(err) => warn!("unable to send stored cookies: {}", err),
+            }
+        }
+    }
+
+    /// Stores the cookies set by `headers` of a response, removing those which expired.
+    pub(crate) fn store(&self, headers: &HeaderMap) {
+        let now = unix_now();
+        let mut jar = self.jar.lock().expect("cookie jar poisoned");
+        for value in headers.get_all(SET_COOKIE) {
+            let cookie = match value
+                .to_str()
+                .ok()
+                .and_then(|value| Cookie::parse(value.to_owned()).ok())
+            {
+                Some(cookie) => cookie,
+                None => {
+                    warn!("ignoring invalid Set-Cookie header: {:?}", value);
+                    continue;
+                }
+            };
+
+            if is_expired(&cookie, now) {
+                jar.force_remove(&cookie);
+            } else {
+                jar.add(cookie);
+            }
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn is_expired(cookie: &Cookie<'_>, now: i64) -> bool {
+    if let Some(max_age) = cookie.max_age() {
+        return max_age.whole_seconds() <= 0;
+    }
+    cookie
+        .expires()
+        .and_then(|expires| expires.datetime())
+        .map(|expires| expires.unix_timestamp() <= now)
+        .unwrap_or(false)
+}
+
+// Matches request paths against the path of a cookie, see RFC 6265, section 5.1.4.
+fn path_matches(cookie_path: &str, request_path: &str) -> bool {
+    match request_path.strip_prefix(cookie_path) {
+        Some(rest) => cookie_path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_cookies(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(SET_COOKIE, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn cookie_header(jar: &TestCookieJar, uri: &'static str) -> Option<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        jar.apply(&Uri::from_static(uri), &mut headers);
+        headers.remove(COOKIE)
+    }
+
+    #[test]
+    fn sends_stored_cookies() {
+        let jar = TestCookieJar::default();
+        jar.store(&set_cookies(&[
+            "a=1; Path=/",
+            "b=2; Path=/admin",
+            "c=3; Secure",
+        ]));
+
+        assert_eq!(cookie_header(&jar, "http://localhost/").unwrap(), "a=1");
+        let admin = cookie_header(&jar, "http://localhost/admin/users").unwrap();
+        assert!(admin == "a=1; b=2" || admin == "b=2; a=1");
+        assert!(cookie_header(&jar, "https://localhost/administrator")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("c=3"));
+        assert_eq!(jar.cookies().len(), 3);
+    }
+
+    #[test]
+    fn removes_expired_cookies() {
+        let jar = TestCookieJar::default();
+        jar.store(&set_cookies(&["session=abc"]));
+        jar.store(&set_cookies(&["session=; Max-Age=0"]));
+        assert!(cookie_header(&jar, "http://localhost/").is_none());
+
+        jar.store(&set_cookies(&[
+            "old=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        ]));
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn keeps_explicit_cookie_headers() {
+        let jar = TestCookieJar::default();
+        jar.store(&set_cookies(&["a=1"]));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("b=2"));
+        jar.apply(&Uri::from_static("http://localhost/"), &mut headers);
+        assert_eq!(headers[COOKIE], "b=2");
+    }
+}
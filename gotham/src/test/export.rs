@@ -0,0 +1,283 @@
+//! Exporting the pages of a `Router` as a static site.
+//!
+//! A `StaticExport` requests every `GET` route of a `Router` without dynamic segments through an
+//! in-process `TestServer`, and writes the response bodies to a directory, which can then be
+//! served by any web server or CDN. Routes with path parameters or globs can't be enumerated, so
+//! their concrete paths are added with `with_path`, e.g. from the list of blog posts.
+//!
+//! A path whose last segment has an extension, like `/feed.xml`, is written to that file, while
+//! any other path is written to `index.html` within a directory of that name, so `/about` is
+//! written to `about/index.html` and can still be served as `/about`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use gotham::router::builder::*;
+//! # use gotham::state::State;
+//! use gotham::test::export::StaticExport;
+//!
+//! # fn handler(state: State) -> (State, &'static str) {
+//! #     (state, "<h1>Hello</h1>")
+//! # }
+//! #
+//! # fn main() {
+//! # let dir = tempfile::tempdir().unwrap();
+//! let router = build_simple_router(|route| {
+//!     route.get("/").to(handler);
+//!     route.get("/about").to(handler);
+//!     route.get("/posts/:slug").to(handler);
+//! });
+//!
+//! let pages = StaticExport::new(router)
+//!     .with_path("/posts/hello-world")
+//!     .export(dir.path())
+//!     .unwrap();
+//!
+//! assert_eq!(pages.len(), 3);
+//! assert!(dir.path().join("about/index.html").is_file());
+//! assert!(dir.path().join("posts/hello-world/index.html").is_file());
+//! # }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use hyper::{Method, StatusCode};
+
+use crate::router::route::Delegation;
+use crate::router::Router;
+use crate::test::TestServer;
+
+/// A page written by `StaticExport::export`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportedPage {
+    path: String,
+    file: PathBuf,
+    status: StatusCode,
+}
+
+impl ExportedPage {
+    /// Returns the path the page was requested at.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the file the page was written to.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Returns the status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+/// Exports the pages of a `Router` to a directory.
+pub struct StaticExport {
+    router: Router,
+    paths: Vec<String>,
+    base_url: String,
+}
+
+impl StaticExport {
+    /// Creates a new `StaticExport` of the `GET` routes of `router`.
+    pub fn new(router: Router) -> Self {
+        StaticExport {
+            router,
+            paths: Vec::new(),
+            base_url: "http://localhost".to_owned(),
+        }
+    }
+
+    /// Adds a path to export, which is requested along with the routes of the `Router`, e.g. a
+    /// concrete path of a route with path parameters.
+    pub fn with_path<P: Into<String>>(mut self, path: P) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Adds several paths to export, see `with_path`.
+    pub fn with_paths<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the scheme and authority pages are requested with, which handlers may use to build
+    /// absolute URLs (defaults to `http://localhost`).
+    pub fn with_base_url<U: Into<String>>(mut self, base_url: U) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Returns the paths which are exported: the `GET` routes of the `Router` without path
+    /// parameters or globs, followed by the paths added with `with_path`, each listed once.
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        let routes = self
+            .router
+            .routes()
+            .into_iter()
+            .filter(|route| route.delegation() == Delegation::Internal)
+            .filter(|route| {
+                route
+                    .methods()
+                    .is_none_or(|methods| methods.contains(&Method::GET))
+            })
+            .filter_map(|route| static_path(route.path()));
+        for path in routes.chain(self.paths.iter().cloned()) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Requests every path and writes the response bodies below `dir`, which is created if it
+    /// doesn't exist. Fails on the first path which isn't answered with a `2xx` status, or can't
+    /// be written.
+    pub fn export<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<Vec<ExportedPage>> {
+        let dir = dir.as_ref();
+        let test_server = TestServer::new(self.router.clone())?;
+        let client = test_server.client();
+
+        let mut pages = Vec::new();
+        for path in self.paths() {
+            let file = dir.join(file_for(&path)?);
+            let response = client
+                .get(format!("{}{}", self.base_url, path))
+                .perform()
+                .with_context(|| format!("failed to request {}", path))?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(anyhow!("{} responded with {}", path, status));
+            }
+            let body = response
+                .read_body()
+                .with_context(|| format!("failed to read the body of {}", path))?;
+
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&file, body).with_context(|| format!("failed to write {:?}", file))?;
+            pages.push(ExportedPage { path, file, status });
+        }
+        Ok(pages)
+    }
+}
+
+// Returns the path of a route without dynamic segments, with escaped static segments unescaped.
+fn static_path(route: &str) -> Option<String> {
+    let mut path = String::new();
+    for segment in route.split('/').filter(|segment| !segment.is_empty()) {
+        if segment.starts_with(&[':', '*'][..]) {
+            return None;
+        }
+        path.push('/');
+        path.push_str(segment.strip_prefix('\\').unwrap_or(segment));
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+    Some(path)
+}
+
+// Returns the file a path is written to, relative to the export directory.
+fn file_for(path: &str) -> anyhow::Result<PathBuf> {
+    let path = path.split(&['?', '#'][..]).next().unwrap_or_default();
+    if !path.starts_with('/') {
+        return Err(anyhow!("path {} must start with a slash", path));
+    }
+
+    let mut file = PathBuf::new();
+    let segments = path.split('/').filter(|segment| !segment.is_empty());
+    for segment in segments {
+        if segment == "." || segment == ".." || segment.contains('\\') {
+            return Err(anyhow!("path {} contains an invalid segment", path));
+        }
+        file.push(segment);
+    }
+    let has_extension = !path.ends_with('/')
+        && file
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().contains('.'));
+    if !has_extension {
+        file.push("index.html");
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::http::response::create_empty_response;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::state::{FromState, State};
+    use hyper::{Body, Response, Uri};
+
+    fn handler(state: State) -> (State, String) {
+        let uri = Uri::borrow_from(&state).to_string();
+        (state, format!("page {}", uri))
+    }
+
+    fn missing(state: State) -> (State, Response<Body>) {
+        let response = create_empty_response(&state, StatusCode::NOT_FOUND);
+        (state, response)
+    }
+
+    #[test]
+    fn exports_get_routes_and_paths() {
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.get("/docs/intro").to(handler);
+            route.get("/feed.xml").to(handler);
+            route.get("/\\:literal").to(handler);
+            route.get("/posts/:id").to(handler);
+            route.post("/contact").to(handler);
+        });
+        let export = StaticExport::new(router).with_paths(vec!["/posts/1", "/docs/intro"]);
+        let mut paths = export.paths();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec!["/", "/:literal", "/docs/intro", "/feed.xml", "/posts/1"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let pages = export.export(dir.path()).unwrap();
+        assert_eq!(pages.len(), 5);
+        assert!(pages.iter().all(|page| page.status() == StatusCode::OK));
+        let root = pages.iter().find(|page| page.path() == "/").unwrap();
+        assert_eq!(root.file(), dir.path().join("index.html"));
+
+        let read = |file: &str| fs::read_to_string(dir.path().join(file)).unwrap();
+        assert_eq!(read("index.html"), "page /");
+        assert_eq!(read("docs/intro/index.html"), "page /docs/intro");
+        assert_eq!(read("feed.xml"), "page /feed.xml");
+        assert_eq!(read("posts/1/index.html"), "page /posts/1");
+        assert!(!dir.path().join("contact").exists());
+    }
+
+    #[test]
+    fn rejects_failed_and_invalid_paths() {
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.get("/missing").to(missing);
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let err = StaticExport::new(router).export(dir.path()).unwrap_err();
+        assert_eq!(err.to_string(), "/missing responded with 404 Not Found");
+
+        assert!(file_for("/../secret").is_err());
+        assert!(file_for("relative").is_err());
+        assert_eq!(
+            file_for("/search?q=1").unwrap(),
+            PathBuf::from("search/index.html")
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Temporary directory trees, for testing handlers which serve files from disk.
+//!
+//! A `FileTree` describes the files, directories and symlinks of a directory tree, which is
+//! created below a new temporary directory by `FileTree::create`. This allows testing the
+//! configuration of `to_dir` routes, like the handling of dotfiles, symlinks, large files or
+//! unicode names, without committing fixture files to the repository. The directory is removed
+//! again when the returned `TestDir` is dropped.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use gotham::handler::FileOptions;
+//! # use gotham::router::builder::*;
+//! use gotham::test::file_tree::FileTree;
+//! use gotham::test::TestServer;
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! # fn main() {
+//! let dir = FileTree::new()
+//!     .file("index.html", "<h1>Hello</h1>")
+//!     .file_with_mtime("old.txt", "old", UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+//!     .file(".env", "SECRET=1")
+//!     .file("grüße/schön.txt", "unicode")
+//!     .large_file("video.mp4", 10 * 1024 * 1024)
+//!     .create()
+//!     .unwrap();
+//!
+//! let router = build_simple_router(|route| {
+//!     route
+//!         .get("/*")
+//!         .to_dir(FileOptions::new(dir.path()).with_deny_hidden(true).build());
+//! });
+//! let test_server = TestServer::new(router).unwrap();
+//! let response = test_server
+//!     .client()
+//!     .get("http://localhost/.env")
+//!     .perform()
+//!     .unwrap();
+//! assert_eq!(response.status(), 404);
+//! # }
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+// The chunk size used to write large files, so they don't have to fit into memory.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Debug)]
+enum Contents {
+    Bytes(Vec<u8>),
+    // a file of the given size, filled with a repeating pattern
+    Large(u64),
+}
+
+#[derive(Clone, Debug)]
+enum Entry {
+    Dir,
+    File {
+        contents: Contents,
+        modified: Option<SystemTime>,
+    },
+    #[cfg(unix)]
+    Symlink(PathBuf),
+}
+
+/// A description of a directory tree, which is created below a new temporary directory by
+/// `create`.
+///
+/// Paths are relative to the root of the tree, and use `/` as separator. Missing parent
+/// directories are created as needed, and entries are created in the order they were added, so
+/// a symlink may point to an entry added before it.
+#[derive(Clone, Debug, Default)]
+pub struct FileTree {
+    entries: Vec<(PathBuf, Entry)>,
+}
+
+impl FileTree {
+    /// Creates an empty `FileTree`.
+    pub fn new() -> Self {
+        FileTree::default()
+    }
+
+    /// Adds an empty directory.
+    pub fn dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.entries.push((path.as_ref().to_path_buf(), Entry::Dir));
+        self
+    }
+
+    /// Adds a file with the given contents.
+    pub fn file<P: AsRef<Path>, C: AsRef<[u8]>>(self, path: P, contents: C) -> Self {
+        self.push_file(path, Contents::Bytes(contents.as_ref().to_vec()), None)
+    }
+
+    /// Adds a file with the given contents and modification time, e.g. to test conditional
+    /// requests and caching headers.
+    pub fn file_with_mtime<P: AsRef<Path>, C: AsRef<[u8]>>(
+        self,
+        path: P,
+        contents: C,
+        modified: SystemTime,
+    ) -> Self {
+        self.push_file(
+            path,
+            Contents::Bytes(contents.as_ref().to_vec()),
+            Some(modified),
+        )
+    }
+
+    /// Adds a file of the given size in bytes, filled with a repeating pattern, e.g. to test
+    /// range requests or throttling. The file is written in chunks, so its size isn't limited by
+    /// the available memory.
+    pub fn large_file<P: AsRef<Path>>(self, path: P, size: u64) -> Self {
+        self.push_file(path, Contents::Large(size), None)
+    }
+
+    /// Adds a symlink pointing to `target`, which is either absolute or relative to the directory
+    /// containing the symlink. Only available on Unix platforms.
+    #[cfg(unix)]
+    pub fn symlink<P: AsRef<Path>, T: AsRef<Path>>(mut self, path: P, target: T) -> Self {
+        self.entries.push((
+            path.as_ref().to_path_buf(),
+            Entry::Symlink(target.as_ref().to_path_buf()),
+        ));
+        self
+    }
+
+    fn push_file<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        contents: Contents,
+        modified: Option<SystemTime>,
+    ) -> Self {
+        self.entries.push((
+            path.as_ref().to_path_buf(),
+            Entry::File { contents, modified },
+        ));
+        self
+    }
+
+    /// Creates the tree below a new directory in the temporary directory of the system.
+    pub fn create(&self) -> io::Result<TestDir> {
+        let path = std::env::temp_dir().join(format!("gotham-{}", Uuid::new_v4()));
+        fs::create_dir(&path)?;
+        // the directory is removed again if creating the entries fails
+        let dir = TestDir { path };
+        self.create_in(dir.path())?;
+        Ok(dir)
+    }
+
+    /// Creates the tree below the given existing directory.
+    pub fn create_in<P: AsRef<Path>>(&self, root: P) -> io::Result<()> {
+        let root = root.as_ref();
+        for (path, entry) in &self.entries {
+            if path.is_absolute() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("path {} is not relative", path.display()),
+                ));
+            }
+            let path = root.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match entry {
+                Entry::Dir => fs::create_dir_all(&path)?,
+                Entry::File { contents, modified } => {
+                    let mut file = File::create(&path)?;
+                    match contents {
+                        Contents::Bytes(bytes) => file.write_all(bytes)?,
+                        Contents::Large(size) => write_pattern(&mut file, *size)?,
+                    }
+                    if let Some(modified) = modified {
+                        file.set_modified(*modified)?;
+                    }
+                }
+                #[cfg(unix)]
+                Entry::Symlink(target) => std::os::unix::fs::symlink(target, &path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Writes `size` bytes of the pattern `0, 1, .., 250, 0, 1, ..`, whose length is prime so that
+// misplaced ranges of the file are noticed.
+fn write_pattern(file: &mut File, size: u64) -> io::Result<()> {
+    let chunk: Vec<u8> = (0..CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let mut remaining = size;
+    let mut offset = 0;
+    while remaining > 0 {
+        let len = remaining.min((CHUNK_SIZE - 251) as u64) as usize;
+        file.write_all(&chunk[offset..offset + len])?;
+        offset = (offset + len) % 251;
+        remaining -= len as u64;
+    }
+    Ok(())
+}
+
+/// Returns the contents of a file of the given size added with `FileTree::large_file`, to compare
+/// responses against.
+pub fn large_file_contents(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+/// A temporary directory created by `FileTree::create`, which is removed with all its contents
+/// when dropped.
+#[derive(Debug)]
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of an entry of the directory.
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn creates_and_removes_tree() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let tree = FileTree::new()
+            .dir("empty")
+            .file("a/b/c.txt", "text")
+            .file_with_mtime("old.txt", "old", modified)
+            .file("ünïcödé.txt", [0xff, 0x00])
+            .large_file("large.bin", 200_000);
+        #[cfg(unix)]
+        let tree = tree.symlink("link.txt", "a/b/c.txt");
+        let dir = tree.create().unwrap();
+
+        assert!(dir.join("empty").is_dir());
+        assert_eq!(fs::read_to_string(dir.join("a/b/c.txt")).unwrap(), "text");
+        assert_eq!(
+            fs::metadata(dir.join("old.txt"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            modified
+        );
+        assert_eq!(fs::read(dir.join("ünïcödé.txt")).unwrap(), [0xff, 0x00]);
+        assert_eq!(
+            fs::read(dir.join("large.bin")).unwrap(),
+            large_file_contents(200_000)
+        );
+        #[cfg(unix)]
+        assert_eq!(fs::read_to_string(dir.join("link.txt")).unwrap(), "text");
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+
+        let err = FileTree::new().file("/abs", "").create().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
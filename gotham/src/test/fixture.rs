@@ -0,0 +1,462 @@
+//! Recording requests and responses as fixtures, and replaying them as regression tests.
+//!
+//! A `FixtureRecorder` added to a pipeline records every request passing through it, along with
+//! the response. The recorded `Fixture` can be saved as JSON, and later be replayed against a
+//! `TestServer` to check that the application still responds the same way, e.g. to lock in API
+//! compatibility across refactors.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! # use gotham::router::Router;
+//! # use gotham::state::State;
+//! use gotham::test::fixture::{Fixture, FixtureRecorder};
+//! use gotham::test::TestServer;
+//!
+//! # fn handler(state: State) -> (State, &'static str) {
+//! #     (state, "Hello!")
+//! # }
+//! #
+//! fn router(recorder: FixtureRecorder) -> Router {
+//!     let (chain, pipelines) = single_pipeline(new_pipeline().add(recorder).build());
+//!     build_router(chain, pipelines, |route| route.get("/").to(handler))
+//! }
+//!
+//! # fn main() {
+//! # let dir = tempfile::tempdir().unwrap();
+//! # let path = dir.path().join("hello.json");
+//! // record the fixture once, e.g. from an integration test
+//! let recorder = FixtureRecorder::new();
+//! let test_server = TestServer::new(router(recorder.clone())).unwrap();
+//! test_server.client().get("http://localhost/").perform().unwrap();
+//! recorder.fixture().save(&path).unwrap();
+//!
+//! // then replay it against the current implementation
+//! let test_server = TestServer::new(router(FixtureRecorder::new())).unwrap();
+//! Fixture::load(&path).unwrap().replay(&test_server.client()).unwrap();
+//! # }
+//! ```
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context};
+use base64::prelude::*;
+use futures_util::future::FutureExt;
+use hyper::client::connect::Connect;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, DATE, HOST};
+use hyper::{body, Body, Method, Response, Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::HandlerFuture;
+use crate::helpers::http::header::X_REQUEST_ID;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{FromState, State};
+use crate::test::{Server, TestClient};
+
+/// A body of a recorded request or response. Bodies which are valid UTF-8 are stored as text,
+/// others are encoded as base64.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedBody {
+    /// A UTF-8 body.
+    Text(String),
+    /// A binary body, encoded as base64.
+    Base64(String),
+}
+
+impl RecordedBody {
+    fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text(text.to_owned()),
+            Err(_) => RecordedBody::Base64(BASE64_STANDARD.encode(bytes)),
+        }
+    }
+
+    /// Returns the bytes of the body.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            RecordedBody::Text(text) => Ok(text.as_bytes().to_vec()),
+            RecordedBody::Base64(encoded) => Ok(BASE64_STANDARD.decode(encoded)?),
+        }
+    }
+}
+
+/// A recorded request.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// The request method.
+    pub method: String,
+    /// The request URI, as received by the server.
+    pub uri: String,
+    /// The request headers, in the order they were received.
+    pub headers: Vec<(String, String)>,
+    /// The request body.
+    pub body: RecordedBody,
+}
+
+/// A recorded response.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// The response status code.
+    pub status: u16,
+    /// The response headers, in the order they were sent.
+    pub headers: Vec<(String, String)>,
+    /// The response body.
+    pub body: RecordedBody,
+}
+
+/// A recorded request, along with the response it received.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The recorded request.
+    pub request: RecordedRequest,
+    /// The recorded response.
+    pub response: RecordedResponse,
+}
+
+/// A sequence of recorded exchanges, which can be saved to and loaded from JSON files, and
+/// replayed against a `TestServer`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The recorded exchanges, in the order the responses were sent.
+    pub exchanges: Vec<Exchange>,
+    /// The names of response headers which are not compared during a replay, because their
+    /// values change between runs. By default, these are `Date` and `X-Request-ID`.
+    #[serde(default = "default_ignored_headers")]
+    pub ignored_headers: Vec<String>,
+}
+
+fn default_ignored_headers() -> Vec<String> {
+    vec![DATE.as_str().to_owned(), X_REQUEST_ID.to_owned()]
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Fixture {
+            exchanges: Vec::new(),
+            ignored_headers: default_ignored_headers(),
+        }
+    }
+}
+
+impl Fixture {
+    /// Creates an empty `Fixture`.
+    pub fn new() -> Self {
+        Fixture::default()
+    }
+
+    /// Ignores the response header `name` during replays, in addition to the ignored headers
+    /// already configured.
+    pub fn ignore_header(mut self, name: &str) -> Self {
+        self.ignored_headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Loads a `Fixture` from the JSON file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Fixture> {
+        let path = path.as_ref();
+        let json = fs::read(path).with_context(|| format!("unable to read {}", path.display()))?;
+        serde_json::from_slice(&json).with_context(|| format!("invalid fixture {}", path.display()))
+    }
+
+    /// Saves the `Fixture` as a JSON file at `path`, replacing an existing file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Sends the recorded requests using `client`, in order, and checks that the responses have
+    /// the recorded status, headers and body. Headers added by the new responses are not
+    /// checked. Fails with a description of the first mismatch.
+    pub fn replay<TS, C>(&self, client: &TestClient<TS, C>) -> anyhow::Result<()>
+    where
+        TS: Server + 'static,
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        for (index, exchange) in self.exchanges.iter().enumerate() {
+            let request = &exchange.request;
+            self.replay_exchange(client, exchange).with_context(|| {
+                format!(
+                    "exchange {} ({} {}) does not match",
+                    index, request.method, request.uri
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    fn replay_exchange<TS, C>(
+        &self,
+        client: &TestClient<TS, C>,
+        exchange: &Exchange,
+    ) -> anyhow::Result<()>
+    where
+        TS: Server + 'static,
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        let request = &exchange.request;
+        let method = Method::from_bytes(request.method.as_bytes())?;
+        let uri = if request.uri.starts_with('/') {
+            format!("http://localhost{}", request.uri)
+        } else {
+            request.uri.clone()
+        };
+
+        let mut test_request = client.build_request(method, Uri::try_from(uri)?);
+        for (name, value) in &request.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())?;
+            // set by the client according to the request URI and body
+            if name != HOST && name != CONTENT_LENGTH {
+                let value = HeaderValue::from_str(value)?;
+                test_request.headers_mut().append(name, value);
+            }
+        }
+        *test_request.body_mut() = Body::from(request.body.to_bytes()?);
+
+        let response = test_request.perform()?;
+        let expected = &exchange.response;
+        if response.status().as_u16() != expected.status {
+            return Err(anyhow!(
+                "expected status {}, got {}",
+                expected.status,
+                response.status().as_u16()
+            ));
+        }
+
+        let headers = response.headers().clone();
+        let body = RecordedBody::new(&response.read_body()?);
+        if body != expected.body {
+            return Err(anyhow!("expected body {:?}, got {:?}", expected.body, body));
+        }
+
+        for name in unique_names(&expected.headers) {
+            if self.ignored_headers.iter().any(|ignored| ignored == name) {
+                continue;
+            }
+            let expected_values = values_of(&expected.headers, name);
+            let actual_values = headers
+                .get_all(name)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                .collect::<Vec<_>>();
+            if expected_values != actual_values {
+                return Err(anyhow!(
+                    "expected header {} to be {:?}, got {:?}",
+                    name,
+                    expected_values,
+                    actual_values
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn record_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.as_str().to_owned(), value)
+        })
+        .collect()
+}
+
+fn unique_names(headers: &[(String, String)]) -> Vec<&str> {
+    let mut names = Vec::<&str>::new();
+    for (name, _) in headers {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn values_of<'a>(headers: &'a [(String, String)], name: &str) -> Vec<&'a str> {
+    headers
+        .iter()
+        .filter(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+/// A `Middleware` recording the requests passing through it and their responses. Clones share
+/// the same recording.
+///
+/// Request and response bodies are buffered entirely, so the recorder is not suitable for
+/// streaming responses.
+#[derive(Clone, Default)]
+pub struct FixtureRecorder {
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+impl FixtureRecorder {
+    /// Creates a new `FixtureRecorder`.
+    pub fn new() -> Self {
+        FixtureRecorder::default()
+    }
+
+    /// Returns a `Fixture` of the exchanges recorded so far.
+    pub fn fixture(&self) -> Fixture {
+        Fixture {
+            exchanges: self.exchanges.lock().expect("recorder poisoned").clone(),
+            ..Fixture::new()
+        }
+    }
+}
+
+impl Middleware for FixtureRecorder {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        async move {
+            let request_body = match body::to_bytes(Body::take_from(&mut state)).await {
+                Ok(bytes) => bytes,
+                Err(err) => return Err((state, err.into())),
+            };
+            let request = RecordedRequest {
+                method: Method::borrow_from(&state).to_string(),
+                uri: Uri::borrow_from(&state).to_string(),
+                headers: record_headers(HeaderMap::borrow_from(&state)),
+                body: RecordedBody::new(&request_body),
+            };
+            state.put(Body::from(request_body));
+
+            let (state, response) = chain(state).await?;
+            let (parts, response_body) = response.into_parts();
+            let response_body = match body::to_bytes(response_body).await {
+                Ok(bytes) => bytes,
+                Err(err) => return Err((state, err.into())),
+            };
+            let recorded = RecordedResponse {
+                status: parts.status.as_u16(),
+                headers: record_headers(&parts.headers),
+                body: RecordedBody::new(&response_body),
+            };
+
+            self.exchanges
+                .lock()
+                .expect("recorder poisoned")
+                .push(Exchange {
+                    request,
+                    response: recorded,
+                });
+            Ok((
+                state,
+                Response::from_parts(parts, Body::from(response_body)),
+            ))
+        }
+        .boxed()
+    }
+}
+
+impl NewMiddleware for FixtureRecorder {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use crate::test::TestServer;
+    use hyper::StatusCode;
+
+    fn router(recorder: FixtureRecorder, greeting: &'static str) -> Router {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(recorder).build());
+        build_router(chain, pipelines, |route| {
+            route
+                .get("/greeting")
+                .to_new_handler(move || Ok(move |state: State| (state, greeting)));
+            route
+                .post("/echo")
+                .to_new_handler(crate::test::helper::TestHandler::default());
+        })
+    }
+
+    fn record(greeting: &'static str) -> Fixture {
+        let recorder = FixtureRecorder::new();
+        let test_server = TestServer::new(router(recorder.clone(), greeting)).unwrap();
+        let client = test_server.client();
+        client
+            .get("http://localhost/greeting")
+            .perform()
+            .unwrap()
+            .assert_status(StatusCode::OK);
+        client
+            .post(
+                "http://localhost/echo",
+                vec![0xff, 0x00],
+                mime::APPLICATION_OCTET_STREAM,
+            )
+            .perform()
+            .unwrap()
+            .assert_status(StatusCode::OK);
+        recorder.fixture()
+    }
+
+    #[test]
+    fn records_exchanges() {
+        let fixture = record("Hello");
+        assert_eq!(fixture.exchanges.len(), 2);
+
+        let greeting = &fixture.exchanges[0];
+        assert_eq!(greeting.request.method, "GET");
+        assert_eq!(greeting.request.uri, "/greeting");
+        assert_eq!(greeting.response.status, 200);
+        assert_eq!(
+            greeting.response.body,
+            RecordedBody::Text("Hello".to_owned())
+        );
+
+        let echo = &fixture.exchanges[1];
+        assert_eq!(echo.request.body, RecordedBody::Base64("/wA=".to_owned()));
+        assert_eq!(echo.response.body.to_bytes().unwrap(), vec![0xff, 0x00]);
+    }
+
+    #[test]
+    fn replays_saved_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fixture.json");
+        record("Hello").save(&path).unwrap();
+
+        let fixture = Fixture::load(&path).unwrap();
+        let test_server = TestServer::new(router(FixtureRecorder::new(), "Hello")).unwrap();
+        fixture.replay(&test_server.client()).unwrap();
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let fixture = record("Hello");
+        let test_server = TestServer::new(router(FixtureRecorder::new(), "Goodbye")).unwrap();
+        let err = fixture.replay(&test_server.client()).unwrap_err();
+        assert!(format!("{:#}", err).contains("exchange 0 (GET /greeting)"));
+        assert!(format!("{:#}", err).contains("Goodbye"));
+    }
+
+    #[test]
+    fn ignores_configured_headers() {
+        let mut fixture = record("Hello");
+        let headers = &mut fixture.exchanges[0].response.headers;
+        headers.push(("x-runtime".to_owned(), "12ms".to_owned()));
+
+        let test_server = TestServer::new(router(FixtureRecorder::new(), "Hello")).unwrap();
+        assert!(fixture.replay(&test_server.client()).is_err());
+        let fixture = fixture.ignore_header("X-Runtime");
+        fixture.replay(&test_server.client()).unwrap();
+    }
+}
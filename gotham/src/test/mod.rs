@@ -1,31 +1,58 @@
+mod assert;
 pub(crate) mod async_test;
+pub(crate) mod clock;
+mod cookie_jar;
+mod stream;
 
 /// Test request behavior, shared between the tls::test and plain::test modules.
 pub mod request;
 
+pub mod file_tree;
+
+pub mod export;
+
+pub mod fixture;
+
+pub mod multipart;
+
+pub mod recorder;
+
+#[cfg(feature = "websocket")]
+pub(crate) mod websocket;
+
 use std::convert::TryFrom;
 use std::fmt;
 use std::future::Future;
 use std::ops::{Deref, DerefMut};
 
 use anyhow::anyhow;
+use bytes::Bytes;
+use cookie::Cookie;
 use futures_util::future::{self, FutureExt, TryFuture, TryFutureExt};
+use hyper::body::HttpBody;
 use hyper::client::connect::Connect;
-use hyper::client::Client;
-use hyper::header::CONTENT_TYPE;
-use hyper::{body, http, Body, Method, Response, Uri};
+use hyper::client::{self, Client};
+use hyper::header::{AsHeaderName, CONTENT_TYPE};
+use hyper::{body, http, Body, Method, Request, Response, StatusCode, Uri};
 use log::warn;
+use serde::de::DeserializeOwned;
 use tokio::time::{sleep, Sleep};
 
+use self::cookie_jar::TestCookieJar;
 use crate::handler::NewHandler;
 pub use crate::plain::test::TestServer;
+pub use multipart::Part;
+pub use recorder::StateRecorder;
 pub use request::TestRequest;
 use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
+pub use stream::TestBodyStream;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::Runtime;
+#[cfg(feature = "websocket")]
+pub use websocket::TestWebSocket;
 
 // publicly reexport the AsyncTestServer helper types.
 pub use async_test::{AsyncTestClient, AsyncTestRequestBuilder, AsyncTestResponse};
@@ -34,12 +61,17 @@ pub(crate) trait BodyReader {
     /// Runs the underlying event loop until the response body has been fully read. An `Ok(_)`
     /// response holds a buffer containing all bytes of the response body.
     fn read_body(&mut self, response: Response<Body>) -> Result<Vec<u8>, hyper::Error>;
+
+    /// Runs the underlying event loop until the next chunk of `body` has been received, failing
+    /// if that takes longer than `timeout`. An `Ok(None)` response signals the end of the body.
+    fn read_chunk(&mut self, body: &mut Body, timeout: Duration) -> anyhow::Result<Option<Bytes>>;
 }
 
 pub(crate) struct TestServerData {
     pub(crate) addr: SocketAddr,
     pub(crate) timeout: u64,
-    pub(crate) runtime: RwLock<Runtime>,
+    // only `None` while dropping
+    runtime: RwLock<Option<Runtime>>,
 }
 
 impl TestServerData {
@@ -55,9 +87,15 @@ impl TestServerData {
         Wrap: Fn(TcpStream) -> F + Send + 'static,
     {
         let runtime = Runtime::new()?;
-        // TODO: Fix this into an async flow
-        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0".parse::<SocketAddr>()?))?;
+        // Binding synchronously doesn't block on the runtime, so servers can be created within
+        // async tests.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0".parse::<SocketAddr>()?)?;
+        listener.set_nonblocking(true)?;
         let addr = listener.local_addr()?;
+        let listener = {
+            let _guard = runtime.enter();
+            TcpListener::from_std(listener)?
+        };
 
         let service_stream = super::bind_server(listener, new_handler, wrap);
         runtime.spawn(service_stream); // Ignore the result
@@ -65,7 +103,7 @@ impl TestServerData {
         Ok(TestServerData {
             addr,
             timeout,
-            runtime: RwLock::new(runtime),
+            runtime: RwLock::new(Some(runtime)),
         })
     }
 
@@ -76,13 +114,20 @@ impl TestServerData {
     {
         // We're creating a private TCP-based pipe here. Bind to an ephemeral port, connect to
         // it and then immediately discard the listener.
-        let test_connect = TestC::from(self.addr);
-        let client = Client::builder().build(test_connect);
+        self.client_with(server, TestC::from(self.addr), &Client::builder())
+    }
 
-        TestClient {
-            client,
-            test_server: server.clone(),
-        }
+    pub(crate) fn client_with<TS, TestC>(
+        &self,
+        server: &TS,
+        test_connect: TestC,
+        builder: &client::Builder,
+    ) -> TestClient<TS, TestC>
+    where
+        TS: Server,
+        TestC: Connect + Clone,
+    {
+        TestClient::new(builder.build(test_connect), server.clone())
     }
 
     pub(crate) fn spawn<F>(&self, future: F)
@@ -92,10 +137,21 @@ impl TestServerData {
         self.runtime
             .write()
             .expect("unable to acquire read lock")
+            .as_ref()
+            .expect("runtime dropped")
             .spawn(future);
     }
 }
 
+impl Drop for TestServerData {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which is not allowed within async tests.
+        if let Some(runtime) = self.runtime.get_mut().ok().and_then(Option::take) {
+            runtime.shutdown_background();
+        }
+    }
+}
+
 impl Server for Arc<TestServerData> {
     fn run_future<F, O>(&self, future: F) -> O
     where
@@ -104,12 +160,14 @@ impl Server for Arc<TestServerData> {
         self.runtime
             .write()
             .expect("unable to acquire write lock")
+            .as_ref()
+            .expect("runtime dropped")
             .block_on(future)
     }
 
     fn request_expiry(&self) -> Sleep {
-        let runtime = self.runtime.write().unwrap();
-        let _guard = runtime.enter();
+        let runtime = self.runtime.read().unwrap();
+        let _guard = runtime.as_ref().expect("runtime dropped").enter();
         sleep(Duration::from_secs(self.timeout))
     }
 }
@@ -161,15 +219,91 @@ impl<T: Server> BodyReader for T {
         let f = body::to_bytes(response.into_body()).and_then(|b| future::ok(b.to_vec()));
         self.run_future(f)
     }
+
+    fn read_chunk(&mut self, body: &mut Body, timeout: Duration) -> anyhow::Result<Option<Bytes>> {
+        self.run_future(async move {
+            match tokio::time::timeout(timeout, body.data()).await {
+                Ok(chunk) => Ok(chunk.transpose()?),
+                Err(_) => Err(anyhow!("timed out waiting for the next chunk")),
+            }
+        })
+    }
 }
 
 /// Client interface for issuing requests to a `Server`.
 pub struct TestClient<TS: Server, C: Connect> {
     pub(crate) client: Client<C, Body>,
     pub(crate) test_server: TS,
+    cookies: Option<TestCookieJar>,
+}
+
+impl<TS: Server, C: Connect> TestClient<TS, C> {
+    pub(crate) fn new(client: Client<C, Body>, test_server: TS) -> Self {
+        TestClient {
+            client,
+            test_server,
+            cookies: None,
+        }
+    }
 }
 
 impl<TS: Server + 'static, C: Connect + Clone + Send + Sync + 'static> TestClient<TS, C> {
+    /// Makes this `TestClient` persist cookies across requests, like a browser would. Cookies set
+    /// by responses are stored, and sent with subsequent requests to matching paths, unless the
+    /// request has a `Cookie` header already. Cookies marked `Secure` are only sent to `https`
+    /// URIs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::state::State;
+    /// # use gotham::hyper::header::{COOKIE, SET_COOKIE};
+    /// # use gotham::hyper::{Body, HeaderMap, Response};
+    /// # use gotham::state::FromState;
+    /// # use gotham::router::builder::*;
+    /// use gotham::test::TestServer;
+    ///
+    /// fn login(state: State) -> (State, Response<Body>) {
+    ///     let response = Response::builder()
+    ///         .header(SET_COOKIE, "user=alice")
+    ///         .body(Body::empty())
+    ///         .unwrap();
+    ///     (state, response)
+    /// }
+    ///
+    /// fn whoami(state: State) -> (State, String) {
+    ///     let cookie = HeaderMap::borrow_from(&state)[COOKIE].to_str().unwrap().to_owned();
+    ///     (state, cookie)
+    /// }
+    ///
+    /// # fn main() {
+    /// let router = build_simple_router(|route| {
+    ///     route.post("/login").to(login);
+    ///     route.get("/whoami").to(whoami);
+    /// });
+    /// let test_server = TestServer::new(router).unwrap();
+    /// let client = test_server.client().with_cookie_jar();
+    ///
+    /// client
+    ///     .post("http://localhost/login", "", gotham::mime::TEXT_PLAIN)
+    ///     .perform()
+    ///     .unwrap();
+    /// let response = client.get("http://localhost/whoami").perform().unwrap();
+    /// assert_eq!(response.read_utf8_body().unwrap(), "user=alice");
+    /// # }
+    /// ```
+    pub fn with_cookie_jar(self) -> Self {
+        TestClient {
+            cookies: Some(TestCookieJar::default()),
+            ..self
+        }
+    }
+
+    /// Returns the cookies stored by this `TestClient`, or `None` if it doesn't persist cookies.
+    pub fn cookies(&self) -> Option<Vec<Cookie<'static>>> {
+        self.cookies.as_ref().map(TestCookieJar::cookies)
+    }
+
     /// Begin constructing a HEAD request using this `TestClient`.
     pub fn head<U>(&self, uri: U) -> TestRequest<'_, TS, C>
     where
@@ -272,17 +406,148 @@ impl<TS: Server + 'static, C: Connect + Clone + Send + Sync + 'static> TestClien
 
     /// Send a constructed request using this `TestClient`, and await the response.
     pub fn perform(&self, req: TestRequest<'_, TS, C>) -> anyhow::Result<TestResponse> {
-        let req_future = self.client.request(req.request()).map_err(|e| {
+        let req_future = self.client.request(self.prepare(req)).map_err(|e| {
             warn!("Error from test client request {:?}", e);
             e
         });
 
-        self.test_server
-            .run_request(req_future)
-            .map(|response| TestResponse {
-                response,
-                reader: Box::new(self.test_server.clone()),
+        let response = self.test_server.run_request(req_future)?;
+        if let Some(cookies) = &self.cookies {
+            cookies.store(response.headers());
+        }
+
+        Ok(TestResponse {
+            response,
+            reader: Box::new(self.test_server.clone()),
+        })
+    }
+
+    /// Sends all `requests` concurrently using this `TestClient`, and awaits their responses,
+    /// which are returned in the order of the requests. Fails if the responses don't arrive
+    /// before the timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::hyper::StatusCode;
+    /// # use gotham::state::State;
+    /// use gotham::test::TestServer;
+    ///
+    /// # fn handler(state: State) -> (State, &'static str) {
+    /// #     (state, "Hello!")
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+    /// let client = test_server.client();
+    ///
+    /// let requests = (0..10).map(|_| client.get("http://localhost/"));
+    /// for response in client.perform_all(requests).unwrap() {
+    ///     response.unwrap().assert_status(StatusCode::OK);
+    /// }
+    /// # }
+    /// ```
+    pub fn perform_all<'a, I>(
+        &'a self,
+        requests: I,
+    ) -> anyhow::Result<Vec<anyhow::Result<TestResponse>>>
+    where
+        I: IntoIterator<Item = TestRequest<'a, TS, C>>,
+    {
+        let requests = requests
+            .into_iter()
+            .map(|req| self.client.request(self.prepare(req)))
+            .collect::<Vec<_>>();
+        let responses = future::join_all(requests)
+            .map(Ok::<_, anyhow::Error>)
+            .boxed();
+
+        let responses = self.test_server.run_request(responses)?;
+        Ok(responses
+            .into_iter()
+            .map(|response| {
+                let response = response.map_err(|e| {
+                    warn!("Error from test client request {:?}", e);
+                    e
+                })?;
+                if let Some(cookies) = &self.cookies {
+                    cookies.store(response.headers());
+                }
+                Ok(TestResponse {
+                    response,
+                    reader: Box::new(self.test_server.clone()),
+                })
             })
+            .collect())
+    }
+
+    /// Send a constructed request using this `TestClient`, returning a `Future` of the response
+    /// instead of blocking until it arrives. The `Future` runs on the runtime which polls it, so it
+    /// can be awaited within async tests, e.g. to issue several requests concurrently. The server
+    /// keeps running on its own runtime.
+    ///
+    /// The request times out like those sent by `perform`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::state::State;
+    /// # use gotham::router::builder::*;
+    /// use gotham::test::TestServer;
+    ///
+    /// # fn handler(state: State) -> (State, &'static str) {
+    /// #     (state, "Hello!")
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let router = build_simple_router(|route| route.get("/").to(handler));
+    /// let test_server = TestServer::new(router).unwrap();
+    /// let client = test_server.client();
+    ///
+    /// # let runtime = tokio::runtime::Runtime::new().unwrap();
+    /// # runtime.block_on(async {
+    /// let (first, second) = futures_util::future::join(
+    ///     client.get("http://localhost/").perform_async(),
+    ///     client.get("http://localhost/").perform_async(),
+    /// )
+    /// .await;
+    /// assert_eq!(first.unwrap().read_utf8_body().await.unwrap(), "Hello!");
+    /// assert_eq!(second.unwrap().read_utf8_body().await.unwrap(), "Hello!");
+    /// # });
+    /// # }
+    /// ```
+    pub fn perform_async(
+        &self,
+        req: TestRequest<'_, TS, C>,
+    ) -> impl Future<Output = anyhow::Result<AsyncTestResponse>> + Send + 'static {
+        let req_future = self.client.request(self.prepare(req));
+        let expiry = self.test_server.request_expiry();
+        let cookies = self.cookies.clone();
+
+        async move {
+            let response = match future::select(req_future, Box::pin(expiry)).await {
+                future::Either::Left((Ok(response), _)) => response,
+                future::Either::Left((Err(e), _)) => {
+                    warn!("Error from test client request {:?}", e);
+                    return Err(e.into());
+                }
+                future::Either::Right(_) => return Err(anyhow!("timed out")),
+            };
+            if let Some(cookies) = &cookies {
+                cookies.store(response.headers());
+            }
+            Ok(response.into())
+        }
+    }
+
+    // Adds the cookies stored for the request, if this client persists cookies.
+    fn prepare(&self, req: TestRequest<'_, TS, C>) -> Request<Body> {
+        let mut request = req.request();
+        if let Some(cookies) = &self.cookies {
+            let uri = request.uri().clone();
+            cookies.apply(&uri, request.headers_mut());
+        }
+        request
     }
 }
 
@@ -364,6 +629,12 @@ impl TestResponse {
         self.reader.read_body(self.response)
     }
 
+    /// Returns a `TestBodyStream` reading the body of the underlying `Response` chunk by chunk,
+    /// e.g. to test streaming responses or server-sent events.
+    pub fn into_stream(self) -> TestBodyStream {
+        TestBodyStream::new(self.response.into_body(), self.reader)
+    }
+
     /// Awaits the UTF-8 encoded body of the underlying `Response`, and returns the `String`. This
     /// will cause the event loop to execute until the `Response` body has been fully read and the
     /// `String` created.
@@ -372,6 +643,68 @@ impl TestResponse {
         let s = String::from_utf8(buf)?;
         Ok(s)
     }
+
+    /// Asserts that the response has the given status code, panicking with both status codes
+    /// otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use gotham::hyper::header::CONTENT_TYPE;
+    /// # use gotham::hyper::StatusCode;
+    /// # use gotham::state::State;
+    /// use gotham::test::TestServer;
+    ///
+    /// # fn handler(state: State) -> (State, &'static str) {
+    /// #     (state, "[1, 2, 3]")
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+    /// let response = test_server.client().get("http://localhost/").perform().unwrap();
+    ///
+    /// response
+    ///     .assert_status(StatusCode::OK)
+    ///     .assert_header(CONTENT_TYPE, "text/plain");
+    /// assert_eq!(response.json::<Vec<u32>>(), vec![1, 2, 3]);
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn assert_status(&self, status: StatusCode) -> &Self {
+        assert::assert_status(self.status(), status);
+        self
+    }
+
+    /// Asserts that the response has a header `name` with the given value, panicking with the
+    /// values present otherwise. Headers with multiple values pass if any of them matches.
+    #[track_caller]
+    pub fn assert_header<N>(&self, name: N, value: &str) -> &Self
+    where
+        N: AsHeaderName + Clone + fmt::Display,
+    {
+        assert::assert_header(self.headers(), name, value);
+        self
+    }
+
+    /// Reads the body of the response as UTF-8 text.
+    ///
+    /// # Panics
+    ///
+    /// If the body cannot be read or is not valid UTF-8.
+    #[track_caller]
+    pub fn text(self) -> String {
+        assert::text(self.read_body().expect("unable to read response body"))
+    }
+
+    /// Reads the body of the response and deserializes it from JSON.
+    ///
+    /// # Panics
+    ///
+    /// If the body cannot be read or deserialized, with the body in the panic message.
+    #[track_caller]
+    pub fn json<T: DeserializeOwned>(self) -> T {
+        assert::json(self.read_body().expect("unable to read response body"))
+    }
 }
 
 #[cfg(test)]
@@ -529,7 +862,7 @@ pub(crate) mod common_tests {
 
         let content_length = {
             let content_length = res.headers().get(CONTENT_LENGTH).expect("ContentLength");
-            assert_eq!(content_length, &format!("{}", data.as_bytes().len()));
+            assert_eq!(content_length, &format!("{}", data.len()));
             content_length.clone()
         };
 
@@ -0,0 +1,110 @@
+//! Building `multipart/form-data` request bodies for tests.
+
+use mime::Mime;
+use uuid::Uuid;
+
+/// A single part of a `multipart/form-data` request body, as passed to `TestRequest::multipart`.
+#[derive(Clone, Debug)]
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<Mime>,
+    body: Vec<u8>,
+}
+
+impl Part {
+    /// Creates a plain form field named `name` with the given value.
+    pub fn text<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        Part {
+            name: name.into(),
+            filename: None,
+            content_type: None,
+            body: value.into().into_bytes(),
+        }
+    }
+
+    /// Creates a file upload field named `name`, containing the file `filename` with the given
+    /// content type and contents.
+    pub fn file<N, F, B>(name: N, filename: F, content_type: Mime, body: B) -> Self
+    where
+        N: Into<String>,
+        F: Into<String>,
+        B: Into<Vec<u8>>,
+    {
+        Part {
+            name: name.into(),
+            filename: Some(filename.into()),
+            content_type: Some(content_type),
+            body: body.into(),
+        }
+    }
+
+    fn write_to(&self, boundary: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let mut disposition = format!("form-data; name=\"{}\"", quote(&self.name));
+        if let Some(filename) = &self.filename {
+            disposition.push_str(&format!("; filename=\"{}\"", quote(filename)));
+        }
+        out.extend_from_slice(format!("Content-Disposition: {}\r\n", disposition).as_bytes());
+        if let Some(content_type) = &self.content_type {
+            out.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(&self.body);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+// Escapes quotes and line breaks in a header parameter value, as browsers do.
+fn quote(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Encodes `parts` as a `multipart/form-data` body, returning the content type (including the
+/// generated boundary) and the body.
+pub(crate) fn encode(parts: &[Part]) -> (Mime, Vec<u8>) {
+    let boundary = format!("gotham-test-{}", Uuid::new_v4().simple());
+    let mut body = Vec::new();
+    for part in parts {
+        part.write_to(&boundary, &mut body);
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    let content_type = format!("multipart/form-data; boundary={}", boundary)
+        .parse()
+        .expect("valid multipart content type");
+    (content_type, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_parts() {
+        let (content_type, body) = encode(&[
+            Part::text("title", "Report"),
+            Part::file("upload", "a\"b.txt", mime::TEXT_PLAIN, "contents"),
+        ]);
+
+        assert_eq!(content_type.essence_str(), "multipart/form-data");
+        let boundary = content_type.get_param("boundary").unwrap().as_str();
+        let expected = format!(
+            "--{0}\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Report\r\n\
+             --{0}\r\n\
+             Content-Disposition: form-data; name=\"upload\"; filename=\"a%22b.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             contents\r\n\
+             --{0}--\r\n",
+            boundary
+        );
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+    }
+}
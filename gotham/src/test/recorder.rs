@@ -0,0 +1,181 @@
+//! Recording `State` data of requests, for assertions on the internal behaviour of a pipeline.
+
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::FutureExt;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::State;
+
+/// A `Middleware` recording data taken from the `State` of each request, once the request has
+/// been handled. Clones share the same records, so the recorder can be added to a pipeline and
+/// inspected by the test after the response has been received.
+///
+/// The data is captured after the rest of the pipeline and the handler have completed, whether
+/// successfully or not, so it includes e.g. extracted path and query parameters, modified session
+/// data and the request ID. Middleware added to the pipeline before the recorder has not yet
+/// processed the response at that point.
+///
+/// # Examples
+///
+/// ```rust
+/// # use gotham::pipeline::{new_pipeline, single_pipeline};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{request_id, State};
+/// use gotham::test::{StateRecorder, TestServer};
+///
+/// # fn handler(state: State) -> (State, &'static str) {
+/// #     (state, "Hello!")
+/// # }
+/// #
+/// # fn main() {
+/// let recorder = StateRecorder::new(|state: &State| request_id(state).to_owned());
+/// let (chain, pipelines) = single_pipeline(new_pipeline().add(recorder.clone()).build());
+/// let router = build_router(chain, pipelines, |route| route.get("/").to(handler));
+///
+/// let test_server = TestServer::new(router).unwrap();
+/// let response = test_server.client().get("http://localhost/").perform().unwrap();
+///
+/// assert_eq!(recorder.records(), vec![response.headers()["x-request-id"].to_str().unwrap()]);
+/// # }
+/// ```
+pub struct StateRecorder<T> {
+    capture: Arc<dyn Fn(&State) -> T + Send + Sync + RefUnwindSafe>,
+    records: Arc<Mutex<Vec<T>>>,
+}
+
+impl<T> Clone for StateRecorder<T> {
+    fn clone(&self) -> Self {
+        StateRecorder {
+            capture: self.capture.clone(),
+            records: self.records.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> StateRecorder<T> {
+    /// Creates a new `StateRecorder`, recording the data returned by `capture` for each request.
+    pub fn new<F>(capture: F) -> Self
+    where
+        F: Fn(&State) -> T + Send + Sync + RefUnwindSafe + 'static,
+    {
+        StateRecorder {
+            capture: Arc::new(capture),
+            records: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns clones of the data recorded so far, in the order the requests completed.
+    pub fn records(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.lock().clone()
+    }
+
+    /// Returns a clone of the data recorded for the most recently completed request.
+    pub fn last(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.lock().last().cloned()
+    }
+
+    /// Removes and returns the data recorded so far.
+    pub fn take(&self) -> Vec<T> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<T>> {
+        self.records.lock().expect("state recorder poisoned")
+    }
+
+    fn record(&self, state: &State) {
+        let record = (self.capture)(state);
+        self.lock().push(record);
+    }
+}
+
+impl<T: Send + 'static> Middleware for StateRecorder<T> {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        chain(state)
+            .inspect(move |result| match result {
+                Ok((state, _)) | Err((state, _)) => self.record(state),
+            })
+            .boxed()
+    }
+}
+
+impl<T: Send + 'static> NewMiddleware for StateRecorder<T> {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{new_pipeline, single_pipeline};
+    use crate::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::response::StaticResponseExtender;
+    use crate::state::{FromState, StateData};
+    use crate::test::TestServer;
+    use hyper::{Body, Response, StatusCode};
+    use serde::Deserialize;
+
+    #[derive(Clone, Deserialize)]
+    struct NameParams {
+        name: String,
+    }
+
+    impl StateData for NameParams {}
+
+    impl StaticResponseExtender for NameParams {
+        type ResBody = Body;
+        fn extend(_: &mut State, _: &mut Response<Body>) {}
+    }
+
+    fn greet(state: State) -> (State, String) {
+        let greeting = format!("Hello, {}!", NameParams::borrow_from(&state).name);
+        (state, greeting)
+    }
+
+    #[test]
+    fn records_extracted_data() {
+        let recorder =
+            StateRecorder::new(|state: &State| NameParams::try_borrow_from(state).cloned());
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(recorder.clone()).build());
+        let router = build_router(chain, pipelines, |route| {
+            route
+                .get("/greet/:name")
+                .with_path_extractor::<NameParams>()
+                .to(greet);
+        });
+        let test_server = TestServer::new(router).unwrap();
+
+        for name in &["alice", "bob"] {
+            test_server
+                .client()
+                .get(format!("http://localhost/greet/{}", name))
+                .perform()
+                .unwrap()
+                .assert_status(StatusCode::OK);
+        }
+
+        let names = recorder
+            .take()
+            .into_iter()
+            .map(|params| params.unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["alice", "bob"]);
+        assert!(recorder.last().is_none());
+    }
+}
@@ -1,11 +1,46 @@
 use std::convert::TryFrom;
+use std::future::Future;
 use std::ops::{Deref, DerefMut};
 
 use hyper::client::connect::Connect;
-use hyper::header::{HeaderValue, IntoHeaderName};
+use hyper::header::{HeaderValue, IntoHeaderName, CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{http, Body, Method, Request, Uri};
+use mime::Mime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
 
-use super::{Server, TestClient, TestResponse};
+use super::multipart::{self, Part};
+use super::{AsyncTestResponse, Server, TestClient, TestResponse};
+
+// The characters left unencoded by `application/x-www-form-urlencoded`, apart from the space
+// which is encoded as `+`.
+const FORM: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'*')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b' ');
+
+/// Serializes `value` as a JSON request body.
+pub(crate) fn json_body<T: Serialize + ?Sized>(value: &T) -> (Mime, Vec<u8>) {
+    let body = serde_json::to_vec(value).expect("unable to serialize JSON request body");
+    (mime::APPLICATION_JSON, body)
+}
+
+/// Encodes `fields` as an `application/x-www-form-urlencoded` request body.
+pub(crate) fn form_body<K: AsRef<str>, V: AsRef<str>>(fields: &[(K, V)]) -> (Mime, Vec<u8>) {
+    let encode = |value: &str| {
+        utf8_percent_encode(value, FORM)
+            .to_string()
+            .replace(' ', "+")
+    };
+    let body = fields
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name.as_ref()), encode(value.as_ref())))
+        .collect::<Vec<_>>()
+        .join("&");
+    (mime::APPLICATION_WWW_FORM_URLENCODED, body.into_bytes())
+}
 
 /// Builder API for constructing `Server` requests. When the request is built,
 /// `RequestBuilder::perform` will issue the request and provide access to the response.
@@ -49,6 +84,14 @@ impl<'a, S: Server + 'static, C: Connect + Clone + Send + Sync + 'static> TestRe
         self.client.perform(self)
     }
 
+    /// Send a constructed request using the `TestClient`, returning a `Future` of the response.
+    /// See `TestClient::perform_async`.
+    pub fn perform_async(
+        self,
+    ) -> impl Future<Output = anyhow::Result<AsyncTestResponse>> + Send + 'static {
+        self.client.perform_async(self)
+    }
+
     /// Extracts the request from this `TestRequest`.
     pub(crate) fn request(self) -> Request<Body> {
         self.request
@@ -62,4 +105,127 @@ impl<'a, S: Server + 'static, C: Connect + Clone + Send + Sync + 'static> TestRe
         self.headers_mut().insert(name, value);
         self
     }
+
+    /// Sets the body of the underlying `Request` to `value` serialized as JSON, along with the
+    /// matching `Content-Type`.
+    ///
+    /// # Panics
+    ///
+    /// If `value` cannot be serialized.
+    pub fn json<T: Serialize + ?Sized>(self, value: &T) -> Self {
+        let (mime, body) = json_body(value);
+        self.with_body(mime, body)
+    }
+
+    /// Sets the body of the underlying `Request` to the URL encoded `fields`, along with the
+    /// matching `Content-Type`.
+    pub fn form<K: AsRef<str>, V: AsRef<str>>(self, fields: &[(K, V)]) -> Self {
+        let (mime, body) = form_body(fields);
+        self.with_body(mime, body)
+    }
+
+    /// Sets the body of the underlying `Request` to a `multipart/form-data` body made of `parts`,
+    /// along with the matching `Content-Type`.
+    pub fn multipart(self, parts: &[Part]) -> Self {
+        let (mime, body) = multipart::encode(parts);
+        self.with_body(mime, body)
+    }
+
+    fn with_body(mut self, mime: Mime, body: Vec<u8>) -> Self {
+        let headers = self.headers_mut();
+        headers.insert(CONTENT_TYPE, mime.to_string().parse().unwrap());
+        headers.insert(CONTENT_LENGTH, body.len().into());
+        *self.body_mut() = body.into();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::HandlerFuture;
+    use crate::helpers::http::response::create_response;
+    use crate::plain::test::TestConnect;
+    use crate::state::{FromState, State};
+    use crate::test::TestServer;
+    use futures_util::FutureExt;
+    use hyper::header::HeaderMap;
+    use hyper::StatusCode;
+    use std::pin::Pin;
+
+    // Responds with the request's content type on the first line, followed by the body.
+    fn echo(mut state: State) -> Pin<Box<HandlerFuture>> {
+        async move {
+            let body = match hyper::body::to_bytes(Body::take_from(&mut state)).await {
+                Ok(body) => body,
+                Err(err) => return Err((state, err.into())),
+            };
+            let content_type = HeaderMap::borrow_from(&state)[CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let echoed = format!("{}\n{}", content_type, String::from_utf8_lossy(&body));
+            let response = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, echoed);
+            Ok((state, response))
+        }
+        .boxed()
+    }
+
+    fn post(request: TestRequest<'_, TestServer, TestConnect>) -> String {
+        request.perform().unwrap().read_utf8_body().unwrap()
+    }
+
+    #[test]
+    fn sends_json_bodies() {
+        #[derive(Serialize)]
+        struct Login<'a> {
+            user: &'a str,
+        }
+
+        let test_server = TestServer::new(|| Ok(echo)).unwrap();
+        let client = test_server.client();
+        let echoed = post(
+            client
+                .build_request(Method::POST, "http://localhost/")
+                .json(&Login { user: "alice" }),
+        );
+        assert_eq!(echoed, "application/json\n{\"user\":\"alice\"}");
+    }
+
+    #[test]
+    fn sends_form_bodies() {
+        let test_server = TestServer::new(|| Ok(echo)).unwrap();
+        let client = test_server.client();
+        let echoed = post(
+            client
+                .build_request(Method::POST, "http://localhost/")
+                .form(&[("name", "J. Doe"), ("q", "a&b=c")]),
+        );
+        assert_eq!(
+            echoed,
+            "application/x-www-form-urlencoded\nname=J.+Doe&q=a%26b%3Dc"
+        );
+    }
+
+    #[test]
+    fn sends_multipart_bodies() {
+        let test_server = TestServer::new(|| Ok(echo)).unwrap();
+        let client = test_server.client();
+        let echoed = post(
+            client
+                .build_request(Method::POST, "http://localhost/")
+                .multipart(&[Part::text("title", "Report")]),
+        );
+        let (content_type, body) = echoed.split_once('\n').unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{0}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nReport\r\n--{0}--\r\n",
+                boundary
+            )
+        );
+    }
 }
@@ -0,0 +1,159 @@
+//! Reading response bodies incrementally in tests.
+
+use std::fmt;
+use std::time::Duration;
+
+use bytes::Bytes;
+use hyper::Body;
+
+use super::BodyReader;
+
+/// Reads the body of a `TestResponse` chunk by chunk, as returned by `TestResponse::into_stream`.
+/// Each chunk must arrive within the timeout, which defaults to 10 seconds. This allows testing
+/// streaming responses, such as server-sent events, which are never completed.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use futures_util::stream::{self, StreamExt};
+/// # use gotham::hyper::{Body, Response};
+/// # use gotham::state::State;
+/// use gotham::test::TestServer;
+///
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     // sends a single event, but never ends the response
+///     let events = stream::iter(vec![Ok::<_, std::io::Error>("data: hello\n\n")])
+///         .chain(stream::pending());
+///     (state, Response::new(Body::wrap_stream(events)))
+/// }
+///
+/// # fn main() {
+/// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// let mut body = response.into_stream().with_timeout(Duration::from_millis(100));
+///
+/// assert_eq!(body.next_chunk().unwrap().unwrap(), "data: hello\n\n");
+/// assert!(body.next_chunk().is_err());
+/// # }
+/// ```
+pub struct TestBodyStream {
+    body: Body,
+    reader: Box<dyn BodyReader>,
+    timeout: Duration,
+}
+
+impl fmt::Debug for TestBodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TestBodyStream")
+    }
+}
+
+impl TestBodyStream {
+    pub(crate) fn new(body: Body, reader: Box<dyn BodyReader>) -> Self {
+        TestBodyStream {
+            body,
+            reader,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the time to wait for each chunk.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Waits for the next chunk of the body, returning `None` at the end of the body. Fails if the
+    /// chunk does not arrive before the timeout, or if reading the body fails.
+    pub fn next_chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        self.reader.read_chunk(&mut self.body, self.timeout)
+    }
+
+    /// Reads the remaining chunks of the body, until its end.
+    pub fn read_to_end(mut self) -> anyhow::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        while let Some(chunk) = self.next_chunk()? {
+            buf.extend_from_slice(&chunk);
+        }
+        Ok(buf)
+    }
+}
+
+impl Iterator for TestBodyStream {
+    type Item = anyhow::Result<Bytes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream::{self, StreamExt};
+    use hyper::Response;
+    use tokio::sync::mpsc;
+
+    use crate::state::State;
+    use crate::test::TestServer;
+
+    use super::*;
+
+    #[test]
+    fn reads_chunks_as_they_arrive() {
+        // the handler only sends the chunks passed to it by the test
+        let (tx, rx) = mpsc::unbounded_channel::<&'static str>();
+        let rx = std::sync::Mutex::new(Some(rx));
+        let test_server = TestServer::new(move || {
+            let rx = rx.lock().unwrap().take();
+            Ok(move |state: State| {
+                let mut rx = rx.expect("single request");
+                let chunks =
+                    stream::poll_fn(move |cx| rx.poll_recv(cx)).map(Ok::<_, std::io::Error>);
+                (state, Response::new(Body::wrap_stream(chunks)))
+            })
+        })
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        let mut body = response
+            .into_stream()
+            .with_timeout(Duration::from_millis(200));
+
+        tx.send("first").unwrap();
+        assert_eq!(body.next_chunk().unwrap().unwrap(), "first");
+        assert!(body
+            .next_chunk()
+            .unwrap_err()
+            .to_string()
+            .contains("timed out"));
+
+        tx.send("second").unwrap();
+        drop(tx);
+        assert_eq!(body.read_to_end().unwrap(), b"second");
+    }
+
+    #[test]
+    fn iterates_over_chunks() {
+        fn handler(state: State) -> (State, Response<Body>) {
+            let chunks = stream::iter(vec![Ok::<_, std::io::Error>("a"), Ok("b"), Ok("c")]);
+            (state, Response::new(Body::wrap_stream(chunks)))
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let body = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap()
+            .into_stream()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap()
+            .concat();
+        assert_eq!(body, b"abc");
+    }
+}
@@ -0,0 +1,248 @@
+//! WebSocket client support for the test servers.
+
+use std::future::Future;
+use std::net::SocketAddr;
+
+use anyhow::anyhow;
+use futures_util::future::{self, Either, FutureExt};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::test::Server;
+use crate::websocket::{CloseCode, CloseFrame, Message};
+
+// Connects to the server at `addr` and performs the opening handshake for `uri`. Any response
+// other than `101 Switching Protocols` is reported as an error.
+pub(crate) async fn connect(
+    addr: SocketAddr,
+    uri: String,
+) -> anyhow::Result<WebSocketStream<TcpStream>> {
+    let stream = TcpStream::connect(addr).await?;
+    let (ws, _) = tokio_tungstenite::client_async(uri, stream).await?;
+    Ok(ws)
+}
+
+/// A WebSocket connection to a `TestServer`, created by `TestServer::websocket`.
+///
+/// Every operation runs the event loop of the `TestServer` until it completes, or until the
+/// request timeout of the server expires.
+///
+/// # Examples
+///
+/// ```rust
+/// # use futures_util::{SinkExt, StreamExt};
+/// # use gotham::router::builder::*;
+/// use gotham::test::TestServer;
+/// use gotham::websocket::{CloseCode, WebSocket};
+///
+/// async fn echo(mut ws: WebSocket) {
+///     while let Some(Ok(message)) = ws.next().await {
+///         if message.is_text() && ws.send(message).await.is_err() {
+///             break;
+///         }
+///     }
+/// }
+///
+/// # fn main() {
+/// let router = build_simple_router(|route| route.get("/ws").to_websocket(echo));
+/// let test_server = TestServer::new(router).unwrap();
+///
+/// let mut ws = test_server.websocket("ws://localhost/ws").unwrap();
+/// ws.send_text("hello").unwrap();
+/// assert_eq!(ws.receive_text().unwrap(), "hello");
+///
+/// ws.close(CloseCode::Normal, "done").unwrap();
+/// let frame = ws.receive_close().unwrap().unwrap();
+/// assert_eq!(frame.code, CloseCode::Normal);
+/// # }
+/// ```
+pub struct TestWebSocket<TS: Server, S> {
+    server: TS,
+    stream: WebSocketStream<S>,
+}
+
+impl<TS, S> TestWebSocket<TS, S>
+where
+    TS: Server,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub(crate) fn new(server: TS, stream: WebSocketStream<S>) -> Self {
+        TestWebSocket { server, stream }
+    }
+
+    /// Sends a message to the server.
+    pub fn send(&mut self, message: Message) -> anyhow::Result<()> {
+        run_with_expiry(&self.server, self.stream.send(message))?.map_err(Into::into)
+    }
+
+    /// Sends a text message to the server.
+    pub fn send_text<T: Into<String>>(&mut self, text: T) -> anyhow::Result<()> {
+        self.send(Message::Text(text.into()))
+    }
+
+    /// Sends a binary message to the server.
+    pub fn send_binary<B: Into<Vec<u8>>>(&mut self, data: B) -> anyhow::Result<()> {
+        self.send(Message::Binary(data.into()))
+    }
+
+    /// Waits for the next message sent by the server, including control frames. Fails if the
+    /// connection has been closed without a close frame.
+    pub fn receive(&mut self) -> anyhow::Result<Message> {
+        match run_with_expiry(&self.server, self.stream.next())? {
+            Some(message) => Ok(message?),
+            None => Err(anyhow!("connection closed")),
+        }
+    }
+
+    /// Waits for the next text message sent by the server, skipping pings and pongs. Fails if any
+    /// other message is received.
+    pub fn receive_text(&mut self) -> anyhow::Result<String> {
+        match self.receive_data()? {
+            Message::Text(text) => Ok(text),
+            other => Err(anyhow!("expected a text message, received {:?}", other)),
+        }
+    }
+
+    /// Waits for the next binary message sent by the server, skipping pings and pongs. Fails if
+    /// any other message is received.
+    pub fn receive_binary(&mut self) -> anyhow::Result<Vec<u8>> {
+        match self.receive_data()? {
+            Message::Binary(data) => Ok(data),
+            other => Err(anyhow!("expected a binary message, received {:?}", other)),
+        }
+    }
+
+    /// Waits for the server to close the connection, returning the close frame it sent (if any).
+    /// Fails if a data message is received first.
+    ///
+    /// This completes the closing handshake for connections closed by either side.
+    pub fn receive_close(&mut self) -> anyhow::Result<Option<CloseFrame<'static>>> {
+        match self.receive_data()? {
+            Message::Close(frame) => Ok(frame),
+            other => Err(anyhow!("expected a close frame, received {:?}", other)),
+        }
+    }
+
+    /// Starts the closing handshake with the given close code and reason. Use `receive_close`
+    /// to wait for the server's response.
+    pub fn close(&mut self, code: CloseCode, reason: &str) -> anyhow::Result<()> {
+        let frame = CloseFrame {
+            code,
+            reason: reason.to_owned().into(),
+        };
+        run_with_expiry(&self.server, self.stream.close(Some(frame)).boxed_local())?
+            .map_err(Into::into)
+    }
+
+    /// Returns the underlying `WebSocketStream`.
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.stream
+    }
+
+    fn receive_data(&mut self) -> anyhow::Result<Message> {
+        loop {
+            match self.receive()? {
+                Message::Ping(_) | Message::Pong(_) => continue,
+                message => return Ok(message),
+            }
+        }
+    }
+}
+
+// Runs `f` on the server's event loop, racing it against the server's request timeout.
+fn run_with_expiry<TS, F>(server: &TS, f: F) -> anyhow::Result<F::Output>
+where
+    TS: Server,
+    F: Future + Unpin,
+{
+    let expiry = server.request_expiry().boxed();
+    match server.run_future(future::select(f, expiry)) {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(anyhow!("timed out")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plain::test::AsyncTestServer;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use crate::test::TestServer;
+    use crate::websocket::WebSocket;
+
+    async fn echo(mut ws: WebSocket) {
+        while let Some(Ok(message)) = ws.next().await {
+            if (message.is_text() || message.is_binary()) && ws.send(message).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn reject(mut ws: WebSocket) {
+        let frame = CloseFrame {
+            code: CloseCode::Policy,
+            reason: "go away".into(),
+        };
+        let _ = ws.close(Some(frame)).await;
+    }
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route.get("/echo").to_websocket(echo);
+            route.get("/reject").to_websocket(reject);
+        })
+    }
+
+    #[test]
+    fn exchanges_messages() {
+        let test_server = TestServer::new(router()).unwrap();
+        let mut ws = test_server.websocket("ws://localhost/echo").unwrap();
+
+        ws.send_text("hello").unwrap();
+        assert_eq!(ws.receive_text().unwrap(), "hello");
+
+        ws.send_binary(vec![1, 2, 3]).unwrap();
+        assert_eq!(ws.receive_binary().unwrap(), vec![1, 2, 3]);
+
+        ws.close(CloseCode::Normal, "").unwrap();
+        let frame = ws.receive_close().unwrap().unwrap();
+        assert_eq!(frame.code, CloseCode::Normal);
+    }
+
+    #[test]
+    fn receives_close_codes() {
+        let test_server = TestServer::new(router()).unwrap();
+        let mut ws = test_server.websocket("ws://localhost/reject").unwrap();
+
+        let frame = ws.receive_close().unwrap().unwrap();
+        assert_eq!(frame.code, CloseCode::Policy);
+        assert_eq!(frame.reason, "go away");
+    }
+
+    #[test]
+    fn fails_on_unexpected_messages() {
+        let test_server = TestServer::new(router()).unwrap();
+        let mut ws = test_server.websocket("ws://localhost/echo").unwrap();
+
+        ws.send_text("hello").unwrap();
+        assert!(ws.receive_close().is_err());
+    }
+
+    #[test]
+    fn fails_on_rejected_handshake() {
+        let test_server = TestServer::new(router()).unwrap();
+        assert!(test_server.websocket("ws://localhost/missing").is_err());
+    }
+
+    #[tokio::test]
+    async fn async_test_server_exchanges_messages() {
+        let test_server = AsyncTestServer::new(router()).await.unwrap();
+        let mut ws = test_server.websocket("ws://localhost/echo").await.unwrap();
+
+        ws.send(Message::text("hello")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), Message::text("hello"));
+    }
+}
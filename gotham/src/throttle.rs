@@ -0,0 +1,327 @@
+//! Per-connection bandwidth limiting.
+//!
+//! A `ThrottleConfig` passed to `bind_server_with_throttle` wraps every accepted connection in a
+//! token bucket, limiting the number of bytes per second which can be read from or written to
+//! that connection. The limits can be changed for an individual connection at runtime through the
+//! `ConnectionThrottle` handle, which is placed into `State` for every request received on a
+//! throttled connection. This allows middleware to e.g. raise the limit for authenticated users,
+//! or lower it for expensive download routes.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate gotham_derive;
+//! #
+//! # use std::pin::Pin;
+//! # use gotham::handler::HandlerFuture;
+//! # use gotham::middleware::Middleware;
+//! # use gotham::state::{FromState, State};
+//! use gotham::throttle::ConnectionThrottle;
+//!
+//! # #[allow(dead_code)]
+//! #[derive(Clone, NewMiddleware)]
+//! struct PremiumBandwidth;
+//!
+//! impl Middleware for PremiumBandwidth {
+//!     fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+//!     where
+//!         Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+//!     {
+//!         if let Some(throttle) = ConnectionThrottle::try_borrow_from(&state) {
+//!             throttle.set_write_limit(Some(10 * 1024 * 1024));
+//!         }
+//!         chain(state)
+//!     }
+//! }
+//! #
+//! # fn main() {}
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::ready;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{sleep_until, Instant, Sleep};
+
+use crate::state::StateData;
+
+/// Global bandwidth limits applied to every connection accepted by the server.
+///
+/// Limits are expressed in bytes per second; `None` means unlimited. An idle connection
+/// accumulates at most one second worth of its limit, which bounds the size of a burst.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ThrottleConfig {
+    read_limit: Option<u64>,
+    write_limit: Option<u64>,
+}
+
+impl ThrottleConfig {
+    /// Creates a new `ThrottleConfig` without any limits.
+    pub fn new() -> Self {
+        ThrottleConfig::default()
+    }
+
+    /// Limits the rate at which request data is read from each connection.
+    pub fn with_read_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.read_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Limits the rate at which response data is written to each connection.
+    pub fn with_write_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.write_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Returns the configured read limit in bytes per second.
+    pub fn read_limit(&self) -> Option<u64> {
+        self.read_limit
+    }
+
+    /// Returns the configured write limit in bytes per second.
+    pub fn write_limit(&self) -> Option<u64> {
+        self.write_limit
+    }
+}
+
+/// A handle to the bandwidth limits of a single connection.
+///
+/// This is stored in `State` for every request served on a connection accepted via
+/// `bind_server_with_throttle`. Changes made through the handle take effect immediately, and
+/// apply to all subsequent requests on the same (keep-alive) connection.
+#[derive(Clone, Debug)]
+pub struct ConnectionThrottle {
+    limits: Arc<Mutex<ThrottleConfig>>,
+}
+
+impl StateData for ConnectionThrottle {}
+
+impl ConnectionThrottle {
+    /// Creates a new handle, initially applying the given limits.
+    pub fn new(config: ThrottleConfig) -> Self {
+        ConnectionThrottle {
+            limits: Arc::new(Mutex::new(config)),
+        }
+    }
+
+    /// Returns the limits currently applied to this connection.
+    pub fn limits(&self) -> ThrottleConfig {
+        *self.limits.lock().expect("throttle limits poisoned")
+    }
+
+    /// Overrides the read limit of this connection. `None` removes the limit.
+    pub fn set_read_limit(&self, bytes_per_sec: Option<u64>) {
+        self.limits
+            .lock()
+            .expect("throttle limits poisoned")
+            .read_limit = bytes_per_sec;
+    }
+
+    /// Overrides the write limit of this connection. `None` removes the limit.
+    pub fn set_write_limit(&self, bytes_per_sec: Option<u64>) {
+        self.limits
+            .lock()
+            .expect("throttle limits poisoned")
+            .write_limit = bytes_per_sec;
+    }
+}
+
+// A token bucket holding up to one second worth of bytes.
+struct Bucket {
+    tokens: u64,
+    last_refill: Instant,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Bucket {
+    fn new() -> Self {
+        Bucket {
+            tokens: 0,
+            last_refill: Instant::now(),
+            delay: None,
+        }
+    }
+
+    // Returns the number of bytes which may be transferred right now, or `Pending` if the bucket
+    // does not hold enough tokens yet, in which case the task is woken once it does. To avoid
+    // trickling single bytes, at least 1/100th of the rate is accumulated before continuing.
+    fn poll_acquire(
+        &mut self,
+        cx: &mut Context<'_>,
+        rate: Option<u64>,
+        wanted: usize,
+    ) -> Poll<usize> {
+        let rate = match rate {
+            Some(rate) if rate > 0 => rate,
+            _ => return Poll::Ready(wanted),
+        };
+        let needed = (rate / 100).clamp(1, wanted as u64);
+
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                self.delay = None;
+            }
+
+            let now = Instant::now();
+            let elapsed = now.saturating_duration_since(self.last_refill);
+            let refill = (elapsed.as_nanos() * rate as u128 / 1_000_000_000) as u64;
+            if refill > 0 {
+                self.tokens = (self.tokens + refill).min(rate);
+                self.last_refill = now;
+            }
+
+            if self.tokens >= needed {
+                return Poll::Ready(self.tokens.min(wanted as u64) as usize);
+            }
+
+            let missing = (needed - self.tokens) as u128;
+            let wait =
+                Duration::from_nanos(((missing * 1_000_000_000) / rate as u128).max(1) as u64);
+            self.delay = Some(Box::pin(sleep_until(now + wait)));
+        }
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.tokens = self.tokens.saturating_sub(n as u64);
+    }
+}
+
+/// An IO stream limited by the bandwidth of its `ConnectionThrottle`.
+pub struct Throttled<IO> {
+    io: IO,
+    throttle: ConnectionThrottle,
+    read_bucket: Bucket,
+    write_bucket: Bucket,
+}
+
+impl<IO> Throttled<IO> {
+    /// Wraps `io`, limiting it according to the given `ConnectionThrottle`.
+    pub fn new(io: IO, throttle: ConnectionThrottle) -> Self {
+        Throttled {
+            io,
+            throttle,
+            read_bucket: Bucket::new(),
+            write_bucket: Bucket::new(),
+        }
+    }
+
+    /// Returns the handle controlling the limits of this stream.
+    pub fn throttle(&self) -> &ConnectionThrottle {
+        &self.throttle
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Throttled<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let limit = this.throttle.limits().read_limit;
+        let allowed = ready!(this.read_bucket.poll_acquire(cx, limit, buf.remaining()));
+
+        let filled = {
+            let dst = buf.initialize_unfilled_to(allowed);
+            let mut limited = ReadBuf::new(dst);
+            ready!(Pin::new(&mut this.io).poll_read(cx, &mut limited))?;
+            limited.filled().len()
+        };
+        buf.advance(filled);
+
+        if limit.is_some() {
+            this.read_bucket.consume(filled);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Throttled<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let limit = this.throttle.limits().write_limit;
+        let allowed = ready!(this.write_bucket.poll_acquire(cx, limit, buf.len()));
+        let written = ready!(Pin::new(&mut this.io).poll_write(cx, &buf[..allowed]))?;
+
+        if limit.is_some() {
+            this.write_bucket.consume(written);
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn unlimited_stream_passes_through() {
+        let (client, server) = duplex(1024);
+        let mut throttled = Throttled::new(client, ConnectionThrottle::new(ThrottleConfig::new()));
+        let mut server = server;
+
+        throttled.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn write_limit_delays_writes() {
+        let (client, mut server) = duplex(64 * 1024);
+        let config = ThrottleConfig::new().with_write_limit(1000);
+        let mut throttled = Throttled::new(client, ConnectionThrottle::new(config));
+
+        let start = Instant::now();
+        throttled.write_all(&[0u8; 2500]).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(2));
+
+        let mut buf = vec![0u8; 2500];
+        server.read_exact(&mut buf).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_limit_can_be_overridden() {
+        let (client, mut server) = duplex(64 * 1024);
+        let config = ThrottleConfig::new().with_read_limit(100);
+        let throttle = ConnectionThrottle::new(config);
+        let mut throttled = Throttled::new(client, throttle.clone());
+
+        server.write_all(&[1u8; 1000]).await.unwrap();
+        throttle.set_read_limit(None);
+
+        let start = Instant::now();
+        let mut buf = vec![0u8; 1000];
+        throttled.read_exact(&mut buf).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(throttle.limits().read_limit(), None);
+    }
+}
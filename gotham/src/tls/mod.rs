@@ -6,6 +6,7 @@ use tokio::net::TcpStream;
 use tokio_rustls::{rustls, Accept, TlsAcceptor};
 
 use super::handler::NewHandler;
+use super::listener::BindOptions;
 use super::{bind_server, new_runtime, tcp_listener, StartError};
 
 #[cfg(feature = "testing")]
@@ -53,7 +54,22 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs + 'static + Send,
 {
-    let listener = tcp_listener(addr).await?;
+    init_server_with_options(addr, new_handler, tls_config, BindOptions::default()).await
+}
+
+/// Returns a `Future` used to spawn an Gotham application, binding the listening socket with the
+/// given `BindOptions`.
+pub async fn init_server_with_options<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+    options: BindOptions,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static + Send,
+{
+    let listener = tcp_listener(addr, options).await?;
     let addr = listener.local_addr().unwrap();
 
     info! {
@@ -13,6 +13,7 @@ use std::time::Duration;
 
 use futures_util::future::{BoxFuture, FutureExt};
 use hyper::client::connect::{Connected, Connection};
+use hyper::client::{self, Client};
 use hyper::service::Service;
 use hyper::Uri;
 use log::info;
@@ -34,11 +35,38 @@ use crate::tls::rustls_wrap;
 fn server_config() -> ServerConfig {
     let cert = Certificate(include_bytes!("tls_cert.der").to_vec());
     let key = PrivateKey(include_bytes!("tls_key.der").to_vec());
-    ServerConfig::builder()
+    server_config_with(cert, key).expect("Unable to create TLS server config")
+}
+
+fn server_config_with(cert: Certificate, key: PrivateKey) -> anyhow::Result<ServerConfig> {
+    Ok(ServerConfig::builder()
         .with_safe_defaults()
         .with_no_client_auth()
-        .with_single_cert(vec![cert], key)
-        .expect("Unable to create TLS server config")
+        .with_single_cert(vec![cert], key)?)
+}
+
+fn client_config(root: &Certificate) -> anyhow::Result<ClientConfig> {
+    let mut root_store = RootCertStore::empty();
+    root_store.add(root)?;
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth())
+}
+
+// Generates a self-signed certificate for `localhost`, `example.com` and `example.org`, returning
+// the server config using it and a client config trusting it.
+#[cfg(feature = "rcgen")]
+fn self_signed_config() -> anyhow::Result<(ServerConfig, ClientConfig)> {
+    let generated = rcgen::generate_simple_self_signed(vec![
+        "localhost".to_owned(),
+        "example.com".to_owned(),
+        "example.org".to_owned(),
+    ])?;
+    let cert = Certificate(generated.serialize_der()?);
+    let key = PrivateKey(generated.serialize_private_key_der());
+    let client = client_config(&cert)?;
+    Ok((server_config_with(cert, key)?, client))
 }
 
 /// The `TestServer` type, which is used as a harness when writing test cases for Hyper services
@@ -70,6 +98,7 @@ fn server_config() -> ServerConfig {
 #[derive(Clone)]
 pub struct TestServer {
     data: Arc<TestServerData>,
+    client_config: Option<Arc<ClientConfig>>,
 }
 
 impl test::Server for TestServer {
@@ -104,12 +133,54 @@ impl TestServer {
         let data = TestServerData::new(new_handler, timeout, rustls_wrap(cfg))?;
         Ok(TestServer {
             data: Arc::new(data),
+            client_config: None,
+        })
+    }
+
+    /// Creates a `TestServer` using a self-signed certificate generated for `localhost`,
+    /// `example.com` and `example.org`, instead of the certificate bundled with Gotham. Clients
+    /// returned by `client` trust the generated certificate.
+    ///
+    /// Timeout will be set to 10 seconds. Requires the `rcgen` feature.
+    #[cfg(feature = "rcgen")]
+    pub fn with_self_signed_cert<NH: NewHandler + 'static>(
+        new_handler: NH,
+    ) -> anyhow::Result<TestServer> {
+        let (server_cfg, client_cfg) = self_signed_config()?;
+        let data = TestServerData::new(new_handler, 10, rustls_wrap(server_cfg))?;
+        Ok(TestServer {
+            data: Arc::new(data),
+            client_config: Some(Arc::new(client_cfg)),
         })
     }
 
     /// Returns a client connected to the `TestServer`. The transport is handled internally.
     pub fn client(&self) -> TestClient<Self, TestConnect> {
-        self.data.client(self)
+        self.client_with_config(&Client::builder())
+    }
+
+    /// Returns a client connected to the `TestServer`, built by `builder`. This allows tests to
+    /// configure the client, e.g. to use HTTP/2 or to change its connection pool settings.
+    pub fn client_with_config(&self, builder: &client::Builder) -> TestClient<Self, TestConnect> {
+        let connect = TestConnect {
+            addr: self.data.addr,
+            config: self.client_config(),
+        };
+        self.data.client_with(self, connect, builder)
+    }
+
+    /// Returns the address the `TestServer` is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.data.addr
+    }
+
+    /// Returns the `ClientConfig` trusting the certificate of this `TestServer`, e.g. for
+    /// connecting to it with another HTTP client.
+    pub fn client_config(&self) -> Arc<ClientConfig> {
+        match &self.client_config {
+            Some(config) => config.clone(),
+            None => TestConnect::from(self.data.addr).config,
+        }
     }
 
     /// Spawns the given future on the `TestServer`'s internal runtime.
@@ -279,13 +350,8 @@ impl Service<Uri> for TestConnect {
 
 impl From<SocketAddr> for TestConnect {
     fn from(addr: SocketAddr) -> Self {
-        let mut root_store = RootCertStore::empty();
-        let ca_cert = include_bytes!("tls_ca_cert.der").to_vec();
-        root_store.add(&Certificate(ca_cert)).unwrap();
-        let cfg = ClientConfig::builder()
-            .with_safe_defaults()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+        let ca_cert = Certificate(include_bytes!("tls_ca_cert.der").to_vec());
+        let cfg = client_config(&ca_cert).unwrap();
 
         Self {
             addr,
@@ -335,6 +401,23 @@ mod tests {
         assert_eq!(42, server.run_future(run_receiver).unwrap());
     }
 
+    #[cfg(feature = "rcgen")]
+    #[test]
+    fn test_server_serves_requests_with_self_signed_cert() {
+        let server = TestServer::with_self_signed_cert(TestHandler::from("hello")).unwrap();
+        let response = server
+            .client()
+            .get("https://example.com/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "hello");
+
+        // the bundled CA does not trust the generated certificate
+        let untrusted: TestClient<_, TestConnect> = server.data.client(&server);
+        assert!(untrusted.get("https://example.com/").perform().is_err());
+    }
+
     #[test]
     fn test_server_adds_client_address_to_state() {
         test::common_tests::adds_client_address_to_state(TestServer::new, TestServer::client);
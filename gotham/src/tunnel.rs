@@ -0,0 +1,393 @@
+//! Support for `CONNECT` tunnels, e.g. for forward proxies.
+//!
+//! A route terminated with `to_tunnel` answers `CONNECT` requests by letting a `TunnelHandler`
+//! open the connection to the authority (`host:port`) the client asked for. Once it is open, the
+//! request is answered with `200 OK` and the client's connection is handed to the handler as a
+//! raw duplex stream, along with the opened connection. If it cannot be opened, the request is
+//! answered with `502 Bad Gateway` instead.
+//!
+//! `Forward` is a `TunnelHandler` which connects to the requested authority over TCP and relays
+//! data in both directions. It only connects to the authorities on its allow-list, so that the
+//! route is not an open proxy.
+//!
+//! The request target of a `CONNECT` request has no path, so tunnel routes are matched as the
+//! root path `/`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use gotham::router::builder::*;
+//! use gotham::tunnel::Forward;
+//!
+//! # fn main() {
+//! let router = build_simple_router(|route| {
+//!     route
+//!         .connect("/")
+//!         .to_tunnel(Forward::new(["api.example.com:443", "db.internal:5432"]));
+//! });
+//! # let _ = router;
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::{self, FutureExt};
+use hyper::http::uri::Authority;
+use hyper::upgrade::Upgraded;
+use hyper::{Method, StatusCode, Uri};
+use log::debug;
+use tokio::io::copy_bidirectional;
+use tokio::net::TcpStream;
+
+use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use crate::helpers::http::response::create_empty_response;
+use crate::state::{request_id, FromState, State};
+use crate::upgrade::spawn_upgraded;
+
+/// Handles the connections of `CONNECT` tunnels established on a route.
+pub trait TunnelHandler: Clone + Send + Sync + RefUnwindSafe + 'static {
+    /// The connection the tunnel leads to, e.g. a `TcpStream`.
+    type Upstream: Send + 'static;
+
+    /// Invoked with the `State` of the `CONNECT` request before the tunnel is established.
+    /// Returning an error rejects the request, e.g. for authorization or to restrict the
+    /// destinations a proxy connects to.
+    fn accept(&self, _state: &mut State, _authority: &Authority) -> Result<(), HandlerError> {
+        Ok(())
+    }
+
+    /// Opens the connection to `authority` before the `CONNECT` request is answered. The request
+    /// is answered with `502 Bad Gateway` if this fails.
+    fn open(
+        &self,
+        authority: Authority,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Self::Upstream>> + Send>>;
+
+    /// Invoked with the opened connection and the client's connection once the tunnel has been
+    /// established. The connection is closed when the returned future completes and the
+    /// `Upgraded` stream has been dropped.
+    fn connected(
+        self,
+        upstream: Self::Upstream,
+        io: Upgraded,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// A `TunnelHandler` which connects to the requested authority over TCP, and relays data between
+/// it and the client until either side closes its connection.
+///
+/// Only the authorities on the allow-list are connected to. `CONNECT` requests for any other
+/// destination are rejected with `403 Forbidden`.
+#[derive(Clone, Debug)]
+pub struct Forward {
+    allowed: Arc<HashSet<String>>,
+}
+
+impl Forward {
+    /// Creates a new `Forward` handler, which only connects to the given authorities
+    /// (`host:port`). Hosts are compared case-insensitively.
+    pub fn new<I>(allowed: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let allowed = allowed
+            .into_iter()
+            .map(|authority| authority.as_ref().to_ascii_lowercase())
+            .collect();
+        Forward {
+            allowed: Arc::new(allowed),
+        }
+    }
+
+    /// Returns `true` if `authority` is on the allow-list.
+    pub fn allows(&self, authority: &Authority) -> bool {
+        self.allowed
+            .contains(&authority.as_str().to_ascii_lowercase())
+    }
+}
+
+impl TunnelHandler for Forward {
+    type Upstream = (Authority, TcpStream);
+
+    fn accept(&self, _state: &mut State, authority: &Authority) -> Result<(), HandlerError> {
+        if self.allows(authority) {
+            Ok(())
+        } else {
+            let err = anyhow::anyhow!("tunnel to {} is not allowed", authority);
+            Err(HandlerError::from(err).with_status(StatusCode::FORBIDDEN))
+        }
+    }
+
+    fn open(
+        &self,
+        authority: Authority,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Self::Upstream>> + Send>> {
+        async move {
+            let port = authority.port_u16().unwrap_or(80);
+            let upstream = TcpStream::connect((authority.host(), port)).await?;
+            Ok((authority, upstream))
+        }
+        .boxed()
+    }
+
+    fn connected(
+        self,
+        (authority, mut upstream): Self::Upstream,
+        mut io: Upgraded,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        async move {
+            match copy_bidirectional(&mut io, &mut upstream).await {
+                Ok((sent, received)) => debug!(
+                    "tunnel to {} closed after sending {} and receiving {} bytes",
+                    authority, sent, received
+                ),
+                Err(err) => debug!("tunnel to {} failed: {}", authority, err),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// The `Handler` created for routes terminated with `to_tunnel`.
+#[derive(Clone)]
+pub struct TunnelRoute<H> {
+    handler: H,
+}
+
+impl<H: TunnelHandler> TunnelRoute<H> {
+    /// Creates a new `TunnelRoute`, passing established tunnels to `handler`.
+    pub fn new(handler: H) -> Self {
+        TunnelRoute { handler }
+    }
+}
+
+impl<H: TunnelHandler> NewHandler for TunnelRoute<H> {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<H: TunnelHandler> Handler for TunnelRoute<H> {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        if *Method::borrow_from(&state) != Method::CONNECT {
+            let response = create_empty_response(&state, StatusCode::METHOD_NOT_ALLOWED);
+            return future::ok((state, response)).boxed();
+        }
+
+        let authority = match Uri::borrow_from(&state).authority() {
+            Some(authority) => authority.clone(),
+            None => {
+                let response = create_empty_response(&state, StatusCode::BAD_REQUEST);
+                return future::ok((state, response)).boxed();
+            }
+        };
+
+        if let Err(err) = self.handler.accept(&mut state, &authority) {
+            return future::err((state, err)).boxed();
+        }
+
+        let handler = self.handler;
+        async move {
+            let upstream = match handler.open(authority.clone()).await {
+                Ok(upstream) => upstream,
+                Err(err) => {
+                    let id = request_id(&state);
+                    debug!(
+                        "[{}] unable to connect tunnel to {}: {}",
+                        id, authority, err
+                    );
+                    let response = create_empty_response(&state, StatusCode::BAD_GATEWAY);
+                    return Ok((state, response));
+                }
+            };
+
+            let upgraded = spawn_upgraded(&mut state, move |io| handler.connected(upstream, io));
+            if let Err(err) = upgraded {
+                return Err((state, err));
+            }
+
+            let response = create_empty_response(&state, StatusCode::OK);
+            Ok((state, response))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Clone)]
+    struct Greeter;
+
+    impl TunnelHandler for Greeter {
+        type Upstream = Authority;
+
+        fn accept(&self, _state: &mut State, authority: &Authority) -> Result<(), HandlerError> {
+            if authority.host() == "forbidden.example" {
+                Err(HandlerError::from(anyhow::anyhow!("forbidden"))
+                    .with_status(StatusCode::FORBIDDEN))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn open(
+            &self,
+            authority: Authority,
+        ) -> Pin<Box<dyn Future<Output = io::Result<Authority>> + Send>> {
+            future::ok(authority).boxed()
+        }
+
+        fn connected(
+            self,
+            authority: Authority,
+            mut io: Upgraded,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            async move {
+                let greeting = format!("hello {}", authority);
+                io.write_all(greeting.as_bytes()).await.unwrap();
+            }
+            .boxed()
+        }
+    }
+
+    async fn serve(router: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            crate::bind_server(listener, router, future::ok).await;
+        });
+        addr
+    }
+
+    // Sends a CONNECT request for `authority`, returning the response head and the connection.
+    async fn connect(addr: SocketAddr, authority: &str) -> (String, TcpStream) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", authority);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        (String::from_utf8(head).unwrap(), stream)
+    }
+
+    #[tokio::test]
+    async fn hands_connection_to_handler() {
+        let addr = serve(build_simple_router(|route| {
+            route.connect("/").to_tunnel(Greeter);
+        }))
+        .await;
+
+        let (head, mut stream) = connect(addr, "example.com:443").await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting).await.unwrap();
+        assert_eq!(greeting, "hello example.com:443");
+    }
+
+    #[tokio::test]
+    async fn rejects_unaccepted_tunnels() {
+        let addr = serve(build_simple_router(|route| {
+            route.connect("/").to_tunnel(Greeter);
+        }))
+        .await;
+
+        let (head, _) = connect(addr, "forbidden.example:443").await;
+        assert!(head.starts_with("HTTP/1.1 403"));
+    }
+
+    #[tokio::test]
+    async fn forwards_to_upstream() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            socket.read_exact(&mut buf).await.unwrap();
+            socket.write_all(&buf).await.unwrap();
+        });
+
+        let addr = serve(build_simple_router(|route| {
+            route
+                .connect("/")
+                .to_tunnel(Forward::new([upstream_addr.to_string()]));
+        }))
+        .await;
+
+        let (head, mut stream) = connect(addr, &upstream_addr.to_string()).await;
+        assert!(head.starts_with("HTTP/1.1 200"));
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test]
+    async fn forwards_only_to_allowed_authorities() {
+        let addr = serve(build_simple_router(|route| {
+            route
+                .connect("/")
+                .to_tunnel(Forward::new(["Example.com:443"]));
+        }))
+        .await;
+
+        let (head, _) = connect(addr, "other.example:443").await;
+        assert!(head.starts_with("HTTP/1.1 403"));
+        let (head, _) = connect(addr, "example.com:80").await;
+        assert!(head.starts_with("HTTP/1.1 403"));
+        assert!(Forward::new(["Example.com:443"]).allows(&"example.COM:443".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn answers_bad_gateway_when_upstream_is_unreachable() {
+        // nothing listens on the address once the listener is dropped
+        let upstream_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let addr = serve(build_simple_router(|route| {
+            route
+                .connect("/")
+                .to_tunnel(Forward::new([upstream_addr.to_string()]));
+        }))
+        .await;
+
+        let (head, _) = connect(addr, &upstream_addr.to_string()).await;
+        assert!(head.starts_with("HTTP/1.1 502"));
+    }
+
+    #[test]
+    fn rejects_other_methods() {
+        let router = build_simple_router(|route| {
+            route.get("/").to_tunnel(Forward::new(["localhost:443"]));
+        });
+        let test_server = crate::test::TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -0,0 +1,123 @@
+//! Serving a Gotham application on a Unix domain socket, e.g. behind a reverse proxy on the same
+//! host.
+//!
+//! Connections over a Unix domain socket have no IP address, so `gotham::state::client_addr`
+//! returns `None` for their requests. Instead, the credentials of the connected process are placed
+//! into the `State` of every request as `PeerCredentials`, where the platform supports retrieving
+//! them.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use hyper::server::conn::Http;
+use log::info;
+use tokio::net::unix::{gid_t, pid_t, uid_t};
+use tokio::net::UnixListener;
+use tokio::sync::watch;
+
+use super::handler::NewHandler;
+use super::service::GothamService;
+use super::{new_runtime, serve_connection, ServerOptions, StartError};
+use crate::state::StateData;
+
+#[cfg(feature = "testing")]
+pub mod test;
+
+/// The credentials of the process on the other end of a Unix domain socket connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PeerCredentials {
+    uid: uid_t,
+    gid: gid_t,
+    pid: Option<pid_t>,
+}
+
+impl StateData for PeerCredentials {}
+
+impl PeerCredentials {
+    /// Returns the user ID of the connected process.
+    pub fn uid(&self) -> uid_t {
+        self.uid
+    }
+
+    /// Returns the group ID of the connected process.
+    pub fn gid(&self) -> gid_t {
+        self.gid
+    }
+
+    /// Returns the process ID of the connected process, if the platform provides it.
+    pub fn pid(&self) -> Option<pid_t> {
+        self.pid
+    }
+}
+
+/// Starts a Gotham application listening on the Unix domain socket at `path`, which must not
+/// exist yet.
+pub fn start<NH, P>(path: P, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    P: AsRef<Path>,
+{
+    let runtime = new_runtime(num_cpus::get());
+    runtime.block_on(init_server(path, new_handler))
+}
+
+/// Returns a `Future` used to spawn a Gotham application listening on the Unix domain socket at
+/// `path`, which must not exist yet.
+pub async fn init_server<NH, P>(path: P, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    P: AsRef<Path>,
+{
+    let listener = UnixListener::bind(path.as_ref())?;
+
+    info! {
+        target: "gotham::start",
+        " Gotham listening on {}", path.as_ref().display()
+    }
+
+    bind_server(listener, new_handler).await
+}
+
+/// Returns a `Future` used to spawn a Gotham application on connections accepted by `listener`.
+pub async fn bind_server<NH>(listener: UnixListener, new_handler: NH) -> !
+where
+    NH: NewHandler + 'static,
+{
+    let protocol = Arc::new(Http::new());
+    let gotham_service = GothamService::new(new_handler);
+    // the server is never shut down
+    let (_shutdown, shutdown_rx) = watch::channel(false);
+
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                log::error!("Socket Error: {}", err);
+                continue;
+            }
+        };
+
+        let mut service = gotham_service.connect_from(None);
+        match peer_credentials(&socket) {
+            Ok(peer_credentials) => service = service.with_peer_credentials(peer_credentials),
+            Err(err) => log::debug!("unable to retrieve peer credentials: {}", err),
+        }
+
+        let accepted_protocol = protocol.clone();
+        let shutdown = shutdown_rx.clone();
+        tokio::spawn(async move {
+            let options = ServerOptions::new();
+            serve_connection(&accepted_protocol, socket, service, options, shutdown).await
+        });
+    }
+}
+
+fn peer_credentials(socket: &tokio::net::UnixStream) -> io::Result<PeerCredentials> {
+    let cred = socket.peer_cred()?;
+    Ok(PeerCredentials {
+        uid: cred.uid(),
+        gid: cred.gid(),
+        pid: cred.pid(),
+    })
+}
@@ -0,0 +1,291 @@
+//! Contains helpers for testing Gotham applications served on a Unix domain socket.
+//!
+//! See the [`TestServer`] type for example usage.
+
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::future::{BoxFuture, FutureExt};
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::{self, Client};
+use hyper::service::Service;
+use hyper::Uri;
+use log::info;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::runtime::Runtime;
+use tokio::time::{sleep, Sleep};
+use uuid::Uuid;
+
+use crate::handler::NewHandler;
+use crate::test::{self, TestClient};
+
+/// The `TestServer` type, which is used as a harness when writing test cases for code specific to
+/// Unix domain sockets, e.g. middleware checking `PeerCredentials`. The server listens on a socket
+/// in the temporary directory, which is removed when the last clone of the server is dropped.
+///
+/// # Examples
+///
+/// ```rust
+/// # use gotham::state::{FromState, State};
+/// use gotham::unix::test::TestServer;
+/// use gotham::unix::PeerCredentials;
+///
+/// fn whoami(state: State) -> (State, String) {
+///     let uid = PeerCredentials::borrow_from(&state).uid();
+///     (state, uid.to_string())
+/// }
+///
+/// # fn main() {
+/// let test_server = TestServer::new(|| Ok(whoami)).unwrap();
+/// let response = test_server.client().get("http://localhost/").perform().unwrap();
+///
+/// assert!(response.read_utf8_body().unwrap().parse::<u32>().is_ok());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TestServer {
+    data: Arc<TestServerData>,
+}
+
+struct TestServerData {
+    path: PathBuf,
+    timeout: u64,
+    // only `None` while dropping
+    runtime: RwLock<Option<Runtime>>,
+}
+
+impl Drop for TestServerData {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which is not allowed within async tests.
+        if let Some(runtime) = self.runtime.get_mut().ok().and_then(Option::take) {
+            runtime.shutdown_background();
+        }
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            log::warn!("unable to remove {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+impl test::Server for TestServer {
+    fn run_future<F, O>(&self, future: F) -> O
+    where
+        F: Future<Output = O>,
+    {
+        self.data
+            .runtime
+            .write()
+            .expect("unable to acquire write lock")
+            .as_ref()
+            .expect("runtime dropped")
+            .block_on(future)
+    }
+
+    fn request_expiry(&self) -> Sleep {
+        let runtime = self.data.runtime.read().unwrap();
+        let _guard = runtime.as_ref().expect("runtime dropped").enter();
+        sleep(Duration::from_secs(self.data.timeout))
+    }
+}
+
+impl TestServer {
+    /// Creates a `TestServer` instance for the `Handler` spawned by `new_handler`, listening on a
+    /// new Unix domain socket.
+    ///
+    /// Timeout will be set to 10 seconds.
+    pub fn new<NH: NewHandler + 'static>(new_handler: NH) -> anyhow::Result<TestServer> {
+        TestServer::with_timeout(new_handler, 10)
+    }
+
+    /// Sets the request timeout to `timeout` seconds and returns a new `TestServer`.
+    pub fn with_timeout<NH: NewHandler + 'static>(
+        new_handler: NH,
+        timeout: u64,
+    ) -> anyhow::Result<TestServer> {
+        let path = std::env::temp_dir().join(format!("gotham-{}.sock", Uuid::new_v4().simple()));
+
+        let runtime = Runtime::new()?;
+        // Binding synchronously doesn't block on the runtime, so servers can be created within
+        // async tests.
+        let listener = std::os::unix::net::UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let listener = {
+            let _guard = runtime.enter();
+            UnixListener::from_std(listener)?
+        };
+        runtime.spawn(super::bind_server(listener, new_handler));
+
+        Ok(TestServer {
+            data: Arc::new(TestServerData {
+                path,
+                timeout,
+                runtime: RwLock::new(Some(runtime)),
+            }),
+        })
+    }
+
+    /// Returns the path of the socket the `TestServer` is listening on. Tests can use this to
+    /// change the permissions of the socket, or to connect to it directly.
+    pub fn path(&self) -> &Path {
+        &self.data.path
+    }
+
+    /// Returns a client connected to the `TestServer`. The transport is handled internally.
+    pub fn client(&self) -> TestClient<Self, TestConnect> {
+        self.client_with_config(&Client::builder())
+    }
+
+    /// Returns a client connected to the `TestServer`, built by `builder`.
+    pub fn client_with_config(&self, builder: &client::Builder) -> TestClient<Self, TestConnect> {
+        let connect = TestConnect {
+            path: self.data.path.clone(),
+        };
+        TestClient::new(builder.build(connect), self.clone())
+    }
+
+    /// Spawns the given future on the `TestServer`'s internal runtime.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.data
+            .runtime
+            .write()
+            .expect("unable to acquire read lock")
+            .as_ref()
+            .expect("runtime dropped")
+            .spawn(future);
+    }
+}
+
+/// `TestConnect` represents the connection between a test client and the `TestServer` instance
+/// that created it. This type should never be used directly.
+#[derive(Clone)]
+pub struct TestConnect {
+    path: PathBuf,
+}
+
+impl Service<Uri> for TestConnect {
+    type Response = TestConnection;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, _req: Uri) -> Self::Future {
+        UnixStream::connect(self.path.clone())
+            .inspect(|s| info!("Client UnixStream connected: {:?}", s))
+            .map(|stream| stream.map(TestConnection))
+            .boxed()
+    }
+}
+
+/// A connection from a test client to a `TestServer`. This type should never be used directly.
+#[derive(Debug)]
+pub struct TestConnection(UnixStream);
+
+impl Connection for TestConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for TestConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TestConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{client_addr, FromState, State};
+    use crate::test::common_tests;
+    use crate::unix::PeerCredentials;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_server_serves_requests() {
+        common_tests::serves_requests(TestServer::new, TestServer::client)
+    }
+
+    #[test]
+    fn test_server_times_out() {
+        common_tests::times_out(TestServer::with_timeout, TestServer::client)
+    }
+
+    #[test]
+    fn test_server_async_echo() {
+        common_tests::async_echo(TestServer::new, TestServer::client)
+    }
+
+    #[test]
+    fn test_server_supports_multiple_servers() {
+        common_tests::supports_multiple_servers(TestServer::new, TestServer::client)
+    }
+
+    #[test]
+    fn adds_peer_credentials_to_state() {
+        fn handler(state: State) -> (State, String) {
+            let peer = PeerCredentials::borrow_from(&state);
+            let body = format!("{} {:?}", peer.uid(), client_addr(&state));
+            (state, body)
+        }
+
+        let server = TestServer::new(|| Ok(handler)).unwrap();
+        let body = server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap()
+            .read_utf8_body()
+            .unwrap();
+
+        // the socket has been created by this process, so it is owned by the peer's user
+        let owner = std::fs::metadata(server.path()).unwrap().uid();
+        assert_eq!(body, format!("{} None", owner));
+    }
+
+    #[test]
+    fn removes_socket_when_dropped() {
+        fn handler(state: State) -> (State, &'static str) {
+            (state, "Hello")
+        }
+
+        let server = TestServer::new(|| Ok(handler)).unwrap();
+        let path = server.path().to_owned();
+        assert!(path.exists());
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,311 @@
+//! Support for protocol upgrades (`101 Switching Protocols`), for implementing custom protocols
+//! on Gotham routes.
+//!
+//! A route terminated with `to_upgrade` answers requests asking to upgrade to the given protocol
+//! with `101 Switching Protocols`, after which the client's connection is handed to an
+//! `UpgradeHandler` as an `Upgraded` duplex stream. Bytes the client sent after the upgrade
+//! request, which the server may already have buffered, are replayed when reading from it.
+//!
+//! Handlers which need the underlying connection (e.g. a `TcpStream`) can take it apart with
+//! `into_parts`, receiving the buffered bytes separately.
+//!
+//! Handlers which do not fit the route model can use `spawn_upgraded`, `take_upgrade` and
+//! `switching_protocols` directly, as the routes for WebSockets and `CONNECT` tunnels do.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use gotham::router::builder::*;
+//! use gotham::upgrade::Upgraded;
+//! use tokio::io::{copy, split};
+//!
+//! async fn echo(io: Upgraded) {
+//!     let (mut reader, mut writer) = split(io);
+//!     let _ = copy(&mut reader, &mut writer).await;
+//! }
+//!
+//! # fn main() {
+//! let router = build_simple_router(|route| {
+//!     route.get("/echo").to_upgrade("echo", echo);
+//! });
+//! # let _ = router;
+//! # }
+//! ```
+
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+
+use futures_util::future::{self, FutureExt};
+use hyper::header::{HeaderMap, HeaderValue, CONNECTION, UPGRADE};
+use hyper::upgrade::OnUpgrade;
+use hyper::{Body, Response, StatusCode};
+use log::{debug, error};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use crate::helpers::http::response::create_empty_response;
+use crate::state::{request_id, FromState, State};
+
+pub use hyper::upgrade::{Parts, Upgraded};
+
+/// Handles the connections upgraded on a route.
+///
+/// This is implemented for all closures and functions taking the `Upgraded` connection and
+/// returning a `Future`.
+pub trait UpgradeHandler: Clone + Send + Sync + RefUnwindSafe + 'static {
+    /// Invoked with the `State` of the upgrade request before `101 Switching Protocols` is sent.
+    /// Returning an error rejects the upgrade, sending the error's status code instead.
+    fn accept(&self, _state: &mut State) -> Result<(), HandlerError> {
+        Ok(())
+    }
+
+    /// Invoked with the client's connection once it has been upgraded. The connection is closed
+    /// when the returned future completes and the `Upgraded` stream has been dropped.
+    fn connected(self, io: Upgraded) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+impl<F, Fut> UpgradeHandler for F
+where
+    F: FnOnce(Upgraded) -> Fut + Clone + Send + Sync + RefUnwindSafe + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn connected(self, io: Upgraded) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self(io).boxed()
+    }
+}
+
+/// Returns `true` if the request in `State` asks to upgrade to `protocol`, which is compared
+/// case-insensitively against the protocols listed in the `Upgrade` header.
+pub fn requested(state: &State, protocol: &str) -> bool {
+    let headers = match HeaderMap::try_borrow_from(state) {
+        Some(headers) => headers,
+        None => return false,
+    };
+    let listed = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|listed| listed.trim().eq_ignore_ascii_case(token))
+    };
+    listed(CONNECTION, "upgrade") && listed(UPGRADE, protocol)
+}
+
+/// Takes the pending upgrade of the connection out of `State`, resolving to the `Upgraded`
+/// connection once a `101 Switching Protocols` response has been sent. Fails for connections
+/// which cannot be upgraded, e.g. over HTTP/2, and if the upgrade has been taken already.
+pub fn take_upgrade(state: &mut State) -> Result<OnUpgrade, HandlerError> {
+    OnUpgrade::try_take_from(state)
+        .ok_or_else(|| HandlerError::from(anyhow::anyhow!("connection cannot be upgraded")))
+}
+
+/// Takes the pending upgrade of the connection out of `State` like `take_upgrade`, and spawns a
+/// task passing the `Upgraded` connection to `connected` once the response has been sent. Failed
+/// upgrades are logged along with the id of the request.
+pub fn spawn_upgraded<F, Fut>(state: &mut State, connected: F) -> Result<(), HandlerError>
+where
+    F: FnOnce(Upgraded) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let on_upgrade = take_upgrade(state)?;
+    let id = request_id(state).to_owned();
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(io) => connected(io).await,
+            Err(err) => error!("[{}] upgrade failed: {}", id, err),
+        }
+    });
+    Ok(())
+}
+
+/// Creates a `101 Switching Protocols` response, switching to `protocol`.
+pub fn switching_protocols(state: &State, protocol: &str) -> Response<Body> {
+    let mut response = create_empty_response(state, StatusCode::SWITCHING_PROTOCOLS);
+    {
+        let headers = response.headers_mut();
+        headers.insert(UPGRADE, protocol_header(protocol));
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    }
+    response
+}
+
+/// Takes the `Upgraded` connection apart, returning the underlying IO object along with the bytes
+/// which have been read from it but not yet consumed. Fails, returning the connection unchanged,
+/// if `T` is not the type of the underlying IO object. This is a `tokio::net::TcpStream` for
+/// servers started with `gotham::start`, but e.g. a `gotham::throttle::Throttled<TcpStream>` for
+/// servers limiting the bandwidth of connections, or the TLS stream for servers using TLS.
+pub fn into_parts<T>(io: Upgraded) -> Result<Parts<T>, Upgraded>
+where
+    T: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    io.downcast()
+}
+
+fn protocol_header(protocol: &str) -> HeaderValue {
+    HeaderValue::from_str(protocol).expect("valid upgrade protocol")
+}
+
+/// The `Handler` created for routes terminated with `to_upgrade`.
+#[derive(Clone)]
+pub struct UpgradeRoute<H> {
+    protocol: &'static str,
+    handler: H,
+}
+
+impl<H: UpgradeHandler> UpgradeRoute<H> {
+    /// Creates a new `UpgradeRoute`, passing connections upgraded to `protocol` to `handler`.
+    pub fn new(protocol: &'static str, handler: H) -> Self {
+        UpgradeRoute { protocol, handler }
+    }
+}
+
+impl<H: UpgradeHandler> NewHandler for UpgradeRoute<H> {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<H: UpgradeHandler> Handler for UpgradeRoute<H> {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        if !requested(&state, self.protocol) {
+            debug!(
+                "[{}] rejecting request without upgrade to {}",
+                request_id(&state),
+                self.protocol
+            );
+            let mut response = create_empty_response(&state, StatusCode::UPGRADE_REQUIRED);
+            {
+                let headers = response.headers_mut();
+                headers.insert(UPGRADE, protocol_header(self.protocol));
+                headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+            }
+            return future::ok((state, response)).boxed();
+        }
+
+        if let Err(err) = self.handler.accept(&mut state) {
+            return future::err((state, err)).boxed();
+        }
+
+        let handler = self.handler;
+        if let Err(err) = spawn_upgraded(&mut state, move |io| handler.connected(io)) {
+            return future::err((state, err)).boxed();
+        }
+
+        let response = switching_protocols(&state, self.protocol);
+        future::ok((state, response)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::router::Router;
+    use crate::test::TestServer;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn serve(router: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            crate::bind_server(listener, router, future::ok).await;
+        });
+        addr
+    }
+
+    // Sends an upgrade request, followed by `early` data, returning the response head and the
+    // connection.
+    async fn upgrade(addr: SocketAddr, protocol: &str, early: &[u8]) -> (String, TcpStream) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: {}\r\n\r\n",
+            protocol
+        );
+        let mut bytes = request.into_bytes();
+        bytes.extend_from_slice(early);
+        stream.write_all(&bytes).await.unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        (String::from_utf8(head).unwrap().to_lowercase(), stream)
+    }
+
+    async fn reverse(io: Upgraded) {
+        let mut parts = into_parts::<TcpStream>(io).unwrap();
+        let mut line = parts.read_buf.to_vec();
+        while !line.ends_with(b"\n") {
+            line.push(parts.io.read_u8().await.unwrap());
+        }
+        line.pop();
+        line.reverse();
+        parts.io.write_all(&line).await.unwrap();
+    }
+
+    #[test]
+    fn detects_upgrade_requests() {
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, HeaderValue::from_static("keep-alive, Upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("h2c, Reverse"));
+        state.put(headers);
+
+        assert!(requested(&state, "reverse"));
+        assert!(!requested(&state, "websocket"));
+    }
+
+    #[tokio::test]
+    async fn hands_connection_to_handler() {
+        let addr = serve(build_simple_router(|route| {
+            route.get("/").to_upgrade("reverse", reverse);
+        }))
+        .await;
+
+        let (head, mut stream) = upgrade(addr, "reverse", b"").await;
+        assert!(head.starts_with("http/1.1 101"));
+        assert!(head.contains("upgrade: reverse\r\n"));
+
+        stream.write_all(b"hello\n").await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "olleh");
+    }
+
+    #[tokio::test]
+    async fn passes_on_buffered_bytes() {
+        let addr = serve(build_simple_router(|route| {
+            route.get("/").to_upgrade("reverse", reverse);
+        }))
+        .await;
+
+        let (head, mut stream) = upgrade(addr, "reverse", b"early\n").await;
+        assert!(head.starts_with("http/1.1 101"));
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "ylrae");
+    }
+
+    #[test]
+    fn rejects_requests_without_upgrade() {
+        let router = build_simple_router(|route| {
+            route.get("/").to_upgrade("reverse", reverse);
+        });
+        let test_server = TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(response.headers()[UPGRADE], "reverse");
+    }
+}
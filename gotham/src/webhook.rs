@@ -0,0 +1,737 @@
+//! Delivery of outbound webhooks, with signing, retries and status tracking.
+//!
+//! Webhooks are configured with `Webhooks` and started with `Webhooks::start`, which returns a
+//! `WebhookHandle`. The handle can be added to a pipeline to place it into the `State` of every
+//! request, so handlers can enqueue webhooks and look up the status of earlier deliveries.
+//!
+//! Every delivery is a `POST` request with the payload as `application/json` body, and the
+//! headers `X-Webhook-Id` and `X-Webhook-Event`. If a secret is configured, the payload is signed
+//! with HMAC-SHA256, and the signature is sent as `X-Webhook-Signature: sha256=<hex>`, which the
+//! receiver can check with `verify_signature`. Deliveries which fail, either because no response
+//! was received or the status isn't `2xx`, are retried with exponential backoff until the maximum
+//! number of attempts is reached.
+//!
+//! Deliveries are kept in memory by default, so pending deliveries are lost when the server
+//! stops. Applications can keep them in a database instead by implementing `WebhookStore`, in
+//! which case pending deliveries are resumed when the webhooks are started again.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use gotham::handler::HandlerResult;
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! use gotham::helpers::http::response::create_empty_response;
+//! use gotham::hyper::StatusCode;
+//! use gotham::state::{FromState, State};
+//! use gotham::webhook::{WebhookHandle, Webhooks};
+//!
+//! async fn handler(state: State) -> HandlerResult {
+//!     let webhooks = WebhookHandle::borrow_from(&state).clone();
+//!     let payload = r#"{"order":42}"#;
+//!     let status = match webhooks
+//!         .enqueue("https://example.com/hooks", "order.created", payload)
+//!         .await
+//!     {
+//!         Ok(_) => StatusCode::ACCEPTED,
+//!         Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+//!     };
+//!     let response = create_empty_response(&state, status);
+//!     Ok((state, response))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let webhooks = Webhooks::new().with_secret("s3cr3t").start();
+//!     let (chain, pipelines) = single_pipeline(new_pipeline().add(webhooks).build());
+//!     let router = build_router(chain, pipelines, |route| {
+//!         route.post("/orders").to_async(handler);
+//!     });
+//!     gotham::init_server("127.0.0.1:7878", router).await.unwrap();
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, RefUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::{self, FutureExt};
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Method, Request};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::handler::HandlerFuture;
+use crate::middleware::{Middleware, NewMiddleware};
+use crate::state::{State, StateData};
+
+/// The header carrying the ID of a delivery, which stays the same across retries.
+pub const X_WEBHOOK_ID: &str = "x-webhook-id";
+
+/// The header carrying the event of a delivery.
+pub const X_WEBHOOK_EVENT: &str = "x-webhook-event";
+
+/// The header carrying the signature of the payload, as `sha256=<hex>`.
+pub const X_WEBHOOK_SIGNATURE: &str = "x-webhook-signature";
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_MAX_COMPLETED: usize = 10_000;
+
+/// The ID of a delivery, as returned by `WebhookHandle::enqueue`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DeliveryId(String);
+
+impl DeliveryId {
+    fn new() -> Self {
+        DeliveryId(Uuid::new_v4().to_string())
+    }
+
+    /// Returns the ID as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for DeliveryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The status of a delivery.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum DeliveryStatus {
+    /// The webhook has not been delivered yet, and will be attempted again.
+    Pending {
+        /// The number of attempts made so far.
+        attempts: u32,
+    },
+    /// The webhook was delivered, and the receiver answered with a `2xx` status.
+    Delivered {
+        /// The number of attempts it took.
+        attempts: u32,
+    },
+    /// Every attempt failed, and the webhook won't be attempted again.
+    Failed {
+        /// The number of attempts made.
+        attempts: u32,
+    },
+}
+
+impl DeliveryStatus {
+    /// Returns the number of attempts made so far.
+    pub fn attempts(&self) -> u32 {
+        match *self {
+            DeliveryStatus::Pending { attempts }
+            | DeliveryStatus::Delivered { attempts }
+            | DeliveryStatus::Failed { attempts } => attempts,
+        }
+    }
+
+    /// Checks whether the delivery is still pending.
+    pub fn is_pending(&self) -> bool {
+        matches!(self, DeliveryStatus::Pending { .. })
+    }
+}
+
+/// A webhook and the state of its delivery, as kept in a `WebhookStore`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Delivery {
+    id: DeliveryId,
+    url: String,
+    event: String,
+    payload: Vec<u8>,
+    status: DeliveryStatus,
+    last_error: Option<String>,
+}
+
+impl Delivery {
+    /// Returns the ID of the delivery.
+    pub fn id(&self) -> &DeliveryId {
+        &self.id
+    }
+
+    /// Returns the URL the webhook is sent to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the event of the webhook.
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    /// Returns the payload of the webhook.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Returns the status of the delivery.
+    pub fn status(&self) -> DeliveryStatus {
+        self.status
+    }
+
+    /// Returns the reason the last attempt failed, if any.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+}
+
+/// Type alias for the trait objects returned by `WebhookStore`.
+pub type StoreFuture<T> = dyn Future<Output = anyhow::Result<T>> + Send;
+
+/// A `WebhookStore` keeps the deliveries and their status, e.g. in memory or in a database.
+pub trait WebhookStore: Send + Sync + RefUnwindSafe {
+    /// Saves a delivery, replacing an earlier version with the same ID.
+    fn save(&self, delivery: &Delivery) -> Pin<Box<StoreFuture<()>>>;
+
+    /// Loads the delivery with the given ID.
+    fn load(&self, id: &DeliveryId) -> Pin<Box<StoreFuture<Option<Delivery>>>>;
+
+    /// Returns the deliveries which are still pending, to resume them when the webhooks are
+    /// started.
+    fn pending(&self) -> Pin<Box<StoreFuture<Vec<Delivery>>>>;
+}
+
+#[derive(Default)]
+struct Deliveries {
+    by_id: HashMap<DeliveryId, Delivery>,
+    completed: VecDeque<DeliveryId>,
+}
+
+/// A `WebhookStore` keeping the deliveries in memory.
+///
+/// Completed deliveries are kept for inspection until the store holds more than `max_completed`
+/// of them, at which point the oldest ones are dropped.
+pub struct MemoryStore {
+    max_completed: usize,
+    deliveries: Mutex<Deliveries>,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        MemoryStore {
+            max_completed: DEFAULT_MAX_COMPLETED,
+            deliveries: Mutex::new(Deliveries::default()),
+        }
+    }
+
+    /// Sets the number of completed deliveries which are kept (defaults to 10,000).
+    pub fn with_max_completed(mut self, max_completed: usize) -> Self {
+        self.max_completed = max_completed;
+        self
+    }
+
+    fn insert(&self, delivery: &Delivery) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        if !delivery.status.is_pending() {
+            deliveries.completed.push_back(delivery.id.clone());
+        }
+        deliveries
+            .by_id
+            .insert(delivery.id.clone(), delivery.clone());
+        while deliveries.completed.len() > self.max_completed {
+            if let Some(id) = deliveries.completed.pop_front() {
+                deliveries.by_id.remove(&id);
+            }
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new()
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("max_completed", &self.max_completed)
+            .field("len", &self.deliveries.lock().unwrap().by_id.len())
+            .finish()
+    }
+}
+
+impl WebhookStore for MemoryStore {
+    fn save(&self, delivery: &Delivery) -> Pin<Box<StoreFuture<()>>> {
+        self.insert(delivery);
+        future::ok(()).boxed()
+    }
+
+    fn load(&self, id: &DeliveryId) -> Pin<Box<StoreFuture<Option<Delivery>>>> {
+        let delivery = self.deliveries.lock().unwrap().by_id.get(id).cloned();
+        future::ok(delivery).boxed()
+    }
+
+    fn pending(&self) -> Pin<Box<StoreFuture<Vec<Delivery>>>> {
+        let pending = self
+            .deliveries
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .filter(|delivery| delivery.status.is_pending())
+            .cloned()
+            .collect();
+        future::ok(pending).boxed()
+    }
+}
+
+/// Configures the delivery of webhooks, before it is started with `Webhooks::start`.
+pub struct Webhooks {
+    secret: Option<Vec<u8>>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Duration,
+    concurrency: usize,
+    store: Arc<dyn WebhookStore>,
+}
+
+impl Webhooks {
+    /// Creates a new configuration, which keeps deliveries in memory and doesn't sign payloads.
+    pub fn new() -> Self {
+        Webhooks {
+            secret: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            timeout: Duration::from_secs(10),
+            concurrency: 16,
+            store: Arc::new(MemoryStore::new()),
+        }
+    }
+
+    /// Signs payloads with the given secret, which the receivers must know to verify them.
+    pub fn with_secret<S: AsRef<[u8]>>(mut self, secret: S) -> Self {
+        self.secret = Some(secret.as_ref().to_vec());
+        self
+    }
+
+    /// Sets the number of attempts after which a delivery fails (defaults to 5).
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry, which doubles with every further retry up to
+    /// `max` (defaults to 1 second and 5 minutes).
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Sets the time to wait for the response of a receiver (defaults to 10 seconds).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of requests sent at the same time (defaults to 16).
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Keeps the deliveries in the given store.
+    pub fn with_store<S>(mut self, store: S) -> Self
+    where
+        S: WebhookStore + 'static,
+    {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Starts delivering webhooks on the current runtime, resuming the pending deliveries of the
+    /// store.
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a tokio runtime.
+    pub fn start(self) -> WebhookHandle {
+        let handle = WebhookHandle {
+            shared: AssertUnwindSafe(Arc::new(Shared {
+                runtime: Handle::current(),
+                client: Client::new(),
+                permits: Semaphore::new(self.concurrency),
+                secret: self.secret,
+                max_attempts: self.max_attempts,
+                initial_backoff: self.initial_backoff,
+                max_backoff: self.max_backoff,
+                timeout: self.timeout,
+                store: self.store,
+                resuming: Mutex::new(Some(HashSet::new())),
+            })),
+        };
+
+        let shared = handle.shared.0.clone();
+        handle.shared.runtime.spawn(async move {
+            let pending = shared.store.pending().await;
+            // deliveries enqueued meanwhile may be pending already, but are delivered by `enqueue`
+            let enqueued = shared.resuming.lock().unwrap().take().unwrap_or_default();
+            match pending {
+                Ok(pending) => {
+                    for delivery in pending {
+                        if enqueued.contains(&delivery.id) {
+                            continue;
+                        }
+                        debug!("resuming webhook delivery {}", delivery.id);
+                        shared.runtime.spawn(deliver(shared.clone(), delivery));
+                    }
+                }
+                Err(err) => error!("failed to load pending webhook deliveries: {:#}", err),
+            }
+        });
+        handle
+    }
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Webhooks::new()
+    }
+}
+
+struct Shared {
+    runtime: Handle,
+    client: Client<HttpConnector, Body>,
+    permits: Semaphore,
+    secret: Option<Vec<u8>>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Duration,
+    store: Arc<dyn WebhookStore>,
+    // The IDs of the deliveries enqueued while the pending deliveries of the store are loaded,
+    // which are not resumed. `None` once the pending deliveries were resumed.
+    resuming: Mutex<Option<HashSet<DeliveryId>>>,
+}
+
+impl Shared {
+    // Returns the delay before the given attempt, which is at least the second one.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(2));
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    async fn send(&self, delivery: &Delivery) -> Result<(), String> {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(delivery.url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .header(X_WEBHOOK_ID, delivery.id.as_str())
+            .header(X_WEBHOOK_EVENT, delivery.event.as_str());
+        if let Some(secret) = &self.secret {
+            builder = builder.header(X_WEBHOOK_SIGNATURE, sign(secret, &delivery.payload));
+        }
+        let request = builder
+            .body(Body::from(delivery.payload.clone()))
+            .map_err(|err| format!("invalid request: {}", err))?;
+
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|err| err.to_string())?;
+        match tokio::time::timeout(self.timeout, self.client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => Ok(()),
+            Ok(Ok(response)) => Err(format!("receiver answered with {}", response.status())),
+            Ok(Err(err)) => Err(format!("request failed: {}", err)),
+            Err(_) => Err(format!("request timed out after {:?}", self.timeout)),
+        }
+    }
+}
+
+// Attempts the delivery until it succeeds or the maximum number of attempts is reached, saving
+// its status after every attempt.
+async fn deliver(shared: Arc<Shared>, mut delivery: Delivery) {
+    loop {
+        let attempts = delivery.status.attempts() + 1;
+        if attempts > 1 {
+            tokio::time::sleep(shared.backoff(attempts)).await;
+        }
+
+        delivery.status = match shared.send(&delivery).await {
+            Ok(()) => {
+                debug!("delivered webhook {} to {}", delivery.id, delivery.url);
+                delivery.last_error = None;
+                DeliveryStatus::Delivered { attempts }
+            }
+            Err(err) if attempts >= shared.max_attempts => {
+                warn!(
+                    "giving up on webhook {} to {} after {} attempts: {}",
+                    delivery.id, delivery.url, attempts, err
+                );
+                delivery.last_error = Some(err);
+                DeliveryStatus::Failed { attempts }
+            }
+            Err(err) => {
+                debug!(
+                    "attempt {} of webhook {} to {} failed: {}",
+                    attempts, delivery.id, delivery.url, err
+                );
+                delivery.last_error = Some(err);
+                DeliveryStatus::Pending { attempts }
+            }
+        };
+
+        if let Err(err) = shared.store.save(&delivery).await {
+            error!("failed to save webhook delivery {}: {:#}", delivery.id, err);
+        }
+        if !delivery.status.is_pending() {
+            return;
+        }
+    }
+}
+
+/// A handle for enqueuing webhooks and inspecting their delivery, which can be cloned freely.
+///
+/// `WebhookHandle` is also a `Middleware`, which places the handle into the `State` of every
+/// request.
+pub struct WebhookHandle {
+    // The runtime handle and client are not `RefUnwindSafe`, but only accessed through tokio
+    // and hyper.
+    shared: AssertUnwindSafe<Arc<Shared>>,
+}
+
+impl Clone for WebhookHandle {
+    fn clone(&self) -> Self {
+        WebhookHandle {
+            shared: AssertUnwindSafe(self.shared.0.clone()),
+        }
+    }
+}
+
+impl WebhookHandle {
+    /// Enqueues a webhook with the given event and JSON payload, which is sent to `url` in the
+    /// background. Resolves to the ID of the delivery once it was saved to the store.
+    pub async fn enqueue<U, E, P>(&self, url: U, event: E, payload: P) -> anyhow::Result<DeliveryId>
+    where
+        U: Into<String>,
+        E: Into<String>,
+        P: Into<Vec<u8>>,
+    {
+        let delivery = Delivery {
+            id: DeliveryId::new(),
+            url: url.into(),
+            event: event.into(),
+            payload: payload.into(),
+            status: DeliveryStatus::Pending { attempts: 0 },
+            last_error: None,
+        };
+        if let Some(enqueued) = self.shared.resuming.lock().unwrap().as_mut() {
+            enqueued.insert(delivery.id.clone());
+        }
+        self.shared.store.save(&delivery).await?;
+
+        let id = delivery.id.clone();
+        self.shared
+            .runtime
+            .spawn(deliver(self.shared.0.clone(), delivery));
+        Ok(id)
+    }
+
+    /// Returns the delivery with the given ID, if the store still holds it.
+    pub async fn delivery(&self, id: &DeliveryId) -> anyhow::Result<Option<Delivery>> {
+        self.shared.store.load(id).await
+    }
+
+    /// Returns the status of the delivery with the given ID, if the store still holds it.
+    pub async fn status(&self, id: &DeliveryId) -> anyhow::Result<Option<DeliveryStatus>> {
+        Ok(self.delivery(id).await?.map(|delivery| delivery.status))
+    }
+}
+
+impl StateData for WebhookHandle {}
+
+impl NewMiddleware for WebhookHandle {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Middleware for WebhookHandle {
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        state.put(self);
+        chain(state)
+    }
+}
+
+/// Signs a payload with HMAC-SHA256, returning the value of the `X-Webhook-Signature` header.
+pub fn sign(secret: &[u8], payload: &[u8]) -> String {
+    hmac_sha256(secret, payload)
+        .iter()
+        .fold(String::from("sha256="), |mut s, b| {
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+}
+
+/// Checks the `X-Webhook-Signature` header of a received webhook against its payload. The
+/// signatures are compared in constant time.
+pub fn verify_signature(secret: &[u8], payload: &[u8], signature: &str) -> bool {
+    let expected = sign(secret, payload);
+    expected.len() == signature.len()
+        && expected
+            .bytes()
+            .zip(signature.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// HMAC as specified in RFC 2104, with the 64 byte blocks of SHA-256.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server, StatusCode};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    // Starts a server which fails the first `failures` requests, and records the signature and
+    // body of the requests it accepts.
+    fn receiver(failures: usize, received: Received) -> SocketAddr {
+        let count = Arc::new(AtomicUsize::new(0));
+        let make_service = make_service_fn(move |_| {
+            let count = count.clone();
+            let received = received.clone();
+            future::ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let count = count.clone();
+                let received = received.clone();
+                async move {
+                    if count.fetch_add(1, Ordering::SeqCst) < failures {
+                        let mut response = Response::new(Body::empty());
+                        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                        return Ok::<_, Infallible>(response);
+                    }
+                    let signature = req.headers()[X_WEBHOOK_SIGNATURE]
+                        .to_str()
+                        .unwrap()
+                        .to_owned();
+                    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                    received.lock().unwrap().push((signature, body.to_vec()));
+                    Ok(Response::new(Body::empty()))
+                }
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    async fn completed(webhooks: &WebhookHandle, id: &DeliveryId) -> Delivery {
+        loop {
+            let delivery = webhooks.delivery(id).await.unwrap().unwrap();
+            if !delivery.status().is_pending() {
+                return delivery;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[test]
+    fn signs_payloads() {
+        // test case 2 of RFC 4231
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let signature = sign(b"secret", b"{}");
+        assert!(verify_signature(b"secret", b"{}", &signature));
+        assert!(!verify_signature(b"other", b"{}", &signature));
+        assert!(!verify_signature(b"secret", b"{ }", &signature));
+        assert!(!verify_signature(b"secret", b"{}", "sha256="));
+    }
+
+    #[tokio::test]
+    async fn retries_deliveries() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let addr = receiver(2, received.clone());
+        let webhooks = Webhooks::new()
+            .with_secret("secret")
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(10))
+            .start();
+
+        let id = webhooks
+            .enqueue(
+                format!("http://{}/", addr),
+                "order.created",
+                r#"{"order":1}"#,
+            )
+            .await
+            .unwrap();
+        let delivery = completed(&webhooks, &id).await;
+        assert_eq!(delivery.status(), DeliveryStatus::Delivered { attempts: 3 });
+        assert_eq!(delivery.last_error(), None);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].1, br#"{"order":1}"#);
+        assert!(verify_signature(b"secret", &received[0].1, &received[0].0));
+    }
+
+    #[tokio::test]
+    async fn fails_deliveries_after_max_attempts() {
+        let addr = receiver(usize::MAX, Arc::new(Mutex::new(Vec::new())));
+        let webhooks = Webhooks::new()
+            .with_max_attempts(2)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .start();
+
+        let id = webhooks
+            .enqueue(format!("http://{}/", addr), "order.created", "{}")
+            .await
+            .unwrap();
+        let delivery = completed(&webhooks, &id).await;
+        assert_eq!(delivery.status(), DeliveryStatus::Failed { attempts: 2 });
+        assert_eq!(
+            delivery.last_error(),
+            Some("receiver answered with 503 Service Unavailable")
+        );
+        assert!(webhooks.status(&DeliveryId::new()).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,660 @@
+//! The permessage-deflate extension (RFC 7692).
+//!
+//! tungstenite does not implement any extensions, so compression is applied underneath it: the
+//! `DeflateStream` sits between the upgraded connection and the `WebSocketStream`, inflating
+//! compressed frames received from the client and compressing data frames sent by the server,
+//! while adjusting the frame headers accordingly. tungstenite only ever sees plain frames.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+use futures_util::ready;
+use hyper::header::{HeaderMap, SEC_WEBSOCKET_EXTENSIONS};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const EXTENSION_NAME: &str = "permessage-deflate";
+// The tail removed from every compressed message, see RFC 7692, section 7.2.1.
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+// The largest window the compressor can be configured with, which the decompressor always uses.
+const MAX_WINDOW_BITS: u8 = 15;
+// Limits the size of a single inflated frame, guarding against decompression bombs before the
+// message size limits of tungstenite apply.
+const MAX_INFLATED_FRAME: usize = 64 << 20;
+// Buffered outgoing data above which writes wait for the connection to drain.
+const WRITE_HIGH_WATER: usize = 64 * 1024;
+
+const FIN: u8 = 0x80;
+const RSV1: u8 = 0x40;
+const OPCODE: u8 = 0x0f;
+const MASK: u8 = 0x80;
+const OP_CONTINUATION: u8 = 0x0;
+
+/// Configuration of the permessage-deflate extension, returned by `WebSocketHandler::deflate`.
+///
+/// Context takeover means the compression state is kept between messages, which improves
+/// compression of similar messages at the expense of memory held per connection.
+#[derive(Clone, Copy, Debug)]
+pub struct DeflateConfig {
+    level: u32,
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+}
+
+impl Default for DeflateConfig {
+    fn default() -> Self {
+        DeflateConfig {
+            level: Compression::default().level(),
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+        }
+    }
+}
+
+impl DeflateConfig {
+    /// Creates a new `DeflateConfig` with the default compression level, allowing context
+    /// takeover in both directions unless the client asks otherwise.
+    pub fn new() -> Self {
+        DeflateConfig::default()
+    }
+
+    /// Sets the compression level, from 0 (none) to 9 (best).
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Resets the server's compression state after every message.
+    pub fn with_server_no_context_takeover(mut self) -> Self {
+        self.server_no_context_takeover = true;
+        self
+    }
+
+    /// Asks the client to reset its compression state after every message.
+    pub fn with_client_no_context_takeover(mut self) -> Self {
+        self.client_no_context_takeover = true;
+        self
+    }
+}
+
+/// The parameters of an accepted permessage-deflate offer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Negotiated {
+    level: u32,
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+    server_max_window_bits: bool,
+}
+
+impl Negotiated {
+    /// The value of the `Sec-WebSocket-Extensions` response header accepting the offer.
+    pub(crate) fn header_value(&self) -> String {
+        let mut value = String::from(EXTENSION_NAME);
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        if self.server_max_window_bits {
+            value.push_str("; server_max_window_bits=15");
+        }
+        value
+    }
+}
+
+/// Picks the first permessage-deflate offer in the request headers which can be accepted.
+pub(crate) fn negotiate(headers: &HeaderMap, config: &DeflateConfig) -> Option<Negotiated> {
+    headers
+        .get_all(SEC_WEBSOCKET_EXTENSIONS)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|offer| {
+            let mut parts = offer.split(';').map(str::trim);
+            if parts.next()? != EXTENSION_NAME {
+                return None;
+            }
+            let params = parts.map(|param| match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            });
+            accept_offer(params, config)
+        })
+        .next()
+}
+
+fn accept_offer<'a, I>(params: I, config: &DeflateConfig) -> Option<Negotiated>
+where
+    I: Iterator<Item = (&'a str, Option<&'a str>)>,
+{
+    let mut negotiated = Negotiated {
+        level: config.level,
+        server_no_context_takeover: config.server_no_context_takeover,
+        client_no_context_takeover: config.client_no_context_takeover,
+        server_max_window_bits: false,
+    };
+
+    let mut seen = Vec::new();
+    for (name, value) in params {
+        if seen.contains(&name) {
+            return None;
+        }
+        seen.push(name);
+
+        match (name, value) {
+            ("server_no_context_takeover", None) => negotiated.server_no_context_takeover = true,
+            ("client_no_context_takeover", None) => negotiated.client_no_context_takeover = true,
+            ("server_max_window_bits", Some(bits)) => {
+                // the compressor cannot be limited to a smaller window
+                if window_bits(bits)? != MAX_WINDOW_BITS {
+                    return None;
+                }
+                negotiated.server_max_window_bits = true;
+            }
+            // the decompressor handles any window size, so there is no need to limit the client
+            ("client_max_window_bits", None) => {}
+            ("client_max_window_bits", Some(bits)) => {
+                window_bits(bits)?;
+            }
+            _ => return None,
+        }
+    }
+    Some(negotiated)
+}
+
+fn window_bits(value: &str) -> Option<u8> {
+    value
+        .parse()
+        .ok()
+        .filter(|bits| (8..=MAX_WINDOW_BITS).contains(bits))
+}
+
+struct FrameHead {
+    byte0: u8,
+    mask: Option<[u8; 4]>,
+    head_len: usize,
+    payload_len: usize,
+}
+
+impl FrameHead {
+    // Parses the head of the frame at the start of `buf`, returning `None` until the whole
+    // frame has been received. Fails as soon as the head announces a payload larger than
+    // `max_payload`, instead of buffering it.
+    fn parse(buf: &[u8], max_payload: Option<usize>) -> io::Result<Option<FrameHead>> {
+        match Self::parse_head(buf) {
+            Some(head) if max_payload.is_some_and(|max| head.payload_len > max) => {
+                Err(invalid_data("frame too large"))
+            }
+            Some(head) if buf.len() - head.head_len >= head.payload_len => Ok(Some(head)),
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_head(buf: &[u8]) -> Option<FrameHead> {
+        if buf.len() < 2 {
+            return None;
+        }
+        let masked = buf[1] & MASK != 0;
+        let (payload_len, mut head_len) = match buf[1] & 0x7f {
+            126 => (u16::from_be_bytes([*buf.get(2)?, *buf.get(3)?]) as usize, 4),
+            127 => {
+                let mut len = [0u8; 8];
+                len.copy_from_slice(buf.get(2..10)?);
+                (u64::from_be_bytes(len) as usize, 10)
+            }
+            len => (len as usize, 2),
+        };
+        let mask = if masked {
+            let mut key = [0u8; 4];
+            key.copy_from_slice(buf.get(head_len..head_len + 4)?);
+            head_len += 4;
+            Some(key)
+        } else {
+            None
+        };
+
+        Some(FrameHead {
+            byte0: buf[0],
+            mask,
+            head_len,
+            payload_len,
+        })
+    }
+
+    fn frame_len(&self) -> usize {
+        self.head_len + self.payload_len
+    }
+
+    fn is_control(&self) -> bool {
+        self.byte0 & 0x08 != 0
+    }
+
+    fn is_fin(&self) -> bool {
+        self.byte0 & FIN != 0
+    }
+}
+
+fn write_frame(out: &mut Vec<u8>, byte0: u8, mask: Option<[u8; 4]>, payload: &mut [u8]) {
+    let mask_bit = if mask.is_some() { MASK } else { 0 };
+    out.push(byte0);
+    match payload.len() {
+        len if len < 126 => out.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if let Some(key) = mask {
+        out.extend_from_slice(&key);
+        apply_mask(payload, key);
+    }
+    out.extend_from_slice(payload);
+}
+
+fn apply_mask(payload: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+struct Codec {
+    negotiated: Negotiated,
+    max_frame_size: Option<usize>,
+    compress: Compress,
+    decompress: Decompress,
+    // whether the message currently being received / sent is compressed
+    inflating: bool,
+    deflating: bool,
+}
+
+impl Codec {
+    fn new(negotiated: Negotiated, max_frame_size: Option<usize>) -> Self {
+        Codec {
+            negotiated,
+            max_frame_size,
+            compress: Compress::new(Compression::new(negotiated.level), false),
+            decompress: Decompress::new(false),
+            inflating: false,
+            deflating: false,
+        }
+    }
+
+    // Inflates the complete frames at the start of `input` into `output`, returning the number
+    // of bytes consumed.
+    fn decode(&mut self, input: &[u8], output: &mut Vec<u8>) -> io::Result<usize> {
+        let mut consumed = 0;
+        while let Some(head) = FrameHead::parse(&input[consumed..], self.max_frame_size)? {
+            let frame = &input[consumed..consumed + head.frame_len()];
+            consumed += head.frame_len();
+
+            if !head.is_control() {
+                if head.byte0 & OPCODE != OP_CONTINUATION {
+                    self.inflating = head.byte0 & RSV1 != 0;
+                }
+                if self.inflating {
+                    let mut payload = frame[head.head_len..].to_vec();
+                    if let Some(key) = head.mask {
+                        apply_mask(&mut payload, key);
+                    }
+                    if head.is_fin() {
+                        payload.extend_from_slice(&DEFLATE_TAIL);
+                        self.inflating = false;
+                    }
+                    let mut inflated = self.inflate(&payload)?;
+                    write_frame(output, head.byte0 & !RSV1, head.mask, &mut inflated);
+                    continue;
+                }
+            }
+            output.extend_from_slice(frame);
+        }
+        Ok(consumed)
+    }
+
+    // Compresses the data frames at the start of `input` into `output`, returning the number of
+    // bytes consumed.
+    fn encode(&mut self, input: &[u8], output: &mut Vec<u8>) -> io::Result<usize> {
+        let mut consumed = 0;
+        while let Some(head) = FrameHead::parse(&input[consumed..], None)? {
+            let frame = &input[consumed..consumed + head.frame_len()];
+            consumed += head.frame_len();
+
+            if head.is_control() {
+                output.extend_from_slice(frame);
+                continue;
+            }
+
+            let mut byte0 = head.byte0;
+            if byte0 & OPCODE != OP_CONTINUATION {
+                self.deflating = true;
+                byte0 |= RSV1;
+            }
+            if !self.deflating {
+                output.extend_from_slice(frame);
+                continue;
+            }
+
+            let mut payload = frame[head.head_len..].to_vec();
+            if let Some(key) = head.mask {
+                apply_mask(&mut payload, key);
+            }
+            let mut deflated = self.deflate(&payload)?;
+            if head.is_fin() {
+                deflated.truncate(deflated.len().saturating_sub(DEFLATE_TAIL.len()));
+                self.deflating = false;
+                if self.negotiated.server_no_context_takeover {
+                    self.compress.reset();
+                }
+            }
+            write_frame(output, byte0, head.mask, &mut deflated);
+        }
+        Ok(consumed)
+    }
+
+    fn inflate(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(input.len() * 2 + 64);
+        let start = self.decompress.total_in();
+        loop {
+            let read = (self.decompress.total_in() - start) as usize;
+            self.decompress
+                .decompress_vec(&input[read..], &mut output, FlushDecompress::Sync)
+                .map_err(invalid_data)?;
+            let read = (self.decompress.total_in() - start) as usize;
+            if read == input.len() && output.len() < output.capacity() {
+                return Ok(output);
+            }
+            if output.len() > MAX_INFLATED_FRAME {
+                return Err(invalid_data("inflated frame too large"));
+            }
+            output.reserve(output.capacity().max(64));
+        }
+    }
+
+    fn deflate(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(input.len() / 2 + 64);
+        let start = self.compress.total_in();
+        loop {
+            let read = (self.compress.total_in() - start) as usize;
+            self.compress
+                .compress_vec(&input[read..], &mut output, FlushCompress::Sync)
+                .map_err(invalid_data)?;
+            let read = (self.compress.total_in() - start) as usize;
+            if read == input.len() && output.len() < output.capacity() {
+                return Ok(output);
+            }
+            output.reserve(output.capacity().max(64));
+        }
+    }
+}
+
+/// The connection underlying a `WebSocket`, applying permessage-deflate if it was negotiated
+/// during the handshake.
+pub struct DeflateStream<IO> {
+    io: IO,
+    codec: Option<Codec>,
+    read_raw: Vec<u8>,
+    read_decoded: Vec<u8>,
+    read_pos: usize,
+    write_raw: Vec<u8>,
+    write_encoded: Vec<u8>,
+}
+
+impl<IO> DeflateStream<IO> {
+    pub(crate) fn new(
+        io: IO,
+        negotiated: Option<Negotiated>,
+        max_frame_size: Option<usize>,
+    ) -> Self {
+        DeflateStream {
+            io,
+            codec: negotiated.map(|negotiated| Codec::new(negotiated, max_frame_size)),
+            read_raw: Vec::new(),
+            read_decoded: Vec::new(),
+            read_pos: 0,
+            write_raw: Vec::new(),
+            write_encoded: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if messages sent and received on this connection are compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.codec.is_some()
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> DeflateStream<IO> {
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_encoded.is_empty() {
+            let n = ready!(Pin::new(&mut self.io).poll_write(cx, &self.write_encoded))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_encoded.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for DeflateStream<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let codec = match &mut this.codec {
+            Some(codec) => codec,
+            None => return Pin::new(&mut this.io).poll_read(cx, buf),
+        };
+
+        loop {
+            if this.read_pos < this.read_decoded.len() {
+                let n = buf.remaining().min(this.read_decoded.len() - this.read_pos);
+                buf.put_slice(&this.read_decoded[this.read_pos..this.read_pos + n]);
+                this.read_pos += n;
+                if this.read_pos == this.read_decoded.len() {
+                    this.read_decoded.clear();
+                    this.read_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            let consumed = codec.decode(&this.read_raw, &mut this.read_decoded)?;
+            this.read_raw.drain(..consumed);
+            if !this.read_decoded.is_empty() {
+                continue;
+            }
+
+            let mut chunk = [0u8; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.io).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                // pass on an incomplete trailing frame, for tungstenite to report
+                this.read_decoded.append(&mut this.read_raw);
+                if this.read_decoded.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+            } else {
+                this.read_raw.extend_from_slice(chunk_buf.filled());
+            }
+        }
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for DeflateStream<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.codec.is_none() {
+            return Pin::new(&mut this.io).poll_write(cx, buf);
+        }
+
+        if this.write_encoded.len() >= WRITE_HIGH_WATER {
+            ready!(this.poll_drain(cx))?;
+        }
+
+        this.write_raw.extend_from_slice(buf);
+        if let Some(codec) = &mut this.codec {
+            let consumed = codec.encode(&this.write_raw, &mut this.write_encoded)?;
+            this.write_raw.drain(..consumed);
+        }
+        // start sending right away, the remainder is sent when flushing
+        if let Poll::Ready(Err(err)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn offer(value: &'static str) -> Option<Negotiated> {
+        let mut headers = HeaderMap::new();
+        headers.insert(SEC_WEBSOCKET_EXTENSIONS, HeaderValue::from_static(value));
+        negotiate(&headers, &DeflateConfig::new())
+    }
+
+    #[test]
+    fn accepts_plain_offer() {
+        let negotiated = offer("permessage-deflate").unwrap();
+        assert_eq!(negotiated.header_value(), "permessage-deflate");
+    }
+
+    #[test]
+    fn accepts_context_takeover_parameters() {
+        let negotiated =
+            offer("permessage-deflate; server_no_context_takeover; client_max_window_bits")
+                .unwrap();
+        assert_eq!(
+            negotiated.header_value(),
+            "permessage-deflate; server_no_context_takeover"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_acceptable_offer() {
+        let negotiated = offer(
+            "permessage-deflate; server_max_window_bits=10, \
+             permessage-deflate; server_max_window_bits=\"15\"",
+        )
+        .unwrap();
+        assert_eq!(
+            negotiated.header_value(),
+            "permessage-deflate; server_max_window_bits=15"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_offers() {
+        assert!(offer("x-webkit-deflate-frame").is_none());
+        assert!(offer("permessage-deflate; unknown").is_none());
+        assert!(offer("permessage-deflate; client_max_window_bits=16").is_none());
+        assert!(offer("permessage-deflate; server_no_context_takeover=1").is_none());
+        assert!(offer(
+            "permessage-deflate; server_no_context_takeover; server_no_context_takeover"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn applies_configured_parameters() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static("permessage-deflate"),
+        );
+        let config = DeflateConfig::new()
+            .with_server_no_context_takeover()
+            .with_client_no_context_takeover();
+        assert_eq!(
+            negotiate(&headers, &config).unwrap().header_value(),
+            "permessage-deflate; server_no_context_takeover; client_no_context_takeover"
+        );
+    }
+
+    #[test]
+    fn round_trips_fragmented_messages() {
+        let negotiated = offer("permessage-deflate").unwrap();
+        let mut server = Codec::new(negotiated, None);
+        let mut client = Codec::new(negotiated, None);
+
+        // a text message in two fragments, followed by a ping
+        let mut plain = Vec::new();
+        write_frame(&mut plain, 0x01, None, &mut b"hello ".to_vec());
+        write_frame(&mut plain, FIN, None, &mut b"world".to_vec());
+        write_frame(&mut plain, FIN | 0x09, None, &mut Vec::new());
+
+        let mut encoded = Vec::new();
+        assert_eq!(server.encode(&plain, &mut encoded).unwrap(), plain.len());
+        assert_eq!(encoded[0], 0x01 | RSV1);
+
+        let mut decoded = Vec::new();
+        // incomplete frames are left for later
+        assert_eq!(client.decode(&encoded[..1], &mut decoded).unwrap(), 0);
+        assert_eq!(
+            client.decode(&encoded, &mut decoded).unwrap(),
+            encoded.len()
+        );
+        assert_eq!(decoded, plain);
+    }
+
+    #[test]
+    fn inflates_masked_frames() {
+        let negotiated = offer("permessage-deflate").unwrap();
+        let mut client = Codec::new(negotiated, None);
+        let mut server = Codec::new(negotiated, None);
+        let key = Some([1, 2, 3, 4]);
+
+        let mut plain = Vec::new();
+        write_frame(&mut plain, FIN | 0x02, key, &mut vec![7u8; 1000]);
+
+        let mut encoded = Vec::new();
+        client.encode(&plain, &mut encoded).unwrap();
+        assert!(encoded.len() < plain.len());
+
+        let mut decoded = Vec::new();
+        server.decode(&encoded, &mut decoded).unwrap();
+        assert_eq!(decoded, plain);
+    }
+
+    #[test]
+    fn rejects_frames_above_max_frame_size() {
+        let negotiated = offer("permessage-deflate").unwrap();
+        let mut server = Codec::new(negotiated, Some(1024));
+
+        // only the head of a frame announcing 1 MiB of payload
+        let mut head = vec![FIN | RSV1 | 0x02, MASK | 127];
+        head.extend_from_slice(&(1u64 << 20).to_be_bytes());
+        head.extend_from_slice(&[1, 2, 3, 4]);
+
+        let err = server.decode(&head, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
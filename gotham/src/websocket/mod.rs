@@ -0,0 +1,436 @@
+//! First-class WebSocket support for Gotham routes.
+//!
+//! A route terminated with `to_websocket` performs the RFC 6455 opening handshake on the incoming
+//! request, responds with `101 Switching Protocols`, and hands the upgraded connection to the
+//! application as a `WebSocket` (a framed `Sink` and `Stream` of `Message`s).
+//!
+//! The handler can inspect the `State` of the upgrade request before the handshake completes,
+//! e.g. to authenticate the client via a session or a header, by implementing
+//! `WebSocketHandler::accept`. Plain closures receiving only the `WebSocket` are supported too.
+//!
+//! Handlers returning a `DeflateConfig` from `WebSocketHandler::deflate` negotiate the
+//! permessage-deflate extension (RFC 7692) with clients offering it, compressing messages
+//! transparently.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use futures_util::{SinkExt, StreamExt};
+//! use gotham::router::builder::*;
+//! use gotham::websocket::WebSocket;
+//!
+//! async fn echo(mut ws: WebSocket) {
+//!     while let Some(Ok(message)) = ws.next().await {
+//!         if message.is_text() || message.is_binary() {
+//!             if ws.send(message).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let router = build_simple_router(|route| {
+//!     route.get("/ws").to_websocket(echo);
+//! });
+//! # let _ = router;
+//! # }
+//! ```
+
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+
+use base64::prelude::*;
+use futures_util::future::{self, FutureExt};
+use hyper::header::{
+    HeaderMap, HeaderValue, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_VERSION,
+};
+use hyper::upgrade::Upgraded;
+use hyper::{Method, StatusCode};
+use log::debug;
+use sha1::{Digest, Sha1};
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use crate::helpers::http::response::create_empty_response;
+use crate::state::{request_id, FromState, State};
+use crate::upgrade::{self, spawn_upgraded, switching_protocols};
+
+mod deflate;
+
+pub use self::deflate::{DeflateConfig, DeflateStream};
+pub use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+pub use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+pub use tokio_tungstenite::tungstenite::{Error as WebSocketError, Message};
+
+/// An established WebSocket connection, usable as a `Stream` of incoming `Message`s and a `Sink`
+/// for outgoing `Message`s.
+pub type WebSocket = WebSocketStream<DeflateStream<Upgraded>>;
+
+const PROTO_WEBSOCKET: &str = "websocket";
+const WEBSOCKET_VERSION: &str = "13";
+const WS_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Handles WebSocket connections established on a route.
+///
+/// This is implemented for all closures and functions taking a `WebSocket` and returning a
+/// `Future`. Implement it directly to take part in the handshake, e.g. to reject unauthorized
+/// clients or to carry data from the upgrade request's `State` into the connection.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::future::Future;
+/// # use std::pin::Pin;
+/// # use hyper::StatusCode;
+/// # use hyper::header::AUTHORIZATION;
+/// # use hyper::HeaderMap;
+/// # use gotham::anyhow::anyhow;
+/// # use gotham::handler::HandlerError;
+/// # use gotham::state::{FromState, State};
+/// use gotham::websocket::{WebSocket, WebSocketHandler};
+///
+/// # #[allow(dead_code)]
+/// #[derive(Clone)]
+/// struct Chat;
+///
+/// impl WebSocketHandler for Chat {
+///     type Context = String;
+///
+///     fn accept(&self, state: &mut State) -> Result<String, HandlerError> {
+///         HeaderMap::borrow_from(state)
+///             .get(AUTHORIZATION)
+///             .and_then(|user| user.to_str().ok())
+///             .map(ToOwned::to_owned)
+///             .ok_or_else(|| {
+///                 HandlerError::from(anyhow!("no user")).with_status(StatusCode::UNAUTHORIZED)
+///             })
+///     }
+///
+///     fn connected(
+///         self,
+///         user: String,
+///         ws: WebSocket,
+///     ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+///         Box::pin(async move {
+///             // Implementation elided.
+/// #           let _ = (user, ws);
+///         })
+///     }
+/// }
+/// ```
+pub trait WebSocketHandler: Clone + Send + Sync + RefUnwindSafe + 'static {
+    /// Data carried from the upgrade request into the established connection.
+    type Context: Send + 'static;
+
+    /// Invoked with the `State` of the upgrade request before the handshake completes. Returning
+    /// an error rejects the connection, sending the error's status code instead of `101`.
+    fn accept(&self, state: &mut State) -> Result<Self::Context, HandlerError>;
+
+    /// Invoked with the established connection. The connection is closed when the returned
+    /// future completes and the `WebSocket` has been dropped.
+    fn connected(
+        self,
+        context: Self::Context,
+        ws: WebSocket,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    /// Protocol configuration (message and frame size limits etc.) of the connection. Defaults
+    /// to the tungstenite defaults.
+    fn config(&self) -> Option<WebSocketConfig> {
+        None
+    }
+
+    /// Enables the permessage-deflate extension for clients offering it, compressing messages
+    /// in both directions. Disabled by default.
+    fn deflate(&self) -> Option<DeflateConfig> {
+        None
+    }
+}
+
+impl<F, Fut> WebSocketHandler for F
+where
+    F: FnOnce(WebSocket) -> Fut + Clone + Send + Sync + RefUnwindSafe + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    type Context = ();
+
+    fn accept(&self, _state: &mut State) -> Result<(), HandlerError> {
+        Ok(())
+    }
+
+    fn connected(self, _context: (), ws: WebSocket) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self(ws).boxed()
+    }
+}
+
+/// Returns `true` if the request in `State` asks for a WebSocket upgrade.
+pub fn requested(state: &State) -> bool {
+    upgrade::requested(state, PROTO_WEBSOCKET)
+}
+
+/// The `Handler` created for routes terminated with `to_websocket`.
+#[derive(Clone)]
+pub struct WebSocketRoute<H> {
+    handler: H,
+}
+
+impl<H: WebSocketHandler> WebSocketRoute<H> {
+    /// Creates a new `WebSocketRoute`, passing established connections to `handler`.
+    pub fn new(handler: H) -> Self {
+        WebSocketRoute { handler }
+    }
+}
+
+impl<H: WebSocketHandler> NewHandler for WebSocketRoute<H> {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<H: WebSocketHandler> Handler for WebSocketRoute<H> {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        let accept_key = match handshake(&state) {
+            Ok(accept_key) => accept_key,
+            Err(status) => {
+                debug!(
+                    "[{}] rejecting invalid WebSocket handshake with {}",
+                    request_id(&state),
+                    status
+                );
+                let mut response = create_empty_response(&state, status);
+                if status == StatusCode::UPGRADE_REQUIRED {
+                    // RFC 6455, section 4.4: advertise the supported version.
+                    response.headers_mut().insert(
+                        SEC_WEBSOCKET_VERSION,
+                        HeaderValue::from_static(WEBSOCKET_VERSION),
+                    );
+                }
+                return future::ok((state, response)).boxed();
+            }
+        };
+
+        let context = match self.handler.accept(&mut state) {
+            Ok(context) => context,
+            Err(err) => return future::err((state, err)).boxed(),
+        };
+
+        let handler = self.handler;
+        let config = handler.config();
+        let max_frame_size = config.unwrap_or_default().max_frame_size;
+        let negotiated = handler
+            .deflate()
+            .and_then(|deflate| deflate::negotiate(HeaderMap::borrow_from(&state), &deflate));
+        let extensions = negotiated.map(|negotiated| negotiated.header_value());
+        let upgraded = spawn_upgraded(&mut state, move |upgraded| async move {
+            let io = DeflateStream::new(upgraded, negotiated, max_frame_size);
+            let ws = WebSocketStream::from_raw_socket(io, Role::Server, config).await;
+            handler.connected(context, ws).await;
+        });
+        if let Err(err) = upgraded {
+            return future::err((state, err)).boxed();
+        }
+
+        let mut response = switching_protocols(&state, PROTO_WEBSOCKET);
+        {
+            let headers = response.headers_mut();
+            headers.insert(SEC_WEBSOCKET_ACCEPT, accept_key);
+            if let Some(extensions) = extensions {
+                let value = HeaderValue::from_str(&extensions).expect("valid extensions header");
+                headers.insert(SEC_WEBSOCKET_EXTENSIONS, value);
+            }
+        }
+        future::ok((state, response)).boxed()
+    }
+}
+
+// Validates the opening handshake of RFC 6455, section 4.2.1, returning the value of the
+// `Sec-WebSocket-Accept` header on success.
+fn handshake(state: &State) -> Result<HeaderValue, StatusCode> {
+    if *Method::borrow_from(state) != Method::GET || !requested(state) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let headers = HeaderMap::borrow_from(state);
+    if headers.get(SEC_WEBSOCKET_VERSION) != Some(&HeaderValue::from_static(WEBSOCKET_VERSION)) {
+        return Err(StatusCode::UPGRADE_REQUIRED);
+    }
+
+    let key = headers
+        .get(SEC_WEBSOCKET_KEY)
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    HeaderValue::from_str(&accept_key(key.as_bytes())).map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+fn accept_key(key: &[u8]) -> String {
+    let mut sha1 = Sha1::default();
+    sha1.update(key);
+    sha1.update(WS_GUID);
+    BASE64_STANDARD.encode(sha1.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use crate::test::TestServer;
+    use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+    use futures_util::{SinkExt, StreamExt};
+    use hyper::header::{CONNECTION, UPGRADE};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn accept_key_from_rfc6455() {
+        // From https://tools.ietf.org/html/rfc6455#section-1.2
+        let key = accept_key(b"dGhlIHNhbXBsZSBub25jZQ==");
+        assert_eq!(key, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    async fn echo(mut ws: WebSocket) {
+        while let Some(Ok(message)) = ws.next().await {
+            if message.is_text() && ws.send(message).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_plain_requests() {
+        let router = build_simple_router(|route| route.get("/ws").to_websocket(echo));
+        let test_server = TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/ws")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let router = build_simple_router(|route| route.get("/ws").to_websocket(echo));
+        let test_server = TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/ws")
+            .with_header(UPGRADE, HeaderValue::from_static("websocket"))
+            .with_header(CONNECTION, HeaderValue::from_static("Upgrade"))
+            .with_header(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"))
+            .with_header(
+                SEC_WEBSOCKET_KEY,
+                HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="),
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(response.headers()[SEC_WEBSOCKET_VERSION], "13");
+    }
+
+    #[tokio::test]
+    async fn echoes_messages() {
+        let router = build_simple_router(|route| route.get("/ws").to_websocket(echo));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            crate::bind_server(listener, router, future::ok).await;
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut ws, response) = tokio_tungstenite::client_async("ws://localhost/ws", stream)
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 101);
+
+        ws.send(Message::text("hello")).await.unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        assert_eq!(reply, Message::text("hello"));
+    }
+
+    #[derive(Clone)]
+    struct CompressedEcho;
+
+    impl WebSocketHandler for CompressedEcho {
+        type Context = ();
+
+        fn accept(&self, _state: &mut State) -> Result<(), HandlerError> {
+            Ok(())
+        }
+
+        fn connected(
+            self,
+            _context: (),
+            ws: WebSocket,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            echo(ws).boxed()
+        }
+
+        fn deflate(&self) -> Option<DeflateConfig> {
+            Some(DeflateConfig::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn echoes_compressed_messages() {
+        let router = build_simple_router(|route| route.get("/ws").to_websocket(CompressedEcho));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            crate::bind_server(listener, router, future::ok).await;
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /ws HTTP/1.1\r\n\
+                  Host: localhost\r\n\
+                  Upgrade: websocket\r\n\
+                  Connection: Upgrade\r\n\
+                  Sec-WebSocket-Version: 13\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_lowercase();
+        assert!(head.starts_with("http/1.1 101"));
+        assert!(head.contains("sec-websocket-extensions: permessage-deflate\r\n"));
+
+        // a masked, compressed text frame
+        let mut payload = Vec::with_capacity(64);
+        Compress::new(Compression::default(), false)
+            .compress_vec(b"hello", &mut payload, FlushCompress::Sync)
+            .unwrap();
+        payload.truncate(payload.len() - 4);
+        let key = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0xc1, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&key);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+        stream.write_all(&frame).await.unwrap();
+
+        let mut reply_head = [0u8; 2];
+        stream.read_exact(&mut reply_head).await.unwrap();
+        assert_eq!(reply_head[0], 0xc1);
+        let mut reply = vec![0u8; reply_head[1] as usize];
+        stream.read_exact(&mut reply).await.unwrap();
+        reply.extend_from_slice(&[0x00, 0x00, 0xff, 0xff]);
+
+        let mut inflated = Vec::with_capacity(64);
+        Decompress::new(false)
+            .decompress_vec(&reply, &mut inflated, FlushDecompress::Sync)
+            .unwrap();
+        assert_eq!(inflated, b"hello");
+    }
+}
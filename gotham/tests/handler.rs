@@ -0,0 +1,8 @@
+#![cfg(feature = "derive")]
+
+#[test]
+fn handler_attribute() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/handler/pass_*.rs");
+    t.compile_fail("tests/ui/handler/fail_*.rs");
+}
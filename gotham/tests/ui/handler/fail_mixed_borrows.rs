@@ -0,0 +1,21 @@
+use gotham::prelude::*;
+
+#[handler]
+fn mutable_value(
+    method: &gotham::hyper::Method,
+    headers: &mut gotham::hyper::HeaderMap,
+) -> String {
+    headers.clear();
+    method.to_string()
+}
+
+#[handler]
+fn mutable_state(
+    state: &mut gotham::state::State,
+    method: &gotham::hyper::Method,
+) -> String {
+    state.put(method.clone());
+    method.to_string()
+}
+
+fn main() {}
@@ -0,0 +1,29 @@
+use gotham::hyper::{HeaderMap, Method, Uri};
+use gotham::prelude::*;
+use gotham::state::State;
+
+#[handler]
+fn shared(method: &Method, uri: &Uri, headers: &HeaderMap) -> String {
+    format!("{} {} {}", method, uri, headers.len())
+}
+
+#[handler]
+fn shared_state(state: &State, uri: &Uri) -> String {
+    format!("{} {}", uri, Method::borrow_from(state))
+}
+
+#[handler]
+fn mutable(headers: &mut HeaderMap, method: Method) -> String {
+    headers.clear();
+    method.to_string()
+}
+
+#[handler]
+async fn mutable_state(state: &mut State, uri: Uri) -> String {
+    state.put(uri.clone());
+    uri.to_string()
+}
+
+fn main() {
+    let _ = (shared, shared_state, mutable, mutable_state);
+}
@@ -0,0 +1,160 @@
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+enum Argument {
+    // `&State` or `&mut State`
+    State { mutable: bool },
+    // `&T` or `&mut T`, borrowed from the state
+    Borrowed { ty: syn::Type, mutable: bool },
+    // `T`, taken from the state
+    Owned { ty: syn::Type },
+}
+
+pub(crate) fn handler(item: syn::ItemFn) -> proc_macro::TokenStream {
+    match expand(item) {
+        Ok(expanded) => expanded.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(item: syn::ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &item.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "handler functions cannot be generic",
+        ));
+    }
+
+    let arguments = sig
+        .inputs
+        .iter()
+        .map(argument)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    // `State` is not `Sync`, so a future holding a `&State` could not be sent between threads
+    if sig.asyncness.is_some() {
+        for (input, arg) in sig.inputs.iter().zip(&arguments) {
+            if let Argument::State { mutable: false } = arg {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "async handler functions cannot borrow the `State`, use `&mut State` instead",
+                ));
+            }
+        }
+    }
+
+    // every borrow is taken from the `State`, so a mutable one must be the only borrow
+    let borrowed = arguments
+        .iter()
+        .filter(|arg| !matches!(arg, Argument::Owned { .. }))
+        .count();
+    if borrowed > 1 {
+        for (input, arg) in sig.inputs.iter().zip(&arguments) {
+            if let Argument::State { mutable: true } | Argument::Borrowed { mutable: true, .. } =
+                arg
+            {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "a mutable borrow from the `State` cannot be combined with other borrowed \
+                     arguments, take the other values by value instead",
+                ));
+            }
+        }
+    }
+
+    let mut takes = Vec::new();
+    let mut borrows = Vec::new();
+    let mut names = Vec::new();
+    for (i, arg) in arguments.into_iter().enumerate() {
+        let name = format_ident!("__gotham_arg{}", i);
+        match arg {
+            Argument::State { mutable: false } => borrows.push(quote!(let #name = &state;)),
+            Argument::State { mutable: true } => borrows.push(quote!(let #name = &mut state;)),
+            Argument::Borrowed { ty, mutable: false } => borrows.push(quote! {
+                let #name = <#ty as ::gotham::state::FromState>::borrow_from(&state);
+            }),
+            Argument::Borrowed { ty, mutable: true } => borrows.push(quote! {
+                let #name = <#ty as ::gotham::state::FromState>::borrow_mut_from(&mut state);
+            }),
+            Argument::Owned { ty } => takes.push(quote! {
+                let #name = <#ty as ::gotham::state::FromState>::take_from(&mut state);
+            }),
+        }
+        names.push(name);
+    }
+
+    let attrs = &item.attrs;
+    let vis = &item.vis;
+    let ident = &sig.ident;
+    let await_response = sig.asyncness.map(|_| quote!(.await));
+
+    // the original function is nested inside the handler, which carries its documentation
+    let mut inner = item.clone();
+    inner.vis = syn::Visibility::Inherited;
+    inner.attrs.retain(|attr| !attr.path().is_ident("doc"));
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #ident(
+            #[allow(unused_mut)] mut state: ::gotham::state::State,
+        ) -> ::std::pin::Pin<::std::boxed::Box<::gotham::handler::HandlerFuture>> {
+            #inner
+
+            #(#takes)*
+            ::std::boxed::Box::pin(async move {
+                let response = {
+                    #(#borrows)*
+                    #ident(#(#names),*) #await_response
+                };
+                let response = ::gotham::handler::IntoResponse::into_response(response, &state);
+                ::std::result::Result::Ok((state, response))
+            })
+        }
+    })
+}
+
+fn argument(arg: &syn::FnArg) -> syn::Result<Argument> {
+    let ty = match arg {
+        syn::FnArg::Typed(pat_type) => &*pat_type.ty,
+        syn::FnArg::Receiver(receiver) => {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "handler functions cannot take `self`",
+            ))
+        }
+    };
+
+    Ok(match ty {
+        syn::Type::Reference(reference) => {
+            let mutable = reference.mutability.is_some();
+            if is_state(&reference.elem) {
+                Argument::State { mutable }
+            } else {
+                Argument::Borrowed {
+                    ty: (*reference.elem).clone(),
+                    mutable,
+                }
+            }
+        }
+        ty if is_state(ty) => {
+            return Err(syn::Error::new(
+                ty.span(),
+                "handler functions cannot take the `State`, use `&State` or `&mut State` instead",
+            ))
+        }
+        ty => Argument::Owned { ty: ty.clone() },
+    })
+}
+
+fn is_state(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "State" && segment.arguments.is_empty())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
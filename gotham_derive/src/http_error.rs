@@ -0,0 +1,131 @@
+use quote::quote;
+use syn::spanned::Spanned;
+
+// The options of an `#[http_error(...)]` attribute.
+#[derive(Default)]
+struct Options {
+    status: Option<u16>,
+    expose: bool,
+}
+
+pub(crate) fn http_error(ast: &syn::DeriveInput) -> proc_macro::TokenStream {
+    match expand(ast) {
+        Ok(expanded) => expanded.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let defaults = options(&ast.attrs, Options::default())?;
+
+    // one match arm per variant, or a single arm for structs
+    let mut statuses = Vec::new();
+    let mut exposed = Vec::new();
+    match &ast.data {
+        syn::Data::Enum(data) => {
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let pattern = quote!(Self::#ident { .. });
+                let options = options(
+                    &variant.attrs,
+                    Options {
+                        status: defaults.status,
+                        expose: defaults.expose,
+                    },
+                )?;
+                statuses.push((pattern.clone(), options.status));
+                exposed.push((pattern, options.expose));
+            }
+        }
+        syn::Data::Struct(_) => {
+            statuses.push((quote!(_), defaults.status));
+            exposed.push((quote!(_), defaults.expose));
+        }
+        syn::Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span(),
+                "HttpError cannot be derived for unions",
+            ))
+        }
+    }
+
+    let status_arms = statuses.into_iter().map(|(pattern, status)| match status {
+        // the status code was validated when parsing the attribute
+        Some(status) => quote! {
+            #pattern => ::gotham::hyper::StatusCode::from_u16(#status)
+                .expect("valid status code"),
+        },
+        None => quote!(#pattern => ::gotham::hyper::StatusCode::INTERNAL_SERVER_ERROR,),
+    });
+    let body_arms = exposed.into_iter().map(|(pattern, expose)| {
+        if expose {
+            quote! {
+                #pattern => ::std::option::Option::Some((
+                    ::gotham::mime::TEXT_PLAIN_UTF_8,
+                    ::std::string::ToString::to_string(self),
+                )),
+            }
+        } else {
+            quote!(#pattern => ::std::option::Option::None,)
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::gotham::handler::HttpError for #name #ty_generics #where_clause {
+            fn status(&self) -> ::gotham::hyper::StatusCode {
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#status_arms)*
+                }
+            }
+
+            fn body(&self) -> ::std::option::Option<(::gotham::mime::Mime, ::std::string::String)> {
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#body_arms)*
+                }
+            }
+        }
+
+        impl #impl_generics ::gotham::handler::IntoResponse for #name #ty_generics #where_clause {
+            fn into_response(
+                self,
+                state: &::gotham::state::State,
+            ) -> ::gotham::hyper::Response<::gotham::hyper::Body> {
+                let err = ::gotham::handler::IntoHandlerError::into_handler_error(self);
+                ::gotham::handler::IntoResponse::into_response(err, state)
+            }
+        }
+    })
+}
+
+fn options(attrs: &[syn::Attribute], mut options: Options) -> syn::Result<Options> {
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("http_error"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("status") {
+                let status: syn::LitInt = meta.value()?.parse()?;
+                match status.base10_parse::<u16>() {
+                    Ok(code @ 100..=999) => options.status = Some(code),
+                    _ => {
+                        return Err(syn::Error::new(
+                            status.span(),
+                            "the status must be a number from 100 to 999",
+                        ))
+                    }
+                }
+                Ok(())
+            } else if meta.path.is_ident("expose") {
+                options.expose = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `status = ...` or `expose`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
@@ -2,6 +2,8 @@
 //! use this crate directly.
 
 mod extenders;
+mod handler;
+mod http_error;
 mod new_middleware;
 mod state;
 
@@ -11,14 +13,44 @@ pub fn static_response_extender(input: proc_macro::TokenStream) -> proc_macro::T
     extenders::bad_request_static_response_extender(&ast)
 }
 
-#[proc_macro_derive(StateData)]
+#[proc_macro_derive(StateData, attributes(state_data))]
 pub fn state_data(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast = syn::parse(input).unwrap();
     state::state_data(&ast)
 }
 
+#[proc_macro_derive(HttpError, attributes(http_error))]
+pub fn http_error(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ast = syn::parse(input).unwrap();
+    http_error::http_error(&ast)
+}
+
 #[proc_macro_derive(NewMiddleware)]
 pub fn new_middleware(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast = syn::parse(input).unwrap();
     new_middleware::new_middleware(&ast)
 }
+
+/// Turns a function taking its inputs as typed arguments into a Gotham handler.
+///
+/// Arguments of type `T` are taken from the `State`, and arguments of type `&T` or `&mut T` are
+/// borrowed from it, so they must be `StateData` placed there by the router or middleware, such as
+/// path and query string extractors. A `&mut State` argument gives access to the state itself. A
+/// mutable borrow must be the only borrowed argument, as all of them borrow from the `State`. The
+/// function may be `async`, and must return a type implementing `IntoResponse`.
+#[proc_macro_attribute]
+pub fn handler(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "the handler attribute takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let item = syn::parse_macro_input!(item as syn::ItemFn);
+    handler::handler(item)
+}
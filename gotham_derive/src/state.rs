@@ -1,12 +1,91 @@
 use quote::quote;
 
 pub(crate) fn state_data(ast: &syn::DeriveInput) -> proc_macro::TokenStream {
+    match expand(ast) {
+        Ok(expanded) => expanded.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let name = &ast.ident;
     let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let accessors = if accessors(&ast.attrs)? {
+        expand_accessors(ast)
+    } else {
+        quote!()
+    };
 
-    let expanded = quote! {
+    Ok(quote! {
         impl #impl_generics ::gotham::state::StateData for #name #ty_generics #where_clause {}
-    };
 
-    expanded.into()
+        #accessors
+    })
+}
+
+// The associated functions generated with `#[state_data(accessors)]`.
+fn expand_accessors(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
+    let name = &ast.ident;
+    let vis = &ast.vis;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        #[allow(dead_code)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Tries to borrow a value of this type from the `State`.
+            #vis fn try_borrow(state: &::gotham::state::State) -> ::std::option::Option<&Self> {
+                state.try_borrow::<Self>()
+            }
+
+            /// Borrows a value of this type from the `State`, panicking with the name of the type
+            /// if it is not present.
+            #vis fn borrow(state: &::gotham::state::State) -> &Self {
+                state.borrow::<Self>()
+            }
+
+            /// Tries to mutably borrow a value of this type from the `State`.
+            #vis fn try_borrow_mut(
+                state: &mut ::gotham::state::State,
+            ) -> ::std::option::Option<&mut Self> {
+                state.try_borrow_mut::<Self>()
+            }
+
+            /// Mutably borrows a value of this type from the `State`, panicking with the name of
+            /// the type if it is not present.
+            #vis fn borrow_mut(state: &mut ::gotham::state::State) -> &mut Self {
+                state.borrow_mut::<Self>()
+            }
+
+            /// Tries to move a value of this type out of the `State`.
+            #vis fn try_take(state: &mut ::gotham::state::State) -> ::std::option::Option<Self> {
+                state.try_take::<Self>()
+            }
+
+            /// Moves a value of this type out of the `State`, panicking with the name of the type
+            /// if it is not present.
+            #vis fn take(state: &mut ::gotham::state::State) -> Self {
+                state.take::<Self>()
+            }
+        }
+    }
+}
+
+// Returns whether the accessors were requested with `#[state_data(accessors)]`.
+fn accessors(attrs: &[syn::Attribute]) -> syn::Result<bool> {
+    let mut accessors = false;
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("state_data"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("accessors") {
+                accessors = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `accessors`"))
+            }
+        })?;
+    }
+    Ok(accessors)
 }
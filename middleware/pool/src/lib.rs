@@ -0,0 +1,438 @@
+//! Provides Middlewares which make a database connection pool available to handlers through the
+//! Gotham `State`.
+//!
+//! The `PoolMiddleware` places a `PoolHandle` into the `State` of each request, which handlers can
+//! use to check out connections when they need them. The `ConnectionMiddleware` checks out a
+//! `Connection` before the request is handled, which returns to the pool once the request
+//! completes, and answers requests with `503 Service Unavailable` if the pool fails to provide
+//! one.
+//!
+//! Pools are supported through the `AsyncPool` trait, which is implemented for the pools of the
+//! `r2d2` (including `diesel::r2d2`), `bb8` and `deadpool` crates when the feature of the same name
+//! is enabled. Both middlewares record `PoolMetrics` about the health of the pool.
+//!
+//! ```rust
+//! # use futures_util::future::{self, BoxFuture, FutureExt};
+//! # use gotham::pipeline::{new_pipeline, single_pipeline};
+//! # use gotham::router::builder::*;
+//! # use gotham::state::{FromState, State};
+//! # use gotham::test::TestServer;
+//! use gotham_middleware_pool::{AsyncPool, Connection, ConnectionMiddleware, PoolStatus};
+//!
+//! // A pool handing out numbers, in place of a real database pool.
+//! #[derive(Clone)]
+//! struct NumberPool;
+//!
+//! impl AsyncPool for NumberPool {
+//!     type Connection = u32;
+//!     type Error = ();
+//!
+//!     fn get(&self) -> BoxFuture<'static, Result<u32, ()>> {
+//!         future::ok(42).boxed()
+//!     }
+//!
+//!     fn status(&self) -> PoolStatus {
+//!         PoolStatus::default()
+//!     }
+//! }
+//!
+//! fn handler(state: State) -> (State, String) {
+//!     let conn = Connection::<NumberPool>::borrow_from(&state);
+//!     let body = format!("connection {}", **conn);
+//!     (state, body)
+//! }
+//!
+//! # fn main() {
+//! let middleware = ConnectionMiddleware::new(NumberPool);
+//! let metrics = middleware.metrics();
+//! let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+//! let router = build_router(chain, pipelines, |route| {
+//!     route.get("/").to(handler);
+//! });
+//!
+//! let test_server = TestServer::new(router).unwrap();
+//! let response = test_server.client().get("http://localhost/").perform().unwrap();
+//! assert_eq!(response.read_utf8_body().unwrap(), "connection 42");
+//! assert_eq!(metrics.snapshot().checkouts, 1);
+//! # }
+//! ```
+#![warn(missing_docs, rust_2018_idioms, unreachable_pub)]
+#![forbid(elided_lifetimes_in_paths, unsafe_code)]
+#![doc(test(no_crate_inject, attr(deny(warnings))))]
+
+use futures_util::future::{self, FutureExt};
+use log::{error, trace};
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::time::Instant;
+
+use gotham::anyhow;
+use gotham::handler::HandlerFuture;
+use gotham::helpers::http::response::create_empty_response;
+use gotham::hyper::StatusCode;
+use gotham::middleware::{Middleware, NewMiddleware};
+use gotham::prelude::*;
+use gotham::state::{request_id, State};
+
+mod metrics;
+mod pool;
+
+pub use metrics::{MetricsSnapshot, PoolMetrics};
+#[cfg(feature = "r2d2")]
+pub use pool::CheckoutError;
+pub use pool::{AsyncPool, PoolStatus};
+
+/// A handle to the pool, placed into the `State` by both middlewares.
+#[derive(StateData)]
+pub struct PoolHandle<P>
+where
+    P: AsyncPool,
+{
+    pool: P,
+    metrics: PoolMetrics<P>,
+}
+
+impl<P> Clone for PoolHandle<P>
+where
+    P: AsyncPool,
+{
+    fn clone(&self) -> Self {
+        PoolHandle {
+            pool: self.pool.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<P> PoolHandle<P>
+where
+    P: AsyncPool,
+{
+    fn new(pool: P) -> Self {
+        PoolHandle {
+            metrics: PoolMetrics::new(pool.clone()),
+            pool,
+        }
+    }
+
+    /// Checks out a connection from the pool, recording the checkout in the metrics.
+    pub fn get(&self) -> impl Future<Output = Result<P::Connection, P::Error>> + Send + 'static {
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        self.pool.get().map(move |result| {
+            metrics.record_checkout(start.elapsed(), result.is_ok());
+            result
+        })
+    }
+
+    /// Returns the pool.
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    /// Returns the metrics of the pool.
+    pub fn metrics(&self) -> &PoolMetrics<P> {
+        &self.metrics
+    }
+}
+
+/// A connection checked out by the `ConnectionMiddleware`, which returns to the pool when the
+/// request completes. It can be returned earlier by taking it from the `State` and dropping it.
+#[derive(StateData)]
+pub struct Connection<P>
+where
+    P: AsyncPool,
+{
+    conn: P::Connection,
+}
+
+impl<P> Connection<P>
+where
+    P: AsyncPool,
+{
+    /// Returns the connection handed out by the pool.
+    pub fn into_inner(self) -> P::Connection {
+        self.conn
+    }
+}
+
+impl<P> Deref for Connection<P>
+where
+    P: AsyncPool,
+{
+    type Target = P::Connection;
+
+    fn deref(&self) -> &P::Connection {
+        &self.conn
+    }
+}
+
+impl<P> DerefMut for Connection<P>
+where
+    P: AsyncPool,
+{
+    fn deref_mut(&mut self) -> &mut P::Connection {
+        &mut self.conn
+    }
+}
+
+/// A Gotham compatible Middleware which places a `PoolHandle` into the `State` of each request.
+pub struct PoolMiddleware<P>
+where
+    P: AsyncPool,
+{
+    handle: AssertUnwindSafe<PoolHandle<P>>,
+}
+
+impl<P> PoolMiddleware<P>
+where
+    P: AsyncPool,
+{
+    /// Creates a new `PoolMiddleware` for the pool.
+    pub fn new(pool: P) -> Self {
+        PoolMiddleware {
+            handle: AssertUnwindSafe(PoolHandle::new(pool)),
+        }
+    }
+
+    /// Returns the metrics of the pool, which are shared by all requests.
+    pub fn metrics(&self) -> PoolMetrics<P> {
+        self.handle.metrics.clone()
+    }
+}
+
+impl<P> Clone for PoolMiddleware<P>
+where
+    P: AsyncPool,
+{
+    fn clone(&self) -> Self {
+        PoolMiddleware {
+            handle: AssertUnwindSafe(self.handle.0.clone()),
+        }
+    }
+}
+
+impl<P> NewMiddleware for PoolMiddleware<P>
+where
+    P: AsyncPool,
+{
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<P> Middleware for PoolMiddleware<P>
+where
+    P: AsyncPool,
+{
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>>,
+    {
+        state.put(self.handle.0);
+        chain(state)
+    }
+}
+
+/// A Gotham compatible Middleware which checks out a `Connection` from the pool before the request
+/// is handled, and places it into the `State` along with a `PoolHandle`. If the pool fails to
+/// provide a connection, the request is answered with `503 Service Unavailable`.
+pub struct ConnectionMiddleware<P>
+where
+    P: AsyncPool,
+{
+    handle: AssertUnwindSafe<PoolHandle<P>>,
+}
+
+impl<P> ConnectionMiddleware<P>
+where
+    P: AsyncPool,
+{
+    /// Creates a new `ConnectionMiddleware` for the pool.
+    pub fn new(pool: P) -> Self {
+        ConnectionMiddleware {
+            handle: AssertUnwindSafe(PoolHandle::new(pool)),
+        }
+    }
+
+    /// Returns the metrics of the pool, which are shared by all requests.
+    pub fn metrics(&self) -> PoolMetrics<P> {
+        self.handle.metrics.clone()
+    }
+}
+
+impl<P> Clone for ConnectionMiddleware<P>
+where
+    P: AsyncPool,
+{
+    fn clone(&self) -> Self {
+        ConnectionMiddleware {
+            handle: AssertUnwindSafe(self.handle.0.clone()),
+        }
+    }
+}
+
+impl<P> NewMiddleware for ConnectionMiddleware<P>
+where
+    P: AsyncPool,
+{
+    type Instance = Self;
+
+    fn new_middleware(&self) -> anyhow::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<P> Middleware for ConnectionMiddleware<P>
+where
+    P: AsyncPool,
+{
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static,
+    {
+        let handle = self.handle.0;
+        handle
+            .get()
+            .then(move |result| match result {
+                Ok(conn) => {
+                    trace!("[{}] checked out connection", request_id(&state));
+                    state.put(Connection::<P> { conn });
+                    state.put(handle);
+                    chain(state)
+                }
+                Err(err) => {
+                    error!(
+                        "[{}] unable to check out connection: {:?}",
+                        request_id(&state),
+                        err
+                    );
+                    let response = create_empty_response(&state, StatusCode::SERVICE_UNAVAILABLE);
+                    future::ok((state, response)).boxed()
+                }
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::BoxFuture;
+    use gotham::handler::HandlerResult;
+    use gotham::pipeline::{new_pipeline, single_pipeline};
+    use gotham::router::builder::build_router;
+    use gotham::test::TestServer;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    // hands out increasing numbers, failing once `limit` is reached
+    #[derive(Clone)]
+    struct CountingPool {
+        next: Arc<AtomicU32>,
+        limit: u32,
+    }
+
+    impl CountingPool {
+        fn new(limit: u32) -> Self {
+            CountingPool {
+                next: Arc::default(),
+                limit,
+            }
+        }
+    }
+
+    impl AsyncPool for CountingPool {
+        type Connection = u32;
+        type Error = &'static str;
+
+        fn get(&self) -> BoxFuture<'static, Result<u32, &'static str>> {
+            let conn = self.next.fetch_add(1, Ordering::SeqCst);
+            if conn < self.limit {
+                future::ok(conn).boxed()
+            } else {
+                future::err("pool exhausted").boxed()
+            }
+        }
+
+        fn status(&self) -> PoolStatus {
+            PoolStatus {
+                connections: self.limit,
+                idle_connections: 1,
+                max_size: Some(self.limit),
+            }
+        }
+    }
+
+    async fn handler(state: State) -> HandlerResult {
+        let body = match Connection::<CountingPool>::try_borrow_from(&state) {
+            Some(conn) => format!("connection {}", **conn),
+            None => {
+                let handle = PoolHandle::<CountingPool>::borrow_from(&state).clone();
+                match handle.get().await {
+                    Ok(conn) => format!("handle {}", conn),
+                    Err(err) => err.to_owned(),
+                }
+            }
+        };
+        let response = body.into_response(&state);
+        Ok((state, response))
+    }
+
+    #[test]
+    fn checks_out_connection_per_request() {
+        let middleware = ConnectionMiddleware::new(CountingPool::new(2));
+        let metrics = middleware.metrics();
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.get("/").to_async(handler);
+        });
+        let test_server = TestServer::new(router).unwrap();
+        let get = || {
+            test_server
+                .client()
+                .get("http://localhost/")
+                .perform()
+                .unwrap()
+        };
+
+        assert_eq!(get().read_utf8_body().unwrap(), "connection 0");
+        assert_eq!(get().read_utf8_body().unwrap(), "connection 1");
+        assert_eq!(get().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.checkouts, 3);
+        assert_eq!(snapshot.failures, 1);
+        assert!(snapshot.max_wait >= snapshot.mean_wait());
+        assert_eq!(snapshot.status.in_use(), 1);
+    }
+
+    #[test]
+    fn places_pool_handle_into_state() {
+        let middleware = PoolMiddleware::new(CountingPool::new(1));
+        let metrics = middleware.metrics();
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.get("/").to_async(handler);
+        });
+        let test_server = TestServer::new(router).unwrap();
+        let get = || {
+            test_server
+                .client()
+                .get("http://localhost/")
+                .perform()
+                .unwrap()
+                .read_utf8_body()
+                .unwrap()
+        };
+
+        assert_eq!(get(), "handle 0");
+        assert_eq!(get(), "pool exhausted");
+        assert_eq!(metrics.snapshot().checkouts, 2);
+        assert_eq!(metrics.snapshot().failures, 1);
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::pool::{AsyncPool, PoolStatus};
+
+#[derive(Default)]
+struct Counters {
+    checkouts: AtomicU64,
+    failures: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+/// Health metrics of a pool, shared by the middleware and all clones of it.
+///
+/// Checkouts are only counted when made through a `PoolHandle` or the `ConnectionMiddleware`.
+pub struct PoolMetrics<P> {
+    pool: P,
+    counters: Arc<Counters>,
+}
+
+impl<P> Clone for PoolMetrics<P>
+where
+    P: Clone,
+{
+    fn clone(&self) -> Self {
+        PoolMetrics {
+            pool: self.pool.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<P> PoolMetrics<P>
+where
+    P: AsyncPool,
+{
+    pub(crate) fn new(pool: P) -> Self {
+        PoolMetrics {
+            pool,
+            counters: Arc::default(),
+        }
+    }
+
+    pub(crate) fn record_checkout(&self, wait: Duration, success: bool) {
+        let counters = &self.counters;
+        let wait = wait.as_micros() as u64;
+        counters.checkouts.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            counters.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        counters.wait_micros.fetch_add(wait, Ordering::Relaxed);
+        counters.max_wait_micros.fetch_max(wait, Ordering::Relaxed);
+    }
+
+    /// Takes a snapshot of the metrics, including the current status of the pool.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let counters = &self.counters;
+        MetricsSnapshot {
+            checkouts: counters.checkouts.load(Ordering::Relaxed),
+            failures: counters.failures.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(counters.wait_micros.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(counters.max_wait_micros.load(Ordering::Relaxed)),
+            status: self.pool.status(),
+        }
+    }
+}
+
+/// The health metrics of a pool at a point in time, as returned by `PoolMetrics::snapshot`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// The number of attempted checkouts.
+    pub checkouts: u64,
+    /// The number of checkouts which failed.
+    pub failures: u64,
+    /// The total time spent waiting for connections.
+    pub total_wait: Duration,
+    /// The longest time spent waiting for a connection.
+    pub max_wait: Duration,
+    /// The current size of the pool.
+    pub status: PoolStatus,
+}
+
+impl MetricsSnapshot {
+    /// Returns the mean time spent waiting for a connection.
+    pub fn mean_wait(&self) -> Duration {
+        match self.checkouts {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos((self.total_wait.as_nanos() / u128::from(n)) as u64),
+        }
+    }
+}
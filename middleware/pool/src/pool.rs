@@ -0,0 +1,127 @@
+use futures_util::future::BoxFuture;
+use std::fmt;
+
+/// The size of a connection pool at a point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// The number of connections currently open, whether in use or idle.
+    pub connections: u32,
+    /// The number of open connections which are not in use.
+    pub idle_connections: u32,
+    /// The maximum number of connections, if the pool reports it.
+    pub max_size: Option<u32>,
+}
+
+impl PoolStatus {
+    /// Returns the number of connections which are currently checked out.
+    pub fn in_use(&self) -> u32 {
+        self.connections.saturating_sub(self.idle_connections)
+    }
+}
+
+/// A pool of connections which can be checked out without blocking the runtime.
+///
+/// This is implemented for the pools of the `r2d2`, `bb8` and `deadpool` crates when the feature
+/// of the same name is enabled, and can be implemented for other pools.
+pub trait AsyncPool: Clone + Send + Sync + 'static {
+    /// The connection handed out by the pool, which returns to the pool when dropped.
+    type Connection: Send + 'static;
+
+    /// The error returned when no connection can be checked out.
+    type Error: fmt::Debug + Send + 'static;
+
+    /// Checks out a connection from the pool.
+    fn get(&self) -> BoxFuture<'static, Result<Self::Connection, Self::Error>>;
+
+    /// Returns the current size of the pool.
+    fn status(&self) -> PoolStatus;
+}
+
+#[cfg(feature = "r2d2")]
+impl<M> AsyncPool for r2d2::Pool<M>
+where
+    M: r2d2::ManageConnection,
+{
+    type Connection = r2d2::PooledConnection<M>;
+    type Error = CheckoutError<r2d2::Error>;
+
+    /// Checks out a connection on the blocking thread pool of tokio, as `r2d2` blocks until a
+    /// connection is available.
+    fn get(&self) -> BoxFuture<'static, Result<Self::Connection, Self::Error>> {
+        let pool = self.clone();
+        Box::pin(async move {
+            match tokio::task::spawn_blocking(move || pool.get()).await {
+                Ok(Ok(conn)) => Ok(conn),
+                Ok(Err(err)) => Err(CheckoutError::Pool(err)),
+                Err(err) => Err(CheckoutError::Join(err)),
+            }
+        })
+    }
+
+    fn status(&self) -> PoolStatus {
+        let state = self.state();
+        PoolStatus {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            max_size: Some(self.max_size()),
+        }
+    }
+}
+
+/// The error of checking out a connection on a blocking thread.
+#[cfg(feature = "r2d2")]
+#[derive(Debug)]
+pub enum CheckoutError<E> {
+    /// The pool failed to provide a connection.
+    Pool(E),
+    /// The blocking task checking out the connection panicked or was cancelled.
+    Join(tokio::task::JoinError),
+}
+
+#[cfg(feature = "bb8")]
+impl<M> AsyncPool for bb8::Pool<M>
+where
+    M: bb8::ManageConnection,
+{
+    type Connection = bb8::PooledConnection<'static, M>;
+    type Error = bb8::RunError<M::Error>;
+
+    fn get(&self) -> BoxFuture<'static, Result<Self::Connection, Self::Error>> {
+        let pool = self.clone();
+        Box::pin(async move { pool.get_owned().await })
+    }
+
+    fn status(&self) -> PoolStatus {
+        let state = self.state();
+        PoolStatus {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            max_size: None,
+        }
+    }
+}
+
+#[cfg(feature = "deadpool")]
+impl<M> AsyncPool for deadpool::managed::Pool<M>
+where
+    M: deadpool::managed::Manager + 'static,
+    M::Type: Send,
+    M::Error: fmt::Debug + Send,
+{
+    type Connection = deadpool::managed::Object<M>;
+    type Error = deadpool::managed::PoolError<M::Error>;
+
+    fn get(&self) -> BoxFuture<'static, Result<Self::Connection, Self::Error>> {
+        let pool = self.clone();
+        Box::pin(async move { pool.get().await })
+    }
+
+    fn status(&self) -> PoolStatus {
+        let status = self.status();
+        PoolStatus {
+            connections: status.size as u32,
+            idle_connections: status.available as u32,
+            max_size: Some(status.max_size as u32),
+        }
+    }
+}